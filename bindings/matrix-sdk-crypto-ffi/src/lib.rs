@@ -505,6 +505,7 @@ fn collect_sessions(
             sender_data: SenderData::legacy(),
             room_id: RoomId::parse(session.room_id)?,
             imported: session.imported,
+            forwarding_curve25519_key_chain: Vec::new(),
             backed_up: session.backed_up,
             history_visibility: None,
             algorithm: RustEventEncryptionAlgorithm::MegolmV1AesSha2,
@@ -302,6 +302,7 @@ impl ClientBuilder {
                 backup_download_strategy:
                     matrix_sdk::encryption::BackupDownloadStrategy::AfterDecryptionFailure,
                 auto_enable_backups: false,
+                check_device_keys_on_restore: false,
             },
             room_key_recipient_strategy: Default::default(),
             decryption_trust_requirement: TrustRequirement::Untrusted,
@@ -474,6 +475,14 @@ impl ClientBuilder {
         Arc::new(builder)
     }
 
+    /// Check that the keys of the device in the crypto store match the ones
+    /// known by the homeserver when a session is restored.
+    pub fn check_device_keys_on_restore(self: Arc<Self>, check: bool) -> Arc<Self> {
+        let mut builder = unwrap_or_clone_arc(self);
+        builder.encryption_settings.check_device_keys_on_restore = check;
+        Arc::new(builder)
+    }
+
     /// Set the strategy to be used for picking recipient devices when sending
     /// an encrypted message.
     pub fn room_key_recipient_strategy(self: Arc<Self>, strategy: CollectStrategy) -> Arc<Self> {
@@ -296,6 +296,9 @@ pub enum NotificationSettingsError {
     /// Unable to update push rule.
     #[error("Unable to update push rule")]
     UnableToUpdatePushRule,
+    /// Unable to access the rooms muted until a point in time.
+    #[error("Unable to access the temporary room mutes")]
+    UnableToAccessTemporaryMutes,
 }
 
 impl From<SdkNotificationSettingsError> for NotificationSettingsError {
@@ -307,6 +310,9 @@ impl From<SdkNotificationSettingsError> for NotificationSettingsError {
             SdkNotificationSettingsError::UnableToSavePushRules => Self::UnableToSavePushRules,
             SdkNotificationSettingsError::InvalidParameter(msg) => Self::InvalidParameter { msg },
             SdkNotificationSettingsError::UnableToUpdatePushRule => Self::UnableToUpdatePushRule,
+            SdkNotificationSettingsError::UnableToAccessTemporaryMutes => {
+                Self::UnableToAccessTemporaryMutes
+            }
         }
     }
 }
@@ -1,7 +1,9 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use matrix_sdk_ui::notification_client::{
+    NotificationBatchStatus as MatrixNotificationBatchStatus,
     NotificationClient as MatrixNotificationClient, NotificationItem as MatrixNotificationItem,
+    NotificationRequest as MatrixNotificationRequest,
 };
 use ruma::{EventId, RoomId};
 
@@ -76,6 +78,39 @@ impl NotificationItem {
     }
 }
 
+#[derive(uniffi::Record)]
+pub struct NotificationRequest {
+    pub room_id: String,
+    pub event_id: String,
+}
+
+#[derive(uniffi::Enum)]
+pub enum NotificationBatchStatus {
+    /// The notification was resolved.
+    Event { item: NotificationItem },
+    /// The notification has been filtered out by the user's push rules.
+    EventFilteredOut,
+    /// The notification couldn't be resolved.
+    Error { message: String },
+    /// The time budget ran out before the notification could be resolved.
+    TimedOut,
+}
+
+impl From<MatrixNotificationBatchStatus> for NotificationBatchStatus {
+    fn from(value: MatrixNotificationBatchStatus) -> Self {
+        match value {
+            MatrixNotificationBatchStatus::Event(item) => {
+                Self::Event { item: NotificationItem::from_inner(item) }
+            }
+            MatrixNotificationBatchStatus::EventFilteredOut => Self::EventFilteredOut,
+            MatrixNotificationBatchStatus::Error(error) => {
+                Self::Error { message: error.to_string() }
+            }
+            MatrixNotificationBatchStatus::TimedOut => Self::TimedOut,
+        }
+    }
+}
+
 #[derive(uniffi::Object)]
 pub struct NotificationClient {
     pub(crate) inner: MatrixNotificationClient,
@@ -109,4 +144,31 @@ impl NotificationClient {
             Ok(None)
         }
     }
+
+    /// Fetch several notifications at once, within the given time budget.
+    ///
+    /// The results are in the same order as the requests.
+    ///
+    /// See also documentation of
+    /// `MatrixNotificationClient::get_notifications`.
+    pub async fn get_notifications(
+        &self,
+        requests: Vec<NotificationRequest>,
+        time_budget_ms: u64,
+    ) -> Result<Vec<NotificationBatchStatus>, ClientError> {
+        let requests = requests
+            .into_iter()
+            .map(|request| {
+                Ok(MatrixNotificationRequest {
+                    room_id: RoomId::parse(request.room_id)?,
+                    event_id: EventId::parse(request.event_id)?,
+                })
+            })
+            .collect::<Result<Vec<_>, ClientError>>()?;
+
+        let results =
+            self.inner.get_notifications(&requests, Duration::from_millis(time_budget_ms)).await;
+
+        Ok(results.into_iter().map(Into::into).collect())
+    }
 }
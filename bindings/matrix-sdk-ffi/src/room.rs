@@ -187,6 +187,16 @@ impl Room {
         Ok(())
     }
 
+    /// Prepare the encryption of the next message sent in this room, by
+    /// sharing the current room key with the devices of the room members.
+    ///
+    /// This can be called when the user opens the composer, so that the first
+    /// message is sent faster. Does nothing if the room isn't encrypted.
+    pub async fn prepare_encryption(&self) -> Result<(), ClientError> {
+        self.inner.prepare_encryption().await?;
+        Ok(())
+    }
+
     pub async fn timeline(&self) -> Result<Arc<Timeline>, ClientError> {
         let mut write_guard = self.timeline.write().await;
         if let Some(timeline) = &*write_guard {
@@ -262,6 +272,29 @@ impl Room {
         Ok(Timeline::new(timeline))
     }
 
+    /// A timeline instance focused on the thread with the given root, that
+    /// receives the new events of the thread from the sync.
+    pub async fn thread_timeline(
+        &self,
+        root_event_id: String,
+        num_events: u16,
+        internal_id_prefix: Option<String>,
+    ) -> Result<Arc<Timeline>, ClientError> {
+        let root_event_id = EventId::parse(root_event_id)?;
+        let room = &self.inner;
+
+        let mut builder = matrix_sdk_ui::timeline::Timeline::builder(room);
+
+        if let Some(internal_id_prefix) = internal_id_prefix {
+            builder = builder.with_internal_id_prefix(internal_id_prefix);
+        }
+
+        let timeline =
+            builder.with_focus(TimelineFocus::Thread { root_event_id, num_events }).build().await?;
+
+        Ok(Timeline::new(timeline))
+    }
+
     /// A timeline instance that can be configured to only include RoomMessage
     /// type events and filter those further based on their message type.
     ///
@@ -683,8 +716,7 @@ impl Room {
 
     /// Mark a room as read, by attaching a read receipt on the latest event.
     ///
-    /// Note: this does NOT unset the unread flag; it's the caller's
-    /// responsibility to do so, if needs be.
+    /// This also unsets the unread flag of the room, if it was set.
     pub async fn mark_as_read(&self, receipt_type: ReceiptType) -> Result<(), ClientError> {
         let timeline = self.timeline().await?;
 
@@ -56,6 +56,9 @@ pub struct RoomInfo {
     /// Events causing mentions/highlights for the user, according to their
     /// notification settings.
     num_unread_mentions: u64,
+    /// The ids of the most recent events causing mentions/highlights for the
+    /// user, in the order they were received.
+    unread_mention_event_ids: Vec<String>,
     /// The currently pinned event ids.
     pinned_event_ids: Vec<String>,
     /// The join rule for this room, if known.
@@ -126,6 +129,12 @@ impl RoomInfo {
             num_unread_messages: room.num_unread_messages(),
             num_unread_notifications: room.num_unread_notifications(),
             num_unread_mentions: room.num_unread_mentions(),
+            unread_mention_event_ids: room
+                .unread_mentions()
+                .event_ids
+                .iter()
+                .map(|event_id| event_id.to_string())
+                .collect(),
             pinned_event_ids,
             join_rule: join_rule.ok(),
         })
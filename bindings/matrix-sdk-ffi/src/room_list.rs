@@ -11,7 +11,7 @@ use matrix_sdk::ruma::{
 use matrix_sdk_ui::{
     room_list_service::filters::{
         new_filter_all, new_filter_any, new_filter_category, new_filter_favourite,
-        new_filter_fuzzy_match_room_name, new_filter_invite, new_filter_joined,
+        new_filter_fuzzy_match_room_name, new_filter_invite, new_filter_joined, new_filter_knocked,
         new_filter_non_left, new_filter_none, new_filter_normalized_match_room_name,
         new_filter_unread, BoxedFilterFn, RoomCategory,
     },
@@ -476,6 +476,7 @@ pub enum RoomListEntriesDynamicFilterKind {
     Unread,
     Favourite,
     Invite,
+    Knocked,
     Category { expect: RoomListFilterCategory },
     None,
     NormalizedMatchRoomName { pattern: String },
@@ -513,6 +514,7 @@ impl From<RoomListEntriesDynamicFilterKind> for BoxedFilterFn {
             Kind::Unread => Box::new(new_filter_unread()),
             Kind::Favourite => Box::new(new_filter_favourite()),
             Kind::Invite => Box::new(new_filter_invite()),
+            Kind::Knocked => Box::new(new_filter_knocked()),
             Kind::Category { expect } => Box::new(new_filter_category(expect.into())),
             Kind::None => Box::new(new_filter_none()),
             Kind::NormalizedMatchRoomName { pattern } => {
@@ -1322,11 +1322,12 @@ impl LazyTimelineItemProvider {
 }
 
 /// Changes how date dividers get inserted, either in between each day or in
-/// between each month
+/// between each month, or not at all
 #[derive(Debug, Clone, uniffi::Enum)]
 pub enum DateDividerMode {
     Daily,
     Monthly,
+    Disabled,
 }
 
 impl From<DateDividerMode> for matrix_sdk_ui::timeline::DateDividerMode {
@@ -1334,6 +1335,7 @@ impl From<DateDividerMode> for matrix_sdk_ui::timeline::DateDividerMode {
         match value {
             DateDividerMode::Daily => Self::Daily,
             DateDividerMode::Monthly => Self::Monthly,
+            DateDividerMode::Disabled => Self::Disabled,
         }
     }
 }
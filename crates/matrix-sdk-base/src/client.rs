@@ -13,12 +13,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[cfg(feature = "e2e-encryption")]
-use std::sync::Arc;
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, BTreeSet, HashMap},
     fmt, iter,
     ops::Deref,
+    sync::Arc,
 };
 
 use eyeball::{SharedObservable, Subscriber};
@@ -50,8 +50,8 @@ use ruma::{
             },
         },
         AnyRoomAccountDataEvent, AnyStrippedStateEvent, AnySyncEphemeralRoomEvent,
-        AnySyncMessageLikeEvent, AnySyncStateEvent, AnySyncTimelineEvent,
-        GlobalAccountDataEventType, StateEvent, StateEventType, SyncStateEvent,
+        AnySyncMessageLikeEvent, AnySyncStateEvent, AnySyncTimelineEvent, GlobalAccountDataEvent,
+        GlobalAccountDataEventType, StateEvent, StateEventType, StaticEventContent, SyncStateEvent,
     },
     push::{Action, PushConditionRoomCtx, Ruleset},
     serde::Raw,
@@ -61,7 +61,7 @@ use ruma::{
 use tokio::sync::{broadcast, Mutex};
 #[cfg(feature = "e2e-encryption")]
 use tokio::sync::{RwLock, RwLockReadGuard};
-use tracing::{debug, error, info, instrument, trace, warn};
+use tracing::{debug, debug_span, error, info, instrument, trace, warn, Instrument};
 
 #[cfg(feature = "e2e-encryption")]
 use crate::latest_event::{is_suitable_for_latest_event, LatestEvent, PossibleLatestEvent};
@@ -74,14 +74,18 @@ use crate::{
     response_processors::AccountDataProcessor,
     rooms::{
         normal::{RoomInfoNotableUpdate, RoomInfoNotableUpdateReasons, RoomMembersUpdate},
-        Room, RoomInfo, RoomState,
+        Room, RoomDisplayNameResolver, RoomInfo, RoomState,
     },
     store::{
-        ambiguity_map::AmbiguityCache, DynStateStore, MemoryStore, Result as StoreResult,
-        StateChanges, StateStoreDataKey, StateStoreDataValue, StateStoreExt, Store, StoreConfig,
+        ambiguity_map::AmbiguityCache, strip_member_profile, DynStateStore, MemberStoragePolicy,
+        MemberStorageStrategy, MemoryStore, Result as StoreResult, StateChanges, StateStoreDataKey,
+        StateStoreDataValue, StateStoreExt, Store, StoreConfig, StoreTransaction,
+    },
+    sync::{
+        JoinedRoomUpdate, LeftRoomUpdate, Notification, RoomUpdates, SyncProcessingReport,
+        SyncResponse, Timeline,
     },
-    sync::{JoinedRoomUpdate, LeftRoomUpdate, Notification, RoomUpdates, SyncResponse, Timeline},
-    RoomStateFilter, SessionMeta,
+    HiddenRoomsEventContent, RoomStateFilter, SessionMeta,
 };
 
 /// A no IO Client implementation.
@@ -112,10 +116,16 @@ pub struct BaseClient {
     /// Observable of when a user is ignored/unignored.
     pub(crate) ignore_user_list_changes: SharedObservable<Vec<String>>,
 
+    /// Observable of when a room is hidden/unhidden.
+    pub(crate) hidden_rooms_changes: SharedObservable<BTreeSet<OwnedRoomId>>,
+
     /// A sender that is used to communicate changes to room information. Each
     /// tick contains the room ID and the reasons that have generated this tick.
     pub(crate) room_info_notable_update_sender: broadcast::Sender<RoomInfoNotableUpdate>,
 
+    /// The policy choosing how the member events of the rooms are persisted.
+    member_storage_policy: MemberStoragePolicy,
+
     /// The strategy to use for picking recipient devices, when sending an
     /// encrypted message.
     #[cfg(feature = "e2e-encryption")]
@@ -155,7 +165,9 @@ impl BaseClient {
             #[cfg(feature = "e2e-encryption")]
             olm_machine: Default::default(),
             ignore_user_list_changes: Default::default(),
+            hidden_rooms_changes: Default::default(),
             room_info_notable_update_sender,
+            member_storage_policy: config.member_storage_policy,
             #[cfg(feature = "e2e-encryption")]
             room_key_recipient_strategy: Default::default(),
             #[cfg(feature = "e2e-encryption")]
@@ -186,11 +198,15 @@ impl BaseClient {
             crypto_store: self.crypto_store.clone(),
             olm_machine: self.olm_machine.clone(),
             ignore_user_list_changes: Default::default(),
+            hidden_rooms_changes: Default::default(),
             room_info_notable_update_sender: self.room_info_notable_update_sender.clone(),
+            member_storage_policy: self.member_storage_policy,
             room_key_recipient_strategy: self.room_key_recipient_strategy.clone(),
             decryption_trust_requirement: self.decryption_trust_requirement,
         };
 
+        copy.store.set_room_display_name_resolver(self.store.room_display_name_resolver());
+
         if let Some(session_meta) = self.session_meta().cloned() {
             copy.store
                 .set_session_meta(session_meta, &copy.room_info_notable_update_sender)
@@ -209,7 +225,8 @@ impl BaseClient {
         cross_process_store_locks_holder: &str,
     ) -> Result<Self> {
         let config = StoreConfig::new(cross_process_store_locks_holder.to_owned())
-            .state_store(MemoryStore::new());
+            .state_store(MemoryStore::new())
+            .member_storage_policy(self.member_storage_policy);
         Ok(Self::with_store_config(config))
     }
 
@@ -222,6 +239,36 @@ impl BaseClient {
         self.store.session_meta()
     }
 
+    /// Set a custom strategy to compute the display names of the rooms, or
+    /// remove it with `None` to go back to the default computation.
+    ///
+    /// The cached display names of all the rooms are cleared, so they are
+    /// computed again with the new strategy.
+    pub fn set_room_display_name_resolver(
+        &self,
+        resolver: Option<Arc<dyn RoomDisplayNameResolver>>,
+    ) {
+        self.store.set_room_display_name_resolver(resolver);
+    }
+
+    /// Get the policy choosing how the member events of the rooms are
+    /// persisted.
+    pub fn member_storage_policy(&self) -> MemberStoragePolicy {
+        self.member_storage_policy
+    }
+
+    /// Whether the display name and avatar of the given member should be left
+    /// out of the store, according to the [`MemberStorageStrategy`] of the
+    /// room.
+    ///
+    /// The profiles of the current user and of the heroes of the room are
+    /// always kept, they are needed to compute the name of the room.
+    fn should_strip_member_profile(&self, room_info: &RoomInfo, user_id: &UserId) -> bool {
+        self.member_storage_policy.strategy_for(room_info) == MemberStorageStrategy::MembershipOnly
+            && self.session_meta().map_or(true, |meta| *meta.user_id != *user_id)
+            && !room_info.heroes().iter().any(|hero| *hero.user_id == *user_id)
+    }
+
     /// Get all the rooms this client knows about.
     pub fn rooms(&self) -> Vec<Room> {
         self.store.rooms()
@@ -427,18 +474,15 @@ impl BaseClient {
 
             match event.raw().deserialize() {
                 Ok(e) => {
+                    trace!(event_id = %e.event_id(), "Handling timeline event");
+
                     #[allow(clippy::single_match)]
                     match &e {
                         AnySyncTimelineEvent::State(s) if !ignore_state_events => {
+                            let mut raw_event: Raw<AnySyncStateEvent> = event.raw().clone().cast();
+
                             match s {
                                 AnySyncStateEvent::RoomMember(member) => {
-                                    Box::pin(ambiguity_cache.handle_event(
-                                        changes,
-                                        room.room_id(),
-                                        member,
-                                    ))
-                                    .await?;
-
                                     match member.membership() {
                                         MembershipState::Join | MembershipState::Invite => {
                                             user_ids.insert(member.state_key().to_owned());
@@ -448,18 +492,30 @@ impl BaseClient {
                                         }
                                     }
 
-                                    handle_room_member_event_for_profiles(
-                                        room.room_id(),
-                                        member,
-                                        changes,
-                                    );
+                                    if self
+                                        .should_strip_member_profile(room_info, member.state_key())
+                                    {
+                                        raw_event = strip_member_profile(&raw_event);
+                                    } else {
+                                        Box::pin(ambiguity_cache.handle_event(
+                                            changes,
+                                            room.room_id(),
+                                            member,
+                                        ))
+                                        .await?;
+
+                                        handle_room_member_event_for_profiles(
+                                            room.room_id(),
+                                            member,
+                                            changes,
+                                        );
+                                    }
                                 }
                                 _ => {
                                     room_info.handle_state_event(s);
                                 }
                             }
 
-                            let raw_event: Raw<AnySyncStateEvent> = event.raw().clone().cast();
                             changes.add_state_event(room.room_id(), s.clone(), raw_event);
                         }
 
@@ -624,9 +680,9 @@ impl BaseClient {
         for (raw_event, event) in iter::zip(raw_events, events) {
             room_info.handle_state_event(event);
 
-            if let AnySyncStateEvent::RoomMember(member) = &event {
-                ambiguity_cache.handle_event(changes, &room_info.room_id, member).await?;
+            let mut raw_event = Cow::Borrowed(raw_event);
 
+            if let AnySyncStateEvent::RoomMember(member) = &event {
                 match member.membership() {
                     MembershipState::Join | MembershipState::Invite => {
                         user_ids.insert(member.state_key().to_owned());
@@ -634,13 +690,18 @@ impl BaseClient {
                     _ => (),
                 }
 
-                handle_room_member_event_for_profiles(&room_info.room_id, member, changes);
+                if self.should_strip_member_profile(room_info, member.state_key()) {
+                    raw_event = Cow::Owned(strip_member_profile(&raw_event));
+                } else {
+                    ambiguity_cache.handle_event(changes, &room_info.room_id, member).await?;
+                    handle_room_member_event_for_profiles(&room_info.room_id, member, changes);
+                }
             }
 
             state_events
                 .entry(event.event_type())
                 .or_insert_with(BTreeMap::new)
-                .insert(event.state_key().to_owned(), raw_event.clone());
+                .insert(event.state_key().to_owned(), raw_event.into_owned());
         }
 
         changes.state.insert((*room_info.room_id).to_owned(), state_events);
@@ -734,7 +795,16 @@ impl BaseClient {
                         }
                         AnyRoomAccountDataEvent::Tag(event) => {
                             on_room_info(room_id, changes, self, |room_info| {
+                                let previous_tags = room_info.base_info.notable_tags;
                                 room_info.base_info.handle_notable_tags(&event.content.tags);
+
+                                if room_info.base_info.notable_tags != previous_tags {
+                                    // Notify the room list, which sorts the rooms by their tags.
+                                    room_info_notable_updates
+                                        .entry(room_id.to_owned())
+                                        .or_default()
+                                        .insert(RoomInfoNotableUpdateReasons::NOTABLE_TAGS);
+                                }
                             });
                         }
 
@@ -932,7 +1002,7 @@ impl BaseClient {
     /// # Arguments
     ///
     /// * `response` - The response that we received after a successful sync.
-    #[instrument(skip_all)]
+    #[instrument(skip_all, fields(next_batch = %response.next_batch))]
     pub async fn receive_sync_response(
         &self,
         response: api::sync::sync_events::v3::Response,
@@ -946,6 +1016,8 @@ impl BaseClient {
         }
 
         let now = Instant::now();
+        let mut processing_report = SyncProcessingReport::default();
+        let mut transaction = StoreTransaction::new(&self.store);
         let mut changes = Box::new(StateChanges::new(response.next_batch.clone()));
 
         #[cfg_attr(not(feature = "e2e-encryption"), allow(unused_mut))]
@@ -970,6 +1042,9 @@ impl BaseClient {
         #[cfg(not(feature = "e2e-encryption"))]
         let to_device = response.to_device.events;
 
+        processing_report.to_device = now.elapsed();
+        let rooms_start = Instant::now();
+
         let mut ambiguity_cache = AmbiguityCache::new(self.store.inner.clone());
 
         let account_data_processor = AccountDataProcessor::process(&response.account_data.events);
@@ -983,7 +1058,7 @@ impl BaseClient {
             BTreeMap::new();
 
         for (room_id, new_info) in response.rooms.join {
-            let room = self.store.get_or_create_room(
+            let room = transaction.get_or_create_room(
                 &room_id,
                 RoomState::Joined,
                 self.room_info_notable_update_sender.clone(),
@@ -1104,7 +1179,7 @@ impl BaseClient {
         }
 
         for (room_id, new_info) in response.rooms.leave {
-            let room = self.store.get_or_create_room(
+            let room = transaction.get_or_create_room(
                 &room_id,
                 RoomState::Left,
                 self.room_info_notable_update_sender.clone(),
@@ -1169,7 +1244,7 @@ impl BaseClient {
         }
 
         for (room_id, new_info) in response.rooms.invite {
-            let room = self.store.get_or_create_room(
+            let room = transaction.get_or_create_room(
                 &room_id,
                 RoomState::Invited,
                 self.room_info_notable_update_sender.clone(),
@@ -1198,7 +1273,7 @@ impl BaseClient {
         }
 
         for (room_id, new_info) in response.rooms.knock {
-            let room = self.store.get_or_create_room(
+            let room = transaction.get_or_create_room(
                 &room_id,
                 RoomState::Knocked,
                 self.room_info_notable_update_sender.clone(),
@@ -1239,13 +1314,33 @@ impl BaseClient {
 
         changes.ambiguity_maps = ambiguity_cache.cache;
 
+        processing_report.rooms = rooms_start.elapsed();
+        let store_start = Instant::now();
+
+        // If anything failed before this point, dropping the transaction has rolled back
+        // the rooms created in memory. Committing only advances the sync token if the
+        // changes could be saved.
+        if let Err(err) = transaction
+            .commit(&changes, Some(response.next_batch.clone()), || {
+                self.apply_changes(&changes, room_info_notable_updates)
+            })
+            .instrument(debug_span!("save_changes", rooms = changes.room_infos.len()))
+            .await
         {
-            let _sync_lock = self.sync_lock().lock().await;
-            self.store.save_changes(&changes).await?;
-            *self.store.sync_token.write().await = Some(response.next_batch.clone());
-            self.apply_changes(&changes, room_info_notable_updates);
+            // The crypto store isn't part of the transaction: the to-device events and
+            // device list changes of this response have already been persisted by the
+            // `OlmMachine`. Since the sync token isn't advanced, the same response will be
+            // received again, and processing it a second time is harmless: already
+            // received room keys are ignored, and Olm messages that have already been
+            // decrypted fail to decrypt again.
+            error!(
+                "Couldn't save the changes of a sync response, it will be received again: {err}"
+            );
+            return Err(err.into());
         }
 
+        processing_report.store = store_start.elapsed();
+
         // Now that all the rooms information have been saved, update the display name
         // cache (which relies on information stored in the database). This will
         // live in memory, until the next sync which will saves the room info to
@@ -1260,7 +1355,15 @@ impl BaseClient {
             }
         }
 
-        info!("Processed a sync response in {:?}", now.elapsed());
+        processing_report.total = now.elapsed();
+
+        info!(
+            to_device = ?processing_report.to_device,
+            rooms = ?processing_report.rooms,
+            store = ?processing_report.store,
+            "Processed a sync response in {:?}",
+            processing_report.total
+        );
 
         let response = SyncResponse {
             rooms: new_rooms,
@@ -1268,6 +1371,7 @@ impl BaseClient {
             account_data: response.account_data.events,
             to_device,
             notifications,
+            processing_report,
         };
 
         Ok(response)
@@ -1293,6 +1397,18 @@ impl BaseClient {
             }
         }
 
+        if let Some(event) = changes
+            .account_data
+            .get(&GlobalAccountDataEventType::from(HiddenRoomsEventContent::TYPE))
+        {
+            match event.deserialize_as::<GlobalAccountDataEvent<HiddenRoomsEventContent>>() {
+                Ok(event) => self.hidden_rooms_changes.set(event.content.rooms),
+                Err(error) => {
+                    error!("Failed to deserialize hidden rooms event: {error}")
+                }
+            }
+        }
+
         for (room_id, room_info) in &changes.room_infos {
             if let Some(room) = self.store.room(room_id) {
                 let room_info_notable_update_reasons =
@@ -1341,6 +1457,7 @@ impl BaseClient {
         let mut user_ids = BTreeSet::new();
 
         let mut ambiguity_map: HashMap<DisplayName, BTreeSet<OwnedUserId>> = Default::default();
+        let room_info = room.clone_info();
 
         for raw_event in &response.chunk {
             let member = match raw_event.deserialize() {
@@ -1369,18 +1486,26 @@ impl BaseClient {
                 _ => (),
             }
 
-            if let StateEvent::Original(e) = &member {
-                if let Some(d) = &e.content.displayname {
-                    let display_name = DisplayName::new(d);
-                    ambiguity_map
-                        .entry(display_name)
-                        .or_default()
-                        .insert(member.state_key().clone());
+            let raw_event: Raw<AnySyncStateEvent> = raw_event.clone().cast();
+
+            let raw_event = if self.should_strip_member_profile(&room_info, member.state_key()) {
+                strip_member_profile(&raw_event)
+            } else {
+                if let StateEvent::Original(e) = &member {
+                    if let Some(d) = &e.content.displayname {
+                        let display_name = DisplayName::new(d);
+                        ambiguity_map
+                            .entry(display_name)
+                            .or_default()
+                            .insert(member.state_key().clone());
+                    }
                 }
-            }
 
-            let sync_member: SyncRoomMemberEvent = member.clone().into();
-            handle_room_member_event_for_profiles(room_id, &sync_member, &mut changes);
+                let sync_member: SyncRoomMemberEvent = member.clone().into();
+                handle_room_member_event_for_profiles(room_id, &sync_member, &mut changes);
+
+                raw_event
+            };
 
             changes
                 .state
@@ -1388,7 +1513,7 @@ impl BaseClient {
                 .or_default()
                 .entry(member.event_type())
                 .or_default()
-                .insert(member.state_key().to_string(), raw_event.clone().cast());
+                .insert(member.state_key().to_string(), raw_event);
             chunk.push(member);
         }
 
@@ -1411,6 +1536,9 @@ impl BaseClient {
 
         let _ = room.room_member_updates_sender.send(RoomMembersUpdate::FullReload);
 
+        // The display name of the room may depend on its members.
+        let _ = room.compute_display_name().await;
+
         Ok(())
     }
 
@@ -1668,6 +1796,14 @@ impl BaseClient {
         self.ignore_user_list_changes.subscribe()
     }
 
+    /// Returns a subscriber that publishes the hidden rooms every time they
+    /// change.
+    ///
+    /// See [`HiddenRoomsEventContent`].
+    pub fn subscribe_to_hidden_rooms_changes(&self) -> Subscriber<BTreeSet<OwnedRoomId>> {
+        self.hidden_rooms_changes.subscribe()
+    }
+
     pub(crate) fn deserialize_state_events(
         raw_events: &[Raw<AnySyncStateEvent>],
     ) -> Vec<(Raw<AnySyncStateEvent>, AnySyncStateEvent)> {
@@ -1739,18 +1875,23 @@ fn handle_room_member_event_for_profiles(
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeSet;
+
     use matrix_sdk_test::{
-        async_test, ruma_response_from_json, sync_timeline_event, InvitedRoomBuilder,
+        async_test, ruma_response_from_json,
+        sync_replay::{scrub_sync_response, SyncReplay, TimeWarp},
+        sync_timeline_event, GlobalAccountDataTestEvent, InvitedRoomBuilder, JoinedRoomBuilder,
         LeftRoomBuilder, StateTestEvent, StrippedStateTestEvent, SyncResponseBuilder,
     };
-    #[cfg(feature = "e2e-encryption")]
-    use ruma::UserId;
-    use ruma::{api::client as api, room_id, serde::Raw, user_id};
+    use ruma::{
+        api::client as api, events::room::member::MembershipState, room_id, serde::Raw, uint,
+        user_id, MilliSecondsSinceUnixEpoch, RoomId, UserId,
+    };
     use serde_json::{json, value::to_raw_value};
 
     use super::BaseClient;
     use crate::{
-        store::{StateStoreExt, StoreConfig},
+        store::{MemberStoragePolicy, StateChanges, StateStoreExt, StoreConfig, StoreTransaction},
         test_utils::logged_in_base_client,
         RoomDisplayName, RoomState, SessionMeta,
     };
@@ -1799,6 +1940,58 @@ mod tests {
         assert_eq!(client.get_room(room_id).unwrap().state(), RoomState::Invited);
     }
 
+    #[async_test]
+    async fn test_sync_processing_report() {
+        let user_id = user_id!("@alice:example.org");
+        let room_id = room_id!("!test:example.org");
+
+        let client = logged_in_base_client(Some(user_id)).await;
+
+        let mut sync_builder = SyncResponseBuilder::new();
+        let response =
+            sync_builder.add_left_room(LeftRoomBuilder::new(room_id)).build_sync_response();
+
+        let report = client.receive_sync_response(response).await.unwrap().processing_report;
+
+        // The stages don't overlap, and are all part of the total.
+        assert!(report.to_device + report.rooms + report.store <= report.total);
+    }
+
+    #[async_test]
+    async fn test_uncommitted_store_transaction_rolls_back_rooms() {
+        let user_id = user_id!("@alice:example.org");
+        let room_id = room_id!("!test:example.org");
+
+        let client = logged_in_base_client(Some(user_id)).await;
+
+        let mut transaction = StoreTransaction::new(&client.store);
+        transaction.get_or_create_room(
+            room_id,
+            RoomState::Joined,
+            client.room_info_notable_update_sender.clone(),
+        );
+        assert!(client.get_room(room_id).is_some());
+
+        // Dropping the transaction without committing it removes the room.
+        drop(transaction);
+        assert!(client.get_room(room_id).is_none());
+
+        let mut transaction = StoreTransaction::new(&client.store);
+        transaction.get_or_create_room(
+            room_id,
+            RoomState::Joined,
+            client.room_info_notable_update_sender.clone(),
+        );
+        transaction
+            .commit(&StateChanges::new("t0".to_owned()), Some("t0".to_owned()), || {})
+            .await
+            .unwrap();
+
+        // Once committed, the room stays, and the sync token has been advanced.
+        assert!(client.get_room(room_id).is_some());
+        assert_eq!(client.store.sync_token.read().await.as_deref(), Some("t0"));
+    }
+
     #[async_test]
     async fn test_invite_displayname() {
         let user_id = user_id!("@alice:example.org");
@@ -2075,6 +2268,175 @@ mod tests {
         assert_eq!(member.avatar_url().unwrap().to_string(), "mxc://localhost/fewjilfewjil42");
     }
 
+    #[async_test]
+    async fn test_membership_only_member_storage() {
+        let user_id = user_id!("@alice:example.org");
+        let hero_id = user_id!("@bob:example.org");
+        let other_id = user_id!("@carol:example.org");
+        let room_id = room_id!("!ithpyNKDtmhneaTQja:example.org");
+
+        let client = BaseClient::with_store_config(
+            StoreConfig::new("cross-process-store-locks-holder-name".to_owned())
+                .member_storage_policy(MemberStoragePolicy::membership_only_above(2)),
+        );
+        client
+            .set_session_meta(
+                SessionMeta { user_id: user_id.to_owned(), device_id: "FOOBAR".into() },
+                #[cfg(feature = "e2e-encryption")]
+                None,
+            )
+            .await
+            .unwrap();
+
+        let member_event = |user_id: &UserId, display_name: &str| {
+            StateTestEvent::Custom(json!({
+                "content": {
+                    "avatar_url": "mxc://localhost/fewjilfewjil42",
+                    "displayname": display_name,
+                    "membership": "join"
+                },
+                "event_id": format!("$member_{}", user_id.localpart()),
+                "origin_server_ts": 151800140,
+                "sender": user_id,
+                "state_key": user_id,
+                "type": "m.room.member",
+            }))
+        };
+
+        let response = SyncResponseBuilder::new()
+            .add_joined_room(
+                matrix_sdk_test::JoinedRoomBuilder::new(room_id)
+                    .set_room_summary(json!({
+                        "m.heroes": [hero_id],
+                        "m.joined_member_count": 3,
+                    }))
+                    .add_state_event(member_event(user_id, "Alice"))
+                    .add_state_event(member_event(hero_id, "Bob"))
+                    .add_state_event(member_event(other_id, "Carol")),
+            )
+            .build_sync_response();
+        client.receive_sync_response(response).await.unwrap();
+
+        let room = client.get_room(room_id).unwrap();
+
+        // The profiles of the current user and of the heroes are kept,
+        let member = room.get_member(user_id).await.unwrap().unwrap();
+        assert_eq!(member.display_name(), Some("Alice"));
+        let member = room.get_member(hero_id).await.unwrap().unwrap();
+        assert_eq!(member.display_name(), Some("Bob"));
+
+        // But only the membership of the other members is stored.
+        let member = room.get_member(other_id).await.unwrap().unwrap();
+        assert_eq!(member.membership(), &MembershipState::Join);
+        assert_eq!(member.display_name(), None);
+        assert_eq!(member.avatar_url(), None);
+        assert!(client.store.get_profile(room_id, other_id).await.unwrap().is_none());
+    }
+
+    #[async_test]
+    async fn test_hidden_rooms() {
+        let client = logged_in_base_client(None).await;
+        let room_a = room_id!("!a:example.org");
+        let room_b = room_id!("!b:example.org");
+        let room_c = room_id!("!c:example.org");
+
+        let hidden_rooms = |rooms: &[&RoomId]| {
+            GlobalAccountDataTestEvent::Custom(json!({
+                "content": { "rooms": rooms },
+                "type": "org.matrix.custom.hidden_rooms",
+            }))
+        };
+
+        let mut subscriber = client.subscribe_to_hidden_rooms_changes();
+
+        let mut sync_builder = SyncResponseBuilder::new();
+        let response = sync_builder
+            .add_joined_room(JoinedRoomBuilder::new(room_a))
+            .add_joined_room(JoinedRoomBuilder::new(room_b))
+            .add_global_account_data_event(hidden_rooms(&[room_a, room_c]))
+            .build_sync_response();
+        client.receive_sync_response(response).await.unwrap();
+
+        assert!(client.get_room(room_a).unwrap().is_hidden());
+        assert!(!client.get_room(room_b).unwrap().is_hidden());
+        assert_eq!(subscriber.next_now(), BTreeSet::from([room_a.to_owned(), room_c.to_owned()]));
+
+        // A room received after the hidden rooms list is hidden too.
+        let response =
+            sync_builder.add_joined_room(JoinedRoomBuilder::new(room_c)).build_sync_response();
+        client.receive_sync_response(response).await.unwrap();
+
+        assert!(client.get_room(room_c).unwrap().is_hidden());
+
+        // Updating the list unhides the rooms that aren't in it anymore.
+        let response = sync_builder
+            .add_global_account_data_event(hidden_rooms(&[room_c]))
+            .build_sync_response();
+        client.receive_sync_response(response).await.unwrap();
+
+        assert!(!client.get_room(room_a).unwrap().is_hidden());
+        assert!(client.get_room(room_c).unwrap().is_hidden());
+        assert_eq!(subscriber.next_now(), BTreeSet::from([room_c.to_owned()]));
+    }
+
+    #[async_test]
+    async fn test_replayed_sync_responses() {
+        let client = logged_in_base_client(None).await;
+        let room_id = room_id!("!room:example.org");
+
+        let room_name = |event_id: &str, name: &str, ts: u64| {
+            StateTestEvent::Custom(json!({
+                "content": { "name": name },
+                "event_id": event_id,
+                "origin_server_ts": ts,
+                "sender": "@alice:example.org",
+                "state_key": "",
+                "type": "m.room.name",
+            }))
+        };
+
+        let mut sync_builder = SyncResponseBuilder::new();
+        let mut responses = vec![
+            sync_builder
+                .add_joined_room(JoinedRoomBuilder::new(room_id).add_state_event(room_name(
+                    "$1",
+                    "First name",
+                    1000,
+                )))
+                .build_json_sync_response(),
+            sync_builder
+                .add_joined_room(
+                    JoinedRoomBuilder::new(room_id)
+                        .add_state_event(room_name("$2", "Second name", 2000))
+                        .add_timeline_event(sync_timeline_event!({
+                            "content": { "body": "A secret", "msgtype": "m.text" },
+                            "event_id": "$3",
+                            "origin_server_ts": 3000,
+                            "sender": "@alice:example.org",
+                            "type": "m.room.message",
+                        })),
+                )
+                .build_json_sync_response(),
+        ];
+        responses.iter_mut().for_each(scrub_sync_response);
+
+        let replay = SyncReplay::from_responses(responses)
+            .time_warp(TimeWarp::EndAt(MilliSecondsSinceUnixEpoch(uint!(10_000))));
+
+        // The message was scrubbed, and the timestamps were shifted, but the state
+        // was kept.
+        let timeline = &replay.json_responses()[1]["rooms"]["join"][room_id.as_str()]["timeline"];
+        assert_eq!(timeline["events"][0]["content"]["body"], "[scrubbed]");
+        assert_eq!(timeline["events"][0]["origin_server_ts"], 10_000);
+
+        for response in replay.responses() {
+            client.receive_sync_response(response).await.unwrap();
+        }
+
+        let room = client.get_room(room_id).unwrap();
+        assert_eq!(room.name().as_deref(), Some("Second name"));
+    }
+
     #[async_test]
     async fn test_reinvited_members_get_a_display_name() {
         let user_id = user_id!("@alice:example.org");
@@ -48,6 +48,7 @@ pub fn make_test_event(room_id: &RoomId, content: &str) -> SyncTimelineEvent {
             sender_claimed_keys: Default::default(),
         },
         verification_state: VerificationState::Verified,
+        session_id: None,
     };
 
     let event = EventFactory::new()
@@ -64,6 +65,7 @@ pub fn make_test_event(room_id: &RoomId, content: &str) -> SyncTimelineEvent {
             unsigned_encryption_info: None,
         }),
         push_actions: vec![Action::Notify],
+        enrichments: Default::default(),
     }
 }
 
@@ -56,13 +56,14 @@ pub use http;
 pub use matrix_sdk_crypto as crypto;
 pub use once_cell;
 pub use rooms::{
-    Room, RoomCreateWithCreatorEventContent, RoomDisplayName, RoomHero, RoomInfo,
-    RoomInfoNotableUpdate, RoomInfoNotableUpdateReasons, RoomMember, RoomMemberships, RoomState,
-    RoomStateFilter,
+    HiddenRoomsEventContent, Room, RoomCreateWithCreatorEventContent, RoomDisplayName,
+    RoomDisplayNameResolver, RoomHero, RoomInfo, RoomInfoNotableUpdate,
+    RoomInfoNotableUpdateReasons, RoomMember, RoomMemberships, RoomState, RoomStateFilter,
 };
 pub use store::{
-    ComposerDraft, ComposerDraftType, QueueWedgeError, StateChanges, StateStore, StateStoreDataKey,
-    StateStoreDataValue, StoreError,
+    CachedUrlPreview, CachedUserProfile, CachedWellKnown, ComposerDraft, ComposerDraftType,
+    ContactActivity, QueueWedgeError, StateChanges, StateStore, StateStoreDataKey,
+    StateStoreDataValue, StoreError, TemporaryRoomMute, UrlPreview, UrlPreviewImage,
 };
 pub use utils::{
     MinimalRoomMemberEvent, MinimalStateEvent, OriginalMinimalStateEvent, RedactedMinimalStateEvent,
@@ -146,6 +146,13 @@ pub trait MediaEventContent {
     ///
     /// Returns `None` if `Self` has no thumbnail.
     fn thumbnail_source(&self) -> Option<MediaSource>;
+
+    /// Get the size of the file of `Self`, in bytes.
+    ///
+    /// Returns `None` if `Self` has no file, or if its size is unknown.
+    fn file_size(&self) -> Option<UInt> {
+        None
+    }
 }
 
 impl MediaEventContent for StickerEventContent {
@@ -156,6 +163,10 @@ impl MediaEventContent for StickerEventContent {
     fn thumbnail_source(&self) -> Option<MediaSource> {
         None
     }
+
+    fn file_size(&self) -> Option<UInt> {
+        self.info.size
+    }
 }
 
 impl MediaEventContent for AudioMessageEventContent {
@@ -166,6 +177,10 @@ impl MediaEventContent for AudioMessageEventContent {
     fn thumbnail_source(&self) -> Option<MediaSource> {
         None
     }
+
+    fn file_size(&self) -> Option<UInt> {
+        self.info.as_ref()?.size
+    }
 }
 
 impl MediaEventContent for FileMessageEventContent {
@@ -176,6 +191,10 @@ impl MediaEventContent for FileMessageEventContent {
     fn thumbnail_source(&self) -> Option<MediaSource> {
         self.info.as_ref()?.thumbnail_source.clone()
     }
+
+    fn file_size(&self) -> Option<UInt> {
+        self.info.as_ref()?.size
+    }
 }
 
 impl MediaEventContent for ImageMessageEventContent {
@@ -189,6 +208,10 @@ impl MediaEventContent for ImageMessageEventContent {
             .and_then(|info| info.thumbnail_source.clone())
             .or_else(|| Some(self.source.clone()))
     }
+
+    fn file_size(&self) -> Option<UInt> {
+        self.info.as_ref()?.size
+    }
 }
 
 impl MediaEventContent for VideoMessageEventContent {
@@ -202,6 +225,10 @@ impl MediaEventContent for VideoMessageEventContent {
             .and_then(|info| info.thumbnail_source.clone())
             .or_else(|| Some(self.source.clone()))
     }
+
+    fn file_size(&self) -> Option<UInt> {
+        self.info.as_ref()?.size
+    }
 }
 
 impl MediaEventContent for LocationMessageEventContent {
@@ -214,9 +241,106 @@ impl MediaEventContent for LocationMessageEventContent {
     }
 }
 
+/// The type of network the device is connected to, as hinted by the
+/// application.
+///
+/// It is used to choose the [`NetworkMediaPolicy`] of a [`MediaPolicy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetworkType {
+    /// A network that isn't billed by usage, like most Wi-Fi networks.
+    Unmetered,
+
+    /// A network that is billed by usage, like most cellular networks.
+    Metered,
+
+    /// The type of the network is unknown.
+    #[default]
+    Unknown,
+}
+
+/// Which media can be downloaded automatically on a type of network.
+///
+/// The default policy allows everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkMediaPolicy {
+    /// The maximum size of the files that can be downloaded automatically, in
+    /// bytes.
+    ///
+    /// Files whose size is unknown are not downloaded automatically when this
+    /// is set. It doesn't apply to thumbnails. If this is `None`, there is no
+    /// limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_auto_download_size: Option<UInt>,
+
+    /// Whether only thumbnails can be downloaded automatically.
+    #[serde(default)]
+    pub thumbnails_only: bool,
+}
+
+impl NetworkMediaPolicy {
+    /// Whether a media with the given format, and the given size for files,
+    /// can be downloaded automatically according to this policy.
+    pub fn allows_auto_download(&self, format: &MediaFormat, size: Option<UInt>) -> bool {
+        match format {
+            MediaFormat::Thumbnail(_) => true,
+            MediaFormat::File => {
+                !self.thumbnails_only
+                    && self
+                        .max_auto_download_size
+                        .map_or(true, |max| size.is_some_and(|size| size <= max))
+            }
+        }
+    }
+}
+
+/// The policy choosing which media can be downloaded automatically, for
+/// example when media events are displayed, depending on the
+/// [`NetworkType`].
+///
+/// Media can always be downloaded when the user explicitly requests it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaPolicy {
+    /// Whether the data saver mode is enabled.
+    ///
+    /// In this mode, only thumbnails are downloaded automatically, whatever the
+    /// network.
+    #[serde(default)]
+    pub data_saver: bool,
+
+    /// The policy on unmetered networks.
+    #[serde(default)]
+    pub unmetered: NetworkMediaPolicy,
+
+    /// The policy on metered networks.
+    #[serde(default)]
+    pub metered: NetworkMediaPolicy,
+
+    /// The policy when the type of the network is unknown.
+    #[serde(default)]
+    pub unknown: NetworkMediaPolicy,
+}
+
+impl MediaPolicy {
+    /// Get the policy to apply on the given type of network, taking the data
+    /// saver mode into account.
+    pub fn for_network(&self, network_type: NetworkType) -> NetworkMediaPolicy {
+        let mut policy = match network_type {
+            NetworkType::Unmetered => self.unmetered.clone(),
+            NetworkType::Metered => self.metered.clone(),
+            NetworkType::Unknown => self.unknown.clone(),
+        };
+
+        if self.data_saver {
+            policy.thumbnails_only = true;
+        }
+
+        policy
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use ruma::mxc_uri;
+    use ruma::{mxc_uri, uint};
     use serde_json::json;
 
     use super::*;
@@ -256,4 +380,34 @@ mod tests {
 
         assert_eq!(file.uri(), mxc_uri);
     }
+
+    #[test]
+    fn test_media_policy() {
+        let thumbnail = MediaFormat::Thumbnail(MediaThumbnailSettings::new(uint!(100), uint!(100)));
+        let policy = MediaPolicy {
+            metered: NetworkMediaPolicy {
+                max_auto_download_size: Some(uint!(1000)),
+                thumbnails_only: false,
+            },
+            ..Default::default()
+        };
+
+        // Everything is allowed by default.
+        let unmetered = policy.for_network(NetworkType::Unmetered);
+        assert!(unmetered.allows_auto_download(&MediaFormat::File, None));
+        assert!(unmetered.allows_auto_download(&thumbnail, None));
+
+        // Only the small files are allowed on metered networks.
+        let metered = policy.for_network(NetworkType::Metered);
+        assert!(metered.allows_auto_download(&MediaFormat::File, Some(uint!(1000))));
+        assert!(!metered.allows_auto_download(&MediaFormat::File, Some(uint!(1001))));
+        assert!(!metered.allows_auto_download(&MediaFormat::File, None));
+        assert!(metered.allows_auto_download(&thumbnail, None));
+
+        // Only thumbnails are allowed in data saver mode.
+        let policy = MediaPolicy { data_saver: true, ..policy };
+        let unmetered = policy.for_network(NetworkType::Unmetered);
+        assert!(!unmetered.allows_auto_download(&MediaFormat::File, Some(uint!(1))));
+        assert!(unmetered.allows_auto_download(&thumbnail, None));
+    }
 }
@@ -115,6 +115,15 @@
 //!   timeline, leading to incorrect results. We have to take that into account
 //!   by resetting the read counts *every* time we see an event that was the
 //!   target of the latest active read receipt.
+//!
+//! ## Threads
+//!
+//! Thread receipts are ignored by the algorithm above. Instead, we keep track
+//! of the latest event of each thread, either from the events in the thread,
+//! or from the thread summary bundled with the thread root, in
+//! `ThreadReadReceipts`. A thread has unread activity if its latest event is
+//! interesting, and the user hasn't sent a thread receipt on it, or on an
+//! event after it in the sync ordering, or sent an event in the thread since.
 #![allow(dead_code)] // too many different build configurations, I give up
 
 use std::{
@@ -176,6 +185,88 @@ pub struct RoomReadReceipts {
     /// not the event ids of the receipt events themselves.
     #[serde(default = "new_nonempty_ring_buffer")]
     pending: RingBuffer<OwnedEventId>,
+
+    /// The ids of the most recent unread events causing highlights for the
+    /// user, in the order they were received.
+    ///
+    /// There are at most [`MAX_MENTION_EVENT_IDS`] of them, so there might be
+    /// fewer than [`Self::num_mentions`].
+    #[serde(default = "new_mention_event_ids_ring_buffer")]
+    mention_event_ids: RingBuffer<OwnedEventId>,
+
+    /// The read state of the threads of the room, by thread root.
+    #[serde(default)]
+    threads: BTreeMap<OwnedEventId, ThreadReadReceipts>,
+}
+
+/// The read state of a thread.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+struct ThreadReadReceipts {
+    /// The latest event known in the thread.
+    latest_event: Option<OwnedEventId>,
+
+    /// The event of the latest thread receipt of the user, or of the latest
+    /// event the user sent in the thread.
+    latest_receipt: Option<OwnedEventId>,
+
+    /// Whether the thread has unread activity.
+    unread: bool,
+
+    /// When the read state of the thread was last updated, relative to the
+    /// other threads of the room.
+    #[serde(default)]
+    last_update: u64,
+}
+
+impl ThreadReadReceipts {
+    /// Update the read state with a new event of the thread.
+    fn on_new_event(&mut self, event_id: OwnedEventId, is_own: bool, marks_as_unread: bool) {
+        if self.latest_event.as_ref() == Some(&event_id)
+            || self.latest_receipt.as_ref() == Some(&event_id)
+        {
+            // We already know about this event, maybe from the thread summary.
+            return;
+        }
+
+        if is_own {
+            // Sending an event in the thread is an implicit receipt.
+            self.latest_receipt = Some(event_id.clone());
+            self.unread = false;
+        } else if marks_as_unread {
+            self.unread = true;
+        }
+
+        self.latest_event = Some(event_id);
+    }
+}
+
+/// The maximum number of event ids kept in
+/// [`RoomReadReceipts::mention_event_ids()`].
+pub const MAX_MENTION_EVENT_IDS: usize = 100;
+
+/// The maximum number of threads whose read state is kept in a
+/// [`RoomReadReceipts`].
+const MAX_THREADS: usize = 100;
+
+/// The maximum number of read threads whose read state is kept in a
+/// [`RoomReadReceipts`], so their latest event isn't considered unread again if
+/// it is received again.
+const MAX_READ_THREADS: usize = 10;
+
+/// The unread mentions of a room, as returned by [`Room::unread_mentions()`].
+///
+/// [`Room::unread_mentions()`]: crate::Room::unread_mentions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UnreadMentions {
+    /// The number of unread events causing highlights for the user.
+    pub count: u64,
+
+    /// The ids of the most recent of these events, in the order they were
+    /// received.
+    ///
+    /// There are at most [`MAX_MENTION_EVENT_IDS`] of them, so there might be
+    /// fewer than `count`.
+    pub event_ids: Vec<OwnedEventId>,
 }
 
 impl Default for RoomReadReceipts {
@@ -186,6 +277,8 @@ impl Default for RoomReadReceipts {
             num_mentions: Default::default(),
             latest_active: Default::default(),
             pending: new_nonempty_ring_buffer(),
+            mention_event_ids: new_mention_event_ids_ring_buffer(),
+            threads: Default::default(),
         }
     }
 }
@@ -196,7 +289,158 @@ fn new_nonempty_ring_buffer() -> RingBuffer<OwnedEventId> {
     RingBuffer::new(NonZeroUsize::new(10).unwrap())
 }
 
+fn new_mention_event_ids_ring_buffer() -> RingBuffer<OwnedEventId> {
+    // SAFETY: `unwrap` is safe because `MAX_MENTION_EVENT_IDS` is not zero.
+    RingBuffer::new(NonZeroUsize::new(MAX_MENTION_EVENT_IDS).unwrap())
+}
+
 impl RoomReadReceipts {
+    /// The ids of the most recent unread events causing highlights for the
+    /// user, in the order they were received.
+    pub fn mention_event_ids(&self) -> impl Iterator<Item = &EventId> {
+        self.mention_event_ids.iter().map(|event_id| event_id.as_ref())
+    }
+
+    /// The unread mentions of the room.
+    pub fn unread_mentions(&self) -> UnreadMentions {
+        UnreadMentions {
+            count: self.num_mentions,
+            event_ids: self.mention_event_ids.iter().cloned().collect(),
+        }
+    }
+
+    /// Whether any thread of the room has unread activity.
+    pub fn has_unread_threads(&self) -> bool {
+        self.threads.values().any(|thread| thread.unread)
+    }
+
+    /// The roots of the threads of the room with unread activity.
+    pub fn unread_threads(&self) -> impl Iterator<Item = &EventId> {
+        self.threads
+            .iter()
+            .filter(|(_, thread)| thread.unread)
+            .map(|(thread_root, _)| thread_root.as_ref())
+    }
+
+    /// Get the read state of the given thread, to update it.
+    fn thread_mut(&mut self, thread_root: OwnedEventId) -> &mut ThreadReadReceipts {
+        let last_update =
+            self.threads.values().map(|thread| thread.last_update + 1).max().unwrap_or_default();
+
+        let thread = self.threads.entry(thread_root).or_default();
+        thread.last_update = last_update;
+        thread
+    }
+
+    /// Forget the read state of the least recently updated threads, to keep
+    /// at most [`MAX_READ_THREADS`] read threads, and [`MAX_THREADS`] threads
+    /// in total.
+    fn prune_threads(&mut self) {
+        if self.threads.len() <= MAX_READ_THREADS {
+            return;
+        }
+
+        let mut threads: Vec<_> = self
+            .threads
+            .iter()
+            .map(|(thread_root, thread)| (thread.last_update, thread.unread, thread_root.clone()))
+            .collect();
+        // Sort the threads from the most recently updated one.
+        threads.sort_unstable_by(|(a, ..), (b, ..)| b.cmp(a));
+
+        let mut num_threads = 0;
+        let mut num_read_threads = 0;
+
+        for (_, unread, thread_root) in threads {
+            if !unread {
+                num_read_threads += 1;
+            }
+
+            if num_threads < MAX_THREADS && (unread || num_read_threads <= MAX_READ_THREADS) {
+                num_threads += 1;
+            } else {
+                trace!(%thread_root, "Forgetting the read state of the thread");
+                self.threads.remove(&thread_root);
+            }
+        }
+    }
+
+    /// Update the read state of the threads with the new events, that can be
+    /// in a thread, or be the root of a thread with a summary.
+    fn process_thread_events(&mut self, user_id: &UserId, new_events: &[SyncTimelineEvent]) {
+        for event in new_events {
+            let Ok(info) = event.raw().deserialize_as::<ThreadEventInfo>() else { continue };
+
+            if let Some(thread_root) = info.thread_root() {
+                if let Some(event_id) = info.event_id.clone() {
+                    let is_own = info.sender.as_deref() == Some(user_id);
+                    self.thread_mut(thread_root).on_new_event(
+                        event_id,
+                        is_own,
+                        marks_as_unread(event.raw(), user_id),
+                    );
+                }
+            }
+
+            let Some(summary) = info.unsigned.relations.and_then(|relations| relations.thread)
+            else {
+                continue;
+            };
+            let (Some(thread_root), Ok(Some(latest_event_id)), Ok(Some(sender))) = (
+                info.event_id,
+                summary.latest_event.get_field::<OwnedEventId>("event_id"),
+                summary.latest_event.get_field::<OwnedUserId>("sender"),
+            ) else {
+                continue;
+            };
+
+            self.thread_mut(thread_root).on_new_event(
+                latest_event_id,
+                sender == user_id,
+                marks_as_unread(&summary.latest_event, user_id),
+            );
+        }
+    }
+
+    /// Mark the threads as read according to the new thread receipts of the
+    /// user.
+    fn process_thread_receipts(
+        &mut self,
+        user_id: &UserId,
+        receipt_event: &ReceiptEventContent,
+        all_events: &Vector<SyncTimelineEvent>,
+    ) {
+        let position = |event_id: &EventId| {
+            all_events.iter().position(|event| event.event_id().as_deref() == Some(event_id))
+        };
+
+        for (event_id, receipts) in &receipt_event.0 {
+            for ty in [ReceiptType::Read, ReceiptType::ReadPrivate] {
+                let Some(receipt) = receipts.get(&ty).and_then(|receipts| receipts.get(user_id))
+                else {
+                    continue;
+                };
+                let ReceiptThread::Thread(thread_root) = &receipt.thread else { continue };
+
+                let thread = self.thread_mut(thread_root.clone());
+                thread.latest_receipt = Some(event_id.clone());
+
+                let is_read = match &thread.latest_event {
+                    Some(latest_event) if latest_event == event_id => true,
+                    Some(latest_event) => position(latest_event)
+                        .zip(position(event_id))
+                        .is_some_and(|(latest_pos, receipt_pos)| latest_pos <= receipt_pos),
+                    None => true,
+                };
+
+                if is_read {
+                    trace!(%thread_root, %event_id, "Thread is read");
+                    thread.unread = false;
+                }
+            }
+        }
+    }
+
     /// Update the [`RoomReadReceipts`] unread counts according to the new
     /// event.
     ///
@@ -218,6 +462,10 @@ impl RoomReadReceipts {
             if !has_mention && action.is_highlight() {
                 self.num_mentions += 1;
                 has_mention = true;
+
+                if let Some(event_id) = event.event_id() {
+                    self.mention_event_ids.push(event_id);
+                }
             }
         }
     }
@@ -227,6 +475,7 @@ impl RoomReadReceipts {
         self.num_unread = 0;
         self.num_notifications = 0;
         self.num_mentions = 0;
+        self.mention_event_ids.clear();
     }
 
     /// Try to find the event to which the receipt attaches to, and if found,
@@ -265,6 +514,57 @@ impl RoomReadReceipts {
     }
 }
 
+/// The fields of an event needed to compute the read state of threads.
+#[derive(Deserialize)]
+struct ThreadEventInfo {
+    event_id: Option<OwnedEventId>,
+    sender: Option<OwnedUserId>,
+    #[serde(default)]
+    content: ThreadEventContent,
+    #[serde(default)]
+    unsigned: ThreadEventUnsigned,
+}
+
+impl ThreadEventInfo {
+    /// The root of the thread this event is in, if any.
+    fn thread_root(&self) -> Option<OwnedEventId> {
+        let relates_to = self.content.relates_to.as_ref()?;
+        (relates_to.rel_type.as_deref() == Some("m.thread"))
+            .then(|| relates_to.event_id.clone())
+            .flatten()
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct ThreadEventContent {
+    #[serde(rename = "m.relates_to")]
+    relates_to: Option<ThreadEventRelatesTo>,
+}
+
+#[derive(Deserialize)]
+struct ThreadEventRelatesTo {
+    rel_type: Option<String>,
+    event_id: Option<OwnedEventId>,
+}
+
+#[derive(Default, Deserialize)]
+struct ThreadEventUnsigned {
+    #[serde(rename = "m.relations")]
+    relations: Option<ThreadEventRelations>,
+}
+
+#[derive(Deserialize)]
+struct ThreadEventRelations {
+    #[serde(rename = "m.thread")]
+    thread: Option<ThreadSummary>,
+}
+
+/// The summary of a thread, bundled with its root.
+#[derive(Deserialize)]
+struct ThreadSummary {
+    latest_event: Raw<AnySyncTimelineEvent>,
+}
+
 /// Provider for timeline events prior to the current sync.
 pub trait PreviousEventsProvider: Send + Sync {
     /// Returns the list of known timeline events, in sync order, for the given
@@ -473,6 +773,12 @@ pub(crate) fn compute_unread_counts(
         all_events
     };
 
+    read_receipts.process_thread_events(user_id, new_events);
+    if let Some(receipt_event) = receipt_event {
+        read_receipts.process_thread_receipts(user_id, receipt_event, &all_events);
+    }
+    read_receipts.prune_threads();
+
     let new_receipt = {
         let mut selector = ReceiptSelector::new(
             &all_events,
@@ -631,7 +937,9 @@ mod tests {
     };
 
     use super::compute_unread_counts;
-    use crate::read_receipts::{marks_as_unread, ReceiptSelector, RoomReadReceipts};
+    use crate::read_receipts::{
+        marks_as_unread, ReceiptSelector, RoomReadReceipts, MAX_READ_THREADS, MAX_THREADS,
+    };
 
     #[test]
     fn test_room_message_marks_as_unread() {
@@ -828,6 +1136,47 @@ mod tests {
         assert_eq!(receipts.num_notifications, 1);
     }
 
+    #[test]
+    fn test_unread_mentions() {
+        fn make_event(event_id: &EventId, push_actions: Vec<Action>) -> SyncTimelineEvent {
+            SyncTimelineEvent::new_with_push_actions(
+                sync_timeline_event!({
+                    "sender": "@bob:example.org",
+                    "type": "m.room.message",
+                    "event_id": event_id,
+                    "origin_server_ts": 12344446,
+                    "content": { "body":"A", "msgtype": "m.text" },
+                }),
+                push_actions,
+            )
+        }
+
+        let user_id = user_id!("@alice:example.org");
+        let highlight = || vec![Action::SetTweak(ruma::push::Tweak::Highlight(true))];
+
+        let mut receipts = RoomReadReceipts::default();
+        receipts.process_event(&make_event(event_id!("$1"), highlight()), user_id);
+        receipts.process_event(&make_event(event_id!("$2"), vec![Action::Notify]), user_id);
+        receipts.process_event(&make_event(event_id!("$3"), highlight()), user_id);
+
+        // Only the events causing a highlight are mentions.
+        let mentions = receipts.unread_mentions();
+        assert_eq!(mentions.count, 2);
+        assert_eq!(mentions.event_ids, [owned_event_id!("$1"), owned_event_id!("$3")]);
+
+        // A receipt on the first mention only leaves the second one unread.
+        let events = [
+            make_event(event_id!("$1"), highlight()),
+            make_event(event_id!("$2"), vec![Action::Notify]),
+            make_event(event_id!("$3"), highlight()),
+        ];
+        assert!(receipts.find_and_process_events(event_id!("$1"), user_id, &events));
+
+        let mentions = receipts.unread_mentions();
+        assert_eq!(mentions.count, 1);
+        assert_eq!(mentions.event_ids, [owned_event_id!("$3")]);
+    }
+
     #[test]
     fn test_find_and_process_events() {
         let ev0 = event_id!("$0");
@@ -1619,4 +1968,150 @@ mod tests {
         // And the active receipt is the implicit one on my event.
         assert_eq!(read_receipts.latest_active.unwrap().event_id, event_id!("$6"));
     }
+
+    fn sync_thread_message(
+        sender: &UserId,
+        event_id: &str,
+        thread_root: &str,
+    ) -> SyncTimelineEvent {
+        SyncTimelineEvent::new(sync_timeline_event!({
+            "sender": sender,
+            "type": "m.room.message",
+            "event_id": event_id,
+            "origin_server_ts": 42,
+            "content": {
+                "body": "In a thread",
+                "msgtype": "m.text",
+                "m.relates_to": { "rel_type": "m.thread", "event_id": thread_root },
+            },
+        }))
+    }
+
+    #[test]
+    fn test_prune_threads() {
+        let user_id = user_id!("@alice:example.org");
+        let other_user_id = user_id!("@bob:example.org");
+        let room_id = room_id!("!room:example.org");
+
+        // More unread threads than the maximum.
+        let events: Vec<_> = (0..MAX_THREADS + 20)
+            .map(|i| {
+                sync_thread_message(other_user_id, &format!("$in_thread{i}"), &format!("$root{i}"))
+            })
+            .collect();
+
+        let mut read_receipts = RoomReadReceipts::default();
+        compute_unread_counts(user_id, room_id, None, Vector::new(), &events, &mut read_receipts);
+
+        // Only the most recently updated threads are kept.
+        assert_eq!(read_receipts.threads.len(), MAX_THREADS);
+        let unread_threads: Vec<_> = read_receipts.unread_threads().collect();
+        assert!(!unread_threads.contains(&event_id!("$root0")));
+        assert!(unread_threads.contains(&event_id!("$root119")));
+
+        // Reading more threads than the maximum of read threads only keeps the most
+        // recent read threads.
+        let own_messages: Vec<_> = (100..MAX_THREADS + 20)
+            .map(|i| sync_thread_message(user_id, &format!("$own{i}"), &format!("$root{i}")))
+            .collect();
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            events.iter().cloned().collect(),
+            &own_messages,
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.unread_threads().count(), MAX_THREADS - 20);
+        assert_eq!(read_receipts.threads.len(), MAX_THREADS - 20 + MAX_READ_THREADS);
+        assert!(read_receipts.threads.contains_key(event_id!("$root119")));
+        assert!(!read_receipts.threads.contains_key(event_id!("$root100")));
+    }
+
+    #[test]
+    fn test_compute_unread_threads() {
+        let user_id = user_id!("@alice:example.org");
+        let other_user_id = user_id!("@bob:example.org");
+        let room_id = room_id!("!room:example.org");
+
+        // A thread root with a summary, and a message in another thread.
+        let root = SyncTimelineEvent::new(sync_timeline_event!({
+            "sender": other_user_id,
+            "type": "m.room.message",
+            "event_id": "$root1",
+            "origin_server_ts": 42,
+            "content": { "body": "Root", "msgtype": "m.text" },
+            "unsigned": {
+                "m.relations": {
+                    "m.thread": {
+                        "latest_event": {
+                            "sender": other_user_id,
+                            "type": "m.room.message",
+                            "event_id": "$latest1",
+                            "origin_server_ts": 42,
+                            "content": { "body": "Latest", "msgtype": "m.text" },
+                        },
+                        "count": 2,
+                        "current_user_participated": false,
+                    },
+                },
+            },
+        }));
+        let in_thread = sync_thread_message(other_user_id, "$in_thread2", "$root2");
+        let events = [root, in_thread];
+
+        let mut read_receipts = RoomReadReceipts::default();
+        compute_unread_counts(user_id, room_id, None, Vector::new(), &events, &mut read_receipts);
+
+        assert!(read_receipts.has_unread_threads());
+        assert_eq!(
+            read_receipts.unread_threads().collect::<Vec<_>>(),
+            vec![event_id!("$root1"), event_id!("$root2")]
+        );
+
+        // A thread receipt on the latest event of the first thread marks it as read.
+        let receipt_event = EventBuilder::new().make_receipt_event_content([(
+            owned_event_id!("$latest1"),
+            ReceiptType::Read,
+            user_id.to_owned(),
+            ReceiptThread::Thread(owned_event_id!("$root1")),
+        )]);
+        compute_unread_counts(
+            user_id,
+            room_id,
+            Some(&receipt_event),
+            events.iter().cloned().collect(),
+            &[],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.unread_threads().collect::<Vec<_>>(), vec![event_id!("$root2")]);
+
+        // Sending a message in the second thread marks it as read.
+        let own_message = sync_thread_message(user_id, "$own", "$root2");
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            events.iter().cloned().collect(),
+            &[own_message],
+            &mut read_receipts,
+        );
+
+        assert!(!read_receipts.has_unread_threads());
+
+        // A new message in the first thread makes it unread again.
+        let new_message = sync_thread_message(other_user_id, "$new", "$root1");
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            Vector::new(),
+            &[new_message],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.unread_threads().collect::<Vec<_>>(), vec![event_id!("$root1")]);
+    }
 }
@@ -19,14 +19,15 @@ use std::{
 
 use ruma::{
     events::{
-        direct::OwnedDirectUserIdentifier, AnyGlobalAccountDataEvent, GlobalAccountDataEventType,
+        direct::OwnedDirectUserIdentifier, AnyGlobalAccountDataEvent, GlobalAccountDataEvent,
+        GlobalAccountDataEventType, StaticEventContent,
     },
     serde::Raw,
     RoomId,
 };
 use tracing::{debug, instrument, trace, warn};
 
-use crate::{store::Store, RoomInfo, StateChanges};
+use crate::{store::Store, HiddenRoomsEventContent, RoomInfo, StateChanges};
 
 /// Applies a function to an existing `RoomInfo` if present in changes, or one
 /// loaded from the database.
@@ -135,6 +136,48 @@ impl AccountDataProcessor {
         }
     }
 
+    /// Processes the hidden rooms:
+    ///
+    /// Given a [`StateChanges`] instance, marks the rooms listed in the
+    /// [`HiddenRoomsEventContent`] as hidden, and the other rooms as not
+    /// hidden anymore.
+    #[instrument(skip_all)]
+    fn process_hidden_rooms(
+        content: &HiddenRoomsEventContent,
+        store: &Store,
+        changes: &mut StateChanges,
+    ) {
+        for room_id in &content.rooms {
+            let is_hidden = changes
+                .room_infos
+                .get(room_id)
+                .map(|info| info.base_info.is_hidden)
+                .or_else(|| store.room(room_id).map(|room| room.is_hidden()));
+
+            if is_hidden == Some(false) {
+                trace!(?room_id, "Marking room as hidden");
+                map_info(room_id, changes, store, |info| {
+                    info.base_info.is_hidden = true;
+                });
+            }
+        }
+
+        // Unhide the rooms that aren't listed anymore.
+        let unhidden_rooms = store
+            .rooms()
+            .into_iter()
+            .filter(|room| room.is_hidden() && !content.rooms.contains(room.room_id()))
+            .map(|room| room.room_id().to_owned())
+            .collect::<Vec<_>>();
+
+        for room_id in unhidden_rooms {
+            trace!(?room_id, "Unmarking room as hidden");
+            map_info(&room_id, changes, store, |info| {
+                info.base_info.is_hidden = false;
+            });
+        }
+    }
+
     /// Applies the processed data to the state changes.
     pub async fn apply(mut self, changes: &mut StateChanges, store: &Store) {
         // Fill in the content of `changes.account_data`.
@@ -158,5 +201,21 @@ impl AccountDataProcessor {
                 warn!("Failed to deserialize direct room account data");
             }
         }
+
+        // Process hidden rooms. Like for the direct rooms, the stored list is applied
+        // when nothing new has been received, so that new rooms are hidden too.
+        let hidden_rooms_event_type =
+            GlobalAccountDataEventType::from(HiddenRoomsEventContent::TYPE);
+        let hidden_rooms = match changes.account_data.get(&hidden_rooms_event_type) {
+            Some(raw) => Some(raw.clone()),
+            None => store.get_account_data_event(hidden_rooms_event_type).await.ok().flatten(),
+        };
+
+        if let Some(raw) = hidden_rooms {
+            match raw.deserialize_as::<GlobalAccountDataEvent<HiddenRoomsEventContent>>() {
+                Ok(event) => Self::process_hidden_rooms(&event.content, store, changes),
+                Err(error) => warn!("Failed to deserialize hidden rooms account data: {error}"),
+            }
+        }
     }
 }
@@ -4,12 +4,14 @@ mod members;
 pub(crate) mod normal;
 
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt,
     hash::Hash,
+    sync::{Arc, RwLock as StdRwLock},
 };
 
 use bitflags::bitflags;
+use matrix_sdk_common::AsyncTraitDeps;
 pub use members::RoomMember;
 pub use normal::{
     Room, RoomHero, RoomInfo, RoomInfoNotableUpdate, RoomInfoNotableUpdateReasons,
@@ -42,7 +44,7 @@ use ruma::{
         RedactedStateEventContent, StaticStateEventContent, SyncStateEvent,
     },
     room::RoomType,
-    EventId, OwnedUserId, RoomVersionId,
+    EventId, OwnedRoomId, OwnedUserId, RoomVersionId,
 };
 use serde::{Deserialize, Serialize};
 
@@ -98,6 +100,33 @@ impl RoomDisplayName {
     }
 }
 
+/// A custom strategy to compute the display names of the rooms, registered with
+/// [`BaseClient::set_room_display_name_resolver()`].
+///
+/// The default computation, from the name, the canonical alias or the heroes of
+/// the room, doesn't fit all the products. For example, a bridge might want to
+/// use the name of the room on the remote network instead.
+///
+/// The result of the resolver is cached like the computed display names, and is
+/// computed again when the state or the members of the room change.
+///
+/// [`BaseClient::set_room_display_name_resolver()`]: crate::BaseClient::set_room_display_name_resolver
+pub trait RoomDisplayNameResolver: AsyncTraitDeps {
+    /// Get the display name of the given room.
+    ///
+    /// `computed` is the display name computed by the SDK. It can be returned
+    /// as is, post-processed or overridden entirely.
+    ///
+    /// This is called with no lock held on the room, so the data of the room
+    /// can be read. It should be fast though, since it's called for every room
+    /// with updates after each sync.
+    fn resolve_display_name(&self, room: &Room, computed: RoomDisplayName) -> RoomDisplayName;
+}
+
+/// The [`RoomDisplayNameResolver`] of a client, shared by all its rooms.
+pub(crate) type SharedRoomDisplayNameResolver =
+    Arc<StdRwLock<Option<Arc<dyn RoomDisplayNameResolver>>>>;
+
 impl fmt::Display for RoomDisplayName {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -153,6 +182,10 @@ pub struct BaseRoomInfo {
     /// Whether this room has been manually marked as unread.
     #[serde(default)]
     pub(crate) is_marked_unread: bool,
+    /// Whether this room is listed in the [`HiddenRoomsEventContent`] of the
+    /// user.
+    #[serde(default)]
+    pub(crate) is_hidden: bool,
     /// Some notable tags.
     ///
     /// We are not interested by all the tags. Some tags are more important than
@@ -353,6 +386,10 @@ impl BaseRoomInfo {
             notable_tags.insert(RoomNotableTags::LOW_PRIORITY);
         }
 
+        if tags.contains_key(&TagName::ServerNotice) {
+            notable_tags.insert(RoomNotableTags::SERVER_NOTICE);
+        }
+
         self.notable_tags = notable_tags;
     }
 }
@@ -363,13 +400,16 @@ bitflags! {
     /// We are not interested by all the tags. Some tags are more important than
     /// others, and this struct describes them.
     #[repr(transparent)]
-    #[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
     pub(crate) struct RoomNotableTags: u8 {
         /// The `m.favourite` tag.
         const FAVOURITE = 0b0000_0001;
 
         /// THe `m.lowpriority` tag.
         const LOW_PRIORITY = 0b0000_0010;
+
+        /// The `m.server_notice` tag.
+        const SERVER_NOTICE = 0b0000_0100;
     }
 }
 
@@ -405,6 +445,7 @@ impl Default for BaseRoomInfo {
             topic: None,
             rtc_member_events: BTreeMap::new(),
             is_marked_unread: false,
+            is_hidden: false,
             notable_tags: RoomNotableTags::empty(),
             pinned_events: None,
         }
@@ -504,6 +545,27 @@ fn default_create_room_version_id() -> RoomVersionId {
     RoomVersionId::V1
 }
 
+/// The content of the custom `org.matrix.custom.hidden_rooms` global account
+/// data event, listing the rooms that the user has hidden from their room
+/// list.
+///
+/// Hidden rooms are still synced like any other room, they are only left out
+/// of the room list. See [`Room::is_hidden()`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "org.matrix.custom.hidden_rooms", kind = GlobalAccountData)]
+pub struct HiddenRoomsEventContent {
+    /// The IDs of the hidden rooms.
+    #[serde(default)]
+    pub rooms: BTreeSet<OwnedRoomId>,
+}
+
+impl HiddenRoomsEventContent {
+    /// Create a new `HiddenRoomsEventContent` with the given hidden rooms.
+    pub fn new(rooms: BTreeSet<OwnedRoomId>) -> Self {
+        Self { rooms }
+    }
+}
+
 bitflags! {
     /// Room membership filter as a bitset.
     ///
@@ -609,6 +671,21 @@ mod tests {
         assert!(base_room_info.notable_tags.contains(RoomNotableTags::LOW_PRIORITY).not());
     }
 
+    #[test]
+    fn test_handle_notable_tags_server_notice() {
+        let mut base_room_info = BaseRoomInfo::default();
+
+        let mut tags = Tags::new();
+        tags.insert(TagName::ServerNotice, TagInfo::default());
+
+        assert!(base_room_info.notable_tags.contains(RoomNotableTags::SERVER_NOTICE).not());
+        base_room_info.handle_notable_tags(&tags);
+        assert!(base_room_info.notable_tags.contains(RoomNotableTags::SERVER_NOTICE));
+        tags.clear();
+        base_room_info.handle_notable_tags(&tags);
+        assert!(base_room_info.notable_tags.contains(RoomNotableTags::SERVER_NOTICE).not());
+    }
+
     #[test]
     fn test_room_alias_from_room_display_name_lowercases() {
         assert_eq!(
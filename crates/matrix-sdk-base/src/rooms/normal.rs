@@ -16,7 +16,7 @@
 use std::sync::RwLock as SyncRwLock;
 use std::{
     collections::{BTreeMap, BTreeSet, HashSet},
-    mem,
+    future, mem,
     sync::{atomic::AtomicBool, Arc},
 };
 
@@ -62,7 +62,7 @@ use tracing::{debug, field::debug, info, instrument, trace, warn};
 
 use super::{
     members::MemberRoomInfo, BaseRoomInfo, RoomCreateWithCreatorEventContent, RoomDisplayName,
-    RoomMember, RoomNotableTags,
+    RoomMember, RoomNotableTags, SharedRoomDisplayNameResolver,
 };
 use crate::{
     deserialized_responses::{
@@ -70,7 +70,7 @@ use crate::{
     },
     latest_event::LatestEvent,
     notification_settings::RoomNotificationMode,
-    read_receipts::RoomReadReceipts,
+    read_receipts::{RoomReadReceipts, UnreadMentions},
     store::{DynStateStore, Result as StoreResult, StateStoreExt},
     sync::UnreadNotificationsCount,
     Error, MinimalStateEvent, OriginalMinimalStateEvent, RoomMemberships, StateStoreDataKey,
@@ -113,6 +113,10 @@ bitflags! {
 
         /// A membership change happened for the current user.
         const MEMBERSHIP = 0b0001_0000;
+
+        /// The notable tags of the `Room` have changed, i.e. it was added to or
+        /// removed from the favourites or the low priority rooms.
+        const NOTABLE_TAGS = 0b0010_0000;
     }
 }
 
@@ -173,6 +177,9 @@ pub struct Room {
 
     /// A sender that will notify receivers when room member updates happen.
     pub room_member_updates_sender: broadcast::Sender<RoomMembersUpdate>,
+
+    /// The custom strategy to compute the display name of the room, if any.
+    display_name_resolver: SharedRoomDisplayNameResolver,
 }
 
 /// The room summary containing member counts and members that should be used to
@@ -307,9 +314,20 @@ impl Room {
             room_info_notable_update_sender,
             seen_knock_request_ids_map: SharedObservable::new_async(None),
             room_member_updates_sender,
+            display_name_resolver: Default::default(),
         }
     }
 
+    /// Use the given [`RoomDisplayNameResolver`](super::RoomDisplayNameResolver)
+    /// to compute the display name of this room.
+    pub(crate) fn with_display_name_resolver(
+        mut self,
+        resolver: SharedRoomDisplayNameResolver,
+    ) -> Self {
+        self.display_name_resolver = resolver;
+        self
+    }
+
     /// Get the unique room id of the room.
     pub fn room_id(&self) -> &RoomId {
         &self.room_id
@@ -381,6 +399,44 @@ impl Room {
         self.inner.read().read_receipts.num_mentions
     }
 
+    /// Get the unread mentions of the room (computed client-side), that is,
+    /// the number and the ids of the unread events causing a highlight.
+    ///
+    /// Unlike [`Self::num_unread_notifications`], this only takes into account
+    /// the events that mention the user, to show a mention badge.
+    pub fn unread_mentions(&self) -> UnreadMentions {
+        self.inner.read().read_receipts.unread_mentions()
+    }
+
+    /// Whether any thread of the room has unread activity (computed
+    /// client-side), from the thread receipts and the thread summaries.
+    pub fn has_unread_threads(&self) -> bool {
+        self.inner.read().read_receipts.has_unread_threads()
+    }
+
+    /// Get the roots of the threads of the room with unread activity
+    /// (computed client-side).
+    pub fn unread_threads(&self) -> Vec<OwnedEventId> {
+        self.inner.read().read_receipts.unread_threads().map(ToOwned::to_owned).collect()
+    }
+
+    /// Get a `Stream` of the roots of the threads of the room with unread
+    /// activity, that yields a new list every time it changes.
+    pub fn unread_threads_stream(&self) -> impl Stream<Item = Vec<OwnedEventId>> {
+        let mut unread_threads = self.unread_threads();
+
+        self.inner.subscribe().filter_map(move |info| {
+            let new_unread_threads: Vec<_> =
+                info.read_receipts.unread_threads().map(ToOwned::to_owned).collect();
+
+            let update = (new_unread_threads != unread_threads).then(|| {
+                unread_threads = new_unread_threads.clone();
+                new_unread_threads
+            });
+            future::ready(update)
+        })
+    }
+
     /// Check if the room has its members fully synced.
     ///
     /// Members might be missing if lazy member loading was enabled for the
@@ -644,7 +700,10 @@ impl Room {
     /// Force recalculating a room's display name, taking into account its name,
     /// aliases and members.
     ///
-    /// The display name is calculated according to [this algorithm][spec].
+    /// The display name is calculated according to [this algorithm][spec], and
+    /// then passed to the
+    /// [`RoomDisplayNameResolver`](super::RoomDisplayNameResolver) of the
+    /// client, if any.
     ///
     /// ⚠ This may be slowish to compute. As such, the result is cached and can
     /// be retrieved via [`Room::cached_display_name`] (sync, returns an option)
@@ -685,6 +744,12 @@ impl Room {
             DisplayNameOrSummary::DisplayName(display_name) => display_name,
         };
 
+        let resolver = self.display_name_resolver.read().unwrap().clone();
+        let display_name = match resolver {
+            Some(resolver) => resolver.resolve_display_name(self, display_name),
+            None => display_name,
+        };
+
         // Update the cached display name before we return the newly computed value.
         self.inner.update_if(|info| {
             if info.cached_display_name.as_ref() != Some(&display_name) {
@@ -698,6 +763,12 @@ impl Room {
         Ok(display_name)
     }
 
+    /// Clear the cached display name of the room, so it is computed again the
+    /// next time it is requested with [`Room::display_name`].
+    pub(crate) fn clear_cached_display_name(&self) {
+        self.inner.update_if(|info| info.cached_display_name.take().is_some());
+    }
+
     /// Compute a [`RoomDisplayName`] from the given [`RoomSummary`].
     async fn compute_display_name_from_summary(
         &self,
@@ -1156,6 +1227,14 @@ impl Room {
         self.inner.read().base_info.notable_tags.contains(RoomNotableTags::LOW_PRIORITY)
     }
 
+    /// Check whether the room is the server notices room.
+    ///
+    /// The homeserver marks the room it sends its notices in with the
+    /// `m.server_notice` tag.
+    pub fn is_server_notice(&self) -> bool {
+        self.inner.read().base_info.notable_tags.contains(RoomNotableTags::SERVER_NOTICE)
+    }
+
     /// Get the receipt as an `OwnedEventId` and `Receipt` tuple for the given
     /// `receipt_type`, `thread` and `user_id` in this room.
     pub async fn load_user_receipt(
@@ -1187,6 +1266,14 @@ impl Room {
         self.inner.read().base_info.is_marked_unread
     }
 
+    /// Returns whether the user has hidden this room from their room list,
+    /// with the [`HiddenRoomsEventContent`] global account data.
+    ///
+    /// [`HiddenRoomsEventContent`]: crate::HiddenRoomsEventContent
+    pub fn is_hidden(&self) -> bool {
+        self.inner.read().base_info.is_hidden
+    }
+
     /// Returns the recency stamp of the room.
     ///
     /// Please read `RoomInfo::recency_stamp` to learn more.
@@ -2193,7 +2280,7 @@ mod tests {
         store::{IntoStateStore, MemoryStore, StateChanges, StateStore, StoreConfig},
         test_utils::logged_in_base_client,
         BaseClient, MinimalStateEvent, OriginalMinimalStateEvent, RoomDisplayName,
-        RoomInfoNotableUpdateReasons, RoomStateFilter, SessionMeta,
+        RoomDisplayNameResolver, RoomInfoNotableUpdateReasons, RoomStateFilter, SessionMeta,
     };
 
     #[test]
@@ -2276,6 +2363,7 @@ mod tests {
                 "encryption": null,
                 "guest_access": null,
                 "history_visibility": null,
+                "is_hidden": false,
                 "is_marked_unread": false,
                 "join_rules": null,
                 "max_power_level": 100,
@@ -2291,7 +2379,9 @@ mod tests {
                 "num_mentions": 0,
                 "num_notifications": 0,
                 "latest_active": null,
-                "pending": []
+                "pending": [],
+                "mention_event_ids": [],
+                "threads": {}
             },
             "recency_stamp": 42,
         });
@@ -2690,6 +2780,60 @@ mod tests {
         );
     }
 
+    #[derive(Debug)]
+    struct BridgeDisplayNameResolver;
+
+    impl RoomDisplayNameResolver for BridgeDisplayNameResolver {
+        fn resolve_display_name(&self, room: &Room, computed: RoomDisplayName) -> RoomDisplayName {
+            match computed {
+                RoomDisplayName::Named(name) => RoomDisplayName::Named(format!("[bridge] {name}")),
+                _ => RoomDisplayName::Calculated(format!("Remote {}", room.room_id())),
+            }
+        }
+    }
+
+    #[async_test]
+    async fn test_display_name_resolver() {
+        let client = logged_in_base_client(None).await;
+        let room_id = room_id!("!test:localhost");
+        let room = client.get_or_create_room(room_id, RoomState::Joined);
+
+        assert_eq!(room.display_name().await.unwrap(), RoomDisplayName::Empty);
+
+        // Setting a resolver clears the cached display name.
+        client.set_room_display_name_resolver(Some(Arc::new(BridgeDisplayNameResolver)));
+        assert_eq!(room.cached_display_name(), None);
+        assert_eq!(
+            room.display_name().await.unwrap(),
+            RoomDisplayName::Calculated("Remote !test:localhost".to_owned())
+        );
+
+        // The resolver receives the computed display name.
+        room.inner.update(|info| info.base_info.name = Some(make_name_event()));
+        assert_eq!(
+            room.compute_display_name().await.unwrap(),
+            RoomDisplayName::Named("[bridge] Test Room".to_owned())
+        );
+        assert_eq!(
+            room.cached_display_name(),
+            Some(RoomDisplayName::Named("[bridge] Test Room".to_owned()))
+        );
+
+        // The rooms created afterwards use the resolver too.
+        let other_room = client.get_or_create_room(room_id!("!other:localhost"), RoomState::Joined);
+        assert_eq!(
+            other_room.display_name().await.unwrap(),
+            RoomDisplayName::Calculated("Remote !other:localhost".to_owned())
+        );
+
+        // Removing the resolver goes back to the default computation.
+        client.set_room_display_name_resolver(None);
+        assert_eq!(
+            room.display_name().await.unwrap(),
+            RoomDisplayName::Named("Test Room".to_owned())
+        );
+    }
+
     #[async_test]
     async fn test_display_name_for_invited_room_is_empty_if_no_info() {
         let (_, room) = make_room_test_helper(RoomState::Invited);
@@ -29,11 +29,12 @@ use ruma::{
         AnySyncStateEvent,
     },
     serde::Raw,
+    time::Instant,
     JsOption, OwnedRoomId, RoomId, UInt, UserId,
 };
 #[cfg(feature = "e2e-encryption")]
 use ruma::{api::client::sync::sync_events::v5, events::AnyToDeviceEvent, events::StateEventType};
-use tracing::{debug, error, instrument, trace, warn};
+use tracing::{debug, debug_span, error, instrument, trace, warn, Instrument};
 
 use super::BaseClient;
 use crate::{
@@ -45,8 +46,11 @@ use crate::{
         RoomState,
     },
     ruma::assign,
-    store::{ambiguity_map::AmbiguityCache, StateChanges, Store},
-    sync::{JoinedRoomUpdate, LeftRoomUpdate, Notification, RoomUpdates, SyncResponse},
+    store::{ambiguity_map::AmbiguityCache, StateChanges, Store, StoreTransaction},
+    sync::{
+        JoinedRoomUpdate, LeftRoomUpdate, Notification, RoomUpdates, SyncProcessingReport,
+        SyncResponse,
+    },
     Room, RoomInfo,
 };
 #[cfg(feature = "e2e-encryption")]
@@ -64,6 +68,7 @@ impl BaseClient {
     /// store.
     ///
     /// Returns whether any change happened.
+    #[instrument(skip_all)]
     pub async fn process_sliding_sync_e2ee(
         &self,
         to_device: Option<&v5::response::ToDevice>,
@@ -108,7 +113,7 @@ impl BaseClient {
             .await?;
 
         trace!("ready to submit e2ee changes to store");
-        self.store.save_changes(&changes).await?;
+        self.store.save_changes(&changes).instrument(debug_span!("save_changes")).await?;
         self.apply_changes(&changes, room_info_notable_updates);
         trace!("applied e2ee changes");
 
@@ -157,11 +162,14 @@ impl BaseClient {
             return Ok(SyncResponse::default());
         };
 
+        let now = Instant::now();
+        let mut processing_report = SyncProcessingReport::default();
         let mut changes = StateChanges::default();
         let mut room_info_notable_updates =
             BTreeMap::<OwnedRoomId, RoomInfoNotableUpdateReasons>::new();
 
         let store = self.store.clone();
+        let mut transaction = StoreTransaction::new(&store);
         let mut ambiguity_cache = AmbiguityCache::new(store.inner.clone());
 
         let account_data_processor = AccountDataProcessor::process(&extensions.account_data.global);
@@ -183,6 +191,7 @@ impl BaseClient {
                     response_room_data,
                     &mut rooms_account_data,
                     &store,
+                    &mut transaction,
                     &user_id,
                     &account_data_processor,
                     &mut changes,
@@ -326,11 +335,22 @@ impl BaseClient {
 
         changes.ambiguity_maps = ambiguity_cache.cache;
 
+        processing_report.rooms = now.elapsed();
+        let store_start = Instant::now();
+
         trace!("ready to submit changes to store");
-        store.save_changes(&changes).await?;
-        self.apply_changes(&changes, room_info_notable_updates);
+        // If anything failed before this point, dropping the transaction has rolled back
+        // the rooms created in memory. The caller already holds the sync lock.
+        transaction
+            .commit_with_sync_lock_held(&changes, || {
+                self.apply_changes(&changes, room_info_notable_updates)
+            })
+            .instrument(debug_span!("save_changes", rooms = changes.room_infos.len()))
+            .await?;
         trace!("applied changes");
 
+        processing_report.store = store_start.elapsed();
+
         // Now that all the rooms information have been saved, update the display name
         // cache (which relies on information stored in the database). This will
         // live in memory, until the next sync which will saves the room info to
@@ -338,6 +358,15 @@ impl BaseClient {
         // above. Oh well.
         new_rooms.update_in_memory_caches(&self.store).await;
 
+        processing_report.total = now.elapsed();
+
+        debug!(
+            rooms = ?processing_report.rooms,
+            store = ?processing_report.store,
+            "Processed a sliding sync response in {:?}",
+            processing_report.total
+        );
+
         Ok(SyncResponse {
             rooms: new_rooms,
             notifications,
@@ -345,16 +374,19 @@ impl BaseClient {
             presence: Default::default(),
             account_data: extensions.account_data.global.clone(),
             to_device: Default::default(),
+            processing_report,
         })
     }
 
     #[allow(clippy::too_many_arguments)]
+    #[instrument(skip_all, fields(room_id = ?room_id))]
     async fn process_sliding_sync_room(
         &self,
         room_id: &RoomId,
         room_data: &http::response::Room,
         rooms_account_data: &mut BTreeMap<OwnedRoomId, Vec<Raw<AnyRoomAccountDataEvent>>>,
         store: &Store,
+        transaction: &mut StoreTransaction<'_>,
         user_id: &UserId,
         account_data_processor: &AccountDataProcessor,
         changes: &mut StateChanges,
@@ -434,6 +466,7 @@ impl BaseClient {
                 &state_events,
                 stripped_state.as_ref(),
                 store,
+                transaction,
                 user_id,
                 room_id,
                 room_info_notable_updates,
@@ -574,12 +607,17 @@ impl BaseClient {
         state_events: &[AnySyncStateEvent],
         stripped_state: Option<&Vec<(Raw<AnyStrippedStateEvent>, AnyStrippedStateEvent)>>,
         store: &Store,
+        transaction: &mut StoreTransaction<'_>,
         user_id: &UserId,
         room_id: &RoomId,
         room_info_notable_updates: &mut BTreeMap<OwnedRoomId, RoomInfoNotableUpdateReasons>,
     ) -> (Room, RoomInfo, Option<InvitedRoom>, Option<KnockedRoom>) {
-        if let Some(stripped_state) = stripped_state {
-            let room = store.get_or_create_room(
+        let previous_state = store.room(room_id).map(|room| room.state());
+
+        let (room, room_info, invited_room, knocked_room) = if let Some(stripped_state) =
+            stripped_state
+        {
+            let room = transaction.get_or_create_room(
                 room_id,
                 RoomState::Invited,
                 self.room_info_notable_update_sender.clone(),
@@ -597,25 +635,25 @@ impl BaseClient {
                 None
             });
 
-            if let Some(membership_event_content) = membership_event_content {
-                if membership_event_content.membership == MembershipState::Knock {
-                    // If we have a `Knock` membership state, set the room as such
-                    room_info.mark_as_knocked();
-                    let raw_events = stripped_state.iter().map(|(raw, _)| raw.clone()).collect();
-                    let knock_state = assign!(v3::KnockState::default(), { events: raw_events });
-                    let knocked_room =
-                        assign!(KnockedRoom::default(), { knock_state: knock_state });
-                    return (room, room_info, None, Some(knocked_room));
-                }
+            if membership_event_content
+                .is_some_and(|content| content.membership == MembershipState::Knock)
+            {
+                // If we have a `Knock` membership state, set the room as such
+                room_info.mark_as_knocked();
+                let raw_events = stripped_state.iter().map(|(raw, _)| raw.clone()).collect();
+                let knock_state = assign!(v3::KnockState::default(), { events: raw_events });
+                let knocked_room = assign!(KnockedRoom::default(), { knock_state: knock_state });
+                (room, room_info, None, Some(knocked_room))
+            } else {
+                // Otherwise assume it's an invited room
+                room_info.mark_as_invited();
+                let raw_events =
+                    stripped_state.iter().map(|(raw, _)| raw.clone()).collect::<Vec<_>>();
+                let invited_room = InvitedRoom::from(v3::InviteState::from(raw_events));
+                (room, room_info, Some(invited_room), None)
             }
-
-            // Otherwise assume it's an invited room
-            room_info.mark_as_invited();
-            let raw_events = stripped_state.iter().map(|(raw, _)| raw.clone()).collect::<Vec<_>>();
-            let invited_room = InvitedRoom::from(v3::InviteState::from(raw_events));
-            (room, room_info, Some(invited_room), None)
         } else {
-            let room = store.get_or_create_room(
+            let room = transaction.get_or_create_room(
                 room_id,
                 RoomState::Joined,
                 self.room_info_notable_update_sender.clone(),
@@ -640,7 +678,19 @@ impl BaseClient {
             );
 
             (room, room_info, None, None)
+        };
+
+        // Report the transitions of an existing room, like a knock that was
+        // accepted, which can't be detected from a membership event when the room
+        // moves from the invite state to the timeline.
+        if previous_state.is_some_and(|previous_state| previous_state != room_info.state()) {
+            room_info_notable_updates
+                .entry(room_id.to_owned())
+                .or_default()
+                .insert(RoomInfoNotableUpdateReasons::MEMBERSHIP);
         }
+
+        (room, room_info, invited_room, knocked_room)
     }
 
     /// Find any m.room.member events that refer to the current user, and update
@@ -895,9 +945,12 @@ fn process_room_properties(
 
 #[cfg(all(test, not(target_family = "wasm")))]
 mod tests {
-    use std::collections::{BTreeMap, HashSet};
     #[cfg(feature = "e2e-encryption")]
     use std::sync::{Arc, RwLock as SyncRwLock};
+    use std::{
+        collections::{BTreeMap, HashSet},
+        time::Duration,
+    };
 
     use assert_matches::assert_matches;
     use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
@@ -1010,6 +1063,25 @@ mod tests {
         assert!(!sync_resp.rooms.invite.contains_key(room_id));
     }
 
+    #[async_test]
+    async fn test_sliding_sync_processing_report() {
+        let client = logged_in_base_client(None).await;
+        let room_id = room_id!("!r:e.uk");
+
+        let response = response_with_room(room_id, http::response::Room::new());
+        let report = client
+            .process_sliding_sync(&response, &(), true)
+            .await
+            .expect("Failed to process sync")
+            .processing_report;
+
+        // The to-device events are processed separately, and the other stages are
+        // all part of the total.
+        assert_eq!(report.to_device, Duration::ZERO);
+        assert!(report.total > Duration::ZERO);
+        assert!(report.rooms + report.store <= report.total);
+    }
+
     #[async_test]
     async fn test_missing_room_name_event() {
         // Given a logged-in client
@@ -2438,6 +2510,40 @@ mod tests {
         );
     }
 
+    #[async_test]
+    async fn test_accepted_knock_triggers_a_membership_notable_update_reason() {
+        // Given a logged-in client, with a knocked room,
+        let client = logged_in_base_client(None).await;
+        let mut room_info_notable_update_stream = client.room_info_notable_update_receiver();
+        let room_id = room_id!("!r:e.uk");
+        let user_id = client.session_meta().unwrap().user_id.to_owned();
+
+        let mut room = http::response::Room::new();
+        set_room_knocked(&mut room, &user_id);
+        let response = response_with_room(room_id, room);
+        client.process_sliding_sync(&response, &(), true).await.expect("Failed to process sync");
+        assert_eq!(client.get_room(room_id).unwrap().state(), RoomState::Knocked);
+
+        // Discard the room info update of the knock.
+        let _ = room_info_notable_update_stream.recv().await;
+
+        // When the knock is accepted and the room is joined,
+        let mut room = http::response::Room::new();
+        set_room_joined(&mut room, &user_id);
+        let response = response_with_room(room_id, room);
+        client.process_sliding_sync(&response, &(), true).await.expect("Failed to process sync");
+
+        // Then the room is joined, and a room info notable update is received.
+        assert_eq!(client.get_room(room_id).unwrap().state(), RoomState::Joined);
+        assert_matches!(
+            room_info_notable_update_stream.recv().await,
+            Ok(RoomInfoNotableUpdate { room_id: received_room_id, reasons: received_reasons }) => {
+                assert_eq!(received_room_id, room_id);
+                assert!(received_reasons.contains(RoomInfoNotableUpdateReasons::MEMBERSHIP));
+            }
+        );
+    }
+
     #[async_test]
     async fn test_unread_marker_can_trigger_a_notable_update_reason() {
         // Given a logged-in client,
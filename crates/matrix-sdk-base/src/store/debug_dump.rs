@@ -0,0 +1,350 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of the state of a room from a state store, to share a reproduction
+//! of a state-calculation bug without leaking the content of the room.
+
+use ruma::{
+    canonical_json::redact_in_place,
+    events::{
+        receipt::{ReceiptThread, ReceiptType},
+        StateEventType,
+    },
+    CanonicalJsonObject, RoomId, RoomVersionId,
+};
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+
+use super::StateStore;
+use crate::{deserialized_responses::RawAnySyncOrStrippedState, RoomMemberships};
+
+/// The placeholder of the values removed from a dump.
+const REDACTED: &str = "<redacted>";
+
+/// The types of the state events exported in a dump.
+const DUMPED_STATE_EVENT_TYPES: &[StateEventType] = &[
+    StateEventType::RoomCreate,
+    StateEventType::RoomPowerLevels,
+    StateEventType::RoomJoinRules,
+    StateEventType::RoomHistoryVisibility,
+    StateEventType::RoomGuestAccess,
+    StateEventType::RoomEncryption,
+    StateEventType::RoomName,
+    StateEventType::RoomTopic,
+    StateEventType::RoomAvatar,
+    StateEventType::RoomCanonicalAlias,
+    StateEventType::RoomMember,
+    StateEventType::RoomThirdPartyInvite,
+    StateEventType::RoomPinnedEvents,
+    StateEventType::RoomTombstone,
+    StateEventType::RoomServerAcl,
+    StateEventType::SpaceChild,
+    StateEventType::SpaceParent,
+];
+
+/// The keys of the [`RoomInfo`](crate::RoomInfo) whose values are removed
+/// from a dump with the content redacted.
+const SENSITIVE_ROOM_INFO_KEYS: &[&str] =
+    &["latest_event", "name", "topic", "avatar_url", "url", "display_name", "cached_display_name"];
+
+/// How much of the data of a room is hidden in a [`RoomStateDump`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum DumpRedactionLevel {
+    /// The data is kept as is.
+    ///
+    /// The dump contains the names, topics and profiles of the room, so it
+    /// shouldn't be shared publicly.
+    None,
+
+    /// The state events are redacted with the algorithm of the version of the
+    /// room, which only keeps what is needed to compute the state. The name,
+    /// the topic and the latest event of the room are removed.
+    Content,
+
+    /// Like [`DumpRedactionLevel::Content`], and the user, room and event IDs
+    /// and the room aliases are replaced by hashes, keeping their server
+    /// names.
+    ///
+    /// The same ID is always replaced by the same hash, so the dump is still
+    /// consistent. The hashes aren't salted, so an ID can still be matched
+    /// against a known one.
+    Identifiers,
+}
+
+/// An export of the state of a room, returned by
+/// [`StateStore::debug_dump()`].
+///
+/// It is meant to be serialized to JSON and attached to a bug report.
+#[derive(Clone, Debug, Serialize)]
+pub struct RoomStateDump {
+    /// How much of the data of the room is hidden.
+    pub redaction_level: DumpRedactionLevel,
+
+    /// The ID of the room.
+    pub room_id: String,
+
+    /// The serialized [`RoomInfo`](crate::RoomInfo) of the room, if the store
+    /// knows about it.
+    pub room_info: Option<JsonValue>,
+
+    /// The state events of the room.
+    pub state_events: Vec<JsonValue>,
+
+    /// The read receipts of the members of the room.
+    pub receipts: Vec<JsonValue>,
+}
+
+/// Export the state of the given room from the store.
+pub(super) async fn dump_room<S: StateStore + ?Sized>(
+    store: &S,
+    room_id: &RoomId,
+    redaction_level: DumpRedactionLevel,
+) -> Result<RoomStateDump, S::Error> {
+    let room_info =
+        store.get_room_infos().await?.into_iter().find(|room_info| room_info.room_id == room_id);
+    let room_version = room_info
+        .as_ref()
+        .and_then(|room_info| room_info.room_version().cloned())
+        .unwrap_or(RoomVersionId::V1);
+
+    let mut room_info = room_info.map(|room_info| serde_json::to_value(room_info)).transpose()?;
+    if redaction_level != DumpRedactionLevel::None {
+        if let Some(room_info) = &mut room_info {
+            redact_keys(room_info, SENSITIVE_ROOM_INFO_KEYS);
+        }
+    }
+
+    let mut state_events = Vec::new();
+    for event_type in DUMPED_STATE_EVENT_TYPES {
+        for event in store.get_state_events(room_id, event_type.clone()).await? {
+            let json = match &event {
+                RawAnySyncOrStrippedState::Sync(raw) => raw.json(),
+                RawAnySyncOrStrippedState::Stripped(raw) => raw.json(),
+            };
+
+            let event: JsonValue = if redaction_level == DumpRedactionLevel::None {
+                serde_json::from_str(json.get())?
+            } else {
+                redact_event(json.get(), &room_version)
+            };
+            state_events.push(event);
+        }
+    }
+
+    let mut receipts = Vec::new();
+    for user_id in store.get_user_ids(room_id, RoomMemberships::empty()).await? {
+        for receipt_type in [ReceiptType::Read, ReceiptType::ReadPrivate] {
+            for thread in [ReceiptThread::Unthreaded, ReceiptThread::Main] {
+                let Some((event_id, receipt)) = store
+                    .get_user_room_receipt_event(
+                        room_id,
+                        receipt_type.clone(),
+                        thread.clone(),
+                        &user_id,
+                    )
+                    .await?
+                else {
+                    continue;
+                };
+
+                receipts.push(json!({
+                    "user_id": user_id,
+                    "receipt_type": receipt_type,
+                    "thread": thread.as_str(),
+                    "event_id": event_id,
+                    "ts": receipt.ts,
+                }));
+            }
+        }
+    }
+
+    let mut dump = RoomStateDump {
+        redaction_level,
+        room_id: room_id.to_string(),
+        room_info,
+        state_events,
+        receipts,
+    };
+
+    if redaction_level == DumpRedactionLevel::Identifiers {
+        dump.room_id = hash_identifier(&dump.room_id).unwrap_or(dump.room_id);
+        for value in
+            dump.room_info.iter_mut().chain(&mut dump.state_events).chain(&mut dump.receipts)
+        {
+            hash_identifiers(value);
+        }
+    }
+
+    Ok(dump)
+}
+
+/// Redact the given event with the redaction algorithm of the room version.
+///
+/// If the event can't be redacted, only its type, its state key and its sender
+/// are kept.
+fn redact_event(json: &str, room_version: &RoomVersionId) -> JsonValue {
+    let Ok(mut event) = serde_json::from_str::<CanonicalJsonObject>(json) else {
+        let event: Option<JsonValue> = serde_json::from_str(json).ok();
+        let field = |name: &str| event.as_ref().and_then(|event| event.get(name)).cloned();
+        return json!({
+            "type": field("type"),
+            "state_key": field("state_key"),
+            "sender": field("sender"),
+        });
+    };
+
+    if redact_in_place(&mut event, room_version, None).is_err() {
+        event.retain(|key, _| key == "type" || key == "state_key" || key == "sender");
+    }
+
+    serde_json::to_value(event).unwrap_or_default()
+}
+
+/// Replace the values of the given keys by a placeholder, in all the objects of
+/// the given JSON value.
+fn redact_keys(value: &mut JsonValue, keys: &[&str]) {
+    match value {
+        JsonValue::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if keys.contains(&key.as_str()) {
+                    if !value.is_null() {
+                        *value = REDACTED.into();
+                    }
+                } else {
+                    redact_keys(value, keys);
+                }
+            }
+        }
+        JsonValue::Array(array) => array.iter_mut().for_each(|value| redact_keys(value, keys)),
+        _ => {}
+    }
+}
+
+/// Replace the Matrix IDs in the strings and the keys of the objects of the
+/// given JSON value by hashes.
+fn hash_identifiers(value: &mut JsonValue) {
+    match value {
+        JsonValue::String(string) => {
+            if let Some(hash) = hash_identifier(string) {
+                *string = hash;
+            }
+        }
+        JsonValue::Object(object) => {
+            *object = std::mem::take(object)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    hash_identifiers(&mut value);
+                    (hash_identifier(&key).unwrap_or(key), value)
+                })
+                .collect();
+        }
+        JsonValue::Array(array) => array.iter_mut().for_each(hash_identifiers),
+        _ => {}
+    }
+}
+
+/// Hash the given string if it looks like a user, room or event ID, or a room
+/// alias, keeping its sigil and its server name.
+fn hash_identifier(string: &str) -> Option<String> {
+    let sigil = string.chars().next().filter(|sigil| matches!(sigil, '@' | '!' | '$' | '#'))?;
+    if string.len() < 2 || string.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let hash = Sha256::digest(string.as_bytes());
+    let hash: String = hash[..8].iter().map(|byte| format!("{byte:02x}")).collect();
+
+    Some(match string.split_once(':') {
+        Some((_, server_name)) => format!("{sigil}{hash}:{server_name}"),
+        None => format!("{sigil}{hash}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::{async_test, event_factory::EventFactory};
+    use ruma::{
+        events::{
+            receipt::ReceiptEventContent,
+            room::{member::MembershipState, name::RoomNameEventContent},
+            AnySyncStateEvent,
+        },
+        room_id,
+        serde::Raw,
+        user_id,
+    };
+    use serde_json::{json, Value as JsonValue};
+
+    use super::{hash_identifier, DumpRedactionLevel, REDACTED};
+    use crate::{
+        store::{MemoryStore, StateChanges, StateStore},
+        RoomInfo, RoomState,
+    };
+
+    #[async_test]
+    async fn test_debug_dump() {
+        let store = MemoryStore::new();
+        let room_id = room_id!("!room:localhost");
+        let user_id = user_id!("@alice:localhost");
+        let f = EventFactory::new().room(room_id).sender(user_id);
+
+        let mut changes = StateChanges::default();
+        let mut room_info = RoomInfo::new(room_id, RoomState::Joined);
+        let name_event =
+            f.event(RoomNameEventContent::new("Secret plans".to_owned())).state_key("");
+        let member_event =
+            f.member(user_id).membership(MembershipState::Join).display_name("Alice");
+        for event in [name_event.into_raw_sync(), member_event.into_raw_sync()] {
+            let event: Raw<AnySyncStateEvent> = event.cast();
+            room_info.handle_state_event(&event.deserialize().unwrap());
+            changes.add_state_event(room_id, event.deserialize().unwrap(), event);
+        }
+        changes.add_room(room_info);
+        let receipts: ReceiptEventContent = serde_json::from_value(json!({
+            "$read": { "m.read": { "@alice:localhost": { "ts": 1000 } } },
+        }))
+        .unwrap();
+        changes.add_receipts(room_id, receipts);
+        store.save_changes(&changes).await.unwrap();
+
+        // Without redaction, everything is kept.
+        let dump = store.debug_dump(room_id, DumpRedactionLevel::None).await.unwrap();
+        let dump = serde_json::to_string(&dump).unwrap();
+        assert!(dump.contains("Secret plans"));
+        assert!(dump.contains("Alice"));
+        assert!(dump.contains("$read"));
+
+        // With the content redacted, the name and the profiles are removed.
+        let dump = store.debug_dump(room_id, DumpRedactionLevel::Content).await.unwrap();
+        assert_eq!(dump.state_events.len(), 2);
+        assert_eq!(dump.receipts.len(), 1);
+        assert_eq!(dump.room_info.as_ref().unwrap()["base_info"]["name"], REDACTED);
+        let dump = serde_json::to_string(&dump).unwrap();
+        assert!(!dump.contains("Secret plans"));
+        assert!(!dump.contains("Alice"));
+        assert!(dump.contains("@alice:localhost"));
+
+        // With the IDs hashed, the IDs are replaced consistently.
+        let dump = store.debug_dump(room_id, DumpRedactionLevel::Identifiers).await.unwrap();
+        let hashed_user_id = hash_identifier(user_id.as_str()).unwrap();
+        assert!(hashed_user_id.ends_with(":localhost"));
+        assert_eq!(dump.room_id, hash_identifier(room_id.as_str()).unwrap());
+        assert_eq!(dump.receipts[0]["user_id"], JsonValue::from(hashed_user_id.clone()));
+        let dump = serde_json::to_string(&dump).unwrap();
+        assert!(!dump.contains("@alice:localhost"));
+        assert!(!dump.contains("$read"));
+        assert!(dump.contains(&hashed_user_id));
+    }
+}
@@ -58,6 +58,10 @@ pub trait StateStoreIntegrationTests {
     async fn test_populate_store(&self) -> Result<()>;
     /// Test room member saving.
     async fn test_member_saving(&self);
+    /// Test saving the members of a large room.
+    async fn test_many_members_saving(&self);
+    /// Test saving the members of a room in a batch.
+    async fn test_room_members_batch_saving(&self);
     /// Test filter saving.
     async fn test_filter_saving(&self);
     /// Test saving a user avatar URL.
@@ -428,6 +432,69 @@ impl StateStoreIntegrationTests for DynStateStore {
         assert!(profiles.unwrap().is_empty());
     }
 
+    async fn test_many_members_saving(&self) {
+        let room_id = room_id!("!test_many_members_saving:localhost");
+
+        // Enough members to need several batches, and not a multiple of the size of a
+        // batch.
+        let mut changes = StateChanges::default();
+        let mut members = BTreeMap::new();
+        let mut profiles = BTreeMap::new();
+
+        for i in 0..250 {
+            let user_id = UserId::parse(format!("@user{i}:localhost")).unwrap();
+            let event_id = EventId::parse(format!("$member{i}")).unwrap();
+            let raw_member_event = custom_membership_event(&user_id, &event_id);
+
+            profiles.insert(user_id.clone(), raw_member_event.deserialize().unwrap().into());
+            members.insert(user_id.to_string(), raw_member_event.cast());
+        }
+
+        changes
+            .state
+            .insert(room_id.to_owned(), BTreeMap::from([(StateEventType::RoomMember, members)]));
+        changes.profiles.insert(room_id.to_owned(), profiles);
+        self.save_changes(&changes).await.unwrap();
+
+        let members = self.get_user_ids(room_id, RoomMemberships::JOIN).await.unwrap();
+        assert_eq!(members.len(), 250);
+
+        let user_id = user_id!("@user123:localhost");
+        assert!(self.get_member_event(room_id, user_id).await.unwrap().is_some());
+        assert!(self.get_profile(room_id, user_id).await.unwrap().is_some());
+    }
+
+    async fn test_room_members_batch_saving(&self) {
+        let room_id = room_id!("!test_room_members_batch_saving:localhost");
+
+        // Enough members to need several batches.
+        let mut members = BTreeMap::new();
+        let mut profiles = BTreeMap::new();
+
+        for i in 0..250 {
+            let user_id = UserId::parse(format!("@user{i}:localhost")).unwrap();
+            let event_id = EventId::parse(format!("$member{i}")).unwrap();
+            let raw_member_event = custom_membership_event(&user_id, &event_id);
+
+            profiles.insert(user_id.clone(), raw_member_event.deserialize().unwrap().into());
+            members.insert(user_id, raw_member_event);
+        }
+
+        self.save_room_members(room_id, members, profiles).await.unwrap();
+
+        let members = self.get_user_ids(room_id, RoomMemberships::JOIN).await.unwrap();
+        assert_eq!(members.len(), 250);
+
+        let user_id = user_id!("@user123:localhost");
+        assert!(self.get_member_event(room_id, user_id).await.unwrap().is_some());
+        assert!(self.get_profile(room_id, user_id).await.unwrap().is_some());
+        assert!(self
+            .get_state_event(room_id, StateEventType::RoomMember, user_id.as_str())
+            .await
+            .unwrap()
+            .is_some());
+    }
+
     async fn test_filter_saving(&self) {
         let filter_name = "filter_name";
         let filter_id = "filter_id_1234";
@@ -1655,6 +1722,18 @@ macro_rules! statestore_integration_tests {
                 store.test_member_saving().await
             }
 
+            #[async_test]
+            async fn test_many_members_saving() {
+                let store = get_store().await.unwrap().into_state_store();
+                store.test_many_members_saving().await
+            }
+
+            #[async_test]
+            async fn test_room_members_batch_saving() {
+                let store = get_store().await.unwrap().into_state_store();
+                store.test_room_members_batch_saving().await
+            }
+
             #[async_test]
             async fn test_filter_saving() {
                 let store = get_store().await.unwrap().into_state_store();
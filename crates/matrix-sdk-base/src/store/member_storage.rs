@@ -0,0 +1,157 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! How the member events of the rooms are persisted in the state store.
+
+use ruma::{
+    events::AnySyncStateEvent,
+    serde::{JsonObject, Raw},
+};
+use serde_json::Value as JsonValue;
+
+use crate::RoomInfo;
+
+/// How the member events of a room are persisted in the state store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemberStorageStrategy {
+    /// The member events are stored as they are received, along with the
+    /// profiles of the members.
+    #[default]
+    Full,
+
+    /// Only the membership of the members is stored.
+    ///
+    /// The display names and avatars are left out of the member events, and
+    /// the profiles and the display name ambiguities of the members are not
+    /// tracked, except for the heroes of the room and the current user. The
+    /// profiles of the other members can be fetched lazily instead.
+    MembershipOnly,
+}
+
+/// The policy choosing the [`MemberStorageStrategy`] of every room, set with
+/// [`StoreConfig::member_storage_policy()`].
+///
+/// By default, the member events of all the rooms are fully stored.
+///
+/// [`StoreConfig::member_storage_policy()`]: super::StoreConfig::member_storage_policy
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemberStoragePolicy {
+    /// The number of joined members above which only the membership of the
+    /// members is stored.
+    membership_only_threshold: Option<u64>,
+}
+
+impl MemberStoragePolicy {
+    /// Store the member events of all the rooms fully.
+    pub fn full() -> Self {
+        Self { membership_only_threshold: None }
+    }
+
+    /// Store only the membership of the members of the rooms with more than
+    /// the given number of joined members.
+    pub fn membership_only_above(joined_members: u64) -> Self {
+        Self { membership_only_threshold: Some(joined_members) }
+    }
+
+    /// Get the strategy to use for the room with the given info.
+    pub fn strategy_for(&self, room_info: &RoomInfo) -> MemberStorageStrategy {
+        match self.membership_only_threshold {
+            Some(threshold) if room_info.joined_members_count() > threshold => {
+                MemberStorageStrategy::MembershipOnly
+            }
+            _ => MemberStorageStrategy::Full,
+        }
+    }
+}
+
+/// Remove the display name and the avatar from a member event, and from its
+/// previous content.
+///
+/// The event is returned untouched if it isn't a JSON object.
+pub(crate) fn strip_member_profile(raw: &Raw<AnySyncStateEvent>) -> Raw<AnySyncStateEvent> {
+    fn strip_profile(content: Option<&mut JsonValue>) {
+        if let Some(JsonValue::Object(content)) = content {
+            content.remove("displayname");
+            content.remove("avatar_url");
+        }
+    }
+
+    let Ok(mut event) = raw.deserialize_as::<JsonObject>() else {
+        return raw.clone();
+    };
+
+    strip_profile(event.get_mut("content"));
+
+    if let Some(JsonValue::Object(unsigned)) = event.get_mut("unsigned") {
+        strip_profile(unsigned.get_mut("prev_content"));
+    }
+
+    Raw::new(&event).map(Raw::cast).unwrap_or_else(|_| raw.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::sync_state_event;
+    use ruma::{events::AnySyncStateEvent, room_id, serde::Raw};
+    use serde_json::json;
+
+    use super::{strip_member_profile, MemberStoragePolicy, MemberStorageStrategy};
+    use crate::{RoomInfo, RoomState};
+
+    #[test]
+    fn test_strategy_for() {
+        let mut room_info = RoomInfo::new(room_id!("!room:localhost"), RoomState::Joined);
+        room_info.update_joined_member_count(1_000);
+
+        assert_eq!(
+            MemberStoragePolicy::full().strategy_for(&room_info),
+            MemberStorageStrategy::Full
+        );
+        assert_eq!(
+            MemberStoragePolicy::membership_only_above(1_000).strategy_for(&room_info),
+            MemberStorageStrategy::Full
+        );
+        assert_eq!(
+            MemberStoragePolicy::membership_only_above(999).strategy_for(&room_info),
+            MemberStorageStrategy::MembershipOnly
+        );
+    }
+
+    #[test]
+    fn test_strip_member_profile() {
+        let raw: Raw<AnySyncStateEvent> = sync_state_event!({
+            "content": {
+                "avatar_url": "mxc://localhost/avatar",
+                "displayname": "Alice",
+                "membership": "join",
+            },
+            "event_id": "$member",
+            "origin_server_ts": 1,
+            "sender": "@alice:localhost",
+            "state_key": "@alice:localhost",
+            "type": "m.room.member",
+            "unsigned": {
+                "prev_content": {
+                    "displayname": "Old Alice",
+                    "membership": "invite",
+                },
+            },
+        });
+
+        let stripped = strip_member_profile(&raw).deserialize_as::<serde_json::Value>().unwrap();
+        assert_eq!(stripped["content"], json!({ "membership": "join" }));
+        assert_eq!(stripped["unsigned"]["prev_content"], json!({ "membership": "invite" }));
+        assert_eq!(stripped["state_key"], "@alice:localhost");
+    }
+}
@@ -30,19 +30,23 @@ use ruma::{
     },
     serde::Raw,
     time::Instant,
-    CanonicalJsonObject, EventId, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedTransactionId,
-    OwnedUserId, RoomId, RoomVersionId, TransactionId, UserId,
+    CanonicalJsonObject, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri,
+    OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId, RoomVersionId, TransactionId, UserId,
 };
 use tracing::{debug, instrument, warn};
 
 use super::{
     send_queue::{ChildTransactionId, QueuedRequest, SentRequestKey},
-    traits::{ComposerDraft, ServerCapabilities},
+    traits::{
+        CachedUrlPreview, CachedUserProfile, CachedWellKnown, ComposerDraft, ContactActivity,
+        ServerCapabilities, TemporaryRoomMute, UploadedFilter,
+    },
     DependentQueuedRequest, DependentQueuedRequestKind, QueuedRequestKind, Result, RoomInfo,
     StateChanges, StateStore, StoreError,
 };
 use crate::{
     deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+    media::MediaPolicy,
     store::QueueWedgeError,
     MinimalRoomMemberEvent, RoomMemberships, StateStoreDataKey, StateStoreDataValue,
 };
@@ -50,6 +54,15 @@ use crate::{
 #[derive(Debug, Default)]
 #[allow(clippy::type_complexity)]
 struct MemoryStoreInner {
+    media_policy: Option<MediaPolicy>,
+    cached_url_previews: Option<Vec<String>>,
+    temporary_room_mutes: Option<BTreeMap<OwnedRoomId, TemporaryRoomMute>>,
+    recovery_key_confirmation: Option<MilliSecondsSinceUnixEpoch>,
+    well_known: Option<CachedWellKnown>,
+    contact_activity: Option<BTreeMap<OwnedUserId, ContactActivity>>,
+    url_previews: HashMap<String, CachedUrlPreview>,
+    uploaded_filters: HashMap<String, UploadedFilter>,
+    user_profiles: HashMap<OwnedUserId, CachedUserProfile>,
     recently_visited_rooms: HashMap<OwnedUserId, Vec<OwnedRoomId>>,
     composer_drafts: HashMap<OwnedRoomId, ComposerDraft>,
     user_avatar_url: HashMap<OwnedUserId, OwnedMxcUri>,
@@ -174,6 +187,33 @@ impl StateStore for MemoryStore {
                 .get(room_id)
                 .cloned()
                 .map(StateStoreDataValue::SeenKnockRequests),
+            StateStoreDataKey::UserProfile(user_id) => {
+                inner.user_profiles.get(user_id).cloned().map(StateStoreDataValue::UserProfile)
+            }
+            StateStoreDataKey::UploadedFilter(name) => {
+                inner.uploaded_filters.get(name).cloned().map(StateStoreDataValue::UploadedFilter)
+            }
+            StateStoreDataKey::UrlPreview(name) => {
+                inner.url_previews.get(name).cloned().map(StateStoreDataValue::UrlPreview)
+            }
+            StateStoreDataKey::ContactActivity => {
+                inner.contact_activity.clone().map(StateStoreDataValue::ContactActivity)
+            }
+            StateStoreDataKey::WellKnown => {
+                inner.well_known.clone().map(StateStoreDataValue::WellKnown)
+            }
+            StateStoreDataKey::RecoveryKeyConfirmation => {
+                inner.recovery_key_confirmation.map(StateStoreDataValue::RecoveryKeyConfirmation)
+            }
+            StateStoreDataKey::TemporaryRoomMutes => {
+                inner.temporary_room_mutes.clone().map(StateStoreDataValue::TemporaryRoomMutes)
+            }
+            StateStoreDataKey::MediaPolicy => {
+                inner.media_policy.clone().map(StateStoreDataValue::MediaPolicy)
+            }
+            StateStoreDataKey::CachedUrlPreviews => {
+                inner.cached_url_previews.clone().map(StateStoreDataValue::CachedUrlPreviews)
+            }
         })
     }
 
@@ -236,6 +276,58 @@ impl StateStore for MemoryStore {
                         .expect("Session data is not a set of seen join request ids"),
                 );
             }
+            StateStoreDataKey::UserProfile(user_id) => {
+                inner.user_profiles.insert(
+                    user_id.to_owned(),
+                    value.into_user_profile().expect("Session data not a user profile"),
+                );
+            }
+            StateStoreDataKey::UploadedFilter(name) => {
+                inner.uploaded_filters.insert(
+                    name.to_owned(),
+                    value.into_uploaded_filter().expect("Session data not an uploaded filter"),
+                );
+            }
+            StateStoreDataKey::UrlPreview(name) => {
+                inner.url_previews.insert(
+                    name.to_owned(),
+                    value.into_url_preview().expect("Session data not a URL preview"),
+                );
+            }
+            StateStoreDataKey::ContactActivity => {
+                inner.contact_activity = Some(
+                    value.into_contact_activity().expect("Session data not the activity of users"),
+                );
+            }
+            StateStoreDataKey::WellKnown => {
+                inner.well_known =
+                    Some(value.into_well_known().expect("Session data not a well-known document"));
+            }
+            StateStoreDataKey::RecoveryKeyConfirmation => {
+                inner.recovery_key_confirmation = Some(
+                    value
+                        .into_recovery_key_confirmation()
+                        .expect("Session data not a recovery key confirmation"),
+                );
+            }
+            StateStoreDataKey::TemporaryRoomMutes => {
+                inner.temporary_room_mutes = Some(
+                    value
+                        .into_temporary_room_mutes()
+                        .expect("Session data not the temporary room mutes"),
+                );
+            }
+            StateStoreDataKey::MediaPolicy => {
+                inner.media_policy =
+                    Some(value.into_media_policy().expect("Session data not a media policy"));
+            }
+            StateStoreDataKey::CachedUrlPreviews => {
+                inner.cached_url_previews = Some(
+                    value
+                        .into_cached_url_previews()
+                        .expect("Session data not the cached URL previews"),
+                );
+            }
         }
 
         Ok(())
@@ -262,6 +354,21 @@ impl StateStore for MemoryStore {
             StateStoreDataKey::SeenKnockRequests(room_id) => {
                 inner.seen_knock_requests.remove(room_id);
             }
+            StateStoreDataKey::UserProfile(user_id) => {
+                inner.user_profiles.remove(user_id);
+            }
+            StateStoreDataKey::UploadedFilter(name) => {
+                inner.uploaded_filters.remove(name);
+            }
+            StateStoreDataKey::UrlPreview(name) => {
+                inner.url_previews.remove(name);
+            }
+            StateStoreDataKey::ContactActivity => inner.contact_activity = None,
+            StateStoreDataKey::WellKnown => inner.well_known = None,
+            StateStoreDataKey::RecoveryKeyConfirmation => inner.recovery_key_confirmation = None,
+            StateStoreDataKey::TemporaryRoomMutes => inner.temporary_room_mutes = None,
+            StateStoreDataKey::MediaPolicy => inner.media_policy = None,
+            StateStoreDataKey::CachedUrlPreviews => inner.cached_url_previews = None,
         }
         Ok(())
     }
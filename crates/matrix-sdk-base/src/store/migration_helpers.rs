@@ -216,6 +216,7 @@ impl BaseRoomInfoV1 {
             topic,
             rtc_member_events: BTreeMap::new(),
             is_marked_unread: false,
+            is_hidden: false,
             notable_tags: RoomNotableTags::empty(),
             pinned_events: None,
         })
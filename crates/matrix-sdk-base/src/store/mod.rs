@@ -36,6 +36,7 @@ use once_cell::sync::OnceCell;
 #[cfg(any(test, feature = "testing"))]
 #[macro_use]
 pub mod integration_tests;
+mod debug_dump;
 mod observable_map;
 mod traits;
 
@@ -60,18 +61,22 @@ use tracing::warn;
 use crate::{
     deserialized_responses::DisplayName,
     event_cache::store as event_cache_store,
-    rooms::{normal::RoomInfoNotableUpdate, RoomInfo, RoomState},
-    MinimalRoomMemberEvent, Room, RoomStateFilter, SessionMeta,
+    rooms::{normal::RoomInfoNotableUpdate, RoomInfo, RoomState, SharedRoomDisplayNameResolver},
+    MinimalRoomMemberEvent, Room, RoomDisplayNameResolver, RoomStateFilter, SessionMeta,
 };
 
 pub(crate) mod ambiguity_map;
+mod member_storage;
 mod memory_store;
 pub mod migration_helpers;
 mod send_queue;
 
 #[cfg(any(test, feature = "testing"))]
 pub use self::integration_tests::StateStoreIntegrationTests;
+pub(crate) use self::member_storage::strip_member_profile;
 pub use self::{
+    debug_dump::{DumpRedactionLevel, RoomStateDump},
+    member_storage::{MemberStoragePolicy, MemberStorageStrategy},
     memory_store::MemoryStore,
     send_queue::{
         ChildTransactionId, DependentQueuedRequest, DependentQueuedRequestKind,
@@ -79,8 +84,10 @@ pub use self::{
         SentMediaInfo, SentRequestKey, SerializableEventContent,
     },
     traits::{
-        ComposerDraft, ComposerDraftType, DynStateStore, IntoStateStore, ServerCapabilities,
-        StateStore, StateStoreDataKey, StateStoreDataValue, StateStoreExt,
+        CachedUrlPreview, CachedUserProfile, CachedWellKnown, ComposerDraft, ComposerDraftType,
+        ContactActivity, DynStateStore, IntoStateStore, ServerCapabilities, StateStore,
+        StateStoreDataKey, StateStoreDataValue, StateStoreExt, TemporaryRoomMute, UploadedFilter,
+        UrlPreview, UrlPreviewImage,
     },
 };
 
@@ -156,6 +163,9 @@ pub(crate) struct Store {
     /// A lock to synchronize access to the store, such that data by the sync is
     /// never overwritten.
     sync_lock: Arc<Mutex<()>>,
+    /// The custom strategy to compute the display names of the rooms, shared
+    /// with all the rooms.
+    room_display_name_resolver: SharedRoomDisplayNameResolver,
 }
 
 impl Store {
@@ -167,6 +177,29 @@ impl Store {
             sync_token: Default::default(),
             rooms: Arc::new(StdRwLock::new(ObservableMap::new())),
             sync_lock: Default::default(),
+            room_display_name_resolver: Default::default(),
+        }
+    }
+
+    /// Get the custom strategy to compute the display names of the rooms, if
+    /// any.
+    pub(crate) fn room_display_name_resolver(&self) -> Option<Arc<dyn RoomDisplayNameResolver>> {
+        self.room_display_name_resolver.read().unwrap().clone()
+    }
+
+    /// Set the custom strategy to compute the display names of the rooms, or
+    /// remove it with `None`.
+    ///
+    /// The cached display names of the rooms are cleared, so they are computed
+    /// again with the new strategy the next time they are requested.
+    pub(crate) fn set_room_display_name_resolver(
+        &self,
+        resolver: Option<Arc<dyn RoomDisplayNameResolver>>,
+    ) {
+        *self.room_display_name_resolver.write().unwrap() = resolver;
+
+        for room in self.rooms.read().unwrap().iter() {
+            room.clear_cached_display_name();
         }
     }
 
@@ -227,7 +260,8 @@ impl Store {
                     self.inner.clone(),
                     room_info,
                     room_info_notable_update_sender.clone(),
-                );
+                )
+                .with_display_name_resolver(self.room_display_name_resolver.clone());
                 let new_room_id = new_room.room_id().to_owned();
 
                 rooms.insert(new_room_id, new_room);
@@ -302,6 +336,7 @@ impl Store {
                     room_type,
                     room_info_notable_update_sender,
                 )
+                .with_display_name_resolver(self.room_display_name_resolver.clone())
             })
             .clone()
     }
@@ -318,6 +353,113 @@ impl Store {
     }
 }
 
+/// Keeps the in-memory state of a [`Store`] in line with its persisted state.
+///
+/// Computing a set of [`StateChanges`] (e.g. when processing a sync response)
+/// creates [`Room`]s in memory before the changes have been persisted. If
+/// anything fails before [`StoreTransaction::commit`] succeeds, dropping the
+/// transaction removes the rooms it created from memory again, so the
+/// in-memory state never gets ahead of what has been persisted.
+///
+/// Committing persists the changes and advances the sync token within the
+/// same critical section, guarded by the [sync lock](Store::sync_lock): the
+/// sync token is never advanced if the changes couldn't be saved.
+///
+/// This is used for both sync v2 and sliding sync responses. It only covers the
+/// state store: changes to the crypto store are persisted by the `OlmMachine`
+/// itself, before the state changes are saved, and the event cache persists its
+/// events after the response has been processed. Neither are rolled back if
+/// saving the state changes fails, so callers must be able to process the same
+/// changes again.
+pub(crate) struct StoreTransaction<'a> {
+    store: &'a Store,
+    /// The rooms that have been created in memory by this transaction.
+    created_rooms: BTreeSet<OwnedRoomId>,
+    committed: bool,
+}
+
+impl<'a> StoreTransaction<'a> {
+    /// Start a new transaction over the given store.
+    pub fn new(store: &'a Store) -> Self {
+        Self { store, created_rooms: BTreeSet::new(), committed: false }
+    }
+
+    /// Like [`Store::get_or_create_room`], but the room will be removed from
+    /// memory again if the transaction isn't committed.
+    pub fn get_or_create_room(
+        &mut self,
+        room_id: &RoomId,
+        room_type: RoomState,
+        room_info_notable_update_sender: broadcast::Sender<RoomInfoNotableUpdate>,
+    ) -> Room {
+        if !self.store.room_exists(room_id) {
+            self.created_rooms.insert(room_id.to_owned());
+        }
+
+        self.store.get_or_create_room(room_id, room_type, room_info_notable_update_sender)
+    }
+
+    /// Persist the given changes and, if they were saved successfully,
+    /// advance the sync token and call `on_saved`, all while holding the sync
+    /// lock.
+    ///
+    /// `on_saved` is typically used to apply the changes to the in-memory
+    /// state.
+    pub async fn commit(
+        mut self,
+        changes: &StateChanges,
+        sync_token: Option<String>,
+        on_saved: impl FnOnce(),
+    ) -> Result<()> {
+        let _sync_lock = self.store.sync_lock.lock().await;
+
+        self.store.inner.save_changes(changes).await?;
+
+        if let Some(sync_token) = sync_token {
+            *self.store.sync_token.write().await = Some(sync_token);
+        }
+
+        on_saved();
+        self.committed = true;
+
+        Ok(())
+    }
+
+    /// Like [`StoreTransaction::commit`], but for callers that already hold
+    /// the sync lock and don't have a sync token to advance, like sliding
+    /// sync.
+    pub async fn commit_with_sync_lock_held(
+        mut self,
+        changes: &StateChanges,
+        on_saved: impl FnOnce(),
+    ) -> Result<()> {
+        self.store.inner.save_changes(changes).await?;
+
+        on_saved();
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for StoreTransaction<'_> {
+    fn drop(&mut self) {
+        if self.committed || self.created_rooms.is_empty() {
+            return;
+        }
+
+        warn!(
+            num_rooms = self.created_rooms.len(),
+            "Rolling back the rooms created by an uncommitted store transaction"
+        );
+
+        let mut rooms = self.store.rooms.write().unwrap();
+        for room_id in &self.created_rooms {
+            rooms.remove(room_id);
+        }
+    }
+}
+
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for Store {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -489,6 +631,7 @@ pub struct StoreConfig {
     pub(crate) crypto_store: Arc<DynCryptoStore>,
     pub(crate) state_store: Arc<DynStateStore>,
     pub(crate) event_cache_store: event_cache_store::EventCacheStoreLock,
+    pub(crate) member_storage_policy: MemberStoragePolicy,
     cross_process_store_locks_holder_name: String,
 }
 
@@ -514,6 +657,7 @@ impl StoreConfig {
                 event_cache_store::MemoryStore::new(),
                 cross_process_store_locks_holder_name.clone(),
             ),
+            member_storage_policy: Default::default(),
             cross_process_store_locks_holder_name,
         }
     }
@@ -544,4 +688,14 @@ impl StoreConfig {
         );
         self
     }
+
+    /// Set the policy choosing how the member events of the rooms are
+    /// persisted.
+    ///
+    /// For very large rooms, storing only the membership of the members keeps
+    /// the size of the state store reasonable.
+    pub fn member_storage_policy(mut self, policy: MemberStoragePolicy) -> Self {
+        self.member_storage_policy = policy;
+        self
+    }
 }
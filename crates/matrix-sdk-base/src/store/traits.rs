@@ -24,31 +24,36 @@ use async_trait::async_trait;
 use growable_bloom_filter::GrowableBloom;
 use matrix_sdk_common::AsyncTraitDeps;
 use ruma::{
-    api::MatrixVersion,
+    api::{client::filter::FilterDefinition, MatrixVersion},
     events::{
         presence::PresenceEvent,
         receipt::{Receipt, ReceiptThread, ReceiptType},
+        room::member::SyncRoomMemberEvent,
         AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent, EmptyStateKey, GlobalAccountDataEvent,
         GlobalAccountDataEventContent, GlobalAccountDataEventType, RedactContent,
         RedactedStateEventContent, RoomAccountDataEvent, RoomAccountDataEventContent,
         RoomAccountDataEventType, StateEventType, StaticEventContent, StaticStateEventContent,
     },
-    serde::Raw,
+    presence::PresenceState,
+    serde::{JsonObject, Raw},
     time::SystemTime,
-    EventId, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId,
-    TransactionId, UserId,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri, OwnedRoomId,
+    OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt, UserId,
 };
 use serde::{Deserialize, Serialize};
 
 use super::{
-    send_queue::SentRequestKey, ChildTransactionId, DependentQueuedRequest,
-    DependentQueuedRequestKind, QueueWedgeError, QueuedRequest, QueuedRequestKind, StateChanges,
-    StoreError,
+    debug_dump::{self, DumpRedactionLevel, RoomStateDump},
+    send_queue::SentRequestKey,
+    ChildTransactionId, DependentQueuedRequest, DependentQueuedRequestKind, QueueWedgeError,
+    QueuedRequest, QueuedRequestKind, StateChanges, StoreError,
 };
 use crate::{
     deserialized_responses::{
         DisplayName, RawAnySyncOrStrippedState, RawMemberEvent, RawSyncOrStrippedState,
     },
+    media::MediaPolicy,
+    notification_settings::RoomNotificationMode,
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships,
 };
 
@@ -95,6 +100,43 @@ pub trait StateStore: AsyncTraitDeps {
     /// Save the set of state changes in the store.
     async fn save_changes(&self, changes: &StateChanges) -> Result<(), Self::Error>;
 
+    /// Save the member events of a room, with the profiles of the members, in
+    /// a single batch.
+    ///
+    /// This is equivalent to calling [`StateStore::save_changes()`] with
+    /// changes containing only these events and profiles, which is what the
+    /// default implementation does. Stores should insert the members in
+    /// batches, here and in [`StateStore::save_changes()`], since inserting
+    /// them one by one dominates the time it takes to save the changes of
+    /// rooms with many members.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the members belong to.
+    ///
+    /// * `members` - The member events, by user ID.
+    ///
+    /// * `profiles` - The profiles of the members, by user ID.
+    async fn save_room_members(
+        &self,
+        room_id: &RoomId,
+        members: BTreeMap<OwnedUserId, Raw<SyncRoomMemberEvent>>,
+        profiles: BTreeMap<OwnedUserId, MinimalRoomMemberEvent>,
+    ) -> Result<(), Self::Error> {
+        let mut changes = StateChanges::default();
+
+        changes.state.entry(room_id.to_owned()).or_default().insert(
+            StateEventType::RoomMember,
+            members.into_iter().map(|(user_id, event)| (user_id.into(), event.cast())).collect(),
+        );
+
+        if !profiles.is_empty() {
+            changes.profiles.insert(room_id.to_owned(), profiles);
+        }
+
+        self.save_changes(&changes).await
+    }
+
     /// Get the stored presence event for the given user.
     ///
     /// # Arguments
@@ -468,6 +510,20 @@ pub trait StateStore: AsyncTraitDeps {
         &self,
         room: &RoomId,
     ) -> Result<Vec<DependentQueuedRequest>, Self::Error>;
+
+    /// Export the state of the given room, to share a reproduction of a bug in
+    /// the computation of its state.
+    ///
+    /// The dump contains the room info, the state events and the read
+    /// receipts of the room, with their content hidden according to the
+    /// given redaction level.
+    async fn debug_dump(
+        &self,
+        room_id: &RoomId,
+        redaction_level: DumpRedactionLevel,
+    ) -> Result<RoomStateDump, Self::Error> {
+        debug_dump::dump_room(self, room_id, redaction_level).await
+    }
 }
 
 #[repr(transparent)]
@@ -508,6 +564,15 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
         self.0.save_changes(changes).await.map_err(Into::into)
     }
 
+    async fn save_room_members(
+        &self,
+        room_id: &RoomId,
+        members: BTreeMap<OwnedUserId, Raw<SyncRoomMemberEvent>>,
+        profiles: BTreeMap<OwnedUserId, MinimalRoomMemberEvent>,
+    ) -> Result<(), Self::Error> {
+        self.0.save_room_members(room_id, members, profiles).await.map_err(Into::into)
+    }
+
     async fn get_presence_event(
         &self,
         user_id: &UserId,
@@ -975,7 +1040,7 @@ impl ServerCapabilities {
     /// [`Self::STALE_THRESHOLD`] milliseconds since the last time we stored
     /// it.
     pub fn maybe_decode(&self) -> Option<(Vec<MatrixVersion>, BTreeMap<String, bool>)> {
-        if now_timestamp_ms() - self.last_fetch_ts >= Self::STALE_THRESHOLD {
+        if is_stale(self.last_fetch_ts, Self::STALE_THRESHOLD) {
             None
         } else {
             Some((
@@ -986,6 +1051,42 @@ impl ServerCapabilities {
     }
 }
 
+/// The `.well-known/matrix/client` document of a server, as cached in the
+/// store.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CachedWellKnown {
+    /// The raw JSON content of the document, including vendor extensions.
+    pub content: JsonObject,
+
+    /// Last time we fetched this data from the server, in milliseconds since
+    /// epoch.
+    last_fetch_ts: f64,
+}
+
+impl CachedWellKnown {
+    /// The number of milliseconds after which the data is considered stale.
+    pub const STALE_THRESHOLD: f64 = (1000 * 60 * 60 * 24) as _; // one day
+
+    /// Wrap a freshly fetched well-known document into this serializable
+    /// struct.
+    pub fn new(content: JsonObject) -> Self {
+        Self { content, last_fetch_ts: now_timestamp_ms() }
+    }
+
+    /// Get the content of the document.
+    ///
+    /// May return `None` if the data is considered stale, after
+    /// [`Self::STALE_THRESHOLD`] milliseconds since the last time we stored
+    /// it.
+    pub fn maybe_decode(&self) -> Option<&JsonObject> {
+        if is_stale(self.last_fetch_ts, Self::STALE_THRESHOLD) {
+            None
+        } else {
+            Some(&self.content)
+        }
+    }
+}
+
 /// Get the current timestamp as the number of milliseconds since Unix Epoch.
 fn now_timestamp_ms() -> f64 {
     SystemTime::now()
@@ -995,6 +1096,12 @@ fn now_timestamp_ms() -> f64 {
         * 1000.0
 }
 
+/// Whether data fetched at `last_fetch_ts` is older than `threshold`, both in
+/// milliseconds.
+fn is_stale(last_fetch_ts: f64, threshold: f64) -> bool {
+    now_timestamp_ms() - last_fetch_ts >= threshold
+}
+
 /// A value for key-value data that should be persisted into the store.
 #[derive(Debug, Clone)]
 pub enum StateStoreDataValue {
@@ -1025,6 +1132,131 @@ pub enum StateStoreDataValue {
 
     /// A list of knock request ids marked as seen in a room.
     SeenKnockRequests(BTreeMap<OwnedEventId, OwnedUserId>),
+
+    /// The cached global profile of a user, with the time it was fetched at.
+    UserProfile(CachedUserProfile),
+
+    /// A filter uploaded to the homeserver, along with its definition.
+    UploadedFilter(UploadedFilter),
+
+    /// The cached preview of a URL, with the time it was fetched at.
+    UrlPreview(CachedUrlPreview),
+
+    /// The activity of users, aggregated from their presence events.
+    ContactActivity(BTreeMap<OwnedUserId, ContactActivity>),
+
+    /// The cached `.well-known/matrix/client` document of the server.
+    WellKnown(CachedWellKnown),
+
+    /// The last time the user confirmed that they still have their recovery
+    /// key.
+    RecoveryKeyConfirmation(MilliSecondsSinceUnixEpoch),
+
+    /// The rooms that are muted until a point in time, with the notification
+    /// mode to restore then.
+    TemporaryRoomMutes(BTreeMap<OwnedRoomId, TemporaryRoomMute>),
+
+    /// The policy choosing which media can be downloaded automatically.
+    MediaPolicy(MediaPolicy),
+
+    /// The URLs whose preview is cached, from the least to the most recently
+    /// used.
+    CachedUrlPreviews(Vec<String>),
+}
+
+/// A user's global profile, as last fetched from the homeserver.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CachedUserProfile {
+    /// The user's display name, if any.
+    pub display_name: Option<String>,
+    /// The user's avatar URL, if any.
+    pub avatar_url: Option<OwnedMxcUri>,
+    /// When the profile was fetched, or last updated from a member event.
+    pub fetched_at: MilliSecondsSinceUnixEpoch,
+}
+
+/// A filter uploaded to the homeserver under a name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadedFilter {
+    /// The ID of the filter, as returned by the homeserver.
+    pub filter_id: String,
+    /// The URL of the homeserver the filter was uploaded to.
+    pub homeserver: String,
+    /// The user the filter was uploaded for.
+    pub user_id: OwnedUserId,
+    /// The definition of the filter.
+    pub definition: FilterDefinition,
+}
+
+/// The preview of a URL, as returned by the homeserver and sanitized.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UrlPreview {
+    /// The title of the page.
+    pub title: Option<String>,
+    /// A short description of the page.
+    pub description: Option<String>,
+    /// The name of the website the page belongs to.
+    pub site_name: Option<String>,
+    /// The image representing the page, uploaded to the media repository by
+    /// the homeserver.
+    pub image: Option<UrlPreviewImage>,
+}
+
+/// The image of a [`UrlPreview`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UrlPreviewImage {
+    /// The MXC URI of the image.
+    pub uri: OwnedMxcUri,
+    /// The width of the image, in pixels.
+    pub width: Option<UInt>,
+    /// The height of the image, in pixels.
+    pub height: Option<UInt>,
+    /// The size of the image, in bytes.
+    pub size: Option<UInt>,
+    /// The MIME type of the image.
+    pub mimetype: Option<String>,
+}
+
+/// A [`UrlPreview`], as last fetched from the homeserver.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CachedUrlPreview {
+    /// The preview.
+    pub preview: UrlPreview,
+    /// The point in time the preview was requested for, if any.
+    pub ts: Option<MilliSecondsSinceUnixEpoch>,
+    /// When the preview was fetched.
+    pub fetched_at: MilliSecondsSinceUnixEpoch,
+}
+
+/// The activity of a user, aggregated from their presence events.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContactActivity {
+    /// The ID of the user.
+    pub user_id: OwnedUserId,
+    /// The last presence state of the user.
+    pub presence: PresenceState,
+    /// Whether the user was actively using their client, according to the last
+    /// presence event.
+    pub currently_active: bool,
+    /// When the user was last active, if known.
+    ///
+    /// Presence events only say how long ago the user was last active, this is
+    /// the point in time it corresponds to when the event was received.
+    pub last_active_at: Option<MilliSecondsSinceUnixEpoch>,
+    /// When the last presence event of the user was received.
+    ///
+    /// It is only persisted along with the changes of the other fields.
+    pub updated_at: MilliSecondsSinceUnixEpoch,
+}
+
+/// A room muted until a point in time.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TemporaryRoomMute {
+    /// When the room should be unmuted.
+    pub until: MilliSecondsSinceUnixEpoch,
+    /// The user-defined notification mode of the room before it was muted, if
+    /// any, to restore when it is unmuted.
+    pub previous_mode: Option<RoomNotificationMode>,
 }
 
 /// Current draft of the composer for the room.
@@ -1096,6 +1328,51 @@ impl StateStoreDataValue {
     pub fn into_seen_knock_requests(self) -> Option<BTreeMap<OwnedEventId, OwnedUserId>> {
         as_variant!(self, Self::SeenKnockRequests)
     }
+
+    /// Get this value if it is a cached user profile.
+    pub fn into_user_profile(self) -> Option<CachedUserProfile> {
+        as_variant!(self, Self::UserProfile)
+    }
+
+    /// Get this value if it is an uploaded filter.
+    pub fn into_uploaded_filter(self) -> Option<UploadedFilter> {
+        as_variant!(self, Self::UploadedFilter)
+    }
+
+    /// Get this value if it is a cached URL preview.
+    pub fn into_url_preview(self) -> Option<CachedUrlPreview> {
+        as_variant!(self, Self::UrlPreview)
+    }
+
+    /// Get this value if it is the activity of users.
+    pub fn into_contact_activity(self) -> Option<BTreeMap<OwnedUserId, ContactActivity>> {
+        as_variant!(self, Self::ContactActivity)
+    }
+
+    /// Get this value if it is a cached well-known document.
+    pub fn into_well_known(self) -> Option<CachedWellKnown> {
+        as_variant!(self, Self::WellKnown)
+    }
+
+    /// Get this value if it is the time of the last recovery key confirmation.
+    pub fn into_recovery_key_confirmation(self) -> Option<MilliSecondsSinceUnixEpoch> {
+        as_variant!(self, Self::RecoveryKeyConfirmation)
+    }
+
+    /// Get this value if it is the temporary room mutes.
+    pub fn into_temporary_room_mutes(self) -> Option<BTreeMap<OwnedRoomId, TemporaryRoomMute>> {
+        as_variant!(self, Self::TemporaryRoomMutes)
+    }
+
+    /// Get this value if it is a media policy.
+    pub fn into_media_policy(self) -> Option<MediaPolicy> {
+        as_variant!(self, Self::MediaPolicy)
+    }
+
+    /// Get this value if it is the URLs whose preview is cached.
+    pub fn into_cached_url_previews(self) -> Option<Vec<String>> {
+        as_variant!(self, Self::CachedUrlPreviews)
+    }
 }
 
 /// A key for key-value data.
@@ -1128,6 +1405,35 @@ pub enum StateStoreDataKey<'a> {
 
     /// A list of knock request ids marked as seen in a room.
     SeenKnockRequests(&'a RoomId),
+
+    /// The cached global profile of a user.
+    UserProfile(&'a UserId),
+
+    /// A filter uploaded with the given name, along with its definition.
+    UploadedFilter(&'a str),
+
+    /// The cached preview of the given URL.
+    UrlPreview(&'a str),
+
+    /// The activity of the users the client received presence for, aggregated
+    /// from their presence events.
+    ContactActivity,
+
+    /// The content of the `.well-known/matrix/client` document of the server.
+    WellKnown,
+
+    /// The last time the user confirmed that they still have their recovery
+    /// key.
+    RecoveryKeyConfirmation,
+
+    /// The rooms that are muted until a point in time.
+    TemporaryRoomMutes,
+
+    /// The policy choosing which media can be downloaded automatically.
+    MediaPolicy,
+
+    /// The URLs whose preview is cached.
+    CachedUrlPreviews,
 }
 
 impl StateStoreDataKey<'_> {
@@ -1157,6 +1463,37 @@ impl StateStoreDataKey<'_> {
     /// Key prefix to use for the
     /// [`SeenKnockRequests`][Self::SeenKnockRequests] variant.
     pub const SEEN_KNOCK_REQUESTS: &'static str = "seen_knock_requests";
+
+    /// Key prefix to use for the [`UserProfile`][Self::UserProfile] variant.
+    pub const USER_PROFILE: &'static str = "user_profile";
+
+    /// Key prefix to use for the [`UploadedFilter`][Self::UploadedFilter]
+    /// variant.
+    pub const UPLOADED_FILTER: &'static str = "uploaded_filter";
+
+    /// Key prefix to use for the [`UrlPreview`][Self::UrlPreview] variant.
+    pub const URL_PREVIEW: &'static str = "url_preview";
+
+    /// Key to use for the [`ContactActivity`][Self::ContactActivity] variant.
+    pub const CONTACT_ACTIVITY: &'static str = "contact_activity";
+
+    /// Key to use for the [`WellKnown`][Self::WellKnown] variant.
+    pub const WELL_KNOWN: &'static str = "well_known";
+
+    /// Key to use for the
+    /// [`RecoveryKeyConfirmation`][Self::RecoveryKeyConfirmation] variant.
+    pub const RECOVERY_KEY_CONFIRMATION: &'static str = "recovery_key_confirmation";
+
+    /// Key to use for the [`TemporaryRoomMutes`][Self::TemporaryRoomMutes]
+    /// variant.
+    pub const TEMPORARY_ROOM_MUTES: &'static str = "temporary_room_mutes";
+
+    /// Key to use for the [`MediaPolicy`][Self::MediaPolicy] variant.
+    pub const MEDIA_POLICY: &'static str = "media_policy";
+
+    /// Key to use for the [`CachedUrlPreviews`][Self::CachedUrlPreviews]
+    /// variant.
+    pub const CACHED_URL_PREVIEWS: &'static str = "cached_url_previews";
 }
 
 #[cfg(test)]
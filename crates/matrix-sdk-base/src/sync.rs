@@ -14,7 +14,7 @@
 
 //! The SDK's representation of the result of a `/sync` request.
 
-use std::{collections::BTreeMap, fmt};
+use std::{collections::BTreeMap, fmt, time::Duration};
 
 use matrix_sdk_common::{debug::DebugRawEvent, deserialized_responses::SyncTimelineEvent};
 use ruma::{
@@ -54,6 +54,8 @@ pub struct SyncResponse {
     pub to_device: Vec<Raw<AnyToDeviceEvent>>,
     /// New notifications per room.
     pub notifications: BTreeMap<OwnedRoomId, Vec<Notification>>,
+    /// How long each stage of the processing of this response took.
+    pub processing_report: SyncProcessingReport,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -64,10 +66,30 @@ impl fmt::Debug for SyncResponse {
             .field("account_data", &DebugListOfRawEventsNoId(&self.account_data))
             .field("to_device", &DebugListOfRawEventsNoId(&self.to_device))
             .field("notifications", &self.notifications)
+            .field("processing_report", &self.processing_report)
             .finish_non_exhaustive()
     }
 }
 
+/// A summary of the time spent in each stage of the processing of a sync
+/// response, for performance debugging.
+///
+/// Stages that didn't run for a given response are left at
+/// [`Duration::ZERO`].
+#[derive(Clone, Debug, Default)]
+pub struct SyncProcessingReport {
+    /// Time spent processing the to-device events and the E2EE related parts
+    /// of the response.
+    pub to_device: Duration,
+    /// Time spent processing the room updates, for all the rooms.
+    pub rooms: Duration,
+    /// Time spent persisting the changes in the state store, and applying
+    /// them in memory.
+    pub store: Duration,
+    /// Total time spent processing the response.
+    pub total: Duration,
+}
+
 /// Updates to rooms in a [`SyncResponse`].
 #[derive(Clone, Default)]
 pub struct RoomUpdates {
@@ -23,7 +23,7 @@ use ruma::{
     },
     DeviceKeyAlgorithm, OwnedDeviceId, OwnedEventId, OwnedUserId,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
@@ -300,6 +300,10 @@ pub struct EncryptionInfo {
     /// Callers that persist this should mark the state as dirty when a device
     /// change is received down the sync.
     pub verification_state: VerificationState,
+    /// The ID of the megolm session that was used to decrypt the event, if it
+    /// is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
 }
 
 /// Represents a matrix room event that has been returned from `/sync`,
@@ -332,6 +336,11 @@ pub struct SyncTimelineEvent {
     /// The push actions associated with this event.
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub push_actions: Vec<Action>,
+
+    /// The metadata attached to this event by the event enrichers of the
+    /// client.
+    #[serde(skip_serializing_if = "EventEnrichments::is_empty")]
+    pub enrichments: EventEnrichments,
 }
 
 // See https://github.com/matrix-org/matrix-rust-sdk/pull/3749#issuecomment-2312939823.
@@ -357,7 +366,11 @@ impl SyncTimelineEvent {
     /// This is a convenience constructor for a plaintext event when you don't
     /// need to set `push_action`, for example inside a test.
     pub fn new(event: Raw<AnySyncTimelineEvent>) -> Self {
-        Self { kind: TimelineEventKind::PlainText { event }, push_actions: vec![] }
+        Self {
+            kind: TimelineEventKind::PlainText { event },
+            push_actions: vec![],
+            enrichments: Default::default(),
+        }
     }
 
     /// Create a new `SyncTimelineEvent` from the given raw event and push
@@ -369,13 +382,21 @@ impl SyncTimelineEvent {
         event: Raw<AnySyncTimelineEvent>,
         push_actions: Vec<Action>,
     ) -> Self {
-        Self { kind: TimelineEventKind::PlainText { event }, push_actions }
+        Self {
+            kind: TimelineEventKind::PlainText { event },
+            push_actions,
+            enrichments: Default::default(),
+        }
     }
 
     /// Create a new `SyncTimelineEvent` to represent the given decryption
     /// failure.
     pub fn new_utd_event(event: Raw<AnySyncTimelineEvent>, utd_info: UnableToDecryptInfo) -> Self {
-        Self { kind: TimelineEventKind::UnableToDecrypt { event, utd_info }, push_actions: vec![] }
+        Self {
+            kind: TimelineEventKind::UnableToDecrypt { event, utd_info },
+            push_actions: vec![],
+            enrichments: Default::default(),
+        }
     }
 
     /// Get the event id of this `SyncTimelineEvent` if the event has any valid
@@ -405,7 +426,11 @@ impl SyncTimelineEvent {
 
 impl From<TimelineEvent> for SyncTimelineEvent {
     fn from(o: TimelineEvent) -> Self {
-        Self { kind: o.kind, push_actions: o.push_actions.unwrap_or_default() }
+        Self {
+            kind: o.kind,
+            push_actions: o.push_actions.unwrap_or_default(),
+            enrichments: Default::default(),
+        }
     }
 }
 
@@ -416,6 +441,46 @@ impl From<DecryptedRoomEvent> for SyncTimelineEvent {
     }
 }
 
+/// Metadata attached to an event by the event enrichers of the client, keyed
+/// by the name of the enricher that produced it.
+///
+/// The values are stored as JSON, so they survive being persisted in the
+/// event cache, and can be read back as any type implementing
+/// [`Deserialize`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EventEnrichments(BTreeMap<String, serde_json::Value>);
+
+impl EventEnrichments {
+    /// Whether no metadata was attached to the event.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the metadata attached under the given key, deserialized as `T`.
+    ///
+    /// Returns `None` if there is no metadata for this key, and an error if it
+    /// can't be deserialized as `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<serde_json::Result<T>> {
+        self.0.get(key).map(|value| T::deserialize(value))
+    }
+
+    /// Get the metadata attached under the given key, as raw JSON.
+    pub fn get_raw(&self, key: &str) -> Option<&serde_json::Value> {
+        self.0.get(key)
+    }
+
+    /// Attach metadata under the given key, replacing any previous value.
+    pub fn insert(&mut self, key: impl Into<String>, value: serde_json::Value) {
+        self.0.insert(key.into(), value);
+    }
+
+    /// Iterate over the keys and the raw JSON values of the metadata.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &serde_json::Value)> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value))
+    }
+}
+
 impl<'de> Deserialize<'de> for SyncTimelineEvent {
     /// Custom deserializer for [`SyncTimelineEvent`], to support older formats.
     ///
@@ -876,12 +941,17 @@ struct SyncTimelineEventDeserializationHelperV1 {
     /// The push actions associated with this event.
     #[serde(default)]
     push_actions: Vec<Action>,
+
+    /// The metadata attached to this event by the event enrichers of the
+    /// client.
+    #[serde(default)]
+    enrichments: EventEnrichments,
 }
 
 impl From<SyncTimelineEventDeserializationHelperV1> for SyncTimelineEvent {
     fn from(value: SyncTimelineEventDeserializationHelperV1) -> Self {
-        let SyncTimelineEventDeserializationHelperV1 { kind, push_actions } = value;
-        SyncTimelineEvent { kind, push_actions }
+        let SyncTimelineEventDeserializationHelperV1 { kind, push_actions, enrichments } = value;
+        SyncTimelineEvent { kind, push_actions, enrichments }
     }
 }
 
@@ -933,7 +1003,7 @@ impl From<SyncTimelineEventDeserializationHelperV0> for SyncTimelineEvent {
             None => TimelineEventKind::PlainText { event },
         };
 
-        SyncTimelineEvent { kind, push_actions }
+        SyncTimelineEvent { kind, push_actions, enrichments: Default::default() }
     }
 }
 
@@ -1113,6 +1183,7 @@ mod tests {
                         sender_claimed_keys: Default::default(),
                     },
                     verification_state: VerificationState::Verified,
+                    session_id: None,
                 },
                 unsigned_encryption_info: Some(BTreeMap::from([(
                     UnsignedEventLocation::RelationsReplace,
@@ -1123,6 +1194,7 @@ mod tests {
                 )])),
             }),
             push_actions: Default::default(),
+            enrichments: Default::default(),
         };
 
         let serialized = serde_json::to_value(&room_event).unwrap();
@@ -1245,6 +1317,30 @@ mod tests {
         });
     }
 
+    #[test]
+    fn sync_timeline_event_enrichments_serialisation() {
+        let mut event = SyncTimelineEvent::new(Raw::new(&example_event()).unwrap().cast());
+
+        // Events without enrichments don't serialize the field.
+        let serialized = serde_json::to_value(&event).unwrap();
+        assert!(serialized.get("enrichments").is_none());
+
+        event.enrichments.insert("org.example.language", json!({ "code": "fr" }));
+        let serialized = serde_json::to_value(&event).unwrap();
+        assert_eq!(serialized["enrichments"], json!({ "org.example.language": { "code": "fr" } }));
+
+        #[derive(Deserialize)]
+        struct Language {
+            code: String,
+        }
+
+        let event: SyncTimelineEvent = serde_json::from_value(serialized).unwrap();
+        let language: Language = event.enrichments.get("org.example.language").unwrap().unwrap();
+        assert_eq!(language.code, "fr");
+        assert!(event.enrichments.get::<Language>("org.example.links").is_none());
+        assert!(event.enrichments.get::<u32>("org.example.language").unwrap().is_err());
+    }
+
     #[test]
     fn sync_timeline_event_deserialisation_migration_for_withheld() {
         // Old serialized version was
@@ -1425,6 +1521,7 @@ mod tests {
                 sender_claimed_keys: Default::default(),
             },
             verification_state: VerificationState::Verified,
+            session_id: None,
         };
 
         with_settings!({sort_maps =>true}, {
@@ -1454,6 +1551,7 @@ mod tests {
                         ]),
                     },
                     verification_state: VerificationState::Verified,
+                    session_id: None,
                 },
                 unsigned_encryption_info: Some(BTreeMap::from([(
                     UnsignedEventLocation::RelationsThreadLatestEvent,
@@ -1466,6 +1564,7 @@ mod tests {
                 )])),
             }),
             push_actions: Default::default(),
+            enrichments: Default::default(),
         };
 
         with_settings!({sort_maps =>true}, {
@@ -41,9 +41,7 @@ type Aes256Ctr = ctr::Ctr128BE<Aes256>;
 /// Matrix attachment.
 pub struct AttachmentDecryptor<'a, R: Read> {
     inner: &'a mut R,
-    expected_hash: Vec<u8>,
-    sha: Sha256,
-    aes: Aes256Ctr,
+    decryptor: ChunkedAttachmentDecryptor,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -51,7 +49,7 @@ impl<'a, R: 'a + Read + std::fmt::Debug> std::fmt::Debug for AttachmentDecryptor
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AttachmentDecryptor")
             .field("inner", &self.inner)
-            .field("expected_hash", &self.expected_hash)
+            .field("expected_hash", &self.decryptor.expected_hash)
             .finish()
     }
 }
@@ -61,22 +59,118 @@ impl<R: Read> Read for AttachmentDecryptor<'_, R> {
         let read_bytes = self.inner.read(buf)?;
 
         if read_bytes == 0 {
-            let hash = self.sha.finalize_reset();
-
-            if hash.as_slice() == self.expected_hash.as_slice() {
+            if self.decryptor.verify_hash() {
                 Ok(0)
             } else {
                 Err(IoError::new(ErrorKind::Other, "Hash mismatch while decrypting"))
             }
         } else {
-            self.sha.update(&buf[0..read_bytes]);
-            self.aes.apply_keystream(&mut buf[0..read_bytes]);
+            self.decryptor.decrypt_chunk(&mut buf[0..read_bytes]);
 
             Ok(read_bytes)
         }
     }
 }
 
+/// A decryptor for Matrix attachments whose data is received in several
+/// chunks, for example while it is being downloaded.
+///
+/// Unlike [`AttachmentDecryptor`], it doesn't need a reader over the whole
+/// encrypted data, so attachments can be decrypted without being fully loaded
+/// in memory.
+pub struct ChunkedAttachmentDecryptor {
+    expected_hash: Vec<u8>,
+    sha: Sha256,
+    aes: Aes256Ctr,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for ChunkedAttachmentDecryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkedAttachmentDecryptor")
+            .field("expected_hash", &self.expected_hash)
+            .finish()
+    }
+}
+
+impl ChunkedAttachmentDecryptor {
+    /// Create a decryptor for the attachment described by the given
+    /// encryption info.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::io::{Cursor, Read};
+    /// # use matrix_sdk_crypto::{AttachmentEncryptor, ChunkedAttachmentDecryptor};
+    /// let data = "Hello world".to_owned();
+    /// let mut cursor = Cursor::new(data.clone());
+    ///
+    /// let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+    ///
+    /// let mut encrypted = Vec::new();
+    /// encryptor.read_to_end(&mut encrypted).unwrap();
+    /// let info = encryptor.finish();
+    ///
+    /// let mut decryptor = ChunkedAttachmentDecryptor::new(info).unwrap();
+    /// for chunk in encrypted.chunks_mut(4) {
+    ///     decryptor.decrypt_chunk(chunk);
+    /// }
+    /// decryptor.finish().unwrap();
+    ///
+    /// assert_eq!(encrypted, data.as_bytes());
+    /// ```
+    pub fn new(info: MediaEncryptionInfo) -> Result<Self, DecryptorError> {
+        if info.version != VERSION {
+            return Err(DecryptorError::UnknownVersion);
+        }
+
+        let hash =
+            info.hashes.get("sha256").ok_or(DecryptorError::MissingHash)?.as_bytes().to_owned();
+        let mut key = info.key.k.into_inner();
+        let iv = info.iv.into_inner();
+
+        if key.len() != KEY_SIZE {
+            return Err(DecryptorError::KeyNonceLength);
+        }
+
+        let key_array = GenericArray::from_slice(&key);
+        let iv = GenericArray::from_exact_iter(iv).ok_or(DecryptorError::KeyNonceLength)?;
+
+        let sha = Sha256::default();
+
+        let aes = Aes256Ctr::new(key_array, &iv);
+        key.zeroize();
+
+        Ok(Self { expected_hash: hash, sha, aes })
+    }
+
+    /// Decrypt the next chunk of the attachment in place.
+    ///
+    /// Chunks must be passed in order, and can have any size.
+    pub fn decrypt_chunk(&mut self, chunk: &mut [u8]) {
+        self.sha.update(&*chunk);
+        self.aes.apply_keystream(chunk);
+    }
+
+    /// Check that the hash of all the chunks decrypted so far matches the
+    /// expected one, and reset it.
+    fn verify_hash(&mut self) -> bool {
+        self.sha.finalize_reset().as_slice() == self.expected_hash.as_slice()
+    }
+
+    /// Check, once all the chunks have been decrypted, that the encrypted data
+    /// matches the hash in the encryption info.
+    ///
+    /// If this fails, the decrypted data has been tampered with or is
+    /// incomplete, and must be discarded.
+    pub fn finish(mut self) -> Result<(), DecryptorError> {
+        if self.verify_hash() {
+            Ok(())
+        } else {
+            Err(DecryptorError::HashMismatch)
+        }
+    }
+}
+
 /// Error type for attachment decryption.
 #[derive(Error, Debug)]
 pub enum DecryptorError {
@@ -94,6 +188,10 @@ pub enum DecryptorError {
     /// attachment encryption spec.
     #[error("Unknown version for the encrypted attachment.")]
     UnknownVersion,
+    /// The hash of the encrypted data doesn't match the one from the
+    /// encryption info.
+    #[error("Hash mismatch while decrypting")]
+    HashMismatch,
 }
 
 impl<'a, R: Read + 'a> AttachmentDecryptor<'a, R> {
@@ -130,28 +228,9 @@ impl<'a, R: Read + 'a> AttachmentDecryptor<'a, R> {
         input: &'a mut R,
         info: MediaEncryptionInfo,
     ) -> Result<AttachmentDecryptor<'a, R>, DecryptorError> {
-        if info.version != VERSION {
-            return Err(DecryptorError::UnknownVersion);
-        }
-
-        let hash =
-            info.hashes.get("sha256").ok_or(DecryptorError::MissingHash)?.as_bytes().to_owned();
-        let mut key = info.key.k.into_inner();
-        let iv = info.iv.into_inner();
+        let decryptor = ChunkedAttachmentDecryptor::new(info)?;
 
-        if key.len() != KEY_SIZE {
-            return Err(DecryptorError::KeyNonceLength);
-        }
-
-        let key_array = GenericArray::from_slice(&key);
-        let iv = GenericArray::from_exact_iter(iv).ok_or(DecryptorError::KeyNonceLength)?;
-
-        let sha = Sha256::default();
-
-        let aes = Aes256Ctr::new(key_array, &iv);
-        key.zeroize();
-
-        Ok(AttachmentDecryptor { inner: input, expected_hash: hash, sha, aes })
+        Ok(AttachmentDecryptor { inner: input, decryptor })
     }
 }
 
@@ -303,9 +382,13 @@ impl From<EncryptedFile> for MediaEncryptionInfo {
 mod tests {
     use std::io::{Cursor, Read};
 
+    use assert_matches::assert_matches;
     use serde_json::json;
 
-    use super::{AttachmentDecryptor, AttachmentEncryptor, MediaEncryptionInfo};
+    use super::{
+        AttachmentDecryptor, AttachmentEncryptor, ChunkedAttachmentDecryptor, DecryptorError,
+        MediaEncryptionInfo,
+    };
 
     const EXAMPLE_DATA: &[u8] = &[
         179, 154, 118, 127, 186, 127, 110, 33, 203, 33, 33, 134, 67, 100, 173, 46, 235, 27, 215,
@@ -379,4 +462,27 @@ mod tests {
 
         decryptor.read_to_end(&mut decrypted_data).unwrap_err();
     }
+
+    #[test]
+    fn chunked_decrypt() {
+        let mut data = EXAMPLE_DATA.to_vec();
+        let mut decryptor = ChunkedAttachmentDecryptor::new(example_key()).unwrap();
+
+        for chunk in data.chunks_mut(5) {
+            decryptor.decrypt_chunk(chunk);
+        }
+        decryptor.finish().unwrap();
+
+        assert_eq!(data, b"It's a secret to everybody");
+    }
+
+    #[test]
+    fn chunked_decrypt_truncated() {
+        let mut data = EXAMPLE_DATA[..10].to_vec();
+        let mut decryptor = ChunkedAttachmentDecryptor::new(example_key()).unwrap();
+
+        decryptor.decrypt_chunk(&mut data);
+
+        assert_matches!(decryptor.finish(), Err(DecryptorError::HashMismatch));
+    }
 }
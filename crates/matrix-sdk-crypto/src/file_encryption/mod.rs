@@ -2,6 +2,7 @@ mod attachments;
 mod key_export;
 
 pub use attachments::{
-    AttachmentDecryptor, AttachmentEncryptor, DecryptorError, MediaEncryptionInfo,
+    AttachmentDecryptor, AttachmentEncryptor, ChunkedAttachmentDecryptor, DecryptorError,
+    MediaEncryptionInfo,
 };
 pub use key_export::{decrypt_room_key_export, encrypt_room_key_export, KeyExportError};
@@ -962,16 +962,20 @@ impl GossipMachine {
 
     async fn accept_forwarded_room_key(
         &self,
-        info: &GossipRequest,
+        info: Option<&GossipRequest>,
         sender_key: Curve25519PublicKey,
         event: &DecryptedForwardedRoomKeyEvent,
     ) -> Result<Option<InboundGroupSession>, CryptoStoreError> {
         match InboundGroupSession::try_from(event) {
             Ok(session) => {
+                let session = session.with_forwarder(sender_key);
+
                 if self.inner.store.compare_group_session(&session).await?
                     == SessionOrdering::Better
                 {
-                    self.mark_as_done(info).await?;
+                    if let Some(info) = info {
+                        self.mark_as_done(info).await?;
+                    }
 
                     info!(
                         ?sender_key,
@@ -1018,6 +1022,27 @@ impl GossipMachine {
         }
     }
 
+    /// Whether we should accept a forwarded room key that we didn't request.
+    ///
+    /// This is only the case if it was
+    /// [enabled](crate::OlmMachine::set_unrequested_room_key_forwards_enabled),
+    /// and if it was sent by one of our own verified devices, for example when
+    /// it forwards all the room keys of a room with
+    /// [`OlmMachine::forward_room_keys_to_own_device()`].
+    ///
+    /// [`OlmMachine::forward_room_keys_to_own_device()`]: crate::OlmMachine::forward_room_keys_to_own_device
+    async fn should_accept_unrequested_forward(
+        &self,
+        sender_key: Curve25519PublicKey,
+    ) -> Result<bool, CryptoStoreError> {
+        if !self.inner.store.get_unrequested_room_key_forwards_enabled().await? {
+            return Ok(false);
+        }
+
+        let device = self.inner.store.get_device_from_curve_key(self.user_id(), sender_key).await?;
+        Ok(device.is_some_and(|device| device.is_verified()))
+    }
+
     /// Receive a forwarded room key event that was sent using any of our
     /// supported content types.
     async fn receive_supported_keys(
@@ -1037,6 +1062,10 @@ impl GossipMachine {
         let Some(request) =
             self.inner.store.get_secret_request_by_info(&info.clone().into()).await?
         else {
+            if self.should_accept_unrequested_forward(sender_key).await? {
+                return self.accept_forwarded_room_key(None, sender_key, event).await;
+            }
+
             warn!(
                 sender_key = ?sender_key,
                 room_id = ?info.room_id(),
@@ -1049,7 +1078,7 @@ impl GossipMachine {
         };
 
         if self.should_accept_forward(&request, sender_key).await? {
-            self.accept_forwarded_room_key(&request, sender_key, event).await
+            self.accept_forwarded_room_key(Some(&request), sender_key, event).await
         } else {
             warn!(
                 ?sender_key,
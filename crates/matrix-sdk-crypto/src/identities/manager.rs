@@ -20,11 +20,13 @@ use std::{
 };
 
 use futures_util::future::join_all;
-use itertools::Itertools;
-use matrix_sdk_common::{executor::spawn, failures_cache::FailuresCache};
+use matrix_sdk_common::{
+    executor::spawn, failures_cache::FailuresCache, locks::RwLock as StdRwLock,
+};
 use ruma::{
-    api::client::keys::get_keys::v3::Response as KeysQueryResponse, serde::Raw, OwnedDeviceId,
-    OwnedServerName, OwnedTransactionId, OwnedUserId, ServerName, TransactionId, UserId,
+    api::client::keys::get_keys::v3::Response as KeysQueryResponse, serde::Raw, time::Instant,
+    OwnedDeviceId, OwnedServerName, OwnedTransactionId, OwnedUserId, ServerName, TransactionId,
+    UserId,
 };
 use tokio::sync::Mutex;
 use tracing::{debug, enabled, info, instrument, trace, warn, Level};
@@ -44,6 +46,28 @@ use crate::{
     CryptoStoreError, LocalTrust, OwnUserIdentity, SignatureError, UserIdentity,
 };
 
+/// The state of the device list of a tracked user, as returned by
+/// [`OlmMachine::tracked_user_state()`].
+///
+/// [`OlmMachine::tracked_user_state()`]: crate::OlmMachine::tracked_user_state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackedUserState {
+    /// The device list of the user is up-to-date.
+    UpToDate,
+
+    /// The device list of the user is outdated, and will be updated by the
+    /// next `/keys/query` request.
+    PendingQuery,
+
+    /// The device list of the user is outdated, but the server of the user
+    /// failed to respond to the previous `/keys/query` requests.
+    ///
+    /// The server isn't queried again until a delay, which increases
+    /// exponentially with the number of failures, expires. The state then
+    /// goes back to [`TrackedUserState::PendingQuery`].
+    ServerUnreachable,
+}
+
 enum DeviceChange {
     New(DeviceData),
     Updated(DeviceData),
@@ -70,21 +94,26 @@ pub(crate) struct IdentityManager {
 
     pub(crate) key_query_manager: Arc<KeyQueryManager>,
 
-    /// Details of the current "in-flight" key query request, if any
-    keys_query_request_details: Arc<Mutex<Option<KeysQueryRequestDetails>>>,
+    /// Details of the "in-flight" key query requests, keyed by request id.
+    keys_query_request_details: Arc<Mutex<HashMap<OwnedTransactionId, KeysQueryRequestDetails>>>,
+
+    /// Users whose keys should be queried before the others, e.g. the members
+    /// of the room the user is currently looking at.
+    priority_users: Arc<StdRwLock<BTreeSet<OwnedUserId>>>,
 }
 
 /// Details of an in-flight key query request
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 struct KeysQueryRequestDetails {
     /// The sequence number, to be passed to
     /// `Store.mark_tracked_users_as_up_to_date`.
     sequence_number: SequenceNumber,
 
-    /// A single batch of queries returned by the Store is broken up into one or
-    /// more actual KeysQueryRequests, each with their own request id. We
-    /// record the outstanding request ids here.
-    request_ids: HashSet<OwnedTransactionId>,
+    /// The users whose keys are queried by this request.
+    users: HashSet<OwnedUserId>,
+
+    /// When the request was created.
+    created_at: Instant,
 }
 
 // Helper type to handle key query response
@@ -97,14 +126,21 @@ struct KeySetInfo {
 impl IdentityManager {
     const MAX_KEY_QUERY_USERS: usize = 250;
 
-    pub fn new(store: Store) -> Self {
-        let keys_query_request_details = Mutex::new(None);
+    /// How long an in-flight key query request prevents the same users from
+    /// being queried again.
+    ///
+    /// Failed requests should be reported with
+    /// [`IdentityManager::receive_keys_query_failure()`]; if they aren't, we
+    /// assume after this delay that they failed and query the users again.
+    const IN_FLIGHT_KEY_QUERY_TIMEOUT: Duration = Duration::from_secs(60);
 
+    pub fn new(store: Store) -> Self {
         IdentityManager {
             store,
             key_query_manager: Default::default(),
             failures: Default::default(),
-            keys_query_request_details: keys_query_request_details.into(),
+            keys_query_request_details: Default::default(),
+            priority_users: Default::default(),
         }
     }
 
@@ -112,6 +148,19 @@ impl IdentityManager {
         &self.store.static_account().user_id
     }
 
+    /// Forget about an in-flight `/keys/query` request that failed, so that
+    /// its users are queried again by the next call to
+    /// [`IdentityManager::users_for_key_query()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - The request_id returned by `users_for_key_query`
+    pub async fn receive_keys_query_failure(&self, request_id: &TransactionId) {
+        if self.keys_query_request_details.lock().await.remove(request_id).is_some() {
+            debug!(?request_id, "A /keys/query request failed, its users will be queried again");
+        }
+    }
+
     /// Receive a successful `/keys/query` response.
     ///
     /// Returns a list of devices newly discovered devices and devices that
@@ -185,17 +234,12 @@ impl IdentityManager {
         // if this request is one of those we expected to be in flight, pass the
         // sequence number back to the store so that it can mark devices up to
         // date
-        let sequence_number = {
-            let mut request_details = self.keys_query_request_details.lock().await;
-
-            request_details.as_mut().and_then(|details| {
-                if details.request_ids.remove(request_id) {
-                    Some(details.sequence_number)
-                } else {
-                    None
-                }
-            })
-        };
+        let sequence_number = self
+            .keys_query_request_details
+            .lock()
+            .await
+            .remove(request_id)
+            .map(|details| details.sequence_number);
 
         if let Some(sequence_number) = sequence_number {
             let cache = self.store.cache().await?;
@@ -810,8 +854,29 @@ impl IdentityManager {
         (TransactionId::new(), KeysQueryRequest::new(users.into_iter().map(|u| u.to_owned())))
     }
 
+    /// Set the users whose keys should be queried before the others.
+    ///
+    /// See [`OlmMachine::set_key_query_priority_users()`].
+    ///
+    /// [`OlmMachine::set_key_query_priority_users()`]: crate::OlmMachine::set_key_query_priority_users
+    pub fn set_priority_users<'a>(&self, users: impl IntoIterator<Item = &'a UserId>) {
+        *self.priority_users.write() = users.into_iter().map(ToOwned::to_owned).collect();
+    }
+
+    /// Whether the given key query request contains users whose keys should be
+    /// queried before the others.
+    pub fn is_priority_request(&self, request: &KeysQueryRequest) -> bool {
+        let priority_users = self.priority_users.read();
+        request.device_keys.keys().any(|user_id| priority_users.contains(user_id))
+    }
+
     /// Get a list of key query requests needed.
     ///
+    /// Users whose keys are already being queried by an in-flight request are
+    /// skipped, unless their device list changed since that request was
+    /// created. Users set with [`IdentityManager::set_priority_users()`] are
+    /// put in their own requests, so they can be sent first.
+    ///
     /// # Returns
     ///
     /// A map of a request ID to the `/keys/query` request.
@@ -823,9 +888,6 @@ impl IdentityManager {
     pub async fn users_for_key_query(
         &self,
     ) -> StoreResult<BTreeMap<OwnedTransactionId, KeysQueryRequest>> {
-        // Forget about any previous key queries in flight.
-        *self.keys_query_request_details.lock().await = None;
-
         // We always want to track our own user, but in case we aren't in an encrypted
         // room yet, we won't be tracking ourselves yet. This ensures we are always
         // tracking ourselves.
@@ -852,7 +914,28 @@ impl IdentityManager {
             // a TTL cache, remembers users for which a previous `/key/query` request has
             // failed. We don't retry a `/keys/query` for such users for a
             // certain amount of time.
-            let users = users.into_iter().filter(|u| !self.failures.contains(u.server_name()));
+            let mut in_flight = self.keys_query_request_details.lock().await;
+
+            // Forget about the requests that have been in flight for too long, they have
+            // most likely failed.
+            in_flight.retain(|_, details| {
+                details.created_at.elapsed() < Self::IN_FLIGHT_KEY_QUERY_TIMEOUT
+            });
+
+            // Don't query the same users again if a request that will bring their devices
+            // up-to-date is already in flight.
+            let users = users.into_iter().filter_map(|(user_id, invalidation)| {
+                let coalesced = in_flight.values().any(|details| {
+                    details.sequence_number >= invalidation && details.users.contains(&user_id)
+                });
+
+                (!self.failures.contains(user_id.server_name()) && !coalesced).then_some(user_id)
+            });
+
+            let (priority_users, other_users): (Vec<_>, Vec<_>) = {
+                let priority_users = self.priority_users.read();
+                users.partition(|user_id| priority_users.contains(user_id))
+            };
 
             // We don't want to create a single `/keys/query` request with an infinite
             // amount of users. Some servers will likely bail out after a
@@ -861,26 +944,30 @@ impl IdentityManager {
             // response.
             //
             // Convert the set of users into multiple /keys/query requests.
-            let requests: BTreeMap<_, _> = users
-                .chunks(Self::MAX_KEY_QUERY_USERS)
-                .into_iter()
-                .map(|user_chunk| {
-                    let request_id = TransactionId::new();
-                    let request = KeysQueryRequest::new(user_chunk);
+            let mut requests = BTreeMap::new();
 
-                    debug!(?request_id, users = ?request.device_keys.keys(), "Created a /keys/query request");
-
-                    (request_id, request)
-                })
-                .collect();
-
-            // Collect the request IDs, these will be used later in the
-            // `receive_keys_query_response()` method to figure out if the user can be
-            // marked as up-to-date/non-dirty.
-            let request_ids = requests.keys().cloned().collect();
-            let request_details = KeysQueryRequestDetails { sequence_number, request_ids };
-
-            *self.keys_query_request_details.lock().await = Some(request_details);
+            for user_chunk in priority_users
+                .chunks(Self::MAX_KEY_QUERY_USERS)
+                .chain(other_users.chunks(Self::MAX_KEY_QUERY_USERS))
+            {
+                let request_id = TransactionId::new();
+                let request = KeysQueryRequest::new(user_chunk.iter().cloned());
+
+                debug!(?request_id, users = ?request.device_keys.keys(), "Created a /keys/query request");
+
+                // Remember the details of the request, they will be used later in the
+                // `receive_keys_query_response()` method to figure out if the users can be
+                // marked as up-to-date/non-dirty.
+                in_flight.insert(
+                    request_id.clone(),
+                    KeysQueryRequestDetails {
+                        sequence_number,
+                        users: user_chunk.iter().cloned().collect(),
+                        created_at: Instant::now(),
+                    },
+                );
+                requests.insert(request_id, request);
+            }
 
             Ok(requests)
         }
@@ -901,6 +988,31 @@ impl IdentityManager {
         self.key_query_manager.synced(cache).await?.mark_tracked_users_as_changed(users).await
     }
 
+    /// See the docs for [`OlmMachine::tracked_user_state()`].
+    ///
+    /// [`OlmMachine::tracked_user_state()`]: crate::OlmMachine::tracked_user_state
+    pub async fn tracked_user_state(
+        &self,
+        user_id: &UserId,
+    ) -> StoreResult<Option<TrackedUserState>> {
+        let cache = self.store.cache().await?;
+        let key_query_manager = self.key_query_manager.synced(&cache).await?;
+
+        if !key_query_manager.tracked_users().contains(user_id) {
+            return Ok(None);
+        }
+
+        let state = if !key_query_manager.is_user_outdated(user_id).await {
+            TrackedUserState::UpToDate
+        } else if self.failures.contains(user_id.server_name()) {
+            TrackedUserState::ServerUnreachable
+        } else {
+            TrackedUserState::PendingQuery
+        };
+
+        Ok(Some(state))
+    }
+
     /// See the docs for [`OlmMachine::update_tracked_users()`].
     pub async fn update_tracked_users(
         &self,
@@ -1535,7 +1647,10 @@ pub(crate) mod tests {
         device_id, key_query, manager_test_helper, other_key_query, other_user_id, user_id,
     };
     use crate::{
-        identities::manager::testing::{other_key_query_cross_signed, own_key_query},
+        identities::manager::{
+            testing::{other_key_query_cross_signed, own_key_query},
+            TrackedUserState,
+        },
         olm::PrivateCrossSigningIdentity,
         CrossSigningKeyExport, OlmMachine,
     };
@@ -1573,7 +1688,7 @@ pub(crate) mod tests {
         );
 
         assert!(
-            !key_query_manager.users_for_key_query().await.0.contains(alice),
+            !key_query_manager.users_for_key_query().await.0.contains_key(alice),
             "The user we don't track doesn't end up in the `/keys/query` request"
         );
     }
@@ -1796,6 +1911,76 @@ pub(crate) mod tests {
         assert!(!queries.iter().any(|(_, r)| r.device_keys.contains_key(alice)));
     }
 
+    #[async_test]
+    async fn test_in_flight_key_queries_are_coalesced() {
+        let manager = manager_test_helper(user_id(), device_id()).await;
+        let alice = other_user_id();
+        manager.update_tracked_users([alice]).await.unwrap();
+
+        let (reqid, req) = manager.users_for_key_query().await.unwrap().pop_first().unwrap();
+        assert!(req.device_keys.contains_key(alice));
+
+        // alice is already being queried, so she isn't queried again.
+        let queries = manager.users_for_key_query().await.unwrap();
+        assert!(!queries.iter().any(|(_, r)| r.device_keys.contains_key(alice)));
+
+        // Unless her device list changes in the meantime.
+        {
+            let cache = manager.store.cache().await.unwrap();
+            manager.receive_device_changes(&cache, [alice].into_iter()).await.unwrap();
+        }
+
+        let (second_reqid, req) = manager.users_for_key_query().await.unwrap().pop_first().unwrap();
+        assert!(req.device_keys.contains_key(alice));
+
+        // Both responses arrive, alice is now up-to-date.
+        manager.receive_keys_query_response(&reqid, &other_key_query()).await.unwrap();
+        manager.receive_keys_query_response(&second_reqid, &other_key_query()).await.unwrap();
+
+        let queries = manager.users_for_key_query().await.unwrap();
+        assert!(!queries.iter().any(|(_, r)| r.device_keys.contains_key(alice)));
+    }
+
+    #[async_test]
+    async fn test_failed_key_query_is_retried() {
+        let manager = manager_test_helper(user_id(), device_id()).await;
+        let alice = other_user_id();
+        manager.update_tracked_users([alice]).await.unwrap();
+
+        let (reqid, req) = manager.users_for_key_query().await.unwrap().pop_first().unwrap();
+        assert!(req.device_keys.contains_key(alice));
+
+        // The request is in flight, so alice isn't queried again.
+        let queries = manager.users_for_key_query().await.unwrap();
+        assert!(!queries.iter().any(|(_, r)| r.device_keys.contains_key(alice)));
+
+        // Once it failed, she is queried again right away.
+        manager.receive_keys_query_failure(&reqid).await;
+
+        let queries = manager.users_for_key_query().await.unwrap();
+        assert!(queries.iter().any(|(_, r)| r.device_keys.contains_key(alice)));
+    }
+
+    #[async_test]
+    async fn test_priority_users_are_queried_separately() {
+        let manager = manager_test_helper(user_id(), device_id()).await;
+        let alice = other_user_id();
+        let bob = user_id!("@bob:example.org");
+        manager.update_tracked_users([alice, bob]).await.unwrap();
+        manager.set_priority_users([alice]);
+
+        let queries = manager.users_for_key_query().await.unwrap();
+        assert_eq!(queries.len(), 2);
+
+        let (_, priority_request) =
+            queries.iter().find(|(_, r)| manager.is_priority_request(r)).unwrap();
+        assert_eq!(priority_request.device_keys.keys().collect::<Vec<_>>(), [alice]);
+
+        let (_, other_request) =
+            queries.iter().find(|(_, r)| !manager.is_priority_request(r)).unwrap();
+        assert!(other_request.device_keys.contains_key(bob));
+    }
+
     #[async_test]
     async fn test_failure_handling() {
         let manager = manager_test_helper(user_id(), device_id()).await;
@@ -1838,6 +2023,55 @@ pub(crate) mod tests {
             .any(|(_, r)| r.device_keys.contains_key(alice)));
     }
 
+    #[async_test]
+    async fn test_tracked_user_state() {
+        let manager = manager_test_helper(user_id(), device_id()).await;
+        let alice = user_id!("@alice:example.org");
+
+        assert_eq!(manager.tracked_user_state(alice).await.unwrap(), None);
+
+        {
+            let cache = manager.store.cache().await.unwrap();
+            let key_query_manager = manager.key_query_manager.synced(&cache).await.unwrap();
+            key_query_manager.mark_user_as_changed(alice).await.unwrap();
+        }
+
+        assert_eq!(
+            manager.tracked_user_state(alice).await.unwrap(),
+            Some(TrackedUserState::PendingQuery)
+        );
+
+        // The server of the user fails to respond.
+        let (reqid, _) = manager.users_for_key_query().await.unwrap().pop_first().unwrap();
+        manager.receive_keys_query_response(&reqid, &key_query_with_failures()).await.unwrap();
+
+        assert_eq!(
+            manager.tracked_user_state(alice).await.unwrap(),
+            Some(TrackedUserState::ServerUnreachable)
+        );
+
+        // Once the backoff expires, the user is queried again.
+        manager.failures.expire(&alice.server_name().to_owned());
+        assert_eq!(
+            manager.tracked_user_state(alice).await.unwrap(),
+            Some(TrackedUserState::PendingQuery)
+        );
+
+        let (reqid, req) = manager.users_for_key_query().await.unwrap().pop_first().unwrap();
+        assert!(req.device_keys.contains_key(alice));
+
+        let response: KeysQueryResponse = ruma_response_from_json(&json!({
+            "device_keys": { "@alice:example.org": {} },
+            "failures": {},
+        }));
+        manager.receive_keys_query_response(&reqid, &response).await.unwrap();
+
+        assert_eq!(
+            manager.tracked_user_state(alice).await.unwrap(),
+            Some(TrackedUserState::UpToDate)
+        );
+    }
+
     #[async_test]
     async fn test_out_of_band_key_query() {
         // build the request
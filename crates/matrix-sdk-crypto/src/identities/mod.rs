@@ -52,6 +52,7 @@ use std::sync::{
 
 pub use device::{Device, DeviceData, LocalTrust, UserDevices};
 pub(crate) use manager::IdentityManager;
+pub use manager::TrackedUserState;
 use serde::{Deserialize, Deserializer, Serializer};
 pub use user::{
     OtherUserIdentity, OtherUserIdentityData, OwnUserIdentity, OwnUserIdentityData, UserIdentity,
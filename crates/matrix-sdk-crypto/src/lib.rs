@@ -29,6 +29,7 @@ mod machine;
 pub mod olm;
 pub mod secret_storage;
 mod session_manager;
+pub mod signing;
 pub mod store;
 pub mod types;
 mod utilities;
@@ -83,18 +84,20 @@ pub use error::{
 };
 pub use file_encryption::{
     decrypt_room_key_export, encrypt_room_key_export, AttachmentDecryptor, AttachmentEncryptor,
-    DecryptorError, KeyExportError, MediaEncryptionInfo,
+    ChunkedAttachmentDecryptor, DecryptorError, KeyExportError, MediaEncryptionInfo,
 };
 pub use gossiping::{GossipRequest, GossippedSecret};
 pub use identities::{
     Device, DeviceData, LocalTrust, OtherUserIdentity, OtherUserIdentityData, OwnUserIdentity,
-    OwnUserIdentityData, UserDevices, UserIdentity, UserIdentityData,
+    OwnUserIdentityData, TrackedUserState, UserDevices, UserIdentity, UserIdentityData,
 };
 pub use machine::{CrossSigningBootstrapRequests, EncryptionSyncChanges, OlmMachine};
 use matrix_sdk_common::deserialized_responses::{DecryptedRoomEvent, UnableToDecryptInfo};
 #[cfg(feature = "qrcode")]
 pub use matrix_sdk_qrcode;
-pub use olm::{Account, CrossSigningStatus, EncryptionSettings, Session};
+pub use olm::{
+    Account, CrossSigningStatus, EncryptionSettings, OneTimeKeysPolicy, Session, SessionShareInfo,
+};
 use serde::{Deserialize, Serialize};
 pub use session_manager::CollectStrategy;
 pub use store::{
@@ -13,11 +13,12 @@
 // limitations under the License.
 
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     sync::Arc,
     time::Duration,
 };
 
+use futures_core::Stream;
 use itertools::Itertools;
 use matrix_sdk_common::{
     deserialized_responses::{
@@ -45,6 +46,7 @@ use ruma::{
         AnyToDeviceEvent, MessageLikeEventContent,
     },
     serde::{JsonObject, Raw},
+    time::Instant,
     DeviceId, MilliSecondsSinceUnixEpoch, OneTimeKeyAlgorithm, OwnedDeviceId, OwnedDeviceKeyId,
     OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt, UserId,
 };
@@ -65,11 +67,12 @@ use crate::{
     dehydrated_devices::{DehydratedDevices, DehydrationError},
     error::{EventError, MegolmError, MegolmResult, OlmError, OlmResult, SetRoomSettingsError},
     gossiping::GossipMachine,
-    identities::{user::UserIdentity, Device, IdentityManager, UserDevices},
+    identities::{user::UserIdentity, Device, IdentityManager, TrackedUserState, UserDevices},
     olm::{
         Account, CrossSigningStatus, EncryptionSettings, IdentityKeys, InboundGroupSession,
-        KnownSenderData, OlmDecryptionInfo, PrivateCrossSigningIdentity, SenderData,
-        SenderDataFinder, SessionType, StaticAccountData,
+        KnownSenderData, OlmDecryptionInfo, OlmMessageHash, OneTimeKeysPolicy,
+        PrivateCrossSigningIdentity, SenderData, SenderDataFinder, SessionShareInfo, SessionType,
+        StaticAccountData,
     },
     session_manager::{GroupSessionManager, SessionManager},
     store::{
@@ -88,7 +91,7 @@ use crate::{
             room_key_withheld::{
                 MegolmV1AesSha2WithheldContent, RoomKeyWithheldContent, RoomKeyWithheldEvent,
             },
-            ToDeviceEvents,
+            EventType, ToDeviceEvents,
         },
         requests::{
             AnyIncomingResponse, KeysQueryRequest, OutgoingRequest, ToDeviceRequest,
@@ -138,6 +141,10 @@ pub struct OlmMachineInner {
     identity_manager: IdentityManager,
     /// A state machine that handles creating room key backups.
     backup_machine: BackupMachine,
+    /// The policy deciding when new one-time keys are generated and uploaded.
+    one_time_keys_policy: StdRwLock<OneTimeKeysPolicy>,
+    /// When the number of one-time keys on the server was last received.
+    one_time_keys_count_updated_at: StdRwLock<Option<Instant>>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -251,6 +258,8 @@ impl OlmMachine {
             key_request_machine,
             identity_manager,
             backup_machine,
+            one_time_keys_policy: Default::default(),
+            one_time_keys_count_updated_at: Default::default(),
         });
 
         Self { inner }
@@ -462,6 +471,18 @@ impl OlmMachine {
         Ok(self.inner.identity_manager.key_query_manager.synced(&cache).await?.tracked_users())
     }
 
+    /// Get the state of the device list of the given user.
+    ///
+    /// Returns `None` if the user isn't tracked. Users whose server failed to
+    /// respond to a `/keys/query` request are queried again automatically,
+    /// with an exponential backoff.
+    pub async fn tracked_user_state(
+        &self,
+        user_id: &UserId,
+    ) -> StoreResult<Option<TrackedUserState>> {
+        self.inner.identity_manager.tracked_user_state(user_id).await
+    }
+
     /// Enable or disable room key requests.
     ///
     /// Room key requests allow the device to request room keys that it might
@@ -503,6 +524,28 @@ impl OlmMachine {
         self.inner.key_request_machine.is_room_key_forwarding_enabled()
     }
 
+    /// Enable or disable accepting the room keys forwarded by our own verified
+    /// devices that we didn't request.
+    ///
+    /// This allows another device of our own user to share the history of a
+    /// room with this device, with
+    /// [`OlmMachine::forward_room_keys_to_own_device()`]. It is disabled by
+    /// default, so only the forwarded room keys that we requested are
+    /// accepted. The setting is persisted in the store.
+    ///
+    /// See also [`OlmMachine::are_unrequested_room_key_forwards_enabled`].
+    pub async fn set_unrequested_room_key_forwards_enabled(&self, enable: bool) -> StoreResult<()> {
+        self.store().set_unrequested_room_key_forwards_enabled(enable).await
+    }
+
+    /// Are the room keys forwarded by our own verified devices that we didn't
+    /// request accepted?
+    ///
+    /// See also [`OlmMachine::set_unrequested_room_key_forwards_enabled`].
+    pub async fn are_unrequested_room_key_forwards_enabled(&self) -> StoreResult<bool> {
+        self.store().get_unrequested_room_key_forwards_enabled().await
+    }
+
     /// Get the outgoing requests that need to be sent out.
     ///
     /// This returns a list of [`OutgoingRequest`]. Those requests need to be
@@ -516,19 +559,26 @@ impl OlmMachine {
         {
             let store_cache = self.inner.store.cache().await?;
             let account = store_cache.account().await?;
-            if let Some(r) = self.keys_for_upload(&account).await.map(|r| OutgoingRequest {
-                request_id: TransactionId::new(),
-                request: Arc::new(r.into()),
-            }) {
+            if let Some(r) = self
+                .keys_for_upload(&account)
+                .await
+                .or_else(|| self.one_time_keys_reconciliation_request(&account))
+                .map(|r| OutgoingRequest {
+                    request_id: TransactionId::new(),
+                    request: Arc::new(r.into()),
+                })
+            {
                 requests.push(r);
             }
         }
 
-        for request in self
-            .inner
-            .identity_manager
-            .users_for_key_query()
-            .await?
+        let mut key_queries: Vec<_> =
+            self.inner.identity_manager.users_for_key_query().await?.into_iter().collect();
+
+        // Put the requests for the priority users first, so they are sent first.
+        key_queries.sort_by_key(|(_, r)| !self.inner.identity_manager.is_priority_request(r));
+
+        for request in key_queries
             .into_iter()
             .map(|(request_id, r)| OutgoingRequest { request_id, request: Arc::new(r.into()) })
         {
@@ -541,6 +591,75 @@ impl OlmMachine {
         Ok(requests)
     }
 
+    /// Set the policy deciding when new one-time keys are generated and
+    /// uploaded, and when the number of one-time keys on the server is
+    /// checked.
+    ///
+    /// See [`OneTimeKeysPolicy`] for the details.
+    pub fn set_one_time_keys_policy(&self, policy: OneTimeKeysPolicy) {
+        *self.inner.one_time_keys_policy.write() = policy;
+    }
+
+    /// Get the policy deciding when new one-time keys are generated and
+    /// uploaded.
+    pub fn one_time_keys_policy(&self) -> OneTimeKeysPolicy {
+        *self.inner.one_time_keys_policy.read()
+    }
+
+    /// Receive the devices for which a one-time key couldn't be claimed while
+    /// creating Olm sessions, because they ran out of one-time keys.
+    ///
+    /// Room keys can't be shared with these devices until they upload new
+    /// one-time keys, or a fallback key.
+    pub fn one_time_keys_exhausted_stream(
+        &self,
+    ) -> impl Stream<Item = BTreeMap<OwnedUserId, BTreeSet<OwnedDeviceId>>> {
+        self.inner.session_manager.one_time_keys_exhausted_stream()
+    }
+
+    /// Get an empty `/keys/upload` request to find out the number of one-time
+    /// keys on the server, if it wasn't received for longer than the
+    /// reconciliation interval of the [`OneTimeKeysPolicy`].
+    fn one_time_keys_reconciliation_request(&self, account: &Account) -> Option<UploadKeysRequest> {
+        let interval = self.one_time_keys_policy().reconciliation_interval?;
+
+        // Before the account is shared, the device keys are uploaded anyway.
+        if !account.shared() {
+            return None;
+        }
+
+        let is_up_to_date = self
+            .inner
+            .one_time_keys_count_updated_at
+            .read()
+            .is_some_and(|updated_at| updated_at.elapsed() < interval);
+
+        if !is_up_to_date {
+            debug!("Checking the number of one-time keys on the server");
+            Some(UploadKeysRequest::new())
+        } else {
+            None
+        }
+    }
+
+    /// Remember that the number of one-time keys on the server was just
+    /// received.
+    fn mark_one_time_keys_count_as_updated(&self) {
+        *self.inner.one_time_keys_count_updated_at.write() = Some(Instant::now());
+    }
+
+    /// Set the users whose keys should be queried before the others.
+    ///
+    /// This is typically used with the members of the room the user is
+    /// currently looking at, so that the trust state of their devices is known
+    /// as soon as possible. The `/keys/query` requests for these users are
+    /// returned first by [`OlmMachine::outgoing_requests()`].
+    ///
+    /// Calling this method replaces the previous set of priority users.
+    pub fn set_key_query_priority_users<'a>(&self, users: impl IntoIterator<Item = &'a UserId>) {
+        self.inner.identity_manager.set_priority_users(users);
+    }
+
     /// Generate an "out-of-band" key query request for the given set of users.
     ///
     /// This can be useful if we need the results from [`get_identity`] or
@@ -731,14 +850,22 @@ impl OlmMachine {
     /// * `response` - The response of the `/keys/upload` request that the
     ///   client performed.
     async fn receive_keys_upload_response(&self, response: &UploadKeysResponse) -> OlmResult<()> {
+        let policy = self.one_time_keys_policy();
+
         self.inner
             .store
             .with_transaction(|mut tr| async {
                 let account = tr.account().await?;
-                account.receive_keys_upload_response(response)?;
+                account.receive_keys_upload_response_with_policy(response, &policy)?;
                 Ok((tr, ()))
             })
-            .await
+            .await?;
+
+        if response.one_time_key_counts.contains_key(&OneTimeKeyAlgorithm::SignedCurve25519) {
+            self.mark_one_time_keys_count_as_updated();
+        }
+
+        Ok(())
     }
 
     /// Get a key claiming request for the user/device pairs that we are
@@ -792,6 +919,20 @@ impl OlmMachine {
         self.inner.identity_manager.receive_keys_query_response(request_id, response).await
     }
 
+    /// Mark a `/keys/query` request returned by
+    /// [`OlmMachine::outgoing_requests()`] as failed.
+    ///
+    /// The users of a `/keys/query` request aren't queried again while it's in
+    /// flight; this lets the next call to [`OlmMachine::outgoing_requests()`]
+    /// retry them.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - The unique id of the request that failed.
+    pub async fn mark_keys_query_as_failed(&self, request_id: &TransactionId) {
+        self.inner.identity_manager.receive_keys_query_failure(request_id).await
+    }
+
     /// Get a request to upload E2EE keys to the server.
     ///
     /// Returns None if no keys need to be uploaded.
@@ -1063,6 +1204,25 @@ impl OlmMachine {
         self.inner.group_session_manager.invalidate_group_session(room_id).await
     }
 
+    /// Get who the outbound group session with the given ID was shared with,
+    /// and the reasons why it was withheld from the other devices.
+    ///
+    /// Only the current outbound group session of a room is kept, so this
+    /// returns `None` if the session was rotated, or if it doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The room id of the room where the session is used.
+    ///
+    /// `session_id` - The id of the session.
+    pub async fn session_share_info(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Option<SessionShareInfo> {
+        self.inner.group_session_manager.session_share_info(room_id, session_id).await
+    }
+
     /// Get to-device requests to share a room key with users in a room.
     ///
     /// # Arguments
@@ -1091,6 +1251,67 @@ impl OlmMachine {
         self.inner.group_session_manager.share_room_key(room_id, users, encryption_settings).await
     }
 
+    /// Get to-device requests to forward all the room keys of a room to
+    /// another device of our own user.
+    ///
+    /// This allows a new device to decrypt the history of a single room,
+    /// without importing a whole backup. The Olm sessions with the device need
+    /// to be established beforehand, using [`OlmMachine::get_missing_sessions`].
+    /// The other device only accepts the room keys if it
+    /// [enabled it](OlmMachine::set_unrequested_room_key_forwards_enabled),
+    /// and if it verified this device.
+    ///
+    /// A to-device request can only hold one message per device, so there is
+    /// one request per room key.
+    ///
+    /// Returns `None` if the device isn't known, or isn't verified.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The room id of the room whose room keys will be forwarded.
+    ///
+    /// `device_id` - The device id of our own device that should receive the
+    /// room keys.
+    pub async fn forward_room_keys_to_own_device(
+        &self,
+        room_id: &RoomId,
+        device_id: &DeviceId,
+    ) -> OlmResult<Option<Vec<ToDeviceRequest>>> {
+        let Some(device) = self.get_device(self.user_id(), device_id, None).await? else {
+            return Ok(None);
+        };
+
+        if device.device_id() == self.device_id() || !device.is_verified() {
+            return Ok(None);
+        }
+
+        let mut sessions = self.store().get_inbound_group_sessions().await?;
+        sessions.retain(|session| session.room_id() == room_id);
+
+        let mut requests = Vec::with_capacity(sessions.len());
+        let mut used_session = None;
+
+        for session in sessions {
+            let (session, content) = device.encrypt_room_key_for_forwarding(session, None).await?;
+
+            requests.push(ToDeviceRequest::new(
+                device.user_id(),
+                device.device_id().to_owned(),
+                content.event_type(),
+                content.cast(),
+            ));
+            used_session = Some(session);
+        }
+
+        if let Some(session) = used_session {
+            self.store().save_sessions(&[session]).await?;
+        }
+
+        debug!(?room_id, ?device_id, count = requests.len(), "Forwarding room keys to own device");
+
+        Ok(Some(requests))
+    }
+
     /// Receive an unencrypted verification event.
     ///
     /// This method can be used to pass verification events that are happening
@@ -1259,13 +1480,45 @@ impl OlmMachine {
         }
     }
 
+    /// Check whether an encrypted to-device event has already been decrypted,
+    /// either earlier in the same sync response or by another process sharing
+    /// the same crypto store, like a notification service extension.
+    ///
+    /// Decrypting such a replayed event a second time would fail and cause the
+    /// Olm session to be considered as wedged, so it must be skipped instead.
+    async fn is_to_device_event_replayed(
+        &self,
+        changes: &Changes,
+        event: &EncryptedToDeviceEvent,
+    ) -> bool {
+        let Some(message_hash) = OlmMessageHash::from_event(event) else {
+            return false;
+        };
+
+        if changes.message_hashes.contains(&message_hash) {
+            return true;
+        }
+
+        match self.store().is_message_known(&message_hash).await {
+            Ok(known) => known,
+            Err(e) => {
+                warn!(error = ?e, "Couldn't check if a to-device event was already decrypted");
+                false
+            }
+        }
+    }
+
+    /// Handle a single to-device event.
+    ///
+    /// Returns `None` if the event is an encrypted event that was already
+    /// processed, in which case it should be dropped.
     #[instrument(skip_all, fields(sender, event_type, message_id))]
     async fn receive_to_device_event(
         &self,
         transaction: &mut StoreTransaction,
         changes: &mut Changes,
         mut raw_event: Raw<AnyToDeviceEvent>,
-    ) -> Raw<AnyToDeviceEvent> {
+    ) -> Option<Raw<AnyToDeviceEvent>> {
         Self::record_message_id(&raw_event);
 
         let event: ToDeviceEvents = match raw_event.deserialize_as() {
@@ -1274,7 +1527,7 @@ impl OlmMachine {
                 // Skip invalid events.
                 warn!("Received an invalid to-device event: {e}");
 
-                return raw_event;
+                return Some(raw_event);
             }
         };
 
@@ -1282,6 +1535,11 @@ impl OlmMachine {
 
         match event {
             ToDeviceEvents::RoomEncrypted(e) => {
+                if self.is_to_device_event_replayed(changes, &e).await {
+                    info!("Skipping a to-device event that was already decrypted");
+                    return None;
+                }
+
                 let decrypted = match self.decrypt_to_device_event(transaction, &e, changes).await {
                     Ok(e) => e,
                     Err(err) => {
@@ -1299,7 +1557,7 @@ impl OlmMachine {
                             }
                         }
 
-                        return raw_event;
+                        return Some(raw_event);
                     }
                 };
 
@@ -1336,7 +1594,7 @@ impl OlmMachine {
             e => self.handle_to_device_event(changes, &e).await,
         }
 
-        raw_event
+        Some(raw_event)
     }
 
     /// Handle a to-device and one-time key counts from a sync response.
@@ -1391,10 +1649,15 @@ impl OlmMachine {
 
         {
             let account = transaction.account().await?;
-            account.update_key_counts(
+            account.update_key_counts_with_policy(
                 sync_changes.one_time_keys_counts,
                 sync_changes.unused_fallback_keys,
-            )
+                &self.one_time_keys_policy(),
+            );
+        }
+
+        if sync_changes.one_time_keys_counts.contains_key(&OneTimeKeyAlgorithm::SignedCurve25519) {
+            self.mark_one_time_keys_count_as_updated();
         }
 
         if let Err(e) = self
@@ -1410,9 +1673,11 @@ impl OlmMachine {
         }
 
         for raw_event in sync_changes.to_device_events {
-            let raw_event =
-                Box::pin(self.receive_to_device_event(transaction, &mut changes, raw_event)).await;
-            events.push(raw_event);
+            if let Some(raw_event) =
+                Box::pin(self.receive_to_device_event(transaction, &mut changes, raw_event)).await
+            {
+                events.push(raw_event);
+            }
         }
 
         let changed_sessions = self
@@ -1601,6 +1866,7 @@ impl OlmMachine {
                     .collect(),
             },
             verification_state,
+            session_id: Some(session.session_id().to_owned()),
         })
     }
 
@@ -65,13 +65,13 @@ use crate::{
             room_key_withheld::{MegolmV1AesSha2WithheldContent, RoomKeyWithheldContent},
             ToDeviceEvent,
         },
-        requests::{AnyOutgoingRequest, ToDeviceRequest},
+        requests::{AnyOutgoingRequest, OutgoingRequest, ToDeviceRequest},
         DeviceKeys, SignedKey, SigningKeys,
     },
     utilities::json_convert,
     verification::tests::bob_id,
     Account, DecryptionSettings, DeviceData, EncryptionSettings, MegolmError, OlmError,
-    RoomEventDecryptionResult, TrustRequirement,
+    OneTimeKeysPolicy, RoomEventDecryptionResult, TrustRequirement,
 };
 
 mod decryption_verification_state;
@@ -190,6 +190,47 @@ async fn test_generate_one_time_keys() {
         .unwrap();
 }
 
+#[async_test]
+async fn test_one_time_keys_reconciliation() {
+    let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+    machine.set_one_time_keys_policy(OneTimeKeysPolicy {
+        reconciliation_interval: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    });
+
+    let keys_upload_requests = |requests: Vec<OutgoingRequest>| {
+        requests
+            .into_iter()
+            .filter_map(|r| match r.request.as_ref() {
+                AnyOutgoingRequest::KeysUpload(request) => Some(request.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // The initial upload of the keys.
+    let requests = keys_upload_requests(machine.outgoing_requests().await.unwrap());
+    assert_eq!(requests.len(), 1);
+    assert!(!requests[0].one_time_keys.is_empty());
+
+    let mut response = keys_upload_response();
+    response.one_time_key_counts.insert(OneTimeKeyAlgorithm::SignedCurve25519, uint!(50));
+    machine.receive_keys_upload_response(&response).await.unwrap();
+
+    // The number of one-time keys was just received, there's nothing to upload.
+    assert!(keys_upload_requests(machine.outgoing_requests().await.unwrap()).is_empty());
+
+    // Once the count is stale, an empty upload is sent to check it.
+    machine.set_one_time_keys_policy(OneTimeKeysPolicy {
+        reconciliation_interval: Some(Duration::ZERO),
+        ..Default::default()
+    });
+    let requests = keys_upload_requests(machine.outgoing_requests().await.unwrap());
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].device_keys.is_none());
+    assert!(requests[0].one_time_keys.is_empty());
+}
+
 #[async_test]
 async fn test_device_key_signing() {
     let machine = OlmMachine::new(user_id(), alice_device_id()).await;
@@ -915,6 +956,87 @@ async fn test_query_ratcheted_key() {
     }
 }
 
+#[async_test]
+async fn test_forward_room_keys_to_own_device() {
+    let (alice, alice_2) =
+        get_machine_pair_with_setup_sessions_test_helper(alice_id(), alice_id(), false).await;
+    let room_id = room_id!("!test:example.org");
+
+    alice.create_outbound_group_session_with_defaults_test_helper(room_id).await.unwrap();
+
+    // The room keys aren't forwarded to unverified devices.
+    let requests =
+        alice.forward_room_keys_to_own_device(room_id, alice_2.device_id()).await.unwrap();
+    assert!(requests.is_none());
+
+    alice
+        .get_device(alice_id(), alice_2.device_id(), None)
+        .await
+        .unwrap()
+        .unwrap()
+        .set_trust_state(crate::LocalTrust::Verified);
+
+    // Forward the room keys and let the other device receive them, returning
+    // the room key it accepted, if any.
+    let forward = || async {
+        let requests = alice
+            .forward_room_keys_to_own_device(room_id, alice_2.device_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(requests.len(), 1);
+
+        let event = ToDeviceEvent::new(
+            alice.user_id().to_owned(),
+            to_device_requests_to_content(requests.into_iter().map(Arc::new).collect()),
+        );
+
+        alice_2
+            .store()
+            .with_transaction(|mut tr| async {
+                let res = alice_2
+                    .decrypt_to_device_event(&mut tr, &event, &mut Changes::default())
+                    .await?;
+                Ok((tr, res))
+            })
+            .await
+            .unwrap()
+            .inbound_group_session
+    };
+
+    // The other device doesn't accept unrequested room keys by default, even from
+    // a verified device.
+    alice_2
+        .get_device(alice_id(), alice.device_id(), None)
+        .await
+        .unwrap()
+        .unwrap()
+        .set_trust_state(crate::LocalTrust::Verified);
+    assert!(!alice_2.are_unrequested_room_key_forwards_enabled().await.unwrap());
+    assert!(forward().await.is_none());
+
+    // Once enabled, the room keys from an unverified device are still rejected.
+    alice_2.set_unrequested_room_key_forwards_enabled(true).await.unwrap();
+    alice_2
+        .get_device(alice_id(), alice.device_id(), None)
+        .await
+        .unwrap()
+        .unwrap()
+        .set_trust_state(crate::LocalTrust::Unset);
+    assert!(forward().await.is_none());
+
+    // The room keys from a verified device are accepted even though they weren't
+    // requested.
+    alice_2
+        .get_device(alice_id(), alice.device_id(), None)
+        .await
+        .unwrap()
+        .unwrap()
+        .set_trust_state(crate::LocalTrust::Verified);
+    let group_session = forward().await.unwrap();
+    assert_eq!(group_session.room_id(), room_id);
+}
+
 #[async_test]
 async fn test_room_key_over_megolm() {
     let (alice, bob) =
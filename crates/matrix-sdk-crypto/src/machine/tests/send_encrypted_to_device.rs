@@ -14,7 +14,7 @@
 
 use assert_matches2::assert_matches;
 use matrix_sdk_test::async_test;
-use ruma::to_device::DeviceIdOrAllDevices;
+use ruma::{events::AnyToDeviceEvent, serde::Raw, to_device::DeviceIdOrAllDevices};
 use serde_json::{json, value::to_raw_value};
 
 use crate::{
@@ -121,3 +121,56 @@ async fn test_send_encrypted_to_device_no_session() {
 
     assert_matches!(encryption_result, Err(OlmError::MissingSession));
 }
+
+#[async_test]
+async fn test_replayed_encrypted_to_device_is_skipped() {
+    let (alice, bob) =
+        get_machine_pair_with_session(tests::alice_id(), tests::user_id(), false).await;
+
+    let device = alice.get_device(bob.user_id(), bob.device_id(), None).await.unwrap().unwrap();
+    let raw_encrypted = device
+        .encrypt_event_raw("m.new_device", &json!({ "device_id": "XYZABCDE" }))
+        .await
+        .expect("Should have encryted the content");
+
+    let request = ToDeviceRequest::new(
+        bob.user_id(),
+        DeviceIdOrAllDevices::DeviceId(tests::bob_device_id().to_owned()),
+        "m.room.encrypted",
+        raw_encrypted.cast(),
+    );
+    let event = ToDeviceEvent::new(
+        alice.user_id().to_owned(),
+        tests::to_device_requests_to_content(vec![request.into()]),
+    );
+    let event: Raw<AnyToDeviceEvent> = json_convert(&event).unwrap();
+
+    // The same event is received twice in the same sync response, only the
+    // first one is decrypted.
+    let sync_changes = EncryptionSyncChanges {
+        to_device_events: vec![event.clone(), event.clone()],
+        changed_devices: &Default::default(),
+        one_time_keys_counts: &Default::default(),
+        unused_fallback_keys: None,
+        next_batch_token: None,
+    };
+    let (decrypted, _) = bob.receive_sync_changes(sync_changes).await.unwrap();
+    assert_eq!(decrypted.len(), 1);
+
+    // The event is received again, as would happen if it was already processed
+    // by another process using the same store. It is skipped, and the session
+    // isn't considered as wedged.
+    let sync_changes = EncryptionSyncChanges {
+        to_device_events: vec![event],
+        changed_devices: &Default::default(),
+        one_time_keys_counts: &Default::default(),
+        unused_fallback_keys: None,
+        next_batch_token: None,
+    };
+    let (decrypted, _) = bob.receive_sync_changes(sync_changes).await.unwrap();
+    assert!(decrypted.is_empty());
+
+    let alice_device =
+        bob.get_device(alice.user_id(), alice.device_id(), None).await.unwrap().unwrap();
+    assert!(!bob.inner.session_manager.is_device_wedged(&alice_device.inner));
+}
@@ -129,7 +129,7 @@ pub(crate) struct DecryptionResult {
 /// A hash of a successfully decrypted Olm message.
 ///
 /// Can be used to check if a message has been replayed to us.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OlmMessageHash {
     /// The curve25519 key of the sender that sent us the Olm message.
     pub sender_key: String,
@@ -150,12 +150,55 @@ impl OlmMessageHash {
 
         Self { sender_key, hash: base64_encode(sha.as_slice()) }
     }
+
+    /// Compute the hash of an encrypted to-device event, without decrypting
+    /// it.
+    ///
+    /// Returns `None` if the event isn't encrypted with a known Olm
+    /// algorithm.
+    pub(crate) fn from_event(event: &EncryptedToDeviceEvent) -> Option<Self> {
+        match &event.content {
+            ToDeviceEncryptedEventContent::OlmV1Curve25519AesSha2(c) => {
+                Some(Self::new(c.sender_key, &c.ciphertext))
+            }
+            #[cfg(feature = "experimental-algorithms")]
+            ToDeviceEncryptedEventContent::OlmV2Curve25519AesSha2(c) => {
+                Some(Self::new(c.sender_key, &c.ciphertext))
+            }
+            ToDeviceEncryptedEventContent::Unknown(_) => None,
+        }
+    }
 }
 
 /// Account data that's static for the lifetime of a Client.
 ///
 /// This data never changes once it's set, so it can be freely passed and cloned
 /// everywhere.
+/// The policy deciding when new one-time keys are generated and uploaded, and
+/// when the number of one-time keys on the server is checked.
+///
+/// It can be changed with [`OlmMachine::set_one_time_keys_policy()`].
+///
+/// [`OlmMachine::set_one_time_keys_policy()`]: crate::OlmMachine::set_one_time_keys_policy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OneTimeKeysPolicy {
+    /// Only generate new one-time keys once the number of one-time keys on the
+    /// server is below this value, to upload them in batches.
+    ///
+    /// If this is `None`, the one-time keys are replenished as soon as one of
+    /// them is claimed.
+    pub low_water_mark: Option<u64>,
+
+    /// Check the number of one-time keys on the server with an empty
+    /// `/keys/upload` request, if it wasn't received for this long.
+    ///
+    /// The number of one-time keys is usually received in every sync
+    /// response, but it can get stale if syncs are missed, which can lead to
+    /// the exhaustion of the one-time keys. If this is `None`, the number is
+    /// never checked.
+    pub reconciliation_interval: Option<Duration>,
+}
+
 #[derive(Clone)]
 #[cfg_attr(not(tarpaulin_include), derive(Debug))]
 pub struct StaticAccountData {
@@ -520,10 +563,26 @@ impl Account {
         self.inner.max_number_of_one_time_keys()
     }
 
+    #[cfg(test)]
     pub(crate) fn update_key_counts(
         &mut self,
         one_time_key_counts: &BTreeMap<OneTimeKeyAlgorithm, UInt>,
         unused_fallback_keys: Option<&[OneTimeKeyAlgorithm]>,
+    ) {
+        self.update_key_counts_with_policy(
+            one_time_key_counts,
+            unused_fallback_keys,
+            &OneTimeKeysPolicy::default(),
+        );
+    }
+
+    /// Update the key counts, generating new one-time keys according to the
+    /// given policy.
+    pub(crate) fn update_key_counts_with_policy(
+        &mut self,
+        one_time_key_counts: &BTreeMap<OneTimeKeyAlgorithm, UInt>,
+        unused_fallback_keys: Option<&[OneTimeKeyAlgorithm]>,
+        policy: &OneTimeKeysPolicy,
     ) {
         if let Some(count) = one_time_key_counts.get(&OneTimeKeyAlgorithm::SignedCurve25519) {
             let count: u64 = (*count).into();
@@ -540,7 +599,7 @@ impl Account {
             }
 
             self.update_uploaded_key_count(count);
-            self.generate_one_time_keys_if_needed();
+            self.generate_one_time_keys_with_policy(policy);
         }
 
         // If the server supports fallback keys or if it did so in the past, shown by
@@ -559,8 +618,18 @@ impl Account {
     ///
     /// Generally `Some` means that keys should be uploaded, while `None` means
     /// that keys should not be uploaded.
-    #[instrument(skip_all)]
     pub fn generate_one_time_keys_if_needed(&mut self) -> Option<u64> {
+        self.generate_one_time_keys_with_policy(&OneTimeKeysPolicy::default())
+    }
+
+    /// Like [`Account::generate_one_time_keys_if_needed()`], but only
+    /// generates new keys once the uploaded key count is below the low-water
+    /// mark of the given policy.
+    #[instrument(skip_all)]
+    pub(crate) fn generate_one_time_keys_with_policy(
+        &mut self,
+        policy: &OneTimeKeysPolicy,
+    ) -> Option<u64> {
         // Only generate one-time keys if there aren't any, otherwise the caller
         // might have failed to upload them the last time this method was
         // called.
@@ -571,7 +640,7 @@ impl Account {
         let count = self.uploaded_key_count();
         let max_keys = self.max_one_time_keys();
 
-        if count >= max_keys as u64 {
+        if count >= max_keys as u64 || policy.low_water_mark.is_some_and(|mark| count >= mark) {
             return None;
         }
 
@@ -1213,6 +1282,16 @@ impl Account {
     pub fn receive_keys_upload_response(
         &mut self,
         response: &upload_keys::v3::Response,
+    ) -> OlmResult<()> {
+        self.receive_keys_upload_response_with_policy(response, &OneTimeKeysPolicy::default())
+    }
+
+    /// Handles a response to a /keys/upload request, generating new one-time
+    /// keys according to the given policy.
+    pub(crate) fn receive_keys_upload_response_with_policy(
+        &mut self,
+        response: &upload_keys::v3::Response,
+        policy: &OneTimeKeysPolicy,
     ) -> OlmResult<()> {
         if !self.shared() {
             debug!("Marking account as shared");
@@ -1223,7 +1302,7 @@ impl Account {
         // First mark the current keys as published, as updating the key counts might
         // generate some new keys if we're still below the limit.
         self.mark_keys_as_published();
-        self.update_key_counts(&response.one_time_key_counts, None);
+        self.update_key_counts_with_policy(&response.one_time_key_counts, None, policy);
 
         Ok(())
     }
@@ -1552,6 +1631,33 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_one_time_keys_low_water_mark() {
+        let mut account = Account::with_device_id(user_id(), device_id());
+        let policy = OneTimeKeysPolicy { low_water_mark: Some(20), ..Default::default() };
+        account.mark_keys_as_published();
+
+        // Above the low-water mark, no key is generated.
+        account.update_uploaded_key_count(30);
+        assert_eq!(account.generate_one_time_keys_with_policy(&policy), None);
+        assert!(account.one_time_keys().is_empty());
+
+        // Without a policy, the keys are topped up.
+        assert_eq!(
+            account.generate_one_time_keys_if_needed(),
+            Some(account.max_one_time_keys() as u64 - 30)
+        );
+        account.mark_keys_as_published();
+
+        // Below the low-water mark, the keys are topped up to the maximum.
+        account.update_uploaded_key_count(10);
+        assert_eq!(
+            account.generate_one_time_keys_with_policy(&policy),
+            Some(account.max_one_time_keys() as u64 - 10)
+        );
+        assert!(!account.one_time_keys().is_empty());
+    }
+
     #[test]
     fn test_fallback_key_creation() -> Result<()> {
         let mut account = Account::with_device_id(user_id(), device_id());
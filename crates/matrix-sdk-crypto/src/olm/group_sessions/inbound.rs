@@ -43,7 +43,7 @@ use super::{
 use crate::{
     error::{EventError, MegolmResult},
     types::{
-        deserialize_curve_key,
+        deserialize_curve_key, deserialize_curve_key_vec,
         events::{
             forwarded_room_key::{
                 ForwardedMegolmV1AesSha2Content, ForwardedMegolmV2AesSha2Content,
@@ -52,7 +52,7 @@ use crate::{
             olm_v1::DecryptedForwardedRoomKeyEvent,
             room::encrypted::{EncryptedEvent, RoomEventEncryptionScheme},
         },
-        serialize_curve_key, EventEncryptionAlgorithm, SigningKeys,
+        serialize_curve_key, serialize_curve_key_vec, EventEncryptionAlgorithm, SigningKeys,
     },
 };
 
@@ -141,6 +141,12 @@ pub struct InboundGroupSession {
     /// correct.
     imported: bool,
 
+    /// The Curve25519 keys of the devices that forwarded this session to us,
+    /// starting with the device that received it from the creator.
+    ///
+    /// Empty if the session was received directly from its creator.
+    forwarding_key_chain: Arc<[Curve25519PublicKey]>,
+
     /// The messaging algorithm of this [`InboundGroupSession`] as defined by
     /// the [spec]. Will be one of the `m.megolm.*` algorithms.
     ///
@@ -205,6 +211,7 @@ impl InboundGroupSession {
             sender_data,
             room_id: room_id.into(),
             imported: false,
+            forwarding_key_chain: Arc::new([]),
             algorithm: encryption_algorithm.into(),
             backed_up: AtomicBool::new(false).into(),
         })
@@ -237,6 +244,7 @@ impl InboundGroupSession {
             sender_data: self.sender_data.clone(),
             room_id: self.room_id().to_owned(),
             imported: self.imported,
+            forwarding_curve25519_key_chain: self.forwarding_key_chain.to_vec(),
             backed_up: self.backed_up(),
             history_visibility: self.history_visibility.as_ref().clone(),
             algorithm: (*self.algorithm).to_owned(),
@@ -289,7 +297,7 @@ impl InboundGroupSession {
             room_id: self.room_id().to_owned(),
             sender_key: self.creator_info.curve25519_key,
             session_id: self.session_id().to_owned(),
-            forwarding_curve25519_key_chain: vec![],
+            forwarding_curve25519_key_chain: self.forwarding_key_chain.to_vec(),
             sender_claimed_keys: (*self.creator_info.signing_keys).clone(),
             session_key,
         }
@@ -325,6 +333,7 @@ impl InboundGroupSession {
             backed_up: AtomicBool::from(pickle.backed_up).into(),
             algorithm: pickle.algorithm.into(),
             imported: pickle.imported,
+            forwarding_key_chain: pickle.forwarding_curve25519_key_chain.into(),
         })
     }
 
@@ -355,6 +364,23 @@ impl InboundGroupSession {
         self.imported
     }
 
+    /// The Curve25519 keys of the devices that forwarded this session to us,
+    /// starting with the device that received it from the creator.
+    ///
+    /// Empty if the session was received directly from its creator, or if it
+    /// was imported without the chain being known.
+    pub fn forwarding_curve25519_key_chain(&self) -> &[Curve25519PublicKey] {
+        &self.forwarding_key_chain
+    }
+
+    /// Append the Curve25519 key of the device that forwarded this session to
+    /// us to its forwarding chain.
+    pub(crate) fn with_forwarder(mut self, forwarder_key: Curve25519PublicKey) -> Self {
+        self.forwarding_key_chain =
+            self.forwarding_key_chain.iter().copied().chain([forwarder_key]).collect();
+        self
+    }
+
     /// Check if the [`InboundGroupSession`] is better than the given other
     /// [`InboundGroupSession`]
     pub async fn compare(&self, other: &InboundGroupSession) -> SessionOrdering {
@@ -511,6 +537,14 @@ pub struct PickledInboundGroupSession {
     /// Flag remembering if the session was directly sent to us by the sender
     /// or if it was imported.
     pub imported: bool,
+    /// The Curve25519 keys of the devices that forwarded the session to us.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_curve_key_vec",
+        serialize_with = "serialize_curve_key_vec",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub forwarding_curve25519_key_chain: Vec<Curve25519PublicKey>,
     /// Flag remembering if the session has been backed up.
     #[serde(default)]
     pub backed_up: bool,
@@ -547,6 +581,7 @@ impl TryFrom<&ExportedRoomKey> for InboundGroupSession {
             first_known_index,
             room_id: key.room_id.to_owned(),
             imported: true,
+            forwarding_key_chain: key.forwarding_curve25519_key_chain.as_slice().into(),
             algorithm: key.algorithm.to_owned().into(),
             backed_up: AtomicBool::from(false).into(),
         })
@@ -577,6 +612,7 @@ impl From<&ForwardedMegolmV1AesSha2Content> for InboundGroupSession {
             first_known_index,
             room_id: value.room_id.to_owned(),
             imported: true,
+            forwarding_key_chain: value.forwarding_curve25519_key_chain.as_slice().into(),
             algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2.into(),
             backed_up: AtomicBool::from(false).into(),
         }
@@ -603,6 +639,7 @@ impl From<&ForwardedMegolmV2AesSha2Content> for InboundGroupSession {
             first_known_index,
             room_id: value.room_id.to_owned(),
             imported: true,
+            forwarding_key_chain: Arc::new([]),
             algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2.into(),
             backed_up: AtomicBool::from(false).into(),
         }
@@ -858,6 +895,32 @@ mod tests {
         assert_eq!(inbound.compare(&copy).await, SessionOrdering::Unconnected);
     }
 
+    #[async_test]
+    async fn test_forwarding_chain_is_kept() {
+        let alice = Account::with_device_id(alice_id(), alice_device_id());
+        let room_id = room_id!("!test:localhost");
+        let forwarder_key =
+            Curve25519PublicKey::from_base64("XbmrPa1kMwmdtNYng1B2gsfoo8UtF+NklzsTZiaVKyY")
+                .unwrap();
+
+        let (_, inbound) = alice.create_group_session_pair_with_defaults(room_id).await;
+        assert!(inbound.forwarding_curve25519_key_chain().is_empty());
+
+        let mut export = inbound.export().await;
+        export.forwarding_curve25519_key_chain = vec![alice.identity_keys().curve25519];
+
+        // The chain of an import is kept, and the forwarder is appended to it.
+        let forwarded =
+            InboundGroupSession::from_export(&export).unwrap().with_forwarder(forwarder_key);
+        let expected_chain = [alice.identity_keys().curve25519, forwarder_key];
+        assert_eq!(forwarded.forwarding_curve25519_key_chain(), expected_chain);
+
+        // It survives a pickling round-trip, and is re-exported.
+        let unpickled = InboundGroupSession::from_pickle(forwarded.pickle().await).unwrap();
+        assert_eq!(unpickled.forwarding_curve25519_key_chain(), expected_chain);
+        assert_eq!(unpickled.export().await.forwarding_curve25519_key_chain, expected_chain);
+    }
+
     #[async_test]
     async fn test_session_comparison_sender_data() {
         let alice = Account::with_device_id(alice_id(), alice_device_id());
@@ -205,6 +205,27 @@ pub struct SharedWith {
     pub olm_wedging_index: SequenceNumber,
 }
 
+/// Who an outbound group session was shared with, as returned by
+/// [`OlmMachine::session_share_info()`].
+///
+/// This is meant to help diagnosing why other devices can't decrypt the
+/// messages encrypted with this session.
+///
+/// [`OlmMachine::session_share_info()`]: crate::OlmMachine::session_share_info
+#[derive(Clone, Debug, Default)]
+pub struct SessionShareInfo {
+    /// The devices that received the room key, with the index of the first
+    /// message they can decrypt.
+    pub shared_with: BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, u32>>,
+
+    /// The devices that were told that the room key was withheld from them,
+    /// with the reason.
+    pub withheld_from: BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, WithheldCode>>,
+
+    /// The devices whose room key or withheld notice hasn't been sent yet.
+    pub pending: BTreeMap<OwnedUserId, BTreeSet<OwnedDeviceId>>,
+}
+
 impl OutboundGroupSession {
     pub(super) fn session_config(
         algorithm: &EventEncryptionAlgorithm,
@@ -643,6 +664,38 @@ impl OutboundGroupSession {
             .insert(device_id.to_owned(), share_info);
     }
 
+    /// Get who this session was shared with, or withheld from.
+    pub fn share_info(&self) -> SessionShareInfo {
+        let mut info = SessionShareInfo::default();
+
+        for (user_id, devices) in self.shared_with_set.read().iter() {
+            for (device_id, share_info) in devices {
+                match share_info {
+                    ShareInfo::Shared(shared) => {
+                        info.shared_with
+                            .entry(user_id.clone())
+                            .or_default()
+                            .insert(device_id.clone(), shared.message_index);
+                    }
+                    ShareInfo::Withheld(code) => {
+                        info.withheld_from
+                            .entry(user_id.clone())
+                            .or_default()
+                            .insert(device_id.clone(), code.clone());
+                    }
+                }
+            }
+        }
+
+        for (_, share_infos) in self.to_share_with_set.read().values() {
+            for (user_id, devices) in share_infos {
+                info.pending.entry(user_id.clone()).or_default().extend(devices.keys().cloned());
+            }
+        }
+
+        info
+    }
+
     /// Get the list of requests that need to be sent out for this session to be
     /// marked as shared.
     pub(crate) fn pending_requests(&self) -> Vec<Arc<ToDeviceRequest>> {
@@ -23,7 +23,7 @@ mod session;
 mod signing;
 mod utility;
 
-pub use account::{Account, OlmMessageHash, PickledAccount, StaticAccountData};
+pub use account::{Account, OlmMessageHash, OneTimeKeysPolicy, PickledAccount, StaticAccountData};
 pub(crate) use account::{OlmDecryptionInfo, SessionType};
 pub(crate) use group_sessions::{
     sender_data_finder::{self, SenderDataFinder},
@@ -32,11 +32,12 @@ pub(crate) use group_sessions::{
 pub use group_sessions::{
     BackedUpRoomKey, EncryptionSettings, ExportedRoomKey, InboundGroupSession, KnownSenderData,
     OutboundGroupSession, PickledInboundGroupSession, PickledOutboundGroupSession, SenderData,
-    SenderDataType, SessionCreationError, SessionExportError, SessionKey, ShareInfo,
+    SenderDataType, SessionCreationError, SessionExportError, SessionKey, SessionShareInfo,
+    ShareInfo,
 };
 pub use session::{PickledSession, Session};
 pub use signing::{CrossSigningStatus, PickledCrossSigningIdentity, PrivateCrossSigningIdentity};
-pub(crate) use utility::{SignedJsonObject, VerifyJson};
+pub(crate) use utility::{to_signable_json, SignJson, SignedJsonObject, VerifyJson};
 pub use vodozemac::{olm::IdentityKeys, Curve25519PublicKey};
 
 #[cfg(test)]
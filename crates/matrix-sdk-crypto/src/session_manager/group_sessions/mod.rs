@@ -40,7 +40,7 @@ use crate::{
     identities::device::MaybeEncryptedRoomKey,
     olm::{
         InboundGroupSession, OutboundGroupSession, SenderData, SenderDataFinder, Session,
-        ShareInfo, ShareState,
+        SessionShareInfo, ShareInfo, ShareState,
     },
     store::{Changes, CryptoStoreWrapper, Result as StoreResult, Store},
     types::{events::room::encrypted::RoomEncryptedEventContent, requests::ToDeviceRequest},
@@ -184,6 +184,17 @@ impl GroupSessionManager {
         self.store.save_changes(changes).await
     }
 
+    /// Get who the outbound group session with the given ID was shared with,
+    /// if it is the current outbound group session of the room.
+    pub async fn session_share_info(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Option<SessionShareInfo> {
+        let session = self.sessions.get_or_load(room_id).await?;
+        (session.session_id() == session_id).then(|| session.share_info())
+    }
+
     #[cfg(test)]
     pub fn get_outbound_group_session(&self, room_id: &RoomId) -> Option<OutboundGroupSession> {
         self.sessions.get(room_id)
@@ -974,6 +985,50 @@ mod tests {
         assert_eq!(withheld_count, 2);
     }
 
+    #[async_test]
+    async fn test_session_share_info() {
+        let machine = machine().await;
+        let room_id = room_id!("!test:localhost");
+        let keys_claim = keys_claim_response();
+
+        let users = keys_claim.one_time_keys.keys().map(Deref::deref);
+        let requests =
+            machine.share_room_key(room_id, users, EncryptionSettings::default()).await.unwrap();
+
+        let session_id = machine
+            .inner
+            .group_session_manager
+            .get_outbound_group_session(room_id)
+            .unwrap()
+            .session_id()
+            .to_owned();
+
+        // Nothing was sent yet.
+        let info = machine.session_share_info(room_id, &session_id).await.unwrap();
+        assert!(info.shared_with.is_empty());
+        assert!(info.withheld_from.is_empty());
+        assert_eq!(info.pending.values().map(BTreeSet::len).sum::<usize>(), 150);
+
+        let response = ToDeviceResponse::new();
+        for request in requests {
+            machine.mark_request_as_sent(&request.txn_id, &response).await.unwrap();
+        }
+
+        let info = machine.session_share_info(room_id, &session_id).await.unwrap();
+        assert_eq!(info.shared_with.values().map(BTreeMap::len).sum::<usize>(), 148);
+        assert!(info.pending.is_empty());
+
+        let withheld: Vec<_> = info.withheld_from.values().flat_map(BTreeMap::values).collect();
+        assert_eq!(withheld, [&WithheldCode::NoOlm, &WithheldCode::NoOlm]);
+
+        // Only the current session of the room is known.
+        assert!(machine.session_share_info(room_id, "unknown").await.is_none());
+        assert!(machine
+            .session_share_info(room_id!("!other:localhost"), &session_id)
+            .await
+            .is_none());
+    }
+
     fn count_withheld_from(requests: &[Arc<ToDeviceRequest>], code: WithheldCode) -> usize {
         requests
             .iter()
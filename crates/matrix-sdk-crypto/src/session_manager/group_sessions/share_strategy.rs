@@ -154,6 +154,12 @@ pub(crate) async fn collect_session_recipients(
             only_allow_trusted_devices,
             error_on_verified_user_problem,
         } => {
+            // The room may override whether untrusted devices get the room key.
+            let only_allow_trusted_devices = store
+                .get_room_only_allow_trusted_devices(outbound.room_id())
+                .await?
+                .unwrap_or(only_allow_trusted_devices);
+
             let mut unsigned_devices_of_verified_users: BTreeMap<OwnedUserId, Vec<OwnedDeviceId>> =
                 Default::default();
 
@@ -707,6 +713,76 @@ mod tests {
         assert_eq!(code, &WithheldCode::Unverified);
     }
 
+    /// Test that the per-room override of `only_allow_trusted_devices` takes
+    /// precedence over the one of the encryption settings.
+    #[async_test]
+    async fn test_share_with_per_device_strategy_room_override() {
+        let machine = set_up_test_machine().await;
+
+        let encryption_settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::DeviceBasedStrategy {
+                only_allow_trusted_devices: false,
+                error_on_verified_user_problem: false,
+            },
+            ..Default::default()
+        };
+
+        let group_session = create_test_outbound_group_session(&machine, &encryption_settings);
+        let users = [KeyDistributionTestData::dan_id(), KeyDistributionTestData::dave_id()];
+
+        // The room only allows trusted devices, so only dan's signed device gets the
+        // key.
+        machine
+            .store()
+            .set_room_only_allow_trusted_devices(group_session.room_id(), Some(true))
+            .await
+            .unwrap();
+
+        let share_result = collect_session_recipients(
+            machine.store(),
+            users.into_iter(),
+            &encryption_settings,
+            &group_session,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(share_result.devices.get(KeyDistributionTestData::dan_id()).unwrap().len(), 1);
+        assert!(share_result.devices.get(KeyDistributionTestData::dave_id()).unwrap().is_empty());
+        assert!(share_result
+            .withheld_devices
+            .iter()
+            .any(|(d, code)| d.device_id() == KeyDistributionTestData::dave_device_id()
+                && *code == WithheldCode::Unverified));
+
+        // Once the override is removed, the encryption settings apply again.
+        machine
+            .store()
+            .set_room_only_allow_trusted_devices(group_session.room_id(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            machine
+                .store()
+                .get_room_only_allow_trusted_devices(group_session.room_id())
+                .await
+                .unwrap(),
+            None
+        );
+
+        let share_result = collect_session_recipients(
+            machine.store(),
+            users.into_iter(),
+            &encryption_settings,
+            &group_session,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(share_result.devices.get(KeyDistributionTestData::dan_id()).unwrap().len(), 2);
+        assert_eq!(share_result.devices.get(KeyDistributionTestData::dave_id()).unwrap().len(), 1);
+    }
+
     /// Test that [`collect_session_recipients`] returns an error if there are
     /// unsigned devices belonging to verified users, when
     /// `error_on_verified_user_problem` is set.
@@ -14,10 +14,13 @@
 
 use std::{
     collections::{BTreeMap, BTreeSet},
+    future,
     sync::Arc,
     time::Duration,
 };
 
+use futures_core::Stream;
+use futures_util::StreamExt;
 use matrix_sdk_common::{failures_cache::FailuresCache, locks::RwLock as StdRwLock};
 use ruma::{
     api::client::keys::claim_keys::v3::{
@@ -28,6 +31,8 @@ use ruma::{
     DeviceId, OneTimeKeyAlgorithm, OwnedDeviceId, OwnedOneTimeKeyId, OwnedServerName,
     OwnedTransactionId, OwnedUserId, SecondsSinceUnixEpoch, ServerName, TransactionId, UserId,
 };
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
 use tracing::{debug, error, info, instrument, warn};
 use vodozemac::Curve25519PublicKey;
 
@@ -73,6 +78,11 @@ pub(crate) struct SessionManager {
     failures: FailuresCache<OwnedServerName>,
 
     failed_devices: Arc<StdRwLock<BTreeMap<OwnedUserId, FailuresCache<OwnedDeviceId>>>>,
+
+    /// Sends the devices for which a one-time key couldn't be claimed, because
+    /// they ran out of one-time keys.
+    one_time_keys_exhausted_sender:
+        broadcast::Sender<BTreeMap<OwnedUserId, BTreeSet<OwnedDeviceId>>>,
 }
 
 impl SessionManager {
@@ -93,9 +103,26 @@ impl SessionManager {
             outgoing_to_device_requests: Default::default(),
             failures: Default::default(),
             failed_devices: Default::default(),
+            one_time_keys_exhausted_sender: broadcast::Sender::new(10),
         }
     }
 
+    /// Receive the devices for which a one-time key couldn't be claimed,
+    /// because they ran out of one-time keys.
+    pub fn one_time_keys_exhausted_stream(
+        &self,
+    ) -> impl Stream<Item = BTreeMap<OwnedUserId, BTreeSet<OwnedDeviceId>>> {
+        BroadcastStream::new(self.one_time_keys_exhausted_sender.subscribe()).filter_map(|result| {
+            future::ready(match result {
+                Ok(devices) => Some(devices),
+                Err(BroadcastStreamRecvError::Lagged(lag)) => {
+                    warn!("one_time_keys_exhausted_stream missed {lag} updates");
+                    None
+                }
+            })
+        })
+    }
+
     /// Mark the outgoing request as sent.
     pub fn mark_outgoing_request_as_sent(&self, id: &TransactionId) {
         self.outgoing_to_device_requests.write().remove(id);
@@ -406,11 +433,23 @@ impl SessionManager {
                     "Tried to create new Olm sessions, but the signed one-time key was missing for some devices",
                 );
 
-                let mut failed_devices_lock = self.failed_devices.write();
+                {
+                    let mut failed_devices_lock = self.failed_devices.write();
 
-                for (user_id, device_set) in missing_devices_by_user {
-                    failed_devices_lock.entry(user_id.clone()).or_default().extend(device_set);
+                    for (user_id, device_set) in &missing_devices_by_user {
+                        failed_devices_lock
+                            .entry((*user_id).clone())
+                            .or_default()
+                            .extend(device_set.iter().cloned());
+                    }
                 }
+
+                let _ = self.one_time_keys_exhausted_sender.send(
+                    missing_devices_by_user
+                        .into_iter()
+                        .map(|(user_id, device_set)| (user_id.clone(), device_set))
+                        .collect(),
+                );
             }
         };
     }
@@ -586,7 +625,15 @@ impl SessionManager {
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::BTreeMap, iter, ops::Deref, sync::Arc, time::Duration};
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        iter,
+        ops::Deref,
+        sync::Arc,
+        time::Duration,
+    };
+
+    use futures_util::{pin_mut, FutureExt, StreamExt};
 
     use matrix_sdk_common::locks::RwLock as StdRwLock;
     use matrix_sdk_test::{async_test, ruma_response_from_json};
@@ -955,6 +1002,37 @@ mod tests {
         })).await;
     }
 
+    #[async_test]
+    async fn test_one_time_keys_exhausted_stream() {
+        let alice = user_id!("@alice:example.org");
+        let alice_account = Account::with_device_id(alice, "DEVICEID".into());
+        let alice_device = DeviceData::from_account(&alice_account);
+
+        let (manager, _identity_manager) = session_manager_test_helper().await;
+        manager.store.save_device_data(&[alice_device]).await.unwrap();
+
+        let stream = manager.one_time_keys_exhausted_stream();
+        pin_mut!(stream);
+
+        let (txn_id, _) = manager.get_missing_sessions(iter::once(alice)).await.unwrap().unwrap();
+
+        // Alice's device is missing from the response, she ran out of one-time keys.
+        let response = ruma_response_from_json(&json!({
+            "one_time_keys": {},
+            "failures": {},
+        }));
+        manager.receive_keys_claim_response(&txn_id, &response).await.unwrap();
+
+        let exhausted = stream.next().now_or_never().flatten().unwrap();
+        assert_eq!(
+            exhausted,
+            BTreeMap::from([(
+                alice.to_owned(),
+                BTreeSet::from([alice_account.device_id().to_owned()])
+            )])
+        );
+    }
+
     /// Helper for failed_devices_handling.
     ///
     /// Takes an invalid /keys/claim response for Alice's device DEVICEID and
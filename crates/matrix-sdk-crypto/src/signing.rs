@@ -0,0 +1,385 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers to canonicalize, sign and verify Matrix JSON objects.
+//!
+//! These are the primitives the [`OlmMachine`] uses internally, exposed so
+//! that signed payloads, like the ones of a `/keys/query` response, can be
+//! verified independently, e.g. to validate test fixtures or to audit a
+//! server's responses.
+//!
+//! See the [signing JSON] and [event hashes] sections of the spec.
+//!
+//! [`OlmMachine`]: crate::OlmMachine
+//! [signing JSON]: https://spec.matrix.org/unstable/appendices/#signing-json
+//! [event hashes]: https://spec.matrix.org/unstable/server-server-api/#calculating-the-reference-hash-for-an-event
+
+use ruma::{
+    api::client::keys::get_keys::v3::Response as KeysQueryResponse,
+    canonical_json::{redact_in_place, RedactionError},
+    serde::{base64::UrlSafe, Base64},
+    CanonicalJsonObject, CanonicalJsonValue, DeviceKeyAlgorithm, DeviceKeyId, OwnedDeviceId,
+    OwnedUserId, RoomVersionId, UserId,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use vodozemac::{base64_encode, Ed25519PublicKey, Ed25519SecretKey, Ed25519Signature};
+
+use crate::{
+    olm::{to_signable_json, SignJson, VerifyJson},
+    types::{DeviceKeys, MasterPubkey, SelfSigningPubkey, Signatures, UserSigningPubkey},
+    SignatureError,
+};
+
+/// Get the canonical form of a JSON object, as it is signed.
+///
+/// The `signatures` and `unsigned` fields are removed from the object before
+/// it is canonicalized.
+pub fn canonical_json(value: Value) -> Result<String, SignatureError> {
+    to_signable_json(value)
+}
+
+/// Sign a JSON object with the given Ed25519 key.
+///
+/// The signature is computed over the [canonical form](canonical_json) of the
+/// object; it's up to the caller to add it to the `signatures` field.
+pub fn sign_json(key: &Ed25519SecretKey, value: Value) -> Result<Ed25519Signature, SignatureError> {
+    key.sign_json(value)
+}
+
+/// Verify the signature made by the given Ed25519 key on a JSON object.
+///
+/// # Arguments
+///
+/// * `public_key` - The public key that supposedly signed the object.
+///
+/// * `user_id` - The user that claims to have signed the object.
+///
+/// * `key_id` - The ID under which the signature is stored in the
+///   `signatures` field of the object.
+///
+/// * `value` - The signed JSON object.
+pub fn verify_json(
+    public_key: Ed25519PublicKey,
+    user_id: &UserId,
+    key_id: &DeviceKeyId,
+    value: Value,
+) -> Result<(), SignatureError> {
+    let signatures: Signatures = serde_json::from_value(
+        value.get("signatures").cloned().ok_or(SignatureError::NoSignatureFound)?,
+    )?;
+    let canonical_json = to_signable_json(value)?;
+
+    public_key.verify_canonicalized_json(user_id, key_id, &signatures, &canonical_json)
+}
+
+/// The object carrying a signature that failed to verify in a `/keys/query`
+/// response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignedObject {
+    /// The master key of the user.
+    MasterKey,
+
+    /// The self-signing key of the user, which must be signed by their master
+    /// key.
+    SelfSigningKey,
+
+    /// The user-signing key of the user, which must be signed by their master
+    /// key.
+    UserSigningKey,
+
+    /// The keys of a device, which must be signed by the device itself, and
+    /// possibly by the self-signing key of the user.
+    Device(OwnedDeviceId),
+}
+
+/// A signature that failed to verify in a `/keys/query` response.
+#[derive(Debug)]
+pub struct SignatureFailure {
+    /// The user owning the object.
+    pub user_id: OwnedUserId,
+
+    /// The object carrying the signature.
+    pub object: SignedObject,
+
+    /// Why the verification failed.
+    pub error: SignatureError,
+}
+
+/// Verify all the signatures of a `/keys/query` response.
+///
+/// This checks that:
+///
+/// * the self-signing and user-signing keys of each user are signed by their
+///   master key,
+/// * the keys of each device are signed by the device itself,
+/// * the keys of each device carrying a signature from the self-signing key of
+///   its user are correctly signed by it.
+///
+/// Returns the list of signatures that failed to verify, which is empty if the
+/// response is valid.
+pub fn verify_keys_query_response(response: &KeysQueryResponse) -> Vec<SignatureFailure> {
+    let mut failures = Vec::new();
+    let mut fail = |user_id: &UserId, object, error| {
+        failures.push(SignatureFailure { user_id: user_id.to_owned(), object, error })
+    };
+
+    let mut self_signing_keys = Vec::new();
+
+    for (user_id, raw_master_key) in &response.master_keys {
+        let master_key = match raw_master_key.deserialize_as::<MasterPubkey>() {
+            Ok(master_key) => master_key,
+            Err(error) => {
+                fail(user_id, SignedObject::MasterKey, error.into());
+                continue;
+            }
+        };
+
+        if let Some(raw) = response.self_signing_keys.get(user_id) {
+            match raw.deserialize_as::<SelfSigningPubkey>() {
+                Ok(self_signing) => match master_key.verify_subkey(&self_signing) {
+                    Ok(()) => self_signing_keys.push(self_signing),
+                    Err(error) => fail(user_id, SignedObject::SelfSigningKey, error),
+                },
+                Err(error) => fail(user_id, SignedObject::SelfSigningKey, error.into()),
+            }
+        }
+
+        if let Some(raw) = response.user_signing_keys.get(user_id) {
+            let result = raw
+                .deserialize_as::<UserSigningPubkey>()
+                .map_err(SignatureError::from)
+                .and_then(|user_signing| master_key.verify_subkey(&user_signing));
+
+            if let Err(error) = result {
+                fail(user_id, SignedObject::UserSigningKey, error);
+            }
+        }
+    }
+
+    for (user_id, devices) in &response.device_keys {
+        let self_signing = self_signing_keys.iter().find(|key| key.user_id() == user_id);
+
+        for (device_id, raw_device_keys) in devices {
+            let result = raw_device_keys
+                .deserialize_as::<DeviceKeys>()
+                .map_err(SignatureError::from)
+                .and_then(|device_keys| verify_device_keys(user_id, &device_keys, self_signing));
+
+            if let Err(error) = result {
+                fail(user_id, SignedObject::Device(device_id.clone()), error);
+            }
+        }
+    }
+
+    failures
+}
+
+fn verify_device_keys(
+    user_id: &UserId,
+    device_keys: &DeviceKeys,
+    self_signing: Option<&SelfSigningPubkey>,
+) -> Result<(), SignatureError> {
+    if device_keys.user_id != user_id {
+        return Err(SignatureError::UserIdMismatch);
+    }
+
+    let key = device_keys.ed25519_key().ok_or(SignatureError::MissingSigningKey)?;
+    let key_id = DeviceKeyId::from_parts(DeviceKeyAlgorithm::Ed25519, &device_keys.device_id);
+    key.verify_json(user_id, &key_id, device_keys)?;
+
+    if let Some(self_signing) = self_signing {
+        let is_cross_signed = device_keys.signatures.get(user_id).is_some_and(|signatures| {
+            self_signing.keys().iter().any(|(k, _)| signatures.contains_key(k))
+        });
+
+        if is_cross_signed {
+            self_signing.verify_device_keys(device_keys)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// An error that happened while computing or verifying the hashes of an event.
+#[derive(Debug, Error)]
+pub enum EventHashError {
+    /// The event couldn't be redacted to compute its reference hash.
+    #[error(transparent)]
+    Redaction(#[from] RedactionError),
+
+    /// The event doesn't contain a SHA-256 content hash.
+    #[error("the event doesn't contain a SHA-256 content hash")]
+    MissingContentHash,
+
+    /// The content hash of the event doesn't match its content.
+    #[error("the content hash of the event doesn't match its content")]
+    ContentHashMismatch,
+}
+
+/// Compute the SHA-256 content hash of an event, encoded as unpadded
+/// base64.
+///
+/// The hash is computed over the canonical form of the event, without its
+/// `unsigned`, `signatures` and `hashes` fields.
+pub fn content_hash(event: &CanonicalJsonObject) -> String {
+    let mut event = event.clone();
+    event.remove("unsigned");
+    event.remove("signatures");
+    event.remove("hashes");
+
+    base64_encode(sha256(event))
+}
+
+/// Verify that the content hash of an event, stored in its `hashes` field,
+/// matches the event.
+pub fn verify_content_hash(event: &CanonicalJsonObject) -> Result<(), EventHashError> {
+    let Some(CanonicalJsonValue::Object(hashes)) = event.get("hashes") else {
+        return Err(EventHashError::MissingContentHash);
+    };
+    let Some(CanonicalJsonValue::String(expected)) = hashes.get("sha256") else {
+        return Err(EventHashError::MissingContentHash);
+    };
+
+    if *expected == content_hash(event) {
+        Ok(())
+    } else {
+        Err(EventHashError::ContentHashMismatch)
+    }
+}
+
+/// Compute the reference hash of an event, in the given room version.
+///
+/// The hash is computed over the canonical form of the redacted event, without
+/// its `unsigned` and `signatures` fields. It's encoded as URL-safe unpadded
+/// base64, except in room versions 1 to 3 where the standard alphabet is used.
+///
+/// In room versions 3 and later, the ID of an event is its reference hash,
+/// prefixed with `$`.
+pub fn reference_hash(
+    event: &CanonicalJsonObject,
+    room_version: &RoomVersionId,
+) -> Result<String, EventHashError> {
+    let mut event = event.clone();
+    redact_in_place(&mut event, room_version, None)?;
+    event.remove("unsigned");
+    event.remove("signatures");
+
+    let hash = sha256(event);
+
+    Ok(match room_version {
+        RoomVersionId::V1 | RoomVersionId::V2 | RoomVersionId::V3 => base64_encode(hash),
+        _ => Base64::<UrlSafe, _>::new(hash).encode(),
+    })
+}
+
+fn sha256(object: CanonicalJsonObject) -> Vec<u8> {
+    Sha256::digest(CanonicalJsonValue::Object(object).to_string()).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use matrix_sdk_test::{
+        ruma_response_from_json,
+        test_json::keys_query_sets::{IdentityChangeDataSet, KeyDistributionTestData},
+    };
+    use ruma::{device_id, user_id, CanonicalJsonObject, RoomVersionId};
+    use serde_json::json;
+    use vodozemac::Ed25519SecretKey;
+
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify_json() {
+        let key = Ed25519SecretKey::new();
+        let user_id = user_id!("@alice:example.org");
+        let key_id = DeviceKeyId::from_parts(DeviceKeyAlgorithm::Ed25519, device_id!("DEVICE"));
+
+        let mut value = json!({ "b": 1, "a": "foo", "unsigned": { "age": 3 } });
+        assert_eq!(canonical_json(value.clone()).unwrap(), r#"{"a":"foo","b":1}"#);
+
+        let signature = sign_json(&key, value.clone()).unwrap();
+        value["signatures"] = json!({ user_id: { key_id.to_string(): signature.to_base64() } });
+
+        verify_json(key.public_key(), user_id, &key_id, value.clone()).unwrap();
+
+        value["b"] = json!(2);
+        assert_matches!(
+            verify_json(key.public_key(), user_id, &key_id, value),
+            Err(SignatureError::VerificationError(_))
+        );
+    }
+
+    #[test]
+    fn test_verify_keys_query_fixtures() {
+        for response in [
+            KeyDistributionTestData::me_keys_query_response(),
+            KeyDistributionTestData::dan_keys_query_response(),
+            IdentityChangeDataSet::key_query_with_identity_a(),
+            IdentityChangeDataSet::key_query_with_identity_b(),
+        ] {
+            let failures = verify_keys_query_response(&response);
+            assert!(failures.is_empty(), "Unexpected signature failures: {failures:?}");
+        }
+    }
+
+    #[test]
+    fn test_verify_keys_query_with_foreign_self_signing_key() {
+        let user_id = IdentityChangeDataSet::user_id();
+        let response = ruma_response_from_json(&json!({
+            "device_keys": {},
+            "master_keys": { user_id: IdentityChangeDataSet::master_signing_keys_a() },
+            "self_signing_keys": { user_id: IdentityChangeDataSet::self_signing_keys_b() },
+        }));
+
+        let failures = verify_keys_query_response(&response);
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].user_id, user_id);
+        assert_eq!(failures[0].object, SignedObject::SelfSigningKey);
+    }
+
+    #[test]
+    fn test_event_hashes() {
+        let mut event: CanonicalJsonObject = serde_json::from_value(json!({
+            "type": "m.room.message",
+            "room_id": "!room:example.org",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 1_000,
+            "content": { "body": "Hello", "msgtype": "m.text" },
+            "unsigned": { "age": 12 },
+        }))
+        .unwrap();
+
+        let hash = content_hash(&event);
+        event.insert(
+            "hashes".to_owned(),
+            CanonicalJsonValue::Object([("sha256".to_owned(), hash.into())].into()),
+        );
+        verify_content_hash(&event).unwrap();
+
+        // The reference hash doesn't depend on the redacted content.
+        let reference = reference_hash(&event, &RoomVersionId::V10).unwrap();
+        assert!(!reference.contains(['+', '/']));
+
+        event.insert(
+            "content".to_owned(),
+            CanonicalJsonValue::Object([("body".to_owned(), "Bye".to_owned().into())].into()),
+        );
+        assert_matches!(verify_content_hash(&event), Err(EventHashError::ContentHashMismatch));
+        assert_eq!(reference_hash(&event, &RoomVersionId::V10).unwrap(), reference);
+    }
+}
@@ -18,7 +18,7 @@
 //! `CryptoStore`.
 
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, HashMap},
     fmt::Display,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -274,6 +274,11 @@ impl UsersForKeyQuery {
         self.next_sequence_number.increment();
     }
 
+    /// Whether the given user is waiting for a key query.
+    pub(super) fn contains_user(&self, user: &UserId) -> bool {
+        self.user_map.contains_key(user)
+    }
+
     /// Record that a user has received an update with the given sequence
     /// number.
     ///
@@ -343,12 +348,15 @@ impl UsersForKeyQuery {
         }
     }
 
-    /// Fetch the list of users waiting for a key query, and the current
-    /// sequence number
-    pub(super) fn users_for_key_query(&self) -> (HashSet<OwnedUserId>, SequenceNumber) {
+    /// Fetch the list of users waiting for a key query, together with the
+    /// sequence number of their last invalidation, and the current sequence
+    /// number
+    pub(super) fn users_for_key_query(
+        &self,
+    ) -> (HashMap<OwnedUserId, SequenceNumber>, SequenceNumber) {
         // we return the sequence number of the last invalidation
         let sequence_number = self.next_sequence_number.previous();
-        (self.user_map.keys().cloned().collect(), sequence_number)
+        (self.user_map.clone(), sequence_number)
     }
 
     /// Check if a key query is pending for a user, and register for a wakeup if
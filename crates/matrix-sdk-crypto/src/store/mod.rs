@@ -53,7 +53,7 @@ use futures_util::StreamExt;
 use matrix_sdk_common::locks::RwLock as StdRwLock;
 use ruma::{
     encryption::KeyUsage, events::secret::request::SecretName, DeviceId, OwnedDeviceId,
-    OwnedRoomId, OwnedUserId, UserId,
+    OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
@@ -323,12 +323,21 @@ impl SyncedKeyQueryManager<'_> {
     /// # Returns
     ///
     /// A pair `(users, sequence_number)`, where `users` is the list of users to
-    /// be queried, and `sequence_number` is the current sequence number,
+    /// be queried, together with the sequence number of their last
+    /// invalidation, and `sequence_number` is the current sequence number,
     /// which should be returned in `mark_tracked_users_as_up_to_date`.
-    pub async fn users_for_key_query(&self) -> (HashSet<OwnedUserId>, SequenceNumber) {
+    pub async fn users_for_key_query(
+        &self,
+    ) -> (HashMap<OwnedUserId, SequenceNumber>, SequenceNumber) {
         self.manager.users_for_key_query.lock().await.users_for_key_query()
     }
 
+    /// Whether the device list of the given user is outdated, and should be
+    /// included in a `/keys/query` request.
+    pub async fn is_user_outdated(&self, user: &UserId) -> bool {
+        self.manager.users_for_key_query.lock().await.contains_user(user)
+    }
+
     /// See the docs for [`crate::OlmMachine::tracked_users()`].
     pub fn tracked_users(&self) -> HashSet<OwnedUserId> {
         self.cache.tracked_users.read().iter().cloned().collect()
@@ -1561,6 +1570,51 @@ impl Store {
         self.set_value("only_allow_trusted_devices", &block_untrusted_devices).await
     }
 
+    /// Check whether the room keys forwarded by our own verified devices are
+    /// accepted, even if we didn't request them.
+    pub async fn get_unrequested_room_key_forwards_enabled(&self) -> Result<bool> {
+        let value =
+            self.get_value("unrequested_room_key_forwards_enabled").await?.unwrap_or_default();
+        Ok(value)
+    }
+
+    /// Set whether the room keys forwarded by our own verified devices are
+    /// accepted, even if we didn't request them.
+    pub async fn set_unrequested_room_key_forwards_enabled(&self, enabled: bool) -> Result<()> {
+        self.set_value("unrequested_room_key_forwards_enabled", &enabled).await
+    }
+
+    /// Get the override of the global flag to only encrypt messages for
+    /// trusted devices for the given room, if one was set.
+    pub async fn get_room_only_allow_trusted_devices(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<bool>> {
+        self.get_value(&Self::room_only_allow_trusted_devices_key(room_id)).await
+    }
+
+    /// Override the global flag whether to encrypt messages for untrusted
+    /// devices in the given room.
+    ///
+    /// Setting it to `None` removes the override, so the room follows the
+    /// global flag again.
+    pub async fn set_room_only_allow_trusted_devices(
+        &self,
+        room_id: &RoomId,
+        block_untrusted_devices: Option<bool>,
+    ) -> Result<()> {
+        let key = Self::room_only_allow_trusted_devices_key(room_id);
+
+        match block_untrusted_devices {
+            Some(value) => self.set_value(&key, &value).await,
+            None => self.remove_custom_value(&key).await,
+        }
+    }
+
+    fn room_only_allow_trusted_devices_key(room_id: &RoomId) -> String {
+        format!("only_allow_trusted_devices:{room_id}")
+    }
+
     /// Get custom stored value associated with a key
     pub async fn get_value<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
         let Some(value) = self.get_custom_value(key).await? else {
@@ -24,10 +24,12 @@ use growable_bloom_filter::GrowableBloom;
 use indexed_db_futures::prelude::*;
 use matrix_sdk_base::{
     deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+    media::MediaPolicy,
     store::{
-        ChildTransactionId, ComposerDraft, DependentQueuedRequest, DependentQueuedRequestKind,
-        QueuedRequest, QueuedRequestKind, SentRequestKey, SerializableEventContent,
-        ServerCapabilities, StateChanges, StateStore, StoreError,
+        CachedUrlPreview, CachedUserProfile, CachedWellKnown, ChildTransactionId, ComposerDraft,
+        ContactActivity, DependentQueuedRequest, DependentQueuedRequestKind, QueuedRequest,
+        QueuedRequestKind, SentRequestKey, SerializableEventContent, ServerCapabilities,
+        StateChanges, StateStore, StoreError, TemporaryRoomMute, UploadedFilter,
     },
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, StateStoreDataKey, StateStoreDataValue,
 };
@@ -44,8 +46,8 @@ use ruma::{
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType, SyncStateEvent,
     },
     serde::Raw,
-    CanonicalJsonObject, EventId, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedTransactionId,
-    OwnedUserId, RoomId, RoomVersionId, TransactionId, UserId,
+    CanonicalJsonObject, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri,
+    OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId, RoomVersionId, TransactionId, UserId,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tracing::{debug, warn};
@@ -177,6 +179,65 @@ fn deserialize_value<T: DeserializeOwned>(
     }
 }
 
+/// Put all the given entries in the given object store.
+///
+/// The requests are not awaited one by one, they all complete with the
+/// transaction of the object store.
+fn bulk_put(store: &IdbObjectStore<'_>, entries: Vec<(JsValue, JsValue)>) -> Result<()> {
+    for (key, value) in entries {
+        store.put_key_val_owned(key, &value)?;
+    }
+
+    Ok(())
+}
+
+/// The entries of the member events of rooms, put in bulk since putting them
+/// one by one dominates the time it takes to save rooms with many members.
+#[derive(Default)]
+struct MemberEntries {
+    /// The entries of the [`keys::ROOM_STATE`] store.
+    state: Vec<(JsValue, JsValue)>,
+    /// The entries of the [`keys::USER_IDS`] store.
+    user_ids: Vec<(JsValue, JsValue)>,
+    /// The entries of the [`keys::PROFILES`] store.
+    profiles: Vec<(JsValue, JsValue)>,
+    /// The keys to delete from the [`keys::STRIPPED_ROOM_STATE`] store.
+    stripped_state_keys: Vec<JsValue>,
+    /// The keys to delete from the [`keys::STRIPPED_USER_IDS`] store.
+    stripped_user_ids_keys: Vec<JsValue>,
+}
+
+impl MemberEntries {
+    /// The object stores that need to be part of the transaction passed to
+    /// [`MemberEntries::put()`].
+    const STORES: [&'static str; 5] = [
+        keys::ROOM_STATE,
+        keys::STRIPPED_ROOM_STATE,
+        keys::USER_IDS,
+        keys::STRIPPED_USER_IDS,
+        keys::PROFILES,
+    ];
+
+    /// Put all the entries with the given transaction.
+    fn put(self, tx: &IdbTransaction<'_>) -> Result<()> {
+        let stripped_state = tx.object_store(keys::STRIPPED_ROOM_STATE)?;
+        for key in &self.stripped_state_keys {
+            stripped_state.delete(key)?;
+        }
+
+        let stripped_user_ids = tx.object_store(keys::STRIPPED_USER_IDS)?;
+        for key in &self.stripped_user_ids_keys {
+            stripped_user_ids.delete(key)?;
+        }
+
+        bulk_put(&tx.object_store(keys::ROOM_STATE)?, self.state)?;
+        bulk_put(&tx.object_store(keys::USER_IDS)?, self.user_ids)?;
+        bulk_put(&tx.object_store(keys::PROFILES)?, self.profiles)?;
+
+        Ok(())
+    }
+}
+
 fn encode_key<T>(store_cipher: Option<&StoreCipher>, table_name: &str, key: T) -> JsValue
 where
     T: SafeEncode,
@@ -334,6 +395,48 @@ impl IndexeddbStateStore {
         encode_to_range(self.store_cipher.as_deref(), table_name, key)
     }
 
+    /// Collect the entries of the given member event of a room, with the
+    /// profile of the member if there is one in `profiles`.
+    fn collect_member_entries(
+        &self,
+        entries: &mut MemberEntries,
+        room_id: &RoomId,
+        state_key: &str,
+        raw_member_event: &Raw<AnySyncStateEvent>,
+        profiles: Option<&BTreeMap<OwnedUserId, MinimalRoomMemberEvent>>,
+    ) -> Result<()> {
+        let key =
+            self.encode_key(keys::ROOM_STATE, (room_id, &StateEventType::RoomMember, state_key));
+        entries.state.push((key.clone(), self.serialize_value(&raw_member_event)?));
+        entries.stripped_state_keys.push(key);
+
+        let event = match raw_member_event.deserialize_as::<SyncRoomMemberEvent>() {
+            Ok(ev) => ev,
+            Err(e) => {
+                let event_id: Option<String> =
+                    raw_member_event.get_field("event_id").ok().flatten();
+                debug!(event_id, "Failed to deserialize member event: {e}");
+                return Ok(());
+            }
+        };
+
+        let key = (room_id, state_key);
+
+        entries.stripped_user_ids_keys.push(self.encode_key(keys::STRIPPED_USER_IDS, key));
+        entries.user_ids.push((
+            self.encode_key(keys::USER_IDS, key),
+            self.serialize_value(&RoomMember::from(&event))?,
+        ));
+
+        if let Some(profile) = profiles.and_then(|p| p.get(event.state_key())) {
+            entries
+                .profiles
+                .push((self.encode_key(keys::PROFILES, key), self.serialize_value(&profile)?));
+        }
+
+        Ok(())
+    }
+
     /// Get user IDs for the given room with the given memberships and stripped
     /// state.
     pub async fn get_user_ids_inner(
@@ -422,6 +525,33 @@ impl IndexeddbStateStore {
             StateStoreDataKey::SeenKnockRequests(room_id) => {
                 self.encode_key(keys::KV, (StateStoreDataKey::SEEN_KNOCK_REQUESTS, room_id))
             }
+            StateStoreDataKey::UserProfile(user_id) => {
+                self.encode_key(keys::KV, (StateStoreDataKey::USER_PROFILE, user_id))
+            }
+            StateStoreDataKey::UploadedFilter(name) => {
+                self.encode_key(keys::KV, (StateStoreDataKey::UPLOADED_FILTER, name))
+            }
+            StateStoreDataKey::UrlPreview(name) => {
+                self.encode_key(keys::KV, (StateStoreDataKey::URL_PREVIEW, name))
+            }
+            StateStoreDataKey::ContactActivity => {
+                self.encode_key(keys::KV, StateStoreDataKey::CONTACT_ACTIVITY)
+            }
+            StateStoreDataKey::WellKnown => {
+                self.encode_key(keys::KV, StateStoreDataKey::WELL_KNOWN)
+            }
+            StateStoreDataKey::RecoveryKeyConfirmation => {
+                self.encode_key(keys::KV, StateStoreDataKey::RECOVERY_KEY_CONFIRMATION)
+            }
+            StateStoreDataKey::TemporaryRoomMutes => {
+                self.encode_key(keys::KV, StateStoreDataKey::TEMPORARY_ROOM_MUTES)
+            }
+            StateStoreDataKey::MediaPolicy => {
+                self.encode_key(keys::KV, StateStoreDataKey::MEDIA_POLICY)
+            }
+            StateStoreDataKey::CachedUrlPreviews => {
+                self.encode_key(keys::KV, StateStoreDataKey::CACHED_URL_PREVIEWS)
+            }
         }
     }
 }
@@ -544,6 +674,42 @@ impl_state_store!({
                 .map(|f| self.deserialize_value::<BTreeMap<OwnedEventId, OwnedUserId>>(&f))
                 .transpose()?
                 .map(StateStoreDataValue::SeenKnockRequests),
+            StateStoreDataKey::UserProfile(_) => value
+                .map(|f| self.deserialize_value::<CachedUserProfile>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::UserProfile),
+            StateStoreDataKey::UploadedFilter(_) => value
+                .map(|f| self.deserialize_value::<UploadedFilter>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::UploadedFilter),
+            StateStoreDataKey::UrlPreview(_) => value
+                .map(|f| self.deserialize_value::<CachedUrlPreview>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::UrlPreview),
+            StateStoreDataKey::ContactActivity => value
+                .map(|f| self.deserialize_value::<BTreeMap<OwnedUserId, ContactActivity>>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::ContactActivity),
+            StateStoreDataKey::WellKnown => value
+                .map(|f| self.deserialize_value::<CachedWellKnown>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::WellKnown),
+            StateStoreDataKey::RecoveryKeyConfirmation => value
+                .map(|f| self.deserialize_value::<MilliSecondsSinceUnixEpoch>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::RecoveryKeyConfirmation),
+            StateStoreDataKey::TemporaryRoomMutes => value
+                .map(|f| self.deserialize_value::<BTreeMap<OwnedRoomId, TemporaryRoomMute>>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::TemporaryRoomMutes),
+            StateStoreDataKey::MediaPolicy => value
+                .map(|f| self.deserialize_value::<MediaPolicy>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::MediaPolicy),
+            StateStoreDataKey::CachedUrlPreviews => value
+                .map(|f| self.deserialize_value::<Vec<String>>(&f))
+                .transpose()?
+                .map(StateStoreDataValue::CachedUrlPreviews),
         };
 
         Ok(value)
@@ -586,6 +752,39 @@ impl_state_store!({
                     .into_seen_knock_requests()
                     .expect("Session data is not a set of seen knock request ids"),
             ),
+            StateStoreDataKey::UserProfile(_) => self.serialize_value(
+                &value.into_user_profile().expect("Session data not a user profile"),
+            ),
+            StateStoreDataKey::UploadedFilter(_) => self.serialize_value(
+                &value.into_uploaded_filter().expect("Session data not an uploaded filter"),
+            ),
+            StateStoreDataKey::UrlPreview(_) => self.serialize_value(
+                &value.into_url_preview().expect("Session data not a URL preview"),
+            ),
+            StateStoreDataKey::ContactActivity => self.serialize_value(
+                &value.into_contact_activity().expect("Session data not the activity of users"),
+            ),
+            StateStoreDataKey::WellKnown => self.serialize_value(
+                &value.into_well_known().expect("Session data not a well-known document"),
+            ),
+            StateStoreDataKey::RecoveryKeyConfirmation => self.serialize_value(
+                &value
+                    .into_recovery_key_confirmation()
+                    .expect("Session data not a recovery key confirmation"),
+            ),
+            StateStoreDataKey::TemporaryRoomMutes => self.serialize_value(
+                &value
+                    .into_temporary_room_mutes()
+                    .expect("Session data not the temporary room mutes"),
+            ),
+            StateStoreDataKey::MediaPolicy => self.serialize_value(
+                &value.into_media_policy().expect("Session data not a media policy"),
+            ),
+            StateStoreDataKey::CachedUrlPreviews => self.serialize_value(
+                &value
+                    .into_cached_url_previews()
+                    .expect("Session data not the cached URL previews"),
+            ),
         };
 
         let tx =
@@ -614,6 +813,34 @@ impl_state_store!({
         Ok(())
     }
 
+    async fn save_room_members(
+        &self,
+        room_id: &RoomId,
+        members: BTreeMap<OwnedUserId, Raw<SyncRoomMemberEvent>>,
+        profiles: BTreeMap<OwnedUserId, MinimalRoomMemberEvent>,
+    ) -> Result<()> {
+        let mut entries = MemberEntries::default();
+
+        for (user_id, raw_member_event) in &members {
+            self.collect_member_entries(
+                &mut entries,
+                room_id,
+                user_id.as_str(),
+                raw_member_event.cast_ref(),
+                Some(&profiles),
+            )?;
+        }
+
+        let tx = self.inner.transaction_on_multi_with_mode(
+            &MemberEntries::STORES,
+            IdbTransactionMode::Readwrite,
+        )?;
+
+        entries.put(&tx)?;
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
     async fn save_changes(&self, changes: &StateChanges) -> Result<()> {
         let mut stores: HashSet<&'static str> = [
             (changes.sync_token.is_some(), keys::KV),
@@ -632,13 +859,7 @@ impl_state_store!({
         .collect();
 
         if !changes.state.is_empty() {
-            stores.extend([
-                keys::ROOM_STATE,
-                keys::USER_IDS,
-                keys::STRIPPED_USER_IDS,
-                keys::STRIPPED_ROOM_STATE,
-                keys::PROFILES,
-            ]);
+            stores.extend(MemberEntries::STORES);
         }
 
         if !changes.redactions.is_empty() {
@@ -715,9 +936,7 @@ impl_state_store!({
         if !changes.state.is_empty() {
             let state = tx.object_store(keys::ROOM_STATE)?;
             let profiles = tx.object_store(keys::PROFILES)?;
-            let user_ids = tx.object_store(keys::USER_IDS)?;
             let stripped_state = tx.object_store(keys::STRIPPED_ROOM_STATE)?;
-            let stripped_user_ids = tx.object_store(keys::STRIPPED_USER_IDS)?;
 
             for (room, user_ids) in &changes.profiles_to_delete {
                 for user_id in user_ids {
@@ -726,48 +945,33 @@ impl_state_store!({
                 }
             }
 
+            // Collect the members to put them all at once, after the other state events.
+            let mut member_entries = MemberEntries::default();
+
             for (room, event_types) in &changes.state {
                 let profile_changes = changes.profiles.get(room);
 
                 for (event_type, events) in event_types {
                     for (state_key, raw_event) in events {
-                        let key = self.encode_key(keys::ROOM_STATE, (room, event_type, state_key));
-                        state.put_key_val(&key, &self.serialize_value(&raw_event)?)?;
-                        stripped_state.delete(&key)?;
-
                         if *event_type == StateEventType::RoomMember {
-                            let event = match raw_event.deserialize_as::<SyncRoomMemberEvent>() {
-                                Ok(ev) => ev,
-                                Err(e) => {
-                                    let event_id: Option<String> =
-                                        raw_event.get_field("event_id").ok().flatten();
-                                    debug!(event_id, "Failed to deserialize member event: {e}");
-                                    continue;
-                                }
-                            };
-
-                            let key = (room, state_key);
-
-                            stripped_user_ids
-                                .delete(&self.encode_key(keys::STRIPPED_USER_IDS, key))?;
-
-                            user_ids.put_key_val_owned(
-                                self.encode_key(keys::USER_IDS, key),
-                                &self.serialize_value(&RoomMember::from(&event))?,
+                            self.collect_member_entries(
+                                &mut member_entries,
+                                room,
+                                state_key,
+                                raw_event,
+                                profile_changes,
                             )?;
-
-                            if let Some(profile) =
-                                profile_changes.and_then(|p| p.get(event.state_key()))
-                            {
-                                profiles.put_key_val_owned(
-                                    self.encode_key(keys::PROFILES, key),
-                                    &self.serialize_value(&profile)?,
-                                )?;
-                            }
+                            continue;
                         }
+
+                        let key = self.encode_key(keys::ROOM_STATE, (room, event_type, state_key));
+                        state.put_key_val(&key, &self.serialize_value(&raw_event)?)?;
+                        stripped_state.delete(&key)?;
                     }
                 }
             }
+
+            member_entries.put(&tx)?;
         }
 
         if !changes.room_infos.is_empty() {
@@ -47,11 +47,12 @@ use vodozemac::Curve25519PublicKey;
 
 use crate::{
     error::{Error, Result},
+    migration::prepare_migration,
     utils::{
         repeat_vars, Key, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt,
         SqliteKeyValueStoreConnExt,
     },
-    OpenStoreError,
+    MigrationConfig, MigrationReport, OpenStoreError,
 };
 
 /// A sqlite based cryptostore.
@@ -79,22 +80,52 @@ impl SqliteCryptoStore {
         path: impl AsRef<Path>,
         passphrase: Option<&str>,
     ) -> Result<Self, OpenStoreError> {
-        let path = path.as_ref();
-        fs::create_dir_all(path).await.map_err(OpenStoreError::CreateDir)?;
-        let cfg = deadpool_sqlite::Config::new(path.join("matrix-sdk-crypto.sqlite3"));
-        let pool = cfg.create_pool(Runtime::Tokio1)?;
+        let pool = create_pool(path.as_ref()).await?;
 
         Self::open_with_pool(pool, passphrase).await
     }
 
+    /// Open the sqlite-based crypto store at the given path using the given
+    /// passphrase to encrypt private data, preparing its migration, if any,
+    /// with the given config.
+    pub async fn open_with_config(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+        config: &MigrationConfig,
+    ) -> Result<Self, OpenStoreError> {
+        let pool = create_pool(path.as_ref()).await?;
+
+        Self::open_with_pool_and_config(pool, passphrase, config).await
+    }
+
     /// Create a sqlite-based crypto store using the given sqlite database pool.
     /// The given passphrase will be used to encrypt private data.
     pub async fn open_with_pool(
         pool: SqlitePool,
         passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        Self::open_with_pool_and_config(pool, passphrase, &MigrationConfig::default()).await
+    }
+
+    /// Describe the migration that opening the sqlite-based crypto store at
+    /// the given path would run, without running it.
+    pub async fn migration_report(
+        path: impl AsRef<Path>,
+    ) -> Result<MigrationReport, OpenStoreError> {
+        let pool = create_pool(path.as_ref()).await?;
+        let conn = pool.get().await?;
+
+        MigrationReport::new(&conn, DATABASE_VERSION).await
+    }
+
+    async fn open_with_pool_and_config(
+        pool: SqlitePool,
+        passphrase: Option<&str>,
+        config: &MigrationConfig,
     ) -> Result<Self, OpenStoreError> {
         let conn = pool.get().await?;
         let version = conn.db_version().await?;
+        prepare_migration(&conn, DATABASE_NAME, version, DATABASE_VERSION, config).await?;
         run_migrations(&conn, version).await?;
         let store_cipher = match passphrase {
             Some(p) => Some(Arc::new(conn.get_or_create_store_cipher(p).await?)),
@@ -192,9 +223,18 @@ impl SqliteCryptoStore {
 
 const DATABASE_VERSION: u8 = 9;
 
+/// The name of the database file.
+const DATABASE_NAME: &str = "matrix-sdk-crypto.sqlite3";
+
 /// key for the dehydrated device pickle key in the key/value table.
 const DEHYDRATED_DEVICE_PICKLE_KEY: &str = "dehydrated_device_pickle_key";
 
+async fn create_pool(path: &Path) -> Result<SqlitePool, OpenStoreError> {
+    fs::create_dir_all(path).await.map_err(OpenStoreError::CreateDir)?;
+    let cfg = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
+    Ok(cfg.create_pool(Runtime::Tokio1)?)
+}
+
 /// Run migrations for the given version of the database.
 async fn run_migrations(conn: &SqliteAsyncConn, version: u8) -> Result<()> {
     if version == 0 {
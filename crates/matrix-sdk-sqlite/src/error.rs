@@ -46,6 +46,18 @@ pub enum OpenStoreError {
     #[error("Invalid database version")]
     InvalidVersion,
 
+    /// The database failed its integrity check before being migrated.
+    #[error("The database failed its integrity check: {}", .0.join(", "))]
+    IntegrityCheck(Vec<String>),
+
+    /// Failed to snapshot the database before migrating it.
+    #[error("Failed to back up the database")]
+    Backup(#[source] rusqlite::Error),
+
+    /// Failed to prepare the file of the database's backup.
+    #[error("Failed to prepare the database's backup file")]
+    BackupFile(#[source] io::Error),
+
     /// Failed to apply migrations.
     #[error("Failed to run migrations")]
     Migration(#[from] Error),
@@ -31,8 +31,9 @@ use tracing::{debug, trace};
 
 use crate::{
     error::{Error, Result},
+    migration::prepare_migration,
     utils::{Key, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt, SqliteKeyValueStoreConnExt},
-    OpenStoreError,
+    MigrationConfig, MigrationReport, OpenStoreError,
 };
 
 mod keys {
@@ -48,6 +49,9 @@ mod keys {
 /// the [`run_migrations`] function.
 const DATABASE_VERSION: u8 = 3;
 
+/// The name of the database file.
+const DATABASE_NAME: &str = "matrix-sdk-event-cache.sqlite3";
+
 /// The string used to identify a chunk of type events, in the `type` field in
 /// the database.
 const CHUNK_TYPE_EVENT_TYPE_STRING: &str = "E";
@@ -81,14 +85,47 @@ impl SqliteEventCacheStore {
         Self::open_with_pool(pool, passphrase).await
     }
 
+    /// Open the SQLite-based event cache store at the given path using the
+    /// given passphrase to encrypt private data, preparing its migration, if
+    /// any, with the given config.
+    pub async fn open_with_config(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+        config: &MigrationConfig,
+    ) -> Result<Self, OpenStoreError> {
+        let pool = create_pool(path.as_ref()).await?;
+
+        Self::open_with_pool_and_config(pool, passphrase, config).await
+    }
+
     /// Open an SQLite-based event cache store using the given SQLite database
     /// pool. The given passphrase will be used to encrypt private data.
     pub async fn open_with_pool(
         pool: SqlitePool,
         passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        Self::open_with_pool_and_config(pool, passphrase, &MigrationConfig::default()).await
+    }
+
+    /// Describe the migration that opening the SQLite-based event cache store
+    /// at the given path would run, without running it.
+    pub async fn migration_report(
+        path: impl AsRef<Path>,
+    ) -> Result<MigrationReport, OpenStoreError> {
+        let pool = create_pool(path.as_ref()).await?;
+        let conn = pool.get().await?;
+
+        MigrationReport::new(&conn, DATABASE_VERSION).await
+    }
+
+    async fn open_with_pool_and_config(
+        pool: SqlitePool,
+        passphrase: Option<&str>,
+        config: &MigrationConfig,
     ) -> Result<Self, OpenStoreError> {
         let conn = pool.get().await?;
         let version = conn.db_version().await?;
+        prepare_migration(&conn, DATABASE_NAME, version, DATABASE_VERSION, config).await?;
         run_migrations(&conn, version).await?;
 
         let store_cipher = match passphrase {
@@ -259,7 +296,7 @@ impl TransactionExtForLinkedChunks for Transaction<'_> {
 
 async fn create_pool(path: &Path) -> Result<SqlitePool, OpenStoreError> {
     fs::create_dir_all(path).await.map_err(OpenStoreError::CreateDir)?;
-    let cfg = deadpool_sqlite::Config::new(path.join("matrix-sdk-event-cache.sqlite3"));
+    let cfg = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
     Ok(cfg.create_pool(Runtime::Tokio1)?)
 }
 
@@ -21,6 +21,7 @@ mod crypto_store;
 mod error;
 #[cfg(feature = "event-cache")]
 mod event_cache_store;
+mod migration;
 #[cfg(feature = "state-store")]
 mod state_store;
 mod utils;
@@ -30,6 +31,7 @@ pub use self::crypto_store::SqliteCryptoStore;
 pub use self::error::OpenStoreError;
 #[cfg(feature = "event-cache")]
 pub use self::event_cache_store::SqliteEventCacheStore;
+pub use self::migration::{MigrationConfig, MigrationReport};
 #[cfg(feature = "state-store")]
 pub use self::state_store::SqliteStateStore;
 
@@ -0,0 +1,194 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers to prepare the schema migrations of the stores.
+
+use std::{path::PathBuf, time::Duration};
+
+use deadpool_sqlite::Object as SqliteAsyncConn;
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::{
+    error::Result,
+    utils::{SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt},
+    OpenStoreError,
+};
+
+/// The number of bytes a migration is assumed to rewrite per second, to
+/// estimate its duration.
+const MIGRATION_THROUGHPUT: u64 = 10 * 1024 * 1024;
+
+/// How to prepare a database before running its schema migrations.
+///
+/// The configuration is only used when the database needs to be migrated, a
+/// new or up-to-date database is opened as usual.
+#[derive(Clone, Debug, Default)]
+pub struct MigrationConfig {
+    backup_dir: Option<PathBuf>,
+    check_integrity: bool,
+}
+
+impl MigrationConfig {
+    /// Create a new `MigrationConfig` that doesn't back up nor check the
+    /// database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot the database into the given directory before migrating it.
+    ///
+    /// The snapshot is named after the database file and its current
+    /// version, e.g. `matrix-sdk-state.sqlite3.v7.bak`, and replaces any
+    /// previous snapshot of the same version. It can be copied back in place
+    /// of the database file if the migration fails.
+    pub fn backup_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.backup_dir = Some(dir.into());
+        self
+    }
+
+    /// Check the integrity of the database before migrating it.
+    ///
+    /// If the check fails, the database is not migrated and opening it fails
+    /// with [`OpenStoreError::IntegrityCheck`].
+    pub fn check_integrity(mut self, check_integrity: bool) -> Self {
+        self.check_integrity = check_integrity;
+        self
+    }
+}
+
+/// What a migration of a database would do, without running it.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct MigrationReport {
+    /// The current version of the database, `0` if it doesn't exist yet.
+    pub current_version: u8,
+
+    /// The version the database would be migrated to.
+    pub target_version: u8,
+
+    /// The size of the database, in bytes.
+    ///
+    /// A backup of the database needs as much free disk space.
+    pub database_size: u64,
+
+    /// The problems found by the integrity check of the database, empty if
+    /// it passed.
+    pub integrity_errors: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Inspect the database behind the given connection.
+    pub(crate) async fn new(
+        conn: &SqliteAsyncConn,
+        target_version: u8,
+    ) -> Result<Self, OpenStoreError> {
+        let current_version = conn.db_version().await?;
+        let database_size = database_size(conn).await?;
+        let integrity_errors = integrity_errors(conn).await?;
+
+        Ok(Self { current_version, target_version, database_size, integrity_errors })
+    }
+
+    /// Whether the database needs to be migrated.
+    pub fn is_needed(&self) -> bool {
+        self.current_version != 0 && self.current_version < self.target_version
+    }
+
+    /// Whether the database passed its integrity check.
+    pub fn is_intact(&self) -> bool {
+        self.integrity_errors.is_empty()
+    }
+
+    /// A rough, pessimistic estimate of how long the migration would take.
+    ///
+    /// It assumes that every version step rewrites the whole database.
+    pub fn estimated_duration(&self) -> Duration {
+        if !self.is_needed() {
+            return Duration::ZERO;
+        }
+
+        let steps = u64::from(self.target_version - self.current_version);
+        let bytes = self.database_size.saturating_mul(steps);
+
+        Duration::from_secs_f64(bytes as f64 / MIGRATION_THROUGHPUT as f64)
+    }
+}
+
+/// Check and back up the database as requested by the `config`, if it needs
+/// to be migrated from version `from` to version `to`.
+pub(crate) async fn prepare_migration(
+    conn: &SqliteAsyncConn,
+    database_name: &str,
+    from: u8,
+    to: u8,
+    config: &MigrationConfig,
+) -> Result<(), OpenStoreError> {
+    // There is nothing to keep in a new database.
+    if from == 0 || from >= to {
+        return Ok(());
+    }
+
+    if config.check_integrity {
+        let errors = integrity_errors(conn).await?;
+
+        if !errors.is_empty() {
+            warn!(version = from, ?errors, "The database failed its integrity check");
+            return Err(OpenStoreError::IntegrityCheck(errors));
+        }
+    }
+
+    if let Some(backup_dir) = &config.backup_dir {
+        fs::create_dir_all(backup_dir).await.map_err(OpenStoreError::BackupFile)?;
+
+        let backup_path = backup_dir.join(format!("{database_name}.v{from}.bak"));
+
+        // `VACUUM INTO` refuses to overwrite a file.
+        if fs::try_exists(&backup_path).await.unwrap_or(false) {
+            fs::remove_file(&backup_path).await.map_err(OpenStoreError::BackupFile)?;
+        }
+
+        let target = backup_path.to_string_lossy().into_owned();
+        conn.execute("VACUUM INTO ?", (target,)).await.map_err(OpenStoreError::Backup)?;
+
+        info!(version = from, path = ?backup_path, "Backed up the database before migrating it");
+    }
+
+    Ok(())
+}
+
+/// The size of the database, in bytes.
+async fn database_size(conn: &SqliteAsyncConn) -> Result<u64> {
+    let size = conn
+        .query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            (),
+            |row| row.get::<_, i64>(0),
+        )
+        .await?;
+
+    Ok(size.try_into().unwrap_or_default())
+}
+
+/// Run an integrity check of the database and return the problems it found.
+async fn integrity_errors(conn: &SqliteAsyncConn) -> Result<Vec<String>> {
+    let rows = conn
+        .prepare("PRAGMA integrity_check", |mut stmt| {
+            let rows = stmt.query_map((), |row| row.get::<_, String>(0))?;
+            rows.collect::<Result<Vec<_>, _>>()
+        })
+        .await?;
+
+    Ok(rows.into_iter().filter(|row| row != "ok").collect())
+}
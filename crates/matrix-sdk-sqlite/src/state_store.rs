@@ -35,18 +35,19 @@ use ruma::{
     CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomId, OwnedTransactionId, OwnedUserId,
     RoomId, RoomVersionId, TransactionId, UserId,
 };
-use rusqlite::{OptionalExtension, Transaction};
+use rusqlite::{OptionalExtension, ToSql, Transaction};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tokio::fs;
 use tracing::{debug, warn};
 
 use crate::{
     error::{Error, Result},
+    migration::prepare_migration,
     utils::{
         repeat_vars, Key, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt,
         SqliteKeyValueStoreConnExt,
     },
-    OpenStoreError,
+    MigrationConfig, MigrationReport, OpenStoreError,
 };
 
 mod keys {
@@ -71,6 +72,9 @@ mod keys {
 /// the [`SqliteStateStore::run_migrations`] function..
 const DATABASE_VERSION: u8 = 10;
 
+/// The name of the database file.
+const DATABASE_NAME: &str = "matrix-sdk-state.sqlite3";
+
 /// A sqlite based cryptostore.
 #[derive(Clone)]
 pub struct SqliteStateStore {
@@ -97,14 +101,47 @@ impl SqliteStateStore {
         Self::open_with_pool(pool, passphrase).await
     }
 
+    /// Open the sqlite-based state store at the given path using the given
+    /// passphrase to encrypt private data, preparing its migration, if any,
+    /// with the given config.
+    pub async fn open_with_config(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+        config: &MigrationConfig,
+    ) -> Result<Self, OpenStoreError> {
+        let pool = create_pool(path.as_ref()).await?;
+
+        Self::open_with_pool_and_config(pool, passphrase, config).await
+    }
+
     /// Create a sqlite-based state store using the given sqlite database pool.
     /// The given passphrase will be used to encrypt private data.
     pub async fn open_with_pool(
         pool: SqlitePool,
         passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        Self::open_with_pool_and_config(pool, passphrase, &MigrationConfig::default()).await
+    }
+
+    /// Describe the migration that opening the sqlite-based state store at the
+    /// given path would run, without running it.
+    pub async fn migration_report(
+        path: impl AsRef<Path>,
+    ) -> Result<MigrationReport, OpenStoreError> {
+        let pool = create_pool(path.as_ref()).await?;
+        let conn = pool.get().await?;
+
+        MigrationReport::new(&conn, DATABASE_VERSION).await
+    }
+
+    async fn open_with_pool_and_config(
+        pool: SqlitePool,
+        passphrase: Option<&str>,
+        config: &MigrationConfig,
     ) -> Result<Self, OpenStoreError> {
         let conn = pool.get().await?;
         let mut version = conn.db_version().await?;
+        prepare_migration(&conn, DATABASE_NAME, version, DATABASE_VERSION, config).await?;
 
         if version == 0 {
             init(&conn).await?;
@@ -393,6 +430,29 @@ impl SqliteStateStore {
             StateStoreDataKey::SeenKnockRequests(room_id) => {
                 Cow::Owned(format!("{}:{room_id}", StateStoreDataKey::SEEN_KNOCK_REQUESTS))
             }
+            StateStoreDataKey::UserProfile(user_id) => {
+                Cow::Owned(format!("{}:{user_id}", StateStoreDataKey::USER_PROFILE))
+            }
+            StateStoreDataKey::UploadedFilter(name) => {
+                Cow::Owned(format!("{}:{name}", StateStoreDataKey::UPLOADED_FILTER))
+            }
+            StateStoreDataKey::UrlPreview(name) => {
+                Cow::Owned(format!("{}:{name}", StateStoreDataKey::URL_PREVIEW))
+            }
+            StateStoreDataKey::ContactActivity => {
+                Cow::Borrowed(StateStoreDataKey::CONTACT_ACTIVITY)
+            }
+            StateStoreDataKey::WellKnown => Cow::Borrowed(StateStoreDataKey::WELL_KNOWN),
+            StateStoreDataKey::RecoveryKeyConfirmation => {
+                Cow::Borrowed(StateStoreDataKey::RECOVERY_KEY_CONFIRMATION)
+            }
+            StateStoreDataKey::TemporaryRoomMutes => {
+                Cow::Borrowed(StateStoreDataKey::TEMPORARY_ROOM_MUTES)
+            }
+            StateStoreDataKey::MediaPolicy => Cow::Borrowed(StateStoreDataKey::MEDIA_POLICY),
+            StateStoreDataKey::CachedUrlPreviews => {
+                Cow::Borrowed(StateStoreDataKey::CACHED_URL_PREVIEWS)
+            }
         };
 
         self.encode_key(keys::KV_BLOB, &*key_s)
@@ -424,11 +484,77 @@ impl SqliteStateStore {
         let member_room_id = self.encode_key(keys::MEMBER, room_id);
         txn.remove_room_members(&member_room_id, Some(stripped))
     }
+
+    /// Get the row of the `state_event` table of the given state event.
+    fn state_event_row(
+        &self,
+        room_id: &RoomId,
+        event_type: &StateEventType,
+        state_key: &str,
+        raw_state_event: &Raw<AnySyncStateEvent>,
+    ) -> Result<StateEventRow> {
+        let event_id: Option<String> = raw_state_event.get_field("event_id").ok().flatten();
+
+        Ok(StateEventRow {
+            room_id: self.encode_key(keys::STATE_EVENT, room_id),
+            event_type: self.encode_key(keys::STATE_EVENT, event_type.to_string()),
+            state_key: self.encode_key(keys::STATE_EVENT, state_key),
+            stripped: false,
+            event_id: event_id.map(|event_id| self.encode_key(keys::STATE_EVENT, event_id)),
+            data: self.serialize_json(raw_state_event)?,
+        })
+    }
+
+    /// Collect the rows of the given member event of a room, with the profile
+    /// of the member if there is one in `profiles`.
+    fn collect_member_rows(
+        &self,
+        rows: &mut MemberEventRows,
+        room_id: &RoomId,
+        state_key: &str,
+        raw_member_event: &Raw<AnySyncStateEvent>,
+        profiles: Option<&BTreeMap<OwnedUserId, MinimalRoomMemberEvent>>,
+    ) -> Result<()> {
+        rows.state_events.push(self.state_event_row(
+            room_id,
+            &StateEventType::RoomMember,
+            state_key,
+            raw_member_event,
+        )?);
+
+        let member_event = match raw_member_event.deserialize_as::<SyncRoomMemberEvent>() {
+            Ok(ev) => ev,
+            Err(e) => {
+                let event_id: Option<String> =
+                    raw_member_event.get_field("event_id").ok().flatten();
+                debug!(event_id, "Failed to deserialize member event: {e}");
+                return Ok(());
+            }
+        };
+
+        rows.members.push(MemberRow {
+            room_id: self.encode_key(keys::MEMBER, room_id),
+            user_id: self.encode_key(keys::MEMBER, state_key),
+            membership: self.encode_key(keys::MEMBER, member_event.membership().as_str()),
+            stripped: false,
+            data: self.serialize_value(&state_key)?,
+        });
+
+        if let Some(profile) = profiles.and_then(|p| p.get(member_event.state_key())) {
+            rows.profiles.push(ProfileRow {
+                room_id: self.encode_key(keys::PROFILE, room_id),
+                user_id: self.encode_key(keys::PROFILE, state_key),
+                data: self.serialize_json(&profile)?,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 async fn create_pool(path: &Path) -> Result<SqlitePool, OpenStoreError> {
     fs::create_dir_all(path).await.map_err(OpenStoreError::CreateDir)?;
-    let cfg = deadpool_sqlite::Config::new(path.join("matrix-sdk-state.sqlite3"));
+    let cfg = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
     Ok(cfg.create_pool(Runtime::Tokio1)?)
 }
 
@@ -446,6 +572,123 @@ async fn init(conn: &SqliteAsyncConn) -> Result<()> {
     .await
 }
 
+/// The maximum number of variables in a single statement, which is the lowest
+/// limit of SQLite.
+const MAX_STATEMENT_VARIABLES: usize = 999;
+
+/// A row that can be inserted with [`insert_rows()`].
+trait Row<const N: usize> {
+    /// The table and the columns the row is inserted into.
+    const INTO: &'static str;
+
+    /// The values of the columns of the row.
+    fn values(&self) -> [&dyn ToSql; N];
+}
+
+/// A row of the `state_event` table.
+struct StateEventRow {
+    room_id: Key,
+    event_type: Key,
+    state_key: Key,
+    stripped: bool,
+    event_id: Option<Key>,
+    data: Vec<u8>,
+}
+
+impl Row<6> for StateEventRow {
+    const INTO: &'static str =
+        "state_event (room_id, event_type, state_key, stripped, event_id, data)";
+
+    fn values(&self) -> [&dyn ToSql; 6] {
+        [
+            &self.room_id,
+            &self.event_type,
+            &self.state_key,
+            &self.stripped,
+            &self.event_id,
+            &self.data,
+        ]
+    }
+}
+
+/// A row of the `member` table.
+struct MemberRow {
+    room_id: Key,
+    user_id: Key,
+    membership: Key,
+    stripped: bool,
+    data: Vec<u8>,
+}
+
+impl Row<5> for MemberRow {
+    const INTO: &'static str = "member (room_id, user_id, membership, stripped, data)";
+
+    fn values(&self) -> [&dyn ToSql; 5] {
+        [&self.room_id, &self.user_id, &self.membership, &self.stripped, &self.data]
+    }
+}
+
+/// A row of the `profile` table.
+struct ProfileRow {
+    room_id: Key,
+    user_id: Key,
+    data: Vec<u8>,
+}
+
+impl Row<3> for ProfileRow {
+    const INTO: &'static str = "profile (room_id, user_id, data)";
+
+    fn values(&self) -> [&dyn ToSql; 3] {
+        [&self.room_id, &self.user_id, &self.data]
+    }
+}
+
+/// The rows of the member events of rooms, inserted in batches since inserting
+/// them one by one dominates the time it takes to save rooms with many members.
+#[derive(Default)]
+struct MemberEventRows {
+    state_events: Vec<StateEventRow>,
+    members: Vec<MemberRow>,
+    profiles: Vec<ProfileRow>,
+}
+
+impl MemberEventRows {
+    /// Insert all the rows, with [`insert_rows()`].
+    fn insert(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        insert_rows(conn, &self.state_events)?;
+        insert_rows(conn, &self.members)?;
+        insert_rows(conn, &self.profiles)
+    }
+}
+
+/// Insert the given rows, with multi-row statements.
+///
+/// The rows are inserted in order, so a row replaces the previous rows with the
+/// same primary key.
+fn insert_rows<R: Row<N>, const N: usize>(
+    conn: &rusqlite::Connection,
+    rows: &[R],
+) -> rusqlite::Result<()> {
+    let chunk_size = MAX_STATEMENT_VARIABLES / N;
+    let row_placeholders = format!("({})", vec!["?"; N].join(", "));
+
+    for chunk in rows.chunks(chunk_size) {
+        let placeholders = vec![row_placeholders.as_str(); chunk.len()].join(", ");
+        let sql = format!("INSERT OR REPLACE INTO {} VALUES {placeholders}", R::INTO);
+        let params = rusqlite::params_from_iter(chunk.iter().flat_map(|row| row.values()));
+
+        // Only cache the statement of full chunks, the last chunk has a different
+        // number of rows every time.
+        if chunk.len() == chunk_size {
+            conn.prepare_cached(&sql)?.execute(params)?;
+        } else {
+            conn.prepare(&sql)?.execute(params)?;
+        }
+    }
+
+    Ok(())
+}
+
 trait SqliteConnectionStateStoreExt {
     fn set_kv_blob(&self, key: &[u8], value: &[u8]) -> rusqlite::Result<()>;
 
@@ -483,17 +726,8 @@ trait SqliteConnectionStateStoreExt {
         stripped: Option<bool>,
     ) -> rusqlite::Result<()>;
 
-    fn set_member(
-        &self,
-        room_id: &[u8],
-        user_id: &[u8],
-        membership: &[u8],
-        stripped: bool,
-        data: &[u8],
-    ) -> rusqlite::Result<()>;
     fn remove_room_members(&self, room_id: &[u8], stripped: Option<bool>) -> rusqlite::Result<()>;
 
-    fn set_profile(&self, room_id: &[u8], user_id: &[u8], data: &[u8]) -> rusqlite::Result<()>;
     fn remove_room_profiles(&self, room_id: &[u8]) -> rusqlite::Result<()>;
     fn remove_room_profile(&self, room_id: &[u8], user_id: &[u8]) -> rusqlite::Result<()>;
 
@@ -624,23 +858,6 @@ impl SqliteConnectionStateStoreExt for rusqlite::Connection {
         Ok(())
     }
 
-    fn set_member(
-        &self,
-        room_id: &[u8],
-        user_id: &[u8],
-        membership: &[u8],
-        stripped: bool,
-        data: &[u8],
-    ) -> rusqlite::Result<()> {
-        self.prepare_cached(
-            "INSERT OR REPLACE
-             INTO member (room_id, user_id, membership, stripped, data)
-             VALUES (?, ?, ?, ?, ?)",
-        )?
-        .execute((room_id, user_id, membership, stripped, data))?;
-        Ok(())
-    }
-
     /// Remove members for the given room.
     ///
     /// If `stripped` is `Some()`, only removes members for the given stripped
@@ -655,16 +872,6 @@ impl SqliteConnectionStateStoreExt for rusqlite::Connection {
         Ok(())
     }
 
-    fn set_profile(&self, room_id: &[u8], user_id: &[u8], data: &[u8]) -> rusqlite::Result<()> {
-        self.prepare_cached(
-            "INSERT OR REPLACE
-             INTO profile (room_id, user_id, data)
-             VALUES (?, ?, ?)",
-        )?
-        .execute((room_id, user_id, data))?;
-        Ok(())
-    }
-
     fn remove_room_profiles(&self, room_id: &[u8]) -> rusqlite::Result<()> {
         self.prepare("DELETE FROM profile WHERE room_id = ?")?.execute((room_id,))?;
         Ok(())
@@ -1001,6 +1208,33 @@ impl StateStore for SqliteStateStore {
                     StateStoreDataKey::SeenKnockRequests(_) => {
                         StateStoreDataValue::SeenKnockRequests(self.deserialize_value(&data)?)
                     }
+                    StateStoreDataKey::UserProfile(_) => {
+                        StateStoreDataValue::UserProfile(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::UploadedFilter(_) => {
+                        StateStoreDataValue::UploadedFilter(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::UrlPreview(_) => {
+                        StateStoreDataValue::UrlPreview(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::ContactActivity => {
+                        StateStoreDataValue::ContactActivity(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::WellKnown => {
+                        StateStoreDataValue::WellKnown(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::RecoveryKeyConfirmation => {
+                        StateStoreDataValue::RecoveryKeyConfirmation(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::TemporaryRoomMutes => {
+                        StateStoreDataValue::TemporaryRoomMutes(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::MediaPolicy => {
+                        StateStoreDataValue::MediaPolicy(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::CachedUrlPreviews => {
+                        StateStoreDataValue::CachedUrlPreviews(self.deserialize_value(&data)?)
+                    }
                 })
             })
             .transpose()
@@ -1040,6 +1274,39 @@ impl StateStore for SqliteStateStore {
                     .into_seen_knock_requests()
                     .expect("Session data is not a set of seen knock request ids"),
             )?,
+            StateStoreDataKey::UserProfile(_) => self.serialize_value(
+                &value.into_user_profile().expect("Session data not a user profile"),
+            )?,
+            StateStoreDataKey::UploadedFilter(_) => self.serialize_value(
+                &value.into_uploaded_filter().expect("Session data not an uploaded filter"),
+            )?,
+            StateStoreDataKey::UrlPreview(_) => self.serialize_value(
+                &value.into_url_preview().expect("Session data not a URL preview"),
+            )?,
+            StateStoreDataKey::ContactActivity => self.serialize_value(
+                &value.into_contact_activity().expect("Session data not the activity of users"),
+            )?,
+            StateStoreDataKey::WellKnown => self.serialize_value(
+                &value.into_well_known().expect("Session data not a well-known document"),
+            )?,
+            StateStoreDataKey::RecoveryKeyConfirmation => self.serialize_value(
+                &value
+                    .into_recovery_key_confirmation()
+                    .expect("Session data not a recovery key confirmation"),
+            )?,
+            StateStoreDataKey::TemporaryRoomMutes => self.serialize_value(
+                &value
+                    .into_temporary_room_mutes()
+                    .expect("Session data not the temporary room mutes"),
+            )?,
+            StateStoreDataKey::MediaPolicy => self.serialize_value(
+                &value.into_media_policy().expect("Session data not a media policy"),
+            )?,
+            StateStoreDataKey::CachedUrlPreviews => self.serialize_value(
+                &value
+                    .into_cached_url_previews()
+                    .expect("Session data not the cached URL previews"),
+            )?,
         };
 
         self.acquire()
@@ -1052,6 +1319,36 @@ impl StateStore for SqliteStateStore {
         self.acquire().await?.delete_kv_blob(self.encode_state_store_data_key(key)).await
     }
 
+    async fn save_room_members(
+        &self,
+        room_id: &RoomId,
+        members: BTreeMap<OwnedUserId, Raw<SyncRoomMemberEvent>>,
+        profiles: BTreeMap<OwnedUserId, MinimalRoomMemberEvent>,
+    ) -> Result<()> {
+        let room_id = room_id.to_owned();
+        let this = self.clone();
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                let mut rows = MemberEventRows::default();
+
+                for (user_id, raw_member_event) in &members {
+                    this.collect_member_rows(
+                        &mut rows,
+                        &room_id,
+                        user_id.as_str(),
+                        raw_member_event.cast_ref(),
+                        Some(&profiles),
+                    )?;
+                }
+
+                rows.insert(txn)?;
+
+                Ok(())
+            })
+            .await
+    }
+
     async fn save_changes(&self, changes: &StateChanges) -> Result<()> {
         let changes = changes.to_owned();
         let this = self.clone();
@@ -1122,70 +1419,40 @@ impl StateStore for SqliteStateStore {
                     }
                 }
 
+                // Collect the rows to insert them in batches, which is much faster than
+                // inserting them one by one in rooms with many members.
+                let mut rows = MemberEventRows::default();
+
                 for (room_id, state_event_types) in state {
                     let profiles = profiles.get(&room_id);
-                    let encoded_room_id = this.encode_key(keys::STATE_EVENT, &room_id);
 
                     for (event_type, state_events) in state_event_types {
-                        let encoded_event_type =
-                            this.encode_key(keys::STATE_EVENT, event_type.to_string());
-
                         for (state_key, raw_state_event) in state_events {
-                            let encoded_state_key = this.encode_key(keys::STATE_EVENT, &state_key);
-                            let data = this.serialize_json(&raw_state_event)?;
-
-                            let event_id: Option<String> =
-                                raw_state_event.get_field("event_id").ok().flatten();
-                            let encoded_event_id =
-                                event_id.as_ref().map(|e| this.encode_key(keys::STATE_EVENT, e));
-
-                            txn.set_state_event(
-                                &encoded_room_id,
-                                &encoded_event_type,
-                                &encoded_state_key,
-                                false,
-                                encoded_event_id.as_deref(),
-                                &data,
-                            )?;
-
                             if event_type == StateEventType::RoomMember {
-                                let member_event = match raw_state_event
-                                    .deserialize_as::<SyncRoomMemberEvent>()
-                                {
-                                    Ok(ev) => ev,
-                                    Err(e) => {
-                                        debug!(event_id, "Failed to deserialize member event: {e}");
-                                        continue;
-                                    }
-                                };
-
-                                let encoded_room_id = this.encode_key(keys::MEMBER, &room_id);
-                                let user_id = this.encode_key(keys::MEMBER, &state_key);
-                                let membership = this
-                                    .encode_key(keys::MEMBER, member_event.membership().as_str());
-                                let data = this.serialize_value(&state_key)?;
-
-                                txn.set_member(
-                                    &encoded_room_id,
-                                    &user_id,
-                                    &membership,
-                                    false,
-                                    &data,
+                                this.collect_member_rows(
+                                    &mut rows,
+                                    &room_id,
+                                    &state_key,
+                                    &raw_state_event,
+                                    profiles,
                                 )?;
-
-                                if let Some(profile) =
-                                    profiles.and_then(|p| p.get(member_event.state_key()))
-                                {
-                                    let room_id = this.encode_key(keys::PROFILE, &room_id);
-                                    let user_id = this.encode_key(keys::PROFILE, &state_key);
-                                    let data = this.serialize_json(&profile)?;
-                                    txn.set_profile(&room_id, &user_id, &data)?;
-                                }
+                            } else {
+                                rows.state_events.push(this.state_event_row(
+                                    &room_id,
+                                    &event_type,
+                                    &state_key,
+                                    &raw_state_event,
+                                )?);
                             }
                         }
                     }
                 }
 
+                rows.insert(txn)?;
+
+                let mut state_events = Vec::new();
+                let mut members = Vec::new();
+
                 for (room_id, stripped_state_event_types) in stripped_state {
                     let encoded_room_id = this.encode_key(keys::STATE_EVENT, &room_id);
 
@@ -1196,14 +1463,14 @@ impl StateStore for SqliteStateStore {
                         for (state_key, raw_stripped_state_event) in stripped_state_events {
                             let encoded_state_key = this.encode_key(keys::STATE_EVENT, &state_key);
                             let data = this.serialize_json(&raw_stripped_state_event)?;
-                            txn.set_state_event(
-                                &encoded_room_id,
-                                &encoded_event_type,
-                                &encoded_state_key,
-                                true,
-                                None,
-                                &data,
-                            )?;
+                            state_events.push(StateEventRow {
+                                room_id: encoded_room_id.clone(),
+                                event_type: encoded_event_type.clone(),
+                                state_key: encoded_state_key,
+                                stripped: true,
+                                event_id: None,
+                                data,
+                            });
 
                             if event_type == StateEventType::RoomMember {
                                 let member_event = match raw_stripped_state_event
@@ -1216,20 +1483,24 @@ impl StateStore for SqliteStateStore {
                                     }
                                 };
 
-                                let room_id = this.encode_key(keys::MEMBER, &room_id);
-                                let user_id = this.encode_key(keys::MEMBER, &state_key);
-                                let membership = this.encode_key(
-                                    keys::MEMBER,
-                                    member_event.content.membership.as_str(),
-                                );
-                                let data = this.serialize_value(&state_key)?;
-
-                                txn.set_member(&room_id, &user_id, &membership, true, &data)?;
+                                members.push(MemberRow {
+                                    room_id: this.encode_key(keys::MEMBER, &room_id),
+                                    user_id: this.encode_key(keys::MEMBER, &state_key),
+                                    membership: this.encode_key(
+                                        keys::MEMBER,
+                                        member_event.content.membership.as_str(),
+                                    ),
+                                    stripped: true,
+                                    data: this.serialize_value(&state_key)?,
+                                });
                             }
                         }
                     }
                 }
 
+                insert_rows(txn, &state_events)?;
+                insert_rows(txn, &members)?;
+
                 for (room_id, receipt_event) in receipts {
                     let room_id = this.encode_key(keys::RECEIPT, room_id);
 
@@ -2137,10 +2408,11 @@ mod migration_tests {
     use serde_json::json;
     use tempfile::{tempdir, TempDir};
 
-    use super::{create_pool, init, keys, SqliteStateStore};
+    use super::{create_pool, init, keys, SqliteStateStore, DATABASE_NAME, DATABASE_VERSION};
     use crate::{
         error::{Error, Result},
         utils::{SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt},
+        MigrationConfig,
     };
 
     static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
@@ -2209,6 +2481,56 @@ mod migration_tests {
         })
     }
 
+    #[async_test]
+    pub async fn test_migration_report() {
+        let path = new_path();
+        create_fake_db(&path, 7).await.unwrap();
+
+        let report = SqliteStateStore::migration_report(&path).await.unwrap();
+        assert_eq!(report.current_version, 7);
+        assert_eq!(report.target_version, DATABASE_VERSION);
+        assert!(report.is_needed());
+        assert!(report.is_intact());
+        assert!(report.database_size > 0);
+        assert!(report.estimated_duration() > std::time::Duration::ZERO);
+
+        // The dry run doesn't migrate the database.
+        let report = SqliteStateStore::migration_report(&path).await.unwrap();
+        assert_eq!(report.current_version, 7);
+
+        let _store = SqliteStateStore::open(&path, Some(SECRET)).await.unwrap();
+
+        let report = SqliteStateStore::migration_report(&path).await.unwrap();
+        assert_eq!(report.current_version, DATABASE_VERSION);
+        assert!(!report.is_needed());
+        assert_eq!(report.estimated_duration(), std::time::Duration::ZERO);
+    }
+
+    #[async_test]
+    pub async fn test_backup_before_migration() {
+        let path = new_path();
+        let backup_dir = new_path();
+        create_fake_db(&path, 7).await.unwrap();
+
+        let config = MigrationConfig::new().backup_dir(&backup_dir).check_integrity(true);
+        let store = SqliteStateStore::open_with_config(&path, Some(SECRET), &config).await.unwrap();
+        assert_eq!(store.pool.get().await.unwrap().db_version().await.unwrap(), DATABASE_VERSION);
+
+        // The backup has the version from before the migration.
+        let backup_path = backup_dir.join(format!("{DATABASE_NAME}.v7.bak"));
+        let conn = rusqlite::Connection::open(&backup_path).unwrap();
+        let version: Vec<u8> = conn
+            .query_row("SELECT value FROM kv WHERE key = 'version'", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, [7]);
+
+        // An up-to-date database isn't backed up again.
+        std::fs::remove_file(&backup_path).unwrap();
+        drop(store);
+        SqliteStateStore::open_with_config(&path, Some(SECRET), &config).await.unwrap();
+        assert!(!backup_path.exists());
+    }
+
     #[async_test]
     pub async fn test_migrating_v1_to_v2() {
         let path = new_path();
@@ -13,11 +13,12 @@
 // limitations under the License.
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use futures_util::{pin_mut, StreamExt as _};
+use futures_util::{future::join_all, pin_mut, StreamExt as _};
 use matrix_sdk::{room::Room, Client, ClientBuildError, SlidingSyncList, SlidingSyncMode};
 use matrix_sdk_base::{
     deserialized_responses::TimelineEvent, sliding_sync::http, RoomState, StoreError,
@@ -36,10 +37,10 @@ use ruma::{
     html::RemoveReplyFallback,
     push::Action,
     serde::Raw,
-    uint, EventId, OwnedEventId, RoomId, UserId,
+    uint, EventId, OwnedEventId, OwnedRoomId, RoomId, UserId,
 };
 use thiserror::Error;
-use tokio::sync::Mutex as AsyncMutex;
+use tokio::{sync::Mutex as AsyncMutex, time::Instant};
 use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
@@ -147,6 +148,70 @@ impl NotificationClient {
         }
     }
 
+    /// Fetches the content of several notifications at once, within the given
+    /// time budget.
+    ///
+    /// This is meant for processes with a hard time limit, like the
+    /// Notification Service Extension on iOS. All the events are fetched with
+    /// a single short-lived sliding sync, and the ones it can't find are then
+    /// fetched concurrently with `/context` queries, like in
+    /// [`Self::get_notification`].
+    ///
+    /// The results are in the same order as the requests. The notifications
+    /// that couldn't be resolved before the time budget ran out are marked as
+    /// [`NotificationBatchStatus::TimedOut`]; the cross-process store lock,
+    /// if it was taken to decrypt an event, is then released.
+    #[instrument(skip_all, fields(num_requests = requests.len()))]
+    pub async fn get_notifications(
+        &self,
+        requests: &[NotificationRequest],
+        time_budget: Duration,
+    ) -> Vec<NotificationBatchStatus> {
+        let deadline = Instant::now() + time_budget;
+
+        let targets = requests.iter().map(|r| (&*r.room_id, &*r.event_id)).collect::<Vec<_>>();
+
+        let mut raw_events = match self.try_sliding_sync(&targets, Some(deadline)).await {
+            Ok(raw_events) => raw_events,
+            Err(err) => {
+                // The `/context` queries may still succeed.
+                warn!("Couldn't run the notifications sliding sync: {err}");
+                BTreeMap::new()
+            }
+        };
+
+        let futures = requests.iter().map(|request| {
+            let raw_event = raw_events.remove(&request.event_id);
+
+            async move {
+                let result = tokio::time::timeout_at(deadline, async {
+                    if let Some(raw_event) = raw_event {
+                        match self.process_raw_notification(&request.room_id, raw_event).await? {
+                            NotificationStatus::Event(item) => return Ok(Some(item)),
+                            NotificationStatus::EventFilteredOut => return Ok(None),
+                            NotificationStatus::EventNotFound => {}
+                        }
+                    }
+
+                    self.get_notification_with_context(&request.room_id, &request.event_id).await
+                })
+                .await;
+
+                match result {
+                    Ok(Ok(Some(item))) => NotificationBatchStatus::Event(item),
+                    Ok(Ok(None)) => NotificationBatchStatus::EventFilteredOut,
+                    Ok(Err(err)) => NotificationBatchStatus::Error(err),
+                    Err(_) => {
+                        debug!(event_id = ?request.event_id, "Time budget exhausted");
+                        NotificationBatchStatus::TimedOut
+                    }
+                }
+            }
+        });
+
+        join_all(futures).await
+    }
+
     /// Run an encryption sync loop, in case an event is still encrypted.
     ///
     /// Will return true if and only:
@@ -168,7 +233,26 @@ impl NotificationClient {
         }
 
         // Serialize calls to this function.
-        let _guard = self.encryption_sync_mutex.lock().await;
+        let _guard = match self.encryption_sync_mutex.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => {
+                let guard = self.encryption_sync_mutex.lock().await;
+
+                // An encryption sync ran for another notification in the meantime, and may
+                // have received the room key for this event too.
+                if let Ok(new_event) = room.decrypt_event(raw_event.cast_ref()).await {
+                    if !matches!(
+                        new_event.kind,
+                        matrix_sdk::deserialized_responses::TimelineEventKind::UnableToDecrypt { .. }
+                    ) {
+                        trace!("Event decrypted by a previous encryption sync");
+                        return Ok(Some(new_event));
+                    }
+                }
+
+                guard
+            }
+        };
 
         // The message is still encrypted, and the client is configured to retry
         // decryption.
@@ -288,17 +372,17 @@ impl NotificationClient {
         }
     }
 
-    /// Try to run a sliding sync (without encryption) to retrieve the event
-    /// from the notification.
+    /// Try to run a sliding sync (without encryption) to retrieve the events
+    /// from the notifications.
     ///
-    /// The event can either be:
+    /// Each event can either be:
     /// - an invite event,
     /// - or a non-invite event.
     ///
     /// In case it's a non-invite event, it's rather easy: we'll request
     /// explicit state that'll be useful for building the
-    /// `NotificationItem`, and subscribe to the room which the notification
-    /// relates to.
+    /// `NotificationItem`, and subscribe to the rooms which the notifications
+    /// relate to.
     ///
     /// In case it's an invite-event, it's trickier because the stripped event
     /// may not contain the event id, so we can't just match on it. Rather,
@@ -306,95 +390,120 @@ impl NotificationClient {
     /// match the current user and are invites), and if the SDK concludes the
     /// room was in the invited state, and we didn't find the event by id,
     /// *then* we'll use that stripped room member event.
+    ///
+    /// If a deadline is given, the sync stops when it's reached, and the
+    /// events found so far are returned.
     #[instrument(skip_all)]
     async fn try_sliding_sync(
         &self,
-        room_id: &RoomId,
-        event_id: &EventId,
-    ) -> Result<Option<RawNotificationEvent>, Error> {
+        targets: &[(&RoomId, &EventId)],
+        deadline: Option<Instant>,
+    ) -> Result<BTreeMap<OwnedEventId, RawNotificationEvent>, Error> {
         // Serialize all the calls to this method by taking a lock at the beginning,
         // that will be dropped later.
         let _guard = self.notification_sync_mutex.lock().await;
 
-        // Set up a sliding sync that only subscribes to the room that had the
-        // notification, so we can figure out the full event and associated
+        // Set up a sliding sync that only subscribes to the rooms that had the
+        // notifications, so we can figure out the full events and associated
         // information.
 
-        let raw_notification = Arc::new(Mutex::new(None));
+        let raw_notifications = Arc::new(Mutex::new(BTreeMap::new()));
 
-        let handler_raw_notification = raw_notification.clone();
-        let target_event_id = event_id.to_owned();
+        let handler_raw_notifications = raw_notifications.clone();
+        let target_event_ids = Arc::new(
+            targets.iter().map(|(_, event_id)| (*event_id).to_owned()).collect::<BTreeSet<_>>(),
+        );
 
+        let handler_target_event_ids = target_event_ids.clone();
         let timeline_event_handler =
-            self.client.add_event_handler(move |raw: Raw<AnySyncTimelineEvent>| async move {
-                match raw.get_field::<OwnedEventId>("event_id") {
-                    Ok(Some(event_id)) => {
-                        if event_id == target_event_id {
-                            // found it! There shouldn't be a previous event before, but if there
-                            // is, that should be ok to just replace it.
-                            *handler_raw_notification.lock().unwrap() =
-                                Some(RawNotificationEvent::Timeline(raw));
+            self.client.add_event_handler(move |raw: Raw<AnySyncTimelineEvent>| {
+                let raw_notifications = handler_raw_notifications.clone();
+                let target_event_ids = handler_target_event_ids.clone();
+
+                async move {
+                    match raw.get_field::<OwnedEventId>("event_id") {
+                        Ok(Some(event_id)) => {
+                            if target_event_ids.contains(&event_id) {
+                                // found it! There shouldn't be a previous event before, but if
+                                // there is, that should be ok to just replace it.
+                                raw_notifications
+                                    .lock()
+                                    .unwrap()
+                                    .insert(event_id, RawNotificationEvent::Timeline(raw));
+                            }
+                        }
+                        Ok(None) => {
+                            warn!("a sync event had no event id");
+                        }
+                        Err(err) => {
+                            warn!("a sync event id couldn't be decoded: {err}");
                         }
-                    }
-                    Ok(None) => {
-                        warn!("a sync event had no event id");
-                    }
-                    Err(err) => {
-                        warn!("a sync event id couldn't be decoded: {err}");
                     }
                 }
             });
 
-        // We'll only use this event if the room is in the invited state.
-        let raw_invite = Arc::new(Mutex::new(None));
+        // We'll only use these events if the rooms are in the invited state.
+        let raw_invites = Arc::new(Mutex::new(BTreeMap::new()));
 
-        let target_event_id = event_id.to_owned();
         let user_id = self.client.user_id().unwrap().to_owned();
-        let handler_raw_invite = raw_invite.clone();
-        let handler_raw_notification = raw_notification.clone();
+        let handler_raw_invites = raw_invites.clone();
+        let handler_raw_notifications = raw_notifications.clone();
+        let handler_target_event_ids = target_event_ids.clone();
         let stripped_member_handler =
-            self.client.add_event_handler(move |raw: Raw<StrippedRoomMemberEvent>| async move {
-                let deserialized = match raw.deserialize() {
-                    Ok(d) => d,
-                    Err(err) => {
-                        warn!("failed to deserialize raw stripped room member event: {err}");
-                        return;
-                    }
-                };
-
-                trace!("received a stripped room member event");
-
-                // Try to match the event by event_id, as it's the most precise. In theory, we
-                // shouldn't receive it, so that's a first attempt.
-                match raw.get_field::<OwnedEventId>("event_id") {
-                    Ok(Some(event_id)) => {
-                        if event_id == target_event_id {
-                            // found it! There shouldn't be a previous event before, but if there
-                            // is, that should be ok to just replace it.
-                            *handler_raw_notification.lock().unwrap() =
-                                Some(RawNotificationEvent::Invite(raw));
+            self.client.add_event_handler(move |raw: Raw<StrippedRoomMemberEvent>, room: Room| {
+                let raw_invites = handler_raw_invites.clone();
+                let raw_notifications = handler_raw_notifications.clone();
+                let target_event_ids = handler_target_event_ids.clone();
+                let user_id = user_id.clone();
+
+                async move {
+                    let deserialized = match raw.deserialize() {
+                        Ok(d) => d,
+                        Err(err) => {
+                            warn!("failed to deserialize raw stripped room member event: {err}");
                             return;
                         }
+                    };
+
+                    trace!("received a stripped room member event");
+
+                    // Try to match the event by event_id, as it's the most precise. In theory, we
+                    // shouldn't receive it, so that's a first attempt.
+                    match raw.get_field::<OwnedEventId>("event_id") {
+                        Ok(Some(event_id)) => {
+                            if target_event_ids.contains(&event_id) {
+                                // found it! There shouldn't be a previous event before, but if
+                                // there is, that should be ok to just replace it.
+                                raw_notifications
+                                    .lock()
+                                    .unwrap()
+                                    .insert(event_id, RawNotificationEvent::Invite(raw));
+                                return;
+                            }
+                        }
+                        Ok(None) => {
+                            debug!("a room member event had no id");
+                        }
+                        Err(err) => {
+                            debug!("a room member event id couldn't be decoded: {err}");
+                        }
                     }
-                    Ok(None) => {
-                        debug!("a room member event had no id");
-                    }
-                    Err(err) => {
-                        debug!("a room member event id couldn't be decoded: {err}");
-                    }
-                }
 
-                // Try to match the event by membership and state_key for the current user.
-                if deserialized.content.membership == MembershipState::Invite
-                    && deserialized.state_key == user_id
-                {
-                    debug!("found an invite event for the current user");
-                    // This could be it! There might be several of these following each other, so
-                    // assume it's the latest one (in sync ordering), and override a previous one if
-                    // present.
-                    *handler_raw_invite.lock().unwrap() = Some(RawNotificationEvent::Invite(raw));
-                } else {
-                    debug!("not an invite event, or not for the current user");
+                    // Try to match the event by membership and state_key for the current user.
+                    if deserialized.content.membership == MembershipState::Invite
+                        && deserialized.state_key == user_id
+                    {
+                        debug!("found an invite event for the current user");
+                        // This could be it! There might be several of these following each other,
+                        // so assume it's the latest one (in sync ordering), and override a
+                        // previous one if present.
+                        raw_invites
+                            .lock()
+                            .unwrap()
+                            .insert(room.room_id().to_owned(), RawNotificationEvent::Invite(raw));
+                    } else {
+                        debug!("not an invite event, or not for the current user");
+                    }
                 }
             });
 
@@ -429,8 +538,10 @@ impl NotificationClient {
             .build()
             .await?;
 
+        let room_ids = targets.iter().map(|(room_id, _)| *room_id).collect::<BTreeSet<_>>();
+
         sync.subscribe_to_rooms(
-            &[room_id],
+            &room_ids.into_iter().collect::<Vec<_>>(),
             Some(assign!(http::request::RoomSubscription::default(), {
                 required_state,
                 timeline_limit: uint!(16)
@@ -444,13 +555,32 @@ impl NotificationClient {
         pin_mut!(stream);
 
         loop {
-            if stream.next().await.is_none() {
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, stream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        debug!("the time budget ran out during the sliding sync");
+                        break;
+                    }
+                },
+                None => stream.next().await,
+            };
+
+            if next.is_none() {
                 // Sliding sync aborted early.
                 break;
             }
 
-            if raw_notification.lock().unwrap().is_some() || raw_invite.lock().unwrap().is_some() {
-                // We got the event.
+            let all_found = {
+                let raw_notifications = raw_notifications.lock().unwrap();
+                let raw_invites = raw_invites.lock().unwrap();
+                targets.iter().all(|(room_id, event_id)| {
+                    raw_notifications.contains_key(*event_id) || raw_invites.contains_key(*room_id)
+                })
+            };
+
+            if all_found {
+                // We got the events.
                 break;
             }
 
@@ -464,13 +594,20 @@ impl NotificationClient {
         self.client.remove_event_handler(stripped_member_handler);
         self.client.remove_event_handler(timeline_event_handler);
 
-        let mut maybe_event = raw_notification.lock().unwrap().take();
+        let mut found_events = std::mem::take(&mut *raw_notifications.lock().unwrap());
+        let mut raw_invites = raw_invites.lock().unwrap();
+
+        for (room_id, event_id) in targets {
+            if found_events.contains_key(*event_id) {
+                continue;
+            }
 
-        if maybe_event.is_none() {
             trace!("we didn't have a non-invite event, looking for invited room now");
             if let Some(room) = self.client.get_room(room_id) {
                 if room.state() == RoomState::Invited {
-                    maybe_event = raw_invite.lock().unwrap().take();
+                    if let Some(invite) = raw_invites.remove(*room_id) {
+                        found_events.insert((*event_id).to_owned(), invite);
+                    }
                 } else {
                     debug!("the room isn't in the invited state");
                 }
@@ -479,10 +616,9 @@ impl NotificationClient {
             }
         }
 
-        let found = if maybe_event.is_some() { "" } else { "not " };
-        trace!("the notification event has been {found}found");
+        trace!("{} of {} notification events have been found", found_events.len(), targets.len());
 
-        Ok(maybe_event)
+        Ok(found_events)
     }
 
     /// Get a full notification, given a room id and event id.
@@ -494,10 +630,22 @@ impl NotificationClient {
         room_id: &RoomId,
         event_id: &EventId,
     ) -> Result<NotificationStatus, Error> {
-        let Some(mut raw_event) = self.try_sliding_sync(room_id, event_id).await? else {
+        let Some(raw_event) =
+            self.try_sliding_sync(&[(room_id, event_id)], None).await?.remove(event_id)
+        else {
             return Ok(NotificationStatus::EventNotFound);
         };
 
+        self.process_raw_notification(room_id, raw_event).await
+    }
+
+    /// Build a notification from an event found by the sliding sync, after
+    /// decrypting it if needed and checking it against the push rules.
+    async fn process_raw_notification(
+        &self,
+        room_id: &RoomId,
+        mut raw_event: RawNotificationEvent,
+    ) -> Result<NotificationStatus, Error> {
         // At this point it should have been added by the sync, if it's not, give up.
         let Some(room) = self.client.get_room(room_id) else { return Err(Error::UnknownRoom) };
 
@@ -600,6 +748,34 @@ pub enum NotificationStatus {
     EventFilteredOut,
 }
 
+/// A notification to fetch with [`NotificationClient::get_notifications`].
+#[derive(Clone, Debug)]
+pub struct NotificationRequest {
+    /// The room of the event of the notification.
+    pub room_id: OwnedRoomId,
+
+    /// The event of the notification.
+    pub event_id: OwnedEventId,
+}
+
+/// The result of fetching a notification with
+/// [`NotificationClient::get_notifications`].
+#[derive(Debug)]
+pub enum NotificationBatchStatus {
+    /// The notification was resolved.
+    Event(NotificationItem),
+
+    /// The notification has been filtered out by the user's push rules.
+    EventFilteredOut,
+
+    /// The notification couldn't be resolved; a dummy notification may be
+    /// displayed instead.
+    Error(Error),
+
+    /// The time budget ran out before the notification could be resolved.
+    TimedOut,
+}
+
 /// The Notification event as it was fetched from remote for the
 /// given `event_id`, represented as Raw but decrypted, thus only
 /// whether it is an invite or regular Timeline event has been
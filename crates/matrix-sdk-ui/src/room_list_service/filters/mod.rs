@@ -58,10 +58,12 @@ mod favourite;
 mod fuzzy_match_room_name;
 mod invite;
 mod joined;
+mod knocked;
 mod non_left;
 mod none;
 mod normalized_match_room_name;
 mod not;
+mod space;
 mod unread;
 
 #[cfg(test)]
@@ -74,6 +76,7 @@ pub use favourite::new_filter as new_filter_favourite;
 pub use fuzzy_match_room_name::new_filter as new_filter_fuzzy_match_room_name;
 pub use invite::new_filter as new_filter_invite;
 pub use joined::new_filter as new_filter_joined;
+pub use knocked::new_filter as new_filter_knocked;
 #[cfg(test)]
 use matrix_sdk::{test_utils::logged_in_client_with_server, Client, SlidingSync};
 #[cfg(test)]
@@ -84,6 +87,7 @@ pub use normalized_match_room_name::new_filter as new_filter_normalized_match_ro
 pub use not::new_filter as new_filter_not;
 #[cfg(test)]
 use ruma::RoomId;
+pub use space::new_filter as new_filter_space;
 use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 pub use unread::new_filter as new_filter_unread;
 #[cfg(test)]
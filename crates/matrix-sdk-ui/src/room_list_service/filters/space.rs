@@ -0,0 +1,78 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    super::{Room, SpaceContext},
+    Filter,
+};
+
+struct SpaceRoomMatcher<F>
+where
+    F: Fn(&Room) -> bool,
+{
+    is_in_space: F,
+}
+
+impl<F> SpaceRoomMatcher<F>
+where
+    F: Fn(&Room) -> bool,
+{
+    fn matches(&self, room: &Room) -> bool {
+        (self.is_in_space)(room)
+    }
+}
+
+/// Create a new filter that will filter out rooms that are not descendants of
+/// the space of the given context (see
+/// [`RoomListService::space_context`](super::super::RoomListService::space_context)).
+///
+/// The filter keeps the context alive, and thus its descendants up to date.
+pub fn new_filter(context: SpaceContext) -> impl Filter {
+    let matcher = SpaceRoomMatcher { is_in_space: move |room| context.contains(room.room_id()) };
+
+    move |room| -> bool { matcher.matches(room) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+
+    use super::{
+        super::{client_and_server_prelude, new_rooms},
+        *,
+    };
+
+    #[async_test]
+    async fn test_is_in_space() {
+        let (client, server, sliding_sync) = client_and_server_prelude().await;
+        let [room] = new_rooms([room_id!("!a:b.c")], &client, &server, &sliding_sync).await;
+
+        let matcher = SpaceRoomMatcher { is_in_space: |_| true };
+
+        assert!(matcher.matches(&room));
+    }
+
+    #[async_test]
+    async fn test_is_not_in_space() {
+        let (client, server, sliding_sync) = client_and_server_prelude().await;
+        let [room] = new_rooms([room_id!("!a:b.c")], &client, &server, &sliding_sync).await;
+
+        let matcher = SpaceRoomMatcher { is_in_space: |_| false };
+
+        assert!(matcher.matches(&room).not());
+    }
+}
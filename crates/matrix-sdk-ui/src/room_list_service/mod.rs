@@ -55,6 +55,7 @@ pub mod filters;
 mod room;
 mod room_list;
 pub mod sorters;
+mod space;
 mod state;
 
 use std::{sync::Arc, time::Duration};
@@ -70,9 +71,10 @@ use matrix_sdk_base::sliding_sync::http;
 pub use room::*;
 pub use room_list::*;
 use ruma::{assign, directory::RoomTypeFilter, events::StateEventType, OwnedRoomId, RoomId, UInt};
+pub use space::SpaceContext;
 pub use state::*;
 use thiserror::Error;
-use tokio::time::timeout;
+use tokio::{sync::broadcast, time::timeout};
 use tracing::debug;
 
 use crate::timeline;
@@ -101,6 +103,14 @@ const DEFAULT_REQUIRED_STATE: &[(StateEventType, &str)] = &[
 const DEFAULT_ROOM_SUBSCRIPTION_EXTRA_REQUIRED_STATE: &[(StateEventType, &str)] =
     &[(StateEventType::RoomPinnedEvents, "")];
 
+/// The `required_state` of the room subscriptions to the spaces of a
+/// [`SpaceContext`].
+const SPACE_REQUIRED_STATE: &[(StateEventType, &str)] = &[
+    (StateEventType::SpaceChild, "*"),
+    // Required to know whether a child is a subspace.
+    (StateEventType::RoomCreate, ""),
+];
+
 /// The default `timeline_limit` value when used with room subscriptions.
 const DEFAULT_ROOM_SUBSCRIPTION_TIMELINE_LIMIT: u32 = 20;
 
@@ -117,6 +127,10 @@ pub struct RoomListService {
     ///
     /// `RoomListService` is a simple state-machine.
     state_machine: StateMachine,
+
+    /// Sender of the rooms which must be filtered again by the room lists,
+    /// e.g. because they've been added to, or removed from, a space.
+    room_refresh_sender: broadcast::Sender<OwnedRoomId>,
 }
 
 impl RoomListService {
@@ -179,7 +193,12 @@ impl RoomListService {
         // Eagerly subscribe the event cache to sync responses.
         client.event_cache().subscribe()?;
 
-        Ok(Self { client, sliding_sync, state_machine: StateMachine::new() })
+        Ok(Self {
+            client,
+            sliding_sync,
+            state_machine: StateMachine::new(),
+            room_refresh_sender: broadcast::Sender::new(32),
+        })
     }
 
     /// Start to sync the room list.
@@ -366,7 +385,14 @@ impl RoomListService {
     }
 
     async fn list_for(&self, sliding_sync_list_name: &str) -> Result<RoomList, Error> {
-        RoomList::new(&self.client, &self.sliding_sync, sliding_sync_list_name, self.state()).await
+        RoomList::new(
+            &self.client,
+            &self.sliding_sync,
+            sliding_sync_list_name,
+            self.state(),
+            self.room_refresh_sender.clone(),
+        )
+        .await
     }
 
     /// Get a [`RoomList`] for all rooms.
@@ -410,6 +436,37 @@ impl RoomListService {
         self.sliding_sync.subscribe_to_rooms(room_ids, Some(settings), cancel_in_flight_request)
     }
 
+    /// Get the context of a space, to restrict the entries of a room list to
+    /// the rooms of this space, and of its subspaces, with
+    /// [`filters::new_filter_space`].
+    ///
+    /// The space and its subspaces are subscribed to, so their `m.space.child`
+    /// state events are synced even though spaces are left out of the room
+    /// lists. As rooms are added to or removed from the space, they're filtered
+    /// again by the room lists, without having to set a new filter.
+    pub async fn space_context(&self, space_id: &RoomId) -> SpaceContext {
+        let sliding_sync = self.sliding_sync.clone();
+        let subscribe_to_spaces = move |room_ids: &[&RoomId]| {
+            let settings = assign!(http::request::RoomSubscription::default(), {
+                required_state: SPACE_REQUIRED_STATE.iter().map(|(state_event, value)| {
+                    (state_event.clone(), (*value).to_owned())
+                })
+                .collect(),
+                timeline_limit: UInt::MIN,
+            });
+
+            sliding_sync.subscribe_to_rooms(room_ids, Some(settings), false);
+        };
+
+        SpaceContext::new(
+            self.client.clone(),
+            space_id.to_owned(),
+            subscribe_to_spaces,
+            self.room_refresh_sender.clone(),
+        )
+        .await
+    }
+
     #[cfg(test)]
     pub fn sliding_sync(&self) -> &SlidingSync {
         &self.sliding_sync
@@ -26,6 +26,7 @@ use matrix_sdk::{
     Client, SlidingSync, SlidingSyncList,
 };
 use matrix_sdk_base::RoomInfoNotableUpdate;
+use ruma::OwnedRoomId;
 use tokio::{
     select,
     sync::broadcast::{self, error::RecvError},
@@ -47,6 +48,7 @@ pub struct RoomList {
     sliding_sync_list: SlidingSyncList,
     loading_state: SharedObservable<RoomListLoadingState>,
     loading_state_task: JoinHandle<()>,
+    room_refresh_sender: broadcast::Sender<OwnedRoomId>,
 }
 
 impl Drop for RoomList {
@@ -61,6 +63,7 @@ impl RoomList {
         sliding_sync: &Arc<SlidingSync>,
         sliding_sync_list_name: &str,
         room_list_service_state: Subscriber<State>,
+        room_refresh_sender: broadcast::Sender<OwnedRoomId>,
     ) -> Result<Self, Error> {
         let sliding_sync_list = sliding_sync
             .on_list(sliding_sync_list_name, |list| ready(list.clone()))
@@ -110,6 +113,7 @@ impl RoomList {
                     loading_state.set(RoomListLoadingState::Loaded { maximum_number_of_rooms });
                 }
             }),
+            room_refresh_sender,
         })
     }
 
@@ -143,11 +147,17 @@ impl RoomList {
     /// call to [`RoomListDynamicEntriesController::set_filter`], the stream
     /// will yield a [`VectorDiff::Reset`] followed by any updates of the
     /// room list under that filter (until the next reset).
+    ///
+    /// The rooms hidden by the user, see [`Room::is_hidden`], are always
+    /// filtered out.
+    ///
+    /// [`Room::is_hidden`]: matrix_sdk_base::Room::is_hidden
     pub fn entries_with_dynamic_adapters(
         &self,
         page_size: usize,
     ) -> (impl Stream<Item = Vec<VectorDiff<Room>>> + '_, RoomListDynamicEntriesController) {
         let room_info_notable_update_receiver = self.client.room_info_notable_update_receiver();
+        let room_refresh_receiver = self.room_refresh_sender.subscribe();
         let list = self.sliding_sync_list.clone();
 
         let filter_fn_cell = AsyncCell::shared();
@@ -169,10 +179,10 @@ impl RoomList {
                 let (raw_values, raw_stream) = self.entries();
 
                 // Combine normal stream events with other updates from rooms
-                let merged_streams = merge_stream_and_receiver(raw_values.clone(), raw_stream, room_info_notable_update_receiver.resubscribe());
+                let merged_streams = merge_stream_and_receiver(raw_values.clone(), raw_stream, room_info_notable_update_receiver.resubscribe(), room_refresh_receiver.resubscribe());
 
                 let (values, stream) = (raw_values, merged_streams)
-                    .filter(filter_fn)
+                    .filter(move |room: &Room| !room.is_hidden() && filter_fn(room))
                     .sort_by(new_sorter_lexicographic(vec![
                         Box::new(new_sorter_recency()),
                         Box::new(new_sorter_name())
@@ -191,12 +201,13 @@ impl RoomList {
 }
 
 /// This function remembers the current state of the unfiltered room list, so it
-/// knows where all rooms are. When the receiver is triggered, a Set operation
-/// for the room position is inserted to the stream.
+/// knows where all rooms are. When one of the receivers is triggered, a Set
+/// operation for the room position is inserted to the stream.
 fn merge_stream_and_receiver(
     mut raw_current_values: Vector<Room>,
     raw_stream: impl Stream<Item = Vec<VectorDiff<Room>>>,
     mut room_info_notable_update_receiver: broadcast::Receiver<RoomInfoNotableUpdate>,
+    mut room_refresh_receiver: broadcast::Receiver<OwnedRoomId>,
 ) -> impl Stream<Item = Vec<VectorDiff<Room>>> {
     stream! {
         pin_mut!(raw_stream);
@@ -244,6 +255,28 @@ fn merge_stream_and_receiver(
                         }
                     }
                 }
+
+                room_id = room_refresh_receiver.recv() => {
+                    match room_id {
+                        Ok(room_id) => {
+                            // Emit a `VectorDiff::Set` for the specific room, so it's filtered again.
+                            if let Some(index) = raw_current_values.iter().position(|room| room.room_id() == room_id) {
+                                let room = &raw_current_values[index];
+                                yield vec![VectorDiff::Set { index, value: room.clone() }];
+                            }
+                        }
+
+                        Err(RecvError::Closed) => {
+                            error!("Cannot receive rooms to refresh because the sender has been closed");
+
+                            break;
+                        }
+
+                        Err(RecvError::Lagged(n)) => {
+                            error!(number_of_missed_updates = n, "Lag when receiving rooms to refresh");
+                        }
+                    }
+                }
             }
         }
     }
@@ -17,12 +17,16 @@
 mod lexicographic;
 mod name;
 mod recency;
+mod tags;
+mod unread;
 
 use std::cmp::Ordering;
 
 pub use lexicographic::new_sorter as new_sorter_lexicographic;
 pub use name::new_sorter as new_sorter_name;
 pub use recency::new_sorter as new_sorter_recency;
+pub use tags::new_sorter as new_sorter_tags;
+pub use unread::new_sorter as new_sorter_unread;
 
 use super::Room;
 
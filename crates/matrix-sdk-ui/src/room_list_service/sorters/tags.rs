@@ -0,0 +1,120 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use super::{Room, Sorter};
+
+/// The rank of a room according to its tags, the lowest ranks come first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TagRank {
+    Favourite,
+    Untagged,
+    LowPriority,
+}
+
+impl TagRank {
+    fn of(room: &Room) -> Self {
+        if room.is_favourite() {
+            Self::Favourite
+        } else if room.is_low_priority() {
+            Self::LowPriority
+        } else {
+            Self::Untagged
+        }
+    }
+}
+
+struct TagsMatcher<F>
+where
+    F: Fn(&Room, &Room) -> (TagRank, TagRank),
+{
+    ranks: F,
+}
+
+impl<F> TagsMatcher<F>
+where
+    F: Fn(&Room, &Room) -> (TagRank, TagRank),
+{
+    fn matches(&self, left: &Room, right: &Room) -> Ordering {
+        let (left_rank, right_rank) = (self.ranks)(left, right);
+
+        left_rank.cmp(&right_rank)
+    }
+}
+
+/// Create a new sorter that will sort two [`Room`] by their tags, i.e. the
+/// favourite rooms come first, and the low priority rooms come last.
+///
+/// The other rooms are considered equal, so this sorter is meant to be
+/// combined with other sorters with [`super::new_sorter_lexicographic`].
+pub fn new_sorter() -> impl Sorter {
+    let matcher = TagsMatcher { ranks: move |left, right| (TagRank::of(left), TagRank::of(right)) };
+
+    move |left, right| -> Ordering { matcher.matches(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+
+    use super::{
+        super::super::filters::{client_and_server_prelude, new_rooms},
+        *,
+    };
+
+    #[async_test]
+    async fn test_with_tags() {
+        let (client, server, sliding_sync) = client_and_server_prelude().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server, &sliding_sync)
+                .await;
+
+        // `room_a` is a favourite, not `room_b`.
+        {
+            let matcher =
+                TagsMatcher { ranks: |_left, _right| (TagRank::Favourite, TagRank::Untagged) };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        }
+
+        // `room_a` has a low priority, not `room_b`.
+        {
+            let matcher =
+                TagsMatcher { ranks: |_left, _right| (TagRank::LowPriority, TagRank::Untagged) };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+        }
+
+        // `room_b` is a favourite, and `room_a` has a low priority.
+        {
+            let matcher =
+                TagsMatcher { ranks: |_left, _right| (TagRank::LowPriority, TagRank::Favourite) };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+        }
+
+        // Both have the same tags.
+        {
+            let matcher =
+                TagsMatcher { ranks: |_left, _right| (TagRank::Untagged, TagRank::Untagged) };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Equal);
+        }
+
+        // Without tags, the rooms are equal.
+        assert_eq!(new_sorter()(&room_a, &room_b), Ordering::Equal);
+    }
+}
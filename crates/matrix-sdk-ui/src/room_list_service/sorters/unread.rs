@@ -0,0 +1,93 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use super::{Room, Sorter};
+
+struct UnreadMatcher<F>
+where
+    F: Fn(&Room, &Room) -> (bool, bool),
+{
+    are_marked_unread: F,
+}
+
+impl<F> UnreadMatcher<F>
+where
+    F: Fn(&Room, &Room) -> (bool, bool),
+{
+    fn matches(&self, left: &Room, right: &Room) -> Ordering {
+        let (left_is_marked_unread, right_is_marked_unread) = (self.are_marked_unread)(left, right);
+
+        // Rooms marked as unread come first.
+        right_is_marked_unread.cmp(&left_is_marked_unread)
+    }
+}
+
+/// Create a new sorter that will sort two [`Room`] by their unread flag, i.e.
+/// the rooms that the user explicitly marked as unread come first.
+///
+/// The other rooms are considered equal, so this sorter is meant to be
+/// combined with other sorters with [`super::new_sorter_lexicographic`].
+pub fn new_sorter() -> impl Sorter {
+    let matcher = UnreadMatcher {
+        are_marked_unread: move |left, right| (left.is_marked_unread(), right.is_marked_unread()),
+    };
+
+    move |left, right| -> Ordering { matcher.matches(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+
+    use super::{
+        super::super::filters::{client_and_server_prelude, new_rooms},
+        *,
+    };
+
+    #[async_test]
+    async fn test_with_unread_flags() {
+        let (client, server, sliding_sync) = client_and_server_prelude().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server, &sliding_sync)
+                .await;
+
+        // `room_a` is marked as unread, not `room_b`.
+        {
+            let matcher = UnreadMatcher { are_marked_unread: |_left, _right| (true, false) };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        }
+
+        // `room_b` is marked as unread, not `room_a`.
+        {
+            let matcher = UnreadMatcher { are_marked_unread: |_left, _right| (false, true) };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+        }
+
+        // Both or none are marked as unread.
+        {
+            let matcher = UnreadMatcher { are_marked_unread: |_left, _right| (true, true) };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Equal);
+
+            let matcher = UnreadMatcher { are_marked_unread: |_left, _right| (false, false) };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Equal);
+        }
+    }
+}
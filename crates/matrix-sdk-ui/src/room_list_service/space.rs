@@ -0,0 +1,382 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use matrix_sdk::{
+    deserialized_responses::SyncOrStrippedState,
+    executor::{spawn, JoinHandle},
+    Client,
+};
+use matrix_sdk_base::sync::RoomUpdates;
+use ruma::{
+    events::{space::child::SpaceChildEventContent, SyncStateEvent},
+    serde::Raw,
+    OwnedRoomId, RoomId,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, trace, warn};
+
+/// The rooms of a space, used to restrict a room list to the selected space,
+/// see [`RoomListService::space_context`].
+///
+/// The descendants of the space, i.e. its children and the children of its
+/// subspaces, recursively, are computed from the `m.space.child` state events
+/// known by the client, and kept up to date as new ones are received.
+///
+/// Cloning is shallow, and thus is cheap to do. The descendants stop being
+/// updated once all the clones have been dropped.
+///
+/// [`RoomListService::space_context`]: super::RoomListService::space_context
+#[derive(Clone)]
+pub struct SpaceContext {
+    inner: Arc<SpaceContextInner>,
+}
+
+struct SpaceContextInner {
+    space_id: OwnedRoomId,
+    descendants: Arc<RwLock<BTreeSet<OwnedRoomId>>>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for SpaceContextInner {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl fmt::Debug for SpaceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpaceContext")
+            .field("space_id", &self.inner.space_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SpaceContext {
+    /// Compute the descendants of the space, and spawn the task keeping them up
+    /// to date.
+    ///
+    /// `subscribe_to_spaces` is called with the space and its subspaces, so
+    /// their `m.space.child` state events are synced, and `room_refresh_sender`
+    /// receives the rooms which became, or stopped being, descendants.
+    pub(super) async fn new(
+        client: Client,
+        space_id: OwnedRoomId,
+        subscribe_to_spaces: impl Fn(&[&RoomId]) + Send + Sync + 'static,
+        room_refresh_sender: broadcast::Sender<OwnedRoomId>,
+    ) -> Self {
+        let room_updates = client.subscribe_to_all_room_updates();
+
+        let (descendants, spaces) = compute_descendants(&client, &space_id).await;
+        subscribe_to_spaces(&spaces.iter().map(|room_id| &**room_id).collect::<Vec<_>>());
+
+        let descendants = Arc::new(RwLock::new(descendants));
+
+        let task = spawn(Self::update_task(
+            client,
+            space_id.clone(),
+            spaces,
+            descendants.clone(),
+            room_updates,
+            subscribe_to_spaces,
+            room_refresh_sender,
+        ));
+
+        Self { inner: Arc::new(SpaceContextInner { space_id, descendants, task }) }
+    }
+
+    /// The ID of the space.
+    pub fn space_id(&self) -> &RoomId {
+        &self.inner.space_id
+    }
+
+    /// Whether the room is a descendant of the space.
+    pub fn contains(&self, room_id: &RoomId) -> bool {
+        self.inner.descendants.read().unwrap().contains(room_id)
+    }
+
+    /// The descendants of the space.
+    pub fn descendants(&self) -> BTreeSet<OwnedRoomId> {
+        self.inner.descendants.read().unwrap().clone()
+    }
+
+    async fn update_task(
+        client: Client,
+        space_id: OwnedRoomId,
+        mut spaces: BTreeSet<OwnedRoomId>,
+        descendants: Arc<RwLock<BTreeSet<OwnedRoomId>>>,
+        mut room_updates: broadcast::Receiver<RoomUpdates>,
+        subscribe_to_spaces: impl Fn(&[&RoomId]),
+        room_refresh_sender: broadcast::Sender<OwnedRoomId>,
+    ) {
+        loop {
+            match room_updates.recv().await {
+                Ok(updates) => {
+                    let has_space_child_changes =
+                        updates.join.iter().any(|(room_id, update)| {
+                            spaces.contains(room_id)
+                                && (update.state.iter().any(is_space_child)
+                                    || update
+                                        .timeline
+                                        .events
+                                        .iter()
+                                        .any(|event| is_space_child(event.raw())))
+                        }) || updates.leave.keys().any(|room_id| spaces.contains(room_id));
+
+                    if !has_space_child_changes {
+                        continue;
+                    }
+                }
+
+                Err(RecvError::Lagged(num_skipped)) => {
+                    // We may have missed some `m.space.child` events, compute everything again.
+                    warn!(num_skipped, "Lagged behind room updates, computing the space again");
+                }
+
+                Err(RecvError::Closed) => break,
+            }
+
+            trace!(%space_id, "Computing the descendants of the space again");
+
+            let (new_descendants, new_spaces) = compute_descendants(&client, &space_id).await;
+
+            let new_subspaces: Vec<_> = new_spaces
+                .iter()
+                .filter(|room_id| !spaces.contains(*room_id))
+                .map(|room_id| &**room_id)
+                .collect();
+            if !new_subspaces.is_empty() {
+                subscribe_to_spaces(&new_subspaces);
+            }
+
+            let changed: Vec<_> = {
+                let mut descendants = descendants.write().unwrap();
+                let changed = descendants.symmetric_difference(&new_descendants).cloned().collect();
+                *descendants = new_descendants;
+                changed
+            };
+
+            for room_id in changed {
+                // Nobody may be listening, that's fine.
+                let _ = room_refresh_sender.send(room_id);
+            }
+
+            spaces = new_spaces;
+        }
+    }
+}
+
+/// Whether the event is an `m.space.child` event.
+fn is_space_child<T>(event: &Raw<T>) -> bool {
+    event.get_field::<String>("type").ok().flatten().as_deref() == Some("m.space.child")
+}
+
+/// Compute the descendants of a space, from the `m.space.child` state events
+/// in the store.
+///
+/// Returns the descendants, and the spaces of the hierarchy, including the
+/// space itself and the rooms that may be subspaces as they aren't known yet.
+async fn compute_descendants(
+    client: &Client,
+    space_id: &RoomId,
+) -> (BTreeSet<OwnedRoomId>, BTreeSet<OwnedRoomId>) {
+    let mut descendants = BTreeSet::new();
+    let mut spaces = BTreeSet::from([space_id.to_owned()]);
+    let mut queue = VecDeque::from([space_id.to_owned()]);
+
+    while let Some(parent_id) = queue.pop_front() {
+        let Some(parent) = client.get_room(&parent_id) else {
+            continue;
+        };
+
+        let events = match parent.get_state_events_static::<SpaceChildEventContent>().await {
+            Ok(events) => events,
+            Err(err) => {
+                error!(room_id = %parent_id, "Couldn't load the children of the space: {err}");
+                continue;
+            }
+        };
+
+        for event in events {
+            let child_id = match event.deserialize() {
+                // A child without `via` has been removed from the space.
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event)))
+                    if !event.content.via.is_empty() =>
+                {
+                    event.state_key
+                }
+                Ok(SyncOrStrippedState::Stripped(event)) if !event.content.via.is_empty() => {
+                    event.state_key
+                }
+                _ => continue,
+            };
+
+            // Hierarchies may contain cycles.
+            if &*child_id == space_id || !descendants.insert(child_id.clone()) {
+                continue;
+            }
+
+            // Spaces are left out of the room lists, so the rooms which aren't known may be
+            // subspaces.
+            if client.get_room(&child_id).map_or(true, |room| room.is_space()) {
+                spaces.insert(child_id.clone());
+                queue.push_back(child_id);
+            }
+        }
+    }
+
+    (descendants, spaces)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use matrix_sdk::{assert_recv_with_timeout, test_utils::logged_in_client_with_server};
+    use matrix_sdk_test::{async_test, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder};
+    use ruma::{owned_room_id, room_id};
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn create_event(room_type: Option<&str>) -> StateTestEvent {
+        StateTestEvent::Custom(json!({
+            "content": { "creator": "@example:localhost", "type": room_type },
+            "event_id": "$create",
+            "origin_server_ts": 0,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.create",
+        }))
+    }
+
+    fn space_child_event(child_id: &str, via: Value) -> StateTestEvent {
+        StateTestEvent::Custom(json!({
+            "content": { "via": via },
+            "event_id": format!("$child-{child_id}"),
+            "origin_server_ts": 0,
+            "sender": "@example:localhost",
+            "state_key": child_id,
+            "type": "m.space.child",
+        }))
+    }
+
+    async fn sync(client: &Client, server: &MockServer, rooms: Vec<JoinedRoomBuilder>) {
+        let mut response_builder = SyncResponseBuilder::default();
+        for room in rooms {
+            response_builder.add_joined_room(room);
+        }
+
+        let _scope = Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .and(header("authorization", "Bearer 1234"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(response_builder.build_json_sync_response()),
+            )
+            .mount_as_scoped(server)
+            .await;
+
+        client.sync_once(Default::default()).await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_space_context() {
+        let (client, server) = logged_in_client_with_server().await;
+
+        let space_id = room_id!("!space:b.c");
+        let subspace_id = room_id!("!subspace:b.c");
+
+        sync(
+            &client,
+            &server,
+            vec![
+                JoinedRoomBuilder::new(space_id).add_state_bulk([
+                    create_event(Some("m.space")),
+                    space_child_event("!room:b.c", json!(["b.c"])),
+                    space_child_event("!subspace:b.c", json!(["b.c"])),
+                    space_child_event("!removed:b.c", json!([])),
+                ]),
+                JoinedRoomBuilder::new(subspace_id).add_state_bulk([
+                    create_event(Some("m.space")),
+                    space_child_event("!deep:b.c", json!(["b.c"])),
+                    // Cycles are fine.
+                    space_child_event("!space:b.c", json!(["b.c"])),
+                ]),
+                JoinedRoomBuilder::new(room_id!("!room:b.c")).add_state_event(create_event(None)),
+            ],
+        )
+        .await;
+
+        let subscribed = Arc::new(Mutex::new(BTreeSet::new()));
+        let (room_refresh_sender, mut room_refresh_receiver) = broadcast::channel(8);
+
+        let context = SpaceContext::new(
+            client.clone(),
+            space_id.to_owned(),
+            {
+                let subscribed = subscribed.clone();
+                move |room_ids: &[&RoomId]| {
+                    subscribed.lock().unwrap().extend(room_ids.iter().map(|id| (*id).to_owned()));
+                }
+            },
+            room_refresh_sender,
+        )
+        .await;
+
+        assert_eq!(
+            context.descendants(),
+            BTreeSet::from([
+                owned_room_id!("!deep:b.c"),
+                owned_room_id!("!room:b.c"),
+                owned_room_id!("!subspace:b.c"),
+            ])
+        );
+        assert!(context.contains(room_id!("!deep:b.c")));
+        assert!(!context.contains(room_id!("!removed:b.c")));
+
+        // The spaces are subscribed to, as well as the unknown rooms which may be
+        // subspaces.
+        assert_eq!(
+            *subscribed.lock().unwrap(),
+            BTreeSet::from([
+                owned_room_id!("!deep:b.c"),
+                owned_room_id!("!space:b.c"),
+                owned_room_id!("!subspace:b.c"),
+            ])
+        );
+
+        // A room is removed from the space.
+        sync(
+            &client,
+            &server,
+            vec![JoinedRoomBuilder::new(space_id)
+                .add_state_event(space_child_event("!room:b.c", json!([])))],
+        )
+        .await;
+
+        assert_eq!(assert_recv_with_timeout!(room_refresh_receiver, 1000), "!room:b.c");
+        assert!(!context.contains(room_id!("!room:b.c")));
+        assert!(context.contains(room_id!("!deep:b.c")));
+    }
+}
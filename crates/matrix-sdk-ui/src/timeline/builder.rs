@@ -87,8 +87,8 @@ impl TimelineBuilder {
         self
     }
 
-    /// Chose when to insert the date separators, either in between each day
-    /// or each month.
+    /// Chose when to insert the date separators, either in between each day,
+    /// each month, or each custom group of events, or not at all.
     pub fn with_date_divider_mode(mut self, mode: DateDividerMode) -> Self {
         self.settings.date_divider_mode = mode;
         self
@@ -101,6 +101,18 @@ impl TimelineBuilder {
         self
     }
 
+    /// Choose whether to insert a [`VirtualTimelineItem::ReadMarker`] item
+    /// after the fully-read event, when the fully-read marker is tracked.
+    ///
+    /// This is enabled by default. The read receipts are still tracked when
+    /// it's disabled.
+    ///
+    /// [`VirtualTimelineItem::ReadMarker`]: super::VirtualTimelineItem::ReadMarker
+    pub fn with_read_marker_item(mut self, enabled: bool) -> Self {
+        self.settings.add_read_marker_item = enabled;
+        self
+    }
+
     /// Use the given filter to choose whether to add events to the timeline.
     ///
     /// # Arguments
@@ -59,8 +59,8 @@ pub(super) use self::{
         ObservableItemsTransactionEntry,
     },
     state::{
-        FullEventMeta, PendingEdit, PendingEditKind, TimelineMetadata, TimelineNewItemPosition,
-        TimelineState, TimelineStateTransaction,
+        FullEventMeta, LocalEdit, PendingEdit, PendingEditKind, TimelineMetadata,
+        TimelineNewItemPosition, TimelineState, TimelineStateTransaction,
     },
 };
 use super::{
@@ -80,6 +80,7 @@ use crate::{
         event_item::EventTimelineItemKind,
         pinned_events_loader::{PinnedEventsLoader, PinnedEventsLoaderError},
         reactions::FullReactionKey,
+        threaded_events_loader::ThreadedEventsLoader,
         TimelineEventFilterFn,
     },
     unable_to_decrypt_hook::UtdHookManager,
@@ -109,6 +110,15 @@ enum TimelineFocusData<P: RoomDataProvider> {
     PinnedEvents {
         loader: PinnedEventsLoader,
     },
+
+    /// The timeline is focused on a thread, and receives the new threaded
+    /// events from the sync.
+    Thread {
+        /// The loader of the events in the thread.
+        loader: ThreadedEventsLoader,
+        /// Number of threaded events to request for the first request.
+        num_events: u16,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -142,6 +152,10 @@ pub(super) struct TimelineSettings {
 
     /// Should the timeline items be grouped by day or month?
     pub(super) date_divider_mode: DateDividerMode,
+
+    /// Should a read marker item be inserted after the fully-read event, when
+    /// tracking read receipts?
+    pub(super) add_read_marker_item: bool,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -150,6 +164,8 @@ impl fmt::Debug for TimelineSettings {
         f.debug_struct("TimelineSettings")
             .field("track_read_receipts", &self.track_read_receipts)
             .field("add_failed_to_parse", &self.add_failed_to_parse)
+            .field("date_divider_mode", &self.date_divider_mode)
+            .field("add_read_marker_item", &self.add_read_marker_item)
             .finish_non_exhaustive()
     }
 }
@@ -161,15 +177,17 @@ impl Default for TimelineSettings {
             event_filter: Arc::new(default_event_filter),
             add_failed_to_parse: true,
             date_divider_mode: DateDividerMode::Daily,
+            add_read_marker_item: true,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum TimelineFocusKind {
     Live,
     Event,
     PinnedEvents,
+    Thread { root_event_id: OwnedEventId },
 }
 
 /// The default event filter for
@@ -280,6 +298,17 @@ impl<P: RoomDataProvider> TimelineController<P> {
                 },
                 TimelineFocusKind::PinnedEvents,
             ),
+
+            TimelineFocus::Thread { root_event_id, num_events } => (
+                TimelineFocusData::Thread {
+                    loader: ThreadedEventsLoader::new(
+                        Arc::new(room_data_provider.clone()),
+                        root_event_id.clone(),
+                    ),
+                    num_events,
+                },
+                TimelineFocusKind::Thread { root_event_id },
+            ),
         };
 
         let state = TimelineState::new(
@@ -363,6 +392,28 @@ impl<P: RoomDataProvider> TimelineController<P> {
 
                 Ok(has_events)
             }
+
+            TimelineFocusData::Thread { loader, num_events } => {
+                // Load the latest events of the thread, and the root if the thread is short
+                // enough.
+                let (loaded_events, _) = loader
+                    .paginate_backwards(*num_events)
+                    .await
+                    .map_err(PaginationError::Paginator)?;
+
+                drop(focus_guard);
+
+                let has_events = !loaded_events.is_empty();
+
+                // The loaded events are in reverse chronological order.
+                self.replace_with_initial_remote_events(
+                    loaded_events.into_iter().rev(),
+                    RemoteEventOrigin::Pagination,
+                )
+                .await;
+
+                Ok(has_events)
+            }
         }
     }
 
@@ -414,23 +465,29 @@ impl<P: RoomDataProvider> TimelineController<P> {
         &self,
         num_events: u16,
     ) -> Result<bool, PaginationError> {
-        let pagination = match &*self.focus.read().await {
+        let (events, hit_end_of_timeline) = match &*self.focus.read().await {
             TimelineFocusData::Live | TimelineFocusData::PinnedEvents { .. } => {
                 return Err(PaginationError::NotEventFocusMode)
             }
-            TimelineFocusData::Event { paginator, .. } => paginator
-                .paginate_backward(num_events.into())
-                .await
-                .map_err(PaginationError::Paginator)?,
+            TimelineFocusData::Event { paginator, .. } => {
+                let pagination = paginator
+                    .paginate_backward(num_events.into())
+                    .await
+                    .map_err(PaginationError::Paginator)?;
+                (pagination.events, pagination.hit_end_of_timeline)
+            }
+            TimelineFocusData::Thread { loader, .. } => {
+                loader.paginate_backwards(num_events).await.map_err(PaginationError::Paginator)?
+            }
         };
 
         self.add_events_at(
-            pagination.events.into_iter(),
+            events.into_iter(),
             TimelineNewItemPosition::Start { origin: RemoteEventOrigin::Pagination },
         )
         .await;
 
-        Ok(pagination.hit_end_of_timeline)
+        Ok(hit_end_of_timeline)
     }
 
     /// Run a forward pagination (in focused mode) and append the results to
@@ -442,9 +499,9 @@ impl<P: RoomDataProvider> TimelineController<P> {
         num_events: u16,
     ) -> Result<bool, PaginationError> {
         let pagination = match &*self.focus.read().await {
-            TimelineFocusData::Live | TimelineFocusData::PinnedEvents { .. } => {
-                return Err(PaginationError::NotEventFocusMode)
-            }
+            TimelineFocusData::Live
+            | TimelineFocusData::PinnedEvents { .. }
+            | TimelineFocusData::Thread { .. } => return Err(PaginationError::NotEventFocusMode),
             TimelineFocusData::Event { paginator, .. } => paginator
                 .paginate_forward(num_events.into())
                 .await
@@ -465,6 +522,14 @@ impl<P: RoomDataProvider> TimelineController<P> {
         matches!(&*self.focus.read().await, TimelineFocusData::Live)
     }
 
+    /// The root of the thread this timeline is focused on, if any.
+    pub(super) async fn thread_root(&self) -> Option<OwnedEventId> {
+        match &*self.focus.read().await {
+            TimelineFocusData::Thread { loader, .. } => Some(loader.root_event_id().to_owned()),
+            _ => None,
+        }
+    }
+
     pub(super) fn with_settings(mut self, settings: TimelineSettings) -> Self {
         self.settings = settings;
         self
@@ -738,7 +803,7 @@ impl<P: RoomDataProvider> TimelineController<P> {
                 .await;
         }
 
-        if track_read_markers {
+        if track_read_markers && self.settings.add_read_marker_item {
             if let Some(fully_read_event_id) =
                 self.room_data_provider.load_fully_read_marker().await
             {
@@ -748,6 +813,10 @@ impl<P: RoomDataProvider> TimelineController<P> {
     }
 
     pub(super) async fn handle_fully_read_marker(&self, fully_read_event_id: OwnedEventId) {
+        if !self.settings.add_read_marker_item {
+            return;
+        }
+
         self.state.write().await.handle_fully_read_marker(fully_read_event_id);
     }
 
@@ -936,6 +1005,13 @@ impl<P: RoomDataProvider> TimelineController<P> {
             return true;
         }
 
+        // Look if this was a local edit echo. The edit stays applied to the edited item
+        // until it's re-inserted.
+        if state.meta.remove_local_edit(txn_id) {
+            debug!("Discarded local edit");
+            return true;
+        }
+
         // Look if this was a local reaction echo.
         if let Some(full_key) =
             state.meta.reactions.map.remove(&TimelineEventItemId::TransactionId(txn_id.to_owned()))
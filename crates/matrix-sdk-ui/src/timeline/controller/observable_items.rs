@@ -412,6 +412,7 @@ mod observable_items_tests {
                     is_own: false,
                     is_highlighted: false,
                     encryption_info: None,
+                    enrichments: Default::default(),
                     original_json: None,
                     latest_edit_json: None,
                     origin: RemoteEventOrigin::Sync,
@@ -26,7 +26,7 @@ use tracing::{debug, error, warn};
 
 use super::{
     rfind_event_by_id, AllRemoteEvents, FullEventMeta, ObservableItemsTransaction,
-    RelativePosition, RoomDataProvider, TimelineMetadata, TimelineState,
+    RelativePosition, RoomDataProvider, TimelineFocusKind, TimelineMetadata, TimelineState,
 };
 use crate::timeline::{controller::TimelineStateTransaction, TimelineItem};
 
@@ -364,7 +364,19 @@ impl TimelineStateTransaction<'_> {
                 }
 
                 for (user_id, receipt) in receipts {
-                    if !matches!(receipt.thread, ReceiptThread::Unthreaded | ReceiptThread::Main) {
+                    let is_in_timeline = match (&receipt.thread, &self.timeline_focus) {
+                        (ReceiptThread::Unthreaded, _) => true,
+                        // The main timeline doesn't include the threaded events.
+                        (ReceiptThread::Main, TimelineFocusKind::Thread { .. }) => false,
+                        (ReceiptThread::Main, _) => true,
+                        (
+                            ReceiptThread::Thread(thread_root),
+                            TimelineFocusKind::Thread { root_event_id },
+                        ) => thread_root == root_event_id,
+                        _ => false,
+                    };
+
+                    if !is_in_timeline {
                         continue;
                     }
 
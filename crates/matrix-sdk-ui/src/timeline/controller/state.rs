@@ -40,7 +40,7 @@ use ruma::{
     push::Action,
     serde::Raw,
     EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedTransactionId, OwnedUserId,
-    RoomVersionId, UserId,
+    RoomVersionId, TransactionId, UserId,
 };
 use tracing::{debug, instrument, trace, warn};
 
@@ -64,6 +64,7 @@ use crate::{
         event_item::{PollState, RemoteEventOrigin, ResponseData},
         item::TimelineUniqueId,
         reactions::Reactions,
+        threaded_events_loader::thread_root,
         traits::RoomDataProvider,
         Profile, TimelineItem, TimelineItemKind,
     },
@@ -359,7 +360,7 @@ impl TimelineState {
             items,
             previous_meta: &mut self.meta,
             meta,
-            timeline_focus: self.timeline_focus,
+            timeline_focus: self.timeline_focus.clone(),
         }
     }
 }
@@ -378,7 +379,7 @@ pub(in crate::timeline) struct TimelineStateTransaction<'a> {
     previous_meta: &'a mut TimelineMetadata,
 
     /// The kind of focus of this timeline.
-    timeline_focus: TimelineFocusKind,
+    pub(super) timeline_focus: TimelineFocusKind,
 }
 
 impl TimelineStateTransaction<'_> {
@@ -545,7 +546,7 @@ impl TimelineStateTransaction<'_> {
         settings: &TimelineSettings,
         date_divider_adjuster: &mut DateDividerAdjuster,
     ) -> HandleEventResult {
-        let SyncTimelineEvent { push_actions, kind } = event;
+        let SyncTimelineEvent { push_actions, kind, enrichments } = event;
         let encryption_info = kind.encryption_info().cloned();
 
         let (raw, utd_info) = match kind {
@@ -582,7 +583,7 @@ impl TimelineStateTransaction<'_> {
 
                     match origin {
                         RemoteEventOrigin::Sync | RemoteEventOrigin::Unknown => {
-                            should_add = match self.timeline_focus {
+                            should_add = match &self.timeline_focus {
                                 TimelineFocusKind::PinnedEvents => {
                                     // Only insert timeline items for pinned events, if the event
                                     // came from the sync.
@@ -600,6 +601,14 @@ impl TimelineStateTransaction<'_> {
                                     // down from the sync.
                                     false
                                 }
+
+                                TimelineFocusKind::Thread { root_event_id } => {
+                                    // Only insert timeline items for the events of the thread, if
+                                    // the event came from the sync.
+                                    event_id == *root_event_id
+                                        || thread_root(&raw)
+                                            .is_some_and(|root| root == *root_event_id)
+                                }
                             };
                         }
 
@@ -737,6 +746,7 @@ impl TimelineStateTransaction<'_> {
                 event_id: event_id.clone(),
                 raw_event: raw,
                 encryption_info,
+                enrichments,
                 txn_id,
                 position,
             },
@@ -995,6 +1005,21 @@ impl std::fmt::Debug for PendingEdit {
     }
 }
 
+/// An edit of one of our own events, which is still being sent.
+#[derive(Clone)]
+pub(in crate::timeline) struct LocalEdit {
+    /// The transaction ID of the local echo of the edit.
+    pub txn_id: OwnedTransactionId,
+    pub kind: PendingEditKind,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for LocalEdit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalEdit").field("txn_id", &self.txn_id).finish_non_exhaustive()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(in crate::timeline) struct TimelineMetadata {
     // **** CONSTANT FIELDS ****
@@ -1043,6 +1068,13 @@ pub(in crate::timeline) struct TimelineMetadata {
     /// Edit events received before the related event they're editing.
     pub pending_edits: RingBuffer<PendingEdit>,
 
+    /// Our own edits which are still being sent, keyed by the edited event.
+    ///
+    /// They take precedence over the bundled edits of the server and the
+    /// pending edits when the edited event is (re-)inserted, since those
+    /// don't know about them yet.
+    pub local_edits: HashMap<OwnedEventId, LocalEdit>,
+
     /// Identifier of the fully-read event, helping knowing where to introduce
     /// the read marker.
     pub fully_read_event: Option<OwnedEventId>,
@@ -1079,6 +1111,7 @@ impl TimelineMetadata {
             reactions: Default::default(),
             pending_poll_events: Default::default(),
             pending_edits: RingBuffer::new(MAX_NUM_STASHED_PENDING_EDITS),
+            local_edits: Default::default(),
             fully_read_event: Default::default(),
             // It doesn't make sense to set this to false until we fill the `fully_read_event`
             // field, otherwise we'll keep on exiting early in `Self::update_read_marker`.
@@ -1104,6 +1137,16 @@ impl TimelineMetadata {
         self.read_receipts.clear();
     }
 
+    /// Forget the local edit with the given transaction ID, once its remote
+    /// echo has been received or it has been discarded.
+    ///
+    /// Returns whether there was such a local edit.
+    pub(crate) fn remove_local_edit(&mut self, txn_id: &TransactionId) -> bool {
+        let len = self.local_edits.len();
+        self.local_edits.retain(|_, edit| edit.txn_id != txn_id);
+        self.local_edits.len() != len
+    }
+
     /// Get the relative positions of two events in the timeline.
     ///
     /// This method assumes that all events since the end of the timeline are
@@ -18,12 +18,12 @@
 use std::{fmt::Display, sync::Arc};
 
 use chrono::{Datelike, Local, TimeZone};
-use ruma::MilliSecondsSinceUnixEpoch;
+use ruma::{MilliSecondsSinceUnixEpoch, UserId};
 use tracing::{error, event_enabled, instrument, trace, warn, Level};
 
 use super::{
     controller::{ObservableItemsTransaction, TimelineMetadata},
-    DateDividerMode, TimelineItem, TimelineItemKind, VirtualTimelineItem,
+    DateDividerMode, EventTimelineItem, TimelineItem, TimelineItemKind, VirtualTimelineItem,
 };
 
 #[derive(Debug, PartialEq)]
@@ -52,6 +52,34 @@ fn timestamp_to_date(ts: MilliSecondsSinceUnixEpoch) -> Date {
     Date { year: datetime.year(), month: datetime.month(), day: datetime.day() }
 }
 
+/// What decides the group of an item, for the date dividers.
+#[derive(Clone, Copy)]
+struct GroupItem<'a> {
+    /// The timestamp of the event, or of the date divider.
+    ts: MilliSecondsSinceUnixEpoch,
+
+    /// The sender of the event, or of the event following the date divider.
+    ///
+    /// It's `None` for a date divider that isn't followed by any event.
+    sender: Option<&'a UserId>,
+}
+
+impl<'a> GroupItem<'a> {
+    fn from_event(event: &'a EventTimelineItem) -> Self {
+        Self { ts: event.timestamp(), sender: Some(event.sender()) }
+    }
+
+    fn from_date_divider(
+        items: &'a ObservableItemsTransaction<'_>,
+        i: usize,
+        ts: MilliSecondsSinceUnixEpoch,
+    ) -> Self {
+        let sender =
+            items.iter().skip(i + 1).find_map(|item| item.as_event()).map(|event| event.sender());
+        Self { ts, sender }
+    }
+}
+
 /// Algorithm ensuring that date dividers are adjusted correctly, according to
 /// new items that have been inserted.
 pub(super) struct DateDividerAdjuster {
@@ -126,15 +154,26 @@ impl DateDividerAdjuster {
         // non-decreasing order of the indices), so we must record the insert
         // position for an operation related to the previous item.
 
+        if matches!(self.mode, DateDividerMode::Disabled) {
+            self.remove_all_date_dividers(items, meta);
+            return;
+        }
+
         let mut prev_item: Option<PrevItemDesc<'_>> = None;
-        let mut latest_event_ts = None;
+        let mut latest_event = None;
 
         for (i, item) in items.iter().enumerate() {
             match item.kind() {
                 TimelineItemKind::Virtual(VirtualTimelineItem::DateDivider(ts)) => {
+                    let date_divider = GroupItem::from_date_divider(items, i, *ts);
+
                     // Record what the last alive item pair is only if we haven't removed the date
                     // divider.
-                    if !self.handle_date_divider(i, *ts, prev_item.as_ref().map(|desc| desc.item)) {
+                    if !self.handle_date_divider(
+                        i,
+                        date_divider,
+                        prev_item.as_ref().map(|desc| desc.item),
+                    ) {
                         prev_item = Some(PrevItemDesc {
                             item_index: i,
                             item,
@@ -144,13 +183,13 @@ impl DateDividerAdjuster {
                 }
 
                 TimelineItemKind::Event(event) => {
-                    let ts = event.timestamp();
+                    let event = GroupItem::from_event(event);
 
-                    self.handle_event(i, ts, prev_item, latest_event_ts);
+                    self.handle_event(i, event, prev_item, latest_event);
 
                     prev_item =
                         Some(PrevItemDesc { item_index: i, item, insert_op_at: self.ops.len() });
-                    latest_event_ts = Some(ts);
+                    latest_event = Some(event);
                 }
 
                 TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker) => {
@@ -207,6 +246,24 @@ impl DateDividerAdjuster {
         self.consumed = true;
     }
 
+    /// Removes all the date dividers, when they're disabled.
+    fn remove_all_date_dividers(
+        &mut self,
+        items: &mut ObservableItemsTransaction<'_>,
+        meta: &mut TimelineMetadata,
+    ) {
+        for (i, item) in items.iter().enumerate() {
+            if item.is_date_divider() {
+                trace!("removing date divider @ {i}, since they're disabled");
+                self.ops.push(DateDividerOperation::Remove(i));
+            }
+        }
+
+        self.process_ops(items, meta);
+        self.ops.clear();
+        self.consumed = true;
+    }
+
     /// Decides what to do with a date divider.
     ///
     /// Returns whether it's been removed or not.
@@ -214,7 +271,7 @@ impl DateDividerAdjuster {
     fn handle_date_divider(
         &mut self,
         i: usize,
-        ts: MilliSecondsSinceUnixEpoch,
+        date_divider: GroupItem<'_>,
         prev_item: Option<&Arc<TimelineItem>>,
     ) -> bool {
         let Some(prev_item) = prev_item else {
@@ -226,7 +283,7 @@ impl DateDividerAdjuster {
         match prev_item.kind() {
             TimelineItemKind::Event(event) => {
                 // This date divider is preceded by an event.
-                if self.is_same_date_divider_group_as(event.timestamp(), ts) {
+                if self.is_same_date_divider_group_as(GroupItem::from_event(event), date_divider) {
                     // The event has the same date as the date divider: remove the current date
                     // divider.
                     trace!("removing date divider following event with same timestamp @ {i}");
@@ -254,15 +311,15 @@ impl DateDividerAdjuster {
     fn handle_event(
         &mut self,
         i: usize,
-        ts: MilliSecondsSinceUnixEpoch,
+        event: GroupItem<'_>,
         prev_item_desc: Option<PrevItemDesc<'_>>,
-        latest_event_ts: Option<MilliSecondsSinceUnixEpoch>,
+        latest_event: Option<GroupItem<'_>>,
     ) {
         let Some(PrevItemDesc { item_index, insert_op_at, item }) = prev_item_desc else {
             // The event was the first item, so there wasn't any date divider before it:
             // insert one.
             trace!("inserting the first date divider @ {}", i);
-            self.ops.push(DateDividerOperation::Insert(i, ts));
+            self.ops.push(DateDividerOperation::Insert(i, event.ts));
             return;
         };
 
@@ -270,26 +327,24 @@ impl DateDividerAdjuster {
             TimelineItemKind::Event(prev_event) => {
                 // The event is preceded by another event. If they're not the same date,
                 // insert a date divider.
-                let prev_ts = prev_event.timestamp();
-
-                if !self.is_same_date_divider_group_as(prev_ts, ts) {
+                if !self.is_same_date_divider_group_as(GroupItem::from_event(prev_event), event) {
                     trace!(
                         "inserting date divider @ {} between two events with different dates",
                         i
                     );
-                    self.ops.push(DateDividerOperation::Insert(i, ts));
+                    self.ops.push(DateDividerOperation::Insert(i, event.ts));
                 }
             }
 
             TimelineItemKind::Virtual(VirtualTimelineItem::DateDivider(prev_ts)) => {
-                let event_date = timestamp_to_date(ts);
+                // The event is preceded by a date divider, which is grouped with it.
+                let date_divider = GroupItem { ts: *prev_ts, sender: event.sender };
 
-                // The event is preceded by a date divider.
-                if timestamp_to_date(*prev_ts) != event_date {
+                if !self.is_same_date_divider_group_as(date_divider, event) {
                     // The date divider is wrong. Should we replace it with the correct value, or
                     // remove it entirely?
-                    if let Some(last_event_ts) = latest_event_ts {
-                        if timestamp_to_date(last_event_ts) == event_date {
+                    if let Some(latest_event) = latest_event {
+                        if self.is_same_date_divider_group_as(latest_event, event) {
                             // There's a previous event with the same date: remove the divider.
                             trace!("removed date divider @ {item_index} between two events that have the same date");
                             self.ops.insert(insert_op_at, DateDividerOperation::Remove(item_index));
@@ -300,7 +355,8 @@ impl DateDividerAdjuster {
                     // There's no previous event or there's one with a different date: replace
                     // the current divider.
                     trace!("replacing date divider @ {item_index} with new timestamp from event");
-                    self.ops.insert(insert_op_at, DateDividerOperation::Replace(item_index, ts));
+                    self.ops
+                        .insert(insert_op_at, DateDividerOperation::Replace(item_index, event.ts));
                 }
             }
 
@@ -436,16 +492,16 @@ impl DateDividerAdjuster {
 
         // 4. Items are properly separated with date dividers.
         {
-            let mut prev_event_ts = None;
-            let mut prev_date_divider_ts = None;
+            let mut prev_event = None;
+            let mut prev_date_divider = None;
 
             for (i, item) in items.iter().enumerate() {
                 if let Some(ev) = item.as_event() {
-                    let ts = ev.timestamp();
+                    let event = GroupItem::from_event(ev);
 
                     // We have the same date as the previous event we've seen.
-                    if let Some(prev_ts) = prev_event_ts {
-                        if !self.is_same_date_divider_group_as(prev_ts, ts) {
+                    if let Some(prev_event) = prev_event {
+                        if !self.is_same_date_divider_group_as(prev_event, event) {
                             report.errors.push(
                                 DateDividerInsertError::MissingDateDividerBetweenEvents { at: i },
                             );
@@ -453,8 +509,8 @@ impl DateDividerAdjuster {
                     }
 
                     // There is a date divider before us, and it's the same date as our timestamp.
-                    if let Some(prev_ts) = prev_date_divider_ts {
-                        if !self.is_same_date_divider_group_as(prev_ts, ts) {
+                    if let Some(prev_date_divider) = prev_date_divider {
+                        if !self.is_same_date_divider_group_as(prev_date_divider, event) {
                             report.errors.push(
                                 DateDividerInsertError::InconsistentDateAfterPreviousDateDivider {
                                     at: i,
@@ -467,21 +523,23 @@ impl DateDividerAdjuster {
                             .push(DateDividerInsertError::MissingDateDividerBeforeEvent { at: i });
                     }
 
-                    prev_event_ts = Some(ts);
+                    prev_event = Some(event);
                 } else if let TimelineItemKind::Virtual(VirtualTimelineItem::DateDivider(ts)) =
                     item.kind()
                 {
+                    let date_divider = GroupItem::from_date_divider(items, i, *ts);
+
                     // The previous date divider is for a different date.
-                    if let Some(prev_ts) = prev_date_divider_ts {
-                        if self.is_same_date_divider_group_as(prev_ts, *ts) {
+                    if let Some(prev_date_divider) = prev_date_divider {
+                        if self.is_same_date_divider_group_as(prev_date_divider, date_divider) {
                             report
                                 .errors
                                 .push(DateDividerInsertError::DuplicateDateDivider { at: i });
                         }
                     }
 
-                    prev_event_ts = None;
-                    prev_date_divider_ts = Some(*ts);
+                    prev_event = None;
+                    prev_date_divider = Some(date_divider);
                 }
             }
         }
@@ -503,18 +561,24 @@ impl DateDividerAdjuster {
         }
     }
 
-    /// Returns whether the two dates for the given timestamps are the same or
-    /// not.
-    fn is_same_date_divider_group_as(
-        &self,
-        lhs: MilliSecondsSinceUnixEpoch,
-        rhs: MilliSecondsSinceUnixEpoch,
-    ) -> bool {
-        match self.mode {
-            DateDividerMode::Daily => timestamp_to_date(lhs) == timestamp_to_date(rhs),
+    /// Returns whether the two given items are in the same date divider group
+    /// or not.
+    fn is_same_date_divider_group_as(&self, lhs: GroupItem<'_>, rhs: GroupItem<'_>) -> bool {
+        match &self.mode {
+            DateDividerMode::Daily => timestamp_to_date(lhs.ts) == timestamp_to_date(rhs.ts),
             DateDividerMode::Monthly => {
-                timestamp_to_date(lhs).is_same_month_as(timestamp_to_date(rhs))
+                timestamp_to_date(lhs.ts).is_same_month_as(timestamp_to_date(rhs.ts))
             }
+            DateDividerMode::Custom(group) => match (lhs.sender, rhs.sender) {
+                (Some(lhs_sender), Some(rhs_sender)) => {
+                    group(lhs.ts, lhs_sender) == group(rhs.ts, rhs_sender)
+                }
+                // A date divider that isn't followed by any event is trailing, it will be
+                // removed anyway.
+                _ => false,
+            },
+            // There are no dividers, so everything is in the same group.
+            DateDividerMode::Disabled => true,
         }
     }
 }
@@ -657,6 +721,7 @@ mod tests {
             is_own: false,
             is_highlighted: false,
             encryption_info: None,
+            enrichments: Default::default(),
             original_json: None,
             latest_edit_json: None,
             origin: crate::timeline::event_item::RemoteEventOrigin::Sync,
@@ -18,7 +18,7 @@ use as_variant::as_variant;
 use indexmap::IndexMap;
 use matrix_sdk::{
     crypto::types::events::UtdCause,
-    deserialized_responses::{EncryptionInfo, UnableToDecryptInfo},
+    deserialized_responses::{EncryptionInfo, EventEnrichments, UnableToDecryptInfo},
     ring_buffer::RingBuffer,
     send_queue::SendHandle,
 };
@@ -53,8 +53,8 @@ use tracing::{debug, error, field::debug, info, instrument, trace, warn};
 use super::{
     algorithms::{rfind_event_by_id, rfind_event_item},
     controller::{
-        ObservableItemsTransaction, ObservableItemsTransactionEntry, PendingEdit, PendingEditKind,
-        TimelineMetadata, TimelineStateTransaction,
+        LocalEdit, ObservableItemsTransaction, ObservableItemsTransactionEntry, PendingEdit,
+        PendingEditKind, TimelineMetadata, TimelineStateTransaction,
     },
     date_dividers::DateDividerAdjuster,
     event_item::{
@@ -95,6 +95,8 @@ pub(super) enum Flow {
         position: TimelineItemPosition,
         /// Information about the encryption for this event.
         encryption_info: Option<EncryptionInfo>,
+        /// The metadata attached to this event by the event enrichers.
+        enrichments: EventEnrichments,
     },
 }
 
@@ -534,10 +536,9 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         msg: RoomMessageEventContent,
         relations: BundledMessageLikeRelations<AnySyncMessageLikeEvent>,
     ) {
-        // Always remove the pending edit, if there's any. The reason is that if
-        // there's an edit in the relations mapping, we want to prefer it over any
-        // other pending edit, since it's more likely to be up to date, and we
-        // don't want to apply another pending edit on top of it.
+        // Always remove the pending edit, if there's any, so it's not applied later
+        // on. If there's also an edit in the relations mapping, the most recent
+        // one of both is used.
         let pending_edit = self
             .ctx
             .flow
@@ -552,13 +553,21 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                 _ => None,
             });
 
-        let (edit_json, edit_content) = extract_room_msg_edit_content(relations)
-            .map(|content| {
-                let edit_json = self.ctx.flow.raw_event().and_then(extract_bundled_edit_event_json);
-                (edit_json, content)
-            })
-            .or(pending_edit)
-            .unzip();
+        let bundled_edit = extract_room_msg_edit_content(relations).map(|content| {
+            let edit_json = self.ctx.flow.raw_event().and_then(extract_bundled_edit_event_json);
+            (edit_json, content)
+        });
+
+        let (edit_json, mut edit_content) = most_recent_edit(bundled_edit, pending_edit).unzip();
+
+        // Our own edit that is still being sent is more recent than anything the server
+        // knows about.
+        if let Some(local_edit) = self.local_edit() {
+            if let PendingEditKind::RoomMessage(replacement) = local_edit.kind {
+                trace!("Applying our own local edit");
+                edit_content = Some(replacement.new_content);
+            }
+        }
 
         let edit_json = edit_json.flatten();
 
@@ -570,6 +579,10 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         &mut self,
         replacement: Replacement<RoomMessageEventContentWithoutRelation>,
     ) {
+        self.update_local_edits(&replacement.event_id, || {
+            PendingEditKind::RoomMessage(replacement.clone())
+        });
+
         if let Some((item_pos, item)) = rfind_event_by_id(self.items, &replacement.event_id) {
             let edit_json = self.ctx.flow.raw_event().cloned();
             if let Some(new_item) = self.apply_msg_edit(&item, replacement.new_content, edit_json) {
@@ -643,6 +656,36 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         }
     }
 
+    /// Keep track of our own edits that are still being sent, so they can be
+    /// reapplied if the edited event is re-inserted, see
+    /// [`TimelineMetadata::local_edits`].
+    fn update_local_edits(
+        &mut self,
+        replaced_event_id: &EventId,
+        kind: impl FnOnce() -> PendingEditKind,
+    ) {
+        match &self.ctx.flow {
+            Flow::Local { txn_id, .. } => {
+                self.meta.local_edits.insert(
+                    replaced_event_id.to_owned(),
+                    LocalEdit { txn_id: txn_id.clone(), kind: kind() },
+                );
+            }
+            Flow::Remote { txn_id: Some(txn_id), .. } => {
+                // This is the remote echo of our own edit, the server knows about it now.
+                self.meta.remove_local_edit(txn_id);
+            }
+            Flow::Remote { txn_id: None, .. } => {}
+        }
+    }
+
+    /// Get our own edit of the current remote event that is still being sent,
+    /// if any.
+    fn local_edit(&self) -> Option<LocalEdit> {
+        let event_id = self.ctx.flow.event_id()?;
+        self.meta.local_edits.get(event_id).cloned()
+    }
+
     /// Look for a pending edit for the given event, and remove it from the list
     /// and return it, if found.
     fn maybe_unstash_pending_edit(
@@ -780,6 +823,10 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         &mut self,
         replacement: Replacement<NewUnstablePollStartEventContentWithoutRelation>,
     ) {
+        self.update_local_edits(&replacement.event_id, || {
+            PendingEditKind::Poll(replacement.clone())
+        });
+
         let Some((item_pos, item)) = rfind_event_by_id(self.items, &replacement.event_id) else {
             if let Flow::Remote { position, raw_event, .. } = &self.ctx.flow {
                 let replaced_event_id = replacement.event_id.clone();
@@ -841,10 +888,9 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         c: NewUnstablePollStartEventContent,
         relations: BundledMessageLikeRelations<AnySyncMessageLikeEvent>,
     ) {
-        // Always remove the pending edit, if there's any. The reason is that if
-        // there's an edit in the relations mapping, we want to prefer it over any
-        // other pending edit, since it's more likely to be up to date, and we
-        // don't want to apply another pending edit on top of it.
+        // Always remove the pending edit, if there's any, so it's not applied later
+        // on. If there's also an edit in the relations mapping, the most recent
+        // one of both is used.
         let pending_edit = self
             .ctx
             .flow
@@ -859,13 +905,21 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                 _ => None,
             });
 
-        let (edit_json, edit_content) = extract_poll_edit_content(relations)
-            .map(|content| {
-                let edit_json = self.ctx.flow.raw_event().and_then(extract_bundled_edit_event_json);
-                (edit_json, content)
-            })
-            .or(pending_edit)
-            .unzip();
+        let bundled_edit = extract_poll_edit_content(relations).map(|content| {
+            let edit_json = self.ctx.flow.raw_event().and_then(extract_bundled_edit_event_json);
+            (edit_json, content)
+        });
+
+        let (edit_json, mut edit_content) = most_recent_edit(bundled_edit, pending_edit).unzip();
+
+        // Our own edit that is still being sent is more recent than anything the server
+        // knows about.
+        if let Some(local_edit) = self.local_edit() {
+            if let PendingEditKind::Poll(replacement) = local_edit.kind {
+                trace!("Applying our own local edit");
+                edit_content = Some(replacement.new_content);
+            }
+        }
 
         let mut poll_state = PollState::new(c, edit_content);
 
@@ -1048,7 +1102,15 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
             }
             .into(),
 
-            Flow::Remote { event_id, raw_event, position, txn_id, encryption_info, .. } => {
+            Flow::Remote {
+                event_id,
+                raw_event,
+                position,
+                txn_id,
+                encryption_info,
+                enrichments,
+                ..
+            } => {
                 let origin = match *position {
                     TimelineItemPosition::Start { origin }
                     | TimelineItemPosition::End { origin }
@@ -1072,6 +1134,7 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                     is_own: self.ctx.is_own_event,
                     is_highlighted: self.ctx.is_highlighted,
                     encryption_info: encryption_info.clone(),
+                    enrichments: enrichments.clone(),
                     original_json: Some(raw_event.clone()),
                     latest_edit_json: edit_json,
                     origin,
@@ -1244,6 +1307,12 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
             } => {
                 trace!("Updating timeline item at position {idx}");
 
+                // Keep the reactions that were received, or sent, while the event couldn't be
+                // decrypted.
+                if let Some(old_item) = self.items[*idx].as_event() {
+                    merge_reactions(&mut item.reactions, &old_item.reactions);
+                }
+
                 // Update all events that replied to this previously encrypted message.
                 Self::maybe_update_responses(self.items, decrypted_event_id, &item);
 
@@ -1356,6 +1425,46 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
     }
 }
 
+/// Pick the most recent edit, by `origin_server_ts`, between the edit bundled
+/// by the server in the edited event and an edit received before the edited
+/// event.
+///
+/// The bundled edit wins when both are as recent, or when the timestamps are
+/// unknown.
+fn most_recent_edit<C>(
+    bundled: Option<(Option<Raw<AnySyncTimelineEvent>>, C)>,
+    pending: Option<(Option<Raw<AnySyncTimelineEvent>>, C)>,
+) -> Option<(Option<Raw<AnySyncTimelineEvent>>, C)> {
+    fn timestamp(
+        edit_json: &Option<Raw<AnySyncTimelineEvent>>,
+    ) -> Option<MilliSecondsSinceUnixEpoch> {
+        edit_json.as_ref()?.get_field("origin_server_ts").ok()?
+    }
+
+    match (bundled, pending) {
+        (Some(bundled), Some(pending)) => {
+            if timestamp(&pending.0) > timestamp(&bundled.0) {
+                trace!("The pending edit is more recent than the bundled one");
+                Some(pending)
+            } else {
+                Some(bundled)
+            }
+        }
+        (bundled, pending) => bundled.or(pending),
+    }
+}
+
+/// Add the reactions of `old` which are missing from `new`.
+fn merge_reactions(new: &mut ReactionsByKeyBySender, old: &ReactionsByKeyBySender) {
+    for (key, senders) in old.iter() {
+        let group = new.entry(key.clone()).or_default();
+
+        for (sender, info) in senders {
+            group.entry(sender.clone()).or_insert_with(|| info.clone());
+        }
+    }
+}
+
 /// Transfer `TimelineDetails` that weren't available on the original
 /// item and have been fetched separately (only `reply_to` for
 /// now) from `old_item` to `item`, given two items for an event
@@ -17,7 +17,7 @@
 use std::{fmt, sync::Arc};
 
 use imbl::{vector, Vector};
-use matrix_sdk::{deserialized_responses::TimelineEvent, Room};
+use matrix_sdk::{custom_msgtypes::CustomMsgType, deserialized_responses::TimelineEvent, Room};
 use ruma::{
     assign,
     events::{
@@ -112,6 +112,15 @@ impl Message {
         &self.msgtype
     }
 
+    /// Parse the `msgtype`-specific data of this message into the given
+    /// custom `msgtype`.
+    ///
+    /// Returns `None` if the `msgtype` of this message is not
+    /// [`CustomMsgType::MSGTYPE`].
+    pub fn custom_msgtype<T: CustomMsgType>(&self) -> Option<serde_json::Result<T>> {
+        T::from_msgtype(&self.msgtype)
+    }
+
     /// Get a reference to the message body.
     ///
     /// Shorthand for `.msgtype().body()`.
@@ -20,7 +20,7 @@ use std::{
 use as_variant::as_variant;
 use indexmap::IndexMap;
 use matrix_sdk::{
-    deserialized_responses::{EncryptionInfo, ShieldState},
+    deserialized_responses::{EncryptionInfo, EventEnrichments, ShieldState},
     send_queue::{SendHandle, SendReactionHandle},
     Client, Error,
 };
@@ -147,6 +147,7 @@ impl EventTimelineItem {
 
         let raw_sync_event = latest_event.event().raw().clone();
         let encryption_info = latest_event.event().encryption_info().cloned();
+        let enrichments = latest_event.event().enrichments.clone();
 
         let Ok(event) = raw_sync_event.deserialize_as::<AnySyncTimelineEvent>() else {
             warn!("Unable to deserialize latest_event as an AnySyncTimelineEvent!");
@@ -195,6 +196,7 @@ impl EventTimelineItem {
             is_own,
             is_highlighted,
             encryption_info,
+            enrichments,
             original_json: Some(raw_sync_event),
             latest_edit_json,
             origin,
@@ -404,6 +406,18 @@ impl EventTimelineItem {
         }
     }
 
+    /// Get the metadata attached to the event by the event enrichers of the
+    /// client.
+    ///
+    /// Returns `None` for local echoes. See
+    /// [`Client::add_event_enricher()`][matrix_sdk::Client::add_event_enricher].
+    pub fn enrichments(&self) -> Option<&EventEnrichments> {
+        match &self.kind {
+            EventTimelineItemKind::Local(_) => None,
+            EventTimelineItemKind::Remote(remote_event) => Some(&remote_event.enrichments),
+        }
+    }
+
     /// Gets the [`ShieldState`] which can be used to decorate messages in the
     /// recommended way.
     pub fn get_shield(&self, strict: bool) -> Option<ShieldState> {
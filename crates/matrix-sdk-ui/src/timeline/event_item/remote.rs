@@ -15,7 +15,7 @@
 use std::fmt;
 
 use indexmap::IndexMap;
-use matrix_sdk::deserialized_responses::EncryptionInfo;
+use matrix_sdk::deserialized_responses::{EncryptionInfo, EventEnrichments};
 use ruma::{
     events::{receipt::Receipt, AnySyncTimelineEvent},
     serde::Raw,
@@ -48,6 +48,10 @@ pub(in crate::timeline) struct RemoteEventTimelineItem {
     /// Encryption information.
     pub encryption_info: Option<EncryptionInfo>,
 
+    /// The metadata attached to the event by the event enrichers of the
+    /// client.
+    pub enrichments: EventEnrichments,
+
     /// JSON of the original event.
     ///
     /// If the event is edited, this *won't* change, instead `latest_edit_json`
@@ -102,6 +106,7 @@ impl fmt::Debug for RemoteEventTimelineItem {
             read_receipts,
             is_own,
             encryption_info,
+            enrichments,
             original_json: _,
             latest_edit_json: _,
             is_highlighted,
@@ -115,6 +120,7 @@ impl fmt::Debug for RemoteEventTimelineItem {
             .field("is_own", is_own)
             .field("is_highlighted", is_highlighted)
             .field("encryption_info", encryption_info)
+            .field("enrichments", enrichments)
             .field("origin", origin)
             .finish_non_exhaustive()
     }
@@ -16,7 +16,7 @@
 //!
 //! See [`Timeline`] for details.
 
-use std::{fs, path::PathBuf, pin::Pin, sync::Arc, task::Poll};
+use std::{fmt, fs, path::PathBuf, pin::Pin, sync::Arc, task::Poll};
 
 use algorithms::rfind_event_by_item_id;
 use event_item::{extract_room_msg_edit_content, TimelineItemHandle};
@@ -75,6 +75,7 @@ mod pinned_events_loader;
 mod reactions;
 #[cfg(test)]
 mod tests;
+mod threaded_events_loader;
 mod to_device;
 mod traits;
 mod virtual_item;
@@ -166,6 +167,15 @@ pub enum TimelineFocus {
 
     /// Only show pinned events.
     PinnedEvents { max_events_to_load: u16, max_concurrent_requests: u16 },
+
+    /// Focus on a thread, i.e. show its root and the events in the thread,
+    /// and receive new threaded events from sync.
+    Thread {
+        /// The event id of the thread root.
+        root_event_id: OwnedEventId,
+        /// Number of threaded events to request for the first request.
+        num_events: u16,
+    },
 }
 
 impl TimelineFocus {
@@ -174,16 +184,61 @@ impl TimelineFocus {
             TimelineFocus::Live => "live".to_owned(),
             TimelineFocus::Event { target, .. } => format!("permalink:{target}"),
             TimelineFocus::PinnedEvents { .. } => "pinned-events".to_owned(),
+            TimelineFocus::Thread { root_event_id, .. } => format!("thread:{root_event_id}"),
         }
     }
 }
 
+/// A function returning the group an event belongs to, given its timestamp
+/// and its sender, for [`DateDividerMode::Custom`].
+///
+/// Consecutive events are in the same group if and only if the function
+/// returns the same value for both of them.
+pub type DateDividerGroupFn = dyn Fn(MilliSecondsSinceUnixEpoch, &UserId) -> u64 + Send + Sync;
+
 /// Changes how dividers get inserted, either in between each day or in between
 /// each month
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub enum DateDividerMode {
     Daily,
     Monthly,
+
+    /// Insert a divider in between each group of events, as computed by the
+    /// given function.
+    ///
+    /// For instance, to insert a divider in between each hour, and each time
+    /// the sender changes:
+    ///
+    /// ```
+    /// # use std::{
+    /// #     hash::{DefaultHasher, Hash, Hasher},
+    /// #     sync::Arc,
+    /// # };
+    /// # use matrix_sdk_ui::timeline::DateDividerMode;
+    /// # use ruma::{MilliSecondsSinceUnixEpoch, UserId};
+    /// let mode =
+    ///     DateDividerMode::Custom(Arc::new(|ts: MilliSecondsSinceUnixEpoch, sender: &UserId| {
+    ///         let mut hasher = DefaultHasher::new();
+    ///         (u64::from(ts.0) / 3_600_000, sender).hash(&mut hasher);
+    ///         hasher.finish()
+    ///     }));
+    /// ```
+    Custom(Arc<DateDividerGroupFn>),
+
+    /// Don't insert any divider.
+    Disabled,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for DateDividerMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Daily => f.write_str("Daily"),
+            Self::Monthly => f.write_str("Monthly"),
+            Self::Custom(_) => f.debug_tuple("Custom").finish_non_exhaustive(),
+            Self::Disabled => f.write_str("Disabled"),
+        }
+    }
 }
 
 impl Timeline {
@@ -742,13 +797,18 @@ impl Timeline {
     ///
     /// This works even if the latest event belongs to a thread, as a threaded
     /// reply also belongs to the unthreaded timeline. No threaded receipt
-    /// will be sent here (see also #3123).
+    /// will be sent here (see also #3123), unless the timeline is focused on a
+    /// thread, in which case a receipt for this thread is sent instead.
     ///
     /// Returns a boolean indicating if we sent the request or not.
     #[instrument(skip(self), fields(room_id = ?self.room().room_id()))]
     pub async fn mark_as_read(&self, receipt_type: ReceiptType) -> Result<bool> {
         if let Some(event_id) = self.controller.latest_event_id().await {
-            self.send_single_receipt(receipt_type, ReceiptThread::Unthreaded, event_id).await
+            let thread = match self.controller.thread_root().await {
+                Some(root_event_id) => ReceiptThread::Thread(root_event_id),
+                None => ReceiptThread::Unthreaded,
+            };
+            self.send_single_receipt(receipt_type, thread, event_id).await
         } else {
             trace!("can't mark room as read because there's no latest event id");
             Ok(false)
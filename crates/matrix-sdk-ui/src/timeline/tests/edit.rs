@@ -22,9 +22,12 @@ use matrix_sdk::deserialized_responses::{
 use matrix_sdk_base::deserialized_responses::{DecryptedRoomEvent, SyncTimelineEvent};
 use matrix_sdk_test::{async_test, ALICE};
 use ruma::{
-    event_id,
+    assign, event_id,
     events::{
-        room::message::{MessageType, RedactedRoomMessageEventContent},
+        relation::Replacement,
+        room::message::{
+            MessageType, RedactedRoomMessageEventContent, Relation, RoomMessageEventContent,
+        },
         BundledMessageLikeRelations,
     },
     room_id,
@@ -178,6 +181,7 @@ async fn test_edit_updates_encryption_info() {
             sender_claimed_keys: BTreeMap::new(),
         },
         verification_state: VerificationState::Verified,
+        session_id: None,
     };
 
     let original_event: SyncTimelineEvent = DecryptedRoomEvent {
@@ -294,6 +298,115 @@ async fn test_relations_edit_overrides_pending_edit_msg() {
     assert_pending!(stream);
 }
 
+#[async_test]
+async fn test_more_recent_pending_edit_overrides_relations_edit() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    let f = &timeline.factory;
+
+    let original_event_id = event_id!("$original");
+    let edit1_event_id = event_id!("$edit1");
+    let edit2_event_id = event_id!("$edit2");
+
+    // The bundled edit is older than the pending one.
+    let mut relations = BundledMessageLikeRelations::new();
+    relations.replace = Some(Box::new(
+        f.text_msg("* edit 1")
+            .edit(original_event_id, MessageType::text_plain("edit 1").into())
+            .event_id(edit1_event_id)
+            .sender(*ALICE)
+            .into_raw_sync(),
+    ));
+
+    timeline
+        .handle_live_event(
+            f.text_msg("* edit 2")
+                .sender(*ALICE)
+                .edit(original_event_id, MessageType::text_plain("edit 2").into())
+                .event_id(edit2_event_id),
+        )
+        .await;
+    assert_pending!(stream);
+
+    timeline
+        .handle_live_event(
+            f.text_msg("original")
+                .sender(*ALICE)
+                .event_id(original_event_id)
+                .bundled_relations(relations),
+        )
+        .await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    // We receive the pending edit, which is the most recent one.
+    let event = item.as_event().unwrap();
+    assert_eq!(
+        event
+            .latest_edit_json()
+            .expect("we should have an edit json")
+            .deserialize()
+            .unwrap()
+            .event_id(),
+        edit2_event_id
+    );
+    assert_eq!(event.content().as_message().unwrap().body(), "edit 2");
+}
+
+#[async_test]
+async fn test_local_edit_overrides_relations_edit() {
+    let timeline = TestTimeline::new();
+
+    let f = &timeline.factory;
+
+    let original_event_id = event_id!("$original");
+
+    timeline
+        .handle_live_event(f.text_msg("original").sender(*ALICE).event_id(original_event_id))
+        .await;
+
+    // We edit our message, the edit is still being sent.
+    timeline
+        .handle_local_event(
+            assign!(RoomMessageEventContent::text_plain("* local edit"), {
+                relates_to: Some(Relation::Replacement(Replacement::new(
+                    original_event_id.to_owned(),
+                    MessageType::text_plain("local edit").into(),
+                ))),
+            })
+            .into(),
+        )
+        .await;
+
+    let item = timeline.controller.items().await.last().unwrap().as_event().unwrap().clone();
+    assert_eq!(item.content().as_message().unwrap().body(), "local edit");
+
+    // The message is received again, with an older edit bundled by the server.
+    let mut relations = BundledMessageLikeRelations::new();
+    relations.replace = Some(Box::new(
+        f.text_msg("* remote edit")
+            .edit(original_event_id, MessageType::text_plain("remote edit").into())
+            .event_id(event_id!("$edit"))
+            .sender(*ALICE)
+            .into_raw_sync(),
+    ));
+
+    timeline.controller.clear().await;
+    timeline
+        .handle_live_event(
+            f.text_msg("original")
+                .sender(*ALICE)
+                .event_id(original_event_id)
+                .bundled_relations(relations),
+        )
+        .await;
+
+    // Our own edit is still applied.
+    let item = timeline.controller.items().await.last().unwrap().as_event().unwrap().clone();
+    assert_eq!(item.content().as_message().unwrap().body(), "local edit");
+}
+
 #[async_test]
 async fn test_relations_edit_overrides_pending_edit_poll() {
     let timeline = TestTimeline::new();
@@ -68,7 +68,10 @@ use super::{
     TimelineItem,
 };
 use crate::{
-    timeline::pinned_events_loader::PinnedEventsRoom, unable_to_decrypt_hook::UtdHookManager,
+    timeline::{
+        pinned_events_loader::PinnedEventsRoom, threaded_events_loader::ThreadedEventsRoom,
+    },
+    unable_to_decrypt_hook::UtdHookManager,
 };
 
 mod basic;
@@ -82,6 +85,7 @@ mod reactions;
 mod read_receipts;
 mod redaction;
 mod shields;
+mod thread;
 mod virt;
 
 struct TestTimeline {
@@ -159,6 +163,20 @@ impl TestTimeline {
         }
     }
 
+    fn with_focus(focus: TimelineFocus) -> Self {
+        Self {
+            controller: TimelineController::new(
+                TestRoomDataProvider::default(),
+                focus,
+                None,
+                None,
+                Some(false),
+            ),
+            event_builder: EventBuilder::new(),
+            factory: EventFactory::new(),
+        }
+    }
+
     fn with_settings(mut self, settings: TimelineSettings) -> Self {
         self.controller = self.controller.with_settings(settings);
         self
@@ -369,6 +387,24 @@ impl PinnedEventsRoom for TestRoomDataProvider {
     }
 }
 
+impl ThreadedEventsRoom for TestRoomDataProvider {
+    fn load_thread_root<'a>(
+        &'a self,
+        _root_event_id: &'a EventId,
+    ) -> BoxFuture<'a, Result<SyncTimelineEvent, PaginatorError>> {
+        unimplemented!();
+    }
+
+    fn load_threaded_events<'a>(
+        &'a self,
+        _root_event_id: &'a EventId,
+        _from: Option<String>,
+        _limit: UInt,
+    ) -> BoxFuture<'a, Result<(Vec<SyncTimelineEvent>, Option<String>), PaginatorError>> {
+        unimplemented!();
+    }
+}
+
 impl RoomDataProvider for TestRoomDataProvider {
     fn own_user_id(&self) -> &UserId {
         &ALICE
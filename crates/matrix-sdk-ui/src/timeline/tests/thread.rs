@@ -0,0 +1,104 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk_test::{async_test, BOB, CAROL};
+use ruma::{
+    event_id,
+    events::receipt::{ReceiptThread, ReceiptType},
+    owned_event_id,
+};
+
+use super::TestTimeline;
+use crate::timeline::{controller::TimelineSettings, TimelineFocus};
+
+#[async_test]
+async fn test_thread_focus_only_adds_threaded_events_from_sync() {
+    let root_event_id = event_id!("$root");
+    let timeline = TestTimeline::with_focus(TimelineFocus::Thread {
+        root_event_id: root_event_id.to_owned(),
+        num_events: 20,
+    });
+
+    let f = &timeline.factory;
+    timeline.handle_live_event(f.text_msg("Root").sender(&BOB).event_id(root_event_id)).await;
+    timeline.handle_live_event(f.text_msg("Not in the thread").sender(&BOB)).await;
+    timeline
+        .handle_live_event(
+            f.text_msg("In the thread").sender(&CAROL).in_thread(root_event_id, root_event_id),
+        )
+        .await;
+    timeline
+        .handle_live_event(
+            f.text_msg("In another thread")
+                .sender(&CAROL)
+                .in_thread(event_id!("$other_root"), event_id!("$other_root")),
+        )
+        .await;
+
+    let items = timeline.controller.items().await;
+    let bodies: Vec<_> = items
+        .iter()
+        .filter_map(|item| item.as_event())
+        .map(|event| event.content().as_message().unwrap().body().to_owned())
+        .collect();
+    assert_eq!(bodies, ["Root", "In the thread"]);
+}
+
+#[async_test]
+async fn test_thread_focus_handles_thread_receipts() {
+    let root_event_id = event_id!("$root");
+    let reply_event_id = event_id!("$reply");
+    let timeline = TestTimeline::with_focus(TimelineFocus::Thread {
+        root_event_id: root_event_id.to_owned(),
+        num_events: 20,
+    })
+    .with_settings(TimelineSettings { track_read_receipts: true, ..Default::default() });
+
+    let f = &timeline.factory;
+    timeline.handle_live_event(f.text_msg("Root").sender(&CAROL).event_id(root_event_id)).await;
+    timeline
+        .handle_live_event(
+            f.text_msg("Reply")
+                .sender(&CAROL)
+                .event_id(reply_event_id)
+                .in_thread(root_event_id, root_event_id),
+        )
+        .await;
+
+    // A receipt in the main timeline doesn't cover the threaded events.
+    timeline
+        .handle_read_receipts([(
+            reply_event_id.to_owned(),
+            ReceiptType::Read,
+            BOB.to_owned(),
+            ReceiptThread::Main,
+        )])
+        .await;
+
+    let reply = timeline.controller.items().await.last().unwrap().as_event().unwrap().clone();
+    assert!(reply.read_receipts().get(*BOB).is_none());
+
+    // A receipt in the thread does.
+    timeline
+        .handle_read_receipts([(
+            reply_event_id.to_owned(),
+            ReceiptType::Read,
+            BOB.to_owned(),
+            ReceiptThread::Thread(owned_event_id!("$root")),
+        )])
+        .await;
+
+    let reply = timeline.controller.items().await.last().unwrap().as_event().unwrap().clone();
+    assert!(reply.read_receipts().get(*BOB).is_some());
+}
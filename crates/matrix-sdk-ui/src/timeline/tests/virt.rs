@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use assert_matches::assert_matches;
 use assert_matches2::assert_let;
 use chrono::{Datelike, Local, TimeZone};
@@ -21,11 +23,15 @@ use matrix_sdk_test::{async_test, ALICE, BOB};
 use ruma::{
     event_id,
     events::{room::message::RoomMessageEventContent, AnyMessageLikeEventContent},
+    MilliSecondsSinceUnixEpoch, UserId,
 };
 use stream_assert::assert_next_matches;
 
 use super::TestTimeline;
-use crate::timeline::{traits::RoomDataProvider as _, VirtualTimelineItem};
+use crate::timeline::{
+    controller::TimelineSettings, traits::RoomDataProvider as _, DateDividerMode,
+    VirtualTimelineItem,
+};
 
 #[async_test]
 async fn test_date_divider() {
@@ -229,3 +235,122 @@ async fn test_update_read_marker() {
 
     assert!(stream.next().now_or_never().is_none());
 }
+
+#[async_test]
+async fn test_date_divider_disabled() {
+    let timeline = TestTimeline::new().with_settings(TimelineSettings {
+        date_divider_mode: DateDividerMode::Disabled,
+        ..Default::default()
+    });
+    let mut stream = timeline.subscribe().await;
+
+    let f = &timeline.factory;
+
+    timeline.handle_live_event(f.text_msg("First day").sender(*ALICE)).await;
+    timeline
+        .handle_live_event(f.text_msg("Second day").sender(*BOB).server_ts(86_400_000 + 3_600_000))
+        .await;
+
+    // Timeline: [A, B], without any date divider.
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[async_test]
+async fn test_custom_date_divider_grouping() {
+    // Group the events by hour.
+    let timeline = TestTimeline::new().with_settings(TimelineSettings {
+        date_divider_mode: DateDividerMode::Custom(Arc::new(
+            |ts: MilliSecondsSinceUnixEpoch, _: &UserId| u64::from(ts.0) / 3_600_000,
+        )),
+        ..Default::default()
+    });
+    let mut stream = timeline.subscribe().await;
+
+    let f = &timeline.factory;
+
+    timeline.handle_live_event(f.text_msg("A").sender(*ALICE).server_ts(0)).await;
+    timeline.handle_live_event(f.text_msg("B").sender(*BOB).server_ts(60_000)).await;
+    timeline.handle_live_event(f.text_msg("C").sender(*ALICE).server_ts(3_600_000)).await;
+
+    // Timeline: [date-divider, A, B, date-divider, C].
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+    let date_divider = assert_next_matches!(stream, VectorDiff::PushFront { value } => value);
+    assert!(date_divider.is_date_divider());
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+    let date_divider =
+        assert_next_matches!(stream, VectorDiff::Insert { index: 3, value } => value);
+    assert_let!(Some(VirtualTimelineItem::DateDivider(ts)) = date_divider.as_virtual());
+    assert_eq!(u64::from(ts.0), 3_600_000);
+
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[async_test]
+async fn test_custom_date_divider_grouping_by_sender() {
+    // Group the consecutive events of the same sender.
+    let timeline = TestTimeline::new().with_settings(TimelineSettings {
+        date_divider_mode: DateDividerMode::Custom(Arc::new(
+            |_: MilliSecondsSinceUnixEpoch, sender: &UserId| u64::from(sender == *BOB),
+        )),
+        ..Default::default()
+    });
+    let mut stream = timeline.subscribe().await;
+
+    let f = &timeline.factory;
+
+    timeline.handle_live_event(f.text_msg("A").sender(*ALICE).server_ts(0)).await;
+    timeline.handle_live_event(f.text_msg("B").sender(*ALICE).server_ts(60_000)).await;
+    timeline.handle_live_event(f.text_msg("C").sender(*BOB).server_ts(120_000)).await;
+
+    // Timeline: [date-divider, A, B, date-divider, C].
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+    let date_divider = assert_next_matches!(stream, VectorDiff::PushFront { value } => value);
+    assert!(date_divider.is_date_divider());
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+    let date_divider =
+        assert_next_matches!(stream, VectorDiff::Insert { index: 3, value } => value);
+    assert_let!(Some(VirtualTimelineItem::DateDivider(ts)) = date_divider.as_virtual());
+    assert_eq!(u64::from(ts.0), 120_000);
+
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[async_test]
+async fn test_read_marker_item_disabled() {
+    let timeline = TestTimeline::new()
+        .with_settings(TimelineSettings { add_read_marker_item: false, ..Default::default() });
+    let mut stream = timeline.subscribe().await;
+
+    let f = &timeline.factory;
+    timeline.handle_live_event(f.text_msg("A").sender(*ALICE)).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let event_id = item.as_event().unwrap().event_id().unwrap().to_owned();
+    let date_divider = assert_next_matches!(stream, VectorDiff::PushFront { value } => value);
+    assert!(date_divider.is_date_divider());
+
+    timeline.controller.handle_fully_read_marker(event_id).await;
+    timeline.handle_live_event(f.text_msg("B").sender(*BOB)).await;
+
+    // No read marker is inserted after A.
+    // Timeline: [date-divider, A, B].
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.is_remote_event());
+    assert!(stream.next().now_or_never().is_none());
+}
@@ -0,0 +1,229 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt::Formatter, sync::Arc};
+
+use matrix_sdk::{
+    event_cache::paginator::PaginatorError, BoxFuture, Room, SendOutsideWasm, SyncOutsideWasm,
+};
+use matrix_sdk_base::deserialized_responses::SyncTimelineEvent;
+use ruma::{
+    api::client::relations::get_relating_events_with_rel_type,
+    assign,
+    events::{
+        relation::RelationType, AnySyncMessageLikeEvent, AnySyncTimelineEvent, SyncMessageLikeEvent,
+    },
+    serde::Raw,
+    EventId, OwnedEventId, UInt,
+};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+/// Where the next backwards pagination of a thread starts from.
+#[derive(Debug)]
+enum ThreadPaginationToken {
+    /// No pagination happened yet, start from the latest event of the thread.
+    Latest,
+
+    /// Continue from the given `/relations` token.
+    Token(String),
+
+    /// All the threaded events and the thread root have been loaded.
+    HitRoot,
+}
+
+/// Utility to load the events of a thread, from the latest one back to the
+/// thread root.
+pub struct ThreadedEventsLoader {
+    /// Backend to load threaded events.
+    room: Arc<dyn ThreadedEventsRoom>,
+
+    /// The root of the thread.
+    root_event_id: OwnedEventId,
+
+    /// The token of the next backwards pagination.
+    ///
+    /// The lock is held during a whole pagination, so that concurrent
+    /// paginations don't load the same events twice.
+    token: Mutex<ThreadPaginationToken>,
+}
+
+impl ThreadedEventsLoader {
+    /// Creates a new `ThreadedEventsLoader` instance.
+    pub fn new(room: Arc<dyn ThreadedEventsRoom>, root_event_id: OwnedEventId) -> Self {
+        Self { room, root_event_id, token: Mutex::new(ThreadPaginationToken::Latest) }
+    }
+
+    /// The root of the thread.
+    pub fn root_event_id(&self) -> &EventId {
+        &self.root_event_id
+    }
+
+    /// Loads up to `num_events` threaded events, older than the ones loaded
+    /// by the previous calls, and the thread root once all the threaded
+    /// events have been loaded.
+    ///
+    /// It returns the loaded events in reverse chronological order, that is
+    /// `events[0]` is the most recent one, as expected when prepending them to
+    /// the timeline, and whether the thread root has been reached.
+    pub async fn paginate_backwards(
+        &self,
+        num_events: u16,
+    ) -> Result<(Vec<SyncTimelineEvent>, bool), PaginatorError> {
+        let mut token = self.token.lock().await;
+
+        let from = match &*token {
+            ThreadPaginationToken::Latest => None,
+            ThreadPaginationToken::Token(token) => Some(token.clone()),
+            ThreadPaginationToken::HitRoot => return Ok((Vec::new(), true)),
+        };
+
+        // The server returns the most recent events first.
+        let (mut events, next_token) =
+            self.room.load_threaded_events(&self.root_event_id, from, num_events.into()).await?;
+
+        if let Some(next_token) = next_token {
+            *token = ThreadPaginationToken::Token(next_token);
+            return Ok((events, false));
+        }
+
+        debug!(root = %self.root_event_id, "Loaded all the threaded events, loading the root");
+
+        let root = self.room.load_thread_root(&self.root_event_id).await?;
+        events.push(root);
+
+        *token = ThreadPaginationToken::HitRoot;
+
+        Ok((events, true))
+    }
+}
+
+pub trait ThreadedEventsRoom: SendOutsideWasm + SyncOutsideWasm {
+    /// Load the root event of a thread, using the cache or network.
+    fn load_thread_root<'a>(
+        &'a self,
+        root_event_id: &'a EventId,
+    ) -> BoxFuture<'a, Result<SyncTimelineEvent, PaginatorError>>;
+
+    /// Load up to `limit` events in a thread, from the most recent one to the
+    /// oldest one, starting at the `from` pagination token if any.
+    ///
+    /// It returns the loaded events and the token to continue the pagination,
+    /// if there are older events in the thread.
+    fn load_threaded_events<'a>(
+        &'a self,
+        root_event_id: &'a EventId,
+        from: Option<String>,
+        limit: UInt,
+    ) -> BoxFuture<'a, Result<(Vec<SyncTimelineEvent>, Option<String>), PaginatorError>>;
+}
+
+impl ThreadedEventsRoom for Room {
+    fn load_thread_root<'a>(
+        &'a self,
+        root_event_id: &'a EventId,
+    ) -> BoxFuture<'a, Result<SyncTimelineEvent, PaginatorError>> {
+        Box::pin(async move {
+            if let Ok((cache, _handles)) = self.event_cache().await {
+                if let Some(event) = cache.event(root_event_id).await {
+                    debug!("Loaded thread root {root_event_id} from cache");
+                    return Ok(event);
+                }
+            }
+
+            debug!("Loading thread root {root_event_id} from HS");
+            self.event(root_event_id, None)
+                .await
+                .map(Into::into)
+                .map_err(|err| PaginatorError::SdkError(Box::new(err)))
+        })
+    }
+
+    fn load_threaded_events<'a>(
+        &'a self,
+        root_event_id: &'a EventId,
+        from: Option<String>,
+        limit: UInt,
+    ) -> BoxFuture<'a, Result<(Vec<SyncTimelineEvent>, Option<String>), PaginatorError>> {
+        Box::pin(async move {
+            let request = assign!(
+                get_relating_events_with_rel_type::v1::Request::new(
+                    self.room_id().to_owned(),
+                    root_event_id.to_owned(),
+                    RelationType::Thread,
+                ),
+                { from, limit: Some(limit) }
+            );
+
+            let response = self
+                .client()
+                .send(request)
+                .await
+                .map_err(|err| PaginatorError::SdkError(Box::new(err)))?;
+
+            let mut events = Vec::with_capacity(response.chunk.len());
+
+            for event in response.chunk {
+                let event = event.cast::<AnySyncTimelineEvent>();
+
+                let event = if let Ok(AnySyncTimelineEvent::MessageLike(
+                    AnySyncMessageLikeEvent::RoomEncrypted(SyncMessageLikeEvent::Original(_)),
+                )) = event.deserialize()
+                {
+                    match self.decrypt_event(event.cast_ref()).await {
+                        Ok(event) => event.into(),
+                        Err(_) => SyncTimelineEvent::new(event),
+                    }
+                } else {
+                    SyncTimelineEvent::new(event)
+                };
+
+                events.push(event);
+            }
+
+            Ok((events, response.next_batch))
+        })
+    }
+}
+
+/// Get the root of the thread the given event is part of, if any.
+///
+/// This only looks at the unencrypted `m.relates_to` field of the content, so
+/// it works with encrypted events too.
+pub(super) fn thread_root(event: &Raw<AnySyncTimelineEvent>) -> Option<OwnedEventId> {
+    #[derive(Deserialize)]
+    struct RelatesTo {
+        rel_type: RelationType,
+        event_id: OwnedEventId,
+    }
+
+    #[derive(Deserialize)]
+    struct Content {
+        #[serde(rename = "m.relates_to")]
+        relates_to: Option<RelatesTo>,
+    }
+
+    let relates_to = event.get_field::<Content>("content").ok()??.relates_to?;
+    (relates_to.rel_type == RelationType::Thread).then_some(relates_to.event_id)
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for ThreadedEventsLoader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadedEventsLoader")
+            .field("root_event_id", &self.root_event_id)
+            .finish_non_exhaustive()
+    }
+}
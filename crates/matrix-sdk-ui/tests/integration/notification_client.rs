@@ -11,11 +11,12 @@ use matrix_sdk_test::{
 };
 use matrix_sdk_ui::{
     notification_client::{
-        NotificationClient, NotificationEvent, NotificationProcessSetup, NotificationStatus,
+        Error, NotificationBatchStatus, NotificationClient, NotificationEvent,
+        NotificationProcessSetup, NotificationRequest, NotificationStatus,
     },
     sync_service::SyncService,
 };
-use ruma::{event_id, events::TimelineEventType, room_id, user_id};
+use ruma::{event_id, events::TimelineEventType, room_id, user_id, EventId, RoomId};
 use serde_json::json;
 use wiremock::{
     matchers::{header, method, path},
@@ -306,3 +307,97 @@ async fn test_notification_client_sliding_sync() {
     assert_eq!(item.room_computed_display_name, sender_display_name);
     assert_eq!(item.is_noisy, Some(false));
 }
+
+#[async_test]
+async fn test_notification_client_batch() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let sender = user_id!("@user:example.org");
+    let message = |room_id: &RoomId, event_id: &EventId| {
+        json!({
+            "content": {
+                "body": "Hello world!",
+                "msgtype": "m.text",
+            },
+            "room_id": room_id,
+            "event_id": event_id,
+            "origin_server_ts": 152049794,
+            "sender": sender,
+            "type": "m.room.message",
+        })
+    };
+
+    // This event is found by the sliding sync.
+    let synced_room_id = room_id!("!synced:example.org");
+    let synced_event_id = event_id!("$synced");
+    // This event isn't, and the `/context` query takes too long.
+    let slow_room_id = room_id!("!slow:example.org");
+    let slow_event_id = event_id!("$slow");
+    // This event isn't either, and the room is unknown.
+    let unknown_room_id = room_id!("!unknown:example.org");
+    let unknown_event_id = event_id!("$unknown");
+
+    // The parent client knows about the slow room.
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(slow_room_id));
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    let synced_event = message(synced_room_id, synced_event_id);
+    Mock::given(SlidingSyncMatcher)
+        .respond_with(move |request: &Request| {
+            let partial_request: PartialSlidingSyncRequest = request.body_json().unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({
+                "txn_id": partial_request.txn_id,
+                "pos": "0",
+                "rooms": {
+                    "!synced:example.org": {
+                        "name": "Synced",
+                        "initial": true,
+                        "timeline": [synced_event.clone()],
+                    },
+                },
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path(format!("/_matrix/client/r0/rooms/{slow_room_id}/context/{slow_event_id}")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(
+                    json!({ "event": message(slow_room_id, slow_event_id), "state": [] }),
+                )
+                .set_delay(Duration::from_secs(10)),
+        )
+        .mount(&server)
+        .await;
+
+    let dummy_sync_service = Arc::new(SyncService::builder(client.clone()).build().await.unwrap());
+    let process_setup =
+        NotificationProcessSetup::SingleProcess { sync_service: dummy_sync_service };
+    let notification_client = NotificationClient::new(client, process_setup).await.unwrap();
+
+    let requests = [
+        (synced_room_id, synced_event_id),
+        (slow_room_id, slow_event_id),
+        (unknown_room_id, unknown_event_id),
+    ]
+    .map(|(room_id, event_id)| NotificationRequest {
+        room_id: room_id.to_owned(),
+        event_id: event_id.to_owned(),
+    });
+
+    let results = notification_client.get_notifications(&requests, Duration::from_secs(2)).await;
+    assert_eq!(results.len(), 3);
+
+    assert_matches!(&results[0], NotificationBatchStatus::Event(item) => {
+        assert_matches!(&item.event, NotificationEvent::Timeline(event) => {
+            assert_eq!(event.event_id(), synced_event_id);
+        });
+    });
+    assert_matches!(&results[1], NotificationBatchStatus::TimedOut);
+    assert_matches!(&results[2], NotificationBatchStatus::Error(Error::UnknownRoom));
+}
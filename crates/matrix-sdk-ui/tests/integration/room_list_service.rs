@@ -1728,6 +1728,116 @@ async fn test_dynamic_entries_stream() -> Result<(), Error> {
     Ok(())
 }
 
+#[async_test]
+async fn test_dynamic_entries_stream_filters_out_hidden_rooms() -> Result<(), Error> {
+    let (_client, server, room_list) = new_room_list_service().await?;
+
+    let sync = room_list.sync();
+    pin_mut!(sync);
+
+    let all_rooms = room_list.all_rooms().await?;
+
+    let (dynamic_entries_stream, dynamic_entries) = all_rooms.entries_with_dynamic_adapters(5);
+    pin_mut!(dynamic_entries_stream);
+
+    sync_then_assert_request_and_fake_response! {
+        [server, room_list, sync]
+        states = Init => SettingUp,
+        assert request >= {
+            "lists": {
+                ALL_ROOMS: {
+                    "timeline_limit": 1,
+                },
+            },
+        },
+        respond with = {
+            "pos": "0",
+            "lists": {
+                ALL_ROOMS: {
+                    "count": 2,
+                },
+            },
+            "rooms": {
+                "!r0:bar.org": {
+                    "initial": true,
+                    "bump_stamp": 1,
+                    "required_state": [],
+                },
+                "!r1:bar.org": {
+                    "initial": true,
+                    "bump_stamp": 2,
+                    "required_state": [],
+                },
+            },
+            "extensions": {
+                "account_data": {
+                    "global": [
+                        {
+                            "type": "org.matrix.custom.hidden_rooms",
+                            "content": {
+                                "rooms": ["!r1:bar.org"],
+                            },
+                        },
+                    ],
+                },
+            },
+        },
+    };
+
+    dynamic_entries.set_filter(Box::new(new_filter_non_left()));
+
+    // The hidden room isn't part of the entries.
+    assert_entries_batch! {
+        [dynamic_entries_stream]
+        reset [ "!r0:bar.org" ];
+        end;
+    };
+    assert_pending!(dynamic_entries_stream);
+
+    sync_then_assert_request_and_fake_response! {
+        [server, room_list, sync]
+        states = SettingUp => Running,
+        assert request >= {
+            "lists": {
+                ALL_ROOMS: {
+                    "timeline_limit": 1,
+                },
+            },
+        },
+        respond with = {
+            "pos": "1",
+            "lists": {
+                ALL_ROOMS: {
+                    "count": 2,
+                },
+            },
+            "rooms": {},
+            "extensions": {
+                "account_data": {
+                    "global": [
+                        {
+                            "type": "org.matrix.custom.hidden_rooms",
+                            "content": {
+                                "rooms": [],
+                            },
+                        },
+                    ],
+                },
+            },
+        },
+    };
+
+    // Once unhidden, the room is back, and it's the most recent one.
+    assert_entries_batch! {
+        [dynamic_entries_stream]
+        insert [ 0 ] [ "!r1:bar.org" ];
+        end;
+    };
+    assert_pending!(dynamic_entries_stream);
+
+    Ok(())
+}
+
 #[async_test]
 async fn test_room_sorting() -> Result<(), Error> {
     let (_client, server, room_list) = new_room_list_service().await?;
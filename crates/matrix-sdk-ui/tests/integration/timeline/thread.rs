@@ -0,0 +1,123 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests specific to a timeline focused on a thread.
+
+use std::time::Duration;
+
+use matrix_sdk::{config::SyncSettings, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::{
+    async_test, event_factory::EventFactory, mocks::mock_encryption_state, JoinedRoomBuilder,
+    SyncResponseBuilder, ALICE, BOB,
+};
+use matrix_sdk_ui::{timeline::TimelineFocus, Timeline};
+use ruma::{event_id, room_id};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path_regex, query_param, query_param_is_missing},
+    Mock, ResponseTemplate,
+};
+
+use crate::mock_sync;
+
+#[async_test]
+async fn test_thread_focus_paginates_backwards_twice() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut sync_response_builder = SyncResponseBuilder::new();
+    sync_response_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    // Mark the room as joined.
+    mock_sync(&server, sync_response_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let f = EventFactory::new().room(room_id);
+    let root_event_id = event_id!("$root");
+    let reply = |body: &str, event_id| {
+        f.text_msg(body)
+            .sender(*ALICE)
+            .event_id(event_id)
+            .in_thread(root_event_id, root_event_id)
+            .into_raw_timeline()
+    };
+
+    // The server returns the most recent threaded events first.
+    Mock::given(method("GET"))
+        .and(path_regex(r"/relations/.*/m\.thread$"))
+        .and(query_param_is_missing("from"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [reply("fourth", event_id!("$4")), reply("third", event_id!("$3"))],
+            "next_batch": "prev1",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/relations/.*/m\.thread$"))
+        .and(query_param("from", "prev1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [reply("second", event_id!("$2")), reply("first", event_id!("$1"))],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/event/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(
+            f.text_msg("root").sender(*BOB).event_id(root_event_id).into_raw_timeline(),
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    mock_encryption_state(&server, false).await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = Timeline::builder(&room)
+        .with_focus(TimelineFocus::Thread {
+            root_event_id: root_event_id.to_owned(),
+            num_events: 2,
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let bodies = || async {
+        timeline
+            .items()
+            .await
+            .iter()
+            .filter_map(|item| item.as_event())
+            .map(|event| event.content().as_message().unwrap().body().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(bodies().await, ["third", "fourth"]);
+
+    let hit_root = timeline.focused_paginate_backwards(2).await.unwrap();
+    assert!(hit_root);
+
+    assert_eq!(bodies().await, ["root", "first", "second", "third", "fourth"]);
+
+    // There is nothing left to load.
+    let hit_root = timeline.focused_paginate_backwards(2).await.unwrap();
+    assert!(hit_root);
+
+    assert_eq!(bodies().await, ["root", "first", "second", "third", "fourth"]);
+}
@@ -14,10 +14,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, BTreeSet};
+
 use matrix_sdk_base::{
     media::{MediaFormat, MediaRequestParameters},
     store::StateStoreExt,
-    StateStoreDataKey, StateStoreDataValue,
+    HiddenRoomsEventContent, StateStoreDataKey, StateStoreDataValue,
 };
 use mime::Mime;
 use ruma::{
@@ -47,7 +49,7 @@ use ruma::{
     ClientSecret, MxcUri, OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomId, SessionId, UInt, UserId,
 };
 use serde::Deserialize;
-use tracing::error;
+use tracing::{debug, error};
 
 use crate::{config::RequestConfig, Client, Error, Result};
 
@@ -257,6 +259,130 @@ impl Account {
         Ok(upload_response.content_uri)
     }
 
+    /// Resize, upload and set the account's avatar.
+    ///
+    /// The image is resized according to `resize_policy` before being
+    /// uploaded, then the user's avatar is set to the MXC URI of the uploaded
+    /// file.
+    ///
+    /// The homeserver usually updates the avatar in the member event of every
+    /// joined room, but the rooms where the user set a different avatar are
+    /// left untouched. If `update_room_overrides` is `true`, the avatar is
+    /// also set in the member event of those rooms. Failing to update a room
+    /// doesn't make this method fail, since the global avatar has already been
+    /// changed at that point.
+    ///
+    /// Returns the MXC URI of the uploaded avatar.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes of the image.
+    ///
+    /// * `content_type` - The type of the image.
+    ///
+    /// * `resize_policy` - How to resize the image before uploading it.
+    ///
+    /// * `update_room_overrides` - Whether to also replace the avatars set
+    ///   specifically for some rooms.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::fs;
+    /// # use matrix_sdk::{Client, AvatarResizePolicy};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let image = fs::read("/home/example/selfie.jpg")?;
+    ///
+    /// let avatar_url = client
+    ///     .account()
+    ///     .set_avatar_from_bytes(
+    ///         image,
+    ///         &mime::IMAGE_JPEG,
+    ///         AvatarResizePolicy::CropToSquare { size: 512 },
+    ///         false,
+    ///     )
+    ///     .await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(feature = "image")]
+    pub async fn set_avatar_from_bytes(
+        &self,
+        data: Vec<u8>,
+        content_type: &Mime,
+        resize_policy: AvatarResizePolicy,
+        update_room_overrides: bool,
+    ) -> Result<OwnedMxcUri> {
+        use ruma::events::room::member::{MembershipState, RoomMemberEventContent};
+        use tracing::warn;
+
+        let user_id = self.client.user_id().ok_or(Error::AuthenticationRequired)?.to_owned();
+
+        // Decoding and resizing the image is CPU-bound, keep it off the async
+        // executor.
+        let content_type = content_type.clone();
+        let resize = move || resize_policy.apply(data, &content_type);
+        #[cfg(not(target_arch = "wasm32"))]
+        let (data, content_type) =
+            tokio::task::spawn_blocking(resize).await.expect("Task join error")?;
+        #[cfg(target_arch = "wasm32")]
+        let (data, content_type) = resize()?;
+
+        // Remember the previous avatar, to know which rooms had a different one.
+        let previous_avatar_url =
+            if update_room_overrides { self.get_avatar_url().await? } else { None };
+
+        let avatar_url = self.upload_avatar(&content_type, data).await?;
+
+        let _ = self
+            .client
+            .store()
+            .set_kv_data(
+                StateStoreDataKey::UserAvatarUrl(&user_id),
+                StateStoreDataValue::UserAvatarUrl(avatar_url.clone()),
+            )
+            .await;
+
+        if update_room_overrides {
+            for room in self.client.joined_rooms() {
+                let member = match room.get_member_no_sync(&user_id).await {
+                    Ok(Some(member)) => member,
+                    Ok(None) => continue,
+                    Err(error) => {
+                        warn!(room_id = %room.room_id(), "Couldn't load own member: {error}");
+                        continue;
+                    }
+                };
+
+                let Some(member_content) = member.event().original_content() else {
+                    continue;
+                };
+
+                let is_override = member_content.avatar_url.as_ref().is_some_and(|url| {
+                    Some(url) != previous_avatar_url.as_ref() && *url != avatar_url
+                });
+
+                if !is_override {
+                    continue;
+                }
+
+                let content = assign!(RoomMemberEventContent::new(MembershipState::Join), {
+                    displayname: member_content.displayname.clone(),
+                    avatar_url: Some(avatar_url.clone()),
+                    is_direct: member_content.is_direct,
+                });
+
+                if let Err(error) = room.send_state_event_for_key(&user_id, content).await {
+                    warn!(room_id = %room.room_id(), "Couldn't update the room avatar: {error}");
+                }
+            }
+        }
+
+        Ok(avatar_url)
+    }
+
     /// Get the profile of the account.
     ///
     /// Allows to get both the display name and avatar URL in a single call.
@@ -865,7 +991,13 @@ impl Account {
         };
 
         for user_id in user_ids {
-            content.entry(user_id.into()).or_default().push(room_id.to_owned());
+            let room_ids = content.entry(user_id.into()).or_default();
+
+            // The room might have been added concurrently, when repairing the DM rooms
+            // after a sync.
+            if !room_ids.iter().any(|id| id == room_id) {
+                room_ids.push(room_id.to_owned());
+            }
         }
 
         // TODO: We should probably save the fact that we need to send this out
@@ -876,6 +1008,60 @@ impl Account {
         Ok(())
     }
 
+    /// Add the given rooms to the `m.direct` account data, as DMs with the
+    /// associated users, if they aren't already, and remove the given left
+    /// rooms from it.
+    ///
+    /// Unlike [`Account::mark_as_dm()`], the account data is only uploaded if
+    /// it actually changed.
+    pub(crate) async fn repair_dm_rooms(
+        &self,
+        dm_rooms: &BTreeMap<OwnedRoomId, Vec<OwnedUserId>>,
+        left_rooms: &BTreeSet<OwnedRoomId>,
+    ) -> Result<()> {
+        use ruma::events::direct::DirectEventContent;
+
+        let _guard = self.client.locks().mark_as_dm_lock.lock().await;
+
+        let mut content = self
+            .fetch_account_data(GlobalAccountDataEventType::Direct)
+            .await?
+            .map(|raw_content| raw_content.deserialize_as::<DirectEventContent>())
+            .transpose()?
+            .unwrap_or_default();
+
+        let mut changed = false;
+
+        for (room_id, user_ids) in dm_rooms {
+            for user_id in user_ids {
+                let room_ids = content.entry(user_id.into()).or_default();
+
+                if !room_ids.contains(room_id) {
+                    room_ids.push(room_id.clone());
+                    changed = true;
+                }
+            }
+        }
+
+        if !left_rooms.is_empty() {
+            content.retain(|_, room_ids| {
+                let num_rooms = room_ids.len();
+                room_ids.retain(|room_id| !left_rooms.contains(room_id));
+                changed |= room_ids.len() != num_rooms;
+
+                // Don't keep users we have no DM with anymore.
+                !room_ids.is_empty()
+            });
+        }
+
+        if changed {
+            debug!("Repairing the DM rooms in m.direct");
+            self.set_account_data(content).await?;
+        }
+
+        Ok(())
+    }
+
     /// Adds the given user ID to the account's ignore list.
     pub async fn ignore_user(&self, user_id: &UserId) -> Result<()> {
         let mut ignored_user_list = self.get_ignored_user_list_event_content().await?;
@@ -910,6 +1096,52 @@ impl Account {
         Ok(ignored_user_list)
     }
 
+    /// Get the rooms that the user has hidden from their room list.
+    ///
+    /// See [`HiddenRoomsEventContent`].
+    pub async fn hidden_rooms(&self) -> Result<BTreeSet<OwnedRoomId>> {
+        Ok(self.get_hidden_rooms_event_content().await?.rooms)
+    }
+
+    /// Hides the given room from the user's room list.
+    ///
+    /// The room isn't left, it is still synced and can be opened like any
+    /// other room, but [`Room::is_hidden()`] will return `true` once the
+    /// updated list is received from the homeserver.
+    ///
+    /// [`Room::is_hidden()`]: matrix_sdk_base::Room::is_hidden
+    pub async fn hide_room(&self, room_id: &RoomId) -> Result<()> {
+        let mut hidden_rooms = self.get_hidden_rooms_event_content().await?;
+
+        if hidden_rooms.rooms.insert(room_id.to_owned()) {
+            self.set_account_data(hidden_rooms).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Shows again the given room in the user's room list, after it has been
+    /// hidden with [`Account::hide_room()`].
+    pub async fn unhide_room(&self, room_id: &RoomId) -> Result<()> {
+        let mut hidden_rooms = self.get_hidden_rooms_event_content().await?;
+
+        if hidden_rooms.rooms.remove(room_id) {
+            self.set_account_data(hidden_rooms).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_hidden_rooms_event_content(&self) -> Result<HiddenRoomsEventContent> {
+        let hidden_rooms = self
+            .account_data::<HiddenRoomsEventContent>()
+            .await?
+            .map(|c| c.deserialize())
+            .transpose()?
+            .unwrap_or_default();
+        Ok(hidden_rooms)
+    }
+
     /// Get the current push rules from storage.
     ///
     /// If no push rules event was found, or it fails to deserialize, a ruleset
@@ -988,3 +1220,135 @@ fn get_raw_content<Ev, C>(raw: Option<Raw<Ev>>) -> Result<Option<Raw<C>>> {
         .transpose()?
         .map(|get_raw| get_raw.content))
 }
+
+/// How to resize an image before uploading it as an avatar, with
+/// [`Account::set_avatar_from_bytes()`].
+#[cfg(feature = "image")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AvatarResizePolicy {
+    /// Upload the image as is.
+    Original,
+
+    /// Scale the image down, keeping its aspect ratio, so that it fits in a
+    /// square of `size` pixels.
+    ///
+    /// Images that already fit are uploaded as is.
+    Fit {
+        /// The maximum width and height of the image, in pixels.
+        size: u32,
+    },
+
+    /// Crop the largest square at the center of the image, and scale it down
+    /// so that its sides are at most `size` pixels.
+    CropToSquare {
+        /// The maximum side of the image, in pixels.
+        size: u32,
+    },
+}
+
+#[cfg(feature = "image")]
+impl AvatarResizePolicy {
+    /// Resize the given image according to this policy.
+    ///
+    /// Returns the data to upload and its content type. JPEG images are
+    /// encoded as JPEG again, all the other formats are encoded as PNG.
+    fn apply(
+        self,
+        data: Vec<u8>,
+        content_type: &Mime,
+    ) -> Result<(Vec<u8>, Mime), crate::media::MediaError> {
+        use std::io::Cursor;
+
+        use image::{imageops::FilterType, ImageFormat};
+
+        let (size, crop) = match self {
+            Self::Original => return Ok((data, content_type.clone())),
+            Self::Fit { size } => (size.max(1), false),
+            Self::CropToSquare { size } => (size.max(1), true),
+        };
+
+        let format = ImageFormat::from_mime_type(content_type.essence_str());
+        let mut image = match format {
+            Some(format) => image::load_from_memory_with_format(&data, format)?,
+            None => image::load_from_memory(&data)?,
+        };
+
+        let needs_crop = crop && image.width() != image.height();
+        let needs_scaling = image.width() > size || image.height() > size;
+
+        if !needs_crop && !needs_scaling {
+            return Ok((data, content_type.clone()));
+        }
+
+        if needs_crop {
+            let side = image.width().min(image.height());
+            image =
+                image.crop_imm((image.width() - side) / 2, (image.height() - side) / 2, side, side);
+        }
+
+        if image.width() > size || image.height() > size {
+            image = image.resize(size, size, FilterType::Lanczos3);
+        }
+
+        let (format, content_type) = if format == Some(ImageFormat::Jpeg) {
+            (ImageFormat::Jpeg, mime::IMAGE_JPEG)
+        } else {
+            (ImageFormat::Png, mime::IMAGE_PNG)
+        };
+
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format)?;
+
+        Ok((data, content_type))
+    }
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageFormat};
+
+    use super::AvatarResizePolicy;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::new_rgba8(width, height)
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn dimensions(data: &[u8]) -> (u32, u32) {
+        let image = image::load_from_memory(data).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn test_avatar_resize_policy() {
+        let data = png(400, 200);
+
+        let (original, content_type) =
+            AvatarResizePolicy::Original.apply(data.clone(), &mime::IMAGE_PNG).unwrap();
+        assert_eq!(original, data);
+        assert_eq!(content_type, mime::IMAGE_PNG);
+
+        let (fit, _) =
+            AvatarResizePolicy::Fit { size: 100 }.apply(data.clone(), &mime::IMAGE_PNG).unwrap();
+        assert_eq!(dimensions(&fit), (100, 50));
+
+        // Small enough images are left untouched.
+        let (fit, _) =
+            AvatarResizePolicy::Fit { size: 500 }.apply(data.clone(), &mime::IMAGE_PNG).unwrap();
+        assert_eq!(fit, data);
+
+        let (square, _) = AvatarResizePolicy::CropToSquare { size: 100 }
+            .apply(data.clone(), &mime::IMAGE_PNG)
+            .unwrap();
+        assert_eq!(dimensions(&square), (100, 100));
+
+        let (square, _) =
+            AvatarResizePolicy::CropToSquare { size: 500 }.apply(data, &mime::IMAGE_PNG).unwrap();
+        assert_eq!(dimensions(&square), (200, 200));
+    }
+}
@@ -0,0 +1,718 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for [application services], like bridges.
+//!
+//! An [`AppService`] serves the application service API to the homeserver:
+//! it receives the transactions of events pushed by the homeserver, and
+//! answers its queries about the users and the room aliases of its
+//! namespaces. It also manages the clients of the virtual users of its
+//! namespace, which send their requests on behalf of these users with the
+//! token of the application service.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # async {
+//! use matrix_sdk::{
+//!     appservice::{AppService, Registration},
+//!     ruma::{events::room::message::SyncRoomMessageEvent, server_name},
+//! };
+//! use url::Url;
+//!
+//! # let registration: Registration = unimplemented!();
+//! let appservice = AppService::builder(
+//!     Url::parse("http://localhost:8008")?,
+//!     server_name!("localhost").to_owned(),
+//!     registration,
+//! )
+//! .build()
+//! .await?;
+//!
+//! // The events of all the transactions are handled by the client of the
+//! // sender of the application service.
+//! appservice.client().add_event_handler(|event: SyncRoomMessageEvent| async move {
+//!     println!("Received a message: {event:?}");
+//! });
+//!
+//! // Send a message on behalf of a virtual user.
+//! let bridged_user = appservice.virtual_user("bridge_alice").register().build().await?;
+//!
+//! appservice.run(("0.0.0.0", 8080)).await?;
+//! # anyhow::Ok(()) };
+//! ```
+//!
+//! [application services]: https://spec.matrix.org/latest/application-service-api/
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::Future,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock},
+};
+
+use matrix_sdk_base::SessionMeta;
+use matrix_sdk_common::{ring_buffer::RingBuffer, BoxFuture};
+use regex::Regex;
+pub use ruma::api::appservice::{Namespace, Namespaces, Registration};
+use ruma::{
+    api::client::{
+        account::register::{self, LoginType},
+        error::ErrorKind,
+        sync::sync_events,
+    },
+    assign,
+    events::{
+        room::member::MembershipState, AnyStrippedStateEvent, AnyTimelineEvent, StateEventType,
+    },
+    serde::Raw,
+    DeviceId, IdParseError, OwnedDeviceId, OwnedRoomAliasId, OwnedRoomId, OwnedServerName,
+    OwnedUserId, RoomAliasId, UserId,
+};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::{net::ToSocketAddrs, sync::Mutex as AsyncMutex};
+use tracing::{debug, instrument};
+use url::Url;
+
+use crate::{
+    matrix_auth::{MatrixSession, MatrixSessionTokens},
+    Client, ClientBuildError, ClientBuilder,
+};
+
+mod webserver;
+
+/// The number of the last transactions whose IDs are remembered, to ignore
+/// them when the homeserver sends them again.
+const PROCESSED_TRANSACTIONS_CAPACITY: usize = 100;
+
+/// An error occurring in an [`AppService`].
+#[derive(Debug, Error)]
+pub enum AppServiceError {
+    /// The user isn't in the namespace of the application service.
+    #[error("the user {0} isn't in the namespace of the application service")]
+    UserNotInNamespace(OwnedUserId),
+
+    /// A regex of the namespaces of the registration is invalid.
+    #[error(transparent)]
+    InvalidRegex(#[from] regex::Error),
+
+    /// The ID of a user is invalid.
+    #[error(transparent)]
+    IdParse(#[from] IdParseError),
+
+    /// A client couldn't be built.
+    #[error(transparent)]
+    ClientBuild(#[from] ClientBuildError),
+
+    /// A request or the processing of a transaction failed.
+    #[error(transparent)]
+    Sdk(#[from] crate::Error),
+
+    /// The server couldn't be started.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A handler of the queries of the homeserver about the users or the room
+/// aliases of the namespaces of an [`AppService`].
+type QueryHandler<T> = Arc<dyn Fn(T) -> BoxFuture<'static, bool> + Send + Sync>;
+
+/// The compiled regexes of the namespaces of a registration.
+#[derive(Debug)]
+struct NamespaceRegexes {
+    users: Vec<Regex>,
+    aliases: Vec<Regex>,
+}
+
+impl NamespaceRegexes {
+    fn new(namespaces: &Namespaces) -> Result<Self, regex::Error> {
+        let compile = |namespaces: &[Namespace]| {
+            namespaces
+                .iter()
+                .map(|namespace| Regex::new(&format!("^{}$", namespace.regex)))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(Self { users: compile(&namespaces.users)?, aliases: compile(&namespaces.aliases)? })
+    }
+}
+
+/// Builder for [`AppService`].
+#[derive(Debug)]
+pub struct AppServiceBuilder {
+    homeserver_url: Url,
+    server_name: OwnedServerName,
+    registration: Registration,
+    client_builder: Option<ClientBuilder>,
+}
+
+impl AppServiceBuilder {
+    /// Set the builder of the clients of the sender and of the virtual users.
+    ///
+    /// Its homeserver and its asserted identity are overridden. Note that the
+    /// same store configuration is used for all the clients built from it,
+    /// use [`VirtualUserBuilder::client_builder()`] to give a store to each
+    /// virtual user.
+    pub fn client_builder(mut self, client_builder: ClientBuilder) -> Self {
+        self.client_builder = Some(client_builder);
+        self
+    }
+
+    /// Build the application service, and the client of its sender.
+    pub async fn build(self) -> Result<AppService, AppServiceError> {
+        let namespaces = NamespaceRegexes::new(&self.registration.namespaces)?;
+        let client_builder = self.client_builder.unwrap_or_else(Client::builder);
+        let sender_id = UserId::parse_with_server_name(
+            &*self.registration.sender_localpart,
+            &self.server_name,
+        )?;
+
+        let sender = build_client(
+            client_builder.clone(),
+            &self.homeserver_url,
+            &self.registration,
+            sender_id,
+            None,
+        )
+        .await?;
+
+        Ok(AppService {
+            inner: Arc::new(AppServiceInner {
+                homeserver_url: self.homeserver_url,
+                server_name: self.server_name,
+                registration: self.registration,
+                namespaces,
+                client_builder,
+                sender,
+                virtual_users: Default::default(),
+                processed_transactions: StdMutex::new(RingBuffer::new(
+                    NonZeroUsize::new(PROCESSED_TRANSACTIONS_CAPACITY).unwrap(),
+                )),
+                transaction_lock: Default::default(),
+                user_query_handler: Default::default(),
+                room_alias_query_handler: Default::default(),
+            }),
+        })
+    }
+}
+
+/// Build a client sending its requests on behalf of the given user, with the
+/// token of the application service.
+async fn build_client(
+    client_builder: ClientBuilder,
+    homeserver_url: &Url,
+    registration: &Registration,
+    user_id: OwnedUserId,
+    device_id: Option<OwnedDeviceId>,
+) -> Result<Client, AppServiceError> {
+    let client = client_builder
+        .homeserver_url(homeserver_url)
+        .assert_identity(user_id.clone())
+        .build()
+        .await?;
+
+    let session = MatrixSession {
+        meta: SessionMeta { user_id, device_id: device_id.unwrap_or_else(DeviceId::new) },
+        tokens: MatrixSessionTokens {
+            access_token: registration.as_token.clone(),
+            refresh_token: None,
+        },
+    };
+    client.matrix_auth().restore_session(session).await?;
+
+    Ok(client)
+}
+
+/// An application service, serving the application service API and managing
+/// the clients of its virtual users.
+///
+/// It is OK to clone this type as much as you need: cloning it is cheap.
+#[derive(Clone, Debug)]
+pub struct AppService {
+    inner: Arc<AppServiceInner>,
+}
+
+struct AppServiceInner {
+    homeserver_url: Url,
+    server_name: OwnedServerName,
+    registration: Registration,
+    namespaces: NamespaceRegexes,
+    client_builder: ClientBuilder,
+
+    /// The client of the sender of the application service.
+    sender: Client,
+
+    /// The clients of the virtual users that were built so far.
+    virtual_users: StdRwLock<BTreeMap<OwnedUserId, Client>>,
+
+    /// The IDs of the last processed transactions.
+    processed_transactions: StdMutex<RingBuffer<String>>,
+
+    /// Lock to process the transactions one after the other.
+    transaction_lock: AsyncMutex<()>,
+
+    user_query_handler: StdRwLock<Option<QueryHandler<OwnedUserId>>>,
+    room_alias_query_handler: StdRwLock<Option<QueryHandler<OwnedRoomAliasId>>>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for AppServiceInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppServiceInner")
+            .field("homeserver_url", &self.homeserver_url)
+            .field("server_name", &self.server_name)
+            .field("id", &self.registration.id)
+            .field("sender", &self.sender)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AppService {
+    /// Create a builder for an application service with the given
+    /// registration, on the homeserver at the given URL with the given server
+    /// name.
+    pub fn builder(
+        homeserver_url: Url,
+        server_name: OwnedServerName,
+        registration: Registration,
+    ) -> AppServiceBuilder {
+        AppServiceBuilder { homeserver_url, server_name, registration, client_builder: None }
+    }
+
+    /// The registration of the application service.
+    pub fn registration(&self) -> &Registration {
+        &self.inner.registration
+    }
+
+    /// The client of the sender of the application service.
+    ///
+    /// It receives all the events pushed by the homeserver, so the event
+    /// handlers of the application service should be registered on it.
+    pub fn client(&self) -> &Client {
+        &self.inner.sender
+    }
+
+    /// Whether the given user is in the namespace of the application service.
+    pub fn is_user_in_namespace(&self, user_id: &UserId) -> bool {
+        user_id.server_name() == self.inner.server_name
+            && self.inner.namespaces.users.iter().any(|regex| regex.is_match(user_id.as_str()))
+    }
+
+    /// Whether the given room alias is in the namespace of the application
+    /// service.
+    pub fn is_room_alias_in_namespace(&self, room_alias: &RoomAliasId) -> bool {
+        self.inner.namespaces.aliases.iter().any(|regex| regex.is_match(room_alias.as_str()))
+    }
+
+    /// Get a builder for the client of the virtual user with the given
+    /// localpart.
+    pub fn virtual_user<'a>(&'a self, localpart: &'a str) -> VirtualUserBuilder<'a> {
+        VirtualUserBuilder {
+            appservice: self,
+            localpart,
+            device_id: None,
+            client_builder: None,
+            register: false,
+        }
+    }
+
+    /// Get the client of the virtual user with the given ID, if it was built
+    /// before.
+    pub fn get_virtual_user(&self, user_id: &UserId) -> Option<Client> {
+        self.inner.virtual_users.read().unwrap().get(user_id).cloned()
+    }
+
+    /// Set the handler of the queries of the homeserver about the users of the
+    /// namespace.
+    ///
+    /// The handler returns whether the user exists. If it does, the user is
+    /// registered before answering the homeserver. Without a handler, the
+    /// users are considered to not exist.
+    pub fn set_user_query_handler<F, Fut>(&self, handler: F)
+    where
+        F: Fn(OwnedUserId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        *self.inner.user_query_handler.write().unwrap() =
+            Some(Arc::new(move |user_id| Box::pin(handler(user_id))));
+    }
+
+    /// Set the handler of the queries of the homeserver about the room aliases
+    /// of the namespace.
+    ///
+    /// The handler returns whether the room exists, after creating it with the
+    /// alias if needed. Without a handler, the rooms are considered to not
+    /// exist.
+    pub fn set_room_alias_query_handler<F, Fut>(&self, handler: F)
+    where
+        F: Fn(OwnedRoomAliasId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        *self.inner.room_alias_query_handler.write().unwrap() =
+            Some(Arc::new(move |room_alias| Box::pin(handler(room_alias))));
+    }
+
+    /// Process a transaction of events pushed by the homeserver.
+    ///
+    /// All the events are processed by the [sender's client](Self::client).
+    /// The clients of the virtual users that were built only process the
+    /// events of the rooms they are in, or were invited to.
+    ///
+    /// A transaction that was already processed is ignored, since the
+    /// homeserver sends it again when it didn't receive the response.
+    ///
+    /// This is called by the server of [`AppService::run()`], it only needs
+    /// to be called to serve the application service API by other means.
+    #[instrument(skip(self, events))]
+    pub async fn receive_transaction(
+        &self,
+        txn_id: &str,
+        events: Vec<Raw<AnyTimelineEvent>>,
+    ) -> Result<(), AppServiceError> {
+        let _transaction_lock = self.inner.transaction_lock.lock().await;
+
+        if self.inner.processed_transactions.lock().unwrap().iter().any(|id| id == txn_id) {
+            debug!("Ignoring a transaction that was already processed");
+            return Ok(());
+        }
+
+        let events: Vec<_> = events.iter().filter_map(TransactionEvent::new).collect();
+
+        self.inner.sender.process_sync(sync_response(txn_id, &events, None)).await?;
+
+        let virtual_users: Vec<_> =
+            self.inner.virtual_users.read().unwrap().values().cloned().collect();
+
+        for client in virtual_users {
+            let response = sync_response(txn_id, &events, Some(&client));
+
+            if !response.rooms.is_empty() {
+                client.process_sync(response).await?;
+            }
+        }
+
+        self.inner.processed_transactions.lock().unwrap().push(txn_id.to_owned());
+
+        Ok(())
+    }
+
+    /// Get a router serving the application service API, to serve it with an
+    /// existing [`axum`] server.
+    pub fn router(&self) -> axum::Router {
+        webserver::router(self.clone())
+    }
+
+    /// Serve the application service API at the given address, until the
+    /// future is dropped.
+    pub async fn run(&self, address: impl ToSocketAddrs) -> Result<(), AppServiceError> {
+        let listener = tokio::net::TcpListener::bind(address).await?;
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+
+    /// Answer a query of the homeserver about a user.
+    async fn query_user(&self, user_id: OwnedUserId) -> Result<bool, AppServiceError> {
+        if !self.is_user_in_namespace(&user_id) {
+            return Ok(false);
+        }
+
+        let handler = self.inner.user_query_handler.read().unwrap().clone();
+        let Some(handler) = handler else { return Ok(false) };
+
+        if !handler(user_id.clone()).await {
+            return Ok(false);
+        }
+
+        self.virtual_user(user_id.localpart()).register().build().await?;
+
+        Ok(true)
+    }
+
+    /// Answer a query of the homeserver about a room alias.
+    async fn query_room_alias(&self, room_alias: OwnedRoomAliasId) -> bool {
+        if !self.is_room_alias_in_namespace(&room_alias) {
+            return false;
+        }
+
+        let handler = self.inner.room_alias_query_handler.read().unwrap().clone();
+        match handler {
+            Some(handler) => handler(room_alias).await,
+            None => false,
+        }
+    }
+}
+
+/// Builder for the client of a virtual user of an [`AppService`], created with
+/// [`AppService::virtual_user()`].
+#[derive(Debug)]
+pub struct VirtualUserBuilder<'a> {
+    appservice: &'a AppService,
+    localpart: &'a str,
+    device_id: Option<OwnedDeviceId>,
+    client_builder: Option<ClientBuilder>,
+    register: bool,
+}
+
+impl VirtualUserBuilder<'_> {
+    /// Set the ID of the device of the client.
+    ///
+    /// Defaults to a random ID. The virtual users don't log in, so this
+    /// device doesn't exist on the homeserver, and the client can't use
+    /// end-to-end encryption.
+    pub fn device_id(mut self, device_id: OwnedDeviceId) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+
+    /// Set the builder of the client, instead of the one of the
+    /// [`AppService`], for example to give it its own store.
+    pub fn client_builder(mut self, client_builder: ClientBuilder) -> Self {
+        self.client_builder = Some(client_builder);
+        self
+    }
+
+    /// Register the user on the homeserver, if it isn't registered yet.
+    pub fn register(mut self) -> Self {
+        self.register = true;
+        self
+    }
+
+    /// Build the client of the virtual user.
+    ///
+    /// If the client was already built, it is returned instead.
+    pub async fn build(self) -> Result<Client, AppServiceError> {
+        let inner = &self.appservice.inner;
+        let user_id = UserId::parse_with_server_name(self.localpart, &inner.server_name)?;
+
+        if !self.appservice.is_user_in_namespace(&user_id) {
+            return Err(AppServiceError::UserNotInNamespace(user_id));
+        }
+
+        if let Some(client) = self.appservice.get_virtual_user(&user_id) {
+            return Ok(client);
+        }
+
+        if self.register {
+            let request = assign!(register::v3::Request::new(), {
+                username: Some(self.localpart.to_owned()),
+                login_type: Some(LoginType::ApplicationService),
+                inhibit_login: true,
+            });
+
+            match inner.sender.send(request).await {
+                Ok(_) => debug!(%user_id, "Registered a virtual user"),
+                Err(error) if error.client_api_error_kind() == Some(&ErrorKind::UserInUse) => {}
+                Err(error) => return Err(crate::Error::from(error).into()),
+            }
+        }
+
+        let client_builder = self.client_builder.unwrap_or_else(|| inner.client_builder.clone());
+        let client = build_client(
+            client_builder,
+            &inner.homeserver_url,
+            &inner.registration,
+            user_id.clone(),
+            self.device_id,
+        )
+        .await?;
+
+        // Another task may have built the client in the meantime.
+        let client = inner.virtual_users.write().unwrap().entry(user_id).or_insert(client).clone();
+
+        Ok(client)
+    }
+}
+
+/// An event of a transaction, with the fields needed to route it.
+struct TransactionEvent {
+    raw: Raw<AnyTimelineEvent>,
+    room_id: OwnedRoomId,
+    membership: Option<(OwnedUserId, MembershipState)>,
+}
+
+impl TransactionEvent {
+    fn new(raw: &Raw<AnyTimelineEvent>) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct Event {
+            room_id: OwnedRoomId,
+            #[serde(rename = "type")]
+            event_type: StateEventType,
+            state_key: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct MemberContent {
+            membership: MembershipState,
+        }
+
+        let event = raw.deserialize_as::<Event>().ok()?;
+
+        let membership = match (event.event_type, event.state_key) {
+            (StateEventType::RoomMember, Some(state_key)) => {
+                let user_id = UserId::parse(state_key).ok()?;
+                let content = raw.get_field::<MemberContent>("content").ok().flatten()?;
+                Some((user_id, content.membership))
+            }
+            _ => None,
+        };
+
+        Some(Self { raw: raw.clone(), room_id: event.room_id, membership })
+    }
+}
+
+/// Convert the events of a transaction to a sync response for the given
+/// client of a virtual user, or for the sender's client if it's `None`.
+///
+/// The sender's client gets all the events, as if it were in all the rooms.
+/// The clients of the virtual users only get the events of the rooms they
+/// know about, or whose membership changed.
+fn sync_response(
+    txn_id: &str,
+    events: &[TransactionEvent],
+    virtual_user: Option<&Client>,
+) -> sync_events::v3::Response {
+    let mut response = sync_events::v3::Response::new(txn_id.to_owned());
+    let Some(client) = virtual_user else {
+        for event in events {
+            let room = response.rooms.join.entry(event.room_id.clone()).or_default();
+            room.timeline.events.push(event.raw.clone().cast());
+        }
+
+        return response;
+    };
+
+    let user_id = client.user_id().expect("the virtual users are always logged in");
+
+    // The last membership of the user in each room of the transaction.
+    let memberships: BTreeMap<&OwnedRoomId, &MembershipState> = events
+        .iter()
+        .filter_map(|event| match &event.membership {
+            Some((member, membership)) if member == user_id => Some((&event.room_id, membership)),
+            _ => None,
+        })
+        .collect();
+
+    for event in events {
+        match memberships.get(&event.room_id) {
+            Some(MembershipState::Invite) => {
+                let room = response.rooms.invite.entry(event.room_id.clone()).or_default();
+                room.invite_state.events.push(event.raw.clone().cast::<AnyStrippedStateEvent>());
+            }
+            Some(MembershipState::Leave | MembershipState::Ban) => {
+                let room = response.rooms.leave.entry(event.room_id.clone()).or_default();
+                room.timeline.events.push(event.raw.clone().cast());
+            }
+            Some(MembershipState::Join) => {
+                let room = response.rooms.join.entry(event.room_id.clone()).or_default();
+                room.timeline.events.push(event.raw.clone().cast());
+            }
+            _ if client.get_room(&event.room_id).is_some() => {
+                let room = response.rooms.join.entry(event.room_id.clone()).or_default();
+                room.timeline.events.push(event.raw.clone().cast());
+            }
+            _ => {}
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use ruma::{owned_user_id, room_alias_id, room_id, serde::Raw, server_name, user_id};
+    use serde_json::json;
+
+    use super::{AppService, Registration};
+    use crate::{test_utils::mocks::MatrixMockServer, RoomState};
+
+    pub(super) fn registration() -> Registration {
+        serde_json::from_value(json!({
+            "id": "bridge",
+            "url": "http://localhost:8080",
+            "as_token": "as_token",
+            "hs_token": "hs_token",
+            "sender_localpart": "bridge",
+            "namespaces": {
+                "users": [{ "exclusive": true, "regex": "@bridge_.*:localhost" }],
+                "aliases": [{ "exclusive": true, "regex": "#bridge_.*:localhost" }],
+                "rooms": [],
+            },
+        }))
+        .unwrap()
+    }
+
+    #[async_test]
+    async fn test_namespaces_and_transactions() {
+        let server = MatrixMockServer::new().await;
+        let appservice = AppService::builder(
+            server.server().uri().parse().unwrap(),
+            server_name!("localhost").to_owned(),
+            registration(),
+        )
+        .build()
+        .await
+        .unwrap();
+
+        assert_eq!(appservice.client().user_id(), Some(user_id!("@bridge:localhost")));
+        assert!(appservice.is_user_in_namespace(user_id!("@bridge_alice:localhost")));
+        assert!(!appservice.is_user_in_namespace(user_id!("@alice:localhost")));
+        assert!(!appservice.is_user_in_namespace(user_id!("@bridge_alice:example.org")));
+        assert!(appservice.is_room_alias_in_namespace(room_alias_id!("#bridge_room:localhost")));
+
+        // Only the users of the namespace can be managed.
+        assert!(appservice.virtual_user("alice").build().await.is_err());
+        let alice = appservice.virtual_user("bridge_alice").build().await.unwrap();
+        assert_eq!(alice.user_id(), Some(user_id!("@bridge_alice:localhost")));
+
+        // Alice is invited to a room, the sender's client gets it as joined.
+        let invite = Raw::new(&json!({
+            "type": "m.room.member",
+            "event_id": "$invite",
+            "room_id": "!room:localhost",
+            "sender": "@bob:localhost",
+            "state_key": "@bridge_alice:localhost",
+            "origin_server_ts": 0,
+            "content": { "membership": "invite" },
+        }))
+        .unwrap()
+        .cast();
+        appservice.receive_transaction("txn1", vec![invite]).await.unwrap();
+
+        let room_id = room_id!("!room:localhost");
+        assert!(appservice.client().get_room(room_id).is_some());
+        assert_eq!(alice.get_room(room_id).unwrap().state(), RoomState::Invited);
+
+        // A transaction that was already processed is ignored.
+        let leave = Raw::new(&json!({
+            "type": "m.room.member",
+            "event_id": "$leave",
+            "room_id": "!room:localhost",
+            "sender": "@bridge_alice:localhost",
+            "state_key": "@bridge_alice:localhost",
+            "origin_server_ts": 1,
+            "content": { "membership": "leave" },
+        }))
+        .unwrap()
+        .cast();
+        appservice.receive_transaction("txn1", vec![leave.clone()]).await.unwrap();
+        assert_eq!(alice.get_room(room_id).unwrap().state(), RoomState::Invited);
+
+        appservice.receive_transaction("txn2", vec![leave]).await.unwrap();
+        assert_eq!(alice.get_room(room_id).unwrap().state(), RoomState::Left);
+        assert!(appservice.get_virtual_user(&owned_user_id!("@bridge_alice:localhost")).is_some());
+    }
+}
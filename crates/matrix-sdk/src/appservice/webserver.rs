@@ -0,0 +1,373 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The server of the application service API.
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Json, Router,
+};
+use ruma::{events::AnyTimelineEvent, serde::Raw, OwnedRoomAliasId, OwnedUserId};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::warn;
+
+use super::AppService;
+
+/// The body of a transaction pushed by the homeserver.
+#[derive(Deserialize)]
+struct Transaction {
+    events: Vec<Raw<AnyTimelineEvent>>,
+}
+
+/// Build the router serving the application service API.
+pub(super) fn router(appservice: AppService) -> Router {
+    Router::new()
+        .route("/_matrix/app/v1/transactions/:txn_id", put(put_transaction))
+        .route("/_matrix/app/v1/users/:user_id", get(get_user))
+        .route("/_matrix/app/v1/rooms/:room_alias", get(get_room_alias))
+        // The legacy paths, still used by some homeservers.
+        .route("/transactions/:txn_id", put(put_transaction))
+        .route("/users/:user_id", get(get_user))
+        .route("/rooms/:room_alias", get(get_room_alias))
+        .layer(middleware::from_fn_with_state(appservice.clone(), authenticate))
+        .with_state(appservice)
+}
+
+/// An error response of the application service API.
+fn error(status: StatusCode, errcode: &str, error: &str) -> Response {
+    (status, Json(json!({ "errcode": errcode, "error": error }))).into_response()
+}
+
+/// Check that the request is sent by the homeserver, with the `hs_token` of
+/// the registration.
+async fn authenticate(
+    State(appservice): State<AppService>,
+    request: Request,
+    next: Next,
+) -> Response {
+    #[derive(Deserialize)]
+    struct Query {
+        access_token: Option<String>,
+    }
+
+    let header_token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(ToOwned::to_owned);
+    let query_token = request
+        .uri()
+        .query()
+        .and_then(|query| serde_html_form::from_str::<Query>(query).ok())
+        .and_then(|query| query.access_token);
+
+    match header_token.or(query_token) {
+        None => error(StatusCode::UNAUTHORIZED, "M_UNAUTHORIZED", "Missing access token"),
+        Some(token) if !tokens_match(&token, &appservice.registration().hs_token) => {
+            error(StatusCode::FORBIDDEN, "M_FORBIDDEN", "Invalid access token")
+        }
+        Some(_) => next.run(request).await,
+    }
+}
+
+/// Compare the given token with the expected one in constant time, to not leak
+/// the expected token through the time taken to answer.
+fn tokens_match(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn put_transaction(
+    State(appservice): State<AppService>,
+    Path(txn_id): Path<String>,
+    Json(transaction): Json<Transaction>,
+) -> Response {
+    match appservice.receive_transaction(&txn_id, transaction.events).await {
+        Ok(()) => Json(json!({})).into_response(),
+        Err(err) => {
+            warn!(%txn_id, "Failed to process a transaction: {err}");
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "M_UNKNOWN",
+                "Failed to process the transaction",
+            )
+        }
+    }
+}
+
+async fn get_user(
+    State(appservice): State<AppService>,
+    Path(user_id): Path<OwnedUserId>,
+) -> Response {
+    match appservice.query_user(user_id).await {
+        Ok(true) => Json(json!({})).into_response(),
+        Ok(false) => error(StatusCode::NOT_FOUND, "M_NOT_FOUND", "The user doesn't exist"),
+        Err(err) => {
+            warn!("Failed to answer a user query: {err}");
+            error(StatusCode::INTERNAL_SERVER_ERROR, "M_UNKNOWN", "Failed to query the user")
+        }
+    }
+}
+
+async fn get_room_alias(
+    State(appservice): State<AppService>,
+    Path(room_alias): Path<OwnedRoomAliasId>,
+) -> Response {
+    if appservice.query_room_alias(room_alias).await {
+        Json(json!({})).into_response()
+    } else {
+        error(StatusCode::NOT_FOUND, "M_NOT_FOUND", "The room alias doesn't exist")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header::AUTHORIZATION, Method, Request, StatusCode},
+        Router,
+    };
+    use matrix_sdk_test::async_test;
+    use ruma::{room_alias_id, room_id, server_name};
+    use serde_json::{json, Value as JsonValue};
+    use tower::ServiceExt;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, ResponseTemplate,
+    };
+
+    use super::{super::tests::registration, tokens_match};
+    use crate::{appservice::AppService, test_utils::mocks::MatrixMockServer};
+
+    async fn appservice(server: &MatrixMockServer) -> AppService {
+        AppService::builder(
+            server.server().uri().parse().unwrap(),
+            server_name!("localhost").to_owned(),
+            registration(),
+        )
+        .build()
+        .await
+        .unwrap()
+    }
+
+    /// Send a request with the `hs_token` of the registration to the router,
+    /// and get the status and the JSON body of the response.
+    async fn send(
+        router: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<JsonValue>,
+    ) -> (StatusCode, JsonValue) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(AUTHORIZATION, "Bearer hs_token")
+            .header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("hs_token", "hs_token"));
+        assert!(!tokens_match("hs_tokem", "hs_token"));
+        assert!(!tokens_match("hs_token2", "hs_token"));
+        assert!(!tokens_match("", "hs_token"));
+    }
+
+    #[async_test]
+    async fn test_authentication() {
+        let server = MatrixMockServer::new().await;
+        let router = appservice(&server).await.router();
+        let uri = "/_matrix/app/v1/rooms/%23bridge_room:localhost";
+
+        // Without a token, the request is unauthorized.
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // With the wrong token, the request is forbidden.
+        let request = Request::builder()
+            .uri(uri)
+            .header(AUTHORIZATION, "Bearer as_token")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let request = Request::builder()
+            .uri(format!("{uri}?access_token=as_token"))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // With the right token, in the header or in the query, the request goes
+        // through. There is no room alias query handler, so the room doesn't exist.
+        let (status, body) = send(&router, Method::GET, uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["errcode"], "M_NOT_FOUND");
+
+        let request = Request::builder()
+            .uri(format!("{uri}?access_token=hs_token"))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[async_test]
+    async fn test_transactions() {
+        let server = MatrixMockServer::new().await;
+        let appservice = appservice(&server).await;
+        let router = appservice.router();
+
+        let invite = |room_id: &str| {
+            json!({
+                "events": [{
+                    "type": "m.room.member",
+                    "event_id": "$invite",
+                    "room_id": room_id,
+                    "sender": "@bob:localhost",
+                    "state_key": "@bridge:localhost",
+                    "origin_server_ts": 0,
+                    "content": { "membership": "invite" },
+                }],
+            })
+        };
+
+        let (status, body) = send(
+            &router,
+            Method::PUT,
+            "/_matrix/app/v1/transactions/txn1",
+            Some(invite("!room:localhost")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({}));
+        assert!(appservice.client().get_room(room_id!("!room:localhost")).is_some());
+
+        // The legacy path works too.
+        let (status, _) =
+            send(&router, Method::PUT, "/transactions/txn2", Some(invite("!other:localhost")))
+                .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(appservice.client().get_room(room_id!("!other:localhost")).is_some());
+    }
+
+    #[async_test]
+    async fn test_user_query() {
+        let server = MatrixMockServer::new().await;
+        let appservice = appservice(&server).await;
+        let router = appservice.router();
+
+        // Without a handler, the users don't exist.
+        let (status, _) =
+            send(&router, Method::GET, "/_matrix/app/v1/users/@bridge_alice:localhost", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let queries = Arc::new(AtomicUsize::new(0));
+        appservice.set_user_query_handler({
+            let queries = queries.clone();
+            move |user_id| {
+                queries.fetch_add(1, Ordering::SeqCst);
+                async move { user_id.localpart() == "bridge_alice" }
+            }
+        });
+
+        // The user is registered before answering the homeserver.
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/register"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@bridge_alice:localhost",
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        let (status, body) =
+            send(&router, Method::GET, "/_matrix/app/v1/users/@bridge_alice:localhost", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({}));
+
+        // The legacy path works too, and the user isn't registered again.
+        let (status, _) = send(&router, Method::GET, "/users/@bridge_alice:localhost", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // The handler doesn't know this user.
+        let (status, _) =
+            send(&router, Method::GET, "/_matrix/app/v1/users/@bridge_bob:localhost", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // The handler isn't called for the users outside of the namespace.
+        let (status, _) =
+            send(&router, Method::GET, "/_matrix/app/v1/users/@alice:localhost", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(queries.load(Ordering::SeqCst), 3);
+    }
+
+    #[async_test]
+    async fn test_room_alias_query() {
+        let server = MatrixMockServer::new().await;
+        let appservice = appservice(&server).await;
+        let router = appservice.router();
+
+        let queries = Arc::new(AtomicUsize::new(0));
+        appservice.set_room_alias_query_handler({
+            let queries = queries.clone();
+            move |room_alias| {
+                queries.fetch_add(1, Ordering::SeqCst);
+                async move { room_alias == room_alias_id!("#bridge_room:localhost") }
+            }
+        });
+
+        let (status, body) =
+            send(&router, Method::GET, "/_matrix/app/v1/rooms/%23bridge_room:localhost", None)
+                .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({}));
+
+        // The legacy path works too.
+        let (status, _) = send(&router, Method::GET, "/rooms/%23bridge_room:localhost", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) =
+            send(&router, Method::GET, "/_matrix/app/v1/rooms/%23bridge_other:localhost", None)
+                .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // The handler isn't called for the room aliases outside of the namespace.
+        let (status, _) =
+            send(&router, Method::GET, "/_matrix/app/v1/rooms/%23room:localhost", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(queries.load(Ordering::SeqCst), 3);
+    }
+}
@@ -223,6 +223,9 @@ impl AttachmentConfig {
 
     /// Set the media metadata to send.
     ///
+    /// With the `attachment-metadata` feature, the metadata is extracted from
+    /// the data of the attachment if it isn't set.
+    ///
     /// # Arguments
     ///
     /// * `info` - The metadata of the media. If the `AttachmentInfo` type
@@ -263,3 +266,346 @@ impl AttachmentConfig {
         self
     }
 }
+
+#[cfg(feature = "attachment-metadata")]
+impl AttachmentInfo {
+    /// Extract the metadata of an attachment from its data.
+    ///
+    /// The dimensions and the [BlurHash](https://blurha.sh/) of the images in
+    /// the formats supported by the `image` feature are extracted, as well as
+    /// the dimensions and the duration of MP4 and QuickTime videos, and the
+    /// duration of MP4 audio clips. The size is always set.
+    ///
+    /// This is best effort: the metadata that can't be extracted is left
+    /// unset.
+    pub fn extract(content_type: &mime::Mime, data: &[u8]) -> Self {
+        let size = UInt::new(data.len() as u64);
+
+        match content_type.type_() {
+            mime::IMAGE => {
+                let mut info = metadata::image_info(content_type, data).unwrap_or_default();
+                info.size = size;
+                Self::Image(info)
+            }
+            mime::VIDEO => {
+                let movie = metadata::Mp4Movie::parse(data).unwrap_or_default();
+                Self::Video(BaseVideoInfo {
+                    duration: movie.duration,
+                    height: movie.height,
+                    width: movie.width,
+                    size,
+                    blurhash: None,
+                })
+            }
+            mime::AUDIO => {
+                let movie = metadata::Mp4Movie::parse(data).unwrap_or_default();
+                Self::Audio(BaseAudioInfo { duration: movie.duration, size })
+            }
+            _ => Self::File(BaseFileInfo { size }),
+        }
+    }
+}
+
+#[cfg(feature = "attachment-metadata")]
+impl AttachmentConfig {
+    /// Extract the metadata of the attachment from its data, if it wasn't set.
+    ///
+    /// Returns the content type of the attachment, which is guessed from its
+    /// data if the given one is `application/octet-stream`, and the data that
+    /// was given.
+    ///
+    /// Decoding the data is CPU-bound, so it is done on a blocking thread.
+    pub(crate) async fn extract_missing_metadata(
+        &mut self,
+        content_type: &mime::Mime,
+        data: Vec<u8>,
+    ) -> (mime::Mime, Vec<u8>) {
+        let content_type = content_type.clone();
+        let extract_info = self.info.is_none();
+        let thumbnail = self.thumbnail.take();
+
+        let extract = move || {
+            let content_type = if content_type == mime::APPLICATION_OCTET_STREAM {
+                metadata::guess_content_type(&data).unwrap_or(content_type)
+            } else {
+                content_type
+            };
+
+            let info = extract_info.then(|| {
+                let mut info = AttachmentInfo::extract(&content_type, &data);
+
+                // The frames of videos can't be decoded, so use the thumbnail
+                // for the BlurHash.
+                if let (AttachmentInfo::Video(video_info), Some(thumbnail)) =
+                    (&mut info, &thumbnail)
+                {
+                    video_info.blurhash =
+                        metadata::image_info(&thumbnail.content_type, &thumbnail.data)
+                            .and_then(|info| info.blurhash);
+                }
+
+                info
+            });
+
+            (content_type, data, info, thumbnail)
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let (content_type, data, info, thumbnail) =
+            tokio::task::spawn_blocking(extract).await.expect("Task join error");
+        #[cfg(target_arch = "wasm32")]
+        let (content_type, data, info, thumbnail) = extract();
+
+        self.thumbnail = thumbnail;
+        if info.is_some() {
+            self.info = info;
+        }
+
+        (content_type, data)
+    }
+}
+
+/// Extraction of the metadata of attachments.
+#[cfg(feature = "attachment-metadata")]
+mod metadata {
+    use std::time::Duration;
+
+    use image::ImageFormat;
+    use mime::Mime;
+    use ruma::UInt;
+    use tracing::debug;
+
+    use super::BaseImageInfo;
+
+    /// The number of horizontal and vertical components of the BlurHashes.
+    const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+    /// The maximum size of the images used to compute the BlurHashes, larger
+    /// images are scaled down first because the details are lost anyway.
+    const BLURHASH_IMAGE_SIZE: u32 = 64;
+
+    /// Get the dimensions and the BlurHash of the given image.
+    pub(super) fn image_info(content_type: &Mime, data: &[u8]) -> Option<BaseImageInfo> {
+        let image = match ImageFormat::from_mime_type(content_type.essence_str()) {
+            Some(format) => image::load_from_memory_with_format(data, format),
+            None => image::load_from_memory(data),
+        };
+
+        let image = match image {
+            Ok(image) => image,
+            Err(error) => {
+                debug!("Could not decode the image: {error}");
+                return None;
+            }
+        };
+
+        let small_image = image.thumbnail(BLURHASH_IMAGE_SIZE, BLURHASH_IMAGE_SIZE).to_rgba8();
+        let blurhash = blurhash::encode(
+            BLURHASH_COMPONENTS.0,
+            BLURHASH_COMPONENTS.1,
+            small_image.width(),
+            small_image.height(),
+            small_image.as_raw(),
+        )
+        .ok();
+
+        Some(BaseImageInfo {
+            height: Some(image.height().into()),
+            width: Some(image.width().into()),
+            blurhash,
+            ..Default::default()
+        })
+    }
+
+    /// Guess the content type of the given data from its first bytes.
+    pub(super) fn guess_content_type(data: &[u8]) -> Option<Mime> {
+        if let Ok(format) = image::guess_format(data) {
+            return format.to_mime_type().parse().ok();
+        }
+
+        match data.get(4..12)? {
+            b"ftypqt  " => "video/quicktime".parse().ok(),
+            [b'f', b't', b'y', b'p', b'M', b'4', b'A', _] => "audio/mp4".parse().ok(),
+            [b'f', b't', b'y', b'p', ..] => "video/mp4".parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The metadata of an MP4 or QuickTime file.
+    #[derive(Debug, Default)]
+    pub(super) struct Mp4Movie {
+        pub duration: Option<Duration>,
+        pub width: Option<UInt>,
+        pub height: Option<UInt>,
+    }
+
+    impl Mp4Movie {
+        /// Read the metadata from the `moov` box of the given file.
+        ///
+        /// Returns `None` if the file doesn't have a `moov` box.
+        pub(super) fn parse(data: &[u8]) -> Option<Self> {
+            let (_, moov) = boxes(data).find(|(box_type, _)| box_type == b"moov")?;
+            let mut movie = Self::default();
+
+            for (box_type, content) in boxes(moov) {
+                match &box_type {
+                    b"mvhd" => movie.duration = parse_mvhd(content),
+                    b"trak" if movie.width.is_none() => {
+                        // The dimensions of the first visual track are used.
+                        if let Some((width, height)) = boxes(content)
+                            .find(|(box_type, _)| box_type == b"tkhd")
+                            .and_then(|(_, tkhd)| parse_tkhd_dimensions(tkhd))
+                        {
+                            movie.width = Some(width.into());
+                            movie.height = Some(height.into());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            Some(movie)
+        }
+    }
+
+    /// Iterate over the boxes at the start of the given data, as `(type,
+    /// content)` tuples.
+    fn boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+        std::iter::from_fn(move || {
+            let size = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?);
+            let box_type: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+
+            let (header_len, size) = match size {
+                // The box extends to the end of the data.
+                0 => (8, data.len()),
+                // The size is a 64-bit integer after the type.
+                1 => (16, usize::try_from(read_u64(data, 8)?).ok()?),
+                size => (8, usize::try_from(size).ok()?),
+            };
+
+            let content = data.get(header_len..size)?;
+            data = &data[size..];
+
+            Some((box_type, content))
+        })
+    }
+
+    /// Get the duration of the movie from the content of an `mvhd` box.
+    fn parse_mvhd(content: &[u8]) -> Option<Duration> {
+        let (timescale, duration) = match content.first()? {
+            0 => (read_u32(content, 12)?, u64::from(read_u32(content, 16)?)),
+            1 => (read_u32(content, 20)?, read_u64(content, 24)?),
+            _ => return None,
+        };
+
+        if timescale == 0 {
+            return None;
+        }
+
+        Some(Duration::from_millis(duration.checked_mul(1000)? / u64::from(timescale)))
+    }
+
+    /// Get the dimensions of a track from the content of a `tkhd` box, if it's
+    /// a visual track.
+    fn parse_tkhd_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+        let offset = match content.first()? {
+            0 => 76,
+            1 => 88,
+            _ => return None,
+        };
+
+        // The dimensions are 16.16 fixed-point numbers.
+        let width = read_u32(content, offset)? >> 16;
+        let height = read_u32(content, offset + 4)? >> 16;
+
+        (width != 0 && height != 0).then_some((width, height))
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+        Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+    }
+
+    fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+        Some(u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+    }
+}
+
+#[cfg(all(test, feature = "attachment-metadata"))]
+mod tests {
+    use std::{io::Cursor, time::Duration};
+
+    use assert_matches2::assert_let;
+    use image::{DynamicImage, ImageFormat};
+    use matrix_sdk_test::async_test;
+    use ruma::uint;
+
+    use super::{AttachmentConfig, AttachmentInfo};
+
+    /// Build an MP4 box with the given type and content.
+    fn mp4_box(box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(content);
+        data
+    }
+
+    /// Build a minimal MP4 file of 2.5 seconds, with a video track of 640x480.
+    fn mp4() -> Vec<u8> {
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&2500u32.to_be_bytes());
+
+        let mut tkhd = vec![0; 84];
+        tkhd[76..80].copy_from_slice(&(640u32 << 16).to_be_bytes());
+        tkhd[80..84].copy_from_slice(&(480u32 << 16).to_be_bytes());
+
+        let trak = mp4_box(b"trak", &mp4_box(b"tkhd", &tkhd));
+        let moov = mp4_box(b"moov", &[mp4_box(b"mvhd", &mvhd), trak].concat());
+
+        [mp4_box(b"ftyp", b"isom\0\0\0\0"), moov].concat()
+    }
+
+    #[async_test]
+    async fn test_extract_image_info() {
+        let mut data = Vec::new();
+        DynamicImage::new_rgba8(40, 20)
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+
+        assert_let!(AttachmentInfo::Image(info) = AttachmentInfo::extract(&mime::IMAGE_PNG, &data));
+        assert_eq!(info.width, Some(uint!(40)));
+        assert_eq!(info.height, Some(uint!(20)));
+        assert_eq!(info.size, Some((data.len() as u32).into()));
+        assert!(info.blurhash.is_some());
+
+        // The content type is guessed from the data.
+        let mut config = AttachmentConfig::new();
+        let (content_type, _) =
+            config.extract_missing_metadata(&mime::APPLICATION_OCTET_STREAM, data).await;
+        assert_eq!(content_type, mime::IMAGE_PNG);
+        assert_let!(Some(AttachmentInfo::Image(_)) = config.info);
+    }
+
+    #[async_test]
+    async fn test_extract_video_info() {
+        let data = mp4();
+
+        let mut config = AttachmentConfig::new();
+        let (content_type, _) =
+            config.extract_missing_metadata(&mime::APPLICATION_OCTET_STREAM, data).await;
+        assert_eq!(content_type.essence_str(), "video/mp4");
+
+        assert_let!(Some(AttachmentInfo::Video(info)) = config.info);
+        assert_eq!(info.duration, Some(Duration::from_millis(2500)));
+        assert_eq!(info.width, Some(uint!(640)));
+        assert_eq!(info.height, Some(uint!(480)));
+
+        // Garbage is handled gracefully.
+        assert_let!(
+            AttachmentInfo::Video(info) =
+                AttachmentInfo::extract(&"video/mp4".parse().unwrap(), b"not a video")
+        );
+        assert_eq!(info.duration, None);
+        assert_eq!(info.width, None);
+    }
+}
@@ -17,9 +17,10 @@
 // TODO:(pixlwave) Move AuthenticationService from the FFI into this module.
 // TODO:(poljar) Move the oidc and matrix_auth modules under this module.
 
-use std::sync::Arc;
+use std::sync::{Arc, RwLock as StdRwLock};
 
 use as_variant::as_variant;
+use async_trait::async_trait;
 use matrix_sdk_base::SessionMeta;
 use tokio::sync::{broadcast, Mutex, OnceCell};
 
@@ -32,6 +33,7 @@ use crate::{
 
 #[cfg(all(feature = "experimental-oidc", feature = "e2e-encryption", not(target_arch = "wasm32")))]
 pub mod qrcode;
+pub mod session_store;
 
 /// Session tokens, for any kind of authentication.
 #[allow(missing_debug_implementations, clippy::large_enum_variant)]
@@ -43,6 +45,32 @@ pub enum SessionTokens {
     Oidc(oidc::OidcSessionTokens),
 }
 
+/// The credentials used by a [`ReauthHandler`] to resume a session after a
+/// soft logout.
+#[allow(missing_debug_implementations)]
+pub enum ReauthCredentials {
+    /// Log in again with the password of the user, on the same device.
+    ///
+    /// Only supported for [`matrix_auth`] sessions.
+    Password(String),
+
+    /// Use new tokens for the session, for instance obtained with an OIDC
+    /// refresh done by the application.
+    Tokens(SessionTokens),
+}
+
+/// A handler to authenticate again when the session was soft logged out.
+///
+/// It is set with [`Client::set_reauth_handler()`].
+#[async_trait]
+pub trait ReauthHandler: Send + Sync {
+    /// Get the credentials to resume the session of the given client.
+    ///
+    /// Returns `None` to give up, in which case the session is considered
+    /// invalid.
+    async fn reauthenticate(&self, client: &Client) -> Option<ReauthCredentials>;
+}
+
 pub(crate) type SessionCallbackError = Box<dyn std::error::Error + Send + Sync>;
 pub(crate) type SaveSessionCallback =
     dyn Fn(Client) -> Result<(), SessionCallbackError> + Send + Sync;
@@ -84,6 +112,12 @@ pub(crate) struct AuthCtx {
     /// Internal invariant: this must be called only after `set_session_tokens`
     /// has been called, not before.
     pub(crate) save_session_callback: OnceCell<Box<SaveSessionCallback>>,
+
+    /// The handler to authenticate again after a soft logout.
+    pub(crate) reauth_handler: StdRwLock<Option<Arc<dyn ReauthHandler>>>,
+
+    /// Lock making sure only one re-authentication happens at a time.
+    pub(crate) reauth_lock: Mutex<()>,
 }
 
 /// An enum over all the possible authentication APIs.
@@ -347,12 +347,10 @@ mod test {
 
     use super::*;
     use crate::{
-        authentication::qrcode::{
-            messages::LoginProtocolType,
-            secure_channel::{test::MockedRendezvousServer, SecureChannel},
-        },
+        authentication::qrcode::{messages::LoginProtocolType, secure_channel::SecureChannel},
         config::RequestConfig,
         http_client::HttpClient,
+        rendezvous::channel::test::MockedRendezvousServer,
     };
 
     enum AliceBehaviour {
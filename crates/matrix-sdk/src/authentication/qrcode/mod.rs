@@ -39,7 +39,6 @@ use crate::{oidc::CrossProcessRefreshLockError, HttpError};
 mod login;
 mod messages;
 mod oidc_client;
-mod rendezvous_channel;
 mod secure_channel;
 
 pub use matrix_sdk_base::crypto::types::qr_login::{
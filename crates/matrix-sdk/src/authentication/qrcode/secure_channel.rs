@@ -23,11 +23,12 @@ use vodozemac::ecies::{CheckCode, Ecies, EstablishedEcies, Message, OutboundCrea
 #[cfg(test)]
 use vodozemac::ecies::{InboundCreationResult, InitialMessage};
 
-use super::{
-    rendezvous_channel::{InboundChannelCreationResult, RendezvousChannel},
-    SecureChannelError as Error,
+use super::SecureChannelError as Error;
+use crate::{
+    config::RequestConfig,
+    http_client::HttpClient,
+    rendezvous::channel::{InboundChannelCreationResult, RendezvousChannel},
 };
-use crate::{config::RequestConfig, http_client::HttpClient};
 
 const LOGIN_INITIATE_MESSAGE: &str = "MATRIX_QR_CODE_LOGIN_INITIATE";
 const LOGIN_OK_MESSAGE: &str = "MATRIX_QR_CODE_LOGIN_OK";
@@ -227,124 +228,14 @@ impl EstablishedSecureChannel {
 }
 
 #[cfg(test)]
-pub(super) mod test {
-    use std::sync::{
-        atomic::{AtomicU8, Ordering},
-        Arc, Mutex,
-    };
-
+mod test {
     use matrix_sdk_base::crypto::types::qr_login::QrCodeMode;
     use matrix_sdk_test::async_test;
-    use serde_json::json;
     use similar_asserts::assert_eq;
-    use url::Url;
-    use wiremock::{
-        matchers::{method, path},
-        Mock, MockGuard, MockServer, ResponseTemplate,
-    };
+    use wiremock::MockServer;
 
     use super::{EstablishedSecureChannel, SecureChannel};
-    use crate::http_client::HttpClient;
-
-    #[allow(dead_code)]
-    pub struct MockedRendezvousServer {
-        pub homeserver_url: Url,
-        pub rendezvous_url: Url,
-        content: Arc<Mutex<Option<String>>>,
-        etag: Arc<AtomicU8>,
-        post_guard: MockGuard,
-        put_guard: MockGuard,
-        get_guard: MockGuard,
-    }
-
-    impl MockedRendezvousServer {
-        pub async fn new(server: &MockServer, location: &str) -> Self {
-            let content: Arc<Mutex<Option<String>>> = Mutex::default().into();
-            let etag = Arc::new(AtomicU8::new(0));
-
-            let homeserver_url = Url::parse(&server.uri())
-                .expect("We should be able to parse the example homeserver");
-
-            let rendezvous_url = homeserver_url
-                .join(location)
-                .expect("We should be able to create a rendezvous URL");
-
-            let post_guard = server
-                .register_as_scoped(
-                    Mock::given(method("POST"))
-                        .and(path("/_matrix/client/unstable/org.matrix.msc4108/rendezvous"))
-                        .respond_with(
-                            ResponseTemplate::new(200)
-                                .append_header("X-Max-Bytes", "10240")
-                                .append_header("ETag", "1")
-                                .append_header("Expires", "Wed, 07 Sep 2022 14:28:51 GMT")
-                                .append_header("Last-Modified", "Wed, 07 Sep 2022 14:27:51 GMT")
-                                .set_body_json(json!({
-                                    "url": rendezvous_url,
-                                })),
-                        ),
-                )
-                .await;
-
-            let put_guard = server
-                .register_as_scoped(
-                    Mock::given(method("PUT")).and(path("/abcdEFG12345")).respond_with({
-                        let content = content.clone();
-                        let etag = etag.clone();
-
-                        move |request: &wiremock::Request| {
-                            *content.lock().unwrap() =
-                                Some(String::from_utf8(request.body.clone()).unwrap());
-                            let current_etag = etag.fetch_add(1, Ordering::SeqCst);
-
-                            ResponseTemplate::new(200)
-                                .append_header("ETag", (current_etag + 2).to_string())
-                                .append_header("Expires", "Wed, 07 Sep 2022 14:28:51 GMT")
-                                .append_header("Last-Modified", "Wed, 07 Sep 2022 14:27:51 GMT")
-                        }
-                    }),
-                )
-                .await;
-
-            let get_guard = server
-                .register_as_scoped(
-                    Mock::given(method("GET")).and(path("/abcdEFG12345")).respond_with({
-                        let content = content.clone();
-                        let etag = etag.clone();
-
-                        move |request: &wiremock::Request| {
-                            let requested_etag = request.headers.get("if-none-match").map(|etag| {
-                                str::parse::<u8>(std::str::from_utf8(etag.as_bytes()).unwrap())
-                                    .unwrap()
-                            });
-
-                            let mut content = content.lock().unwrap();
-                            let current_etag = etag.load(Ordering::SeqCst);
-
-                            if requested_etag == Some(current_etag) || requested_etag.is_none() {
-                                let content = content.take();
-
-                                ResponseTemplate::new(200)
-                                    .append_header("ETag", (current_etag).to_string())
-                                    .append_header("Expires", "Wed, 07 Sep 2022 14:28:51 GMT")
-                                    .append_header("Last-Modified", "Wed, 07 Sep 2022 14:27:51 GMT")
-                                    .set_body_string(content.unwrap_or_default())
-                            } else {
-                                let etag = requested_etag.unwrap_or_default();
-
-                                ResponseTemplate::new(304)
-                                    .append_header("ETag", etag.to_string())
-                                    .append_header("Expires", "Wed, 07 Sep 2022 14:28:51 GMT")
-                                    .append_header("Last-Modified", "Wed, 07 Sep 2022 14:27:51 GMT")
-                            }
-                        }
-                    }),
-                )
-                .await;
-
-            Self { content, etag, post_guard, put_guard, get_guard, homeserver_url, rendezvous_url }
-        }
-    }
+    use crate::{http_client::HttpClient, rendezvous::channel::test::MockedRendezvousServer};
 
     #[async_test]
     async fn test_creation() {
@@ -0,0 +1,324 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistence of the session of a [`Client`], with the tokens encrypted at
+//! rest.
+//!
+//! A [`SessionStore`] can be given to [`MatrixAuth::persist_session_to()`],
+//! so that the session is saved every time the tokens are refreshed, and read
+//! back with [`MatrixAuth::restore_session_from()`].
+//!
+//! [`EncryptedFileSessionStore`] is a reference implementation, storing the
+//! session in a file encrypted with a passphrase, or with a key provided by
+//! the OS keystore.
+//!
+//! [`Client`]: crate::Client
+//! [`MatrixAuth::persist_session_to()`]: crate::matrix_auth::MatrixAuth::persist_session_to
+//! [`MatrixAuth::restore_session_from()`]: crate::matrix_auth::MatrixAuth::restore_session_from
+
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use matrix_sdk_store_encryption::StoreCipher;
+#[cfg(not(target_arch = "wasm32"))]
+use ruma::serde::Base64;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use crate::matrix_auth::MatrixSession;
+
+/// A session, as persisted by a [`SessionStore`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredSession {
+    /// The URL of the homeserver of the session.
+    pub homeserver: Url,
+
+    /// The session itself, including its tokens.
+    pub session: MatrixSession,
+}
+
+/// An error occurring while loading or saving a session.
+#[derive(Debug, Error)]
+pub enum SessionStoreError {
+    /// The session couldn't be read from or written to the disk.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The stored data couldn't be (de)serialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The session couldn't be encrypted or decrypted, for instance because
+    /// the passphrase is wrong.
+    #[error(transparent)]
+    Encryption(#[from] matrix_sdk_store_encryption::Error),
+
+    /// The encryption key couldn't be obtained from the key provider.
+    #[error("couldn't get the session encryption key: {0}")]
+    KeyProvider(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Storage for the session of a client.
+///
+/// The methods are synchronous, since they're called from the session
+/// callbacks of the client.
+pub trait SessionStore: fmt::Debug + Send + Sync {
+    /// Load the stored session, if any.
+    fn load(&self) -> Result<Option<StoredSession>, SessionStoreError>;
+
+    /// Save the session, replacing the previous one.
+    fn save(&self, session: &StoredSession) -> Result<(), SessionStoreError>;
+
+    /// Remove the stored session, for instance after logging out.
+    fn clear(&self) -> Result<(), SessionStoreError>;
+}
+
+/// A callback returning the key to encrypt the session with, typically from
+/// the OS keystore.
+pub type SessionKeyProvider =
+    dyn Fn() -> Result<[u8; 32], Box<dyn std::error::Error + Send + Sync>> + Send + Sync;
+
+/// How the session is encrypted at rest by an [`EncryptedFileSessionStore`].
+pub enum SessionEncryptionKey {
+    /// A passphrase, that a key is derived from.
+    Passphrase(String),
+
+    /// A callback returning the key, typically from the OS keystore.
+    Provider(Box<SessionKeyProvider>),
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SessionEncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passphrase(_) => f.debug_tuple("Passphrase").finish_non_exhaustive(),
+            Self::Provider(_) => f.debug_tuple("Provider").finish_non_exhaustive(),
+        }
+    }
+}
+
+/// The content of the file of an [`EncryptedFileSessionStore`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Serialize, Deserialize)]
+struct SessionFile {
+    /// The cipher, encrypted with the passphrase or key.
+    cipher: Base64,
+
+    /// The session, encrypted with the cipher.
+    session: Option<Base64>,
+}
+
+/// A [`SessionStore`] storing the session in a file, encrypted with a
+/// passphrase or with a key provided by the OS keystore.
+///
+/// The file is written atomically, and is only readable by the current user
+/// on Unix.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct EncryptedFileSessionStore {
+    path: PathBuf,
+    cipher: StoreCipher,
+
+    /// The cipher, encrypted with the passphrase or key, so that it doesn't
+    /// need to be exported again on every save.
+    exported_cipher: Vec<u8>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl EncryptedFileSessionStore {
+    /// Open the session store at the given path.
+    ///
+    /// If the file exists, the key is checked against it. Otherwise, the file
+    /// is only created once a session is saved.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        key: SessionEncryptionKey,
+    ) -> Result<Self, SessionStoreError> {
+        let path = path.into();
+
+        let (cipher, exported_cipher) = match std::fs::read(&path) {
+            Ok(content) => {
+                let file: SessionFile = serde_json::from_slice(&content)?;
+                let exported_cipher = file.cipher.into_inner();
+
+                let cipher = match &key {
+                    SessionEncryptionKey::Passphrase(passphrase) => {
+                        StoreCipher::import(passphrase, &exported_cipher)?
+                    }
+                    SessionEncryptionKey::Provider(provider) => StoreCipher::import_with_key(
+                        &provider().map_err(SessionStoreError::KeyProvider)?,
+                        &exported_cipher,
+                    )?,
+                };
+
+                (cipher, exported_cipher)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let cipher = StoreCipher::new()?;
+
+                let exported_cipher = match &key {
+                    SessionEncryptionKey::Passphrase(passphrase) => cipher.export(passphrase)?,
+                    SessionEncryptionKey::Provider(provider) => cipher
+                        .export_with_key(&provider().map_err(SessionStoreError::KeyProvider)?)?,
+                };
+
+                (cipher, exported_cipher)
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self { path, cipher, exported_cipher })
+    }
+
+    /// The path of the file the session is stored in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write(&self, session: Option<Vec<u8>>) -> Result<(), SessionStoreError> {
+        let file = SessionFile {
+            cipher: Base64::new(self.exported_cipher.clone()),
+            session: session.map(Base64::new),
+        };
+
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        // Write to a temporary file first, which is only readable by the current
+        // user, so that the session is never left half-written.
+        let mut tmp_file = tempfile::NamedTempFile::new_in(dir)?;
+        tmp_file.write_all(&serde_json::to_vec(&file)?)?;
+        tmp_file.as_file().sync_all()?;
+        tmp_file.persist(&self.path).map_err(|err| err.error)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for EncryptedFileSessionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFileSessionStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SessionStore for EncryptedFileSessionStore {
+    fn load(&self) -> Result<Option<StoredSession>, SessionStoreError> {
+        let content = match std::fs::read(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let file: SessionFile = serde_json::from_slice(&content)?;
+
+        file.session
+            .map(|session| self.cipher.decrypt_value(session.as_bytes()))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    fn save(&self, session: &StoredSession) -> Result<(), SessionStoreError> {
+        self.write(Some(self.cipher.encrypt_value(session)?))
+    }
+
+    fn clear(&self) -> Result<(), SessionStoreError> {
+        self.write(None)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use matrix_sdk_base::SessionMeta;
+    use matrix_sdk_test::async_test;
+    use ruma::{device_id, user_id};
+    use url::Url;
+
+    use super::{
+        EncryptedFileSessionStore, SessionEncryptionKey, SessionStore, SessionStoreError,
+        StoredSession,
+    };
+    use crate::matrix_auth::{MatrixSession, MatrixSessionTokens};
+
+    fn session() -> StoredSession {
+        StoredSession {
+            homeserver: Url::parse("https://example.org").unwrap(),
+            session: MatrixSession {
+                meta: SessionMeta {
+                    user_id: user_id!("@alice:example.org").to_owned(),
+                    device_id: device_id!("DEVICEID").to_owned(),
+                },
+                tokens: MatrixSessionTokens {
+                    access_token: "secret_access_token".to_owned(),
+                    refresh_token: Some("secret_refresh_token".to_owned()),
+                },
+            },
+        }
+    }
+
+    #[async_test]
+    async fn test_encrypted_file_session_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let passphrase = || SessionEncryptionKey::Passphrase("passphrase".to_owned());
+
+        let store = EncryptedFileSessionStore::open(&path, passphrase()).unwrap();
+        assert!(store.load().unwrap().is_none());
+
+        store.save(&session()).unwrap();
+
+        // The tokens aren't stored in plain text.
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("secret_access_token"));
+        assert!(!content.contains("secret_refresh_token"));
+
+        let store = EncryptedFileSessionStore::open(&path, passphrase()).unwrap();
+        let stored = store.load().unwrap().unwrap();
+        assert_eq!(stored.homeserver, session().homeserver);
+        assert_eq!(stored.session, session().session);
+
+        store.clear().unwrap();
+        assert!(store.load().unwrap().is_none());
+    }
+
+    #[async_test]
+    async fn test_encrypted_file_session_store_wrong_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+
+        let store = EncryptedFileSessionStore::open(
+            &path,
+            SessionEncryptionKey::Provider(Box::new(|| Ok([1; 32]))),
+        )
+        .unwrap();
+        store.save(&session()).unwrap();
+
+        let result = EncryptedFileSessionStore::open(
+            &path,
+            SessionEncryptionKey::Provider(Box::new(|| Ok([2; 32]))),
+        );
+        assert!(matches!(result, Err(SessionStoreError::Encryption(_))));
+    }
+}
@@ -189,7 +189,7 @@ async fn discover_homeserver(
             Default::default(),
         )
         .await
-        .map_err(|e| match e {
+        .map_err(|e| match e.into_without_request_ids() {
             HttpError::Api(err) => ClientBuildError::AutoDiscovery(err),
             err => ClientBuildError::Http(err),
         })?;
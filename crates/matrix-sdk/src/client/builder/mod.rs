@@ -19,6 +19,8 @@ use std::{fmt, sync::Arc};
 
 use homeserver_config::*;
 use matrix_sdk_base::{store::StoreConfig, BaseClient};
+#[cfg(feature = "appservice")]
+use ruma::OwnedUserId;
 use ruma::{
     api::{error::FromHttpResponseError, MatrixVersion},
     OwnedServerName, ServerName,
@@ -37,9 +39,14 @@ use crate::http_client::HttpSettings;
 #[cfg(feature = "experimental-oidc")]
 use crate::oidc::OidcCtx;
 use crate::{
-    authentication::AuthCtx, client::ClientServerCapabilities, config::RequestConfig,
-    error::RumaApiError, http_client::HttpClient, send_queue::SendQueueData,
-    sliding_sync::VersionBuilder as SlidingSyncVersionBuilder, HttpError, IdParseError,
+    authentication::AuthCtx,
+    client::ClientServerCapabilities,
+    config::{EndpointOverrides, RequestConfig},
+    error::RumaApiError,
+    http_client::HttpClient,
+    send_queue::SendQueueData,
+    sliding_sync::VersionBuilder as SlidingSyncVersionBuilder,
+    HttpError, IdParseError,
 };
 
 /// Builder that allows creating and configuring various parts of a [`Client`].
@@ -88,6 +95,9 @@ pub struct ClientBuilder {
     http_cfg: Option<HttpConfig>,
     store_config: BuilderStoreConfig,
     request_config: RequestConfig,
+    endpoint_overrides: EndpointOverrides,
+    #[cfg(feature = "appservice")]
+    asserted_identity: Option<OwnedUserId>,
     respect_login_well_known: bool,
     server_versions: Option<Box<[MatrixVersion]>>,
     handle_refresh_tokens: bool,
@@ -113,6 +123,9 @@ impl ClientBuilder {
                 Self::DEFAULT_CROSS_PROCESS_STORE_LOCKS_HOLDER_NAME.to_owned(),
             )),
             request_config: Default::default(),
+            endpoint_overrides: Default::default(),
+            #[cfg(feature = "appservice")]
+            asserted_identity: None,
             respect_login_well_known: true,
             server_versions: None,
             handle_refresh_tokens: false,
@@ -280,6 +293,28 @@ impl ClientBuilder {
         self
     }
 
+    /// Override the URLs of the requests sent to the homeserver, for
+    /// deployments behind gateways that rewrite paths or that serve some
+    /// endpoints from another domain.
+    ///
+    /// See [`EndpointOverrides`] for more details.
+    pub fn endpoint_overrides(mut self, endpoint_overrides: EndpointOverrides) -> Self {
+        self.endpoint_overrides = endpoint_overrides;
+        self
+    }
+
+    /// Send all the requests on behalf of the given user, by asserting their
+    /// identity with the `user_id` query parameter.
+    ///
+    /// This is only allowed for the users in the namespace of an application
+    /// service, authenticated with its `as_token`. See
+    /// [`AppService`](crate::appservice::AppService) to manage such users.
+    #[cfg(feature = "appservice")]
+    pub fn assert_identity(mut self, user_id: OwnedUserId) -> Self {
+        self.asserted_identity = Some(user_id);
+        self
+    }
+
     /// Set the proxy through which all the HTTP requests should go.
     ///
     /// Note, only HTTP proxies are supported.
@@ -338,6 +373,21 @@ impl ClientBuilder {
         self
     }
 
+    /// Don't ask the homeserver to compress the bodies of the responses.
+    ///
+    /// By default, the responses compressed with gzip, or with zstd if the
+    /// `compression` feature is enabled, are decompressed as they are
+    /// received. This saves bandwidth on constrained networks for large
+    /// responses like the initial sync or the members of a room, but not
+    /// memory: the decompressed body is buffered in full before it is
+    /// deserialized. Disabling it can make sense when the homeserver is on a
+    /// local network, to save the CPU time spent on compression.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn disable_response_decompression(mut self) -> Self {
+        self.http_settings().disable_response_decompression = true;
+        self
+    }
+
     /// Specify a [`reqwest::Client`] instance to handle sending requests and
     /// receiving responses.
     ///
@@ -346,6 +396,7 @@ impl ClientBuilder {
     /// [`disable_ssl_verification`][ClientBuilder::disable_ssl_verification],
     /// [`add_root_certificates`][ClientBuilder::add_root_certificates],
     /// [`disable_built_in_root_certificates`][ClientBuilder::disable_built_in_root_certificates],
+    /// [`disable_response_decompression`][ClientBuilder::disable_response_decompression],
     /// and [`user_agent()`][ClientBuilder::user_agent].
     pub fn http_client(mut self, client: reqwest::Client) -> Self {
         self.http_cfg = Some(HttpConfig::Custom(client));
@@ -486,7 +537,10 @@ impl ClientBuilder {
             client
         };
 
-        let http_client = HttpClient::new(inner_http_client.clone(), self.request_config);
+        let http_client = HttpClient::new(inner_http_client.clone(), self.request_config)
+            .with_endpoint_overrides(self.endpoint_overrides);
+        #[cfg(feature = "appservice")]
+        let http_client = http_client.with_asserted_identity(self.asserted_identity);
 
         #[allow(unused_variables)]
         let HomeserverDiscoveryResult { server, homeserver, well_known, supported_versions } =
@@ -520,6 +574,8 @@ impl ClientBuilder {
             auth_data: OnceCell::default(),
             reload_session_callback: OnceCell::default(),
             save_session_callback: OnceCell::default(),
+            reauth_handler: Default::default(),
+            reauth_lock: Mutex::new(()),
             #[cfg(feature = "experimental-oidc")]
             oidc: OidcCtx::new(allow_insecure_oidc),
         });
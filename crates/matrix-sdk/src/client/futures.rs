@@ -14,11 +14,15 @@
 
 #![deny(unreachable_pub)]
 
-use std::{fmt::Debug, future::IntoFuture};
+use std::{
+    fmt::Debug,
+    future::{Future, IntoFuture},
+};
 
 use eyeball::SharedObservable;
 #[cfg(not(target_arch = "wasm32"))]
 use eyeball::Subscriber;
+use http::Method;
 #[cfg(feature = "experimental-oidc")]
 use mas_oidc_client::{
     error::{
@@ -28,12 +32,15 @@ use mas_oidc_client::{
     types::errors::ClientErrorCode,
 };
 use matrix_sdk_common::boxed_into_future;
-use ruma::api::{client::error::ErrorKind, error::FromHttpResponseError, OutgoingRequest};
+use ruma::{
+    api::{client::error::ErrorKind, error::FromHttpResponseError, OutgoingRequest},
+    time::Instant,
+};
 #[cfg(feature = "experimental-oidc")]
 use tracing::error;
 use tracing::trace;
 
-use super::super::Client;
+use super::{super::Client, ClientStatus};
 #[cfg(feature = "experimental-oidc")]
 use crate::oidc::OidcError;
 use crate::{
@@ -104,85 +111,193 @@ where
         let Self { client, request, config, send_progress, homeserver_override } = self;
 
         Box::pin(async move {
-            let res = Box::pin(client.send_inner(
-                request.clone(),
-                config,
-                homeserver_override.clone(),
-                send_progress.clone(),
-            ))
-            .await;
-
-            // An `M_UNKNOWN_TOKEN` error can potentially be fixed with a token refresh.
-            if let Err(Some(ErrorKind::UnknownToken { soft_logout })) =
-                res.as_ref().map_err(HttpError::client_api_error_kind)
-            {
-                trace!("Token refresh: Unknown token error received.");
-
-                // If automatic token refresh isn't supported, there is nothing more to do.
-                if !client.inner.auth_ctx.handle_refresh_tokens {
-                    trace!("Token refresh: Automatic refresh disabled.");
-                    client.broadcast_unknown_token(soft_logout);
-                    return res;
-                }
+            send_with_token_refresh(&client, R::METADATA.method, config.is_none(), || {
+                Box::pin(client.send_inner(
+                    request.clone(),
+                    config,
+                    homeserver_override.clone(),
+                    send_progress.clone(),
+                ))
+            })
+            .await
+        })
+    }
+}
+
+impl<R> SendRequest<R>
+where
+    R: OutgoingRequest + Clone + Debug + Send + Sync + 'static,
+    HttpError: From<FromHttpResponseError<R::EndpointError>>,
+{
+    /// Send the request, and return the raw response without reading its
+    /// body, so it can be streamed.
+    ///
+    /// The progress of the upload is not reported, and the homeserver override
+    /// is ignored.
+    ///
+    /// See [`HttpClient::send_streamed()`] for the differences with a
+    /// regular request.
+    ///
+    /// [`HttpClient::send_streamed()`]: crate::http_client::HttpClient::send_streamed
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn streamed(self) -> HttpResult<reqwest::Response> {
+        let Self { client, request, config, .. } = self;
+
+        send_with_token_refresh(&client, R::METADATA.method, config.is_none(), || {
+            Box::pin(client.send_streamed_inner(request.clone(), config))
+        })
+        .await
+    }
+}
+
+/// Send a request with `send`, and send it again if it failed because of an
+/// `M_UNKNOWN_TOKEN` error that could be fixed with a token refresh, or by
+/// authenticating again after a soft logout.
+///
+/// The status of the client and of the connection are updated with the result
+/// of the request.
+async fn send_with_token_refresh<T, F, Fut>(
+    client: &Client,
+    method: Method,
+    has_default_config: bool,
+    send: F,
+) -> HttpResult<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = HttpResult<T>>,
+{
+    let access_token = client.access_token();
+    let start = Instant::now();
+
+    let res = send().await;
+
+    update_client_status(client, method, &res).await;
+
+    // Requests with a custom config, like long-polling sync requests or
+    // uploads, are expected to take longer, so they don't tell much
+    // about the latency.
+    let latency = has_default_config.then(|| start.elapsed());
+    client.update_connection_state(!matches!(res, Err(HttpError::Reqwest(_))), latency);
 
-                // Try to refresh the token and retry the request.
-                if let Err(refresh_error) = client.refresh_access_token().await {
-                    match &refresh_error {
-                        RefreshTokenError::RefreshTokenRequired => {
-                            trace!("Token refresh: The session doesn't have a refresh token.");
-                            // Refreshing access tokens is not supported by this `Session`, ignore.
-                            client.broadcast_unknown_token(soft_logout);
-                        }
-
-                        #[cfg(feature = "experimental-oidc")]
-                        RefreshTokenError::Oidc(oidc_error) => {
-                            match **oidc_error {
-                                OidcError::Oidc(OidcClientError::TokenRefresh(
-                                    TokenRefreshError::Token(TokenRequestError::Http(
-                                        OidcHttpError {
-                                            body:
-                                                Some(OidcErrorBody {
-                                                    error: ClientErrorCode::InvalidGrant,
-                                                    ..
-                                                }),
-                                            ..
-                                        },
-                                    )),
-                                )) => {
-                                    error!("Token refresh: OIDC refresh_token rejected with invalid grant");
-                                    // The refresh was denied, signal to sign out the user.
-                                    client.broadcast_unknown_token(soft_logout);
-                                }
-                                _ => {
-                                    trace!("Token refresh: OIDC refresh encountered a problem.");
-                                    // The refresh failed for other reasons, no
-                                    // need to sign out.
-                                }
-                            };
-                            return Err(HttpError::RefreshToken(refresh_error));
-                        }
-
-                        _ => {
-                            trace!("Token refresh: Token refresh failed.");
-                            // This isn't necessarily correct, but matches the behaviour when
-                            // implementing OIDC.
-                            client.broadcast_unknown_token(soft_logout);
-                            return Err(HttpError::RefreshToken(refresh_error));
-                        }
-                    }
-                } else {
+    // An `M_UNKNOWN_TOKEN` error can potentially be fixed with a token refresh, or by
+    // authenticating again after a soft logout.
+    if let Err(Some(ErrorKind::UnknownToken { soft_logout })) =
+        res.as_ref().map_err(HttpError::client_api_error_kind)
+    {
+        let soft_logout = *soft_logout;
+        trace!("Token refresh: Unknown token error received.");
+
+        let outcome = if !client.inner.auth_ctx.handle_refresh_tokens {
+            // If automatic token refresh isn't supported, the session is invalid.
+            trace!("Token refresh: Automatic refresh disabled.");
+            TokenRefreshOutcome::SessionInvalid(None)
+        } else {
+            // Try to refresh the token and retry the request.
+            match client.refresh_access_token().await {
+                Ok(()) => {
                     trace!("Token refresh: Refresh succeeded, retrying request.");
-                    return Box::pin(client.send_inner(
-                        request,
-                        config,
-                        homeserver_override,
-                        send_progress,
-                    ))
-                    .await;
+                    TokenRefreshOutcome::Refreshed
                 }
+                Err(refresh_error) => token_refresh_failure(refresh_error),
             }
+        };
 
-            res
-        })
+        match outcome {
+            TokenRefreshOutcome::Refreshed => {}
+
+            TokenRefreshOutcome::SessionInvalid(error) => {
+                if !client.handle_unknown_token(soft_logout, access_token.as_deref()).await {
+                    return match error {
+                        Some(error) => Err(error),
+                        None => res,
+                    };
+                }
+
+                trace!("Soft logout: Session resumed, retrying request.");
+            }
+
+            TokenRefreshOutcome::Failed(error) => return Err(error),
+        }
+
+        return send().await;
+    }
+
+    res
+}
+
+/// Update the status of the account from the result of a request.
+///
+/// A locked account can't do anything, so any successful request means that
+/// it was unlocked. A suspended account can still read, so only a successful
+/// request that isn't a `GET` means that it isn't suspended anymore.
+async fn update_client_status<T>(client: &Client, method: Method, res: &HttpResult<T>) {
+    let status = match res {
+        Ok(_) => match client.status() {
+            ClientStatus::Locked => ClientStatus::Active,
+            ClientStatus::Suspended if method != Method::GET => ClientStatus::Active,
+            _ => return,
+        },
+
+        Err(error) => match error.client_api_error_kind() {
+            Some(ErrorKind::UserLocked) => ClientStatus::Locked,
+            Some(kind) if kind.errcode().as_str() == "M_USER_SUSPENDED" => ClientStatus::Suspended,
+            _ => return,
+        },
+    };
+
+    client.update_status(status).await;
+}
+
+/// The outcome of an attempt to refresh the access token after an
+/// `M_UNKNOWN_TOKEN` error.
+enum TokenRefreshOutcome {
+    /// The access token was refreshed, the request can be retried.
+    Refreshed,
+
+    /// The session is invalid, the user needs to authenticate again.
+    ///
+    /// Contains the error to return instead of the `M_UNKNOWN_TOKEN` error, if
+    /// any.
+    SessionInvalid(Option<HttpError>),
+
+    /// The refresh failed for another reason, the session might still be valid.
+    Failed(HttpError),
+}
+
+/// Figure out what a failure to refresh the access token means for the
+/// session.
+fn token_refresh_failure(refresh_error: RefreshTokenError) -> TokenRefreshOutcome {
+    match &refresh_error {
+        RefreshTokenError::RefreshTokenRequired => {
+            trace!("Token refresh: The session doesn't have a refresh token.");
+            // Refreshing access tokens is not supported by this `Session`, ignore.
+            TokenRefreshOutcome::SessionInvalid(None)
+        }
+
+        #[cfg(feature = "experimental-oidc")]
+        RefreshTokenError::Oidc(oidc_error) => match **oidc_error {
+            OidcError::Oidc(OidcClientError::TokenRefresh(TokenRefreshError::Token(
+                TokenRequestError::Http(OidcHttpError {
+                    body: Some(OidcErrorBody { error: ClientErrorCode::InvalidGrant, .. }),
+                    ..
+                }),
+            ))) => {
+                error!("Token refresh: OIDC refresh_token rejected with invalid grant");
+                // The refresh was denied, signal to sign out the user.
+                TokenRefreshOutcome::SessionInvalid(Some(HttpError::RefreshToken(refresh_error)))
+            }
+            _ => {
+                trace!("Token refresh: OIDC refresh encountered a problem.");
+                // The refresh failed for other reasons, no need to sign out.
+                TokenRefreshOutcome::Failed(HttpError::RefreshToken(refresh_error))
+            }
+        },
+
+        _ => {
+            trace!("Token refresh: Token refresh failed.");
+            // This isn't necessarily correct, but matches the behaviour when
+            // implementing OIDC.
+            TokenRefreshOutcome::SessionInvalid(Some(HttpError::RefreshToken(refresh_error)))
+        }
     }
 }
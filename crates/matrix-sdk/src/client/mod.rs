@@ -15,26 +15,35 @@
 // limitations under the License.
 
 use std::{
-    collections::{btree_map, BTreeMap},
+    cmp::Ordering,
+    collections::{btree_map, BTreeMap, BTreeSet},
     fmt::{self, Debug},
     future::{ready, Future},
     pin::Pin,
-    sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock, Weak},
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc, Mutex as StdMutex, RwLock as StdRwLock, Weak,
+    },
+    time::Duration,
 };
 
+use async_stream::stream;
 use eyeball::{SharedObservable, Subscriber};
 use eyeball_im::{Vector, VectorDiff};
 use futures_core::Stream;
-use futures_util::StreamExt;
+use futures_util::{stream, StreamExt};
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::crypto::store::LockableCryptoStore;
 use matrix_sdk_base::{
+    deserialized_responses::MemberEvent,
     event_cache::store::EventCacheStoreLock,
-    store::{DynStateStore, ServerCapabilities},
+    store::{CachedWellKnown, DynStateStore, ServerCapabilities, UploadedFilter},
     sync::{Notification, RoomUpdates},
-    BaseClient, RoomInfoNotableUpdate, RoomState, RoomStateFilter, SendOutsideWasm, SessionMeta,
-    StateStoreDataKey, StateStoreDataValue, SyncOutsideWasm,
+    BaseClient, CachedUserProfile, ContactActivity, RoomInfoNotableUpdate, RoomMemberships,
+    RoomState, RoomStateFilter, SendOutsideWasm, SessionMeta, StateStoreDataKey,
+    StateStoreDataValue, SyncOutsideWasm,
 };
+use matrix_sdk_common::executor::spawn;
 #[cfg(feature = "e2e-encryption")]
 use ruma::events::{room::encryption::RoomEncryptionEventContent, InitialStateEvent};
 use ruma::{
@@ -45,7 +54,7 @@ use ruma::{
             device::{delete_devices, get_devices, update_device},
             directory::{get_public_rooms, get_public_rooms_filtered},
             discovery::{
-                get_capabilities::{self, Capabilities},
+                get_capabilities::{self, Capabilities, RoomVersionsCapability},
                 get_supported_versions,
             },
             error::ErrorKind,
@@ -53,19 +62,27 @@ use ruma::{
             knock::knock_room,
             membership::{join_room_by_id, join_room_by_id_or_alias},
             room::create_room,
-            session::login::v3::DiscoveryInfo,
+            session::login::{self, v3::DiscoveryInfo},
             sync::sync_events,
             uiaa,
             user_directory::search_users,
+            voip::get_turn_server_info,
         },
         error::FromHttpResponseError,
         MatrixVersion, OutgoingRequest,
     },
     assign,
+    events::{
+        direct::DirectUserIdentifier, room::member::MembershipState, tag::TagName,
+        AnySyncStateEvent, AnySyncTimelineEvent, StateEventType, SyncStateEvent,
+    },
+    presence::PresenceState,
     push::Ruleset,
+    serde::{JsonObject, Raw},
     time::Instant,
-    DeviceId, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedServerName,
-    RoomAliasId, RoomId, RoomOrAliasId, ServerName, UInt, UserId,
+    DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedEventId, OwnedRoomId,
+    OwnedRoomOrAliasId, OwnedServerName, OwnedUserId, RoomAliasId, RoomId, RoomOrAliasId,
+    ServerName, UInt, UserId,
 };
 use serde::de::DeserializeOwned;
 use tokio::sync::{broadcast, Mutex, OnceCell, RwLock, RwLockReadGuard};
@@ -76,9 +93,14 @@ use self::futures::SendRequest;
 #[cfg(feature = "experimental-oidc")]
 use crate::oidc::Oidc;
 use crate::{
-    authentication::{AuthCtx, AuthData, ReloadSessionCallback, SaveSessionCallback},
-    config::RequestConfig,
+    authentication::{
+        AuthCtx, AuthData, ReauthCredentials, ReauthHandler, ReloadSessionCallback,
+        SaveSessionCallback, SessionTokens,
+    },
+    config::{RequestConfig, SyncMode},
+    custom_msgtypes::ParseCustomMsgTypeFn,
     deduplicating_handler::DeduplicatingHandler,
+    enrichment::EventEnricher,
     error::{HttpError, HttpResult},
     event_cache::EventCache,
     event_handler::{
@@ -86,12 +108,20 @@ use crate::{
         EventHandlerStore, ObservableEventHandler, SyncEvent,
     },
     http_client::HttpClient,
-    matrix_auth::MatrixAuth,
-    notification_settings::NotificationSettings,
+    matrix_auth::{MatrixAuth, MatrixSessionTokens, RegisterBuilder},
+    media::{MediaPolicy, NetworkType},
+    notification_settings::{NotificationSettings, TemporaryRoomMutes},
+    peeked_room::PeekedRoom,
+    room::{invites::PendingInvite, state_history::StateEventChange, RoomMember},
     room_preview::RoomPreview,
     send_queue::SendQueueData,
+    server_notices::ServerNotice,
     sliding_sync::Version as SlidingSyncVersion,
     sync::{RoomUpdate, SyncResponse},
+    turn_servers::{self, TurnServers},
+    url_preview::UrlPreviewLru,
+    utils::sleep,
+    well_known::{self, WellKnownConfig, WellKnownError},
     Account, AuthApi, AuthSession, Error, Media, Pusher, RefreshTokenError, Result, Room,
     TransmissionProgress,
 };
@@ -117,6 +147,23 @@ type NotificationHandlerFn =
 #[cfg(target_arch = "wasm32")]
 type NotificationHandlerFn = Box<dyn Fn(Notification, Room, Client) -> NotificationHandlerFut>;
 
+/// How long a profile cached by [`Client::get_profiles`] is considered fresh.
+const PROFILE_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How many `/profile` requests [`Client::get_profiles`] sends at once, and
+/// how many cached profiles are looked up at once after a sync.
+const MAX_CONCURRENT_PROFILE_REQUESTS: usize = 10;
+
+/// The average latency above which the connection is considered degraded.
+const DEGRADED_LATENCY: Duration = Duration::from_secs(3);
+
+/// The weight of the latest request in the average latency.
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+/// The maximum number of devices deleted with a single request by
+/// [`Client::delete_inactive_devices()`].
+const DEVICE_DELETION_BATCH_SIZE: usize = 25;
+
 /// Enum controlling if a loop running callbacks should continue or abort.
 ///
 /// This is mainly used in the [`sync_with_callback`] method, the return value
@@ -143,6 +190,48 @@ pub enum SessionChange {
     TokensRefreshed,
 }
 
+/// The status of the account of a `Client`, as reported by the homeserver.
+///
+/// See [`Client::subscribe_to_status()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientStatus {
+    /// The account can be used normally.
+    Active,
+
+    /// The account has been locked by an administrator of the homeserver.
+    ///
+    /// All requests fail with an `M_USER_LOCKED` error until the account is
+    /// unlocked, except logging out.
+    Locked,
+
+    /// The account has been suspended by an administrator of the homeserver,
+    /// as defined in [MSC3823].
+    ///
+    /// The account can still read its data, but requests that modify it, like
+    /// sending messages, fail with an `M_USER_SUSPENDED` error.
+    ///
+    /// [MSC3823]: https://github.com/matrix-org/matrix-spec-proposals/pull/3823
+    Suspended,
+}
+
+/// The state of the connection of a `Client` to the homeserver.
+///
+/// See [`Client::subscribe_to_connection_state()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The homeserver answers requests normally.
+    Online,
+
+    /// The homeserver answers requests, but slowly.
+    Degraded {
+        /// The average time it takes to get a response from the homeserver.
+        latency: Duration,
+    },
+
+    /// The homeserver can't be reached.
+    Offline,
+}
+
 /// An async/await enabled Matrix client.
 ///
 /// All of the state is held in an `Arc` so the `Client` can be cloned freely.
@@ -158,6 +247,21 @@ pub(crate) struct ClientLocks {
     /// explanation.
     pub(crate) mark_as_dm_lock: Mutex<()>,
 
+    /// Lock ensuring that reactions are toggled one at a time, with
+    /// [`Room::toggle_reaction()`].
+    ///
+    /// It holds the reactions redacted this way, mapped to the event they
+    /// reacted to, until their redaction comes back from the homeserver.
+    pub(crate) toggle_reaction_lock: Mutex<BTreeMap<OwnedEventId, OwnedEventId>>,
+
+    /// Lock ensuring that only one DM room is created at a time with a user,
+    /// with [`Client::dm_room_with()`].
+    ///
+    /// It holds the DM rooms created this way, until they show up in the
+    /// `m.direct` account data. The map is only locked to get the cell of a
+    /// user, the room is created while holding the cell only.
+    pub(crate) dm_room_lock: StdMutex<BTreeMap<OwnedUserId, Arc<OnceCell<Room>>>>,
+
     /// Lock ensuring that only a single secret store is getting opened at the
     /// same time.
     ///
@@ -184,6 +288,11 @@ pub(crate) struct ClientLocks {
     #[cfg(feature = "e2e-encryption")]
     pub(crate) backup_upload_lock: Mutex<()>,
 
+    /// Lock ensuring that the room keys waiting for backups to be enabled are
+    /// only modified by a single task at a time.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) pending_utd_sessions_lock: Mutex<()>,
+
     /// Handler making sure we only have one group session sharing request in
     /// flight per room.
     #[cfg(feature = "e2e-encryption")]
@@ -205,6 +314,16 @@ pub(crate) struct ClientLocks {
     /// internal implementation detail, see [`Self::send_single_receipt`].
     pub(crate) read_receipt_deduplicated_handler: DeduplicatingHandler<(String, OwnedEventId)>,
 
+    /// Handler to ensure that only one profile request is running at a time,
+    /// given a user.
+    pub(crate) profile_request_deduplicated_handler: DeduplicatingHandler<OwnedUserId>,
+
+    /// Handler to ensure that a given state event history is only fetched once
+    /// at a time, with [`Room::state_event_history()`]. The key is the room
+    /// ID, the event type and the state key.
+    pub(crate) state_event_history_deduplicated_handler:
+        DeduplicatingHandler<(OwnedRoomId, String, String)>,
+
     #[cfg(feature = "e2e-encryption")]
     pub(crate) cross_process_crypto_store_lock:
         OnceCell<CrossProcessStoreLock<LockableCryptoStore>>,
@@ -286,12 +405,26 @@ pub(crate) struct ClientInner {
     /// keyed by room.
     pub(crate) typing_notice_times: StdRwLock<BTreeMap<OwnedRoomId, Instant>>,
 
+    /// The servers computed by [`Room::route()`].
+    pub(crate) room_routes: StdMutex<RoomRoutes>,
+
+    /// The histories fetched by [`Room::state_event_history()`], keyed by room
+    /// ID, event type and state key.
+    pub(crate) state_event_histories:
+        StdMutex<BTreeMap<(OwnedRoomId, String, String), Vec<StateEventChange>>>,
+
     /// Event handlers. See `add_event_handler`.
     pub(crate) event_handlers: EventHandlerStore,
 
     /// Notification handlers. See `register_notification_handler`.
     notification_handlers: RwLock<Vec<NotificationHandlerFn>>,
 
+    /// Event enrichers. See [`Client::add_event_enricher()`].
+    pub(crate) event_enrichers: StdRwLock<Vec<Arc<dyn EventEnricher>>>,
+
+    /// The custom `msgtype`s, see [`Client::register_custom_msgtype()`].
+    pub(crate) custom_msgtypes: StdRwLock<BTreeMap<String, ParseCustomMsgTypeFn>>,
+
     /// The sender-side of channels used to receive room updates.
     pub(crate) room_update_channels: StdMutex<BTreeMap<OwnedRoomId, broadcast::Sender<RoomUpdate>>>,
 
@@ -299,6 +432,10 @@ pub(crate) struct ClientInner {
     /// sync response.
     pub(crate) room_updates_sender: broadcast::Sender<RoomUpdates>,
 
+    /// The sender-side of a channel used to observe the notices of the
+    /// homeserver. See [`Client::subscribe_to_server_notices()`].
+    server_notices_sender: broadcast::Sender<ServerNotice>,
+
     /// Whether the client should update its homeserver URL with the discovery
     /// information present in the login response.
     respect_login_well_known: bool,
@@ -327,6 +464,125 @@ pub(crate) struct ClientInner {
     ///
     /// [`SendQueue`]: crate::send_queue::SendQueue
     pub(crate) send_queue_data: Arc<SendQueueData>,
+
+    /// The status of the account, see [`Client::subscribe_to_status()`].
+    status: SharedObservable<ClientStatus>,
+
+    /// Whether the [`SendQueue`] was disabled because the account isn't
+    /// active, and must be enabled again once it is.
+    ///
+    /// [`SendQueue`]: crate::send_queue::SendQueue
+    send_queue_disabled_by_status: AtomicBool,
+
+    /// The state of the connection to the homeserver, see
+    /// [`Client::subscribe_to_connection_state()`].
+    connection_state: SharedObservable<ConnectionState>,
+
+    /// The average time it takes to get a response from the homeserver.
+    average_latency: StdMutex<Option<Duration>>,
+
+    /// The client configuration advertised by the server, see
+    /// [`Client::well_known_config()`].
+    well_known: SharedObservable<Option<WellKnownConfig>>,
+
+    /// The `.well-known/matrix/client` document the configuration comes from,
+    /// with the time it was fetched, like it's cached in the store.
+    well_known_cache: StdRwLock<Option<CachedWellKnown>>,
+
+    /// The room the user is currently looking at, see
+    /// [`Client::set_active_room()`].
+    active_room: StdRwLock<Option<OwnedRoomId>>,
+
+    /// The mode of the sync loop, see [`Client::set_sync_mode()`].
+    sync_mode: StdMutex<SyncModeState>,
+
+    /// The TURN servers last fetched from the homeserver, see
+    /// [`Client::turn_servers()`].
+    turn_servers: Mutex<Option<TurnServers>>,
+
+    /// The TURN servers to use instead of the ones of the homeserver, see
+    /// [`Client::set_turn_servers_override()`].
+    turn_servers_override: SharedObservable<Option<TurnServers>>,
+
+    /// The rooms muted until a point in time, see
+    /// [`NotificationSettings::mute_room_until()`].
+    pub(crate) temporary_room_mutes: TemporaryRoomMutes,
+
+    /// The policy choosing which media can be downloaded automatically, loaded
+    /// lazily from the store, see [`Media::set_media_policy()`].
+    pub(crate) media_policy: Mutex<Option<MediaPolicy>>,
+
+    /// The activity of the users the client received presence for, loaded
+    /// lazily from the store, see [`Client::recently_active_contacts()`].
+    pub(crate) contact_activities: Mutex<Option<BTreeMap<OwnedUserId, ContactActivity>>>,
+
+    /// The order in which the cached URL previews were used, loaded lazily
+    /// from the store, see [`Client::get_url_preview()`].
+    pub(crate) url_preview_lru: Mutex<Option<UrlPreviewLru>>,
+
+    /// The type of the network the device is connected to, see
+    /// [`Media::set_network_type()`].
+    pub(crate) network_type: StdRwLock<NetworkType>,
+}
+
+/// The state of the [`SyncMode`] of a client.
+#[derive(Debug, Default)]
+struct SyncModeState {
+    /// The current mode.
+    mode: SyncMode,
+
+    /// The sync token from before the client switched to
+    /// [`SyncMode::Minimal`], if the events of the rooms still need to be
+    /// caught up on.
+    ///
+    /// `Some(None)` means that the client hadn't synced yet, so the catch-up
+    /// is an initial sync.
+    catch_up_token: Option<Option<String>>,
+}
+
+/// The servers computed by [`Room::route()`], keyed by room.
+///
+/// They are invalidated when the members, the power levels or the server ACLs
+/// of the room change.
+#[derive(Debug, Default)]
+pub(crate) struct RoomRoutes {
+    routes: BTreeMap<OwnedRoomId, Vec<OwnedServerName>>,
+
+    /// Incremented every time routes are invalidated, so a route computed
+    /// concurrently with an invalidation isn't cached.
+    generation: u64,
+}
+
+impl RoomRoutes {
+    /// Get the cached route of a room.
+    pub(crate) fn get(&self, room_id: &RoomId) -> Option<&Vec<OwnedServerName>> {
+        self.routes.get(room_id)
+    }
+
+    /// The current generation, to pass to [`Self::insert()`] once the route
+    /// has been computed.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Cache the route of a room, unless some routes have been invalidated
+    /// since the given generation, as it might be outdated.
+    pub(crate) fn insert(
+        &mut self,
+        room_id: &RoomId,
+        route: Vec<OwnedServerName>,
+        generation: u64,
+    ) {
+        if generation == self.generation {
+            self.routes.insert(room_id.to_owned(), route);
+        }
+    }
+
+    /// Forget the route of a room.
+    pub(crate) fn invalidate(&mut self, room_id: &RoomId) {
+        self.generation += 1;
+        self.routes.remove(room_id);
+    }
 }
 
 impl ClientInner {
@@ -361,12 +617,17 @@ impl ClientInner {
             cross_process_store_locks_holder_name,
             server_capabilities: RwLock::new(server_capabilities),
             typing_notice_times: Default::default(),
+            room_routes: Default::default(),
+            state_event_histories: Default::default(),
             event_handlers: Default::default(),
             notification_handlers: Default::default(),
+            event_enrichers: Default::default(),
+            custom_msgtypes: Default::default(),
             room_update_channels: Default::default(),
             // A single `RoomUpdates` is sent once per sync, so we assume that 32 is sufficient
             // ballast for all observers to catch up.
             room_updates_sender: broadcast::Sender::new(32),
+            server_notices_sender: broadcast::Sender::new(8),
             respect_login_well_known,
             sync_beat: event_listener::Event::new(),
             event_cache,
@@ -375,6 +636,21 @@ impl ClientInner {
             e2ee: EncryptionData::new(encryption_settings),
             #[cfg(feature = "e2e-encryption")]
             verification_state: SharedObservable::new(VerificationState::Unknown),
+            status: SharedObservable::new(ClientStatus::Active),
+            send_queue_disabled_by_status: AtomicBool::new(false),
+            connection_state: SharedObservable::new(ConnectionState::Online),
+            average_latency: Default::default(),
+            well_known: SharedObservable::new(None),
+            well_known_cache: Default::default(),
+            active_room: Default::default(),
+            sync_mode: Default::default(),
+            turn_servers: Default::default(),
+            turn_servers_override: SharedObservable::new(None),
+            temporary_room_mutes: Default::default(),
+            media_policy: Default::default(),
+            contact_activities: Default::default(),
+            url_preview_lru: Default::default(),
+            network_type: Default::default(),
         };
 
         #[allow(clippy::let_and_return)]
@@ -415,6 +691,14 @@ impl Client {
         self.inner.base_client.subscribe_to_ignore_user_list_changes()
     }
 
+    /// Returns a subscriber that publishes the hidden rooms every time they
+    /// change.
+    ///
+    /// See [`Account::hide_room()`].
+    pub fn subscribe_to_hidden_rooms_changes(&self) -> Subscriber<BTreeSet<OwnedRoomId>> {
+        self.inner.base_client.subscribe_to_hidden_rooms_changes()
+    }
+
     /// Create a new [`ClientBuilder`].
     pub fn builder() -> ClientBuilder {
         ClientBuilder::new()
@@ -478,6 +762,14 @@ impl Client {
         Ok(res.capabilities)
     }
 
+    /// Get the room versions supported by the homeserver, along with the
+    /// version it uses by default when creating rooms.
+    ///
+    /// This is the `m.room_versions` capability of the homeserver.
+    pub async fn supported_room_versions(&self) -> HttpResult<RoomVersionsCapability> {
+        Ok(self.get_capabilities().await?.room_versions)
+    }
+
     /// Get a copy of the default request config.
     ///
     /// The default request config is what's used when sending requests if no
@@ -508,6 +800,22 @@ impl Client {
         self.inner.homeserver.read().unwrap().clone()
     }
 
+    /// Hint the client about the room the user is currently looking at, if
+    /// any.
+    ///
+    /// The client uses this to prioritize the work related to this room: for
+    /// instance, the `/keys/query` requests for its members are sent before
+    /// the others, so that the trust state of their devices is known sooner.
+    pub fn set_active_room(&self, room_id: Option<&RoomId>) {
+        *self.inner.active_room.write().unwrap() = room_id.map(ToOwned::to_owned);
+    }
+
+    /// The room the user is currently looking at, as set with
+    /// [`Client::set_active_room()`].
+    pub fn active_room(&self) -> Option<OwnedRoomId> {
+        self.inner.active_room.read().unwrap().clone()
+    }
+
     /// Get the sliding sync version.
     pub fn sliding_sync_version(&self) -> SlidingSyncVersion {
         self.inner.sliding_sync_version.read().unwrap().clone()
@@ -616,6 +924,32 @@ impl Client {
         MatrixAuth::new(self.clone())
     }
 
+    /// Register an account on the homeserver, with the native Matrix
+    /// authentication API.
+    ///
+    /// The returned [`RegisterBuilder`] completes the authentication stages
+    /// required by the homeserver, with the data set on it. It can also
+    /// register a guest account, or convert the guest account of this client
+    /// to a full account.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// client
+    ///     .register()
+    ///     .username("alice")
+    ///     .password("hunter2")
+    ///     .registration_token("0123456789")
+    ///     .await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn register(&self) -> RegisterBuilder {
+        RegisterBuilder::new(self.matrix_auth())
+    }
+
     /// Get the account of the current owner of the client.
     pub fn account(&self) -> Account {
         Account::new(self.clone())
@@ -1071,6 +1405,48 @@ impl Client {
         self.inner.room_updates_sender.subscribe()
     }
 
+    /// Get the server notices room, i.e. the joined room that the homeserver
+    /// sends its notices to the user in.
+    ///
+    /// It's the room marked with the `m.server_notice` tag.
+    pub fn server_notices_room(&self) -> Option<Room> {
+        self.joined_rooms().into_iter().find(|room| room.is_server_notice())
+    }
+
+    /// Subscribe to the notices sent by the homeserver in the server notices
+    /// room, as they're received in sync responses.
+    ///
+    /// Apps should notably show a blocking banner when a
+    /// [`ServerNoticeKind::UsageLimitReached`] notice is received.
+    ///
+    /// [`ServerNoticeKind::UsageLimitReached`]: crate::server_notices::ServerNoticeKind::UsageLimitReached
+    pub fn subscribe_to_server_notices(&self) -> broadcast::Receiver<ServerNotice> {
+        self.inner.server_notices_sender.subscribe()
+    }
+
+    /// Broadcast the notices received in the server notices room.
+    pub(crate) fn handle_server_notices(&self, rooms: &RoomUpdates) {
+        let Some(own_user_id) = self.user_id() else {
+            return;
+        };
+
+        for (room_id, update) in &rooms.join {
+            if !self.get_room(room_id).is_some_and(|room| room.is_server_notice()) {
+                continue;
+            }
+
+            for event in &update.timeline.events {
+                if let Some(notice) =
+                    ServerNotice::from_timeline_event(room_id.clone(), event.raw(), own_user_id)
+                {
+                    debug!(event_id = %notice.event_id, "Received a server notice");
+                    // It's fine if there are no subscribers.
+                    let _ = self.inner.server_notices_sender.send(notice);
+                }
+            }
+        }
+    }
+
     pub(crate) async fn notification_handlers(
         &self,
     ) -> RwLockReadGuard<'_, Vec<NotificationHandlerFn>> {
@@ -1114,6 +1490,35 @@ impl Client {
             .collect()
     }
 
+    /// Get the rooms with the given tag, like [`TagName::Favorite`], sorted by
+    /// the order of the tag.
+    ///
+    /// The rooms whose tag has an `order` come first, from the lowest to the
+    /// highest order, followed by the rooms whose tag has no `order`. Rooms
+    /// with the same order are sorted by room ID.
+    pub async fn rooms_by_tag(&self, tag: &TagName) -> Result<Vec<Room>> {
+        let mut tagged_rooms = Vec::new();
+
+        for room in self.rooms() {
+            if let Some(tag_info) = room.tags().await?.and_then(|mut tags| tags.remove(tag)) {
+                tagged_rooms.push((tag_info.order, room));
+            }
+        }
+
+        tagged_rooms.sort_by(|(left_order, left_room), (right_order, right_room)| {
+            let by_order = match (left_order, right_order) {
+                (Some(left_order), Some(right_order)) => left_order.total_cmp(right_order),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+
+            by_order.then_with(|| left_room.room_id().cmp(right_room.room_id()))
+        });
+
+        Ok(tagged_rooms.into_iter().map(|(_, room)| room).collect())
+    }
+
     /// Returns the invited rooms this client knows about.
     pub fn invited_rooms(&self) -> Vec<Room> {
         self.base_client()
@@ -1123,6 +1528,454 @@ impl Client {
             .collect()
     }
 
+    /// Get the invites the current user hasn't acted upon yet, as an
+    /// observable list.
+    ///
+    /// The returned stream first yields the current pending invites, then a
+    /// new list every time a sync response adds an invite, or turns a pending
+    /// invite into a joined or left room.
+    ///
+    /// Each [`PendingInvite`] is enriched with the inviter's profile, a
+    /// preview of the room and the number of rooms shared with the inviter.
+    pub fn pending_invites(&self) -> impl Stream<Item = Vec<PendingInvite>> + '_ {
+        let mut room_updates = self.subscribe_to_all_room_updates();
+
+        stream! {
+            let mut invites = self.load_pending_invites().await;
+            let mut invited_room_ids =
+                invites.iter().map(|invite| invite.room_id().to_owned()).collect::<BTreeSet<_>>();
+            yield invites;
+
+            loop {
+                match room_updates.recv().await {
+                    Ok(updates) => {
+                        let touches_invites = !updates.invite.is_empty()
+                            || updates
+                                .join
+                                .keys()
+                                .chain(updates.leave.keys())
+                                .any(|room_id| invited_room_ids.contains(room_id));
+
+                        if !touches_invites {
+                            continue;
+                        }
+                    }
+                    // We may have missed an update, recompute the list to be safe.
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+
+                invites = self.load_pending_invites().await;
+                invited_room_ids =
+                    invites.iter().map(|invite| invite.room_id().to_owned()).collect();
+                yield invites;
+            }
+        }
+    }
+
+    /// Compute the current list of pending invites, skipping (and logging)
+    /// the invites that couldn't be loaded.
+    async fn load_pending_invites(&self) -> Vec<PendingInvite> {
+        let mut invites = Vec::new();
+
+        for room in self.invited_rooms() {
+            match PendingInvite::from_room(self, &room).await {
+                Ok(invite) => invites.push(invite),
+                Err(err) => {
+                    warn!(room_id = ?room.room_id(), "couldn't load a pending invite: {err}")
+                }
+            }
+        }
+
+        invites
+    }
+
+    /// Get the global profiles of the given users.
+    ///
+    /// Profiles are cached in the state store for an hour; only the users whose
+    /// profile is missing from the cache or stale are fetched from the
+    /// homeserver, a few at a time. Concurrent calls asking for the same user
+    /// share a single `/profile` request. Cached profiles are also kept up to
+    /// date by the member events received through sync.
+    ///
+    /// Users whose profile the homeserver doesn't know about are left out of
+    /// the returned map. When the profile of a user can't be fetched, the error
+    /// is logged and their stale cached profile is returned instead, if any,
+    /// so a single failure doesn't deprive the caller of the other profiles.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use ruma::user_id;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let alice = user_id!("@alice:example.org");
+    /// let bob = user_id!("@bob:example.org");
+    ///
+    /// let profiles = client.get_profiles([alice, bob]).await?;
+    ///
+    /// if let Some(profile) = profiles.get(alice) {
+    ///     println!("Alice is known as {:?}", profile.display_name);
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn get_profiles<'a>(
+        &self,
+        user_ids: impl IntoIterator<Item = &'a UserId>,
+    ) -> Result<BTreeMap<OwnedUserId, CachedUserProfile>> {
+        let now = MilliSecondsSinceUnixEpoch::now();
+        let mut profiles = BTreeMap::new();
+        let mut stale_profiles = BTreeMap::new();
+        let mut to_fetch = Vec::new();
+
+        for user_id in user_ids {
+            match self.cached_profile(user_id).await? {
+                Some(profile) if !is_profile_stale(&profile, now) => {
+                    profiles.insert(user_id.to_owned(), profile);
+                }
+                Some(profile) => {
+                    stale_profiles.insert(user_id.to_owned(), profile);
+                    to_fetch.push(user_id.to_owned());
+                }
+                None => to_fetch.push(user_id.to_owned()),
+            }
+        }
+
+        let mut results = stream::iter(to_fetch)
+            .map(|user_id| async move {
+                let result = self.fetch_profile(&user_id).await;
+                (user_id, result)
+            })
+            .buffer_unordered(MAX_CONCURRENT_PROFILE_REQUESTS);
+
+        while let Some((user_id, result)) = results.next().await {
+            if let Err(err) = result {
+                warn!(%user_id, "Couldn't fetch a profile: {err}");
+
+                if let Some(profile) = stale_profiles.remove(&user_id) {
+                    profiles.insert(user_id, profile);
+                }
+
+                continue;
+            }
+
+            if let Some(profile) = self.cached_profile(&user_id).await? {
+                profiles.insert(user_id, profile);
+            }
+        }
+
+        Ok(profiles)
+    }
+
+    /// Get the cached profile of a user, regardless of its age.
+    async fn cached_profile(&self, user_id: &UserId) -> Result<Option<CachedUserProfile>> {
+        Ok(self
+            .store()
+            .get_kv_data(StateStoreDataKey::UserProfile(user_id))
+            .await?
+            .and_then(|value| value.into_user_profile()))
+    }
+
+    /// Fetch the profile of a user from the homeserver and cache it, making
+    /// sure only one request is in flight for a given user.
+    async fn fetch_profile(&self, user_id: &UserId) -> Result<()> {
+        self.locks()
+            .profile_request_deduplicated_handler
+            .run(user_id.to_owned(), async move {
+                let key = StateStoreDataKey::UserProfile(user_id);
+
+                match self.account().fetch_user_profile_of(user_id).await {
+                    Ok(response) => {
+                        let profile = CachedUserProfile {
+                            display_name: response.displayname,
+                            avatar_url: response.avatar_url,
+                            fetched_at: MilliSecondsSinceUnixEpoch::now(),
+                        };
+
+                        self.store()
+                            .set_kv_data(key, StateStoreDataValue::UserProfile(profile))
+                            .await?;
+                    }
+
+                    Err(err) if err.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                        debug!(%user_id, "The homeserver doesn't know the profile of this user");
+                        self.store().remove_kv_data(key).await?;
+                    }
+
+                    Err(err) => return Err(err),
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    /// Update the cached profiles of the users who changed their display name
+    /// or avatar in one of the joined rooms of a sync response.
+    ///
+    /// Only profiles that are already cached are updated, so that we don't
+    /// start caching the profile of every member we hear about. When it can't
+    /// be told whether a member event reflects the global profile of the user,
+    /// like when they join a room again, their cached profile is invalidated.
+    pub(crate) async fn refresh_cached_profiles(&self, rooms: &RoomUpdates) {
+        let events = rooms.join.values().flat_map(|room| {
+            let state = room.state.iter().map(|raw| raw.cast_ref::<AnySyncTimelineEvent>());
+            state.chain(room.timeline.events.iter().map(|event| event.raw()))
+        });
+
+        // Only a join from the user themselves carries their global profile. The other
+        // events are filtered out before being deserialized, and only the latest
+        // member event of each user is kept.
+        let mut member_events = BTreeMap::new();
+
+        for raw in events {
+            let is_member_event = raw
+                .get_field::<String>("type")
+                .ok()
+                .flatten()
+                .is_some_and(|event_type| event_type == "m.room.member");
+            if !is_member_event
+                || raw.get_field::<String>("sender").ok().flatten()
+                    != raw.get_field::<String>("state_key").ok().flatten()
+            {
+                continue;
+            }
+
+            let Ok(AnySyncTimelineEvent::State(AnySyncStateEvent::RoomMember(
+                SyncStateEvent::Original(event),
+            ))) = raw.deserialize()
+            else {
+                continue;
+            };
+
+            if event.content.membership == MembershipState::Join {
+                member_events.insert(event.state_key.clone(), event);
+            }
+        }
+
+        if member_events.is_empty() {
+            return;
+        }
+
+        // Look up the cached profiles concurrently.
+        let mut lookups = stream::iter(member_events.into_values())
+            .map(|event| async move {
+                let cached_profile = self.cached_profile(&event.state_key).await;
+                (event, cached_profile)
+            })
+            .buffer_unordered(MAX_CONCURRENT_PROFILE_REQUESTS);
+
+        while let Some((event, cached_profile)) = lookups.next().await {
+            let cached_profile = match cached_profile {
+                Ok(Some(profile)) => profile,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!(user_id = %event.state_key, "Couldn't load a cached profile: {err}");
+                    continue;
+                }
+            };
+
+            let key = StateStoreDataKey::UserProfile(&event.state_key);
+
+            // Without the previous profile of the member in this room, there's no way to
+            // know whether the new one is the global profile, the cached profile is
+            // fetched again the next time it's needed.
+            let Some(prev_content) = event
+                .unsigned
+                .prev_content
+                .as_ref()
+                .filter(|prev_content| prev_content.membership == MembershipState::Join)
+            else {
+                if event.content.displayname != cached_profile.display_name
+                    || event.content.avatar_url != cached_profile.avatar_url
+                {
+                    debug!(user_id = %event.state_key, "Invalidating a cached profile");
+
+                    if let Err(err) = self.store().remove_kv_data(key).await {
+                        warn!(
+                            user_id = %event.state_key,
+                            "Couldn't invalidate a cached profile: {err}"
+                        );
+                    }
+                }
+
+                continue;
+            };
+
+            // The member event only reflects a change of the global profile if the room
+            // was using the global profile before. Otherwise, the user has a custom
+            // profile in this room, which must not overwrite the global one.
+            if prev_content.displayname != cached_profile.display_name
+                || prev_content.avatar_url != cached_profile.avatar_url
+            {
+                continue;
+            }
+
+            let profile = CachedUserProfile {
+                display_name: event.content.displayname,
+                avatar_url: event.content.avatar_url,
+                fetched_at: MilliSecondsSinceUnixEpoch::now(),
+            };
+
+            if let Err(err) =
+                self.store().set_kv_data(key, StateStoreDataValue::UserProfile(profile)).await
+            {
+                warn!(user_id = %event.state_key, "Couldn't update a cached profile: {err}");
+            }
+        }
+    }
+
+    /// Forget the servers computed by [`Room::route()`] for the rooms whose
+    /// members, power levels or server ACLs changed in a sync response.
+    pub(crate) fn invalidate_room_routes(&self, rooms: &RoomUpdates) {
+        fn changes_route(raw: &Raw<AnySyncTimelineEvent>) -> bool {
+            matches!(
+                raw.get_field::<StateEventType>("type"),
+                Ok(Some(
+                    StateEventType::RoomMember
+                        | StateEventType::RoomPowerLevels
+                        | StateEventType::RoomServerAcl
+                ))
+            )
+        }
+
+        // Even if no route is cached, one might be being computed from the previous
+        // state, so the invalidation must be recorded anyway.
+        let mut routes = self.inner.room_routes.lock().unwrap();
+
+        for (room_id, update) in &rooms.join {
+            let state = update.state.iter().map(|raw| raw.cast_ref::<AnySyncTimelineEvent>());
+            if state
+                .chain(update.timeline.events.iter().map(|event| event.raw()))
+                .any(changes_route)
+            {
+                routes.invalidate(room_id);
+            }
+        }
+
+        for (room_id, update) in &rooms.leave {
+            let state = update.state.iter().map(|raw| raw.cast_ref::<AnySyncTimelineEvent>());
+            if state
+                .chain(update.timeline.events.iter().map(|event| event.raw()))
+                .any(changes_route)
+            {
+                routes.invalidate(room_id);
+            }
+        }
+
+        // The stripped state of invited and knocked rooms is replaced as a whole.
+        for room_id in rooms.invite.keys().chain(rooms.knocked.keys()) {
+            routes.invalidate(room_id);
+        }
+    }
+
+    /// Keep the `m.direct` account data in sync with the DM rooms the user
+    /// joined or left.
+    ///
+    /// The rooms the user joined from a DM invite are added if they're
+    /// missing from it, which happens when the invite was accepted by a client
+    /// that doesn't handle DMs. The DM rooms the user left, or was banned
+    /// from, are removed from it, so they're not picked by
+    /// [`Client::dm_room_with()`] anymore; forgotten rooms are removed by
+    /// [`Room::forget()`] already. The account data is updated in a
+    /// background task.
+    pub(crate) async fn repair_direct_rooms(&self, rooms: &RoomUpdates) {
+        let Some(own_user_id) = self.user_id() else {
+            return;
+        };
+
+        let mut dm_rooms = BTreeMap::new();
+
+        for (room_id, update) in &rooms.join {
+            let state = update.state.iter().map(|raw| raw.cast_ref::<AnySyncTimelineEvent>());
+            let mut events = state.chain(update.timeline.events.iter().map(|event| event.raw()));
+
+            let joined_from_dm_invite = events.any(|raw| {
+                // Only deserialize the own membership events.
+                let is_own_member_event = raw
+                    .get_field::<String>("type")
+                    .ok()
+                    .flatten()
+                    .is_some_and(|event_type| event_type == "m.room.member")
+                    && raw
+                        .get_field::<String>("state_key")
+                        .ok()
+                        .flatten()
+                        .is_some_and(|state_key| state_key == own_user_id.as_str());
+                if !is_own_member_event {
+                    return false;
+                }
+
+                let Ok(AnySyncTimelineEvent::State(AnySyncStateEvent::RoomMember(
+                    SyncStateEvent::Original(event),
+                ))) = raw.deserialize()
+                else {
+                    return false;
+                };
+
+                event.state_key == own_user_id
+                    && event.content.membership == MembershipState::Join
+                    && event.unsigned.prev_content.is_some_and(|prev| {
+                        prev.membership == MembershipState::Invite && prev.is_direct == Some(true)
+                    })
+            });
+
+            if !joined_from_dm_invite {
+                continue;
+            }
+
+            let Some(room) = self.get_room(room_id) else {
+                continue;
+            };
+            if room.direct_targets_length() != 0 {
+                continue;
+            }
+
+            let members = match room.members_no_sync(RoomMemberships::ACTIVE).await {
+                Ok(members) => members,
+                Err(err) => {
+                    warn!(%room_id, "Couldn't load the members of a DM room: {err}");
+                    continue;
+                }
+            };
+
+            let targets: Vec<_> = members
+                .iter()
+                .map(|member| member.user_id().to_owned())
+                .filter(|user_id| user_id != own_user_id)
+                .collect();
+
+            if !targets.is_empty() {
+                dm_rooms.insert(room_id.clone(), targets);
+            }
+        }
+
+        // The rooms in the `leave` section are the ones the user left or was banned from.
+        let left_dm_rooms: BTreeSet<_> = rooms
+            .leave
+            .keys()
+            .filter(|room_id| {
+                self.get_room(room_id).is_some_and(|room| room.direct_targets_length() != 0)
+            })
+            .cloned()
+            .collect();
+
+        if dm_rooms.is_empty() && left_dm_rooms.is_empty() {
+            return;
+        }
+
+        // Don't block the sync with the requests to update the account data.
+        let client = self.clone();
+        spawn(async move {
+            if let Err(err) = client.account().repair_dm_rooms(&dm_rooms, &left_dm_rooms).await {
+                warn!("Couldn't repair the DM rooms in m.direct: {err}");
+            }
+        });
+    }
+
     /// Returns the left rooms this client knows about.
     pub fn left_rooms(&self) -> Vec<Room> {
         self.base_client()
@@ -1166,6 +2019,43 @@ impl Client {
         RoomPreview::from_not_joined(self, room_id, room_or_alias_id, via).await
     }
 
+    /// Peek into a world-readable room, without joining it.
+    ///
+    /// This fetches the state and the most recent events of the room, and
+    /// returns a read-only [`PeekedRoom`] handle. Since the room won't be part
+    /// of the sync responses, the handle needs to be polled to receive new
+    /// events.
+    ///
+    /// Fails with [`Error::NotWorldReadable`] if the history visibility of the
+    /// room isn't `world_readable`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::room_alias_id};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let room = client.peek_room(room_alias_id!("#archive:example.org").into()).await?;
+    ///
+    /// for event in room.events() {
+    ///     println!("{:?}", event.raw());
+    /// }
+    ///
+    /// // Later on…
+    /// room.poll_for_updates().await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn peek_room(&self, room_or_alias_id: &RoomOrAliasId) -> Result<PeekedRoom> {
+        let room_id = match <&RoomId>::try_from(room_or_alias_id) {
+            Ok(room_id) => room_id.to_owned(),
+            Err(alias) => self.resolve_room_alias(alias).await?.room_id,
+        };
+
+        PeekedRoom::new(self, room_id).await
+    }
+
     /// Resolve a room alias to a room id and a list of servers which know
     /// about it.
     ///
@@ -1230,17 +2120,31 @@ impl Client {
     /// See the documentation of the corresponding authentication API's
     /// `restore_session` method for more information.
     ///
+    /// If [`EncryptionSettings::check_device_keys_on_restore`] is set, the
+    /// keys of the device in the crypto store are then checked against the
+    /// ones known by the homeserver, and an [`Error::SessionMismatch`] is
+    /// returned if they don't match.
+    ///
     /// # Panics
     ///
     /// Panics if a session was already restored or logged in.
+    ///
+    /// [`EncryptionSettings::check_device_keys_on_restore`]: crate::encryption::EncryptionSettings::check_device_keys_on_restore
     #[instrument(skip_all)]
     pub async fn restore_session(&self, session: impl Into<AuthSession>) -> Result<()> {
         let session = session.into();
         match session {
-            AuthSession::Matrix(s) => Box::pin(self.matrix_auth().restore_session(s)).await,
+            AuthSession::Matrix(s) => Box::pin(self.matrix_auth().restore_session(s)).await?,
             #[cfg(feature = "experimental-oidc")]
-            AuthSession::Oidc(s) => Box::pin(self.oidc().restore_session(*s)).await,
+            AuthSession::Oidc(s) => Box::pin(self.oidc().restore_session(*s)).await?,
         }
+
+        #[cfg(feature = "e2e-encryption")]
+        if self.encryption().settings().check_device_keys_on_restore {
+            self.encryption().check_own_device_keys().await?;
+        }
+
+        Ok(())
     }
 
     pub(crate) async fn set_session_meta(
@@ -1289,13 +2193,22 @@ impl Client {
     /// This method will either get a filter ID from the store or upload the
     /// filter definition to the homeserver and return the new filter ID.
     ///
+    /// The stored filter ID is only reused if it was uploaded to the same
+    /// homeserver, for the same user, with the same definition. Otherwise the
+    /// filter is uploaded again, and the new ID replaces the stored one.
+    ///
+    /// Named filters can also be used directly for syncing, with
+    /// [`SyncSettings::named_filter()`].
+    ///
+    /// [`SyncSettings::named_filter()`]: crate::config::SyncSettings::named_filter
+    ///
     /// # Arguments
     ///
     /// * `filter_name` - The unique name of the filter, this name will be used
     /// locally to store and identify the filter ID returned by the server.
     ///
     /// * `definition` - The filter definition that should be uploaded to the
-    /// server if no filter ID can be found in the store.
+    /// server if no matching filter ID can be found in the store.
     ///
     /// # Examples
     ///
@@ -1335,19 +2248,46 @@ impl Client {
         filter_name: &str,
         definition: FilterDefinition,
     ) -> Result<String> {
-        if let Some(filter) = self.inner.base_client.get_filter(filter_name).await? {
-            debug!("Found filter locally");
-            Ok(filter)
+        let user_id = self.user_id().ok_or(Error::AuthenticationRequired)?;
+        let homeserver = self.homeserver();
+
+        if let Some(filter) = self
+            .store()
+            .get_kv_data(StateStoreDataKey::UploadedFilter(filter_name))
+            .await?
+            .and_then(|value| value.into_uploaded_filter())
+        {
+            if filter.user_id == user_id
+                && filter.homeserver == homeserver.as_str()
+                && serde_json::to_value(&filter.definition)? == serde_json::to_value(&definition)?
+            {
+                debug!("Found filter locally");
+                return Ok(filter.filter_id);
+            }
+
+            debug!("The stored filter doesn't match, uploading it again");
         } else {
             debug!("Didn't find filter locally");
-            let user_id = self.user_id().ok_or(Error::AuthenticationRequired)?;
-            let request = FilterUploadRequest::new(user_id.to_owned(), definition);
-            let response = self.send(request).await?;
+        }
 
-            self.inner.base_client.receive_filter_upload(filter_name, &response).await?;
+        let request = FilterUploadRequest::new(user_id.to_owned(), definition.clone());
+        let response = self.send(request).await?;
 
-            Ok(response.filter_id)
-        }
+        self.store()
+            .set_kv_data(
+                StateStoreDataKey::UploadedFilter(filter_name),
+                StateStoreDataValue::UploadedFilter(UploadedFilter {
+                    filter_id: response.filter_id.clone(),
+                    homeserver: homeserver.to_string(),
+                    user_id: user_id.to_owned(),
+                    definition,
+                }),
+            )
+            .await?;
+        // Keep the filter available through `BaseClient::get_filter()` too.
+        self.inner.base_client.receive_filter_upload(filter_name, &response).await?;
+
+        Ok(response.filter_id)
     }
 
     /// Join a room by `RoomId`.
@@ -1445,6 +2385,11 @@ impl Client {
     /// one user is invited, the room will be automatically added to the direct
     /// rooms in the account data.
     ///
+    /// If the request asks for a specific room version, it is checked against
+    /// the [supported room versions][Self::supported_room_versions] of the
+    /// homeserver first; if it isn't available, the room is created with the
+    /// homeserver's default room version instead.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -1461,7 +2406,26 @@ impl Client {
     /// assert!(client.create_room(request).await.is_ok());
     /// # };
     /// ```
-    pub async fn create_room(&self, request: create_room::v3::Request) -> Result<Room> {
+    pub async fn create_room(&self, mut request: create_room::v3::Request) -> Result<Room> {
+        if let Some(room_version) = &request.room_version {
+            match self.supported_room_versions().await {
+                Ok(room_versions) if !room_versions.available.contains_key(room_version) => {
+                    warn!(
+                        requested = %room_version,
+                        default = %room_versions.default,
+                        "the requested room version isn't supported by the homeserver, \
+                         falling back to the server's default"
+                    );
+                    request.room_version = None;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    // Let the server decide whether it supports the room version.
+                    warn!("couldn't fetch the supported room versions: {err}");
+                }
+            }
+        }
+
         let invite = request.invite.clone();
         let is_direct_room = request.is_direct;
         let response = self.send(request).await?;
@@ -1513,6 +2477,119 @@ impl Client {
         self.create_room(request).await
     }
 
+    /// Get the DM room with the given user, creating it if there's none.
+    ///
+    /// The joined rooms marked in the `m.direct` account data as DMs with this
+    /// user only are considered first: a room they have joined is preferred
+    /// over a room they're only invited to, and rooms they've left are
+    /// ignored. Failing that, a joined room with only the two of you, that
+    /// was created or joined as a DM but is missing from `m.direct`, is used
+    /// and added to `m.direct`.
+    ///
+    /// Otherwise, a new room is created with [`Client::create_dm()`]. Calling
+    /// this method again before the new room shows up in `m.direct` returns
+    /// the same room.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The ID of the user to get a DM room with.
+    #[instrument(skip(self))]
+    pub async fn dm_room_with(&self, user_id: &UserId) -> Result<Room> {
+        if let Some(room) = self.find_dm_room(user_id).await? {
+            self.locks().dm_room_lock.lock().unwrap().remove(user_id);
+            return Ok(room);
+        }
+
+        let created_dm_room = {
+            let mut created_dm_rooms = self.locks().dm_room_lock.lock().unwrap();
+            let created_dm_room = created_dm_rooms.entry(user_id.to_owned()).or_default();
+
+            // Forget about a room created before that was left since.
+            if created_dm_room.get().is_some_and(|room| room.state() != RoomState::Joined) {
+                *created_dm_room = Default::default();
+            }
+
+            created_dm_room.clone()
+        };
+
+        // Concurrent calls for the same user wait for the room created by the first
+        // one.
+        let room = created_dm_room
+            .get_or_try_init(|| async {
+                debug!("No DM room found, creating one");
+                self.create_dm(user_id).await
+            })
+            .await?;
+
+        Ok(room.clone())
+    }
+
+    /// Find an existing DM room with the given user.
+    ///
+    /// See [`Client::dm_room_with()`] for the rules.
+    async fn find_dm_room(&self, user_id: &UserId) -> Result<Option<Room>> {
+        let own_user_id = self.user_id().ok_or(Error::AuthenticationRequired)?;
+        let target = <&DirectUserIdentifier>::from(user_id);
+
+        let mut invited = None;
+        let mut unmarked = None;
+
+        for room in self.joined_rooms() {
+            let targets = room.direct_targets();
+
+            if targets.is_empty() {
+                // Only look for unmarked DM rooms until one is found.
+                if unmarked.is_some() || room.active_members_count() != 2 {
+                    continue;
+                }
+
+                let Some(member) = room.get_member_no_sync(user_id).await? else {
+                    continue;
+                };
+                if !matches!(member.membership(), MembershipState::Join | MembershipState::Invite) {
+                    continue;
+                }
+
+                let own_member = room.get_member_no_sync(own_user_id).await?;
+                if is_direct_membership(&member)
+                    || own_member.is_some_and(|m| is_direct_membership(&m))
+                {
+                    unmarked = Some(room);
+                }
+
+                continue;
+            }
+
+            if targets.len() != 1 || !targets.contains(target) {
+                continue;
+            }
+
+            match room.get_member_no_sync(user_id).await?.as_ref().map(|member| member.membership())
+            {
+                Some(MembershipState::Join) => return Ok(Some(room)),
+                Some(MembershipState::Invite) => {
+                    invited.get_or_insert(room);
+                }
+                _ => {}
+            }
+        }
+
+        if invited.is_some() {
+            return Ok(invited);
+        }
+
+        if let Some(room) = &unmarked {
+            debug!(room_id = %room.room_id(), "Found a DM room missing from m.direct");
+
+            let dm_rooms = BTreeMap::from([(room.room_id().to_owned(), vec![user_id.to_owned()])]);
+            if let Err(err) = self.account().repair_dm_rooms(&dm_rooms, &BTreeSet::new()).await {
+                warn!(room_id = %room.room_id(), "Couldn't add a DM room to m.direct: {err}");
+            }
+        }
+
+        Ok(unmarked)
+    }
+
     /// Search the homeserver's directory for public rooms with a filter.
     ///
     /// # Arguments
@@ -1634,12 +2711,166 @@ impl Client {
             .await
     }
 
-    fn broadcast_unknown_token(&self, soft_logout: &bool) {
+    /// Send a request, and return the raw response without reading its body,
+    /// so it can be streamed.
+    ///
+    /// This doesn't handle token refreshes, use
+    /// [`SendRequest::streamed()`] instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn send_streamed_inner<Request>(
+        &self,
+        request: Request,
+        config: Option<RequestConfig>,
+    ) -> HttpResult<reqwest::Response>
+    where
+        Request: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<Request::EndpointError>>,
+    {
+        let access_token = self.access_token();
+
+        self.inner
+            .http_client
+            .send_streamed(
+                request,
+                config,
+                self.homeserver().to_string(),
+                access_token.as_deref(),
+                &self.server_versions().await?,
+            )
+            .await
+    }
+
+    /// Handle an access token that was rejected by the homeserver, and that
+    /// couldn't be refreshed.
+    ///
+    /// After a soft logout, the [`ReauthHandler`] is used to resume the
+    /// session, if one is set. Otherwise, or if that fails, subscribers of the
+    /// session changes are notified that the session is invalid.
+    ///
+    /// Returns `true` if the session was resumed and the failed request can be
+    /// sent again.
+    pub(crate) async fn handle_unknown_token(
+        &self,
+        soft_logout: bool,
+        failed_access_token: Option<&str>,
+    ) -> bool {
+        if soft_logout && self.resume_soft_logged_out_session(failed_access_token).await {
+            return true;
+        }
+
         _ = self
             .inner
             .auth_ctx
             .session_change_sender
-            .send(SessionChange::UnknownToken { soft_logout: *soft_logout });
+            .send(SessionChange::UnknownToken { soft_logout });
+
+        false
+    }
+
+    /// Authenticate again with the [`ReauthHandler`], after a soft logout.
+    ///
+    /// The device, and thus the end-to-end encryption state, is kept: only the
+    /// tokens of the session are replaced.
+    ///
+    /// Returns `true` if the session was resumed.
+    async fn resume_soft_logged_out_session(&self, failed_access_token: Option<&str>) -> bool {
+        let Some(handler) = self.inner.auth_ctx.reauth_handler.read().unwrap().clone() else {
+            return false;
+        };
+
+        // Only authenticate once if several requests failed at the same time.
+        let _guard = self.inner.auth_ctx.reauth_lock.lock().await;
+
+        if self.access_token().as_deref() != failed_access_token {
+            debug!("Soft logout: The session was already resumed");
+            return true;
+        }
+
+        let Some(credentials) = handler.reauthenticate(self).await else {
+            debug!("Soft logout: The re-authentication handler gave up");
+            return false;
+        };
+
+        let resumed = match credentials {
+            ReauthCredentials::Password(password) => self.login_after_soft_logout(password).await,
+
+            ReauthCredentials::Tokens(SessionTokens::Matrix(tokens)) => {
+                if matches!(self.auth_api(), Some(AuthApi::Matrix(_))) {
+                    self.matrix_auth().set_session_tokens(tokens);
+                    true
+                } else {
+                    warn!("Soft logout: Got Matrix tokens for a session using another API");
+                    false
+                }
+            }
+
+            #[cfg(feature = "experimental-oidc")]
+            ReauthCredentials::Tokens(SessionTokens::Oidc(tokens)) => {
+                if matches!(self.auth_api(), Some(AuthApi::Oidc(_))) {
+                    self.oidc().set_session_tokens(tokens);
+                    true
+                } else {
+                    warn!("Soft logout: Got OIDC tokens for a session using another API");
+                    false
+                }
+            }
+        };
+
+        if !resumed {
+            return false;
+        }
+
+        if let Some(save_session_callback) = self.inner.auth_ctx.save_session_callback.get() {
+            if let Err(err) = save_session_callback(self.clone()) {
+                error!("when saving session after soft logout: {err}");
+            }
+        }
+
+        _ = self.inner.auth_ctx.session_change_sender.send(SessionChange::TokensRefreshed);
+
+        true
+    }
+
+    /// Log in again with the password of the user on the current device, and
+    /// use the new tokens for the session.
+    async fn login_after_soft_logout(&self, password: String) -> bool {
+        let (Some(user_id), Some(device_id)) = (self.user_id(), self.device_id()) else {
+            return false;
+        };
+
+        if !matches!(self.auth_api(), Some(AuthApi::Matrix(_))) {
+            warn!("Soft logout: Can't log in with a password to a session using another API");
+            return false;
+        }
+
+        let login_info = login::v3::LoginInfo::Password(login::v3::Password::new(
+            uiaa::UserIdentifier::UserIdOrLocalpart(user_id.to_string()),
+            password,
+        ));
+        let request = assign!(login::v3::Request::new(login_info), {
+            device_id: Some(device_id.to_owned()),
+            refresh_token: self.inner.auth_ctx.handle_refresh_tokens,
+        });
+
+        let response = match self.send_inner(request, None, None, Default::default()).await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!("Soft logout: Couldn't log in again: {err}");
+                return false;
+            }
+        };
+
+        if response.user_id != user_id || response.device_id != device_id {
+            warn!("Soft logout: The homeserver logged in with another user or device");
+            return false;
+        }
+
+        self.matrix_auth().set_session_tokens(MatrixSessionTokens {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+        });
+
+        true
     }
 
     /// Fetches server capabilities from network; no caching.
@@ -1758,19 +2989,205 @@ impl Client {
         self.get_or_load_and_cache_server_capabilities(|caps| caps.unstable_features.clone()).await
     }
 
-    /// Empty the server version and unstable features cache.
+    /// Empty the server version and unstable features cache.
+    ///
+    /// Since the SDK caches server capabilities (versions and unstable
+    /// features), it's possible to have a stale entry in the cache. This
+    /// functions makes it possible to force reset it.
+    pub async fn reset_server_capabilities(&self) -> Result<()> {
+        // Empty the in-memory caches.
+        let mut guard = self.inner.server_capabilities.write().await;
+        guard.server_versions = None;
+        guard.unstable_features = None;
+
+        // Empty the store cache.
+        Ok(self.store().remove_kv_data(StateStoreDataKey::ServerCapabilities).await?)
+    }
+
+    /// Get the client configuration advertised by the server in its
+    /// `.well-known/matrix/client` document.
+    ///
+    /// The configuration is cached in memory and in the store, and is fetched
+    /// again from the server once the cached version is older than
+    /// [`CachedWellKnown::STALE_THRESHOLD`].
+    ///
+    /// The document is verified before being used: it must advertise the
+    /// homeserver used by this client.
+    pub async fn well_known_config(&self) -> Result<WellKnownConfig, WellKnownError> {
+        let cached = self.inner.well_known_cache.read().unwrap().clone();
+        let cached = match cached {
+            Some(cached) => Some(cached),
+            None => match self.store().get_kv_data(StateStoreDataKey::WellKnown).await {
+                Ok(stored) => stored.and_then(|stored| stored.into_well_known()),
+                Err(err) => {
+                    warn!("error when loading the cached well-known document: {err}");
+                    // fallthrough to network.
+                    None
+                }
+            },
+        };
+
+        // Both caches are only used until the document is stale.
+        if let Some(cached) = cached {
+            if let Some(content) = cached.maybe_decode() {
+                match self.verify_well_known(content.clone()) {
+                    Ok(config) => {
+                        *self.inner.well_known_cache.write().unwrap() = Some(cached);
+                        self.inner.well_known.set_if_not_eq(Some(config.clone()));
+                        return Ok(config);
+                    }
+                    Err(err) => {
+                        warn!("the cached well-known document is invalid: {err}");
+                    }
+                }
+            }
+        }
+
+        self.refresh_well_known_config().await
+    }
+
+    /// Fetch the `.well-known/matrix/client` document from the server again,
+    /// bypassing the caches.
+    ///
+    /// Subscribers of [`Client::subscribe_to_well_known_config()`] are
+    /// notified if the configuration changed.
+    pub async fn refresh_well_known_config(&self) -> Result<WellKnownConfig, WellKnownError> {
+        let server = self.server().cloned().unwrap_or_else(|| self.homeserver());
+
+        let response = self
+            .inner
+            .http_client
+            .inner
+            .get(well_known::well_known_url(&server))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(HttpError::from)?;
+        let body = response.bytes().await.map_err(HttpError::from)?;
+
+        let content = well_known::parse_body(&body)?;
+        let config = self.verify_well_known(content.clone())?;
+
+        let cached = CachedWellKnown::new(content);
+
+        if let Err(err) = self
+            .store()
+            .set_kv_data(
+                StateStoreDataKey::WellKnown,
+                StateStoreDataValue::WellKnown(cached.clone()),
+            )
+            .await
+        {
+            warn!("error when caching the well-known document: {err}");
+        }
+
+        *self.inner.well_known_cache.write().unwrap() = Some(cached);
+        self.inner.well_known.set_if_not_eq(Some(config.clone()));
+
+        Ok(config)
+    }
+
+    /// Subscribe to changes of the client configuration advertised by the
+    /// server.
+    ///
+    /// The current value is `None` until the configuration has been loaded
+    /// with [`Client::well_known_config()`] or
+    /// [`Client::refresh_well_known_config()`].
+    pub fn subscribe_to_well_known_config(&self) -> Subscriber<Option<WellKnownConfig>> {
+        self.inner.well_known.subscribe()
+    }
+
+    fn verify_well_known(&self, content: JsonObject) -> Result<WellKnownConfig, WellKnownError> {
+        let config = WellKnownConfig::parse(content)?;
+        config.verify_homeserver(&self.homeserver())?;
+        Ok(config)
+    }
+
+    /// Get the TURN servers to use for VoIP calls.
+    ///
+    /// The credentials are cached until shortly before they expire, so this
+    /// method only sends a request to the homeserver when needed. If an
+    /// override was set with [`Client::set_turn_servers_override()`], it is
+    /// returned instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let turn_servers = client.turn_servers().await?;
+    ///
+    /// for uri in &turn_servers.uris {
+    ///     println!("Using TURN server {uri} as {}", turn_servers.username);
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn turn_servers(&self) -> HttpResult<TurnServers> {
+        if let Some(servers) = self.inner.turn_servers_override.get() {
+            return Ok(servers);
+        }
+
+        // Hold the lock during the request, so that concurrent callers wait for
+        // the result instead of sending their own request.
+        let mut cached = self.inner.turn_servers.lock().await;
+
+        if let Some(servers) = cached.as_ref().filter(|s| !s.needs_refresh(Instant::now())) {
+            return Ok(servers.clone());
+        }
+
+        let response = self.send(get_turn_server_info::v3::Request::new()).await?;
+        let servers = TurnServers::from_response(response, Instant::now());
+        *cached = Some(servers.clone());
+
+        Ok(servers)
+    }
+
+    /// Get a stream of the TURN servers to use for VoIP calls.
+    ///
+    /// The current TURN servers are sent as the first item, then new ones are
+    /// sent whenever the credentials are refreshed, shortly before they
+    /// expire, or when the override set with
+    /// [`Client::set_turn_servers_override()`] changes. The credentials are
+    /// only refreshed while the stream is polled.
+    ///
+    /// If the credentials can't be fetched, the request is retried
+    /// periodically.
+    pub fn subscribe_to_turn_servers(&self) -> impl Stream<Item = TurnServers> {
+        let client = self.clone();
+        let mut override_stream = self.inner.turn_servers_override.subscribe();
+
+        stream! {
+            loop {
+                let delay = match client.turn_servers().await {
+                    Ok(servers) => {
+                        let delay = servers.refresh_delay(Instant::now());
+                        yield servers;
+                        delay
+                    }
+                    Err(error) => {
+                        warn!("Couldn't fetch the TURN servers: {error}");
+                        Some(turn_servers::RETRY_DELAY)
+                    }
+                };
+
+                tokio::select! {
+                    _ = sleep(delay.unwrap_or_default()), if delay.is_some() => {}
+                    Some(_) = override_stream.next() => {}
+                    else => break,
+                }
+            }
+        }
+    }
+
+    /// Set the TURN servers to use instead of the ones of the homeserver, for
+    /// deployments with a static TURN configuration.
     ///
-    /// Since the SDK caches server capabilities (versions and unstable
-    /// features), it's possible to have a stale entry in the cache. This
-    /// functions makes it possible to force reset it.
-    pub async fn reset_server_capabilities(&self) -> Result<()> {
-        // Empty the in-memory caches.
-        let mut guard = self.inner.server_capabilities.write().await;
-        guard.server_versions = None;
-        guard.unstable_features = None;
-
-        // Empty the store cache.
-        Ok(self.store().remove_kv_data(StateStoreDataKey::ServerCapabilities).await?)
+    /// Set `None` to use the TURN servers of the homeserver again.
+    pub fn set_turn_servers_override(&self, servers: Option<TurnServers>) {
+        self.inner.turn_servers_override.set(servers);
     }
 
     /// Check whether MSC 4028 is enabled on the homeserver.
@@ -1869,6 +3286,103 @@ impl Client {
         self.send(request).await
     }
 
+    /// Delete the devices of the current user that the homeserver hasn't seen
+    /// for at least `older_than`.
+    ///
+    /// The current device is never deleted, nor are the devices the
+    /// homeserver doesn't know the last activity of.
+    ///
+    /// The devices are deleted in batches, all authenticated with the same
+    /// `auth_data`. Like with [`Client::delete_devices()`], the first request
+    /// needs to set it to `None` and will fail with a `UiaaResponse` if the
+    /// homeserver requires user-interactive auth, in which case this method
+    /// must be called again with some `auth_data`.
+    ///
+    /// Deleting the devices stops at the first batch that fails, and its error
+    /// is returned. The batches deleted before it stay deleted, so calling this
+    /// method again, for example with new `auth_data` if the homeserver
+    /// doesn't accept the same user-interactive auth session for several
+    /// requests, only deletes the remaining devices.
+    ///
+    /// Returns the devices that were deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `older_than` - How long a device must have been inactive to be
+    ///   deleted.
+    ///
+    /// * `auth_data` - The data for the user-interactive auth, if required.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use matrix_sdk::{ruma::api::client::uiaa, Client};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let older_than = Duration::from_secs(90 * 24 * 60 * 60);
+    ///
+    /// if let Err(e) = client.delete_inactive_devices(older_than, None).await {
+    ///     if let Some(info) = e.as_uiaa_response() {
+    ///         let mut password = uiaa::Password::new(
+    ///             uiaa::UserIdentifier::UserIdOrLocalpart("example".to_owned()),
+    ///             "wordpass".to_owned(),
+    ///         );
+    ///         password.session = info.session.clone();
+    ///
+    ///         let deleted = client
+    ///             .delete_inactive_devices(older_than, Some(uiaa::AuthData::Password(password)))
+    ///             .await?;
+    ///
+    ///         println!("Deleted {} devices", deleted.len());
+    ///     }
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip(self, auth_data))]
+    pub async fn delete_inactive_devices(
+        &self,
+        older_than: Duration,
+        auth_data: Option<uiaa::AuthData>,
+    ) -> HttpResult<Vec<OwnedDeviceId>> {
+        let now = u64::from(MilliSecondsSinceUnixEpoch::now().0);
+        let is_older = |ts: MilliSecondsSinceUnixEpoch| {
+            Duration::from_millis(now.saturating_sub(ts.0.into())) >= older_than
+        };
+
+        let inactive_devices: Vec<_> = self
+            .devices()
+            .await?
+            .devices
+            .into_iter()
+            .filter(|device| self.device_id() != Some(&*device.device_id))
+            .filter(|device| device.last_seen_ts.is_some_and(is_older))
+            .map(|device| device.device_id)
+            .collect();
+
+        debug!(num_devices = inactive_devices.len(), "Deleting the inactive devices");
+
+        let mut deleted = Vec::with_capacity(inactive_devices.len());
+
+        for batch in inactive_devices.chunks(DEVICE_DELETION_BATCH_SIZE) {
+            if let Err(error) = self.delete_devices(batch, auth_data.clone()).await {
+                // Let the caller handle the error, which might be a request for
+                // user-interactive auth, and call us again for the remaining devices.
+                warn!(
+                    num_deleted = deleted.len(),
+                    "Couldn't delete a batch of inactive devices: {error}"
+                );
+                return Err(error);
+            }
+
+            deleted.extend_from_slice(batch);
+        }
+
+        Ok(deleted)
+    }
+
     /// Change the display name of a device owned by the current user.
     ///
     /// Returns a `update_device::Response` which specifies the result
@@ -1985,8 +3499,10 @@ impl Client {
     #[instrument(skip(self))]
     pub async fn sync_once(
         &self,
-        sync_settings: crate::config::SyncSettings,
+        mut sync_settings: crate::config::SyncSettings,
     ) -> Result<SyncResponse> {
+        self.resolve_named_filter(&mut sync_settings).await?;
+
         // The sync might not return for quite a while due to the timeout.
         // We'll see if there's anything crypto related to send out before we
         // sync, i.e. if we closed our client after a sync but before the
@@ -1998,13 +3514,33 @@ impl Client {
             error!(error = ?e, "Error while sending outgoing E2EE requests");
         }
 
-        let request = assign!(sync_events::v3::Request::new(), {
+        let mut request = assign!(sync_events::v3::Request::new(), {
             filter: sync_settings.filter.map(|f| *f),
             since: sync_settings.token,
             full_state: sync_settings.full_state,
             set_presence: sync_settings.set_presence,
             timeout: sync_settings.timeout,
         });
+
+        let catch_up_token = {
+            let state = self.inner.sync_mode.lock().unwrap();
+
+            match state.mode {
+                SyncMode::Minimal => {
+                    request.filter =
+                        Some(sync_events::v3::Filter::FilterDefinition(SyncMode::minimal_filter()));
+                    request.set_presence = PresenceState::Offline;
+                    None
+                }
+                SyncMode::Full => state.catch_up_token.clone(),
+            }
+        };
+
+        if let Some(token) = &catch_up_token {
+            debug!(?token, "Catching up on the events missed in the minimal sync mode");
+            request.since = token.clone();
+        }
+
         let mut request_config = self.request_config();
         if let Some(timeout) = sync_settings.timeout {
             request_config.timeout += timeout;
@@ -2014,6 +3550,14 @@ impl Client {
         let next_batch = response.next_batch.clone();
         let response = self.process_sync(response).await?;
 
+        if catch_up_token.is_some() {
+            let mut state = self.inner.sync_mode.lock().unwrap();
+
+            if state.mode == SyncMode::Full {
+                state.catch_up_token = None;
+            }
+        }
+
         #[cfg(feature = "e2e-encryption")]
         if let Err(e) = self.send_outgoing_requests().await {
             error!(error = ?e, "Error while sending outgoing E2EE requests");
@@ -2318,6 +3862,39 @@ impl Client {
         }
     }
 
+    /// Change the amount of data requested by the sync loop.
+    ///
+    /// The new mode is used from the next `/sync` request, the one currently
+    /// in flight, if any, isn't interrupted.
+    ///
+    /// When switching back from [`SyncMode::Minimal`] to [`SyncMode::Full`],
+    /// the next `/sync` request catches up on the events of the rooms that
+    /// were skipped in the meantime, by syncing again from the position the
+    /// client was at when it switched to [`SyncMode::Minimal`].
+    pub async fn set_sync_mode(&self, mode: SyncMode) {
+        let token = self.sync_token().await;
+        let mut state = self.inner.sync_mode.lock().unwrap();
+
+        if state.mode == mode {
+            return;
+        }
+
+        debug!(?mode, "Changing the sync mode");
+
+        // Keep the oldest position, if the client didn't catch up yet since the last
+        // time it was in the minimal mode.
+        if mode == SyncMode::Minimal && state.catch_up_token.is_none() {
+            state.catch_up_token = Some(token);
+        }
+
+        state.mode = mode;
+    }
+
+    /// The current mode of the sync loop, see [`Client::set_sync_mode()`].
+    pub fn sync_mode(&self) -> SyncMode {
+        self.inner.sync_mode.lock().unwrap().mode
+    }
+
     /// Get the current, if any, sync token of the client.
     /// This will be None if the client didn't sync at least once.
     pub(crate) async fn sync_token(&self) -> Option<String> {
@@ -2330,6 +3907,138 @@ impl Client {
         self.send(request).await
     }
 
+    /// Get the current status of the account, as reported by the homeserver.
+    pub fn status(&self) -> ClientStatus {
+        self.inner.status.get()
+    }
+
+    /// Subscribe to the changes of the status of the account.
+    ///
+    /// The status is updated from the responses of the homeserver: it changes
+    /// to [`ClientStatus::Locked`] or [`ClientStatus::Suspended`] when a
+    /// request fails with the corresponding error, and back to
+    /// [`ClientStatus::Active`] when a request that was forbidden succeeds
+    /// again.
+    ///
+    /// The [`SendQueue`](crate::send_queue::SendQueue) is disabled while the
+    /// account isn't active, and enabled again once it is, unless it was
+    /// already disabled before.
+    pub fn subscribe_to_status(&self) -> Subscriber<ClientStatus> {
+        self.inner.status.subscribe()
+    }
+
+    /// Update the status of the account, pausing or resuming the send queue
+    /// accordingly.
+    pub(crate) async fn update_status(&self, status: ClientStatus) {
+        let Some(previous) = self.inner.status.set_if_not_eq(status) else {
+            return;
+        };
+
+        debug!(?previous, ?status, "The status of the account changed");
+
+        let send_queue = self.send_queue();
+
+        if status != ClientStatus::Active {
+            // Only take over the send queue if it was enabled, so that it's not enabled
+            // again later if the user disabled it.
+            if send_queue.is_enabled() {
+                self.inner.send_queue_disabled_by_status.store(true, AtomicOrdering::SeqCst);
+                send_queue.set_enabled(false).await;
+            }
+        } else if self.inner.send_queue_disabled_by_status.swap(false, AtomicOrdering::SeqCst) {
+            send_queue.set_enabled(true).await;
+        }
+    }
+
+    /// Get the current state of the connection to the homeserver.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.inner.connection_state.get()
+    }
+
+    /// Subscribe to the changes of the state of the connection to the
+    /// homeserver.
+    ///
+    /// The state is updated from the outcome of the requests, including the
+    /// sync requests: it changes to [`ConnectionState::Offline`] when a request
+    /// fails because the homeserver can't be reached, and back to
+    /// [`ConnectionState::Online`] or [`ConnectionState::Degraded`] as soon as
+    /// a request gets a response. The latency is averaged over the requests
+    /// sent with the default [`RequestConfig`], which leaves out long-polling
+    /// sync requests and media uploads.
+    ///
+    /// When the connection comes back, the [`SendQueue`] and the outgoing
+    /// end-to-end encryption requests, like the upload of keys, resume right
+    /// away instead of waiting for their next attempt.
+    ///
+    /// [`SendQueue`]: crate::send_queue::SendQueue
+    pub fn subscribe_to_connection_state(&self) -> Subscriber<ConnectionState> {
+        self.inner.connection_state.subscribe()
+    }
+
+    /// Update the state of the connection after a request, resuming the
+    /// pending requests if the connection came back.
+    ///
+    /// `latency` is the time it took to get a response, if the request is
+    /// representative of the latency of the homeserver.
+    pub(crate) fn update_connection_state(&self, reachable: bool, latency: Option<Duration>) {
+        let state = if reachable {
+            let mut average_latency = self.inner.average_latency.lock().unwrap();
+
+            if let Some(latency) = latency {
+                *average_latency = Some(match *average_latency {
+                    Some(average) => {
+                        average.mul_f64(1.0 - LATENCY_SMOOTHING)
+                            + latency.mul_f64(LATENCY_SMOOTHING)
+                    }
+                    None => latency,
+                });
+            }
+
+            match *average_latency {
+                Some(latency) if latency >= DEGRADED_LATENCY => {
+                    ConnectionState::Degraded { latency }
+                }
+                _ => ConnectionState::Online,
+            }
+        } else {
+            ConnectionState::Offline
+        };
+
+        let Some(previous) = self.inner.connection_state.set_if_not_eq(state) else {
+            return;
+        };
+
+        // Don't log every change of the latency.
+        if previous == ConnectionState::Offline || state == ConnectionState::Offline {
+            debug!(?previous, ?state, "The state of the connection changed");
+        }
+
+        if previous == ConnectionState::Offline {
+            // Don't hold up the request that noticed the connection came back.
+            let client = self.clone();
+            spawn(async move {
+                client.resume_after_reconnection().await;
+            });
+        }
+    }
+
+    /// Resume sending the requests that were waiting for the connection to
+    /// come back.
+    async fn resume_after_reconnection(&self) {
+        let send_queue = self.send_queue();
+
+        // Wake up the rooms whose queue was disabled by a network error, unless
+        // the queue was disabled on purpose.
+        if send_queue.is_enabled() {
+            send_queue.set_enabled(true).await;
+        }
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Err(error) = self.send_outgoing_requests().await {
+            warn!("Couldn't send the outgoing requests after reconnecting: {error}");
+        }
+    }
+
     /// Subscribes a new receiver to client SessionChange broadcasts.
     pub fn subscribe_to_session_changes(&self) -> broadcast::Receiver<SessionChange> {
         let broadcast = &self.inner.auth_ctx.session_change_sender;
@@ -2360,6 +4069,51 @@ impl Client {
         Ok(())
     }
 
+    /// Set the handler used to authenticate again after a soft logout.
+    ///
+    /// When the homeserver rejects the access token with a soft logout, and
+    /// the token couldn't be refreshed, the handler is asked for credentials
+    /// to resume the session on the same device. This keeps the end-to-end
+    /// encryption state, so the client can carry on as if nothing happened:
+    /// the failed request is sent again, and [`SessionChange::TokensRefreshed`]
+    /// is broadcast.
+    ///
+    /// If there is no handler, or if it can't resume the session,
+    /// [`SessionChange::UnknownToken`] is broadcast, and the error is returned.
+    /// The store is left untouched either way, so the user can also log in
+    /// again on the same device later.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use matrix_sdk::{
+    ///     async_trait,
+    ///     authentication::{ReauthCredentials, ReauthHandler},
+    ///     Client,
+    /// };
+    /// # use url::Url;
+    ///
+    /// struct AskForPassword;
+    ///
+    /// #[async_trait]
+    /// impl ReauthHandler for AskForPassword {
+    ///     async fn reauthenticate(&self, _client: &Client) -> Option<ReauthCredentials> {
+    ///         // Ask the user for their password…
+    ///         # let password = String::new();
+    ///         Some(ReauthCredentials::Password(password))
+    ///     }
+    /// }
+    ///
+    /// # async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// let client = Client::new(homeserver).await?;
+    /// client.set_reauth_handler(AskForPassword);
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn set_reauth_handler(&self, handler: impl ReauthHandler + 'static) {
+        *self.inner.auth_ctx.reauth_handler.write().unwrap() = Some(Arc::new(handler));
+    }
+
     /// Get the notification settings of the current owner of the client.
     pub async fn notification_settings(&self) -> NotificationSettings {
         let ruleset = self.account().push_rules().await.unwrap_or_else(|_| Ruleset::new());
@@ -2443,6 +4197,25 @@ impl Client {
     }
 }
 
+/// Whether a member event, or the invite it replaced, flags the room as a DM.
+fn is_direct_membership(member: &RoomMember) -> bool {
+    match member.event().as_ref() {
+        MemberEvent::Sync(SyncStateEvent::Original(event)) => event
+            .content
+            .is_direct
+            .or_else(|| event.unsigned.prev_content.as_ref().and_then(|prev| prev.is_direct))
+            .unwrap_or(false),
+        MemberEvent::Sync(SyncStateEvent::Redacted(_)) => false,
+        MemberEvent::Stripped(event) => event.content.is_direct.unwrap_or(false),
+    }
+}
+
+/// Whether a cached profile is older than [`PROFILE_CACHE_TTL`].
+fn is_profile_stale(profile: &CachedUserProfile, now: MilliSecondsSinceUnixEpoch) -> bool {
+    let age = u64::from(now.0).saturating_sub(profile.fetched_at.0.into());
+    Duration::from_millis(age) >= PROFILE_CACHE_TTL
+}
+
 /// A weak reference to the inner client, useful when trying to get a handle
 /// on the owning client.
 #[derive(Clone)]
@@ -2486,17 +4259,19 @@ struct ClientServerCapabilities {
 // The http mocking library is not supported for wasm32
 #[cfg(all(test, not(target_arch = "wasm32")))]
 pub(crate) mod tests {
-    use std::{sync::Arc, time::Duration};
+    use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
     use assert_matches::assert_matches;
-    use futures_util::FutureExt;
+    use futures_util::{pin_mut, FutureExt, StreamExt};
     use matrix_sdk_base::{
-        store::{MemoryStore, StoreConfig},
+        deserialized_responses::SyncTimelineEvent,
+        store::{CachedWellKnown, MemoryStore, StoreConfig},
+        sync::{JoinedRoomUpdate, RoomUpdates, Timeline},
         RoomState,
     };
     use matrix_sdk_test::{
-        async_test, test_json, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
-        DEFAULT_TEST_ROOM_ID,
+        async_test, sync_timeline_event, test_json, JoinedRoomBuilder, StateTestEvent,
+        SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
     };
     #[cfg(target_arch = "wasm32")]
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
@@ -2505,7 +4280,7 @@ pub(crate) mod tests {
         api::{client::room::create_room::v3::Request as CreateRoomRequest, MatrixVersion},
         assign,
         events::ignored_user_list::IgnoredUserListEventContent,
-        owned_room_id, room_alias_id, room_id, RoomId, ServerName, UserId,
+        owned_room_id, owned_server_name, room_alias_id, room_id, RoomId, ServerName, UserId,
     };
     use serde_json::json;
     use tokio::{
@@ -2514,18 +4289,20 @@ pub(crate) mod tests {
     };
     use url::Url;
     use wiremock::{
-        matchers::{body_json, header, method, path, query_param_is_missing},
+        matchers::{body_json, header, method, path, query_param, query_param_is_missing},
         Mock, MockServer, ResponseTemplate,
     };
 
     use super::Client;
     use crate::{
         client::WeakClient,
-        config::{RequestConfig, SyncSettings},
+        config::{RequestConfig, SyncMode, SyncSettings},
         test_utils::{
             logged_in_client, mocks::MatrixMockServer, no_retry_test_client, set_client_session,
             test_client_builder, test_client_builder_with_server,
         },
+        turn_servers::TurnServers,
+        well_known::WellKnownError,
         Error,
     };
 
@@ -2977,6 +4754,147 @@ pub(crate) mod tests {
             .any(|version| *version == MatrixVersion::V1_0));
     }
 
+    #[async_test]
+    async fn test_well_known_config_caching() {
+        let server = MockServer::start().await;
+        let well_known = |jitsi_domain: &str| {
+            json!({
+                "m.homeserver": { "base_url": server.uri() },
+                "io.element.e2ee": { "secure_backup_required": true },
+                "im.vector.riot.jitsi": { "preferredDomain": jitsi_domain },
+            })
+        };
+
+        let well_known_mock = Mock::given(method("GET"))
+            .and(path("/.well-known/matrix/client"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(well_known("jitsi.example.org")))
+            .named("first well-known mock")
+            .expect(1)
+            .mount_as_scoped(&server)
+            .await;
+
+        let memory_store = Arc::new(MemoryStore::new());
+        let build_client = || {
+            Client::builder()
+                .homeserver_url(server.uri())
+                .store_config(
+                    StoreConfig::new("cross-process-store-locks-holder-name".to_owned())
+                        .state_store(memory_store.clone()),
+                )
+                .build()
+        };
+
+        let client = build_client().await.unwrap();
+        let mut subscriber = client.subscribe_to_well_known_config();
+        assert!(subscriber.get().is_none());
+
+        // The first call hits the network.
+        let config = client.well_known_config().await.unwrap();
+        assert!(config.e2ee.unwrap().secure_backup_required);
+        assert_eq!(config.jitsi.unwrap().preferred_domain, "jitsi.example.org");
+        assert!(subscriber.next().now_or_never().unwrap().is_some());
+
+        // The second call hits the in-memory cache.
+        client.well_known_config().await.unwrap();
+
+        drop(client);
+
+        // The third call hits the store.
+        let client = build_client().await.unwrap();
+        let config = client.well_known_config().await.unwrap();
+        assert_eq!(config.jitsi.unwrap().preferred_domain, "jitsi.example.org");
+
+        drop(well_known_mock);
+        server.verify().await;
+
+        // Refreshing hits the network again, and notifies subscribers of the change.
+        Mock::given(method("GET"))
+            .and(path("/.well-known/matrix/client"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(well_known("meet.example.org")))
+            .named("second well-known mock")
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let mut subscriber = client.subscribe_to_well_known_config();
+        client.refresh_well_known_config().await.unwrap();
+
+        let config = subscriber.next().now_or_never().unwrap().unwrap();
+        assert_eq!(config.jitsi.unwrap().preferred_domain, "meet.example.org");
+
+        // A stale document is fetched again, even if it's in memory.
+        let stale: CachedWellKnown = serde_json::from_value(json!({
+            "content": well_known("jitsi.example.org"),
+            "last_fetch_ts": 0.0,
+        }))
+        .unwrap();
+        *client.inner.well_known_cache.write().unwrap() = Some(stale);
+
+        let config = client.well_known_config().await.unwrap();
+        assert_eq!(config.jitsi.unwrap().preferred_domain, "meet.example.org");
+    }
+
+    #[async_test]
+    async fn test_well_known_config_homeserver_mismatch() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("GET"))
+            .and(path("/.well-known/matrix/client"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "m.homeserver": { "base_url": "https://matrix.example.org" },
+            })))
+            .mount(&server)
+            .await;
+
+        assert_matches!(
+            client.well_known_config().await,
+            Err(WellKnownError::HomeserverMismatch { .. })
+        );
+        assert!(client.subscribe_to_well_known_config().get().is_none());
+    }
+
+    #[async_test]
+    async fn test_turn_servers_caching_and_override() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/voip/turnServer"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "username": "1443779631:@user:example.com",
+                "password": "JlKfBy1QwLrO20385QyAtEyIv0=",
+                "uris": ["turn:turn.example.com:3478?transport=udp"],
+                "ttl": 86400,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // The credentials are only fetched once while they are valid.
+        let servers = client.turn_servers().await.unwrap();
+        assert_eq!(servers.username, "1443779631:@user:example.com");
+        assert_eq!(servers.uris, ["turn:turn.example.com:3478?transport=udp"]);
+        assert!(servers.expires_at.is_some());
+        assert_eq!(client.turn_servers().await.unwrap(), servers);
+
+        // The override takes precedence over the homeserver.
+        let static_servers = TurnServers::new_static(
+            "static".to_owned(),
+            "secret".to_owned(),
+            vec!["turn:turn.example.org".to_owned()],
+        );
+        client.set_turn_servers_override(Some(static_servers.clone()));
+
+        let stream = client.subscribe_to_turn_servers();
+        pin_mut!(stream);
+        assert_eq!(stream.next().await.unwrap(), static_servers);
+
+        // Removing the override goes back to the cached credentials.
+        client.set_turn_servers_override(None);
+        assert_eq!(stream.next().await.unwrap(), servers);
+    }
+
     #[async_test]
     async fn test_no_network_doesnt_cause_infinite_retries() {
         // Note: not `no_retry_test_client` or `logged_in_client` which uses the former,
@@ -2990,6 +4908,67 @@ pub(crate) mod tests {
         client.whoami().await.unwrap_err();
     }
 
+    #[async_test]
+    async fn test_minimal_sync_mode() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+        let is_minimal = |request: &wiremock::Request| {
+            request
+                .url
+                .query_pairs()
+                .any(|(key, value)| key == "filter" && value.contains("timeline"))
+        };
+
+        // A first sync in the full mode.
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .and(query_param_is_missing("since"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "next_batch": "t1" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        client.sync_once(SyncSettings::default()).await.unwrap();
+
+        // Then a sync in the minimal mode, using the minimal filter.
+        client.set_sync_mode(SyncMode::Minimal).await;
+        assert_eq!(client.sync_mode(), SyncMode::Minimal);
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .and(query_param("since", "t1"))
+            .and(is_minimal)
+            .and(query_param("set_presence", "offline"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "next_batch": "t2" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        client.sync_once(SyncSettings::default().token("t1")).await.unwrap();
+
+        // Switching back to the full mode catches up from where the minimal mode
+        // started.
+        client.set_sync_mode(SyncMode::Full).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .and(query_param("since", "t1"))
+            .and(query_param_is_missing("filter"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "next_batch": "t3" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        client.sync_once(SyncSettings::default().token("t2")).await.unwrap();
+
+        // Once caught up, the given token is used again.
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .and(query_param("since", "t3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "next_batch": "t4" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        client.sync_once(SyncSettings::default().token("t3")).await.unwrap();
+    }
+
     #[async_test]
     async fn test_await_room_remote_echo_returns_the_room_if_it_was_already_synced() {
         let (client_builder, server) = test_client_builder_with_server().await;
@@ -3152,4 +5131,90 @@ pub(crate) mod tests {
             .await;
         assert_matches!(ret, Ok(()));
     }
+
+    #[async_test]
+    async fn test_room_route_cache_invalidation() {
+        let client = logged_in_client(None).await;
+        let room_id = room_id!("!room:localhost");
+        client.base_client().get_or_create_room(room_id, RoomState::Joined);
+        let room = client.get_room(room_id).unwrap();
+
+        // The route is cached once computed.
+        assert!(room.route().await.unwrap().is_empty());
+        assert!(client.inner.room_routes.lock().unwrap().get(room_id).is_some());
+
+        let cached_route = vec![owned_server_name!("cached.localhost")];
+        let cache_route = || {
+            let mut routes = client.inner.room_routes.lock().unwrap();
+            let generation = routes.generation();
+            routes.insert(room_id, cached_route.clone(), generation);
+        };
+        let updates_with = |event| RoomUpdates {
+            join: BTreeMap::from([(
+                room_id.to_owned(),
+                JoinedRoomUpdate {
+                    timeline: Timeline {
+                        limited: false,
+                        prev_batch: None,
+                        events: vec![SyncTimelineEvent::new(event)],
+                    },
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+
+        // Other events don't invalidate the cached route.
+        cache_route();
+        client.invalidate_room_routes(&updates_with(sync_timeline_event!({
+            "content": { "body": "hello", "msgtype": "m.text" },
+            "event_id": "$message",
+            "origin_server_ts": 151800140,
+            "sender": "@alice:localhost",
+            "type": "m.room.message",
+        })));
+        assert_eq!(room.route().await.unwrap(), cached_route);
+
+        // The members, the power levels and the server ACLs do, and the route is
+        // computed again.
+        let route_changes = [
+            sync_timeline_event!({
+                "content": { "membership": "join" },
+                "event_id": "$member",
+                "origin_server_ts": 151800140,
+                "sender": "@alice:localhost",
+                "state_key": "@alice:localhost",
+                "type": "m.room.member",
+            }),
+            sync_timeline_event!({
+                "content": { "users": { "@alice:localhost": 100 } },
+                "event_id": "$power_levels",
+                "origin_server_ts": 151800140,
+                "sender": "@alice:localhost",
+                "state_key": "",
+                "type": "m.room.power_levels",
+            }),
+            sync_timeline_event!({
+                "content": { "allow": ["*"], "deny": ["evil.localhost"] },
+                "event_id": "$server_acl",
+                "origin_server_ts": 151800140,
+                "sender": "@alice:localhost",
+                "state_key": "",
+                "type": "m.room.server_acl",
+            }),
+        ];
+
+        for event in route_changes.clone() {
+            cache_route();
+            client.invalidate_room_routes(&updates_with(event));
+            assert!(room.route().await.unwrap().is_empty());
+        }
+
+        // A route computed before an invalidation isn't cached, as it might be outdated.
+        let [member_event, ..] = route_changes;
+        let generation = client.inner.room_routes.lock().unwrap().generation();
+        client.invalidate_room_routes(&updates_with(member_event));
+        client.inner.room_routes.lock().unwrap().insert(room_id, cached_route.clone(), generation);
+        assert!(client.inner.room_routes.lock().unwrap().get(room_id).is_none());
+    }
 }
@@ -0,0 +1,294 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use http::{header::AUTHORIZATION, Request, Uri};
+use tracing::warn;
+use url::Url;
+
+/// The prefix of the paths of the endpoints of the client-server API.
+const CLIENT_API_PREFIX: &str = "/_matrix/client";
+
+/// Overrides of the URLs of the requests sent to the homeserver.
+///
+/// By default, the endpoints are reached under `/_matrix/` on the homeserver
+/// URL. Deployments behind gateways that rewrite paths, or that serve some
+/// endpoints from another domain, like media from a CDN, can use this to
+/// change the URLs of the requests. The overrides are applied to every request
+/// sent by the [`Client`], and are set with
+/// [`ClientBuilder::endpoint_overrides()`].
+///
+/// The access token is only sent to the origin of the homeserver URL: when an
+/// override sends a request to another origin, its `Authorization` header is
+/// removed.
+///
+/// # Examples
+///
+/// ```
+/// use matrix_sdk::{config::EndpointOverrides, Client};
+/// use url::Url;
+///
+/// let overrides = EndpointOverrides::new()
+///     .client_api_prefix("/gateway/client")
+///     .route("/_matrix/client/v1/media", Url::parse("https://cdn.example.org/media")?);
+///
+/// let client_builder = Client::builder().endpoint_overrides(overrides);
+/// # anyhow::Ok(())
+/// ```
+///
+/// [`Client`]: crate::Client
+/// [`ClientBuilder::endpoint_overrides()`]: crate::ClientBuilder::endpoint_overrides
+#[derive(Clone, Debug, Default)]
+pub struct EndpointOverrides {
+    client_api_prefix: Option<String>,
+    routes: Vec<(String, Url)>,
+}
+
+impl EndpointOverrides {
+    /// Create an `EndpointOverrides` that doesn't change any URL.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the `/_matrix/client` prefix of the paths of the endpoints of
+    /// the client-server API with the given one.
+    pub fn client_api_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.client_api_prefix = Some(prefix.into().trim_end_matches('/').to_owned());
+        self
+    }
+
+    /// Send the requests whose path starts with `path_prefix` to `base_url`.
+    ///
+    /// The `path_prefix`, for example `/_matrix/client/v1/media`, is replaced
+    /// with the path of `base_url`, the rest of the path and the query string
+    /// are kept. If several routes match a request, the one with the longest
+    /// prefix is used. Routes take precedence over the
+    /// [client API prefix](Self::client_api_prefix).
+    ///
+    /// If `base_url` has another origin than the homeserver URL, the requests
+    /// sent to it don't include the access token.
+    pub fn route(mut self, path_prefix: impl Into<String>, base_url: Url) -> Self {
+        let path_prefix = path_prefix.into().trim_end_matches('/').to_owned();
+        self.routes.push((path_prefix, base_url));
+        self
+    }
+
+    /// Whether no URL is changed by these overrides.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.client_api_prefix.is_none() && self.routes.is_empty()
+    }
+
+    /// Apply these overrides to a request.
+    ///
+    /// If the request is redirected to another origin, its `Authorization`
+    /// header is removed so the access token isn't leaked.
+    pub(crate) fn apply_to_request<T>(&self, request: &mut Request<T>) {
+        if self.is_empty() {
+            return;
+        }
+
+        let uri = self.apply(request.uri());
+
+        if origin(&uri) != origin(request.uri()) {
+            request.headers_mut().remove(AUTHORIZATION);
+        }
+
+        *request.uri_mut() = uri;
+    }
+
+    /// Apply these overrides to the URI of a request.
+    ///
+    /// The path of the homeserver URL, if any, is kept unless a route matches.
+    fn apply(&self, uri: &Uri) -> Uri {
+        let path = uri.path();
+        let query = uri.query();
+
+        // Skip the path of the homeserver URL to match the paths of the endpoints.
+        let (base_path, endpoint_path) = path.split_at(path.find("/_matrix/").unwrap_or(0));
+
+        let route = self
+            .routes
+            .iter()
+            .filter_map(|(prefix, base_url)| {
+                Some((prefix.len(), base_url, strip_path_prefix(endpoint_path, prefix)?))
+            })
+            .max_by_key(|(len, ..)| *len);
+
+        let new_uri = if let Some((_, base_url, rest)) = route {
+            let mut url = base_url.clone();
+            let path = format!("{}{rest}", url.path().trim_end_matches('/'));
+            url.set_path(&path);
+            url.set_query(query);
+            url.to_string().parse()
+        } else if let Some((prefix, rest)) = self
+            .client_api_prefix
+            .as_deref()
+            .zip(strip_path_prefix(endpoint_path, CLIENT_API_PREFIX))
+        {
+            let mut path_and_query = format!("{base_path}{prefix}{rest}");
+            if let Some(query) = query {
+                path_and_query.push('?');
+                path_and_query.push_str(query);
+            }
+
+            let mut parts = uri.clone().into_parts();
+            path_and_query.parse().map(|path_and_query| {
+                parts.path_and_query = Some(path_and_query);
+                Uri::from_parts(parts).expect("the URI is still valid with a new path")
+            })
+        } else {
+            return uri.clone();
+        };
+
+        new_uri.unwrap_or_else(|error| {
+            warn!("Couldn't apply the endpoint overrides to {uri}: {error}");
+            uri.clone()
+        })
+    }
+}
+
+/// Get the scheme, host and port of the given URI.
+fn origin(uri: &Uri) -> (Option<&str>, Option<&str>, Option<u16>) {
+    let port = uri.port_u16().or_else(|| match uri.scheme_str() {
+        Some("http") => Some(80),
+        Some("https") => Some(443),
+        _ => None,
+    });
+
+    (uri.scheme_str(), uri.host(), port)
+}
+
+/// Strip the given prefix from the path, only if it ends at a segment
+/// boundary.
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{header::AUTHORIZATION, Request, Uri};
+    use url::Url;
+
+    use super::EndpointOverrides;
+
+    fn apply(overrides: &EndpointOverrides, uri: &str) -> String {
+        overrides.apply(&uri.parse::<Uri>().unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_no_overrides() {
+        let overrides = EndpointOverrides::new();
+        assert!(overrides.is_empty());
+
+        let uri = "https://example.org/_matrix/client/v3/sync?since=s1";
+        assert_eq!(apply(&overrides, uri), uri);
+    }
+
+    #[test]
+    fn test_client_api_prefix() {
+        let overrides = EndpointOverrides::new().client_api_prefix("/gateway/client/");
+
+        assert_eq!(
+            apply(&overrides, "https://example.org/_matrix/client/v3/sync?since=s1"),
+            "https://example.org/gateway/client/v3/sync?since=s1"
+        );
+
+        // The path of the homeserver URL is kept.
+        assert_eq!(
+            apply(&overrides, "https://example.org/base/_matrix/client/versions"),
+            "https://example.org/base/gateway/client/versions"
+        );
+
+        // Other APIs are left untouched.
+        assert_eq!(
+            apply(&overrides, "https://example.org/_matrix/media/v3/config"),
+            "https://example.org/_matrix/media/v3/config"
+        );
+        assert_eq!(
+            apply(&overrides, "https://example.org/_matrix/clients/foo"),
+            "https://example.org/_matrix/clients/foo"
+        );
+    }
+
+    #[test]
+    fn test_routes() {
+        let overrides = EndpointOverrides::new()
+            .client_api_prefix("/gateway/client")
+            .route("/_matrix/client/v1/media", Url::parse("https://cdn.example.org/media").unwrap())
+            .route(
+                "/_matrix/client/v1/media/thumbnail",
+                Url::parse("https://thumbnails.example.org/").unwrap(),
+            );
+
+        assert_eq!(
+            apply(
+                &overrides,
+                "https://example.org/_matrix/client/v1/media/download/example.org/abc?timeout_ms=20000"
+            ),
+            "https://cdn.example.org/media/download/example.org/abc?timeout_ms=20000"
+        );
+
+        // The route with the longest prefix wins.
+        assert_eq!(
+            apply(
+                &overrides,
+                "https://example.org/_matrix/client/v1/media/thumbnail/example.org/abc"
+            ),
+            "https://thumbnails.example.org/example.org/abc"
+        );
+
+        // Other requests use the client API prefix.
+        assert_eq!(
+            apply(&overrides, "https://example.org/_matrix/client/v3/profile/@alice:example.org"),
+            "https://example.org/gateway/client/v3/profile/@alice:example.org"
+        );
+    }
+
+    #[test]
+    fn test_authorization_only_sent_to_same_origin() {
+        let overrides = EndpointOverrides::new()
+            .client_api_prefix("/gateway/client")
+            .route("/_matrix/client/v1/media", Url::parse("https://cdn.example.org/media").unwrap())
+            .route("/_matrix/client/v3/sync", Url::parse("https://example.org:443/sync").unwrap());
+
+        let request = |uri: &str| {
+            let mut request =
+                Request::get(uri).header(AUTHORIZATION, "Bearer secret").body(()).unwrap();
+            overrides.apply_to_request(&mut request);
+            request
+        };
+
+        // The prefix keeps the origin of the homeserver.
+        let request_with_prefix = request("https://example.org/_matrix/client/v3/profile/@a:b.c");
+        assert_eq!(
+            request_with_prefix.uri().to_string(),
+            "https://example.org/gateway/client/v3/profile/@a:b.c"
+        );
+        assert!(request_with_prefix.headers().contains_key(AUTHORIZATION));
+
+        // A route with the same origin keeps the access token.
+        let same_origin_request = request("https://example.org/_matrix/client/v3/sync");
+        assert_eq!(same_origin_request.uri().to_string(), "https://example.org:443/sync");
+        assert!(same_origin_request.headers().contains_key(AUTHORIZATION));
+
+        // A route to another origin doesn't get the access token.
+        let cdn_request =
+            request("https://example.org/_matrix/client/v1/media/download/example.org/abc");
+        assert_eq!(
+            cdn_request.uri().to_string(),
+            "https://cdn.example.org/media/download/example.org/abc"
+        );
+        assert!(!cdn_request.headers().contains_key(AUTHORIZATION));
+    }
+}
@@ -14,9 +14,13 @@
 
 //! Configuration to change the behaviour of the [`Client`][crate::Client].
 
+mod endpoints;
 mod request;
 mod sync;
 
-pub use matrix_sdk_base::store::StoreConfig;
+pub use endpoints::EndpointOverrides;
+pub use matrix_sdk_base::store::{MemberStoragePolicy, MemberStorageStrategy, StoreConfig};
+#[cfg(feature = "compression")]
+pub use request::RequestCompression;
 pub use request::RequestConfig;
-pub use sync::SyncSettings;
+pub use sync::{SyncMode, SyncSettings};
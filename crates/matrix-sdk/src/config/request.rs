@@ -49,6 +49,26 @@ pub struct RequestConfig {
     pub(crate) max_concurrent_requests: Option<NonZeroUsize>,
     pub(crate) force_auth: bool,
     pub(crate) force_matrix_version: Option<MatrixVersion>,
+    #[cfg(feature = "compression")]
+    pub(crate) request_compression: Option<RequestCompression>,
+}
+
+/// The compression of the bodies of requests, see
+/// [`RequestConfig::request_compression()`].
+#[cfg(feature = "compression")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RequestCompression {
+    /// Compress the bodies with gzip, and send them with a `Content-Encoding:
+    /// gzip` header.
+    Gzip,
+
+    /// Compress the bodies with zstd, and send them with a `Content-Encoding:
+    /// zstd` header.
+    ///
+    /// It compresses better and faster than gzip, but fewer servers support
+    /// it.
+    Zstd,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -61,6 +81,8 @@ impl Debug for RequestConfig {
             force_auth,
             max_concurrent_requests,
             force_matrix_version,
+            #[cfg(feature = "compression")]
+            request_compression,
         } = self;
 
         let mut res = fmt.debug_struct("RequestConfig");
@@ -70,6 +92,9 @@ impl Debug for RequestConfig {
             .maybe_field("max_concurrent_requests", max_concurrent_requests)
             .maybe_field("force_matrix_version", force_matrix_version);
 
+        #[cfg(feature = "compression")]
+        res.maybe_field("request_compression", request_compression);
+
         if *force_auth {
             res.field("force_auth", &true);
         }
@@ -87,6 +112,8 @@ impl Default for RequestConfig {
             max_concurrent_requests: Default::default(),
             force_auth: false,
             force_matrix_version: Default::default(),
+            #[cfg(feature = "compression")]
+            request_compression: Default::default(),
         }
     }
 }
@@ -153,6 +180,28 @@ impl RequestConfig {
         self
     }
 
+    /// Compress the JSON bodies of requests with the given algorithm. The
+    /// default is to not compress them.
+    ///
+    /// Only use it if the homeserver, or a proxy in front of it, accepts
+    /// compressed request bodies. Small bodies are always sent uncompressed.
+    /// If the homeserver rejects a compressed body with a `400 Bad Request` or
+    /// `415 Unsupported Media Type` error, the request is sent again
+    /// uncompressed, and if that one is accepted, the client stops compressing
+    /// request bodies.
+    ///
+    /// This has no effect on Wasm. Note that the responses are decompressed
+    /// if the homeserver compressed them with gzip or zstd, whatever this
+    /// setting is, see [`ClientBuilder::disable_response_decompression()`].
+    ///
+    /// [`ClientBuilder::disable_response_decompression()`]: crate::ClientBuilder::disable_response_decompression
+    #[cfg(feature = "compression")]
+    #[must_use]
+    pub fn request_compression(mut self, compression: RequestCompression) -> Self {
+        self.request_compression = Some(compression);
+        self
+    }
+
     /// Force the Matrix version used to select which version of the endpoint to
     /// use.
     ///
@@ -169,6 +218,8 @@ impl RequestConfig {
 mod tests {
     use std::time::Duration;
 
+    #[cfg(feature = "compression")]
+    use super::RequestCompression;
     use super::RequestConfig;
 
     #[test]
@@ -197,4 +248,17 @@ mod tests {
         let cfg = RequestConfig::short_retry();
         assert_eq!(cfg.retry_limit, Some(3));
     }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn testing_request_compression() {
+        let cfg = RequestConfig::new();
+        assert_eq!(cfg.request_compression, None);
+
+        let cfg = cfg.request_compression(RequestCompression::Gzip);
+        assert_eq!(cfg.request_compression, Some(RequestCompression::Gzip));
+
+        let cfg = cfg.request_compression(RequestCompression::Zstd);
+        assert_eq!(cfg.request_compression, Some(RequestCompression::Zstd));
+    }
 }
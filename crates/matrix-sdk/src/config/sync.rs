@@ -15,15 +15,63 @@
 use std::{fmt, time::Duration};
 
 use matrix_sdk_common::debug::DebugStructExt;
-use ruma::{api::client::sync::sync_events, presence::PresenceState};
+use ruma::{
+    api::client::{
+        filter::{Filter, FilterDefinition, RoomEventFilter},
+        sync::sync_events,
+    },
+    presence::PresenceState,
+};
 
 const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// The amount of data requested by the sync loop of the [`Client`].
+///
+/// See [`Client::set_sync_mode()`].
+///
+/// [`Client`]: crate::Client
+/// [`Client::set_sync_mode()`]: crate::Client::set_sync_mode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Sync everything allowed by the filter of the [`SyncSettings`].
+    #[default]
+    Full,
+
+    /// Only sync the to-device events, the global account data and the
+    /// invites, and don't mark the user as online.
+    ///
+    /// This is intended for the short background execution windows of mobile
+    /// platforms, to keep the end-to-end encryption state and the invites up
+    /// to date while using as little battery and bandwidth as possible. The
+    /// events of the rooms the user is in are fetched again once the client
+    /// switches back to [`SyncMode::Full`].
+    Minimal,
+}
+
+impl SyncMode {
+    /// The filter used by the [`SyncMode::Minimal`] mode.
+    ///
+    /// The rooms the user is in stay part of the response, since invites
+    /// can't be filtered separately, but all their events are filtered out.
+    pub(crate) fn minimal_filter() -> FilterDefinition {
+        let mut filter = FilterDefinition::default();
+        filter.presence = Filter::ignore_all();
+        filter.room.include_leave = false;
+        filter.room.timeline = RoomEventFilter::ignore_all();
+        filter.room.state = RoomEventFilter::ignore_all();
+        filter.room.ephemeral = RoomEventFilter::ignore_all();
+        filter.room.account_data = RoomEventFilter::ignore_all();
+        filter
+    }
+}
+
 /// Settings for a sync call.
 #[derive(Clone)]
 pub struct SyncSettings {
     // Filter is pretty big at 1000 bytes, box it to reduce stack size
     pub(crate) filter: Option<Box<sync_events::v3::Filter>>,
+    // A filter to upload, or to get the ID of from the store, before syncing.
+    pub(crate) named_filter: Option<Box<(String, FilterDefinition)>>,
     pub(crate) timeout: Option<Duration>,
     pub(crate) token: Option<String>,
     pub(crate) full_state: bool,
@@ -39,9 +87,10 @@ impl Default for SyncSettings {
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for SyncSettings {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { filter, timeout, token: _, full_state, set_presence } = self;
+        let Self { filter, named_filter, timeout, token: _, full_state, set_presence } = self;
         f.debug_struct("SyncSettings")
             .maybe_field("filter", filter)
+            .maybe_field("named_filter", &named_filter.as_ref().map(|named_filter| &named_filter.0))
             .maybe_field("timeout", timeout)
             .field("full_state", full_state)
             .field("set_presence", set_presence)
@@ -55,6 +104,7 @@ impl SyncSettings {
     pub fn new() -> Self {
         Self {
             filter: None,
+            named_filter: None,
             timeout: Some(DEFAULT_SYNC_TIMEOUT),
             token: None,
             full_state: false,
@@ -95,6 +145,30 @@ impl SyncSettings {
     #[must_use]
     pub fn filter(mut self, filter: sync_events::v3::Filter) -> Self {
         self.filter = Some(Box::new(filter));
+        self.named_filter = None;
+        self
+    }
+
+    /// Set the sync filter by name.
+    ///
+    /// Before syncing, the ID of the filter is looked up in the store, or the
+    /// filter is uploaded to the homeserver, with
+    /// [`Client::get_or_upload_filter()`]. This way, the filter is only
+    /// uploaded once, and not on every start of the application.
+    ///
+    /// This replaces the filter set with [`SyncSettings::filter()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The unique name of the filter.
+    ///
+    /// * `definition` - The definition of the filter.
+    ///
+    /// [`Client::get_or_upload_filter()`]: crate::Client::get_or_upload_filter
+    #[must_use]
+    pub fn named_filter(mut self, name: impl Into<String>, definition: FilterDefinition) -> Self {
+        self.named_filter = Some(Box::new((name.into(), definition)));
+        self.filter = None;
         self
     }
 
@@ -0,0 +1,255 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The activity of the users the client receives presence for, aggregated
+//! from their presence events and kept in the state store.
+
+use std::{cmp::Reverse, collections::BTreeMap, time::Duration};
+
+use matrix_sdk_base::{ContactActivity, StateStoreDataKey, StateStoreDataValue};
+use ruma::{
+    events::presence::PresenceEvent, serde::Raw, MilliSecondsSinceUnixEpoch, OwnedUserId, UserId,
+};
+use tokio::sync::{MappedMutexGuard, MutexGuard};
+use tracing::warn;
+
+use crate::{Client, Result};
+
+/// Update the activity of a user with one of their presence events, received
+/// at `now`.
+fn apply_presence_event(
+    previous: Option<ContactActivity>,
+    event: PresenceEvent,
+    now: MilliSecondsSinceUnixEpoch,
+) -> ContactActivity {
+    let content = event.content;
+    let currently_active = content.currently_active.unwrap_or(false);
+
+    let last_active_at = if currently_active {
+        Some(now)
+    } else {
+        content.last_active_ago.map(|ago| {
+            let ago = Duration::from_millis(ago.into());
+            now.to_system_time()
+                .and_then(|now| now.checked_sub(ago))
+                .and_then(MilliSecondsSinceUnixEpoch::from_system_time)
+                .unwrap_or(now)
+        })
+    };
+
+    // The time computed from a relative duration is approximate, never go back
+    // in time because of it.
+    let previous_last_active_at = previous.and_then(|previous| previous.last_active_at);
+    let last_active_at = last_active_at.max(previous_last_active_at);
+
+    ContactActivity {
+        user_id: event.sender,
+        presence: content.presence,
+        currently_active,
+        last_active_at,
+        updated_at: now,
+    }
+}
+
+impl Client {
+    /// Get the activity of all the users the client received presence for.
+    ///
+    /// It is loaded from the store the first time, and kept in memory
+    /// afterwards.
+    async fn contact_activities(
+        &self,
+    ) -> Result<MappedMutexGuard<'_, BTreeMap<OwnedUserId, ContactActivity>>> {
+        let mut activities = self.inner.contact_activities.lock().await;
+
+        if activities.is_none() {
+            let loaded = self
+                .store()
+                .get_kv_data(StateStoreDataKey::ContactActivity)
+                .await?
+                .and_then(|value| value.into_contact_activity())
+                .unwrap_or_default();
+
+            *activities = Some(loaded);
+        }
+
+        Ok(MutexGuard::map(activities, |activities| activities.get_or_insert_with(BTreeMap::new)))
+    }
+
+    /// Get the activity of a user, aggregated from the presence events
+    /// received in sync responses.
+    ///
+    /// Returns `None` if no presence event was received for this user.
+    pub async fn contact_activity(&self, user_id: &UserId) -> Result<Option<ContactActivity>> {
+        Ok(self.contact_activities().await?.get(user_id).cloned())
+    }
+
+    /// Get the users that were active the most recently, aggregated from the
+    /// presence events received in sync responses.
+    ///
+    /// The users that are currently active come first, followed by the other
+    /// ones from the most to the least recently active. Users whose last
+    /// activity is unknown are left out.
+    ///
+    /// This is meant to sort lists of contacts, like in a picker to start a
+    /// conversation, without maintaining a separate presence cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of users to return.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// for activity in client.recently_active_contacts(10).await? {
+    ///     println!("{} was last active at {:?}", activity.user_id, activity.last_active_at);
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn recently_active_contacts(&self, limit: usize) -> Result<Vec<ContactActivity>> {
+        let mut activities: Vec<_> = self
+            .contact_activities()
+            .await?
+            .values()
+            .filter(|activity| activity.currently_active || activity.last_active_at.is_some())
+            .cloned()
+            .collect();
+
+        activities
+            .sort_by_key(|activity| Reverse((activity.currently_active, activity.last_active_at)));
+        activities.truncate(limit);
+
+        Ok(activities)
+    }
+
+    /// Aggregate the presence events of a sync response into the activity of
+    /// the users.
+    ///
+    /// The presence of the current user is ignored. The activities are only
+    /// persisted if the presence events changed them, not if they only
+    /// refreshed when they were received.
+    pub(crate) async fn update_contact_activity(&self, presence: &[Raw<PresenceEvent>]) {
+        let own_user_id = self.user_id();
+        let events: Vec<_> = presence
+            .iter()
+            .filter_map(|raw| raw.deserialize().ok())
+            .filter(|event: &PresenceEvent| Some(&*event.sender) != own_user_id)
+            .collect();
+
+        if events.is_empty() {
+            return;
+        }
+
+        let mut activities = match self.contact_activities().await {
+            Ok(activities) => activities,
+            Err(err) => {
+                warn!("Couldn't load the activity of the contacts: {err}");
+                return;
+            }
+        };
+
+        let now = MilliSecondsSinceUnixEpoch::now();
+        let mut has_changed = false;
+
+        for event in events {
+            let previous = activities.remove(&event.sender);
+            let activity = apply_presence_event(previous.clone(), event, now);
+
+            has_changed |= !previous.is_some_and(|previous| is_same_activity(&previous, &activity));
+            activities.insert(activity.user_id.clone(), activity);
+        }
+
+        if !has_changed {
+            return;
+        }
+
+        if let Err(err) = self
+            .store()
+            .set_kv_data(
+                StateStoreDataKey::ContactActivity,
+                StateStoreDataValue::ContactActivity(activities.clone()),
+            )
+            .await
+        {
+            warn!("Couldn't save the activity of the contacts: {err}");
+        }
+    }
+}
+
+/// Whether two activities of a user are the same, regardless of when they
+/// were received.
+fn is_same_activity(a: &ContactActivity, b: &ContactActivity) -> bool {
+    a.presence == b.presence
+        && a.currently_active == b.currently_active
+        && a.last_active_at == b.last_active_at
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{
+        events::presence::PresenceEvent, owned_user_id, presence::PresenceState, uint,
+        MilliSecondsSinceUnixEpoch,
+    };
+    use serde_json::json;
+
+    use super::apply_presence_event;
+
+    fn presence_event(content: serde_json::Value) -> PresenceEvent {
+        serde_json::from_value(json!({
+            "type": "m.presence",
+            "sender": "@alice:localhost",
+            "content": content,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_presence_event() {
+        let now = MilliSecondsSinceUnixEpoch(uint!(100_000));
+
+        let activity = apply_presence_event(
+            None,
+            presence_event(json!({ "presence": "online", "last_active_ago": 60_000 })),
+            now,
+        );
+        assert_eq!(activity.user_id, owned_user_id!("@alice:localhost"));
+        assert_eq!(activity.presence, PresenceState::Online);
+        assert!(!activity.currently_active);
+        assert_eq!(activity.last_active_at, Some(MilliSecondsSinceUnixEpoch(uint!(40_000))));
+
+        // An event without `last_active_ago` keeps the last known activity.
+        let later = MilliSecondsSinceUnixEpoch(uint!(200_000));
+        let activity = apply_presence_event(
+            Some(activity),
+            presence_event(json!({ "presence": "offline" })),
+            later,
+        );
+        assert_eq!(activity.presence, PresenceState::Offline);
+        assert_eq!(activity.last_active_at, Some(MilliSecondsSinceUnixEpoch(uint!(40_000))));
+        assert_eq!(activity.updated_at, later);
+
+        // A currently active user is active now.
+        let activity = apply_presence_event(
+            Some(activity),
+            presence_event(json!({ "presence": "online", "currently_active": true })),
+            later,
+        );
+        assert!(activity.currently_active);
+        assert_eq!(activity.last_active_at, Some(later));
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Custom `msgtype`s for `m.room.message` events.
+//!
+//! The specification allows `m.room.message` events to have a `msgtype` that
+//! it doesn't define, like `nic.custom.confetti`, as long as they have a
+//! `body` that other clients can display instead. Such messages are
+//! represented by [`MessageType`] as an opaque JSON object.
+//!
+//! A type implementing [`CustomMsgType`] can be converted to and from a
+//! [`MessageType`]. Registering it with [`Client::register_custom_msgtype`]
+//! also makes the messages of that type available to the event handlers,
+//! already deserialized, by adding an `Option<CustomMessage>` argument.
+
+use std::{any::Any, borrow::Cow, fmt, sync::Arc};
+
+use ruma::events::room::message::MessageType;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{value::RawValue as RawJsonValue, Value as JsonValue};
+use tracing::warn;
+
+use crate::Client;
+
+/// A custom `msgtype` for `m.room.message` events.
+///
+/// The fields of the type are the fields of the message content, apart from
+/// `msgtype` and `body`.
+///
+/// # Examples
+///
+/// ```
+/// use matrix_sdk::{
+///     custom_msgtypes::CustomMsgType,
+///     ruma::events::room::message::{MessageType, RoomMessageEventContent},
+/// };
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct Confetti {
+///     color: String,
+/// }
+///
+/// impl CustomMsgType for Confetti {
+///     const MSGTYPE: &'static str = "nic.custom.confetti";
+///
+///     fn body(&self) -> String {
+///         format!("🎉 {} confetti", self.color)
+///     }
+/// }
+///
+/// let msgtype = Confetti { color: "blue".to_owned() }.to_msgtype()?;
+/// assert_eq!(msgtype.body(), "🎉 blue confetti");
+///
+/// let content = RoomMessageEventContent::new(msgtype);
+/// let confetti = Confetti::from_msgtype(&content.msgtype).unwrap()?;
+/// assert_eq!(confetti.color, "blue");
+/// # anyhow::Ok(())
+/// ```
+pub trait CustomMsgType: Serialize + DeserializeOwned + fmt::Debug + Send + Sync + 'static {
+    /// The `msgtype` of the messages, e.g. `nic.custom.confetti`.
+    const MSGTYPE: &'static str;
+
+    /// The plain text fallback of the message, for the clients that don't
+    /// support this `msgtype`.
+    fn body(&self) -> String;
+
+    /// Convert this value to a [`MessageType`], to send it in an
+    /// `m.room.message` event.
+    fn to_msgtype(&self) -> serde_json::Result<MessageType> {
+        let JsonValue::Object(data) = serde_json::to_value(self)? else {
+            return Err(serde::ser::Error::custom("a custom msgtype must serialize to a map"));
+        };
+
+        MessageType::new(Self::MSGTYPE, self.body(), data)
+    }
+
+    /// Parse a [`MessageType`] into this type.
+    ///
+    /// Returns `None` if the `msgtype` is not [`Self::MSGTYPE`].
+    fn from_msgtype(msgtype: &MessageType) -> Option<serde_json::Result<Self>> {
+        (msgtype.msgtype() == Self::MSGTYPE)
+            .then(|| serde_json::from_value(JsonValue::Object(msgtype.data().into_owned())))
+    }
+}
+
+/// The function parsing the messages of a registered custom `msgtype`.
+pub(crate) type ParseCustomMsgTypeFn =
+    Arc<dyn Fn(&MessageType) -> Option<serde_json::Result<CustomMessage>> + Send + Sync>;
+
+/// A message of a custom `msgtype` registered with
+/// [`Client::register_custom_msgtype`].
+#[derive(Clone)]
+pub struct CustomMessage {
+    msgtype: &'static str,
+    value: Arc<dyn Any + Send + Sync>,
+    debug: fn(&(dyn Any + Send + Sync), &mut fmt::Formatter<'_>) -> fmt::Result,
+}
+
+impl CustomMessage {
+    fn new<T: CustomMsgType>(value: T) -> Self {
+        Self {
+            msgtype: T::MSGTYPE,
+            value: Arc::new(value),
+            debug: |value, f| {
+                let value = value.downcast_ref::<T>().expect("the type should not change");
+                fmt::Debug::fmt(value, f)
+            },
+        }
+    }
+
+    /// The `msgtype` of the message.
+    pub fn msgtype(&self) -> &str {
+        self.msgtype
+    }
+
+    /// Get the message as the given type, if it is of this type.
+    pub fn downcast_ref<T: CustomMsgType>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for CustomMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.debug)(&*self.value, f)
+    }
+}
+
+impl Client {
+    /// Register a custom `msgtype`.
+    ///
+    /// The `m.room.message` events with this `msgtype` are then deserialized
+    /// into `T` for the event handlers that take an `Option<CustomMessage>`
+    /// argument. Registering the same `msgtype` again replaces the previous
+    /// type.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{
+    /// #     custom_msgtypes::{CustomMessage, CustomMsgType},
+    /// #     ruma::events::room::message::OriginalSyncRoomMessageEvent,
+    /// #     Client,
+    /// # };
+    /// # use serde::{Deserialize, Serialize};
+    /// # use url::Url;
+    /// # #[derive(Debug, Deserialize, Serialize)]
+    /// # struct Confetti { color: String }
+    /// # impl CustomMsgType for Confetti {
+    /// #     const MSGTYPE: &'static str = "nic.custom.confetti";
+    /// #     fn body(&self) -> String { "🎉".to_owned() }
+    /// # }
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// client.register_custom_msgtype::<Confetti>();
+    ///
+    /// client.add_event_handler(
+    ///     |_ev: OriginalSyncRoomMessageEvent, custom: Option<CustomMessage>| async move {
+    ///         if let Some(confetti) = custom.as_ref().and_then(|c| c.downcast_ref::<Confetti>()) {
+    ///             println!("Throwing {} confetti", confetti.color);
+    ///         }
+    ///     },
+    /// );
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn register_custom_msgtype<T: CustomMsgType>(&self) {
+        let parse: ParseCustomMsgTypeFn = Arc::new(|msgtype| {
+            T::from_msgtype(msgtype).map(|result| result.map(CustomMessage::new))
+        });
+
+        self.inner.custom_msgtypes.write().unwrap().insert(T::MSGTYPE.to_owned(), parse);
+    }
+
+    /// Whether the given `msgtype` was registered with
+    /// [`Client::register_custom_msgtype`].
+    pub fn is_custom_msgtype_registered(&self, msgtype: &str) -> bool {
+        self.inner.custom_msgtypes.read().unwrap().contains_key(msgtype)
+    }
+
+    /// Parse a [`MessageType`] with the custom `msgtype` registered for it, if
+    /// any.
+    pub fn parse_custom_msgtype(&self, msgtype: &MessageType) -> Option<CustomMessage> {
+        let parse = self.inner.custom_msgtypes.read().unwrap().get(msgtype.msgtype()).cloned()?;
+
+        match parse(msgtype)? {
+            Ok(message) => Some(message),
+            Err(error) => {
+                warn!(msgtype = msgtype.msgtype(), "Couldn't parse a custom msgtype: {error}");
+                None
+            }
+        }
+    }
+
+    /// Parse a raw `m.room.message` event with the custom `msgtype`
+    /// registered for it, if any.
+    pub(crate) fn parse_custom_message(&self, raw: &RawJsonValue) -> Option<CustomMessage> {
+        #[derive(Deserialize)]
+        struct EventFields<'a> {
+            #[serde(rename = "type")]
+            event_type: &'a str,
+            #[serde(borrow)]
+            content: &'a RawJsonValue,
+        }
+
+        #[derive(Deserialize)]
+        struct ContentFields<'a> {
+            msgtype: Cow<'a, str>,
+        }
+
+        let event = serde_json::from_str::<EventFields<'_>>(raw.get()).ok()?;
+        if event.event_type != "m.room.message" {
+            return None;
+        }
+
+        let content = serde_json::from_str::<ContentFields<'_>>(event.content.get()).ok()?;
+        if !self.is_custom_msgtype_registered(&content.msgtype) {
+            return None;
+        }
+
+        let msgtype = serde_json::from_str::<MessageType>(event.content.get()).ok()?;
+        self.parse_custom_msgtype(&msgtype)
+    }
+}
@@ -34,7 +34,8 @@ use ruma::{
     api::client::{
         backup::{
             add_backup_keys, create_backup_version, get_backup_keys, get_backup_keys_for_room,
-            get_backup_keys_for_session, get_latest_backup_info, RoomKeyBackup,
+            get_backup_keys_for_session, get_latest_backup_info, update_backup_version,
+            RoomKeyBackup,
         },
         error::ErrorKind,
     },
@@ -46,7 +47,7 @@ use ruma::{
     OwnedRoomId, RoomId, TransactionId,
 };
 use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
-use tracing::{error, info, instrument, trace, warn, Span};
+use tracing::{debug, error, info, instrument, trace, warn, Span};
 
 pub mod futures;
 pub(crate) mod types;
@@ -55,9 +56,18 @@ pub use types::{BackupState, UploadState};
 
 use self::futures::WaitForSteadyState;
 use crate::{
-    crypto::olm::ExportedRoomKey, encryption::BackupDownloadStrategy, Client, Error, Room,
+    crypto::olm::ExportedRoomKey, encryption::BackupDownloadStrategy, executor::spawn, Client,
+    Error, Room,
 };
 
+/// The key of the crypto store custom value holding the room keys of UTD
+/// events which couldn't be downloaded because backups weren't enabled.
+const PENDING_UTD_SESSIONS_KEY: &str = "backups.pending_utd_sessions";
+
+/// The maximum number of room keys remembered in the
+/// [`PENDING_UTD_SESSIONS_KEY`] custom value.
+const MAX_PENDING_UTD_SESSIONS: usize = 1000;
+
 /// The backups manager for the [`Client`].
 #[derive(Debug, Clone)]
 pub struct Backups {
@@ -516,6 +526,125 @@ impl Backups {
         }
     }
 
+    /// Remember that we couldn't download the given room key because backups
+    /// weren't enabled, so that it can be downloaded once they are, see
+    /// [`Self::download_pending_utd_room_keys()`].
+    pub(crate) async fn add_pending_utd_session(
+        &self,
+        olm_machine: &OlmMachine,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<(), Error> {
+        let _guard = self.client.locks().pending_utd_sessions_lock.lock().await;
+
+        let mut sessions = Self::load_pending_utd_sessions(olm_machine).await?;
+
+        if sessions.len() >= MAX_PENDING_UTD_SESSIONS {
+            debug!("Too many room keys are waiting for backups, not remembering this one");
+            return Ok(());
+        }
+
+        if sessions.insert((room_id.to_owned(), session_id.to_owned())) {
+            Self::save_pending_utd_sessions(olm_machine, &sessions).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Download, from the server-side key backup, the room keys of the
+    /// events which failed to decrypt while backups weren't enabled.
+    ///
+    /// This is done automatically once backups get enabled, if the
+    /// [`BackupDownloadStrategy::AfterDecryptionFailure`] strategy is used.
+    /// Timelines are notified of the downloaded room keys, and retry to
+    /// decrypt the events encrypted with them.
+    ///
+    /// Returns the number of room keys that were downloaded.
+    pub async fn download_pending_utd_room_keys(&self) -> Result<usize, Error> {
+        let _guard = self.client.locks().pending_utd_sessions_lock.lock().await;
+
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let sessions = Self::load_pending_utd_sessions(olm_machine).await?;
+
+        if sessions.is_empty() {
+            return Ok(0);
+        }
+
+        debug!(count = sessions.len(), "Downloading the room keys of UTD events from the backup");
+
+        let mut remaining = BTreeSet::new();
+        let mut downloaded = 0;
+        let mut sessions = sessions.into_iter();
+
+        while let Some((room_id, session_id)) = sessions.next() {
+            match self.download_room_key(&room_id, &session_id).await {
+                Ok(true) => downloaded += 1,
+                Ok(false) => {
+                    // Backups got disabled in the meantime, keep the remaining room keys for
+                    // the next time they get enabled.
+                    remaining.insert((room_id, session_id));
+                    remaining.extend(sessions);
+                    break;
+                }
+                Err(e) if e.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                    debug!(%room_id, session_id, "The room key isn't in the backup");
+                }
+                Err(e) => {
+                    warn!(%room_id, session_id, "Couldn't download the room key: {e:?}");
+                    remaining.insert((room_id, session_id));
+                }
+            }
+        }
+
+        Self::save_pending_utd_sessions(olm_machine, &remaining).await?;
+
+        Ok(downloaded)
+    }
+
+    /// Spawn a task downloading the room keys of UTD events from the backup,
+    /// if the client is set up to download room keys after decryption
+    /// failures.
+    fn maybe_download_pending_utd_room_keys(&self) {
+        if self.client.inner.e2ee.encryption_settings.backup_download_strategy
+            != BackupDownloadStrategy::AfterDecryptionFailure
+        {
+            return;
+        }
+
+        let backups = self.clone();
+
+        spawn(async move {
+            if let Err(e) = backups.download_pending_utd_room_keys().await {
+                warn!("Couldn't download the room keys of UTD events from the backup: {e:?}");
+            }
+        });
+    }
+
+    async fn load_pending_utd_sessions(
+        olm_machine: &OlmMachine,
+    ) -> Result<BTreeSet<(OwnedRoomId, String)>, Error> {
+        match olm_machine.store().get_custom_value(PENDING_UTD_SESSIONS_KEY).await? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Ok(BTreeSet::new()),
+        }
+    }
+
+    async fn save_pending_utd_sessions(
+        olm_machine: &OlmMachine,
+        sessions: &BTreeSet<(OwnedRoomId, String)>,
+    ) -> Result<(), Error> {
+        if sessions.is_empty() {
+            olm_machine.store().remove_custom_value(PENDING_UTD_SESSIONS_KEY).await?;
+        } else {
+            let value = serde_json::to_vec(sessions)?;
+            olm_machine.store().set_custom_value(PENDING_UTD_SESSIONS_KEY, value).await?;
+        }
+
+        Ok(())
+    }
+
     /// Set the state of the backup.
     fn set_state(&self, new_state: BackupState) {
         let old_state = self.client.inner.e2ee.backup_state.global_state.set(new_state);
@@ -642,6 +771,38 @@ impl Backups {
         }
     }
 
+    /// Sign the active backup version again and upload the new signatures.
+    ///
+    /// This is needed after the cross-signing keys have been reset, otherwise
+    /// the other devices won't trust the backup anymore. This is a no-op if
+    /// no backup is active.
+    pub(crate) async fn sign_active_backup(&self) -> Result<(), Error> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let backup_keys = olm_machine.backup_machine().get_backup_keys().await?;
+
+        let (Some(decryption_key), Some(version)) =
+            (backup_keys.decryption_key, backup_keys.backup_version)
+        else {
+            trace!("No active backup, not signing it");
+            return Ok(());
+        };
+
+        let mut backup_info = decryption_key.to_backup_info();
+
+        if let Err(e) = olm_machine.backup_machine().sign_backup(&mut backup_info).await {
+            warn!("Unable to sign the active backup version: {e:?}");
+            return Ok(());
+        }
+
+        let algorithm = Raw::new(&backup_info)?.cast();
+        let request = update_backup_version::v3::Request::new(version, algorithm);
+        self.client.send(request).await?;
+
+        Ok(())
+    }
+
     async fn delete_backup_from_server(&self, version: String) -> Result<(), Error> {
         let request = ruma::api::client::backup::delete_backup_version::v3::Request::new(version);
 
@@ -892,6 +1053,7 @@ impl Backups {
             Ok(enabled) => {
                 if enabled {
                     self.set_state(BackupState::Enabled);
+                    self.maybe_download_pending_utd_room_keys();
                 } else {
                     self.set_state(BackupState::Unknown);
                 }
@@ -954,7 +1116,10 @@ impl Backups {
 
         // Let us first check if we have a stored backup recovery key and a backup
         // version.
-        if !self.resume_backup_from_stored_backup_key(olm_machine).await? {
+        if self.resume_backup_from_stored_backup_key(olm_machine).await? {
+            // Some room keys might have been left over by a previous session.
+            self.maybe_download_pending_utd_room_keys();
+        } else {
             // We didn't manage to enable backups from a stored backup recovery key, let us
             // check our secret inbox. Perhaps we can find a valid key there.
             self.maybe_resume_from_secret_inbox(olm_machine).await?;
@@ -1038,9 +1203,10 @@ mod test {
     use std::time::Duration;
 
     use matrix_sdk_test::async_test;
+    use ruma::room_id;
     use serde_json::json;
     use wiremock::{
-        matchers::{header, method, path},
+        matchers::{header, method, path, path_regex},
         Mock, MockServer, ResponseTemplate,
     };
 
@@ -1149,6 +1315,72 @@ mod test {
         server.verify().await;
     }
 
+    #[async_test]
+    async fn test_pending_utd_room_keys_are_downloaded_once_backups_are_enabled() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+        let backups = client.encryption().backups();
+        let room_id = room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost");
+        let session_id = "gM8i47Xhu0q52xLfgUXzanCMpLinoyVyH7R58cBuVBU";
+
+        {
+            let machine = client.olm_machine().await;
+            let machine = machine.as_ref().unwrap();
+            backups
+                .add_pending_utd_session(machine, room_id, session_id)
+                .await
+                .expect("We should be able to remember the room key");
+        }
+
+        // Backups aren't enabled yet, the room key is kept for later.
+        let downloaded = backups
+            .download_pending_utd_room_keys()
+            .await
+            .expect("We should be able to try to download the room keys");
+        assert_eq!(downloaded, 0);
+
+        {
+            let machine = client.olm_machine().await;
+            let sessions =
+                Backups::load_pending_utd_sessions(machine.as_ref().unwrap()).await.unwrap();
+            assert!(sessions.contains(&(room_id.to_owned(), session_id.to_owned())));
+        }
+
+        Mock::given(method("POST"))
+            .and(path("_matrix/client/unstable/room_keys/version"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "version": "1" })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/.*/room_keys/keys/.*/.*"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "errcode": "M_NOT_FOUND",
+                "error": "No room key found"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        backups.create().await.expect("We should be able to create a new backup");
+
+        // The room key isn't in the backup, so it's forgotten.
+        let downloaded = backups
+            .download_pending_utd_room_keys()
+            .await
+            .expect("We should be able to try to download the room keys");
+        assert_eq!(downloaded, 0);
+
+        {
+            let machine = client.olm_machine().await;
+            let sessions =
+                Backups::load_pending_utd_sessions(machine.as_ref().unwrap()).await.unwrap();
+            assert!(sessions.is_empty());
+        }
+
+        server.verify().await;
+    }
+
     #[async_test]
     async fn test_when_a_backup_exists_then_fetch_exists_on_server_returns_true() {
         let server = MatrixMockServer::new().await;
@@ -25,6 +25,7 @@ use eyeball::Subscriber;
 use matrix_sdk_common::boxed_into_future;
 use ruma::events::room::{EncryptedFile, EncryptedFileInit};
 
+use super::{secret_storage::SecretStore, CrossSigningResetHandle, Encryption};
 use crate::{config::RequestConfig, Client, Media, Result, TransmissionProgress};
 
 /// Future returned by [`Client::upload_encrypted_file`].
@@ -121,3 +122,36 @@ where
         })
     }
 }
+
+/// Future returned by [`Encryption::reset_cross_signing`].
+#[allow(missing_debug_implementations)]
+pub struct ResetCrossSigning {
+    encryption: Encryption,
+    secret_store: Option<SecretStore>,
+}
+
+impl ResetCrossSigning {
+    pub(crate) fn new(encryption: Encryption) -> Self {
+        Self { encryption, secret_store: None }
+    }
+
+    /// Store the new private cross-signing keys in the given secret store,
+    /// replacing the old ones.
+    ///
+    /// Without this, the secret storage keeps the old keys, and the other
+    /// devices can't get the new ones by recovering from it.
+    pub fn with_secret_store(mut self, secret_store: SecretStore) -> Self {
+        self.secret_store = Some(secret_store);
+        self
+    }
+}
+
+impl IntoFuture for ResetCrossSigning {
+    type Output = Result<Option<CrossSigningResetHandle>>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { encryption, secret_store } = self;
+        Box::pin(async move { encryption.reset_cross_signing_inner(secret_store).await })
+    }
+}
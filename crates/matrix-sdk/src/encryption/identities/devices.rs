@@ -18,7 +18,10 @@ use matrix_sdk_base::crypto::{
     store::CryptoStoreError, Device as BaseDevice, DeviceData, LocalTrust,
     UserDevices as BaseUserDevices,
 };
-use ruma::{events::key::verification::VerificationMethod, DeviceId, OwnedDeviceId, OwnedUserId};
+use ruma::{
+    events::key::verification::VerificationMethod, DeviceId, MilliSecondsSinceUnixEpoch,
+    OwnedDeviceId, OwnedUserId,
+};
 
 use super::ManualVerifyError;
 use crate::{
@@ -234,7 +237,7 @@ impl Device {
     )]
     pub async fn start_verification(&self) -> Result<SasVerification> {
         let (sas, request) = self.inner.start_verification().await?;
-        self.client.send_to_device(&request).await?;
+        self.client.send_to_device_request(&request).await?;
 
         Ok(SasVerification { inner: sas, client: self.client.clone() })
     }
@@ -549,6 +552,25 @@ impl Device {
         self.inner.set_local_trust(trust_state).await
     }
 
+    /// Blacklist or unblacklist the device.
+    ///
+    /// A blacklisted device never receives room keys, it gets an
+    /// `m.blacklisted` withheld code instead. Unblacklisting a device resets
+    /// its local trust state, it does nothing if the device isn't blacklisted.
+    ///
+    /// # Arguments
+    ///
+    /// * `blacklisted` - Whether the device should be blacklisted.
+    pub async fn set_blacklisted(&self, blacklisted: bool) -> Result<(), CryptoStoreError> {
+        if blacklisted {
+            self.set_local_trust(LocalTrust::BlackListed).await
+        } else if self.is_blacklisted() {
+            self.set_local_trust(LocalTrust::Unset).await
+        } else {
+            Ok(())
+        }
+    }
+
     /// Is the device cross-signed by its own user.
     pub fn is_cross_signed_by_owner(&self) -> bool {
         self.inner.is_cross_signed_by_owner()
@@ -580,3 +602,63 @@ impl UserDevices {
         self.inner.devices().map(move |d| Device { inner: d, client: client.clone() })
     }
 }
+
+/// A device that [`Encryption::stale_devices()`] suggests to get rid of.
+///
+/// [`Encryption::stale_devices()`]: crate::encryption::Encryption::stale_devices
+#[derive(Clone, Debug)]
+pub struct StaleDevice {
+    /// The stale device.
+    pub device: Device,
+
+    /// When the homeserver last saw the device.
+    ///
+    /// This is only known for the devices of the current user.
+    pub last_seen_ts: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// Why the device is considered stale.
+    pub reason: StaleDeviceReason,
+}
+
+impl StaleDevice {
+    /// The suggested way to get rid of the device.
+    pub fn suggested_action(&self) -> StaleDeviceAction {
+        match self.reason {
+            StaleDeviceReason::Inactive => StaleDeviceAction::SignOut,
+            StaleDeviceReason::UnknownToHomeserver | StaleDeviceReason::NotCrossSigned => {
+                StaleDeviceAction::Distrust
+            }
+        }
+    }
+}
+
+/// Why a device is considered stale by [`Encryption::stale_devices()`].
+///
+/// [`Encryption::stale_devices()`]: crate::encryption::Encryption::stale_devices
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StaleDeviceReason {
+    /// The device of the current user hasn't been seen by the homeserver
+    /// for the whole inactivity period.
+    Inactive,
+
+    /// The device of the current user has been removed from the homeserver,
+    /// but its keys are still known.
+    UnknownToHomeserver,
+
+    /// The device of another user isn't cross-signed by its owner, and has
+    /// been known for at least the inactivity period.
+    NotCrossSigned,
+}
+
+/// The suggested way to get rid of a [`StaleDevice`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StaleDeviceAction {
+    /// Sign the device out, with [`Client::delete_devices()`].
+    SignOut,
+
+    /// Stop sharing room keys with the device, with
+    /// [`Encryption::distrust_devices()`].
+    ///
+    /// [`Encryption::distrust_devices()`]: crate::encryption::Encryption::distrust_devices
+    Distrust,
+}
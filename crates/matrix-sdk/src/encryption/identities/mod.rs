@@ -87,7 +87,9 @@
 mod devices;
 mod users;
 
-pub use devices::{Device, DeviceUpdates, UserDevices};
+pub use devices::{
+    Device, DeviceUpdates, StaleDevice, StaleDeviceAction, StaleDeviceReason, UserDevices,
+};
 pub use matrix_sdk_base::crypto::types::MasterPubkey;
 pub use users::{IdentityUpdates, UserIdentity};
 
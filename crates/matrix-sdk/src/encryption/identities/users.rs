@@ -440,6 +440,44 @@ impl UserIdentity {
         self.inner.pin().await
     }
 
+    /// Accept the current identity of this user, resolving any identity
+    /// violation it is in.
+    ///
+    /// If the identity was previously verified, the verification requirement
+    /// is withdrawn (see [`UserIdentity::withdraw_verification()`]), otherwise
+    /// the current identity is pinned (see [`UserIdentity::pin()`]).
+    ///
+    /// This is the action to take once the user has acknowledged that the
+    /// identity of one of their contacts changed.
+    pub async fn remember_identity(&self) -> Result<(), CryptoStoreError> {
+        if self.inner.has_verification_violation() {
+            self.inner.withdraw_verification().await
+        } else {
+            self.inner.pin().await
+        }
+    }
+
+    /// Was this identity verified at some point, and is not anymore?
+    ///
+    /// Such a violation should be resolved by verifying the new identity, or
+    /// by withdrawing the verification requirement with
+    /// [`UserIdentity::withdraw_verification()`].
+    pub fn has_verification_violation(&self) -> bool {
+        self.inner.has_verification_violation()
+    }
+
+    /// Has this identity changed since we first saw it, in a way the user
+    /// needs to acknowledge?
+    ///
+    /// This is never the case for our own identity, nor for verified
+    /// identities.
+    pub fn identity_needs_user_approval(&self) -> bool {
+        match &self.inner {
+            CryptoUserIdentity::Own(_) => false,
+            CryptoUserIdentity::Other(identity) => identity.identity_needs_user_approval(),
+        }
+    }
+
     /// Get the public part of the Master key of this user identity.
     ///
     /// The public part of the Master key is usually used to uniquely identify
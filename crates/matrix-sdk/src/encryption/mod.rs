@@ -20,8 +20,10 @@ use std::{
     collections::{BTreeMap, HashSet},
     io::{Cursor, Read, Write},
     iter,
+    ops::Deref,
     path::PathBuf,
-    sync::Arc,
+    sync::{Arc, RwLock as StdRwLock},
+    time::Duration,
 };
 
 use eyeball::{SharedObservable, Subscriber};
@@ -32,11 +34,15 @@ use futures_util::{
 };
 use matrix_sdk_base::crypto::{
     store::RoomKeyInfo,
-    types::requests::{
-        OutgoingRequest, OutgoingVerificationRequest, RoomMessageRequest, ToDeviceRequest,
+    types::{
+        requests::{
+            OutgoingRequest, OutgoingVerificationRequest, RoomMessageRequest, ToDeviceRequest,
+        },
+        DeviceKeys,
     },
     CrossSigningBootstrapRequests, OlmMachine,
 };
+use matrix_sdk_base::RoomMemberships;
 use matrix_sdk_common::{executor::spawn, locks::Mutex as StdMutex};
 use ruma::{
     api::client::{
@@ -55,7 +61,8 @@ use ruma::{
         direct::DirectUserIdentifier,
         room::{MediaSource, ThumbnailInfo},
     },
-    DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedUserId, TransactionId, UserId,
+    DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedUserId, RoomId, TransactionId,
+    UserId,
 };
 use serde::Deserialize;
 use tokio::sync::{Mutex, RwLockReadGuard};
@@ -66,10 +73,13 @@ use vodozemac::Curve25519PublicKey;
 
 use self::{
     backups::{types::BackupClientState, Backups},
-    futures::UploadEncryptedFile,
-    identities::{Device, DeviceUpdates, IdentityUpdates, UserDevices, UserIdentity},
+    futures::{ResetCrossSigning, UploadEncryptedFile},
+    identities::{
+        Device, DeviceUpdates, IdentityUpdates, StaleDevice, StaleDeviceReason, UserDevices,
+        UserIdentity,
+    },
     recovery::{Recovery, RecoveryState},
-    secret_storage::SecretStorage,
+    secret_storage::{SecretStorage, SecretStorageProvider, SecretStore},
     tasks::{BackupDownloadTask, BackupUploadingTask, ClientTasks},
     verification::{SasVerification, Verification, VerificationRequest},
 };
@@ -96,10 +106,10 @@ pub use matrix_sdk_base::crypto::{
     },
     vodozemac, CrossSigningStatus, CryptoStoreError, DecryptorError, EventError, KeyExportError,
     LocalTrust, MediaEncryptionInfo, MegolmError, OlmError, RoomKeyImportResult, SecretImportError,
-    SessionCreationError, SignatureError, VERSION,
+    SessionCreationError, SessionShareInfo, SignatureError, TrackedUserState, VERSION,
 };
 
-pub use crate::error::RoomKeyImportError;
+pub use crate::error::{RoomKeyImportError, SessionMismatch, SessionMismatchRecovery};
 
 /// All the data related to the encryption state.
 pub(crate) struct EncryptionData {
@@ -115,6 +125,14 @@ pub(crate) struct EncryptionData {
 
     /// All state related to secret storage recovery.
     pub recovery_state: SharedObservable<RecoveryState>,
+
+    /// The last time the user confirmed that they still have their recovery
+    /// key.
+    pub recovery_key_confirmation: SharedObservable<Option<MilliSecondsSinceUnixEpoch>>,
+
+    /// The keystore of the platform holding the well-known secrets, see
+    /// [`SecretStorage::set_provider()`].
+    pub secret_storage_provider: StdRwLock<Option<Arc<dyn SecretStorageProvider>>>,
 }
 
 impl EncryptionData {
@@ -125,6 +143,8 @@ impl EncryptionData {
             tasks: StdMutex::new(Default::default()),
             backup_state: Default::default(),
             recovery_state: Default::default(),
+            recovery_key_confirmation: Default::default(),
+            secret_storage_provider: Default::default(),
         }
     }
 
@@ -174,6 +194,14 @@ pub struct EncryptionSettings {
 
     /// Automatically create a backup version if no backup exists.
     pub auto_enable_backups: bool,
+
+    /// Check that the keys of the device in the crypto store match the ones
+    /// known by the homeserver when a session is restored, with
+    /// [`Encryption::check_own_device_keys()`].
+    ///
+    /// If they don't match, [`Client::restore_session()`] fails with an
+    /// [`Error::SessionMismatch`].
+    pub check_device_keys_on_restore: bool,
 }
 
 /// Settings for end-to-end encryption features.
@@ -249,6 +277,7 @@ pub struct CrossSigningResetHandle {
     signatures_request: UploadSignaturesRequest,
     auth_type: CrossSigningResetAuthType,
     is_cancelled: Mutex<bool>,
+    secret_store: Option<SecretStore>,
 }
 
 impl CrossSigningResetHandle {
@@ -265,6 +294,7 @@ impl CrossSigningResetHandle {
             signatures_request,
             auth_type,
             is_cancelled: Mutex::new(false),
+            secret_store: None,
         }
     }
 
@@ -277,6 +307,9 @@ impl CrossSigningResetHandle {
     /// Continue the cross-signing reset by either waiting for the
     /// authentication to be done on the side of the OIDC issuer or by
     /// providing additional [`AuthData`] the homeserver requires.
+    ///
+    /// Once the new keys are uploaded, the rest of the reset is done as
+    /// described in [`Encryption::reset_cross_signing()`].
     pub async fn auth(&self, auth: Option<AuthData>) -> Result<()> {
         let mut upload_request = self.upload_request.clone();
         upload_request.auth = auth;
@@ -291,9 +324,10 @@ impl CrossSigningResetHandle {
             }
         }
 
-        self.client.send(self.signatures_request.clone()).await?;
-
-        Ok(())
+        self.client
+            .encryption()
+            .finish_cross_signing_reset(self.signatures_request.clone(), self.secret_store.as_ref())
+            .await
     }
 
     /// Cancel the ongoing identity reset process
@@ -411,7 +445,19 @@ impl Client {
     ) -> Result<get_keys::v3::Response> {
         let request = assign!(get_keys::v3::Request::new(), { device_keys });
 
-        let response = self.send(request).await?;
+        let response = match self.send(request).await {
+            Ok(response) => response,
+            Err(error) => {
+                // Let the users of the request be queried again by the next
+                // `/keys/query` request.
+                if let Some(olm_machine) = self.olm_machine().await.as_ref() {
+                    olm_machine.mark_keys_query_as_failed(request_id).await;
+                }
+
+                return Err(error.into());
+            }
+        };
+
         self.mark_request_as_sent(request_id, &response).await?;
         self.encryption().update_state_after_keys_query(&response).await;
 
@@ -572,7 +618,7 @@ impl Client {
             .await
     }
 
-    pub(crate) async fn send_to_device(
+    pub(crate) async fn send_to_device_request(
         &self,
         request: &ToDeviceRequest,
     ) -> HttpResult<ToDeviceResponse> {
@@ -593,7 +639,7 @@ impl Client {
 
         match request {
             ToDevice(t) => {
-                self.send_to_device(&t).await?;
+                self.send_to_device_request(&t).await?;
             }
             InRoom(r) => {
                 self.room_send_helper(&r).await?;
@@ -628,7 +674,7 @@ impl Client {
                 self.keys_upload(r.request_id(), request).await?;
             }
             AnyOutgoingRequest::ToDeviceRequest(request) => {
-                let response = self.send_to_device(request).await?;
+                let response = self.send_to_device_request(request).await?;
                 self.mark_request_as_sent(r.request_id(), &response).await?;
             }
             AnyOutgoingRequest::SignatureUpload(request) => {
@@ -648,6 +694,25 @@ impl Client {
         Ok(())
     }
 
+    /// Make the members of the active room, if any, the priority users for the
+    /// `/keys/query` requests.
+    async fn update_key_query_priority_users(&self, olm_machine: &OlmMachine) {
+        let users = match self.active_room() {
+            Some(room_id) => {
+                match self.store().get_user_ids(&room_id, RoomMemberships::ACTIVE).await {
+                    Ok(users) => users,
+                    Err(error) => {
+                        warn!(%room_id, ?error, "Couldn't load the members of the active room");
+                        Vec::new()
+                    }
+                }
+            }
+            None => Vec::new(),
+        };
+
+        olm_machine.set_key_query_priority_users(users.iter().map(Deref::deref));
+    }
+
     pub(crate) async fn send_outgoing_requests(&self) -> Result<()> {
         const MAX_CONCURRENT_REQUESTS: usize = 20;
 
@@ -657,15 +722,17 @@ impl Client {
             warn!("Error while claiming one-time keys {:?}", e);
         }
 
-        let outgoing_requests = stream::iter(
-            self.olm_machine()
-                .await
-                .as_ref()
-                .ok_or(Error::NoOlmMachine)?
-                .outgoing_requests()
-                .await?,
-        )
-        .map(|r| self.send_outgoing_request(r));
+        let outgoing_requests = {
+            let olm_machine = self.olm_machine().await;
+            let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+            self.update_key_query_priority_users(olm_machine).await;
+
+            olm_machine.outgoing_requests().await?
+        };
+
+        let outgoing_requests =
+            stream::iter(outgoing_requests).map(|r| self.send_outgoing_request(r));
 
         let requests = outgoing_requests.buffer_unordered(MAX_CONCURRENT_REQUESTS);
 
@@ -763,6 +830,24 @@ impl Encryption {
         }
     }
 
+    /// Get the state of the device list of the given tracked user.
+    ///
+    /// This allows to find out whether the devices of the user are
+    /// up-to-date, or whether the server of the user is unreachable, in which
+    /// case it's queried again automatically after an exponential backoff.
+    ///
+    /// Returns `None` if the user isn't tracked.
+    pub async fn tracked_user_state(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<TrackedUserState>, CryptoStoreError> {
+        if let Some(machine) = self.client.olm_machine().await.as_ref() {
+            machine.tracked_user_state(user_id).await
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Get a [`Subscriber`] for the [`VerificationState`].
     ///
     /// # Examples
@@ -877,6 +962,29 @@ impl Encryption {
         Ok(device.map(|d| Device { inner: d, client: self.client.clone() }))
     }
 
+    /// Get who the room key with the given session ID was shared with, and the
+    /// reasons why it was withheld from the other devices.
+    ///
+    /// This helps diagnosing why other users can't decrypt the messages we
+    /// sent. Only the room key currently used to encrypt the messages of the
+    /// room is known, so this returns `None` if it was rotated since, if the
+    /// session doesn't exist, or if the client hasn't been logged in.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room where the room key is used.
+    ///
+    /// * `session_id` - The ID of the Megolm session of the room key, as found
+    ///   in the encrypted events.
+    pub async fn session_share_info(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Option<SessionShareInfo> {
+        let olm = self.client.olm_machine().await;
+        olm.as_ref()?.session_share_info(room_id, session_id).await
+    }
+
     /// Get a map holding all the devices of an user.
     ///
     /// This will always return an empty map if the client hasn't been logged
@@ -915,6 +1023,129 @@ impl Encryption {
         Ok(UserDevices { inner: devices, client: self.client.clone() })
     }
 
+    /// Get the devices of a user that look abandoned, and that room keys are
+    /// needlessly shared with.
+    ///
+    /// For the current user, a device is stale if the homeserver hasn't seen
+    /// it for at least `inactivity`, or if it has been removed from the
+    /// homeserver. The current device is never considered stale.
+    ///
+    /// The homeserver doesn't tell when the devices of other users were last
+    /// active, so a device of theirs is stale if it has been known for at
+    /// least `inactivity` without being cross-signed by its owner, provided
+    /// the owner has set up cross-signing.
+    ///
+    /// The device list of the user is refreshed first if it's outdated, or if
+    /// they aren't tracked.
+    ///
+    /// Each stale device comes with a [suggested
+    /// action](StaleDevice::suggested_action) to get rid of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The ID of the user owning the devices.
+    ///
+    /// * `inactivity` - How long a device can stay inactive before being
+    ///   considered stale.
+    #[instrument(skip(self))]
+    pub async fn stale_devices(
+        &self,
+        user_id: &UserId,
+        inactivity: Duration,
+    ) -> Result<Vec<StaleDevice>> {
+        {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            let state = olm.tracked_user_state(user_id).await?;
+
+            if state != Some(TrackedUserState::UpToDate) {
+                debug!("The device list is outdated or unknown, refreshing it");
+                let (request_id, request) = olm.query_keys_for_users([user_id]);
+                self.client.keys_query(&request_id, request.device_keys).await?;
+            }
+        }
+
+        let now = u64::from(MilliSecondsSinceUnixEpoch::now().0);
+        let is_older = |ts: MilliSecondsSinceUnixEpoch| {
+            Duration::from_millis(now.saturating_sub(ts.0.into())) >= inactivity
+        };
+
+        let devices = self.get_user_devices(user_id).await?;
+        let mut stale_devices = Vec::new();
+
+        if self.client.user_id() == Some(user_id) {
+            let last_seen: BTreeMap<_, _> = self
+                .client
+                .devices()
+                .await?
+                .devices
+                .into_iter()
+                .map(|device| (device.device_id, device.last_seen_ts))
+                .collect();
+
+            for device in devices.devices() {
+                if self.client.device_id() == Some(device.device_id()) {
+                    continue;
+                }
+
+                let (last_seen_ts, reason) = match last_seen.get(device.device_id()) {
+                    None => (None, StaleDeviceReason::UnknownToHomeserver),
+                    Some(&last_seen_ts) => {
+                        if !is_older(last_seen_ts.unwrap_or(device.first_time_seen_ts())) {
+                            continue;
+                        }
+                        (last_seen_ts, StaleDeviceReason::Inactive)
+                    }
+                };
+
+                stale_devices.push(StaleDevice { device, last_seen_ts, reason });
+            }
+        } else if self.get_user_identity(user_id).await?.is_some() {
+            stale_devices.extend(
+                devices
+                    .devices()
+                    .filter(|device| {
+                        !device.is_cross_signed_by_owner() && is_older(device.first_time_seen_ts())
+                    })
+                    .map(|device| StaleDevice {
+                        device,
+                        last_seen_ts: None,
+                        reason: StaleDeviceReason::NotCrossSigned,
+                    }),
+            );
+        }
+
+        debug!(num_stale_devices = stale_devices.len(), "Found stale devices");
+
+        Ok(stale_devices)
+    }
+
+    /// Stop sharing room keys with the given devices of a user, by
+    /// blacklisting them locally.
+    ///
+    /// This is meant to be used with the devices returned by
+    /// [`Encryption::stale_devices()`]. The devices that aren't known are
+    /// ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The ID of the user owning the devices.
+    ///
+    /// * `device_ids` - The IDs of the devices to distrust.
+    pub async fn distrust_devices(
+        &self,
+        user_id: &UserId,
+        device_ids: &[OwnedDeviceId],
+    ) -> Result<(), CryptoStoreError> {
+        for device_id in device_ids {
+            if let Some(device) = self.get_device(user_id, device_id).await? {
+                device.set_local_trust(LocalTrust::BlackListed).await?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Get the E2EE identity of a user from the crypto store.
     ///
     /// Usually, we only have the E2EE identity of a user locally if the user
@@ -1154,6 +1385,20 @@ impl Encryption {
 
     /// Reset the cross-signing keys.
     ///
+    /// This creates new cross-signing keys and uploads them, which may require
+    /// additional authentication. In that case, a [`CrossSigningResetHandle`]
+    /// is returned, and the reset continues once
+    /// [`CrossSigningResetHandle::auth()`] is called.
+    ///
+    /// Once the new keys are uploaded:
+    ///
+    /// * the current device is signed with the new self-signing key,
+    /// * the new private keys are stored in the secret storage, if a
+    ///   [`SecretStore`] is provided with
+    ///   [`ResetCrossSigning::with_secret_store()`],
+    /// * the active backup, if any, is signed with the new master key so the
+    ///   other devices keep trusting it.
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -1188,7 +1433,14 @@ impl Encryption {
     /// }
     /// # anyhow::Ok(()) };
     /// ```
-    pub async fn reset_cross_signing(&self) -> Result<Option<CrossSigningResetHandle>> {
+    pub fn reset_cross_signing(&self) -> ResetCrossSigning {
+        ResetCrossSigning::new(self.clone())
+    }
+
+    pub(crate) async fn reset_cross_signing_inner(
+        &self,
+        secret_store: Option<SecretStore>,
+    ) -> Result<Option<CrossSigningResetHandle>> {
         let olm = self.client.olm_machine().await;
         let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
 
@@ -1213,22 +1465,45 @@ impl Encryption {
             if let Some(auth_type) = CrossSigningResetAuthType::new(&self.client, &error).await? {
                 let client = self.client.clone();
 
-                Ok(Some(CrossSigningResetHandle::new(
+                let mut handle = CrossSigningResetHandle::new(
                     client,
                     upload_signing_keys_req,
                     upload_signatures_req,
                     auth_type,
-                )))
+                );
+                handle.secret_store = secret_store;
+
+                Ok(Some(handle))
             } else {
                 Err(error.into())
             }
         } else {
-            self.client.send(upload_signatures_req).await?;
+            self.finish_cross_signing_reset(upload_signatures_req, secret_store.as_ref()).await?;
 
             Ok(None)
         }
     }
 
+    /// Finish resetting the cross-signing keys, once the new public keys have
+    /// been uploaded.
+    async fn finish_cross_signing_reset(
+        &self,
+        signatures_request: UploadSignaturesRequest,
+        secret_store: Option<&SecretStore>,
+    ) -> Result<()> {
+        // This contains the signature of the current device by the new
+        // self-signing key.
+        self.client.send(signatures_request).await?;
+
+        if let Some(secret_store) = secret_store {
+            secret_store.export_cross_signing_keys().await?;
+        }
+
+        self.backups().sign_active_backup().await?;
+
+        Ok(())
+    }
+
     /// Query the user's own device keys, if, and only if, we didn't have their
     /// identity in the first place.
     async fn ensure_initial_key_query(&self) -> Result<()> {
@@ -1485,6 +1760,21 @@ impl Encryption {
         Some(olm.store().room_keys_received_stream())
     }
 
+    /// Enable or disable accepting the room keys that our own verified devices
+    /// forward without us requesting them.
+    ///
+    /// This needs to be enabled on a device for it to accept the room keys
+    /// shared with [`Room::share_room_keys_with_own_device()`]. It is disabled
+    /// by default.
+    ///
+    /// [`Room::share_room_keys_with_own_device()`]: crate::Room::share_room_keys_with_own_device
+    pub async fn set_unrequested_room_key_forwards_enabled(&self, enabled: bool) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.set_unrequested_room_key_forwards_enabled(enabled).await?)
+    }
+
     /// Get the secret storage manager of the client.
     pub fn secret_storage(&self) -> SecretStorage {
         SecretStorage { client: self.client.to_owned() }
@@ -1712,6 +2002,77 @@ impl Encryption {
         Ok(())
     }
 
+    /// Check that the keys of our own device in the crypto store match the
+    /// keys that the homeserver has for it.
+    ///
+    /// A mismatch means that the session was restored with the wrong crypto
+    /// store, for example one restored from an old backup, and that the
+    /// messages sent from this device wouldn't be decryptable by the other
+    /// devices. It is reported as an [`Error::SessionMismatch`], with the ways
+    /// to recover from it.
+    ///
+    /// This is done automatically when restoring a session if
+    /// [`EncryptionSettings::check_device_keys_on_restore`] is set.
+    ///
+    /// It is not an error if the keys haven't been uploaded yet.
+    pub async fn check_own_device_keys(&self) -> Result<()> {
+        let (user_id, device_id, identity_keys, keys_uploaded) = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+            // The device keys are only part of the upload request if they haven't been
+            // uploaded yet.
+            let keys_uploaded = olm
+                .upload_device_keys()
+                .await?
+                .map_or(true, |(_, request)| request.device_keys.is_none());
+
+            (
+                olm.user_id().to_owned(),
+                olm.device_id().to_owned(),
+                olm.identity_keys(),
+                keys_uploaded,
+            )
+        };
+
+        let request = assign!(get_keys::v3::Request::new(), {
+            device_keys: BTreeMap::from([(user_id.clone(), vec![device_id.clone()])]),
+        });
+        let response = self.client.send(request).await?;
+
+        let server_keys = response
+            .device_keys
+            .get(&user_id)
+            .and_then(|devices| devices.get(&device_id))
+            .map(|raw| raw.deserialize_as::<DeviceKeys>())
+            .transpose()?;
+
+        let Some(server_keys) = server_keys else {
+            if keys_uploaded {
+                warn!("The homeserver doesn't have the keys of our own device");
+                return Err(SessionMismatch::MissingDeviceKeys { device_id }.into());
+            }
+
+            return Ok(());
+        };
+
+        let server_ed25519 = server_keys.ed25519_key();
+
+        if server_ed25519 != Some(identity_keys.ed25519)
+            || server_keys.curve25519_key() != Some(identity_keys.curve25519)
+        {
+            warn!("The keys of our own device don't match the ones of the homeserver");
+            return Err(SessionMismatch::MismatchedDeviceKeys {
+                device_id,
+                local_ed25519: identity_keys.ed25519,
+                server_ed25519,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn update_state_after_keys_query(&self, response: &get_keys::v3::Response) {
         self.recovery().update_state_after_keys_query(response).await;
 
@@ -147,6 +147,7 @@ impl<'a> IntoFuture for Enable<'a> {
 
             progress.set(EnableProgress::Done { recovery_key: key });
             recovery.update_recovery_state().await?;
+            recovery.record_recovery_key_confirmation().await?;
 
             Ok(store.secret_storage_key())
         };
@@ -196,6 +197,7 @@ impl<'a> IntoFuture for Reset<'a> {
 
             let store: SecretStore = create_store.await?;
             recovery.update_recovery_state().await?;
+            recovery.record_recovery_key_confirmation().await?;
 
             Ok(store.secret_storage_key())
         };
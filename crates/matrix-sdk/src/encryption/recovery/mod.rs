@@ -90,31 +90,53 @@
 //!
 //! [`Recovery key`]: https://spec.matrix.org/v1.8/client-server-api/#recovery-key
 
+use std::time::Duration;
+
+use async_stream::stream;
 use futures_core::{Future, Stream};
 use futures_util::StreamExt as _;
+use matrix_sdk_base::{StateStoreDataKey, StateStoreDataValue};
 use ruma::{
     api::client::keys::get_keys,
     events::{
-        secret::send::ToDeviceSecretSendEvent,
+        secret::{request::SecretName, send::ToDeviceSecretSendEvent},
         secret_storage::default_key::SecretStorageDefaultKeyEvent,
     },
+    MilliSecondsSinceUnixEpoch,
 };
 use tracing::{error, info, instrument, warn};
 
 #[cfg(doc)]
 use crate::encryption::{
     backups::Backups,
-    secret_storage::{SecretStorage, SecretStore},
+    secret_storage::{SecretStorage, SecretStorageProvider, SecretStore},
+};
+use crate::{
+    client::WeakClient,
+    encryption::{
+        backups::BackupState,
+        secret_storage::{
+            import_known_secrets,
+            provider::{self, ProviderSecrets},
+            SecretStorageError,
+        },
+    },
+    utils::sleep,
+    Client,
 };
-use crate::{client::WeakClient, encryption::backups::BackupState, Client};
 
 pub mod futures;
+mod recommendations;
 mod types;
-pub use self::types::{EnableProgress, RecoveryError, RecoveryState, Result};
 use self::{
     futures::{Enable, RecoverAndReset, Reset},
+    recommendations::SetupState,
     types::{BackupDisabledContent, SecretStorageDisabledContent},
 };
+pub use self::{
+    recommendations::SetupRecommendations,
+    types::{EnableProgress, RecoveryError, RecoveryState, Result},
+};
 use crate::encryption::{AuthData, CrossSigningResetAuthType, CrossSigningResetHandle};
 
 /// The recovery manager for the [`Client`].
@@ -450,10 +472,62 @@ impl Recovery {
 
         store.import_secrets().await?;
         self.update_recovery_state().await?;
+        self.record_recovery_key_confirmation().await?;
 
         Ok(())
     }
 
+    /// Recover all the secrets from the [`SecretStorageProvider`] set with
+    /// [`SecretStorage::set_provider()`], without the recovery key.
+    ///
+    /// Returns whether all the secrets are now available locally. It is
+    /// `false` if there is no provider, if it failed, or if the secrets it
+    /// holds don't match the current ones of the account. In that case, fall
+    /// back to asking the user for the recovery key and call
+    /// [`Recovery::recover()`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let recovery = client.encryption().recovery();
+    ///
+    /// if !recovery.recover_from_provider().await? {
+    ///     recovery.recover("my recovery key or passphrase").await?;
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip_all)]
+    pub async fn recover_from_provider(&self) -> Result<bool> {
+        let Some(secrets) = provider::load_secrets(&self.client).await else {
+            return Ok(false);
+        };
+
+        if secrets.is_empty() {
+            info!("The secret storage provider doesn't hold any secret");
+            return Ok(false);
+        }
+
+        let ProviderSecrets { cross_signing_keys, backup_recovery_key } = secrets;
+
+        match import_known_secrets(&self.client, cross_signing_keys, backup_recovery_key).await {
+            Ok(()) => {}
+            Err(SecretStorageError::SecretImportError(err)) => {
+                warn!("The secrets of the secret storage provider couldn't be imported: {err}");
+                return Ok(false);
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        self.update_recovery_state().await?;
+
+        self.all_known_secrets_available().await
+    }
+
     /// Is this device the last device the user has?
     ///
     /// This method is useful to check if we should recommend to the user that
@@ -474,6 +548,168 @@ impl Recovery {
         Ok(devices.devices().count() == 1)
     }
 
+    /// Get a stream of the actions the user should take to improve the
+    /// encryption setup of the current device.
+    ///
+    /// The recommendations are computed from the [`VerificationState`] of the
+    /// current device, the [`BackupState`] and the [`RecoveryState`]. If
+    /// recovery is enabled, the user is also asked to confirm that they still
+    /// have their recovery key once every `recovery_key_check_interval`, using
+    /// [`Recovery::confirm_recovery_key()`].
+    ///
+    /// This method will send out the current recommendations as the first
+    /// update, subsequent updates are only sent when the recommendations
+    /// change.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// use futures_util::StreamExt;
+    ///
+    /// let recovery = client.encryption().recovery();
+    /// let check_interval = Duration::from_secs(30 * 24 * 60 * 60);
+    ///
+    /// let mut recommendations = recovery.setup_recommendations_stream(check_interval).await;
+    ///
+    /// while let Some(recommendations) = recommendations.next().await {
+    ///     if recommendations.verify_device {
+    ///         println!("Please verify this device");
+    ///     }
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    ///
+    /// [`VerificationState`]: crate::encryption::VerificationState
+    pub async fn setup_recommendations_stream(
+        &self,
+        recovery_key_check_interval: Duration,
+    ) -> impl Stream<Item = SetupRecommendations> {
+        let encryption = self.client.encryption();
+
+        match self.client.store().get_kv_data(StateStoreDataKey::RecoveryKeyConfirmation).await {
+            Ok(value) => {
+                let last_confirmation =
+                    value.and_then(|value| value.into_recovery_key_confirmation());
+                self.client.inner.e2ee.recovery_key_confirmation.set_if_not_eq(last_confirmation);
+            }
+            Err(err) => warn!("Couldn't load the last recovery key confirmation: {err}"),
+        }
+
+        let mut verification_state = encryption.verification_state();
+        let mut backup_state = encryption.backups().state_stream();
+        let mut recovery_state = self.client.inner.e2ee.recovery_state.subscribe();
+        let mut last_confirmation = self.client.inner.e2ee.recovery_key_confirmation.subscribe();
+
+        let mut state = SetupState {
+            verification_state: verification_state.get(),
+            backup_state: encryption.backups().state(),
+            recovery_state: recovery_state.get(),
+            last_confirmation: last_confirmation.get(),
+        };
+
+        stream! {
+            let mut previous = None;
+
+            loop {
+                let (recommendations, next_check) =
+                    state.recommendations(MilliSecondsSinceUnixEpoch::now(), recovery_key_check_interval);
+
+                if previous != Some(recommendations) {
+                    previous = Some(recommendations);
+                    yield recommendations;
+                }
+
+                tokio::select! {
+                    Some(new_state) = verification_state.next() => {
+                        state.verification_state = new_state;
+                    }
+                    Some(new_state) = backup_state.next() => {
+                        // If we lagged behind, the current state is all we need.
+                        state.backup_state =
+                            new_state.unwrap_or_else(|_| encryption.backups().state());
+                    }
+                    Some(new_state) = recovery_state.next() => {
+                        state.recovery_state = new_state;
+                    }
+                    Some(new_confirmation) = last_confirmation.next() => {
+                        state.last_confirmation = new_confirmation;
+                    }
+                    // Compute the recommendations again once the confirmation is due.
+                    _ = sleep(next_check.unwrap_or_default()), if next_check.is_some() => {}
+                    else => break,
+                }
+            }
+        }
+    }
+
+    /// Check that the given recovery key, or recovery passphrase, can still
+    /// decrypt the secrets in secret storage.
+    ///
+    /// This doesn't import the secrets, but records that the user confirmed
+    /// that they still have their recovery key, which resets the
+    /// [`SetupRecommendations::confirm_recovery_key`] reminder.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let recovery = client.encryption().recovery();
+    ///
+    /// if recovery.confirm_recovery_key("my recovery key or passphrase").await.is_err() {
+    ///     println!("This recovery key doesn't work anymore, let's create a new one");
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip_all)]
+    pub async fn confirm_recovery_key(&self, recovery_key: &str) -> Result<()> {
+        let store =
+            self.client.encryption().secret_storage().open_secret_store(recovery_key).await?;
+
+        // Opening the store only checks the key against the key description, make
+        // sure that a secret can actually be decrypted with it.
+        let mut decrypted = false;
+
+        for secret_name in [SecretName::CrossSigningMasterKey, SecretName::RecoveryKey] {
+            if store.get_secret(secret_name).await?.is_some() {
+                decrypted = true;
+                break;
+            }
+        }
+
+        if !decrypted {
+            return Err(RecoveryError::NoDecryptableSecret);
+        }
+
+        self.record_recovery_key_confirmation().await
+    }
+
+    /// Remember that the user just proved that they have their recovery key.
+    async fn record_recovery_key_confirmation(&self) -> Result<()> {
+        let now = MilliSecondsSinceUnixEpoch::now();
+
+        self.client
+            .store()
+            .set_kv_data(
+                StateStoreDataKey::RecoveryKeyConfirmation,
+                StateStoreDataValue::RecoveryKeyConfirmation(now),
+            )
+            .await
+            .map_err(crate::Error::from)?;
+        self.client.inner.e2ee.recovery_key_confirmation.set(Some(now));
+
+        Ok(())
+    }
+
     /// Did we correctly set up cross-signing and backups?
     async fn all_known_secrets_available(&self) -> Result<bool> {
         // Cross-signing state is fine if we have all the private cross-signing keys, as
@@ -0,0 +1,175 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use ruma::MilliSecondsSinceUnixEpoch;
+
+use super::RecoveryState;
+use crate::encryption::{backups::BackupState, VerificationState};
+
+/// The actions the user should take to improve the encryption setup of the
+/// current device.
+///
+/// See [`Recovery::setup_recommendations_stream()`].
+///
+/// [`Recovery::setup_recommendations_stream()`]: super::Recovery::setup_recommendations_stream
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SetupRecommendations {
+    /// The current device isn't signed by the user identity and should be
+    /// verified.
+    pub verify_device: bool,
+
+    /// No backup is active for the current device and one should be enabled.
+    pub enable_backup: bool,
+
+    /// The user didn't confirm that they still have their recovery key for
+    /// longer than the configured interval, and should do it with
+    /// [`Recovery::confirm_recovery_key()`].
+    ///
+    /// [`Recovery::confirm_recovery_key()`]: super::Recovery::confirm_recovery_key
+    pub confirm_recovery_key: bool,
+}
+
+impl SetupRecommendations {
+    /// Whether there is nothing to recommend.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The inputs used to compute the [`SetupRecommendations`].
+#[derive(Clone, Copy, Debug)]
+pub(super) struct SetupState {
+    pub verification_state: VerificationState,
+    pub backup_state: BackupState,
+    pub recovery_state: RecoveryState,
+    pub last_confirmation: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl SetupState {
+    /// Compute the recommendations at the given time.
+    ///
+    /// Returns the recommendations and, if the recovery key confirmation isn't
+    /// due yet, how long until it becomes due.
+    pub fn recommendations(
+        &self,
+        now: MilliSecondsSinceUnixEpoch,
+        check_interval: Duration,
+    ) -> (SetupRecommendations, Option<Duration>) {
+        let verify_device = self.verification_state == VerificationState::Unverified;
+
+        // Wait until the recovery state is known, an unknown backup state is the
+        // initial state of every client.
+        let enable_backup = self.backup_state == BackupState::Unknown
+            && self.recovery_state != RecoveryState::Unknown;
+
+        let mut next_check = None;
+        let confirm_recovery_key = self.recovery_state == RecoveryState::Enabled
+            && match self.last_confirmation {
+                Some(last) => {
+                    let elapsed =
+                        Duration::from_millis(now.get().saturating_sub(last.get()).into());
+
+                    match check_interval.checked_sub(elapsed) {
+                        Some(remaining) if !remaining.is_zero() => {
+                            next_check = Some(remaining);
+                            false
+                        }
+                        _ => true,
+                    }
+                }
+                None => true,
+            };
+
+        (SetupRecommendations { verify_device, enable_backup, confirm_recovery_key }, next_check)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ruma::{uint, MilliSecondsSinceUnixEpoch, UInt};
+
+    use super::{SetupRecommendations, SetupState};
+    use crate::encryption::{backups::BackupState, recovery::RecoveryState, VerificationState};
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn ts(days: u32) -> MilliSecondsSinceUnixEpoch {
+        MilliSecondsSinceUnixEpoch(uint!(1_000_000_000_000) + UInt::from(days * 86_400_000))
+    }
+
+    #[test]
+    fn test_nothing_to_recommend() {
+        let state = SetupState {
+            verification_state: VerificationState::Verified,
+            backup_state: BackupState::Enabled,
+            recovery_state: RecoveryState::Enabled,
+            last_confirmation: Some(ts(0)),
+        };
+
+        let (recommendations, next_check) = state.recommendations(ts(1), 7 * DAY);
+        assert!(recommendations.is_empty());
+        assert_eq!(next_check, Some(6 * DAY));
+    }
+
+    #[test]
+    fn test_unverified_without_backup() {
+        let state = SetupState {
+            verification_state: VerificationState::Unverified,
+            backup_state: BackupState::Unknown,
+            recovery_state: RecoveryState::Disabled,
+            last_confirmation: None,
+        };
+
+        let (recommendations, next_check) = state.recommendations(ts(0), 7 * DAY);
+        assert_eq!(
+            recommendations,
+            SetupRecommendations {
+                verify_device: true,
+                enable_backup: true,
+                confirm_recovery_key: false
+            }
+        );
+        assert_eq!(next_check, None);
+
+        // Nothing is recommended until the state is known.
+        let state = SetupState {
+            verification_state: VerificationState::Unknown,
+            recovery_state: RecoveryState::Unknown,
+            ..state
+        };
+        assert!(state.recommendations(ts(0), 7 * DAY).0.is_empty());
+    }
+
+    #[test]
+    fn test_recovery_key_confirmation_due() {
+        let state = SetupState {
+            verification_state: VerificationState::Verified,
+            backup_state: BackupState::Enabled,
+            recovery_state: RecoveryState::Enabled,
+            last_confirmation: Some(ts(0)),
+        };
+
+        let (recommendations, next_check) = state.recommendations(ts(7), 7 * DAY);
+        assert!(recommendations.confirm_recovery_key);
+        assert_eq!(next_check, None);
+
+        // A key that was never confirmed on this device must be confirmed.
+        let state = SetupState { last_confirmation: None, ..state };
+        assert!(state.recommendations(ts(0), 7 * DAY).0.confirm_recovery_key);
+    }
+}
@@ -47,6 +47,11 @@ pub enum RecoveryError {
     /// Error in the secret storage subsystem.
     #[error(transparent)]
     SecretStorage(#[from] crate::encryption::secret_storage::SecretStorageError),
+
+    /// The recovery key opened the secret storage, but none of the secrets it
+    /// contains could be decrypted with it.
+    #[error("None of the secrets in secret storage could be decrypted with the recovery key")]
+    NoDecryptableSecret,
 }
 
 /// Enum describing the states the [`Recovery::enable()`] method can be in.
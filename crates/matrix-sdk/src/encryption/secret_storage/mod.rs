@@ -60,7 +60,7 @@
 //! [spec]: https://spec.matrix.org/v1.8/client-server-api/#secret-storage
 //! [account data]: https://spec.matrix.org/v1.8/client-server-api/#client-config
 
-use std::string::FromUtf8Error;
+use std::{string::FromUtf8Error, sync::Arc};
 
 use matrix_sdk_base::crypto::{
     secret_storage::{DecodeError, MacError, SecretStorageKey},
@@ -79,9 +79,12 @@ use super::identities::ManualVerifyError;
 use crate::Client;
 
 mod futures;
+pub(crate) mod provider;
 mod secret_store;
 
 pub use futures::CreateStore;
+pub use provider::{SecretStorageProvider, SecretStorageProviderError};
+pub(crate) use secret_store::import_known_secrets;
 pub use secret_store::SecretStore;
 
 /// Convenicence type alias for the secret-storage specific results.
@@ -269,6 +272,21 @@ impl SecretStorage {
         CreateStore { secret_storage: self, passphrase: None }
     }
 
+    /// Set the [`SecretStorageProvider`] keeping the well-known secrets in a
+    /// keystore of the platform.
+    ///
+    /// The secrets already known by the client are stored in it right away.
+    pub async fn set_provider(&self, provider: impl SecretStorageProvider + 'static) {
+        *self.client.inner.e2ee.secret_storage_provider.write().unwrap() = Some(Arc::new(provider));
+        provider::save_known_secrets(&self.client).await;
+    }
+
+    /// Get the [`SecretStorageProvider`] set with [`Self::set_provider()`], if
+    /// any.
+    pub(crate) fn provider(&self) -> Option<Arc<dyn SecretStorageProvider>> {
+        self.client.inner.e2ee.secret_storage_provider.read().unwrap().clone()
+    }
+
     /// Run a network request to find if secret storage is set up for this user.
     pub async fn is_enabled(&self) -> crate::Result<bool> {
         if let Some(content) = self
@@ -0,0 +1,168 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hooks to keep the well-known secrets in a keystore of the platform, see
+//! [`SecretStorageProvider`].
+
+use async_trait::async_trait;
+use matrix_sdk_base::crypto::CrossSigningKeyExport;
+use ruma::events::secret::request::SecretName;
+use tracing::{debug, warn};
+use zeroize::Zeroize;
+
+use crate::Client;
+
+/// The error type of a [`SecretStorageProvider`].
+pub type SecretStorageProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A keystore of the platform, like the Secure Enclave on Apple platforms or
+/// StrongBox on Android, holding the well-known secrets of the user.
+///
+/// It is set with [`SecretStorage::set_provider()`]. The SDK then stores the
+/// following secrets in it whenever they are imported from or exported to the
+/// [`SecretStore`], in addition to the secret storage on the homeserver:
+///
+/// - `m.cross_signing.master`: The master cross-signing key.
+/// - `m.cross_signing.self_signing`: The self-signing cross-signing key.
+/// - `m.cross_signing.user_signing`: The user-signing cross-signing key.
+/// - `m.megolm_backup.v1`: The backup recovery key.
+///
+/// They can be imported back with [`Recovery::recover_from_provider()`],
+/// without the recovery key.
+///
+/// Errors of the provider are logged and never fail the operation that
+/// triggered them, the secret storage on the homeserver stays the source of
+/// truth.
+///
+/// [`SecretStorage::set_provider()`]: super::SecretStorage::set_provider
+/// [`SecretStore`]: super::SecretStore
+/// [`Recovery::recover_from_provider()`]: crate::encryption::recovery::Recovery::recover_from_provider
+#[async_trait]
+pub trait SecretStorageProvider: Send + Sync {
+    /// Store a secret, replacing the one with the same name if any.
+    async fn store_secret(
+        &self,
+        name: &SecretName,
+        secret: &str,
+    ) -> Result<(), SecretStorageProviderError>;
+
+    /// Retrieve the secret with the given name, if it was stored.
+    async fn retrieve_secret(
+        &self,
+        name: &SecretName,
+    ) -> Result<Option<String>, SecretStorageProviderError>;
+}
+
+/// The secrets stored in a [`SecretStorageProvider`].
+pub(crate) struct ProviderSecrets {
+    /// The private cross-signing keys.
+    pub cross_signing_keys: CrossSigningKeyExport,
+
+    /// The backup recovery key, encoded as base64.
+    pub backup_recovery_key: Option<String>,
+}
+
+impl ProviderSecrets {
+    /// Whether none of the secrets were found.
+    pub fn is_empty(&self) -> bool {
+        self.cross_signing_keys.master_key.is_none()
+            && self.cross_signing_keys.self_signing_key.is_none()
+            && self.cross_signing_keys.user_signing_key.is_none()
+            && self.backup_recovery_key.is_none()
+    }
+}
+
+/// Store the well-known secrets known by the client in its
+/// [`SecretStorageProvider`], if any.
+pub(crate) async fn save_known_secrets(client: &Client) {
+    let Some(provider) = client.encryption().secret_storage().provider() else {
+        return;
+    };
+
+    let olm_machine = client.olm_machine().await;
+    let Some(olm_machine) = olm_machine.as_ref() else {
+        return;
+    };
+
+    let cross_signing_keys = match olm_machine.export_cross_signing_keys().await {
+        Ok(keys) => keys.unwrap_or_default(),
+        Err(err) => {
+            warn!("Couldn't export the cross-signing keys for the secret storage provider: {err}");
+            return;
+        }
+    };
+
+    let backup_recovery_key = match olm_machine.backup_machine().get_backup_keys().await {
+        Ok(keys) => keys.decryption_key.map(|key| key.to_base64()),
+        Err(err) => {
+            warn!("Couldn't export the backup recovery key for the secret storage provider: {err}");
+            None
+        }
+    };
+
+    let mut secrets = ProviderSecrets { cross_signing_keys, backup_recovery_key };
+    let named_secrets = [
+        (SecretName::CrossSigningMasterKey, &secrets.cross_signing_keys.master_key),
+        (SecretName::CrossSigningSelfSigningKey, &secrets.cross_signing_keys.self_signing_key),
+        (SecretName::CrossSigningUserSigningKey, &secrets.cross_signing_keys.user_signing_key),
+        (SecretName::RecoveryKey, &secrets.backup_recovery_key),
+    ];
+
+    for (name, secret) in named_secrets {
+        let Some(secret) = secret else {
+            continue;
+        };
+
+        match provider.store_secret(&name, secret).await {
+            Ok(()) => debug!(%name, "Stored the secret in the secret storage provider"),
+            Err(err) => {
+                warn!(%name, "Couldn't store the secret in the secret storage provider: {err}")
+            }
+        }
+    }
+
+    secrets.backup_recovery_key.zeroize();
+}
+
+/// Retrieve the well-known secrets from the [`SecretStorageProvider`] of the
+/// client.
+///
+/// Returns `None` if there is no provider, or if it failed.
+pub(crate) async fn load_secrets(client: &Client) -> Option<ProviderSecrets> {
+    let provider = client.encryption().secret_storage().provider()?;
+
+    let mut secrets = ProviderSecrets {
+        cross_signing_keys: CrossSigningKeyExport::default(),
+        backup_recovery_key: None,
+    };
+
+    let slots = [
+        (SecretName::CrossSigningMasterKey, &mut secrets.cross_signing_keys.master_key),
+        (SecretName::CrossSigningSelfSigningKey, &mut secrets.cross_signing_keys.self_signing_key),
+        (SecretName::CrossSigningUserSigningKey, &mut secrets.cross_signing_keys.user_signing_key),
+        (SecretName::RecoveryKey, &mut secrets.backup_recovery_key),
+    ];
+
+    for (name, slot) in slots {
+        match provider.retrieve_secret(&name).await {
+            Ok(secret) => *slot = secret,
+            Err(err) => {
+                warn!(%name, "Couldn't retrieve the secret from the secret storage provider: {err}");
+                return None;
+            }
+        }
+    }
+
+    Some(secrets)
+}
@@ -30,7 +30,7 @@ use tracing::{
 };
 use zeroize::Zeroize;
 
-use super::{DecryptionError, Result};
+use super::{provider, DecryptionError, Result};
 use crate::Client;
 
 #[cfg_attr(doc, aquamarine::aquamarine)]
@@ -293,24 +293,6 @@ impl SecretStore {
         Ok(())
     }
 
-    async fn maybe_enable_backups(&self) -> Result<()> {
-        if let Some(mut secret) = self.get_secret(SecretName::RecoveryKey).await? {
-            let ret = self.client.encryption().backups().maybe_enable_backups(&secret).await;
-
-            if let Err(e) = &ret {
-                warn!("Could not enable backups from secret storage: {e:?}");
-            }
-
-            secret.zeroize();
-
-            Ok(ret.map(|_| ())?)
-        } else {
-            info!("No backup recovery key found.");
-
-            Ok(())
-        }
-    }
-
     /// Retrieve and store well-known secrets locally
     ///
     /// This method retrieves and stores all well-known secrets from the account
@@ -361,15 +343,7 @@ impl SecretStore {
     /// ```
     ///
     /// [`Device`]: crate::encryption::identities::Device
-    #[instrument(fields(user_id, device_id, cross_signing_status))]
     pub async fn import_secrets(&self) -> Result<()> {
-        let olm_machine = self.client.olm_machine().await;
-        let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
-
-        Span::current()
-            .record("user_id", display(olm_machine.user_id()))
-            .record("device_id", display(olm_machine.device_id()));
-
         info!("Fetching the private cross-signing keys from the secret store");
 
         // Get all our private cross-signing keys from the secret store.
@@ -377,49 +351,44 @@ impl SecretStore {
 
         info!(cross_signing_keys = ?export, "Received the cross signing keys from the server");
 
-        // We need to ensure that we have the public parts of the cross-signing keys,
-        // those are represented as the `OwnUserIdentity` struct. The public
-        // parts from the server are compared to the public parts re-derived from the
-        // private parts. We will only import the private parts of the cross-signing
-        // keys if they match to the public parts, otherwise we would risk
-        // importing some stale cross-signing keys leftover in the secret store.
-        let (request_id, request) = olm_machine.query_keys_for_users([olm_machine.user_id()]);
-        self.client.keys_query(&request_id, request.device_keys).await?;
+        let backup_recovery_key = self.get_secret(SecretName::RecoveryKey).await?;
 
-        // Let's now try to import our private cross-signing keys.
-        let status = olm_machine.import_cross_signing_keys(export).await?;
+        import_known_secrets(&self.client, export, backup_recovery_key).await?;
+        provider::save_known_secrets(&self.client).await;
 
-        Span::current().record("cross_signing_status", debug(&status));
+        Ok(())
+    }
 
-        info!("Done importing the cross signing keys");
+    pub(super) async fn export_secrets(&self) -> Result<()> {
+        self.put_local_cross_signing_keys().await?;
 
-        if status.has_self_signing {
-            info!("Successfully imported the self-signing key, attempting to sign our own device");
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
 
-            // Now that we successfully imported them, the self-signing key can be used to
-            // verify our own device so other devices and user identities trust
-            // it if the trust our user identity.
-            if let Some(own_device) = self.client.encryption().get_own_device().await? {
-                own_device.verify().await?;
+        let backup_keys = olm_machine.backup_machine().get_backup_keys().await?;
 
-                // Another /keys/query request to ensure that the signatures we uploaded using
-                // `own_device.verify()` are attached to the `Device` we have in storage.
-                let (request_id, request) =
-                    olm_machine.query_keys_for_users([olm_machine.user_id()]);
-                self.client.keys_query(&request_id, request.device_keys).await?;
+        if let Some(backup_recovery_key) = backup_keys.decryption_key {
+            let mut key = backup_recovery_key.to_base64();
+            self.put_secret(SecretName::RecoveryKey, &key).await?;
 
-                info!("Successfully signed our own device, the device is now verified");
-            } else {
-                error!("Couldn't find our own device in the store");
-            }
+            key.zeroize();
         }
 
-        self.maybe_enable_backups().await?;
+        provider::save_known_secrets(&self.client).await;
 
         Ok(())
     }
 
-    pub(super) async fn export_secrets(&self) -> Result<()> {
+    /// Store the private cross-signing keys of the current device in the
+    /// secret storage, replacing the ones that may already be there.
+    pub(crate) async fn export_cross_signing_keys(&self) -> Result<()> {
+        self.put_local_cross_signing_keys().await?;
+        provider::save_known_secrets(&self.client).await;
+
+        Ok(())
+    }
+
+    async fn put_local_cross_signing_keys(&self) -> Result<()> {
         let olm_machine = self.client.olm_machine().await;
         let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
 
@@ -427,15 +396,6 @@ impl SecretStore {
             self.put_cross_signing_keys(cross_signing_keys).await?;
         }
 
-        let backup_keys = olm_machine.backup_machine().get_backup_keys().await?;
-
-        if let Some(backup_recovery_key) = backup_keys.decryption_key {
-            let mut key = backup_recovery_key.to_base64();
-            self.put_secret(SecretName::RecoveryKey, &key).await?;
-
-            key.zeroize();
-        }
-
         Ok(())
     }
 }
@@ -445,3 +405,75 @@ impl fmt::Debug for SecretStore {
         f.debug_struct("SecretStore").field("key", &self.key).finish_non_exhaustive()
     }
 }
+
+/// Import the private cross-signing keys and the backup recovery key into the
+/// client, wherever they were retrieved from.
+#[instrument(skip_all, fields(user_id, device_id, cross_signing_status))]
+pub(crate) async fn import_known_secrets(
+    client: &Client,
+    export: CrossSigningKeyExport,
+    backup_recovery_key: Option<String>,
+) -> Result<()> {
+    let olm_machine = client.olm_machine().await;
+    let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+    Span::current()
+        .record("user_id", display(olm_machine.user_id()))
+        .record("device_id", display(olm_machine.device_id()));
+
+    // We need to ensure that we have the public parts of the cross-signing keys,
+    // those are represented as the `OwnUserIdentity` struct. The public
+    // parts from the server are compared to the public parts re-derived from the
+    // private parts. We will only import the private parts of the cross-signing
+    // keys if they match to the public parts, otherwise we would risk
+    // importing some stale cross-signing keys leftover in the secret store.
+    let (request_id, request) = olm_machine.query_keys_for_users([olm_machine.user_id()]);
+    client.keys_query(&request_id, request.device_keys).await?;
+
+    // Let's now try to import our private cross-signing keys.
+    let status = olm_machine.import_cross_signing_keys(export).await?;
+
+    Span::current().record("cross_signing_status", debug(&status));
+
+    info!("Done importing the cross signing keys");
+
+    if status.has_self_signing {
+        info!("Successfully imported the self-signing key, attempting to sign our own device");
+
+        // Now that we successfully imported them, the self-signing key can be used to
+        // verify our own device so other devices and user identities trust
+        // it if the trust our user identity.
+        if let Some(own_device) = client.encryption().get_own_device().await? {
+            own_device.verify().await?;
+
+            // Another /keys/query request to ensure that the signatures we uploaded using
+            // `own_device.verify()` are attached to the `Device` we have in storage.
+            let (request_id, request) = olm_machine.query_keys_for_users([olm_machine.user_id()]);
+            client.keys_query(&request_id, request.device_keys).await?;
+
+            info!("Successfully signed our own device, the device is now verified");
+        } else {
+            error!("Couldn't find our own device in the store");
+        }
+    }
+
+    maybe_enable_backups(client, backup_recovery_key).await
+}
+
+async fn maybe_enable_backups(client: &Client, backup_recovery_key: Option<String>) -> Result<()> {
+    if let Some(mut secret) = backup_recovery_key {
+        let ret = client.encryption().backups().maybe_enable_backups(&secret).await;
+
+        if let Err(e) = &ret {
+            warn!("Could not enable backups from secret storage: {e:?}");
+        }
+
+        secret.zeroize();
+
+        Ok(ret.map(|_| ())?)
+    } else {
+        info!("No backup recovery key found.");
+
+        Ok(())
+    }
+}
@@ -342,12 +342,32 @@ impl BackupDownloadTaskListenerState {
         };
 
         // If backups aren't enabled, there's no point in trying to download a room key.
+        // Remember it instead, so it gets downloaded once backups are enabled.
         if !client.encryption().backups().are_enabled().await {
             debug!(
                 ?download_request,
                 "Not performing backup download because backups are not enabled"
             );
 
+            if !machine
+                .is_room_key_available(download_request.event.cast_ref(), &download_request.room_id)
+                .await
+                .unwrap_or(false)
+            {
+                if let Err(e) = client
+                    .encryption()
+                    .backups()
+                    .add_pending_utd_session(
+                        machine,
+                        &download_request.room_id,
+                        &download_request.megolm_session_id,
+                    )
+                    .await
+                {
+                    warn!(?download_request, "Couldn't remember the room key to download: {e:?}");
+                }
+            }
+
             return false;
         }
 
@@ -0,0 +1,241 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Enrichment of the events received from the homeserver with metadata
+//! computed by the embedder of the SDK.
+//!
+//! An [`EventEnricher`] registered with [`Client::add_event_enricher`] is
+//! called for every timeline event of the joined and left rooms received in a
+//! sync response, after it has been decrypted. The value it returns is
+//! attached to the event in its [`SyncTimelineEvent::enrichments`], under the
+//! key of the enricher, before the event is delivered to the event handlers,
+//! the event cache and the timelines.
+//!
+//! This is useful to compute once the metadata that would otherwise be
+//! computed every time the event is displayed, like the language of a message
+//! or the classification of the links it contains.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use matrix_sdk_base::{deserialized_responses::SyncTimelineEvent, sync::RoomUpdates};
+use tracing::{error, instrument};
+
+use crate::{Client, Room};
+
+/// A plugin computing metadata for the events of a room.
+///
+/// # Examples
+///
+/// ```no_run
+/// use matrix_sdk::{
+///     async_trait, deserialized_responses::SyncTimelineEvent, enrichment::EventEnricher,
+///     Room,
+/// };
+/// use ruma::events::{AnySyncMessageLikeEvent, AnySyncTimelineEvent};
+/// use serde_json::json;
+///
+/// struct LinkCounter;
+///
+/// #[async_trait]
+/// impl EventEnricher for LinkCounter {
+///     fn key(&self) -> &str {
+///         "org.example.link_count"
+///     }
+///
+///     async fn enrich(
+///         &self,
+///         _room: &Room,
+///         event: &SyncTimelineEvent,
+///     ) -> Option<serde_json::Value> {
+///         let AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+///             message,
+///         )) = event.raw().deserialize().ok()?
+///         else {
+///             return None;
+///         };
+///         let body = message.as_original()?.content.body();
+///         let count = body.split_whitespace().filter(|word| word.starts_with("https://")).count();
+///
+///         Some(json!(count))
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait EventEnricher: Send + Sync {
+    /// The key under which the metadata computed by this enricher is stored
+    /// in the [`SyncTimelineEvent::enrichments`] of an event.
+    ///
+    /// It should be namespaced, using the Java package naming convention, to
+    /// avoid clashes between enrichers.
+    fn key(&self) -> &str;
+
+    /// Compute the metadata of the given event of the given room.
+    ///
+    /// Returns `None` if this enricher has nothing to attach to this event.
+    async fn enrich(&self, room: &Room, event: &SyncTimelineEvent) -> Option<serde_json::Value>;
+}
+
+impl Client {
+    /// Register an enricher, that will attach metadata to the timeline events
+    /// received in sync responses, in back-paginations of the event cache, and
+    /// fetched with [`Room::event()`] or [`Room::event_with_context()`].
+    ///
+    /// The enrichers are called concurrently for each event, and the event
+    /// is only delivered to the event handlers and the event cache once all
+    /// the enrichers are done with it, so they should be reasonably fast.
+    ///
+    /// The metadata can then be read from the [`SyncTimelineEvent::enrichments`]
+    /// of the events, or by adding an [`EventEnrichments`] argument to an event
+    /// handler.
+    ///
+    /// [`EventEnrichments`]: crate::deserialized_responses::EventEnrichments
+    pub fn add_event_enricher(&self, enricher: impl EventEnricher + 'static) {
+        self.inner.event_enrichers.write().unwrap().push(Arc::new(enricher));
+    }
+
+    /// Attach the metadata of the registered enrichers to the timeline events
+    /// of the joined and left rooms of a sync response.
+    #[instrument(skip_all)]
+    pub(crate) async fn enrich_sync_response(&self, rooms: &mut RoomUpdates) {
+        let enrichers = self.inner.event_enrichers.read().unwrap().clone();
+
+        if enrichers.is_empty() {
+            return;
+        }
+
+        let timelines = rooms
+            .join
+            .iter_mut()
+            .map(|(room_id, update)| (room_id, &mut update.timeline.events))
+            .chain(
+                rooms
+                    .leave
+                    .iter_mut()
+                    .map(|(room_id, update)| (room_id, &mut update.timeline.events)),
+            );
+
+        for (room_id, events) in timelines {
+            let Some(room) = self.get_room(room_id) else {
+                error!(?room_id, "Can't enrich events, room not found");
+                continue;
+            };
+
+            enrich_events(&enrichers, &room, events).await;
+        }
+    }
+
+    /// Attach the metadata of the registered enrichers to the given events of
+    /// a room, that weren't received in a sync response.
+    #[instrument(skip_all, fields(room_id = ?room.room_id()))]
+    pub(crate) async fn enrich_events(&self, room: &Room, events: &mut [SyncTimelineEvent]) {
+        let enrichers = self.inner.event_enrichers.read().unwrap().clone();
+        enrich_events(&enrichers, room, events).await;
+    }
+}
+
+/// Attach the metadata of the given enrichers to the given events of a room.
+async fn enrich_events(
+    enrichers: &[Arc<dyn EventEnricher>],
+    room: &Room,
+    events: &mut [SyncTimelineEvent],
+) {
+    if enrichers.is_empty() {
+        return;
+    }
+
+    for event in events {
+        let values = {
+            let event = &*event;
+            join_all(enrichers.iter().map(|enricher| enricher.enrich(room, event))).await
+        };
+
+        for (enricher, value) in enrichers.iter().zip(values) {
+            if let Some(value) = value {
+                event.enrichments.insert(enricher.key(), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use matrix_sdk_base::deserialized_responses::{EventEnrichments, SyncTimelineEvent};
+    use matrix_sdk_test::{
+        async_test, sync_timeline_event, JoinedRoomBuilder, SyncResponseBuilder,
+        DEFAULT_TEST_ROOM_ID,
+    };
+    use ruma::events::room::message::OriginalSyncRoomMessageEvent;
+    use serde_json::json;
+
+    use super::EventEnricher;
+    use crate::{test_utils::logged_in_client, Room};
+
+    struct BodyLength;
+
+    #[async_trait]
+    impl EventEnricher for BodyLength {
+        fn key(&self) -> &str {
+            "org.example.body_length"
+        }
+
+        async fn enrich(
+            &self,
+            _room: &Room,
+            event: &SyncTimelineEvent,
+        ) -> Option<serde_json::Value> {
+            let content = event.raw().get_field::<serde_json::Value>("content").ok()??;
+            Some(json!(content["body"].as_str()?.len()))
+        }
+    }
+
+    #[async_test]
+    async fn test_enrich_sync_response() {
+        let client = logged_in_client(None).await;
+        client.add_event_enricher(BodyLength);
+
+        let received = Arc::new(Mutex::new(None));
+        client.add_event_handler({
+            let received = received.clone();
+            move |_ev: OriginalSyncRoomMessageEvent, enrichments: EventEnrichments| async move {
+                *received.lock().unwrap() = Some(enrichments);
+            }
+        });
+
+        let response = SyncResponseBuilder::default()
+            .add_joined_room(JoinedRoomBuilder::default().add_timeline_event(
+                sync_timeline_event!({
+                    "content": { "body": "hello", "msgtype": "m.text" },
+                    "event_id": "$message",
+                    "origin_server_ts": 152037280,
+                    "sender": "@alice:example.org",
+                    "type": "m.room.message",
+                }),
+            ))
+            .build_sync_response();
+        let response = client.process_sync(response).await.unwrap();
+
+        // The metadata is attached to the events of the sync response…
+        let event = &response.rooms.join[*DEFAULT_TEST_ROOM_ID].timeline.events[0];
+        assert_eq!(event.enrichments.get::<usize>("org.example.body_length").unwrap().unwrap(), 5);
+
+        // … and given to the event handlers.
+        let enrichments = received.lock().unwrap().take().unwrap();
+        assert_eq!(enrichments.get_raw("org.example.body_length"), Some(&json!(5)));
+    }
+}
@@ -14,7 +14,7 @@
 
 //! Error conditions.
 
-use std::{io::Error as IoError, sync::Arc, time::Duration};
+use std::{fmt, io::Error as IoError, sync::Arc, time::Duration};
 
 use as_variant::as_variant;
 use http::StatusCode;
@@ -22,13 +22,16 @@ use http::StatusCode;
 use matrix_sdk_base::crypto::ScanError;
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::crypto::{
-    CryptoStoreError, DecryptorError, KeyExportError, MegolmError, OlmError,
+    vodozemac::Ed25519PublicKey, CryptoStoreError, DecryptorError, KeyExportError, MegolmError,
+    OlmError,
 };
 use matrix_sdk_base::{
     event_cache::store::EventCacheStoreError, Error as SdkBaseError, QueueWedgeError, RoomState,
     StoreError,
 };
 use reqwest::Error as ReqwestError;
+#[cfg(feature = "e2e-encryption")]
+use ruma::OwnedDeviceId;
 use ruma::{
     api::{
         client::{
@@ -39,13 +42,17 @@ use ruma::{
     },
     events::tag::InvalidUserTagName,
     push::{InsertPushRuleError, RemovePushRuleError},
-    IdParseError,
+    IdParseError, OwnedUserId,
 };
 use serde_json::Error as JsonError;
 use thiserror::Error;
 use url::ParseError as UrlParseError;
 
-use crate::{event_cache::EventCacheError, media::MediaError, store_locks::LockStoreError};
+use crate::{
+    authentication::session_store::SessionStoreError, event_cache::EventCacheError,
+    media::MediaError, room::privacy_settings::JoinRuleError, room_creation::RoomCreationError,
+    store_locks::LockStoreError,
+};
 
 /// Result type of the matrix-sdk.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -84,6 +91,78 @@ impl RumaApiError {
     }
 }
 
+/// How the homeserver invalidated the access token of the session, as
+/// reported by an `M_UNKNOWN_TOKEN` error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenInvalidation {
+    /// The session was soft logged out.
+    ///
+    /// The device still exists, so the session can be resumed by
+    /// authenticating again on the same device, keeping the local data and
+    /// the end-to-end encryption state.
+    SoftLogout,
+
+    /// The device was logged out, the session can't be resumed.
+    LoggedOut,
+}
+
+/// The category of an [`Error`] or [`HttpError`], to present it to the user
+/// without matching on every error code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// The homeserver couldn't be reached.
+    Network,
+
+    /// The session isn't authenticated, or its access token is invalid.
+    Authentication,
+
+    /// The homeserver rate-limited the request.
+    RateLimited,
+
+    /// The homeserver failed to handle the request, or sent an invalid
+    /// response.
+    Server,
+
+    /// The request was rejected as invalid or not allowed, or the local data
+    /// doesn't allow to make it.
+    Validation,
+
+    /// An end-to-end encryption operation failed.
+    Crypto,
+
+    /// A local store failed.
+    Storage,
+
+    /// Any other error.
+    Other,
+}
+
+/// The ids of an HTTP request, to correlate the logs of the client with the
+/// logs of the homeserver.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestIds {
+    /// The id generated by the client for the request, which is recorded in
+    /// the `request_id` field of the tracing span of the request.
+    pub client_request_id: String,
+
+    /// The id of the request reported by the homeserver, or by a proxy in
+    /// front of it, in the `X-Request-Id` header of the response.
+    pub server_request_id: Option<String>,
+}
+
+impl fmt::Display for RequestIds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "client request id: {}", self.client_request_id)?;
+
+        if let Some(server_request_id) = &self.server_request_id {
+            write!(f, ", server request id: {server_request_id}")?;
+        }
+
+        Ok(())
+    }
+}
+
 /// An HTTP error, representing either a connection error or an error while
 /// converting the raw HTTP response into a Matrix response.
 #[derive(Error, Debug)]
@@ -108,6 +187,19 @@ pub enum HttpError {
     /// Error while refreshing the access token.
     #[error(transparent)]
     RefreshToken(RefreshTokenError),
+
+    /// An error in the response of the homeserver, with the ids of the
+    /// request.
+    ///
+    /// The accessors of [`HttpError`] look through this variant, use
+    /// [`HttpError::without_request_ids()`] to match on the underlying error.
+    #[error("{source} ({request_ids})")]
+    WithRequestIds {
+        /// The underlying error.
+        source: Box<HttpError>,
+        /// The ids of the request.
+        request_ids: RequestIds,
+    },
 }
 
 #[rustfmt::skip] // stop rustfmt breaking the `<code>` in docs across multiple lines
@@ -118,7 +210,7 @@ impl HttpError {
     ///
     /// Otherwise, returns `None`.
     pub fn as_ruma_api_error(&self) -> Option<&RumaApiError> {
-        as_variant!(self, Self::Api(FromHttpResponseError::Server(e)) => e)
+        as_variant!(self.without_request_ids(), Self::Api(FromHttpResponseError::Server(e)) => e)
     }
 
     /// Shorthand for
@@ -130,6 +222,33 @@ impl HttpError {
 
 // Another impl block that's formatted with rustfmt.
 impl HttpError {
+    /// Attach the ids of the request to this error.
+    pub(crate) fn with_request_ids(self, request_ids: RequestIds) -> Self {
+        Self::WithRequestIds { source: Box::new(self), request_ids }
+    }
+
+    /// The ids of the request that failed, if the homeserver responded to it.
+    pub fn request_ids(&self) -> Option<&RequestIds> {
+        as_variant!(self, Self::WithRequestIds { request_ids, .. } => request_ids)
+    }
+
+    /// The underlying error, if `self` is
+    /// [`WithRequestIds`](Self::WithRequestIds), or `self` otherwise.
+    pub fn without_request_ids(&self) -> &HttpError {
+        match self {
+            Self::WithRequestIds { source, .. } => source.without_request_ids(),
+            _ => self,
+        }
+    }
+
+    /// Like [`HttpError::without_request_ids()`], but consumes the error.
+    pub fn into_without_request_ids(self) -> HttpError {
+        match self {
+            Self::WithRequestIds { source, .. } => source.into_without_request_ids(),
+            _ => self,
+        }
+    }
+
     /// If `self` is a server error in the `errcode` + `error` format expected
     /// for client-API endpoints, returns the error kind (`errcode`).
     pub fn client_api_error_kind(&self) -> Option<&ErrorKind> {
@@ -137,6 +256,16 @@ impl HttpError {
             .and_then(|e| as_variant!(&e.body, ErrorBody::Standard { kind, .. } => kind))
     }
 
+    /// If `self` is an `M_UNKNOWN_TOKEN` error, returns how the access token
+    /// was invalidated.
+    pub fn token_invalidation(&self) -> Option<TokenInvalidation> {
+        match self.client_api_error_kind()? {
+            ErrorKind::UnknownToken { soft_logout: true } => Some(TokenInvalidation::SoftLogout),
+            ErrorKind::UnknownToken { soft_logout: false } => Some(TokenInvalidation::LoggedOut),
+            _ => None,
+        }
+    }
+
     /// Try to destructure the error into an universal interactive auth info.
     ///
     /// Some requests require universal interactive auth, doing such a request
@@ -152,6 +281,44 @@ impl HttpError {
         self.as_ruma_api_error().and_then(as_variant!(RumaApiError::Uiaa))
     }
 
+    /// The [`ErrorCategory`] of this error.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            HttpError::Reqwest(_) => ErrorCategory::Network,
+            HttpError::NotClientRequest | HttpError::IntoHttp(_) => ErrorCategory::Validation,
+            HttpError::RefreshToken(_) => ErrorCategory::Authentication,
+            HttpError::Api(FromHttpResponseError::Server(api_error)) => match api_error {
+                RumaApiError::Uiaa(_) => ErrorCategory::Authentication,
+                RumaApiError::ClientApi(e) => match &e.body {
+                    ErrorBody::Standard { kind, .. } => match kind {
+                        ErrorKind::LimitExceeded { .. } => ErrorCategory::RateLimited,
+                        ErrorKind::UnknownToken { .. } | ErrorKind::MissingToken => {
+                            ErrorCategory::Authentication
+                        }
+                        _ => ErrorCategory::from_status_code(e.status_code),
+                    },
+                    _ => ErrorCategory::from_status_code(e.status_code),
+                },
+                RumaApiError::Other(e) => ErrorCategory::from_status_code(e.status_code),
+            },
+            // The response couldn't be deserialized.
+            HttpError::Api(_) => ErrorCategory::Server,
+            HttpError::WithRequestIds { source, .. } => source.category(),
+        }
+    }
+
+    /// Whether sending the same request again may succeed.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self.retry_kind(), RetryKind::Permanent)
+    }
+
+    /// How long the homeserver asked to wait before sending the request
+    /// again, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        as_variant!(self.retry_kind(), RetryKind::Transient { retry_after } => retry_after)
+            .flatten()
+    }
+
     /// Returns whether an HTTP error response should be qualified as transient
     /// or permanent.
     pub(crate) fn retry_kind(&self) -> RetryKind {
@@ -163,6 +330,7 @@ impl HttpError {
             HttpError::Api(FromHttpResponseError::Server(api_error)) => {
                 RetryKind::from_api_error(api_error)
             }
+            HttpError::WithRequestIds { source, .. } => source.retry_kind(),
             _ => RetryKind::Permanent,
         }
     }
@@ -177,11 +345,7 @@ pub(crate) enum RetryKind {
     /// The request failed with a "transient" error, meaning it could be retried
     /// either soon, or after a given amount of time expressed in
     /// `retry_after`.
-    Transient {
-        // This is used only for attempts to retry, so on non-wasm32 code (in the `native` module).
-        #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
-        retry_after: Option<Duration>,
-    },
+    Transient { retry_after: Option<Duration> },
 
     /// The request failed with a non-transient error, and retrying it would
     /// likely cause the same error again, so it's not worth retrying.
@@ -252,6 +416,20 @@ impl RetryKind {
     }
 }
 
+impl ErrorCategory {
+    /// Construct an [`ErrorCategory`] from a HTTP [`StatusCode`], when the
+    /// error code doesn't tell more.
+    fn from_status_code(status_code: StatusCode) -> Self {
+        match status_code {
+            StatusCode::TOO_MANY_REQUESTS => ErrorCategory::RateLimited,
+            StatusCode::UNAUTHORIZED => ErrorCategory::Authentication,
+            s if s.is_server_error() => ErrorCategory::Server,
+            s if s.is_client_error() => ErrorCategory::Validation,
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+
 /// Internal representation of errors.
 #[derive(Error, Debug)]
 #[non_exhaustive]
@@ -382,6 +560,52 @@ pub enum Error {
     /// An error happened during handling of a media subrequest.
     #[error(transparent)]
     Media(#[from] MediaError),
+
+    /// Refused to enable encryption in a public room without being forced to.
+    #[error("refusing to enable encryption in a public room")]
+    EncryptionInPublicRoom,
+
+    /// Tried to peek into a room whose history isn't world-readable.
+    #[error("the room isn't world-readable, so it can't be peeked into")]
+    NotWorldReadable,
+
+    /// Tried to share room keys with a device that isn't a known and verified
+    /// device of our own user.
+    #[error("the device isn't a known and verified device of our own user")]
+    NotVerifiedOwnDevice,
+
+    /// The session couldn't be loaded from or saved to a session store.
+    #[error(transparent)]
+    SessionStore(#[from] SessionStoreError),
+
+    /// The options to create a room are inconsistent.
+    #[error(transparent)]
+    RoomCreation(#[from] RoomCreationError),
+
+    /// The join rule of a room couldn't be updated.
+    #[error(transparent)]
+    JoinRule(#[from] JoinRuleError),
+
+    /// The order of a room tag isn't between 0 and 1.
+    #[error("the order of a tag must be between 0 and 1, got {0}")]
+    InvalidTagOrder(f64),
+
+    /// The homeserver returned another user ID than the one of the guest
+    /// account that was upgraded to a full account.
+    #[error("the registered user {registered} doesn't match the guest user {guest}")]
+    GuestUpgradeMismatch {
+        /// The user ID of the guest account.
+        guest: OwnedUserId,
+
+        /// The user ID returned by the homeserver.
+        registered: OwnedUserId,
+    },
+
+    /// The keys of the device of the restored session don't match the ones
+    /// known by the homeserver.
+    #[cfg(feature = "e2e-encryption")]
+    #[error(transparent)]
+    SessionMismatch(#[from] SessionMismatch),
 }
 
 #[rustfmt::skip] // stop rustfmt breaking the `<code>` in docs across multiple lines
@@ -409,6 +633,12 @@ impl Error {
         })
     }
 
+    /// If `self` is an `M_UNKNOWN_TOKEN` error, returns how the access token
+    /// was invalidated.
+    pub fn token_invalidation(&self) -> Option<TokenInvalidation> {
+        as_variant!(self, Self::Http).and_then(HttpError::token_invalidation)
+    }
+
     /// Try to destructure the error into an universal interactive auth info.
     ///
     /// Some requests require universal interactive auth, doing such a request
@@ -425,6 +655,156 @@ impl Error {
     }
 }
 
+// Another impl block that's formatted with rustfmt.
+impl Error {
+    /// The [`ErrorCategory`] of this error.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::Http(e) => e.category(),
+
+            Error::AuthenticationRequired | Error::MultipleSessionCallbacks => {
+                ErrorCategory::Authentication
+            }
+            #[cfg(feature = "experimental-oidc")]
+            Error::Oidc(_) => ErrorCategory::Authentication,
+
+            Error::InsufficientData
+            | Error::Identifier(_)
+            | Error::Url(_)
+            | Error::UserTagName(_)
+            | Error::WrongRoomState(_)
+            | Error::EncryptionInPublicRoom
+            | Error::NotWorldReadable
+            | Error::RoomCreation(_)
+            | Error::JoinRule(_)
+            | Error::InvalidTagOrder(_) => ErrorCategory::Validation,
+
+            #[cfg(feature = "e2e-encryption")]
+            Error::BadCryptoStoreState
+            | Error::NoOlmMachine
+            | Error::CryptoStoreError(_)
+            | Error::OlmError(_)
+            | Error::MegolmError(_)
+            | Error::DecryptorError(_)
+            | Error::SessionMismatch(_) => ErrorCategory::Crypto,
+            #[cfg(feature = "qrcode")]
+            Error::QrCodeScanError(_) => ErrorCategory::Crypto,
+            Error::BackupNotEnabled | Error::NotVerifiedOwnDevice => ErrorCategory::Crypto,
+
+            Error::SendQueueWedgeError(e) => match e {
+                QueueWedgeError::InsecureDevices { .. }
+                | QueueWedgeError::IdentityViolations { .. }
+                | QueueWedgeError::CrossVerificationRequired => ErrorCategory::Crypto,
+                QueueWedgeError::MissingMediaContent | QueueWedgeError::InvalidMimeType { .. } => {
+                    ErrorCategory::Validation
+                }
+                QueueWedgeError::GenericApiError { .. } => ErrorCategory::Other,
+            },
+
+            Error::StateStore(_)
+            | Error::EventCacheStore(_)
+            | Error::CrossProcessLockError(_)
+            | Error::SessionStore(_) => ErrorCategory::Storage,
+
+            Error::SerdeJson(_)
+            | Error::Io(_)
+            | Error::SlidingSync(_)
+            | Error::ConcurrentRequestFailed
+            | Error::UnknownError(_)
+            | Error::EventCache(_)
+            | Error::Media(_) => ErrorCategory::Other,
+        }
+    }
+
+    /// Whether trying the same operation again may succeed.
+    ///
+    /// Only errors of HTTP requests, when the homeserver couldn't be reached
+    /// or asked to try again later, are retryable.
+    pub fn is_retryable(&self) -> bool {
+        as_variant!(self, Self::Http).is_some_and(HttpError::is_retryable)
+    }
+
+    /// How long the homeserver asked to wait before trying again, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        as_variant!(self, Self::Http).and_then(HttpError::retry_after)
+    }
+
+    /// The ids of the HTTP request that failed, if the homeserver responded
+    /// to it.
+    pub fn request_ids(&self) -> Option<&RequestIds> {
+        as_variant!(self, Self::Http).and_then(HttpError::request_ids)
+    }
+}
+
+/// A mismatch between the keys of the device in the crypto store and the keys
+/// of the device known by the homeserver, found by
+/// [`Encryption::check_own_device_keys()`].
+///
+/// Using the session as is would lead to messages that other devices can't
+/// decrypt, so one of the [`SessionMismatch::recovery_options()`] should be
+/// applied instead.
+///
+/// [`Encryption::check_own_device_keys()`]: crate::encryption::Encryption::check_own_device_keys
+#[cfg(feature = "e2e-encryption")]
+#[derive(Error, Debug, Clone)]
+pub enum SessionMismatch {
+    /// The homeserver doesn't have any keys for the device, although the crypto
+    /// store says they were uploaded.
+    ///
+    /// This happens when the device was deleted, or when the crypto store
+    /// comes from another installation.
+    #[error("the homeserver doesn't have the keys of the device {device_id}")]
+    MissingDeviceKeys {
+        /// The ID of the device.
+        device_id: OwnedDeviceId,
+    },
+
+    /// The homeserver has different keys for the device.
+    ///
+    /// This happens when the crypto store was restored from a backup, or
+    /// belongs to another device with the same ID.
+    #[error("the keys of the device {device_id} don't match the keys known by the homeserver")]
+    MismatchedDeviceKeys {
+        /// The ID of the device.
+        device_id: OwnedDeviceId,
+        /// The Ed25519 key of the device in the crypto store.
+        local_ed25519: Ed25519PublicKey,
+        /// The Ed25519 key of the device known by the homeserver, if any.
+        server_ed25519: Option<Ed25519PublicKey>,
+    },
+}
+
+#[cfg(feature = "e2e-encryption")]
+impl SessionMismatch {
+    /// The ways to recover from this mismatch, the first one being the
+    /// recommended one.
+    pub fn recovery_options(&self) -> &'static [SessionMismatchRecovery] {
+        match self {
+            Self::MissingDeviceKeys { .. } => {
+                &[SessionMismatchRecovery::RotateDevice, SessionMismatchRecovery::WipeCryptoStore]
+            }
+            // The homeserver refuses new keys for a device that already has some.
+            Self::MismatchedDeviceKeys { .. } => &[SessionMismatchRecovery::RotateDevice],
+        }
+    }
+}
+
+/// A way to recover from a [`SessionMismatch`].
+#[cfg(feature = "e2e-encryption")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionMismatchRecovery {
+    /// Log out, delete the crypto store, and log in again to get a new device
+    /// with new keys.
+    RotateDevice,
+
+    /// Delete the crypto store, and restore the session again, so that new
+    /// keys are created and uploaded for the same device.
+    ///
+    /// The room keys of the deleted crypto store are lost, unless they were
+    /// backed up.
+    WipeCryptoStore,
+}
+
 /// Error for the room key importing functionality.
 #[cfg(feature = "e2e-encryption")]
 #[derive(Error, Debug)]
@@ -585,6 +965,9 @@ pub enum NotificationSettingsError {
     /// Unable to save the push rules
     #[error("Unable to save push rules")]
     UnableToSavePushRules,
+    /// Unable to access the rooms muted until a point in time.
+    #[error("Unable to access the temporary room mutes")]
+    UnableToAccessTemporaryMutes,
 }
 
 impl From<InsertPushRuleError> for NotificationSettingsError {
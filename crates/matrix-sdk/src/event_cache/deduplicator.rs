@@ -18,6 +18,7 @@
 use std::{collections::BTreeSet, fmt, sync::Mutex};
 
 use growable_bloom_filter::{GrowableBloom, GrowableBloomBuilder};
+use ruma::OwnedEventId;
 use tracing::warn;
 
 use super::room::events::{Event, RoomEvents};
@@ -137,6 +138,19 @@ impl Deduplicator {
             }
         })
     }
+
+    /// Check whether an event may have been seen already, without learning
+    /// about it.
+    ///
+    /// False positives are possible, while false negatives are impossible.
+    pub fn may_contain(&self, event_id: &OwnedEventId) -> bool {
+        self.bloom_filter.lock().unwrap().contains(event_id)
+    }
+
+    /// Learn about an event that's known not to be a duplicate.
+    pub fn learn(&self, event_id: &OwnedEventId) {
+        self.bloom_filter.lock().unwrap().insert(event_id);
+    }
 }
 
 /// Information about the scanned collection of events.
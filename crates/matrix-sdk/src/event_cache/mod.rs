@@ -28,7 +28,7 @@
 #![forbid(missing_docs)]
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, VecDeque},
     fmt::Debug,
     sync::{Arc, OnceLock},
 };
@@ -58,7 +58,7 @@ use tokio::sync::{
     broadcast::{error::RecvError, Receiver},
     Mutex, RwLock,
 };
-use tracing::{error, info, info_span, instrument, trace, warn, Instrument as _, Span};
+use tracing::{debug, error, info, info_span, instrument, trace, warn, Instrument as _, Span};
 
 use self::paginator::PaginatorError;
 use crate::{client::WeakClient, Client};
@@ -69,6 +69,7 @@ mod room;
 
 pub mod paginator;
 pub use pagination::{PaginationToken, RoomPagination, TimelineHasBeenResetWhilePaginating};
+pub(crate) use room::EventIsland;
 pub use room::RoomEventCache;
 
 /// An error observed in the [`EventCache`].
@@ -164,6 +165,7 @@ impl EventCache {
                 by_room: Default::default(),
                 drop_handles: Default::default(),
                 all_events: Default::default(),
+                memory_budget: Default::default(),
             }),
         }
     }
@@ -185,6 +187,24 @@ impl EventCache {
         self.inner.has_storage()
     }
 
+    /// Set the maximum number of events to keep in memory, across all the
+    /// rooms.
+    ///
+    /// When the budget is exceeded, the in-memory events of the least
+    /// recently viewed rooms are dropped; a room is viewed when it's
+    /// subscribed to, or back-paginated. Rooms with active subscribers are
+    /// never unloaded. The events are kept in storage, and transparently
+    /// reloaded the next time the room is viewed. New events received by sync
+    /// for an unloaded room are appended to storage without reloading it,
+    /// unless some of them may be duplicates of known events.
+    ///
+    /// This only has an effect if the storage has been enabled with
+    /// [`Self::enable_storage`], and is enforced after handling the next
+    /// sync response. `None` disables the budget, which is the default.
+    pub fn set_memory_budget(&self, max_events: Option<usize>) {
+        *self.inner.memory_budget.lock().unwrap() = max_events;
+    }
+
     /// Starts subscribing the [`EventCache`] to sync responses, if not done
     /// before.
     ///
@@ -235,7 +255,7 @@ impl EventCache {
     // store.
     #[cfg(any(test, feature = "testing"))]
     pub async fn empty_immutable_cache(&self) {
-        self.inner.all_events.write().await.events.clear();
+        self.inner.all_events.write().await.clear();
     }
 
     #[instrument(skip_all)]
@@ -355,6 +375,10 @@ impl EventCache {
 type AllEventsMap = BTreeMap<OwnedEventId, (OwnedRoomId, SyncTimelineEvent)>;
 type RelationsMap = BTreeMap<OwnedEventId, BTreeMap<OwnedEventId, RelationType>>;
 
+/// The maximum number of events kept in the [`AllEventsCache`]; past this
+/// size, the oldest events are evicted.
+const MAX_ALL_EVENTS: usize = 10_000;
+
 /// Cache wrapper containing both copies of received events and lists of event
 /// ids related to them.
 #[derive(Default, Clone)]
@@ -364,74 +388,91 @@ struct AllEventsCache {
     /// A cache of related event ids for an event id. The key is the original
     /// event id and the value a list of event ids related to it.
     relations: RelationsMap,
+    /// The ids of the events in `events`, from the least to the most recently
+    /// inserted, to evict the oldest ones first.
+    insertion_order: VecDeque<OwnedEventId>,
 }
 
 impl AllEventsCache {
     fn clear(&mut self) {
         self.events.clear();
         self.relations.clear();
+        self.insertion_order.clear();
     }
 
-    /// If the event is related to another one, its id is added to the relations
-    /// map.
-    fn append_related_event(&mut self, event: &SyncTimelineEvent) {
-        // Handle and cache events and relations.
-        let Ok(AnySyncTimelineEvent::MessageLike(ev)) = event.raw().deserialize() else {
+    /// Insert an event of the given room in the cache, along with its
+    /// relation, evicting the oldest events if the cache gets too large.
+    fn insert(&mut self, room_id: &RoomId, event: SyncTimelineEvent) {
+        let Some(event_id) = event.event_id() else {
             return;
         };
 
-        // Handle redactions separately, as their logic is slightly different.
-        if let AnySyncMessageLikeEvent::RoomRedaction(SyncRoomRedactionEvent::Original(ev)) = &ev {
-            if let Some(redacted_event_id) = ev.content.redacts.as_ref().or(ev.redacts.as_ref()) {
-                self.relations
-                    .entry(redacted_event_id.to_owned())
-                    .or_default()
-                    .insert(ev.event_id.to_owned(), RelationType::Replacement);
-            }
+        self.append_related_event(&event);
+
+        // An event can be inserted again, e.g. once it's been decrypted; it keeps its
+        // position in the eviction order then.
+        if self.events.insert(event_id.clone(), (room_id.to_owned(), event)).is_none() {
+            self.insertion_order.push_back(event_id);
+        }
+
+        while self.events.len() > MAX_ALL_EVENTS {
+            let Some(oldest) = self.insertion_order.pop_front() else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+    }
+
+    /// Remove all the events of the given room from the cache, e.g. when
+    /// the room's events have been unloaded from memory.
+    fn remove_room(&mut self, room_id: &RoomId) {
+        let event_ids = self
+            .events
+            .iter()
+            .filter(|(_, (event_room_id, _))| event_room_id == room_id)
+            .map(|(event_id, _)| event_id.clone())
+            .collect::<Vec<_>>();
+
+        if event_ids.is_empty() {
             return;
         }
 
-        let relationship = match ev.original_content() {
-            Some(AnyMessageLikeEventContent::RoomMessage(c)) => {
-                if let Some(relation) = c.relates_to {
-                    match relation {
-                        Relation::Replacement(replacement) => {
-                            Some((replacement.event_id, RelationType::Replacement))
-                        }
-                        Relation::Reply { in_reply_to } => {
-                            Some((in_reply_to.event_id, RelationType::Reference))
-                        }
-                        Relation::Thread(thread) => Some((thread.event_id, RelationType::Thread)),
-                        // Do nothing for custom
-                        _ => None,
-                    }
-                } else {
-                    None
+        for event_id in &event_ids {
+            self.remove(event_id);
+        }
+
+        self.insertion_order.retain(|event_id| self.events.contains_key(event_id));
+    }
+
+    /// Remove a single event and the relations it's part of.
+    ///
+    /// This doesn't update `insertion_order`, which is up to the caller.
+    fn remove(&mut self, event_id: &EventId) {
+        let Some((_, event)) = self.events.remove(event_id) else {
+            return;
+        };
+
+        self.relations.remove(event_id);
+
+        if let Some((related_to, _)) = relationship(&event) {
+            if let Some(related_event_ids) = self.relations.get_mut(&related_to) {
+                related_event_ids.remove(event_id);
+                if related_event_ids.is_empty() {
+                    self.relations.remove(&related_to);
                 }
             }
-            Some(AnyMessageLikeEventContent::PollResponse(c)) => {
-                Some((c.relates_to.event_id, RelationType::Reference))
-            }
-            Some(AnyMessageLikeEventContent::PollEnd(c)) => {
-                Some((c.relates_to.event_id, RelationType::Reference))
-            }
-            Some(AnyMessageLikeEventContent::UnstablePollResponse(c)) => {
-                Some((c.relates_to.event_id, RelationType::Reference))
-            }
-            Some(AnyMessageLikeEventContent::UnstablePollEnd(c)) => {
-                Some((c.relates_to.event_id, RelationType::Reference))
-            }
-            Some(AnyMessageLikeEventContent::Reaction(c)) => {
-                Some((c.relates_to.event_id, RelationType::Annotation))
-            }
-            _ => None,
+        }
+    }
+
+    /// If the event is related to another one, its id is added to the relations
+    /// map.
+    fn append_related_event(&mut self, event: &SyncTimelineEvent) {
+        let Some(event_id) = event.event_id() else {
+            return;
         };
 
-        if let Some(relationship) = relationship {
-            self.relations
-                .entry(relationship.0)
-                .or_default()
-                .insert(ev.event_id().to_owned(), relationship.1);
+        if let Some((related_to, relation_type)) = relationship(event) {
+            self.relations.entry(related_to).or_default().insert(event_id, relation_type);
         }
     }
 
@@ -482,6 +523,60 @@ impl AllEventsCache {
     }
 }
 
+/// Returns the id of the event the given event is related to, and how, if
+/// any.
+fn relationship(event: &SyncTimelineEvent) -> Option<(OwnedEventId, RelationType)> {
+    let Ok(AnySyncTimelineEvent::MessageLike(ev)) = event.raw().deserialize() else {
+        return None;
+    };
+
+    // Handle redactions separately, as their logic is slightly different.
+    if let AnySyncMessageLikeEvent::RoomRedaction(SyncRoomRedactionEvent::Original(ev)) = &ev {
+        return ev
+            .content
+            .redacts
+            .as_ref()
+            .or(ev.redacts.as_ref())
+            .map(|redacted_event_id| (redacted_event_id.to_owned(), RelationType::Replacement));
+    }
+
+    match ev.original_content() {
+        Some(AnyMessageLikeEventContent::RoomMessage(c)) => {
+            if let Some(relation) = c.relates_to {
+                match relation {
+                    Relation::Replacement(replacement) => {
+                        Some((replacement.event_id, RelationType::Replacement))
+                    }
+                    Relation::Reply { in_reply_to } => {
+                        Some((in_reply_to.event_id, RelationType::Reference))
+                    }
+                    Relation::Thread(thread) => Some((thread.event_id, RelationType::Thread)),
+                    // Do nothing for custom
+                    _ => None,
+                }
+            } else {
+                None
+            }
+        }
+        Some(AnyMessageLikeEventContent::PollResponse(c)) => {
+            Some((c.relates_to.event_id, RelationType::Reference))
+        }
+        Some(AnyMessageLikeEventContent::PollEnd(c)) => {
+            Some((c.relates_to.event_id, RelationType::Reference))
+        }
+        Some(AnyMessageLikeEventContent::UnstablePollResponse(c)) => {
+            Some((c.relates_to.event_id, RelationType::Reference))
+        }
+        Some(AnyMessageLikeEventContent::UnstablePollEnd(c)) => {
+            Some((c.relates_to.event_id, RelationType::Reference))
+        }
+        Some(AnyMessageLikeEventContent::Reaction(c)) => {
+            Some((c.relates_to.event_id, RelationType::Annotation))
+        }
+        _ => None,
+    }
+}
+
 struct EventCacheInner {
     /// A weak reference to the inner client, useful when trying to get a handle
     /// on the owning client.
@@ -516,6 +611,10 @@ struct EventCacheInner {
 
     /// Handles to keep alive the task listening to updates.
     drop_handles: OnceLock<Arc<EventCacheDropHandles>>,
+
+    /// The maximum number of events to keep in memory across all the rooms,
+    /// see [`EventCache::set_memory_budget`].
+    memory_budget: std::sync::Mutex<Option<usize>>,
 }
 
 impl EventCacheInner {
@@ -583,9 +682,68 @@ impl EventCacheInner {
         // Invited rooms.
         // TODO: we don't anything with `updates.invite` at this point.
 
+        self.enforce_memory_budget().await;
+
         Ok(())
     }
 
+    /// Unload the events of the least recently viewed rooms, until the number
+    /// of in-memory events fits in the memory budget.
+    ///
+    /// The events stay in storage, so this is a no-op if it's not enabled.
+    async fn enforce_memory_budget(&self) {
+        let Some(max_events) = *self.memory_budget.lock().unwrap() else {
+            return;
+        };
+
+        if !self.has_storage() {
+            return;
+        }
+
+        let rooms = self.by_room.read().await;
+
+        let mut num_events = 0;
+        let mut candidates = Vec::new();
+
+        for (room_id, room) in rooms.iter() {
+            let room_num_events = room.inner.state.read().await.events().num_events();
+            num_events += room_num_events;
+
+            // Rooms with subscribers are being displayed, don't pull the rug from under
+            // their feet.
+            if room_num_events > 0 && room.inner.sender.receiver_count() == 0 {
+                candidates.push((room.inner.last_viewed(), room_id, room_num_events, room));
+            }
+        }
+
+        if num_events <= max_events {
+            return;
+        }
+
+        // Rooms that have never been viewed come first, then from the least to the most
+        // recently viewed.
+        candidates.sort_by_key(|(last_viewed, ..)| *last_viewed);
+
+        for (_, room_id, room_num_events, room) in candidates {
+            if num_events <= max_events {
+                break;
+            }
+
+            if room.inner.state.write().await.unload() {
+                self.all_events.write().await.remove_room(room_id);
+                trace!(?room_id, room_num_events, "unloaded room events");
+                num_events -= room_num_events;
+            }
+        }
+
+        if num_events > max_events {
+            debug!(
+                num_events,
+                max_events, "couldn't unload enough rooms to fit in the memory budget"
+            );
+        }
+    }
+
     /// Return a room-specific view over the [`EventCache`].
     ///
     /// It may not be found, if the room isn't known to the client, in which
@@ -698,10 +856,10 @@ mod tests {
     use futures_util::FutureExt as _;
     use matrix_sdk_base::sync::{JoinedRoomUpdate, RoomUpdates, Timeline};
     use matrix_sdk_test::{async_test, event_factory::EventFactory};
-    use ruma::{event_id, room_id, serde::Raw, user_id};
+    use ruma::{event_id, room_id, serde::Raw, user_id, EventId};
     use serde_json::json;
 
-    use super::{EventCacheError, RoomEventCacheUpdate};
+    use super::{AllEventsCache, EventCacheError, RoomEventCacheUpdate, MAX_ALL_EVENTS};
     use crate::test_utils::{assert_event_matches_msg, logged_in_client};
 
     #[async_test]
@@ -871,6 +1029,44 @@ mod tests {
         assert!(event_cache.event(event_id).await.is_none());
     }
 
+    #[test]
+    fn test_all_events_cache_eviction() {
+        let room_id1 = room_id!("!galette:saucisse.bzh");
+        let room_id2 = room_id!("!crepe:saucisse.bzh");
+        let f = EventFactory::new().sender(user_id!("@ben:saucisse.bzh"));
+
+        let mut cache = AllEventsCache::default();
+
+        // A reaction in the first room, to an event of the second room.
+        let target = event_id!("$target");
+        cache.insert(room_id2, f.text_msg("hey").room(room_id2).event_id(target).into());
+        cache.insert(
+            room_id1,
+            f.reaction(target, "👍".to_owned())
+                .room(room_id1)
+                .event_id(event_id!("$reaction"))
+                .into(),
+        );
+        assert_eq!(cache.collect_related_events(target, None).len(), 1);
+
+        // Removing the first room removes the relation too.
+        cache.remove_room(room_id1);
+        assert!(cache.events.get(event_id!("$reaction")).is_none());
+        assert!(cache.events.get(target).is_some());
+        assert!(cache.relations.is_empty());
+        assert_eq!(cache.insertion_order.len(), 1);
+
+        // Past the maximum size, the oldest events are evicted first.
+        for i in 0..MAX_ALL_EVENTS {
+            let event_id = EventId::parse(format!("$ev{i}")).unwrap();
+            cache.insert(room_id1, f.text_msg("yo").room(room_id1).event_id(&event_id).into());
+        }
+        assert_eq!(cache.events.len(), MAX_ALL_EVENTS);
+        assert_eq!(cache.insertion_order.len(), MAX_ALL_EVENTS);
+        assert!(cache.events.get(target).is_none());
+        assert!(cache.events.get(event_id!("$ev0")).is_some());
+    }
+
     #[async_test]
     async fn test_add_initial_events() {
         // TODO: remove this test when the event cache uses its own persistent storage.
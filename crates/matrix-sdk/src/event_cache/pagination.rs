@@ -122,6 +122,11 @@ impl RoomPagination {
     async fn run_backwards_impl(&self, batch_size: u16) -> Result<Option<BackPaginationOutcome>> {
         const DEFAULT_WAIT_FOR_TOKEN_DURATION: Duration = Duration::from_secs(3);
 
+        // The events, and thus the gaps, may have been unloaded to respect the memory
+        // budget.
+        self.inner.state.write().await.load_if_unloaded().await?;
+        self.inner.mark_viewed();
+
         let prev_token = self.get_or_wait_for_token(Some(DEFAULT_WAIT_FOR_TOKEN_DURATION)).await;
 
         let prev_token = match prev_token {
@@ -141,6 +146,19 @@ impl RoomPagination {
         let PaginationResult { events, hit_end_of_timeline: reached_start } =
             paginator.paginate_backward(batch_size.into()).await?;
 
+        let mut sync_events: Vec<SyncTimelineEvent> = events
+            .iter()
+            // Reverse the order of the events as `/messages` has been called with `dir=b`
+            // (backward). The `RoomEvents` API expects the first event to be the oldest.
+            .rev()
+            .cloned()
+            .map(SyncTimelineEvent::from)
+            .collect();
+
+        if let Some(room) = self.inner.weak_room.get() {
+            room.client().enrich_events(&room, &mut sync_events).await;
+        }
+
         // Make sure the `RoomEvents` isn't updated while we are saving events from
         // backpagination.
         let mut state = self.inner.state.write().await;
@@ -172,17 +190,9 @@ impl RoomPagination {
             .with_events_mut(move |room_events| {
                 // Note: The chunk could be empty.
                 //
-                // If there's any event, they are presented in reverse order (i.e. the first one
+                // If there's any event, they have been reversed above (i.e. the first one
                 // should be prepended first).
 
-                let sync_events = events
-                    .iter()
-                    // Reverse the order of the events as `/messages` has been called with `dir=b`
-                    // (backward). The `RoomEvents` API expects the first event to be the oldest.
-                    .rev()
-                    .cloned()
-                    .map(SyncTimelineEvent::from);
-
                 let first_event_pos = room_events.events().next().map(|(item_pos, _)| item_pos);
 
                 // First, insert events.
@@ -82,6 +82,11 @@ impl RoomEvents {
         self.chunks.num_items() == 0
     }
 
+    /// Returns the number of events in the room.
+    pub fn num_events(&self) -> usize {
+        self.chunks.num_items()
+    }
+
     /// Clear all events.
     ///
     /// All events, all gaps, everything is dropped, move into the void, into
@@ -14,7 +14,11 @@
 
 //! All event cache types for a single room.
 
-use std::{collections::BTreeMap, fmt, sync::Arc};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    sync::Arc,
+};
 
 use events::Gap;
 use matrix_sdk_base::{
@@ -23,8 +27,11 @@ use matrix_sdk_base::{
     sync::{JoinedRoomUpdate, LeftRoomUpdate, Timeline},
 };
 use ruma::{
-    events::{relation::RelationType, AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent},
+    events::{
+        relation::RelationType, AnyRoomAccountDataEvent, AnyStateEvent, AnySyncEphemeralRoomEvent,
+    },
     serde::Raw,
+    time::Instant,
     EventId, OwnedEventId, OwnedRoomId,
 };
 use tokio::sync::{
@@ -73,7 +80,12 @@ impl RoomEventCache {
     pub async fn subscribe(
         &self,
     ) -> Result<(Vec<SyncTimelineEvent>, Receiver<RoomEventCacheUpdate>)> {
-        let state = self.inner.state.read().await;
+        let mut state = self.inner.state.write().await;
+
+        // The events may have been unloaded to respect the memory budget.
+        state.load_if_unloaded().await?;
+        self.inner.mark_viewed();
+
         let events = state.events().events().map(|(_position, item)| item.clone()).collect();
 
         Ok((events, self.inner.sender.subscribe()))
@@ -122,6 +134,70 @@ impl RoomEventCache {
         }
     }
 
+    /// Remember the events around a focused event, loaded with `/context` and
+    /// saved with [`Self::save_events`], as an island: they are not connected
+    /// to the linked chunk of the room, but [`Self::island`] can find them
+    /// again.
+    ///
+    /// Only the [`MAX_ISLANDS`] most recently used islands are kept.
+    pub(crate) async fn save_island(&self, event_id: OwnedEventId, island: EventIsland) {
+        let mut islands = self.inner.islands.write().await;
+
+        islands.retain(|(id, _)| *id != event_id);
+        islands.push_back((event_id, island));
+
+        if islands.len() > MAX_ISLANDS {
+            islands.pop_front();
+        }
+    }
+
+    /// Find the island around the given event, if it contains at least
+    /// `before` events before the event and `after` events after it, or all
+    /// the events until the start or the end of the room.
+    ///
+    /// Returns the event, the events before it in reverse chronological order,
+    /// the events after it in chronological order, and the island.
+    pub(crate) async fn island(
+        &self,
+        event_id: &EventId,
+        before: usize,
+        after: usize,
+    ) -> Option<(SyncTimelineEvent, Vec<SyncTimelineEvent>, Vec<SyncTimelineEvent>, EventIsland)>
+    {
+        let island = {
+            let mut islands = self.inner.islands.write().await;
+            let index = islands.iter().position(|(id, _)| id == event_id)?;
+
+            // Keep the island around longer, since it's being used.
+            let entry = islands.remove(index)?;
+            let island = entry.1.clone();
+            islands.push_back(entry);
+
+            island
+        };
+
+        let enough_before =
+            island.events_before.len() >= before || island.prev_batch_token.is_none();
+        let enough_after = island.events_after.len() >= after || island.next_batch_token.is_none();
+        if !enough_before || !enough_after {
+            return None;
+        }
+
+        let event = self.event(event_id).await?;
+
+        let mut events_before = Vec::with_capacity(island.events_before.len());
+        for event_id in &island.events_before {
+            events_before.push(self.event(event_id).await?);
+        }
+
+        let mut events_after = Vec::with_capacity(island.events_after.len());
+        for event_id in &island.events_after {
+            events_after.push(self.event(event_id).await?);
+        }
+
+        Some((event, events_before, events_after, island))
+    }
+
     /// Clear all the storage for this [`RoomEventCache`].
     ///
     /// This will get rid of all the events from the linked chunk and persisted
@@ -132,6 +208,7 @@ impl RoomEventCache {
 
         // Clear the (temporary) events mappings.
         self.inner.all_events.write().await.clear();
+        self.inner.islands.write().await.clear();
 
         // Reset the paginator.
         // TODO: properly stop any ongoing back-pagination.
@@ -149,11 +226,8 @@ impl RoomEventCache {
     // there'll be no distinction between the linked chunk and the separate
     // cache. There is a discussion in https://github.com/matrix-org/matrix-rust-sdk/issues/3886.
     pub(crate) async fn save_event(&self, event: SyncTimelineEvent) {
-        if let Some(event_id) = event.event_id() {
-            let mut cache = self.inner.all_events.write().await;
-
-            cache.append_related_event(&event);
-            cache.events.insert(event_id, (self.inner.room_id.clone(), event));
+        if event.event_id().is_some() {
+            self.inner.all_events.write().await.insert(&self.inner.room_id, event);
         } else {
             warn!("couldn't save event without event id in the event cache");
         }
@@ -168,9 +242,8 @@ impl RoomEventCache {
     pub(crate) async fn save_events(&self, events: impl IntoIterator<Item = SyncTimelineEvent>) {
         let mut cache = self.inner.all_events.write().await;
         for event in events {
-            if let Some(event_id) = event.event_id() {
-                cache.append_related_event(&event);
-                cache.events.insert(event_id, (self.inner.room_id.clone(), event));
+            if event.event_id().is_some() {
+                cache.insert(&self.inner.room_id, event);
             } else {
                 warn!("couldn't save event without event id in the event cache");
             }
@@ -184,6 +257,32 @@ impl RoomEventCache {
     }
 }
 
+/// The maximum number of islands kept by a [`RoomEventCache`].
+const MAX_ISLANDS: usize = 10;
+
+/// The events around a focused event, loaded with `/context`, that are not
+/// connected to the linked chunk of the room.
+#[derive(Clone, Debug)]
+pub(crate) struct EventIsland {
+    /// The IDs of the events before the focused event, in reverse
+    /// chronological order.
+    pub events_before: Vec<OwnedEventId>,
+
+    /// The IDs of the events after the focused event, in chronological order.
+    pub events_after: Vec<OwnedEventId>,
+
+    /// The token to paginate backwards from the island, `None` if it reaches
+    /// the start of the room.
+    pub prev_batch_token: Option<String>,
+
+    /// The token to paginate forwards from the island, `None` if it reaches
+    /// the end of the room.
+    pub next_batch_token: Option<String>,
+
+    /// The state events that came with the island.
+    pub state: Vec<Raw<AnyStateEvent>>,
+}
+
 /// The (non-cloneable) details of the `RoomEventCache`.
 pub(super) struct RoomEventCacheInner {
     /// The room id for this room.
@@ -201,6 +300,10 @@ pub(super) struct RoomEventCacheInner {
     /// [`RoomEventCacheInner`] instances.
     all_events: Arc<RwLock<AllEventsCache>>,
 
+    /// The islands of events loaded around focused events, with the ID of the
+    /// focused event, from the least to the most recently used.
+    islands: RwLock<VecDeque<(OwnedEventId, EventIsland)>>,
+
     /// A notifier that we received a new pagination token.
     pub pagination_batch_token_notifier: Notify,
 
@@ -213,6 +316,16 @@ pub(super) struct RoomEventCacheInner {
     /// paginator is only used for queries that interact with the actual event
     /// cache.
     pub paginator: Paginator<WeakRoom>,
+
+    /// The room, used to enrich the events received from back-paginations.
+    pub weak_room: WeakRoom,
+
+    /// The last time the events of this room have been subscribed to or
+    /// paginated, used to pick the rooms to unload when the memory budget of
+    /// the event cache is exceeded.
+    ///
+    /// `None` if the room has never been viewed.
+    last_viewed: std::sync::Mutex<Option<Instant>>,
 }
 
 impl RoomEventCacheInner {
@@ -230,12 +343,26 @@ impl RoomEventCacheInner {
             room_id: weak_room.room_id().to_owned(),
             state: RwLock::new(state),
             all_events: all_events_cache,
+            islands: Default::default(),
             sender,
             pagination_batch_token_notifier: Default::default(),
-            paginator: Paginator::new(weak_room),
+            paginator: Paginator::new(weak_room.clone()),
+            weak_room,
+            last_viewed: Default::default(),
         }
     }
 
+    /// Remember that the events of this room have just been viewed.
+    pub(super) fn mark_viewed(&self) {
+        *self.last_viewed.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// The last time the events of this room have been viewed, see
+    /// [`Self::mark_viewed`].
+    pub(super) fn last_viewed(&self) -> Option<Instant> {
+        *self.last_viewed.lock().unwrap()
+    }
+
     fn handle_account_data(&self, account_data: Vec<Raw<AnyRoomAccountDataEvent>>) {
         if account_data.is_empty() {
             return;
@@ -406,51 +533,68 @@ impl RoomEventCacheInner {
         // Add the previous back-pagination token (if present), followed by the timeline
         // events themselves.
         let sync_timeline_events_diffs = {
-            let (_, sync_timeline_events_diffs) = state
-                .with_events_mut(|room_events| {
-                    if let Some(prev_token) = &prev_batch {
-                        room_events.push_gap(Gap { prev_token: prev_token.clone() });
-                    }
-
-                    let added_unique_events = room_events.push_events(sync_timeline_events.clone());
-
-                    if !added_unique_events {
-                        debug!(
-                            "not storing previous batch token, because we deduplicated all new sync events"
-                        );
+            // If the events of the room have been unloaded to respect the memory budget,
+            // append the new ones to storage directly, rather than reloading all of them
+            // for a single sync. Nobody observes an unloaded room, so there's no update
+            // to send.
+            let appended_while_unloaded = state
+                .append_while_unloaded(
+                    prev_batch.as_ref().map(|prev_token| Gap { prev_token: prev_token.clone() }),
+                    &sync_timeline_events,
+                )
+                .await?;
 
+            let sync_timeline_events_diffs = if appended_while_unloaded {
+                Vec::new()
+            } else {
+                let (_, sync_timeline_events_diffs) = state
+                    .with_events_mut(|room_events| {
                         if let Some(prev_token) = &prev_batch {
-                            // Note: there can't be any race with another task touching the linked
-                            // chunk at this point, because we're using `with_events_mut` which
-                            // guards access to the data.
-                            trace!("removing gap we just inserted");
-
-                                // Find the gap that had the previous-batch token we inserted above.
-                            let prev_gap_id = room_events
-                                .rchunks()
-                                .find_map(|c| {
-                                    let gap = as_variant::as_variant!(c.content(), ChunkContent::Gap)?;
-                                    (gap.prev_token == *prev_token).then_some(c.identifier())
-                                })
-                                .expect("we just inserted the gap beforehand");
-
-                            room_events
-                                .replace_gap_at([], prev_gap_id)
-                                .expect("we obtained the valid position beforehand");
+                            room_events.push_gap(Gap { prev_token: prev_token.clone() });
                         }
-                    }
-                })
-                .await?;
+
+                        let added_unique_events =
+                            room_events.push_events(sync_timeline_events.clone());
+
+                        if !added_unique_events {
+                            debug!(
+                                "not storing previous batch token, because we deduplicated all new sync events"
+                            );
+
+                            if let Some(prev_token) = &prev_batch {
+                                // Note: there can't be any race with another task touching the
+                                // linked chunk at this point, because we're using
+                                // `with_events_mut` which guards access to the data.
+                                trace!("removing gap we just inserted");
+
+                                // Find the gap that had the previous-batch token we inserted
+                                // above.
+                                let prev_gap_id = room_events
+                                    .rchunks()
+                                    .find_map(|c| {
+                                        let gap = as_variant::as_variant!(
+                                            c.content(),
+                                            ChunkContent::Gap
+                                        )?;
+                                        (gap.prev_token == *prev_token).then_some(c.identifier())
+                                    })
+                                    .expect("we just inserted the gap beforehand");
+
+                                room_events
+                                    .replace_gap_at([], prev_gap_id)
+                                    .expect("we obtained the valid position beforehand");
+                            }
+                        }
+                    })
+                    .await?;
+
+                sync_timeline_events_diffs
+            };
 
             let mut all_events = self.all_events.write().await;
 
             for sync_timeline_event in sync_timeline_events {
-                if let Some(event_id) = sync_timeline_event.event_id() {
-                    all_events.append_related_event(&sync_timeline_event);
-                    all_events
-                        .events
-                        .insert(event_id.to_owned(), (self.room_id.clone(), sync_timeline_event));
-                }
+                all_events.insert(&self.room_id, sync_timeline_event);
             }
 
             sync_timeline_events_diffs
@@ -511,7 +655,7 @@ fn chunk_debug_string(content: &ChunkContent<SyncTimelineEvent, Gap>) -> String
 
 // Use a private module to hide `events` to this parent module.
 mod private {
-    use std::sync::Arc;
+    use std::{collections::BTreeSet, sync::Arc};
 
     use eyeball_im::VectorDiff;
     use matrix_sdk_base::{
@@ -523,14 +667,16 @@ mod private {
             },
             Event, Gap,
         },
-        linked_chunk::{LinkedChunk, LinkedChunkBuilder, RawChunk, Update},
+        linked_chunk::{
+            ChunkIdentifier, LinkedChunk, LinkedChunkBuilder, Position, RawChunk, Update,
+        },
     };
     use once_cell::sync::OnceCell;
     use ruma::{serde::Raw, OwnedRoomId, RoomId};
     use tracing::{error, instrument, trace};
 
     use super::{chunk_debug_string, events::RoomEvents};
-    use crate::event_cache::EventCacheError;
+    use crate::event_cache::{deduplicator::Deduplicator, EventCacheError};
 
     /// State for a single room's event cache.
     ///
@@ -549,6 +695,10 @@ mod private {
         /// The events of the room.
         events: RoomEvents,
 
+        /// Set if the in-memory events have been dropped to respect the
+        /// memory budget of the event cache, see [`Self::unload`].
+        unloaded: Option<UnloadedChunks>,
+
         /// Have we ever waited for a previous-batch-token to come from sync, in
         /// the context of pagination? We do this at most once per room,
         /// the first time we try to run backward pagination. We reset
@@ -556,6 +706,48 @@ mod private {
         pub waited_for_initial_prev_token: bool,
     }
 
+    /// What's kept about the persisted linked chunk of a room whose in-memory
+    /// events have been dropped, to append new events to it without reloading
+    /// it.
+    struct UnloadedChunks {
+        /// The identifier of the last chunk, after which new chunks are
+        /// linked.
+        last_chunk: ChunkIdentifier,
+
+        /// The identifier of the next chunk to create; higher than all the
+        /// identifiers in use.
+        next_chunk: u64,
+
+        /// Knows about the persisted events, to detect new events that may
+        /// be duplicates.
+        deduplicator: Deduplicator,
+    }
+
+    impl UnloadedChunks {
+        fn new(events: &RoomEvents) -> Self {
+            let last_chunk = events
+                .rchunks()
+                .next()
+                .expect("a linked chunk always has at least one chunk")
+                .identifier();
+            let next_chunk =
+                events.chunks().map(|chunk| chunk.identifier().index()).max().unwrap_or(0) + 1;
+            let deduplicator =
+                Deduplicator::with_initial_events(events.events().map(|(_pos, event)| event));
+
+            Self { last_chunk, next_chunk, deduplicator }
+        }
+
+        /// Link a new chunk after the last one, and return the identifiers of
+        /// the previous and the new chunks.
+        fn new_chunk(&mut self) -> (ChunkIdentifier, ChunkIdentifier) {
+            let previous = self.last_chunk;
+            self.last_chunk = ChunkIdentifier::new(self.next_chunk);
+            self.next_chunk += 1;
+            (previous, self.last_chunk)
+        }
+    }
+
     impl RoomEventCacheState {
         async fn try_reload_linked_chunk(
             room: &RoomId,
@@ -581,35 +773,146 @@ mod private {
             })?)
         }
 
+        /// Load the events of a room from storage, if it's been enabled.
+        async fn load_events(
+            room: &RoomId,
+            store: &OnceCell<EventCacheStoreLock>,
+        ) -> Result<RoomEvents, EventCacheError> {
+            let Some(store) = store.get() else {
+                return Ok(RoomEvents::default());
+            };
+
+            let locked = store.lock().await?;
+
+            // Try to reload a linked chunk from storage. If it fails, log the error and
+            // restart with a fresh, empty linked chunk.
+            let linked_chunk = match Self::try_reload_linked_chunk(room, &locked).await {
+                Ok(linked_chunk) => linked_chunk,
+                Err(err) => {
+                    error!("error when reloading a linked chunk from memory: {err}");
+
+                    // Clear storage for this room.
+                    locked.handle_linked_chunk_updates(room, vec![Update::Clear]).await?;
+
+                    // Restart with an empty linked chunk.
+                    None
+                }
+            };
+
+            Ok(RoomEvents::with_initial_chunks(linked_chunk))
+        }
+
         /// Create a new state, or reload it from storage if it's been enabled.
         pub async fn new(
             room: OwnedRoomId,
             store: Arc<OnceCell<EventCacheStoreLock>>,
         ) -> Result<Self, EventCacheError> {
-            let events = if let Some(store) = store.get() {
-                let locked = store.lock().await?;
+            let events = Self::load_events(&room, &store).await?;
 
-                // Try to reload a linked chunk from storage. If it fails, log the error and
-                // restart with a fresh, empty linked chunk.
-                let linked_chunk = match Self::try_reload_linked_chunk(&room, &locked).await {
-                    Ok(linked_chunk) => linked_chunk,
-                    Err(err) => {
-                        error!("error when reloading a linked chunk from memory: {err}");
+            Ok(Self { room, store, events, unloaded: None, waited_for_initial_prev_token: false })
+        }
 
-                        // Clear storage for this room.
-                        locked.handle_linked_chunk_updates(&room, vec![Update::Clear]).await?;
+        /// Whether the in-memory events have been dropped with
+        /// [`Self::unload`], and not reloaded since.
+        pub fn is_unloaded(&self) -> bool {
+            self.unloaded.is_some()
+        }
 
-                        // Restart with an empty linked chunk.
-                        None
-                    }
-                };
+        /// Drop the in-memory events of the room, keeping the persisted ones.
+        ///
+        /// This only happens if storage is enabled, as the events would be
+        /// lost otherwise. They're reloaded from storage by
+        /// [`Self::load_if_unloaded`].
+        ///
+        /// Returns whether the events have been dropped.
+        pub fn unload(&mut self) -> bool {
+            if self.unloaded.is_some() || self.store.get().is_none() {
+                return false;
+            }
 
-                RoomEvents::with_initial_chunks(linked_chunk)
-            } else {
-                RoomEvents::default()
+            self.unloaded = Some(UnloadedChunks::new(&self.events));
+            self.events = RoomEvents::default();
+
+            // The updates of the new, empty, linked chunk must not reach the storage, as
+            // they would conflict with the persisted chunks.
+            let _ = self.events.updates().take();
+
+            true
+        }
+
+        /// Reload the events of the room from storage, if they've been dropped
+        /// with [`Self::unload`].
+        pub async fn load_if_unloaded(&mut self) -> Result<(), EventCacheError> {
+            if self.unloaded.is_none() {
+                return Ok(());
+            }
+
+            trace!("reloading the unloaded events of the room from storage");
+
+            self.events = Self::load_events(&self.room, &self.store).await?;
+            self.unloaded = None;
+
+            Ok(())
+        }
+
+        /// Append a gap, then events, to the persisted linked chunk of a room
+        /// whose in-memory events have been dropped with [`Self::unload`],
+        /// without reloading it.
+        ///
+        /// Returns `false`, and doesn't touch storage, if the room isn't
+        /// unloaded, or if some of the events may be duplicates: these must
+        /// be deduplicated against the reloaded events.
+        pub async fn append_while_unloaded(
+            &mut self,
+            gap: Option<Gap>,
+            events: &[Event],
+        ) -> Result<bool, EventCacheError> {
+            let Some(unloaded) = &mut self.unloaded else {
+                return Ok(false);
             };
 
-            Ok(Self { room, store, events, waited_for_initial_prev_token: false })
+            let mut event_ids = BTreeSet::new();
+
+            for event in events {
+                // Events without an id are filtered out when pushing them to the in-memory
+                // events.
+                let Some(event_id) = event.event_id() else {
+                    return Ok(false);
+                };
+
+                if unloaded.deduplicator.may_contain(&event_id) || !event_ids.insert(event_id) {
+                    return Ok(false);
+                }
+            }
+
+            let mut updates = Vec::new();
+
+            if let Some(gap) = gap {
+                let (previous, new) = unloaded.new_chunk();
+                updates.push(Update::NewGapChunk {
+                    previous: Some(previous),
+                    new,
+                    next: None,
+                    gap,
+                });
+            }
+
+            for items in events.chunks(DEFAULT_CHUNK_CAPACITY) {
+                let (previous, new) = unloaded.new_chunk();
+                updates.push(Update::NewItemsChunk { previous: Some(previous), new, next: None });
+                updates
+                    .push(Update::PushItems { at: Position::new(new, 0), items: items.to_vec() });
+            }
+
+            for event_id in &event_ids {
+                unloaded.deduplicator.learn(event_id);
+            }
+
+            trace!("appending {} events to the storage of the unloaded room", events.len());
+
+            self.send_updates_to_store(updates).await?;
+
+            Ok(true)
         }
 
         /// Removes the bundled relations from an event, if they were present.
@@ -655,8 +958,15 @@ mod private {
         /// Propagate changes to the underlying storage.
         #[instrument(skip_all)]
         async fn propagate_changes(&mut self) -> Result<(), EventCacheError> {
-            let mut updates = self.events.updates().take();
+            let updates = self.events.updates().take();
+            self.send_updates_to_store(updates).await
+        }
 
+        /// Send updates of the linked chunk to the underlying storage.
+        async fn send_updates_to_store(
+            &self,
+            mut updates: Vec<Update<Event, Gap>>,
+        ) -> Result<(), EventCacheError> {
             if updates.is_empty() {
                 return Ok(());
             }
@@ -713,6 +1023,7 @@ mod private {
         pub async fn reset(&mut self) -> Result<(), EventCacheError> {
             self.events.reset();
             self.propagate_changes().await?;
+            self.unloaded = None;
             self.waited_for_initial_prev_token = false;
             Ok(())
         }
@@ -732,6 +1043,9 @@ mod private {
             &mut self,
             func: F,
         ) -> Result<(O, Vec<VectorDiff<SyncTimelineEvent>>), EventCacheError> {
+            // New events must be added to the persisted ones, not to an empty linked chunk.
+            self.load_if_unloaded().await?;
+
             let output = func(&mut self.events);
             self.propagate_changes().await?;
             let updates_as_vector_diffs = self.events.updates_as_vector_diffs();
@@ -831,9 +1145,10 @@ mod tests {
             relation::RelationType, room::message::RoomMessageEventContentWithoutRelation,
             AnySyncMessageLikeEvent, AnySyncTimelineEvent,
         },
-        room_id, user_id, RoomId,
+        room_id, user_id, OwnedEventId, RoomId,
     };
 
+    use super::{EventIsland, MAX_ISLANDS};
     use crate::test_utils::{client::MockClientBuilder, logged_in_client};
 
     #[async_test]
@@ -1440,6 +1755,109 @@ mod tests {
         assert_eq!(items[1].event_id().unwrap(), event_id2);
     }
 
+    #[cfg(not(target_arch = "wasm32"))] // This uses the cross-process lock, so needs time support.
+    #[async_test]
+    async fn test_memory_budget_unloads_and_reloads() {
+        let room_id1 = room_id!("!galette:saucisse.bzh");
+        let room_id2 = room_id!("!crepe:saucisse.bzh");
+
+        let client = MockClientBuilder::new("http://localhost".to_owned()).build().await;
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+        event_cache.enable_storage().unwrap();
+
+        let mut room_event_caches = Vec::new();
+
+        for room_id in [room_id1, room_id2] {
+            client.base_client().get_or_create_room(room_id, matrix_sdk_base::RoomState::Joined);
+            let room = client.get_room(room_id).unwrap();
+            let (room_event_cache, drop_handles) = room.event_cache().await.unwrap();
+
+            let f = EventFactory::new().room(room_id).sender(*ALICE);
+            let timeline = Timeline {
+                limited: false,
+                prev_batch: None,
+                events: vec![f.text_msg("hey yo").into_sync()],
+            };
+            room_event_cache
+                .inner
+                .handle_joined_room_update(
+                    true,
+                    JoinedRoomUpdate { timeline, ..Default::default() },
+                )
+                .await
+                .unwrap();
+
+            room_event_caches.push((room_event_cache, drop_handles));
+        }
+
+        let room_event_cache1 = &room_event_caches[0].0;
+        let room_event_cache2 = &room_event_caches[1].0;
+
+        // View the second room, then stop observing it.
+        let (items, stream) = room_event_cache2.subscribe().await.unwrap();
+        assert_eq!(items.len(), 1);
+        drop(stream);
+
+        // Without a budget, nothing happens.
+        event_cache.inner.enforce_memory_budget().await;
+        assert!(!room_event_cache1.inner.state.read().await.is_unloaded());
+
+        // With a budget of a single event, the room that has never been viewed gets
+        // unloaded first, and that's enough.
+        event_cache.set_memory_budget(Some(1));
+        event_cache.inner.enforce_memory_budget().await;
+        assert!(room_event_cache1.inner.state.read().await.is_unloaded());
+        assert!(!room_event_cache2.inner.state.read().await.is_unloaded());
+
+        // Viewing the room again reloads its events from storage.
+        let (items, _stream) = room_event_cache1.subscribe().await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert!(!room_event_cache1.inner.state.read().await.is_unloaded());
+
+        // The first room is being observed, so it's not unloaded; the second room is,
+        // even though it's been viewed.
+        event_cache.inner.enforce_memory_budget().await;
+        assert!(!room_event_cache1.inner.state.read().await.is_unloaded());
+        assert!(room_event_cache2.inner.state.read().await.is_unloaded());
+
+        // New events for the unloaded room are appended to storage, without reloading
+        // the room.
+        let f = EventFactory::new().room(room_id2).sender(*ALICE);
+        let new_event = f.text_msg("new").event_id(event_id!("$new")).into_sync();
+        let timeline = Timeline {
+            limited: true,
+            prev_batch: Some("prev".to_owned()),
+            events: vec![new_event.clone()],
+        };
+        room_event_cache2
+            .inner
+            .handle_joined_room_update(true, JoinedRoomUpdate { timeline, ..Default::default() })
+            .await
+            .unwrap();
+        assert!(room_event_cache2.inner.state.read().await.is_unloaded());
+
+        // An event that may be a duplicate requires reloading the room, to deduplicate it.
+        let timeline = Timeline { limited: false, prev_batch: None, events: vec![new_event] };
+        room_event_cache2
+            .inner
+            .handle_joined_room_update(true, JoinedRoomUpdate { timeline, ..Default::default() })
+            .await
+            .unwrap();
+        assert!(!room_event_cache2.inner.state.read().await.is_unloaded());
+
+        let (items, _stream) = room_event_cache2.subscribe().await.unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].event_id().as_deref(), Some(event_id!("$new")));
+
+        // The gap has been persisted too.
+        let state = room_event_cache2.inner.state.read().await;
+        let mut chunks = state.events().chunks();
+        assert_matches!(chunks.next().unwrap().content(), ChunkContent::Items(_));
+        assert_matches!(chunks.next().unwrap().content(), ChunkContent::Gap(_));
+    }
+
     #[cfg(not(target_arch = "wasm32"))] // This uses the cross-process lock, so needs time support.
     #[async_test]
     async fn test_load_from_storage_resilient_to_failure() {
@@ -1591,6 +2009,50 @@ mod tests {
         }
     }
 
+    #[async_test]
+    async fn test_islands_are_bounded() {
+        let room_id = room_id!("!galette:saucisse.bzh");
+        let f = EventFactory::new().room(room_id).sender(user_id!("@ben:saucisse.bzh"));
+
+        let client = logged_in_client(None).await;
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+
+        client.base_client().get_or_create_room(room_id, matrix_sdk_base::RoomState::Joined);
+        let room = client.get_room(room_id).unwrap();
+
+        let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+        let event_ids: Vec<_> =
+            (0..=MAX_ISLANDS).map(|i| OwnedEventId::try_from(format!("${i}")).unwrap()).collect();
+        let island = EventIsland {
+            events_before: Vec::new(),
+            events_after: Vec::new(),
+            prev_batch_token: None,
+            next_batch_token: None,
+            state: Vec::new(),
+        };
+
+        for event_id in &event_ids {
+            room_event_cache.save_event(f.text_msg("hello").event_id(event_id).into()).await;
+        }
+
+        for event_id in &event_ids[..MAX_ISLANDS] {
+            room_event_cache.save_island(event_id.clone(), island.clone()).await;
+        }
+
+        // Using the oldest island keeps it around.
+        assert!(room_event_cache.island(&event_ids[0], 0, 0).await.is_some());
+
+        // So the next oldest one is evicted when there are too many islands.
+        room_event_cache.save_island(event_ids[MAX_ISLANDS].clone(), island).await;
+
+        assert!(room_event_cache.island(&event_ids[0], 0, 0).await.is_some());
+        assert!(room_event_cache.island(&event_ids[1], 0, 0).await.is_none());
+        assert!(room_event_cache.island(&event_ids[MAX_ISLANDS], 0, 0).await.is_some());
+    }
+
     async fn assert_relations(
         room_id: &RoomId,
         original_event: SyncTimelineEvent,
@@ -15,12 +15,12 @@
 
 use std::ops::Deref;
 
-use matrix_sdk_base::deserialized_responses::EncryptionInfo;
+use matrix_sdk_base::deserialized_responses::{EncryptionInfo, EventEnrichments};
 use ruma::push::Action;
 use serde_json::value::RawValue as RawJsonValue;
 
 use super::{EventHandlerData, EventHandlerHandle};
-use crate::{Client, Room};
+use crate::{custom_msgtypes::CustomMessage, Client, Room};
 
 /// Context for an event handler.
 ///
@@ -88,6 +88,27 @@ impl EventHandlerContext for Vec<Action> {
     }
 }
 
+/// The metadata attached by the event enrichers, see
+/// [`Client::add_event_enricher`].
+///
+/// It is empty for events that are not part of a room timeline.
+impl EventHandlerContext for EventEnrichments {
+    fn from_data(data: &EventHandlerData<'_>) -> Option<Self> {
+        Some(data.enrichments.cloned().unwrap_or_default())
+    }
+}
+
+/// The message deserialized into the type registered for its custom `msgtype`
+/// with [`Client::register_custom_msgtype`].
+///
+/// It is `None` for other events, and for messages with a `msgtype` that isn't
+/// registered.
+impl EventHandlerContext for Option<CustomMessage> {
+    fn from_data(data: &EventHandlerData<'_>) -> Option<Self> {
+        Some(data.client.parse_custom_message(data.raw))
+    }
+}
+
 /// A custom value registered with
 /// [`.add_event_handler_context`][Client::add_event_handler_context].
 #[derive(Debug)]
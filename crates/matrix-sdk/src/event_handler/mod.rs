@@ -50,7 +50,7 @@ use eyeball::{SharedObservable, Subscriber};
 use futures_core::Stream;
 use futures_util::stream::{FuturesUnordered, StreamExt};
 use matrix_sdk_base::{
-    deserialized_responses::{EncryptionInfo, SyncTimelineEvent},
+    deserialized_responses::{EncryptionInfo, EventEnrichments, SyncTimelineEvent},
     SendOutsideWasm, SyncOutsideWasm,
 };
 use pin_project_lite::pin_project;
@@ -239,6 +239,7 @@ pub struct EventHandlerData<'a> {
     raw: &'a RawJsonValue,
     encryption_info: Option<&'a EncryptionInfo>,
     push_actions: &'a [Action],
+    enrichments: Option<&'a EventEnrichments>,
     handle: EventHandlerHandle,
 }
 
@@ -343,7 +344,8 @@ impl Client {
 
         for raw_event in events {
             let event_type = raw_event.deserialize_as::<ExtractType<'_>>()?.event_type;
-            self.call_event_handlers(room, raw_event.json(), kind, &event_type, None, &[]).await;
+            self.call_event_handlers(room, raw_event.json(), kind, &event_type, None, &[], None)
+                .await;
         }
 
         Ok(())
@@ -370,8 +372,16 @@ impl Client {
             let redacted = unsigned.and_then(|u| u.redacted_because).is_some();
             let handler_kind = HandlerKind::state_redacted(redacted);
 
-            self.call_event_handlers(room, raw_event.json(), handler_kind, &event_type, None, &[])
-                .await;
+            self.call_event_handlers(
+                room,
+                raw_event.json(),
+                handler_kind,
+                &event_type,
+                None,
+                &[],
+                None,
+            )
+            .await;
         }
 
         Ok(())
@@ -403,6 +413,7 @@ impl Client {
             let raw_event = item.raw().json();
             let encryption_info = item.encryption_info();
             let push_actions = &item.push_actions;
+            let enrichments = Some(&item.enrichments);
 
             // Event handlers for possibly-redacted timeline events
             self.call_event_handlers(
@@ -412,6 +423,7 @@ impl Client {
                 &event_type,
                 encryption_info,
                 push_actions,
+                enrichments,
             )
             .await;
 
@@ -423,6 +435,7 @@ impl Client {
                 &event_type,
                 encryption_info,
                 push_actions,
+                enrichments,
             )
             .await;
 
@@ -435,6 +448,7 @@ impl Client {
                 &event_type,
                 encryption_info,
                 push_actions,
+                enrichments,
             )
             .await;
         }
@@ -442,6 +456,7 @@ impl Client {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[instrument(skip_all, fields(?event_kind, ?event_type, room_id))]
     async fn call_event_handlers(
         &self,
@@ -451,6 +466,7 @@ impl Client {
         event_type: &str,
         encryption_info: Option<&EncryptionInfo>,
         push_actions: &[Action],
+        enrichments: Option<&EventEnrichments>,
     ) {
         let room_id = room.map(|r| r.room_id());
         if let Some(room_id) = room_id {
@@ -472,6 +488,7 @@ impl Client {
                     raw,
                     encryption_info,
                     push_actions,
+                    enrichments,
                     handle,
                 };
 
@@ -681,7 +698,7 @@ mod tests {
         future,
         sync::{
             atomic::{AtomicU8, Ordering::SeqCst},
-            Arc,
+            Arc, Mutex,
         },
     };
 
@@ -694,6 +711,7 @@ mod tests {
         events::{
             room::{
                 member::{OriginalSyncRoomMemberEvent, StrippedRoomMemberEvent},
+                message::OriginalSyncRoomMessageEvent,
                 name::OriginalSyncRoomNameEvent,
                 power_levels::OriginalSyncRoomPowerLevelsEvent,
             },
@@ -706,6 +724,7 @@ mod tests {
     use serde_json::json;
 
     use crate::{
+        custom_msgtypes::{CustomMessage, CustomMsgType},
         event_handler::Ctx,
         test_utils::{logged_in_client, no_retry_test_client},
         Client, Room,
@@ -1020,6 +1039,69 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_custom_msgtype_event_handler() -> crate::Result<()> {
+        #[derive(Debug, serde::Deserialize, serde::Serialize)]
+        struct Confetti {
+            color: String,
+        }
+
+        impl CustomMsgType for Confetti {
+            const MSGTYPE: &'static str = "nic.custom.confetti";
+
+            fn body(&self) -> String {
+                format!("{} confetti", self.color)
+            }
+        }
+
+        let client = logged_in_client(None).await;
+        client.register_custom_msgtype::<Confetti>();
+
+        let colors = Arc::new(Mutex::new(Vec::new()));
+        client.add_event_handler({
+            let colors = colors.clone();
+            move |_ev: OriginalSyncRoomMessageEvent, custom: Option<CustomMessage>| {
+                let color = custom.map(|custom| {
+                    assert_eq!(custom.msgtype(), "nic.custom.confetti");
+                    custom.downcast_ref::<Confetti>().unwrap().color.clone()
+                });
+                colors.lock().unwrap().push(color);
+                future::ready(())
+            }
+        });
+
+        let response = SyncResponseBuilder::default()
+            .add_joined_room(
+                JoinedRoomBuilder::default()
+                    .add_timeline_event(sync_timeline_event!({
+                        "content": {
+                            "msgtype": "nic.custom.confetti",
+                            "body": "blue confetti",
+                            "color": "blue",
+                        },
+                        "event_id": "$confetti",
+                        "origin_server_ts": 151800140,
+                        "sender": "@example:localhost",
+                        "type": "m.room.message",
+                    }))
+                    .add_timeline_event(sync_timeline_event!({
+                        "content": {
+                            "msgtype": "m.text",
+                            "body": "Hello",
+                        },
+                        "event_id": "$text",
+                        "origin_server_ts": 151800141,
+                        "sender": "@example:localhost",
+                        "type": "m.room.message",
+                    })),
+            )
+            .build_sync_response();
+        client.process_sync(response).await?;
+
+        assert_eq!(*colors.lock().unwrap(), [Some("blue".to_owned()), None]);
+        Ok(())
+    }
+
     #[async_test]
     #[allow(dependency_on_unit_never_type_fallback)]
     async fn test_observe_events() -> crate::Result<()> {
@@ -34,7 +34,10 @@ use ruma::api::{
 use tokio::sync::{Semaphore, SemaphorePermit};
 use tracing::{debug, field::debug, instrument, trace};
 
-use crate::{config::RequestConfig, error::HttpError};
+use crate::{
+    config::{EndpointOverrides, RequestConfig},
+    error::HttpError,
+};
 
 #[cfg(not(target_arch = "wasm32"))]
 mod native;
@@ -76,6 +79,12 @@ pub(crate) struct HttpClient {
     pub(crate) request_config: RequestConfig,
     concurrent_request_semaphore: MaybeSemaphore,
     next_request_id: Arc<AtomicU64>,
+    endpoint_overrides: Arc<EndpointOverrides>,
+    #[cfg(feature = "appservice")]
+    asserted_identity: Option<Arc<ruma::OwnedUserId>>,
+    /// Whether the homeserver rejected a compressed request body.
+    #[cfg(all(feature = "compression", not(target_arch = "wasm32")))]
+    request_compression_rejected: Arc<std::sync::atomic::AtomicBool>,
 }
 
 impl HttpClient {
@@ -87,9 +96,29 @@ impl HttpClient {
                 request_config.max_concurrent_requests,
             ),
             next_request_id: AtomicU64::new(0).into(),
+            endpoint_overrides: Default::default(),
+            #[cfg(feature = "appservice")]
+            asserted_identity: None,
+            #[cfg(all(feature = "compression", not(target_arch = "wasm32")))]
+            request_compression_rejected: Default::default(),
         }
     }
 
+    /// Apply the given overrides to the URLs of all the requests sent with
+    /// this client.
+    pub(crate) fn with_endpoint_overrides(mut self, endpoint_overrides: EndpointOverrides) -> Self {
+        self.endpoint_overrides = Arc::new(endpoint_overrides);
+        self
+    }
+
+    /// Send all the requests on behalf of the given user, with the `user_id`
+    /// query parameter of the application service API.
+    #[cfg(feature = "appservice")]
+    pub(crate) fn with_asserted_identity(mut self, user_id: Option<ruma::OwnedUserId>) -> Self {
+        self.asserted_identity = user_id.map(Arc::new);
+        self
+    }
+
     fn get_request_id(&self) -> String {
         let request_id = self.next_request_id.fetch_add(1, Ordering::SeqCst);
         format!("REQ-{request_id}")
@@ -125,10 +154,17 @@ impl HttpClient {
             None => SendAccessToken::None,
         };
 
-        let request = request
+        let mut request = request
             .try_into_http_request::<BytesMut>(&homeserver, send_access_token, server_versions)?
             .map(|body| body.freeze());
 
+        self.endpoint_overrides.apply_to_request(&mut request);
+
+        #[cfg(feature = "appservice")]
+        if let Some(user_id) = &self.asserted_identity {
+            *request.uri_mut() = assert_identity(request.uri(), user_id);
+        }
+
         Ok(request)
     }
 
@@ -142,6 +178,7 @@ impl HttpClient {
             request_size,
             request_body,
             request_id,
+            server_request_id,
             status,
             response_size,
             sentry_event_id,
@@ -165,15 +202,16 @@ impl HttpClient {
             None => self.request_config,
         };
 
+        let request_id = self.get_request_id();
+
         // Keep some local variables in a separate scope so the compiler doesn't include
         // them in the future type. https://github.com/rust-lang/rust/issues/57478
         let request = {
-            let request_id = self.get_request_id();
             let span = tracing::Span::current();
 
             // At this point in the code, the config isn't behind an Option anymore, that's
             // why we record it here, instead of in the #[instrument] macro.
-            span.record("config", debug(config)).record("request_id", request_id);
+            span.record("config", debug(config)).record("request_id", &request_id);
 
             let auth_scheme = R::METADATA.authentication;
             match auth_scheme {
@@ -216,7 +254,7 @@ impl HttpClient {
 
         // There's a bunch of state in send_request, factor out a pinned inner
         // future to reduce this size of futures that await this function.
-        match Box::pin(self.send_request::<R>(request, config, send_progress)).await {
+        match Box::pin(self.send_request::<R>(request, &request_id, config, send_progress)).await {
             Ok(response) => {
                 debug!("Got response");
                 Ok(response)
@@ -227,6 +265,71 @@ impl HttpClient {
             }
         }
     }
+
+    /// Send a request and return the response as soon as its headers have been
+    /// received, so its body can be streamed.
+    ///
+    /// Unlike [`HttpClient::send()`], failed requests aren't retried, and no
+    /// timeout is applied, since reading the body of a large response can take
+    /// a long time.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[instrument(
+        skip(self, request, config, homeserver, access_token, server_versions),
+        fields(uri, status)
+    )]
+    pub(crate) async fn send_streamed<R>(
+        &self,
+        request: R,
+        config: Option<RequestConfig>,
+        homeserver: String,
+        access_token: Option<&str>,
+        server_versions: &[MatrixVersion],
+    ) -> Result<reqwest::Response, HttpError>
+    where
+        R: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<R::EndpointError>>,
+    {
+        use ruma::api::{error::MatrixError, EndpointError, IncomingResponse};
+
+        use crate::error::RumaApiError;
+
+        let config = config.unwrap_or(self.request_config);
+
+        let request = self
+            .serialize_request(request, config, homeserver, access_token, server_versions)
+            .map_err(HttpError::IntoHttp)?;
+        let request = reqwest::Request::try_from(request)?;
+
+        let span = tracing::Span::current();
+        span.record("uri", request.url().path());
+
+        let _handle = self.concurrent_request_semaphore.acquire().await;
+        let response = self.inner.execute(request).await?;
+
+        let status = response.status();
+        span.record("status", status.as_u16());
+
+        if status.is_client_error() || status.is_server_error() {
+            let response = response_to_http_response(response).await?;
+            let body = response.body().clone();
+
+            return Err(match R::IncomingResponse::try_from_http_response(response) {
+                Ok(_) => {
+                    // Ruma shouldn't manage to parse a response with an error
+                    // status, but it must not be treated as a success anyway.
+                    let mut response = http::Response::new(body);
+                    *response.status_mut() = status;
+
+                    HttpError::Api(FromHttpResponseError::Server(RumaApiError::Other(
+                        MatrixError::from_http_response(response),
+                    )))
+                }
+                Err(error) => error.into(),
+            });
+        }
+
+        Ok(response)
+    }
 }
 
 /// Progress of sending or receiving a payload.
@@ -238,6 +341,30 @@ pub struct TransmissionProgress {
     pub total: usize,
 }
 
+/// Add the `user_id` query parameter of the application service API to the
+/// given URI.
+#[cfg(feature = "appservice")]
+fn assert_identity(uri: &http::Uri, user_id: &ruma::UserId) -> http::Uri {
+    let user_id = urlencoding::encode(user_id.as_str());
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{query}&user_id={user_id}", uri.path()),
+        None => format!("{}?user_id={user_id}", uri.path()),
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query =
+        Some(path_and_query.parse().expect("the path and query are still valid"));
+    http::Uri::from_parts(parts).expect("the URI is still valid with a new query")
+}
+
+/// Get the id of the request reported by the homeserver, or by a proxy in front
+/// of it, in the `X-Request-Id` header of the response.
+fn server_request_id(response: &http::Response<Bytes>) -> Option<String> {
+    let server_request_id = response.headers().get("x-request-id")?.to_str().ok()?.to_owned();
+    tracing::Span::current().record("server_request_id", &server_request_id);
+    Some(server_request_id)
+}
+
 async fn response_to_http_response(
     mut response: reqwest::Response,
 ) -> Result<http::Response<Bytes>, reqwest::Error> {
@@ -270,7 +397,8 @@ impl tower::Service<http::Request<Bytes>> for HttpClient {
         std::task::Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: http::Request<Bytes>) -> Self::Future {
+    fn call(&mut self, mut req: http::Request<Bytes>) -> Self::Future {
+        self.endpoint_overrides.apply_to_request(&mut req);
         let inner = self.inner.clone();
 
         let fut = async move {
@@ -388,4 +516,44 @@ mod tests {
         assert_eq!(counter.load(Ordering::SeqCst), 254, "Not all requests passed through");
         bg_task.abort();
     }
+
+    #[cfg(feature = "experimental-oidc")]
+    #[async_test]
+    async fn test_endpoint_overrides_apply_to_service_requests() {
+        use tower::ServiceExt as _;
+        use url::Url;
+        use wiremock::{matchers::header_exists, MockServer};
+
+        use crate::{config::EndpointOverrides, http_client::HttpClient};
+
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/gateway/versions"))
+            .and(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/gateway/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let overrides = EndpointOverrides::new()
+            .route("/_matrix/client", Url::parse(&format!("{}/gateway", server.uri())).unwrap());
+        let client = HttpClient::new(reqwest::Client::new(), RequestConfig::default())
+            .with_endpoint_overrides(overrides);
+
+        // The request is sent to the overridden URL, on another origin, without the
+        // access token.
+        let request = http::Request::get("https://example.org/_matrix/client/versions")
+            .header(http::header::AUTHORIZATION, "Bearer secret")
+            .body(bytes::Bytes::new())
+            .unwrap();
+        let response = client.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+    }
 }
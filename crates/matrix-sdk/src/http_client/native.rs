@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "compression")]
+use std::io::Write;
 use std::{
     fmt::Debug,
     mem,
@@ -23,21 +25,39 @@ use backoff::{future::retry, Error as RetryError, ExponentialBackoff};
 use bytes::Bytes;
 use bytesize::ByteSize;
 use eyeball::SharedObservable;
+#[cfg(feature = "compression")]
+use flate2::{write::GzEncoder, Compression};
 use http::header::CONTENT_LENGTH;
+#[cfg(feature = "compression")]
+use http::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    HeaderValue, StatusCode,
+};
 use reqwest::Certificate;
 use ruma::api::{error::FromHttpResponseError, IncomingResponse, OutgoingRequest};
 use tracing::{debug, info, warn};
 
-use super::{response_to_http_response, HttpClient, TransmissionProgress, DEFAULT_REQUEST_TIMEOUT};
+use super::{
+    response_to_http_response, server_request_id, HttpClient, TransmissionProgress,
+    DEFAULT_REQUEST_TIMEOUT,
+};
+#[cfg(feature = "compression")]
+use crate::config::RequestCompression;
 use crate::{
     config::RequestConfig,
-    error::{HttpError, RetryKind},
+    error::{HttpError, RequestIds, RetryKind},
 };
 
+/// The minimum size of a request body to compress it, smaller bodies don't get
+/// smaller with compression.
+#[cfg(feature = "compression")]
+const MIN_COMPRESSED_BODY_SIZE: usize = 1024;
+
 impl HttpClient {
     pub(super) async fn send_request<R>(
         &self,
         request: http::Request<Bytes>,
+        request_id: &str,
         config: RequestConfig,
         send_progress: SharedObservable<TransmissionProgress>,
     ) -> Result<R::IncomingResponse, HttpError>
@@ -86,7 +106,8 @@ impl HttpClient {
                     }
                 };
 
-                let response = send_request(&self.inner, &request, config.timeout, send_progress)
+                let response = self
+                    .send_maybe_compressed_request(&request, &config, send_progress)
                     .await
                     .map_err(error_type)?;
 
@@ -109,13 +130,69 @@ impl HttpClient {
                     }
                 }
 
+                let request_ids = RequestIds {
+                    client_request_id: request_id.to_owned(),
+                    server_request_id: server_request_id(&response),
+                };
+
                 R::IncomingResponse::try_from_http_response(response)
-                    .map_err(|e| error_type(HttpError::from(e)))
+                    .map_err(|e| error_type(HttpError::from(e).with_request_ids(request_ids)))
             }
         };
 
         retry::<_, HttpError, _, _, _>(backoff, send_request).await
     }
+
+    /// Send the given request, with a compressed body if the config asks for
+    /// it.
+    ///
+    /// If the homeserver rejects the compressed body, the request is sent again
+    /// uncompressed, and the bodies of the next requests are not compressed
+    /// anymore.
+    async fn send_maybe_compressed_request(
+        &self,
+        request: &http::Request<Bytes>,
+        config: &RequestConfig,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<http::Response<Bytes>, HttpError> {
+        #[cfg(feature = "compression")]
+        if let Some(compressed_request) = config
+            .request_compression
+            .filter(|_| !self.request_compression_rejected.load(Ordering::SeqCst))
+            .and_then(|compression| compress_request(request, compression))
+        {
+            let response = send_request(
+                &self.inner,
+                &compressed_request,
+                config.timeout,
+                send_progress.clone(),
+            )
+            .await?;
+
+            let status = response.status();
+            if status != StatusCode::UNSUPPORTED_MEDIA_TYPE && status != StatusCode::BAD_REQUEST {
+                return Ok(response);
+            }
+
+            let response =
+                send_request(&self.inner, request, config.timeout, send_progress).await?;
+
+            // A bad request might have nothing to do with the compression, only
+            // give up on it if the uncompressed body was accepted.
+            if response.status() != status {
+                warn!(
+                    %status,
+                    "The homeserver rejected a compressed request body, \
+                     not compressing the next ones"
+                );
+                self.request_compression_rejected.store(true, Ordering::SeqCst);
+            }
+
+            return Ok(response);
+        }
+
+        send_request(&self.inner, request, config.timeout, send_progress).await
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -127,6 +204,7 @@ pub(crate) struct HttpSettings {
     pub(crate) timeout: Duration,
     pub(crate) additional_root_certificates: Vec<Certificate>,
     pub(crate) disable_built_in_root_certificates: bool,
+    pub(crate) disable_response_decompression: bool,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -139,6 +217,7 @@ impl Default for HttpSettings {
             timeout: DEFAULT_REQUEST_TIMEOUT,
             additional_root_certificates: Default::default(),
             disable_built_in_root_certificates: false,
+            disable_response_decompression: false,
         }
     }
 }
@@ -172,6 +251,16 @@ impl HttpSettings {
             http_client = http_client.tls_built_in_root_certs(false);
         }
 
+        if self.disable_response_decompression {
+            info!("Response decompression disabled in the HTTP client.");
+            http_client = http_client.no_gzip();
+
+            #[cfg(feature = "compression")]
+            {
+                http_client = http_client.no_zstd();
+            }
+        }
+
         if let Some(p) = &self.proxy {
             info!(proxy_url = p, "Setting the proxy for the HTTP client");
             http_client = http_client.proxy(reqwest::Proxy::all(p.as_str())?);
@@ -241,6 +330,47 @@ fn clone_request(request: &http::Request<Bytes>) -> http::Request<Bytes> {
     builder.body(request.body().clone()).unwrap()
 }
 
+/// Get a copy of the given request with a compressed body, if it's a JSON body
+/// large enough to benefit from it.
+#[cfg(feature = "compression")]
+fn compress_request(
+    request: &http::Request<Bytes>,
+    compression: RequestCompression,
+) -> Option<http::Request<Bytes>> {
+    let is_json = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    if !is_json
+        || request.body().len() < MIN_COMPRESSED_BODY_SIZE
+        || request.headers().contains_key(CONTENT_ENCODING)
+    {
+        return None;
+    }
+
+    let (mut parts, body) = clone_request(request).into_parts();
+
+    let (body, encoding) = match compression {
+        RequestCompression::Gzip => {
+            let mut encoder =
+                GzEncoder::new(Vec::with_capacity(body.len() / 2), Compression::default());
+            encoder.write_all(&body).expect("writing to a Vec should never fail");
+            (encoder.finish().expect("writing to a Vec should never fail"), "gzip")
+        }
+        RequestCompression::Zstd => {
+            let body = zstd::stream::encode_all(&body[..], zstd::DEFAULT_COMPRESSION_LEVEL)
+                .expect("reading from a slice should never fail");
+            (body, "zstd")
+        }
+    };
+
+    parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+
+    Some(http::Request::from_parts(parts, body.into()))
+}
+
 struct BytesChunks {
     bytes: Bytes,
     size: usize,
@@ -273,6 +403,164 @@ mod tests {
 
     use super::BytesChunks;
 
+    #[cfg(feature = "compression")]
+    mod compression {
+        use std::{io::Read, time::Duration};
+
+        use bytes::Bytes;
+        use flate2::read::GzDecoder;
+        use http::header::{CONTENT_ENCODING, CONTENT_TYPE};
+        use matrix_sdk_test::async_test;
+        use wiremock::{
+            matchers::{header_exists, header_regex, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        use super::super::{
+            compress_request, send_request, HttpClient, HttpSettings, MIN_COMPRESSED_BODY_SIZE,
+        };
+        use crate::config::{RequestCompression, RequestConfig};
+
+        fn json_request(body: Vec<u8>) -> http::Request<Bytes> {
+            http::Request::builder()
+                .method("PUT")
+                .uri("https://example.org/_matrix/client/v3/sendToDevice/m.test/1")
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.into())
+                .unwrap()
+        }
+
+        #[test]
+        fn test_compress_request() {
+            let body = vec![b'a'; MIN_COMPRESSED_BODY_SIZE * 4];
+            let request =
+                compress_request(&json_request(body.clone()), RequestCompression::Gzip).unwrap();
+
+            assert_eq!(request.headers()[CONTENT_ENCODING], "gzip");
+            assert!(request.body().len() < body.len());
+
+            let mut decompressed = Vec::new();
+            GzDecoder::new(&request.body()[..]).read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, body);
+        }
+
+        #[test]
+        fn test_compress_request_zstd() {
+            let body = vec![b'a'; MIN_COMPRESSED_BODY_SIZE * 4];
+            let request =
+                compress_request(&json_request(body.clone()), RequestCompression::Zstd).unwrap();
+
+            assert_eq!(request.headers()[CONTENT_ENCODING], "zstd");
+            assert!(request.body().len() < body.len());
+
+            let decompressed = zstd::stream::decode_all(&request.body()[..]).unwrap();
+            assert_eq!(decompressed, body);
+        }
+
+        #[test]
+        fn test_compress_request_skips_small_bodies() {
+            let body = vec![b'a'; MIN_COMPRESSED_BODY_SIZE - 1];
+            assert!(compress_request(&json_request(body), RequestCompression::Gzip).is_none());
+        }
+
+        #[test]
+        fn test_compress_request_skips_non_json_bodies() {
+            let body = vec![b'a'; MIN_COMPRESSED_BODY_SIZE * 4];
+            let mut request = json_request(body.clone());
+            request.headers_mut().insert(CONTENT_TYPE, "image/png".parse().unwrap());
+
+            assert!(compress_request(&request, RequestCompression::Gzip).is_none());
+        }
+
+        #[async_test]
+        async fn test_rejected_request_compression() {
+            let server = MockServer::start().await;
+            let body = vec![b'a'; MIN_COMPRESSED_BODY_SIZE * 4];
+
+            Mock::given(method("PUT"))
+                .and(path("/compressed"))
+                .and(header_exists("content-encoding"))
+                .respond_with(ResponseTemplate::new(415))
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            // Only reached by the uncompressed requests, since the mock above takes
+            // precedence.
+            Mock::given(method("PUT"))
+                .and(path("/compressed"))
+                .respond_with(ResponseTemplate::new(200))
+                .expect(2)
+                .mount(&server)
+                .await;
+
+            let config = RequestConfig::new().request_compression(RequestCompression::Gzip);
+            let client = HttpClient::new(HttpSettings::default().make_client().unwrap(), config);
+
+            let mut request = json_request(body);
+            *request.uri_mut() = format!("{}/compressed", server.uri()).parse().unwrap();
+
+            // The compressed body is rejected, so the request is sent again uncompressed.
+            let response = client
+                .send_maybe_compressed_request(&request, &config, Default::default())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+
+            // The next requests are not compressed anymore.
+            let response = client
+                .send_maybe_compressed_request(&request, &config, Default::default())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+        }
+
+        #[async_test]
+        async fn test_response_decompression() {
+            let server = MockServer::start().await;
+            let body = br#"{"rooms":{}}"#.repeat(100);
+
+            Mock::given(method("GET"))
+                .and(path("/zstd"))
+                .and(header_regex("accept-encoding", "zstd"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("content-encoding", "zstd")
+                        .set_body_raw(
+                            zstd::stream::encode_all(&body[..], 0).unwrap(),
+                            "application/json",
+                        ),
+                )
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let client = HttpSettings::default().make_client().unwrap();
+            let request = http::Request::builder()
+                .uri(format!("{}/zstd", server.uri()))
+                .body(Bytes::new())
+                .unwrap();
+
+            let response =
+                send_request(&client, &request, Duration::from_secs(5), Default::default())
+                    .await
+                    .unwrap();
+            assert_eq!(response.body().as_ref(), &body[..]);
+
+            // With the decompression disabled, the homeserver isn't asked to compress the
+            // response.
+            let settings =
+                HttpSettings { disable_response_decompression: true, ..HttpSettings::default() };
+            let client = settings.make_client().unwrap();
+
+            let response =
+                send_request(&client, &request, Duration::from_secs(5), Default::default())
+                    .await
+                    .unwrap();
+            assert_eq!(response.status(), 404);
+        }
+    }
+
     #[test]
     fn test_bytes_chunks() {
         let bytes = Bytes::new();
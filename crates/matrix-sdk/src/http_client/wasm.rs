@@ -19,13 +19,17 @@ use bytesize::ByteSize;
 use eyeball::SharedObservable;
 use ruma::api::{error::FromHttpResponseError, IncomingResponse, OutgoingRequest};
 
-use super::{response_to_http_response, HttpClient, TransmissionProgress};
-use crate::{config::RequestConfig, error::HttpError};
+use super::{response_to_http_response, server_request_id, HttpClient, TransmissionProgress};
+use crate::{
+    config::RequestConfig,
+    error::{HttpError, RequestIds},
+};
 
 impl HttpClient {
     pub(super) async fn send_request<R>(
         &self,
         request: http::Request<Bytes>,
+        request_id: &str,
         _config: RequestConfig,
         _send_progress: SharedObservable<TransmissionProgress>,
     ) -> Result<R::IncomingResponse, HttpError>
@@ -44,6 +48,12 @@ impl HttpClient {
             .record("status", status_code.as_u16())
             .record("response_size", response_size.to_string_as(true));
 
-        Ok(R::IncomingResponse::try_from_http_response(response)?)
+        let request_ids = RequestIds {
+            client_request_id: request_id.to_owned(),
+            server_request_id: server_request_id(&response),
+        };
+
+        R::IncomingResponse::try_from_http_response(response)
+            .map_err(|e| HttpError::from(e).with_request_ids(request_ids))
     }
 }
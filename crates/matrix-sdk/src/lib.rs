@@ -25,36 +25,52 @@ pub use matrix_sdk_base::crypto;
 pub use matrix_sdk_base::{
     deserialized_responses,
     store::{DynStateStore, MemoryStore, StateStoreExt},
-    ComposerDraft, ComposerDraftType, QueueWedgeError, Room as BaseRoom,
-    RoomCreateWithCreatorEventContent, RoomDisplayName, RoomHero, RoomInfo,
-    RoomMember as BaseRoomMember, RoomMemberships, RoomState, SessionMeta, StateChanges,
-    StateStore, StoreError,
+    CachedUserProfile, ComposerDraft, ComposerDraftType, ContactActivity, HiddenRoomsEventContent,
+    QueueWedgeError, Room as BaseRoom, RoomCreateWithCreatorEventContent, RoomDisplayName,
+    RoomHero, RoomInfo, RoomMember as BaseRoomMember, RoomMemberships, RoomState, SessionMeta,
+    StateChanges, StateStore, StoreError, UrlPreview, UrlPreviewImage,
 };
 pub use matrix_sdk_common::*;
 pub use reqwest;
 
 mod account;
+#[cfg(all(feature = "appservice", not(target_arch = "wasm32")))]
+pub mod appservice;
 pub mod attachment;
 pub mod authentication;
 mod client;
 pub mod config;
+mod contact_activity;
+pub mod custom_msgtypes;
 mod deduplicating_handler;
 #[cfg(feature = "e2e-encryption")]
 pub mod encryption;
+pub mod enrichment;
 mod error;
 pub mod event_cache;
 pub mod event_handler;
 mod http_client;
+pub mod long_poll;
 pub mod matrix_auth;
+pub mod matrix_uri;
 pub mod media;
 pub mod notification_settings;
 #[cfg(feature = "experimental-oidc")]
 pub mod oidc;
+pub mod peeked_room;
+pub mod prefetch;
 pub mod pusher;
+#[cfg(all(feature = "rageshake", not(target_arch = "wasm32")))]
+pub mod rageshake;
+#[cfg(all(feature = "unstable-msc4108", not(target_arch = "wasm32")))]
+pub mod rendezvous;
 pub mod room;
+pub mod room_creation;
 pub mod room_directory_search;
 pub mod room_preview;
 pub mod send_queue;
+pub mod server_notices;
+pub mod settings_export;
 pub mod utils;
 pub mod futures {
     //! Named futures returned from methods on types in [the crate root][crate].
@@ -63,17 +79,24 @@ pub mod futures {
 }
 pub mod sliding_sync;
 pub mod sync;
+pub mod to_device;
+pub mod turn_servers;
+mod url_preview;
+pub mod well_known;
 #[cfg(feature = "experimental-widgets")]
 pub mod widget;
 
 pub use account::Account;
+#[cfg(feature = "image")]
+pub use account::AvatarResizePolicy;
 pub use authentication::{AuthApi, AuthSession, SessionTokens};
 pub use client::{
-    sanitize_server_name, Client, ClientBuildError, ClientBuilder, LoopCtrl, SessionChange,
+    sanitize_server_name, Client, ClientBuildError, ClientBuilder, ClientStatus, ConnectionState,
+    LoopCtrl, SessionChange,
 };
 pub use error::{
-    Error, HttpError, HttpResult, NotificationSettingsError, RefreshTokenError, Result,
-    RumaApiError,
+    Error, ErrorCategory, HttpError, HttpResult, NotificationSettingsError, RefreshTokenError,
+    RequestIds, Result, RumaApiError, TokenInvalidation,
 };
 pub use http_client::TransmissionProgress;
 #[cfg(all(feature = "e2e-encryption", feature = "sqlite"))]
@@ -0,0 +1,219 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A driver for the long-polling endpoints other than `/sync`, like the
+//! `/events` endpoint for guests, or custom endpoints of bridges and bots.
+//!
+//! The [`LongPollDriver`] sends the requests in a loop, like the sync loop of
+//! the [`Client`]: it waits between the requests if the server responds too
+//! quickly, and backs off when they fail.
+
+use std::{fmt::Debug, future::Future, time::Duration};
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::{pin_mut, StreamExt};
+use ruma::{
+    api::{error::FromHttpResponseError, OutgoingRequest},
+    time::Instant,
+};
+use tracing::{debug, warn};
+
+use crate::{config::RequestConfig, utils::sleep, Client, HttpError, LoopCtrl, Result};
+
+/// The default minimum interval between the start of two requests.
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The delay before sending a request again after the first failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The default maximum delay before sending a request again after a failure.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A driver sending the requests to a long-polling endpoint in a loop.
+///
+/// Each request is built from the response to the previous one, which allows
+/// to pass the position in the stream of events from one request to the next.
+///
+/// After a failure, the request is sent again after a delay that doubles with
+/// every consecutive failure, up to [`LongPollDriver::max_backoff()`], or
+/// after the delay asked by the homeserver if it rate-limited the request.
+/// Errors that can't be fixed by sending the request again end the loop.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use matrix_sdk::{Client, long_poll::LongPollDriver, LoopCtrl};
+/// # use ruma::api::client::sync::sync_events;
+/// # async {
+/// # let client: Client = unimplemented!();
+/// LongPollDriver::new(client)
+///     .run(
+///         |previous: Option<&sync_events::v3::Response>| {
+///             let mut request = sync_events::v3::Request::new();
+///             request.since = previous.map(|response| response.next_batch.clone());
+///             request
+///         },
+///         |response| async move {
+///             if let Ok(response) = response {
+///                 println!("Got a response, next batch: {}", response.next_batch);
+///             }
+///             Ok(LoopCtrl::Continue)
+///         },
+///     )
+///     .await?;
+/// # anyhow::Ok(()) };
+/// ```
+#[derive(Debug, Clone)]
+pub struct LongPollDriver {
+    client: Client,
+    request_config: Option<RequestConfig>,
+    min_interval: Duration,
+    max_backoff: Duration,
+}
+
+impl LongPollDriver {
+    /// Create a driver sending its requests with the given client.
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            request_config: None,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Set the configuration of the requests.
+    ///
+    /// The timeout of the requests should be longer than the time the
+    /// homeserver holds them, otherwise they are aborted before the server
+    /// responds when there is no new data.
+    pub fn request_config(mut self, request_config: RequestConfig) -> Self {
+        self.request_config = Some(request_config);
+        self
+    }
+
+    /// Set the minimum interval between the start of two requests, to avoid
+    /// hammering servers that respond immediately.
+    ///
+    /// Defaults to one second.
+    pub fn min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Set the maximum delay before sending a request again after a failure.
+    ///
+    /// Defaults to one minute.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Send the requests in a loop, and get their results as a stream.
+    ///
+    /// The first request is built by calling `make_request` with `None`, and
+    /// the next ones by calling it with the previous successful response. The
+    /// same request is sent again after a failure.
+    ///
+    /// The stream ends after an error that can't be fixed by sending the
+    /// request again. The loop is aborted by dropping the stream.
+    pub fn stream<R, F>(
+        &self,
+        mut make_request: F,
+    ) -> impl Stream<Item = Result<R::IncomingResponse, HttpError>> + '_
+    where
+        R: OutgoingRequest + Clone + Debug + 'static,
+        HttpError: From<FromHttpResponseError<R::EndpointError>>,
+        F: FnMut(Option<&R::IncomingResponse>) -> R + 'static,
+    {
+        stream! {
+            let mut request = make_request(None);
+            let mut last_request_time: Option<Instant> = None;
+            let mut failures = 0;
+
+            loop {
+                if let Some(delay) = last_request_time
+                    .and_then(|time| self.min_interval.checked_sub(time.elapsed()))
+                {
+                    sleep(delay).await;
+                }
+                last_request_time = Some(Instant::now());
+
+                let result = self
+                    .client
+                    .send(request.clone())
+                    .with_request_config(self.request_config)
+                    .await;
+
+                match result {
+                    Ok(response) => {
+                        failures = 0;
+                        request = make_request(Some(&response));
+                        yield Ok(response);
+                    }
+                    Err(error) => {
+                        if !error.is_retryable() {
+                            warn!("The long-poll request failed permanently: {error}");
+                            yield Err(error);
+                            break;
+                        }
+
+                        let delay = self.backoff(&error, failures);
+                        failures += 1;
+                        debug!(?delay, failures, "The long-poll request failed: {error}");
+
+                        yield Err(error);
+                        sleep(delay).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send the requests in a loop, and call the callback with their results.
+    ///
+    /// The requests are built like with [`LongPollDriver::stream()`]. The loop
+    /// runs until the callback returns [`LoopCtrl::Break`] or an error, or the
+    /// requests fail with an error that can't be fixed by sending them again.
+    pub async fn run<R, F, C, Fut>(&self, make_request: F, callback: C) -> Result<()>
+    where
+        R: OutgoingRequest + Clone + Debug + 'static,
+        HttpError: From<FromHttpResponseError<R::EndpointError>>,
+        F: FnMut(Option<&R::IncomingResponse>) -> R + 'static,
+        C: Fn(Result<R::IncomingResponse, HttpError>) -> Fut,
+        Fut: Future<Output = Result<LoopCtrl>>,
+    {
+        let stream = self.stream(make_request);
+        pin_mut!(stream);
+
+        while let Some(result) = stream.next().await {
+            if callback(result).await? == LoopCtrl::Break {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How long to wait before sending a request again after the given error,
+    /// which happened after `failures` consecutive failures.
+    fn backoff(&self, error: &HttpError, failures: u32) -> Duration {
+        if let Some(retry_after) = error.retry_after() {
+            return retry_after;
+        }
+
+        INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(failures)).min(self.max_backoff)
+    }
+}
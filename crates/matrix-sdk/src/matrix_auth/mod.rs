@@ -16,9 +16,9 @@
 
 //! Types to interact with the native Matrix authentication API.
 
-use std::fmt;
 #[cfg(feature = "sso-login")]
 use std::future::Future;
+use std::{fmt, sync::Arc};
 
 use eyeball::SharedObservable;
 use futures_core::Stream;
@@ -43,17 +43,22 @@ use tracing::{debug, error, info, instrument};
 use url::Url;
 
 use crate::{
-    authentication::AuthData,
+    authentication::{
+        session_store::{SessionStore, StoredSession},
+        AuthData, SessionTokens,
+    },
     client::SessionChange,
     error::{HttpError, HttpResult},
     Client, Error, RefreshTokenError, Result,
 };
 
 mod login_builder;
+mod register_builder;
 
 pub use self::login_builder::LoginBuilder;
 #[cfg(feature = "sso-login")]
 pub use self::login_builder::SsoLoginBuilder;
+pub use self::register_builder::RegisterBuilder;
 
 #[derive(Clone)]
 pub(crate) struct MatrixAuthData {
@@ -895,6 +900,90 @@ impl MatrixAuth {
         Ok(())
     }
 
+    /// Restore the session saved in the given [`SessionStore`], if any.
+    ///
+    /// Returns `false` if the store doesn't contain a session, in which case
+    /// the user needs to log in again.
+    ///
+    /// The homeserver of the stored session is not used here, so the client
+    /// should be built with [`StoredSession::homeserver`] beforehand.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a session was already restored or logged in.
+    pub async fn restore_session_from(&self, store: &dyn SessionStore) -> Result<bool> {
+        let Some(stored) = store.load()? else {
+            return Ok(false);
+        };
+
+        self.restore_session(stored.session).await?;
+        Ok(true)
+    }
+
+    /// Persist the session of the client in the given [`SessionStore`].
+    ///
+    /// The current session, if any, is saved right away, and then saved again
+    /// every time the access token is refreshed, so this should be called
+    /// after logging in or restoring the session.
+    ///
+    /// This uses the session callbacks of the client, so it fails with
+    /// [`Error::MultipleSessionCallbacks`] if they were already set with
+    /// [`Client::set_session_callbacks()`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::sync::Arc;
+    ///
+    /// use matrix_sdk::{
+    ///     authentication::session_store::{
+    ///         EncryptedFileSessionStore, SessionEncryptionKey,
+    ///     },
+    ///     Client,
+    /// };
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    ///
+    /// let store = Arc::new(EncryptedFileSessionStore::open(
+    ///     "session.json",
+    ///     SessionEncryptionKey::Passphrase("secret passphrase".to_owned()),
+    /// )?);
+    ///
+    /// let client = Client::new(homeserver).await?;
+    /// let auth = client.matrix_auth();
+    ///
+    /// if !auth.restore_session_from(&*store).await? {
+    ///     auth.login_username("example", "my-password").send().await?;
+    /// }
+    ///
+    /// auth.persist_session_to(store)?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn persist_session_to(&self, store: Arc<dyn SessionStore>) -> Result<()> {
+        let reload_store = store.clone();
+        let save_store = store.clone();
+
+        self.client.set_session_callbacks(
+            Box::new(move |_| {
+                let stored = reload_store.load()?.ok_or("no session in the session store")?;
+                Ok(SessionTokens::Matrix(stored.session.tokens))
+            }),
+            Box::new(move |client| {
+                if let Some(session) = client.matrix_auth().session() {
+                    save_store.save(&StoredSession { homeserver: client.homeserver(), session })?;
+                }
+                Ok(())
+            }),
+        )?;
+
+        if let Some(session) = self.session() {
+            store.save(&StoredSession { homeserver: self.client.homeserver(), session })?;
+        }
+
+        Ok(())
+    }
+
     /// Receive a login response and update the homeserver and the base client
     /// if needed.
     ///
@@ -0,0 +1,414 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg_attr(not(target_arch = "wasm32"), deny(clippy::future_not_send))]
+
+use std::future::{Future, IntoFuture};
+
+use matrix_sdk_common::{boxed_into_future, BoxFuture, SendOutsideWasm};
+use ruma::{
+    api::client::{
+        account::{
+            register::{self, RegistrationKind},
+            request_registration_token_via_email,
+        },
+        uiaa::{self, AuthData, AuthType, ThirdpartyIdCredentials, UiaaInfo},
+    },
+    assign, OwnedClientSecret, OwnedSessionId, UInt,
+};
+use serde::Deserialize;
+use tracing::{debug, info, instrument};
+
+use super::{MatrixAuth, MatrixSessionTokens};
+use crate::{config::RequestConfig, Error, Result};
+
+/// The hook called to complete the `m.login.recaptcha` stage, with the public
+/// key of the ReCaptcha, and returning the response of the user.
+type ReCaptchaHook =
+    Box<dyn Fn(Option<String>) -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+/// Builder type used to register an account, by completing the stages of the
+/// [User-Interactive Authentication API] required by the homeserver.
+///
+/// Created with [`Client::register()`](crate::Client::register). Finalized
+/// with [`.send()`](Self::send).
+///
+/// The `m.login.dummy` stage is completed automatically, and the other
+/// supported stages are completed with the data set on the builder:
+///
+/// * `m.login.registration_token` with
+///   [`registration_token()`](Self::registration_token),
+/// * `m.login.email.identity` with
+///   [`email_identity()`](Self::email_identity),
+/// * `m.login.recaptcha` with [`recaptcha()`](Self::recaptcha).
+///
+/// [User-Interactive Authentication API]: https://spec.matrix.org/v1.12/client-server-api/#user-interactive-authentication-api
+#[allow(missing_debug_implementations)]
+pub struct RegisterBuilder {
+    auth: MatrixAuth,
+    kind: RegistrationKind,
+    upgrade_guest: bool,
+    username: Option<String>,
+    password: Option<String>,
+    device_id: Option<String>,
+    initial_device_display_name: Option<String>,
+    request_refresh_token: bool,
+    registration_token: Option<String>,
+    email_identity: Option<ThirdpartyIdCredentials>,
+    recaptcha: Option<ReCaptchaHook>,
+}
+
+impl RegisterBuilder {
+    pub(super) fn new(auth: MatrixAuth) -> Self {
+        Self {
+            auth,
+            kind: RegistrationKind::User,
+            upgrade_guest: false,
+            username: None,
+            password: None,
+            device_id: None,
+            initial_device_display_name: None,
+            request_refresh_token: false,
+            registration_token: None,
+            email_identity: None,
+            recaptcha: None,
+        }
+    }
+
+    /// Set the localpart of the user ID of the account.
+    ///
+    /// If not set, the homeserver will generate one.
+    pub fn username(mut self, value: &str) -> Self {
+        self.username = Some(value.to_owned());
+        self
+    }
+
+    /// Set the password of the account.
+    pub fn password(mut self, value: &str) -> Self {
+        self.password = Some(value.to_owned());
+        self
+    }
+
+    /// Register a guest account, instead of a full account.
+    ///
+    /// Guest accounts don't need to complete any authentication stage, but
+    /// have limited permissions. They can be converted to full accounts later
+    /// with [`upgrade_guest()`](Self::upgrade_guest).
+    pub fn guest(mut self) -> Self {
+        self.kind = RegistrationKind::Guest;
+        self
+    }
+
+    /// Convert the guest account the client is logged in with to a full
+    /// account.
+    ///
+    /// The registration request is authenticated with the access token of the
+    /// guest account, and the tokens of the session are replaced by the ones
+    /// returned by the homeserver. The client must be logged in, otherwise
+    /// [`Error::AuthenticationRequired`] is returned, and the homeserver must
+    /// return the user ID of the guest account, otherwise
+    /// [`Error::GuestUpgradeMismatch`] is returned.
+    ///
+    /// [`Error::AuthenticationRequired`]: crate::Error::AuthenticationRequired
+    /// [`Error::GuestUpgradeMismatch`]: crate::Error::GuestUpgradeMismatch
+    pub fn upgrade_guest(mut self) -> Self {
+        self.upgrade_guest = true;
+        self
+    }
+
+    /// Set the device ID.
+    ///
+    /// The device ID is a unique ID that will be associated with this session.
+    /// If not set, the homeserver will create one.
+    pub fn device_id(mut self, value: &str) -> Self {
+        self.device_id = Some(value.to_owned());
+        self
+    }
+
+    /// Set the initial device display name.
+    ///
+    /// The device display name is the public name that will be associated with
+    /// the device ID. It can be changed later.
+    pub fn initial_device_display_name(mut self, value: &str) -> Self {
+        self.initial_device_display_name = Some(value.to_owned());
+        self
+    }
+
+    /// Advertise support for [refreshing access tokens].
+    ///
+    /// See [`LoginBuilder::request_refresh_token()`] for more details.
+    ///
+    /// [refreshing access tokens]: https://spec.matrix.org/v1.3/client-server-api/#refreshing-access-tokens
+    /// [`LoginBuilder::request_refresh_token()`]: super::LoginBuilder::request_refresh_token
+    pub fn request_refresh_token(mut self) -> Self {
+        self.request_refresh_token = true;
+        self
+    }
+
+    /// Set the token to complete the `m.login.registration_token` stage, for
+    /// homeservers that require a token to register.
+    pub fn registration_token(mut self, token: &str) -> Self {
+        self.registration_token = Some(token.to_owned());
+        self
+    }
+
+    /// Set the credentials to complete the `m.login.email.identity` stage.
+    ///
+    /// The session ID is returned by
+    /// [`MatrixAuth::request_registration_email_token()`], called with the
+    /// same client secret, and the user must have clicked on the link in the
+    /// email they received before the registration is sent.
+    pub fn email_identity(mut self, sid: OwnedSessionId, client_secret: OwnedClientSecret) -> Self {
+        self.email_identity = Some(ThirdpartyIdCredentials::new(sid, client_secret));
+        self
+    }
+
+    /// Set the hook to complete the `m.login.recaptcha` stage.
+    ///
+    /// The hook is called with the public key of the ReCaptcha, if the
+    /// homeserver provided one, and must present the ReCaptcha to the user and
+    /// return their response.
+    pub fn recaptcha<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(Option<String>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + SendOutsideWasm + 'static,
+    {
+        self.recaptcha = Some(Box::new(move |public_key| Box::pin(hook(public_key))));
+        self
+    }
+
+    /// Send the registration request, and complete the required
+    /// authentication stages.
+    ///
+    /// If a session is returned by the homeserver, the client is logged in
+    /// with the new account. When [upgrading a guest
+    /// account](Self::upgrade_guest), the tokens of the session are updated
+    /// instead.
+    ///
+    /// If the homeserver requires stages that can't be completed with the data
+    /// set on the builder, or if a stage failed, the error of the last request
+    /// is returned, and its [`UiaaInfo`] can be retrieved with
+    /// [`Error::as_uiaa_response()`](crate::Error::as_uiaa_response).
+    ///
+    /// Instead of calling this function and `.await`ing its return value, you
+    /// can also `.await` the `RegisterBuilder` directly.
+    #[instrument(target = "matrix_sdk::client", name = "register", skip_all)]
+    pub async fn send(self) -> Result<register::v3::Response> {
+        let upgrade_guest = self.upgrade_guest;
+        if upgrade_guest && !self.auth.logged_in() {
+            return Err(Error::AuthenticationRequired);
+        }
+
+        info!(homeserver = self.auth.client.homeserver().as_str(), upgrade_guest, "Registering");
+
+        let mut auth = None;
+        let mut previous_stage = None;
+
+        loop {
+            let request = self.request(auth.take());
+
+            let error = match self.send_request(request, upgrade_guest).await {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+
+            let Some(info) = error.as_uiaa_response().cloned() else { return Err(error) };
+
+            if info.auth_error.is_some() {
+                // The data set on the builder for the previous stage is invalid.
+                return Err(error);
+            }
+
+            let Some(stage) = next_stage(&info, |stage| self.supports_stage(stage)) else {
+                return Err(error);
+            };
+
+            if previous_stage.as_ref() == Some(&stage) {
+                // The homeserver didn't accept the previous stage, without saying why.
+                return Err(error);
+            }
+
+            debug!(?stage, "Completing authentication stage");
+
+            auth = self.auth_data(&stage, &info).await?;
+            previous_stage = Some(stage);
+
+            if auth.is_none() {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Create the registration request with the given authentication data.
+    fn request(&self, auth: Option<AuthData>) -> register::v3::Request {
+        assign!(register::v3::Request::new(), {
+            username: self.username.clone(),
+            password: self.password.clone(),
+            device_id: self.device_id.clone().map(Into::into),
+            initial_device_display_name: self.initial_device_display_name.clone(),
+            refresh_token: self.request_refresh_token,
+            kind: self.kind.clone(),
+            auth,
+        })
+    }
+
+    /// Send the registration request.
+    async fn send_request(
+        &self,
+        request: register::v3::Request,
+        upgrade_guest: bool,
+    ) -> Result<register::v3::Response> {
+        if !upgrade_guest {
+            return self.auth.register(request).await;
+        }
+
+        // The guest account is identified by its access token.
+        let response = self
+            .auth
+            .client
+            .send(request)
+            .with_request_config(RequestConfig::new().force_auth())
+            .await?;
+
+        let guest = self.auth.client.user_id().ok_or(Error::AuthenticationRequired)?;
+        if &*response.user_id != guest {
+            return Err(Error::GuestUpgradeMismatch {
+                guest: guest.to_owned(),
+                registered: response.user_id,
+            });
+        }
+
+        if let Some(access_token) = response.access_token.clone() {
+            self.auth.set_session_tokens(MatrixSessionTokens {
+                access_token,
+                refresh_token: response.refresh_token.clone(),
+            });
+        }
+
+        Ok(response)
+    }
+
+    /// Whether the given stage can be completed with the data of this builder.
+    fn supports_stage(&self, stage: &AuthType) -> bool {
+        match stage {
+            AuthType::Dummy => true,
+            AuthType::RegistrationToken => self.registration_token.is_some(),
+            AuthType::EmailIdentity => self.email_identity.is_some(),
+            AuthType::ReCaptcha => self.recaptcha.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Get the authentication data to complete the given stage.
+    async fn auth_data(&self, stage: &AuthType, info: &UiaaInfo) -> Result<Option<AuthData>> {
+        let session = info.session.clone();
+
+        let auth = match stage {
+            AuthType::Dummy => {
+                Some(AuthData::Dummy(assign!(uiaa::Dummy::new(), { session: session })))
+            }
+            AuthType::RegistrationToken => self.registration_token.clone().map(|token| {
+                AuthData::RegistrationToken(
+                    assign!(uiaa::RegistrationToken::new(token), { session: session }),
+                )
+            }),
+            AuthType::EmailIdentity => self.email_identity.clone().map(|credentials| {
+                AuthData::EmailIdentity(
+                    assign!(uiaa::EmailIdentity::new(credentials), { session: session }),
+                )
+            }),
+            AuthType::ReCaptcha => match &self.recaptcha {
+                Some(hook) => {
+                    let response = hook(recaptcha_public_key(info)).await?;
+                    Some(AuthData::ReCaptcha(
+                        assign!(uiaa::ReCaptcha::new(response), { session: session }),
+                    ))
+                }
+                None => None,
+            },
+            _ => None,
+        };
+
+        Ok(auth)
+    }
+}
+
+impl IntoFuture for RegisterBuilder {
+    type Output = Result<register::v3::Response>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.send())
+    }
+}
+
+/// Get the next stage to complete, in the first flow of the given UIAA info
+/// that only contains supported stages.
+fn next_stage(info: &UiaaInfo, is_supported: impl Fn(&AuthType) -> bool) -> Option<AuthType> {
+    info.flows
+        .iter()
+        .filter(|flow| flow.stages.starts_with(&info.completed))
+        .find(|flow| flow.stages[info.completed.len()..].iter().all(&is_supported))
+        .and_then(|flow| flow.stages.get(info.completed.len()).cloned())
+}
+
+/// Get the public key of the ReCaptcha from the parameters of the given UIAA
+/// info.
+fn recaptcha_public_key(info: &UiaaInfo) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Params {
+        #[serde(rename = "m.login.recaptcha")]
+        recaptcha: Option<ReCaptchaParams>,
+    }
+
+    #[derive(Deserialize)]
+    struct ReCaptchaParams {
+        public_key: String,
+    }
+
+    let params = serde_json::from_str::<Params>(info.params.get()).ok()?;
+    params.recaptcha.map(|recaptcha| recaptcha.public_key)
+}
+
+impl MatrixAuth {
+    /// Ask the homeserver to send an email to the given address, to validate
+    /// it for the `m.login.email.identity` stage of a registration.
+    ///
+    /// The returned session ID, and the same client secret, must then be
+    /// given to [`RegisterBuilder::email_identity()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `email` - The email address to validate.
+    ///
+    /// * `client_secret` - A secret generated by the client, like with
+    ///   [`ClientSecret::new()`](ruma::ClientSecret::new).
+    ///
+    /// * `send_attempt` - The number of the attempt. A new email is only sent
+    ///   if it's higher than the previous attempt with the same client secret.
+    pub async fn request_registration_email_token(
+        &self,
+        email: &str,
+        client_secret: OwnedClientSecret,
+        send_attempt: UInt,
+    ) -> Result<OwnedSessionId> {
+        let request = request_registration_token_via_email::v3::Request::new(
+            client_secret,
+            email.to_owned(),
+            send_attempt,
+        );
+        let response = self.client.send(request).await?;
+
+        Ok(response.sid)
+    }
+}
@@ -0,0 +1,293 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Handle `matrix:` and `https://matrix.to` URIs, like the ones an app
+//! receives when it is registered as the handler of the `matrix` URI scheme.
+
+use matrix_sdk_base::RoomState;
+use ruma::{
+    matrix_uri::{MatrixId, UriAction as RumaUriAction},
+    IdParseError, MatrixToUri, MatrixUri, OwnedEventId, OwnedRoomId, OwnedServerName, OwnedUserId,
+    RoomId, RoomOrAliasId,
+};
+use thiserror::Error;
+use tracing::{debug, instrument};
+
+use crate::{Client, HttpError, Room};
+
+/// An error occurring while handling a Matrix URI.
+#[derive(Debug, Error)]
+pub enum MatrixUriError {
+    /// The URI is neither a valid `matrix:` URI nor a valid `matrix.to` link.
+    #[error("invalid Matrix URI: {0}")]
+    InvalidUri(#[from] IdParseError),
+
+    /// The URI points to an entity that isn't supported.
+    #[error("the Matrix URI points to an unsupported entity")]
+    UnsupportedId,
+
+    /// The room alias of the URI couldn't be resolved.
+    #[error("couldn't resolve the room alias: {0}")]
+    ResolveAlias(HttpError),
+}
+
+/// The action an app should execute to handle a Matrix URI, as returned by
+/// [`Client::handle_matrix_uri()`].
+#[derive(Clone, Debug)]
+pub enum UriAction {
+    /// The room is known, and the app should navigate to it.
+    ///
+    /// This is returned for rooms that are joined, and for rooms with a
+    /// pending invite or knock, for which the app would present the invite or
+    /// the knock.
+    NavigateToRoom {
+        /// The room to navigate to.
+        room: Room,
+
+        /// The event to focus on in the timeline, if any.
+        event_id: Option<OwnedEventId>,
+    },
+
+    /// The room isn't known, and the app should offer to join it, with
+    /// [`Client::join_room_by_id_or_alias()`] for example.
+    JoinRoom {
+        /// The ID of the room, with its alias resolved.
+        room_id: OwnedRoomId,
+
+        /// The servers to join the room through.
+        via: Vec<OwnedServerName>,
+
+        /// The event to focus on in the timeline after joining, if any.
+        event_id: Option<OwnedEventId>,
+
+        /// Whether the URI asks to join the room without confirmation, with
+        /// the `action=join` query parameter.
+        join_requested: bool,
+    },
+
+    /// The app should show the profile of the user.
+    ShowUser {
+        /// The ID of the user.
+        user_id: OwnedUserId,
+
+        /// Whether the URI asks to start a chat with the user, with the
+        /// `action=chat` query parameter.
+        start_chat: bool,
+    },
+}
+
+impl Client {
+    /// Parse a `matrix:` URI, or a `https://matrix.to` link, and find out the
+    /// action to execute to handle it.
+    ///
+    /// Room aliases are resolved into room IDs. When the URI doesn't include
+    /// `via` servers to join a room through, the servers returned when
+    /// resolving the alias are used, or else the server of the room ID.
+    ///
+    /// Nothing is executed, it's up to the app to execute the returned
+    /// [`UriAction`], so that the user can confirm it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, matrix_uri::UriAction};
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// match client.handle_matrix_uri("matrix:r/somewhere:example.org?action=join").await? {
+    ///     UriAction::NavigateToRoom { .. } => { /* open the room */ }
+    ///     UriAction::JoinRoom { room_id, via, .. } => {
+    ///         client.join_room_by_id_or_alias((&*room_id).into(), &via).await?;
+    ///     }
+    ///     UriAction::ShowUser { .. } => { /* show the profile */ }
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn handle_matrix_uri(&self, uri: &str) -> Result<UriAction, MatrixUriError> {
+        let (id, via, action) = match MatrixUri::parse(uri) {
+            Ok(uri) => (uri.id().clone(), uri.via().to_owned(), uri.action().cloned()),
+            Err(error) => {
+                // Try again as a matrix.to link, but report the error of the `matrix:` URI
+                // if it's neither.
+                let uri = MatrixToUri::parse(uri).map_err(|_| error)?;
+                (uri.id().clone(), uri.via().to_owned(), None)
+            }
+        };
+
+        debug!(?id, ?via, ?action, "Parsed Matrix URI");
+
+        let (room_or_alias_id, event_id) = match id {
+            MatrixId::User(user_id) => {
+                return Ok(UriAction::ShowUser {
+                    user_id,
+                    start_chat: action == Some(RumaUriAction::Chat),
+                });
+            }
+            MatrixId::Room(room_id) => (room_id.into(), None),
+            MatrixId::RoomAlias(alias) => (alias.into(), None),
+            MatrixId::Event(room_or_alias_id, event_id) => (room_or_alias_id, Some(event_id)),
+            _ => return Err(MatrixUriError::UnsupportedId),
+        };
+
+        let (room_id, via) = self.resolve_room_or_alias_id(&room_or_alias_id, via).await?;
+
+        if let Some(room) = self.get_room(&room_id) {
+            if matches!(room.state(), RoomState::Joined | RoomState::Invited | RoomState::Knocked) {
+                return Ok(UriAction::NavigateToRoom { room, event_id });
+            }
+        }
+
+        Ok(UriAction::JoinRoom {
+            room_id,
+            via,
+            event_id,
+            join_requested: action == Some(RumaUriAction::Join),
+        })
+    }
+
+    /// Get the room ID and the servers to join the room through, for the
+    /// given room ID or alias.
+    async fn resolve_room_or_alias_id(
+        &self,
+        room_or_alias_id: &RoomOrAliasId,
+        via: Vec<OwnedServerName>,
+    ) -> Result<(OwnedRoomId, Vec<OwnedServerName>), MatrixUriError> {
+        let (room_id, via) = match <&RoomId>::try_from(room_or_alias_id) {
+            Ok(room_id) => (room_id.to_owned(), via),
+            Err(alias) => {
+                let response =
+                    self.resolve_room_alias(alias).await.map_err(MatrixUriError::ResolveAlias)?;
+                let via = if via.is_empty() { response.servers } else { via };
+                (response.room_id, via)
+            }
+        };
+
+        let via = via_or_room_server(via, &room_id);
+
+        Ok((room_id, via))
+    }
+}
+
+/// Use the server of the room ID to join the room through, if no other
+/// server is known.
+fn via_or_room_server(via: Vec<OwnedServerName>, room_id: &RoomId) -> Vec<OwnedServerName> {
+    if !via.is_empty() {
+        return via;
+    }
+
+    <&RoomOrAliasId>::from(room_id).server_name().map(ToOwned::to_owned).into_iter().collect()
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use assert_matches2::assert_let;
+    use matrix_sdk_test::async_test;
+    use ruma::{event_id, owned_server_name, room_id, user_id};
+
+    use super::{MatrixUriError, UriAction};
+    use crate::test_utils::mocks::MatrixMockServer;
+
+    #[async_test]
+    async fn test_handle_matrix_uri_for_user() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        assert_let!(
+            Ok(UriAction::ShowUser { user_id, start_chat }) =
+                client.handle_matrix_uri("matrix:u/alice:example.org?action=chat").await
+        );
+        assert_eq!(user_id, user_id!("@alice:example.org"));
+        assert!(start_chat);
+
+        assert_let!(
+            Ok(UriAction::ShowUser { user_id, start_chat }) =
+                client.handle_matrix_uri("https://matrix.to/#/@bob:example.org").await
+        );
+        assert_eq!(user_id, user_id!("@bob:example.org"));
+        assert!(!start_chat);
+    }
+
+    #[async_test]
+    async fn test_handle_matrix_uri_for_unknown_room() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        server
+            .mock_room_directory_resolve_alias()
+            .ok("!room:example.org", vec!["other.org".to_owned()])
+            .expect(2)
+            .mount()
+            .await;
+
+        // The servers of the alias are used when the URI has none.
+        assert_let!(
+            Ok(UriAction::JoinRoom { room_id, via, event_id, join_requested }) =
+                client.handle_matrix_uri("matrix:r/somewhere:example.org?action=join").await
+        );
+        assert_eq!(room_id, room_id!("!room:example.org"));
+        assert_eq!(via, vec![owned_server_name!("other.org")]);
+        assert!(event_id.is_none());
+        assert!(join_requested);
+
+        // The servers of the URI are preferred.
+        assert_let!(
+            Ok(UriAction::JoinRoom { via, event_id, join_requested, .. }) = client
+                .handle_matrix_uri("matrix:r/somewhere:example.org/e/event?via=third.org")
+                .await
+        );
+        assert_eq!(via, vec![owned_server_name!("third.org")]);
+        assert_eq!(event_id.as_deref(), Some(event_id!("$event")));
+        assert!(!join_requested);
+
+        // The server of the room ID is used as a last resort.
+        assert_let!(
+            Ok(UriAction::JoinRoom { via, .. }) =
+                client.handle_matrix_uri("matrix:roomid/room:example.org").await
+        );
+        assert_eq!(via, vec![owned_server_name!("example.org")]);
+    }
+
+    #[async_test]
+    async fn test_handle_matrix_uri_for_joined_room() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!room:example.org");
+
+        server.sync_joined_room(&client, room_id).await;
+
+        assert_let!(
+            Ok(UriAction::NavigateToRoom { room, event_id }) =
+                client.handle_matrix_uri("https://matrix.to/#/!room:example.org/$event").await
+        );
+        assert_eq!(room.room_id(), room_id);
+        assert_eq!(event_id.as_deref(), Some(event_id!("$event")));
+    }
+
+    #[async_test]
+    async fn test_handle_matrix_uri_errors() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        server.mock_room_directory_resolve_alias().not_found().expect(1).mount().await;
+
+        assert_let!(
+            Err(MatrixUriError::InvalidUri(_)) =
+                client.handle_matrix_uri("https://example.org").await
+        );
+        assert_let!(
+            Err(MatrixUriError::ResolveAlias(_)) =
+                client.handle_matrix_uri("matrix:r/nowhere:example.org").await
+        );
+    }
+}
@@ -24,6 +24,7 @@ use std::{fmt, fs::File, path::Path};
 use eyeball::SharedObservable;
 use futures_util::future::try_join;
 pub use matrix_sdk_base::media::*;
+use matrix_sdk_base::{StateStoreDataKey, StateStoreDataValue};
 use mime::Mime;
 use ruma::{
     api::{
@@ -38,7 +39,11 @@ use ruma::{
 use tempfile::{Builder as TempFileBuilder, NamedTempFile, TempDir};
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::{fs::File as TokioFile, io::AsyncWriteExt};
+#[cfg(not(target_arch = "wasm32"))]
+use tracing::warn;
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::HttpError;
 use crate::{
     attachment::Thumbnail, config::RequestConfig, futures::SendRequest, Client, Error, Result,
     TransmissionProgress,
@@ -141,6 +146,15 @@ pub enum MediaError {
     /// Local-only media content was not found.
     #[error("local-only media content was not found")]
     LocalMediaNotFound,
+
+    /// Only `http` and `https` URLs can be previewed.
+    #[error("only http and https URLs can be previewed")]
+    UnsupportedPreviewUrl,
+
+    /// An image couldn't be decoded or encoded.
+    #[cfg(feature = "image")]
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
 }
 
 /// `IntoFuture` returned by [`Media::upload`].
@@ -419,27 +433,7 @@ impl Media {
             }
         };
 
-        // Use the authenticated endpoints when the server supports Matrix 1.11 or the
-        // authenticated media stable feature.
-        const AUTHENTICATED_MEDIA_STABLE_FEATURE: &str = "org.matrix.msc3916.stable";
-
-        let (use_auth, request_config) =
-            if self.client.server_versions().await?.contains(&MatrixVersion::V1_11) {
-                (true, None)
-            } else if self
-                .client
-                .unstable_features()
-                .await?
-                .get(AUTHENTICATED_MEDIA_STABLE_FEATURE)
-                .is_some_and(|is_supported| *is_supported)
-            {
-                // We need to force the use of the stable endpoint with the Matrix version
-                // because Ruma does not handle stable features.
-                let request_config = self.client.request_config();
-                (true, Some(request_config.force_matrix_version(MatrixVersion::V1_11)))
-            } else {
-                (false, None)
-            };
+        let (use_auth, request_config) = self.authenticated_media_config().await?;
 
         let content: Vec<u8> = match &request.source {
             MediaSource::Encrypted(file) => {
@@ -525,6 +519,143 @@ impl Media {
         Ok(content)
     }
 
+    /// Download a media file's content to the given path, without loading it
+    /// in memory.
+    ///
+    /// If the content is encrypted and encryption is enabled, it is decrypted
+    /// while it is downloaded, and the SHA-256 hash from its encryption info is
+    /// checked once the download is complete.
+    ///
+    /// The media cache isn't used. If the download fails, or if the hash
+    /// doesn't match, the file at `path` is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source of the media file.
+    ///
+    /// * `path` - The path of the file to write the content to. It is created
+    ///   if it doesn't exist, and truncated otherwise.
+    ///
+    /// * `progress` - An observable to report the progress of the download.
+    ///   The total is only known if the homeserver sends the size of the
+    ///   content.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn download_to_file(
+        &self,
+        source: &MediaSource,
+        path: &Path,
+        progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<()> {
+        let mut file = TokioFile::create(path).await?;
+
+        let result = self.download_to_file_inner(source, &mut file, progress).await;
+
+        if result.is_err() {
+            drop(file);
+            // Don't leave a partial or tampered file behind.
+            if let Err(err) = tokio::fs::remove_file(path).await {
+                warn!("Couldn't remove a partially downloaded media file: {err}");
+            }
+        }
+
+        result
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn download_to_file_inner(
+        &self,
+        source: &MediaSource,
+        file: &mut TokioFile,
+        progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<()> {
+        if let Some(uri) = Self::as_local_uri(source) {
+            let content = self.get_local_media_content(uri).await?;
+            progress.set(TransmissionProgress { current: content.len(), total: content.len() });
+            file.write_all(&content).await?;
+            file.sync_all().await?;
+            return Ok(());
+        }
+
+        let uri = match source {
+            MediaSource::Plain(uri) => uri,
+            MediaSource::Encrypted(file) => &file.url,
+        };
+
+        let (use_auth, request_config) = self.authenticated_media_config().await?;
+
+        let mut response = if use_auth {
+            let request = authenticated_media::get_content::v1::Request::from_uri(uri)?;
+            self.client.send(request).with_request_config(request_config).streamed().await?
+        } else {
+            #[allow(deprecated)]
+            let request = media::get_content::v3::Request::from_url(uri)?;
+            self.client.send(request).streamed().await?
+        };
+
+        #[cfg(feature = "e2e-encryption")]
+        let mut decryptor = match source {
+            MediaSource::Encrypted(file) => {
+                Some(matrix_sdk_base::crypto::ChunkedAttachmentDecryptor::new(
+                    file.as_ref().clone().into(),
+                )?)
+            }
+            MediaSource::Plain(_) => None,
+        };
+
+        let total = response.content_length().and_then(|len| len.try_into().ok()).unwrap_or(0);
+        progress.set(TransmissionProgress { current: 0, total });
+
+        while let Some(chunk) = response.chunk().await.map_err(HttpError::from)? {
+            #[cfg(feature = "e2e-encryption")]
+            let chunk = match &mut decryptor {
+                Some(decryptor) => {
+                    let mut chunk = chunk.to_vec();
+                    decryptor.decrypt_chunk(&mut chunk);
+                    chunk.into()
+                }
+                None => chunk,
+            };
+
+            file.write_all(&chunk).await?;
+            progress.update(|p| p.current += chunk.len());
+        }
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(decryptor) = decryptor {
+            decryptor.finish()?;
+        }
+
+        // Make sure the file metadata is flushed to disk.
+        file.sync_all().await?;
+
+        Ok(())
+    }
+
+    /// Whether the authenticated media endpoints should be used, along with
+    /// the request config to use them.
+    pub(crate) async fn authenticated_media_config(&self) -> Result<(bool, Option<RequestConfig>)> {
+        // Use the authenticated endpoints when the server supports Matrix 1.11 or the
+        // authenticated media stable feature.
+        const AUTHENTICATED_MEDIA_STABLE_FEATURE: &str = "org.matrix.msc3916.stable";
+
+        if self.client.server_versions().await?.contains(&MatrixVersion::V1_11) {
+            Ok((true, None))
+        } else if self
+            .client
+            .unstable_features()
+            .await?
+            .get(AUTHENTICATED_MEDIA_STABLE_FEATURE)
+            .is_some_and(|is_supported| *is_supported)
+        {
+            // We need to force the use of the stable endpoint with the Matrix version
+            // because Ruma does not handle stable features.
+            let request_config = self.client.request_config();
+            Ok((true, Some(request_config.force_matrix_version(MatrixVersion::V1_11))))
+        } else {
+            Ok((false, None))
+        }
+    }
+
     /// Get a media file's content that is only available in the media cache.
     ///
     /// # Arguments
@@ -671,6 +802,144 @@ impl Media {
         Ok(())
     }
 
+    /// Get the policy choosing which media can be downloaded automatically.
+    ///
+    /// It is loaded from the store the first time, and defaults to a policy
+    /// that allows everything.
+    pub async fn media_policy(&self) -> Result<MediaPolicy> {
+        let mut policy = self.client.inner.media_policy.lock().await;
+
+        if let Some(policy) = &*policy {
+            return Ok(policy.clone());
+        }
+
+        let loaded = self
+            .client
+            .store()
+            .get_kv_data(StateStoreDataKey::MediaPolicy)
+            .await?
+            .and_then(StateStoreDataValue::into_media_policy)
+            .unwrap_or_default();
+
+        Ok(policy.insert(loaded).clone())
+    }
+
+    /// Set the policy choosing which media can be downloaded automatically.
+    ///
+    /// It is applied immediately and persisted in the store.
+    pub async fn set_media_policy(&self, policy: MediaPolicy) -> Result<()> {
+        let mut current = self.client.inner.media_policy.lock().await;
+
+        self.client
+            .store()
+            .set_kv_data(
+                StateStoreDataKey::MediaPolicy,
+                StateStoreDataValue::MediaPolicy(policy.clone()),
+            )
+            .await?;
+        *current = Some(policy);
+
+        Ok(())
+    }
+
+    /// Get the type of the network the device is connected to, as last set
+    /// with [`Media::set_network_type()`].
+    pub fn network_type(&self) -> NetworkType {
+        *self.client.inner.network_type.read().unwrap()
+    }
+
+    /// Set the type of the network the device is connected to, to choose the
+    /// [`NetworkMediaPolicy`] to apply.
+    ///
+    /// It should be called by the application every time the network changes.
+    /// It is not persisted, and defaults to [`NetworkType::Unknown`].
+    pub fn set_network_type(&self, network_type: NetworkType) {
+        *self.client.inner.network_type.write().unwrap() = network_type;
+    }
+
+    /// Whether a media with the given format can be downloaded automatically,
+    /// according to the [`MediaPolicy`] and the current [`NetworkType`].
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format of the media.
+    ///
+    /// * `size` - The size of the file, in bytes, if known.
+    pub async fn can_auto_download(
+        &self,
+        format: &MediaFormat,
+        size: Option<UInt>,
+    ) -> Result<bool> {
+        let policy = self.media_policy().await?.for_network(self.network_type());
+        Ok(policy.allows_auto_download(format, size))
+    }
+
+    /// Get the file of the given media event content, if it can be downloaded
+    /// automatically according to the [`MediaPolicy`].
+    ///
+    /// Returns `Ok(None)` if the event content has no file, or if the policy
+    /// doesn't allow to download it automatically. It can still be downloaded
+    /// with [`Media::get_file()`] when the user requests it explicitly.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_content` - The media event content.
+    ///
+    /// * `use_cache` - If we should use the media cache for this file.
+    pub async fn auto_download_file(
+        &self,
+        event_content: &impl MediaEventContent,
+        use_cache: bool,
+    ) -> Result<Option<Vec<u8>>> {
+        if !self.can_auto_download(&MediaFormat::File, event_content.file_size()).await? {
+            return Ok(None);
+        }
+
+        self.get_file(event_content, use_cache).await
+    }
+
+    /// Get a thumbnail of the given media event content, if it can be
+    /// downloaded automatically according to the [`MediaPolicy`].
+    ///
+    /// Returns `Ok(None)` if the event content has no thumbnail, or if the
+    /// policy doesn't allow to download it automatically. It can still be
+    /// downloaded with [`Media::get_thumbnail()`] when the user requests it
+    /// explicitly.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_content` - The media event content.
+    ///
+    /// * `settings` - The _desired_ settings of the thumbnail. The actual
+    ///   thumbnail may not match the settings specified.
+    ///
+    /// * `use_cache` - If we should use the media cache for this thumbnail.
+    pub async fn auto_download_thumbnail(
+        &self,
+        event_content: &impl MediaEventContent,
+        settings: MediaThumbnailSettings,
+        use_cache: bool,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(source) = event_content.thumbnail_source() else { return Ok(None) };
+
+        // The server can't generate thumbnails of encrypted media, so when an
+        // encrypted media has no thumbnail of its own, the whole file would be
+        // downloaded.
+        let format = if matches!(source, MediaSource::Encrypted(_))
+            && event_content.source().is_some_and(|file| file.unique_key() == source.unique_key())
+        {
+            MediaFormat::File
+        } else {
+            MediaFormat::Thumbnail(settings.clone())
+        };
+
+        if !self.can_auto_download(&format, event_content.file_size()).await? {
+            return Ok(None);
+        }
+
+        self.get_thumbnail(event_content, settings, use_cache).await
+    }
+
     /// Upload the file bytes in `data` and return the source information.
     pub(crate) async fn upload_plain_media_and_thumbnail(
         &self,
@@ -36,9 +36,11 @@ use self::{command::Command, rule_commands::RuleCommands, rules::Rules};
 mod command;
 mod rule_commands;
 mod rules;
+mod temporary_mutes;
 
 pub use matrix_sdk_base::notification_settings::RoomNotificationMode;
 
+pub(crate) use self::temporary_mutes::TemporaryRoomMutes;
 use crate::{
     config::RequestConfig, error::NotificationSettingsError, event_handler::EventHandlerDropGuard,
     Client, Result,
@@ -82,6 +84,60 @@ impl From<bool> for IsOneToOne {
     }
 }
 
+/// The push rule from which the notification mode of a room is resolved, see
+/// [`NotificationSettings::explain_room_notification_mode()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomNotificationModeSource {
+    /// An enabled `Override` rule with a condition on the room, and without a
+    /// `Notify` action, mutes the room.
+    Override {
+        /// The ID of the `Override` rule.
+        rule_id: String,
+    },
+    /// The `Room` rule of the room decides whether all the messages notify.
+    Room {
+        /// The ID of the `Room` rule, which is the ID of the room.
+        rule_id: String,
+    },
+    /// The room has no user-defined rule, the predefined `Underride` rule for
+    /// this kind of room decides whether all the messages notify.
+    Underride {
+        /// The ID of the predefined `Underride` rule.
+        rule_id: String,
+    },
+    /// The room has no user-defined rule, and the predefined `Underride` rule
+    /// for this kind of room is disabled or missing, so only mentions and
+    /// keywords notify.
+    Default,
+}
+
+impl RoomNotificationModeSource {
+    /// The kind and the ID of the push rule, if the mode is resolved from a
+    /// push rule.
+    pub fn rule(&self) -> Option<(RuleKind, &str)> {
+        match self {
+            Self::Override { rule_id } => Some((RuleKind::Override, rule_id)),
+            Self::Room { rule_id } => Some((RuleKind::Room, rule_id)),
+            Self::Underride { rule_id } => Some((RuleKind::Underride, rule_id)),
+            Self::Default => None,
+        }
+    }
+}
+
+/// The notification mode of a room, and why the room has this mode, as
+/// returned by [`NotificationSettings::explain_room_notification_mode()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomNotificationModeExplanation {
+    /// The notification mode of the room.
+    pub mode: RoomNotificationMode,
+    /// The push rule from which the mode is resolved.
+    pub source: RoomNotificationModeSource,
+    /// The other user-defined rules matching the room, which didn't decide
+    /// its mode, like a disabled `Override` rule, or a `Room` rule shadowed
+    /// by a muting `Override` rule.
+    pub shadowed_rules: Vec<(RuleKind, String)>,
+}
+
 /// A high-level API to manage the client owner's push notification settings.
 #[derive(Debug, Clone)]
 pub struct NotificationSettings {
@@ -105,6 +161,9 @@ impl NotificationSettings {
         let changes_sender = broadcast::Sender::new(100);
         let rules = Arc::new(RwLock::new(Rules::new(ruleset)));
 
+        // Lift the mutes that expired while the client wasn't running.
+        client.inner.temporary_room_mutes.start(&client);
+
         // Listen for PushRulesEvent
         let push_rules_event_handler_handle = client.add_event_handler({
             let changes_sender = changes_sender.clone();
@@ -150,6 +209,28 @@ impl NotificationSettings {
         self.rules.read().await.get_default_room_notification_mode(is_encrypted, is_one_to_one)
     }
 
+    /// Get the notification mode of a room, along with the push rule it is
+    /// resolved from.
+    ///
+    /// This allows settings UIs to tell why a room is muted, for example, and
+    /// to offer to fix it precisely, like deleting the user-defined rules of
+    /// the room with [`Self::delete_user_defined_room_rules()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room
+    /// * `is_encrypted` - `Yes` if the room is encrypted
+    /// * `is_one_to_one` - `Yes` if the room is a direct chat involving two
+    ///   people
+    pub async fn explain_room_notification_mode(
+        &self,
+        room_id: &RoomId,
+        is_encrypted: IsEncrypted,
+        is_one_to_one: IsOneToOne,
+    ) -> RoomNotificationModeExplanation {
+        self.rules.read().await.explain_room_notification_mode(room_id, is_encrypted, is_one_to_one)
+    }
+
     /// Get all room IDs for which a user-defined rule exists.
     pub async fn get_rooms_with_user_defined_rules(&self, enabled: Option<bool>) -> Vec<String> {
         self.rules.read().await.get_rooms_with_user_defined_rules(enabled)
@@ -266,10 +347,24 @@ impl NotificationSettings {
     }
 
     /// Set the notification mode for a room.
+    ///
+    /// This cancels any mute set with [`Self::mute_room_until()`] for the
+    /// room.
     pub async fn set_room_notification_mode(
         &self,
         room_id: &RoomId,
         mode: RoomNotificationMode,
+    ) -> Result<(), NotificationSettingsError> {
+        self.apply_room_notification_mode(room_id, mode).await?;
+        self.cancel_temporary_mute(room_id).await
+    }
+
+    /// Set the notification mode for a room, without touching its temporary
+    /// mute.
+    async fn apply_room_notification_mode(
+        &self,
+        room_id: &RoomId,
+        mode: RoomNotificationMode,
     ) -> Result<(), NotificationSettingsError> {
         let rules = self.rules.read().await.clone();
 
@@ -319,9 +414,22 @@ impl NotificationSettings {
     }
 
     /// Delete all user defined rules for a room.
+    ///
+    /// This cancels any mute set with [`Self::mute_room_until()`] for the
+    /// room.
     pub async fn delete_user_defined_room_rules(
         &self,
         room_id: &RoomId,
+    ) -> Result<(), NotificationSettingsError> {
+        self.apply_delete_user_defined_room_rules(room_id).await?;
+        self.cancel_temporary_mute(room_id).await
+    }
+
+    /// Delete all user defined rules for a room, without touching its
+    /// temporary mute.
+    async fn apply_delete_user_defined_room_rules(
+        &self,
+        room_id: &RoomId,
     ) -> Result<(), NotificationSettingsError> {
         let rules = self.rules.read().await.clone();
 
@@ -524,26 +632,36 @@ impl NotificationSettings {
 // The http mocking library is not supported for wasm32
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
-    use std::sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
     };
 
     use assert_matches::assert_matches;
+    use matrix_sdk_base::{
+        StateChanges, StateStoreDataKey, StateStoreDataValue, TemporaryRoomMute,
+    };
     use matrix_sdk_test::{
         async_test,
         notification_settings::{build_ruleset, get_server_default_ruleset},
         test_json,
     };
     use ruma::{
+        events::GlobalAccountDataEventType,
         push::{
             Action, AnyPushRuleRef, NewPatternedPushRule, NewPushRule, PredefinedOverrideRuleId,
             PredefinedUnderrideRuleId, RuleKind,
         },
-        OwnedRoomId, RoomId,
+        serde::Raw,
+        uint, MilliSecondsSinceUnixEpoch, OwnedRoomId, RoomId,
     };
     use serde_json::json;
     use stream_assert::{assert_next_eq, assert_pending};
+    use tokio::time::timeout;
     use tokio_stream::wrappers::BroadcastStream;
     use wiremock::{
         matchers::{header, method, path, path_regex},
@@ -557,6 +675,7 @@ mod tests {
             IsEncrypted, IsOneToOne, NotificationSettings, RoomNotificationMode,
         },
         test_utils::logged_in_client,
+        utils::sleep,
         Client,
     };
 
@@ -1095,6 +1214,113 @@ mod tests {
         assert!(settings.get_user_defined_room_notification_mode(&room_id).await.is_none());
     }
 
+    #[async_test]
+    async fn test_mute_room_until() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+        Mock::given(method("DELETE")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+        let client = logged_in_client(Some(server.uri())).await;
+        let room_id = get_test_room_id();
+        let until = MilliSecondsSinceUnixEpoch(uint!(4_000_000_000_000));
+
+        // Start with a user defined mode
+        let settings = from_insert_rules(&client, vec![(RuleKind::Room, &room_id, true)]);
+
+        // Mute the room for a while
+        settings.mute_room_until(&room_id, until).await.unwrap();
+        assert_eq!(
+            settings.get_user_defined_room_notification_mode(&room_id).await,
+            Some(RoomNotificationMode::Mute)
+        );
+        assert_eq!(settings.room_muted_until(&room_id).await.unwrap(), Some(until));
+
+        // The mute is persisted with the mode to restore
+        let mutes = client
+            .store()
+            .get_kv_data(StateStoreDataKey::TemporaryRoomMutes)
+            .await
+            .unwrap()
+            .and_then(StateStoreDataValue::into_temporary_room_mutes)
+            .unwrap();
+        assert_eq!(
+            mutes.get(&room_id).unwrap().previous_mode,
+            Some(RoomNotificationMode::AllMessages)
+        );
+
+        // Setting the mode explicitly cancels the mute
+        settings
+            .set_room_notification_mode(&room_id, RoomNotificationMode::MentionsAndKeywordsOnly)
+            .await
+            .unwrap();
+        assert_eq!(settings.room_muted_until(&room_id).await.unwrap(), None);
+    }
+
+    #[async_test]
+    async fn test_mute_room_until_expired() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+        Mock::given(method("DELETE")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+        let client = logged_in_client(Some(server.uri())).await;
+        let room_id = get_test_room_id();
+        let until = MilliSecondsSinceUnixEpoch(uint!(4_000_000_000_000));
+
+        // Start with a user defined mode, and mute the room for a while
+        let settings = from_insert_rules(&client, vec![(RuleKind::Room, &room_id, true)]);
+        settings.mute_room_until(&room_id, until).await.unwrap();
+
+        // The muted room is synced back
+        let ruleset = settings.rules.read().await.ruleset.clone();
+        let mut changes = StateChanges::default();
+        changes.account_data.insert(
+            GlobalAccountDataEventType::PushRules,
+            Raw::new(&json!({ "type": "m.push_rules", "content": { "global": ruleset } }))
+                .unwrap()
+                .cast(),
+        );
+        client.store().save_changes(&changes).await.unwrap();
+
+        let is_room_rule_put = |request: &wiremock::Request| {
+            request.method == wiremock::http::Method::PUT
+                && request.url.path().contains("/pushrules/global/room/")
+        };
+        let room_rule_puts = server.received_requests().await.unwrap();
+        let room_rule_puts = room_rule_puts.iter().filter(|r| is_room_rule_put(*r)).count();
+
+        // The mute expires
+        let mutes = BTreeMap::from([(
+            room_id.clone(),
+            TemporaryRoomMute {
+                until: MilliSecondsSinceUnixEpoch(uint!(1)),
+                previous_mode: Some(RoomNotificationMode::AllMessages),
+            },
+        )]);
+        client
+            .store()
+            .set_kv_data(
+                StateStoreDataKey::TemporaryRoomMutes,
+                StateStoreDataValue::TemporaryRoomMutes(mutes),
+            )
+            .await
+            .unwrap();
+        client.inner.temporary_room_mutes.notify_changed();
+
+        // The previous mode is restored
+        timeout(Duration::from_secs(5), async {
+            loop {
+                let requests = server.received_requests().await.unwrap();
+                if requests.iter().filter(|r| is_room_rule_put(*r)).count() > room_rule_puts {
+                    break;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the notification mode of the room should have been restored");
+
+        // And the mute is forgotten
+        assert_eq!(settings.room_muted_until(&room_id).await.unwrap(), None);
+    }
+
     #[async_test]
     async fn test_unmute_room_default_mode() {
         let server = MockServer::start().await;
@@ -4,13 +4,16 @@ use imbl::HashSet;
 use indexmap::IndexSet;
 use ruma::{
     push::{
-        AnyPushRuleRef, PatternedPushRule, PredefinedContentRuleId, PredefinedOverrideRuleId,
-        PredefinedUnderrideRuleId, PushCondition, RuleKind, Ruleset,
+        AnyPushRuleRef, ConditionalPushRule, PatternedPushRule, PredefinedContentRuleId,
+        PredefinedOverrideRuleId, PredefinedUnderrideRuleId, PushCondition, RuleKind, Ruleset,
     },
     RoomId,
 };
 
-use super::{command::Command, rule_commands::RuleCommands, RoomNotificationMode};
+use super::{
+    command::Command, rule_commands::RuleCommands, RoomNotificationMode,
+    RoomNotificationModeExplanation, RoomNotificationModeSource,
+};
 use crate::{
     error::NotificationSettingsError,
     notification_settings::{IsEncrypted, IsOneToOne},
@@ -62,13 +65,9 @@ impl Rules {
         custom_rules
     }
 
-    /// Gets the user defined notification mode for a room.
-    pub(crate) fn get_user_defined_room_notification_mode(
-        &self,
-        room_id: &RoomId,
-    ) -> Option<RoomNotificationMode> {
-        // Search for an enabled `Override` rule
-        if self.ruleset.override_.iter().any(|x| {
+    /// Gets the enabled `Override` rule muting a room, if any.
+    fn get_muting_override_rule(&self, room_id: &RoomId) -> Option<&ConditionalPushRule> {
+        self.ruleset.override_.iter().find(|x| {
             // enabled
             x.enabled &&
             // with a condition of type `EventMatch` for this `room_id`
@@ -79,7 +78,16 @@ impl Rules {
             )) &&
             // and without a Notify action
             !x.actions.iter().any(|x| x.should_notify())
-        }) {
+        })
+    }
+
+    /// Gets the user defined notification mode for a room.
+    pub(crate) fn get_user_defined_room_notification_mode(
+        &self,
+        room_id: &RoomId,
+    ) -> Option<RoomNotificationMode> {
+        // Search for an enabled `Override` rule
+        if self.get_muting_override_rule(room_id).is_some() {
             return Some(RoomNotificationMode::Mute);
         }
 
@@ -126,6 +134,58 @@ impl Rules {
         }
     }
 
+    /// Gets the notification mode for a room, along with the push rule it is
+    /// resolved from.
+    ///
+    /// The mode is the same as the user defined one if any, or the default
+    /// one otherwise.
+    pub(crate) fn explain_room_notification_mode(
+        &self,
+        room_id: &RoomId,
+        is_encrypted: IsEncrypted,
+        is_one_to_one: IsOneToOne,
+    ) -> RoomNotificationModeExplanation {
+        let mode_for_rule = |triggers_notification: bool| {
+            if triggers_notification {
+                RoomNotificationMode::AllMessages
+            } else {
+                RoomNotificationMode::MentionsAndKeywordsOnly
+            }
+        };
+
+        let (mode, source) = if let Some(rule) = self.get_muting_override_rule(room_id) {
+            let source = RoomNotificationModeSource::Override { rule_id: rule.rule_id.clone() };
+            (RoomNotificationMode::Mute, source)
+        } else if let Some(rule) = self.ruleset.get(RuleKind::Room, room_id) {
+            let source = RoomNotificationModeSource::Room { rule_id: rule.rule_id().to_owned() };
+            (mode_for_rule(rule.triggers_notification()), source)
+        } else {
+            let rule_id = get_predefined_underride_room_rule_id(is_encrypted, is_one_to_one);
+
+            match self.ruleset.get(RuleKind::Underride, rule_id.as_str()).filter(|r| r.enabled()) {
+                Some(rule) => {
+                    let rule_id = rule_id.as_str().to_owned();
+                    (
+                        mode_for_rule(rule.triggers_notification()),
+                        RoomNotificationModeSource::Underride { rule_id },
+                    )
+                }
+                None => (
+                    RoomNotificationMode::MentionsAndKeywordsOnly,
+                    RoomNotificationModeSource::Default,
+                ),
+            }
+        };
+
+        let shadowed_rules = self
+            .get_custom_rules_for_room(room_id)
+            .into_iter()
+            .filter(|(kind, rule_id)| source.rule() != Some((kind.clone(), rule_id.as_str())))
+            .collect();
+
+        RoomNotificationModeExplanation { mode, source, shadowed_rules }
+    }
+
     /// Get all room IDs for which a user-defined rule exists.
     pub(crate) fn get_rooms_with_user_defined_rules(&self, enabled: Option<bool>) -> Vec<String> {
         let test_if_enabled = enabled.is_some();
@@ -328,7 +388,7 @@ pub(crate) mod tests {
         error::NotificationSettingsError,
         notification_settings::{
             rules::{self, Rules},
-            IsEncrypted, IsOneToOne, RoomNotificationMode,
+            IsEncrypted, IsOneToOne, RoomNotificationMode, RoomNotificationModeSource,
         },
     };
 
@@ -421,6 +481,68 @@ pub(crate) mod tests {
         assert_eq!(mode, Some(RoomNotificationMode::Mute));
     }
 
+    #[async_test]
+    async fn test_explain_room_notification_mode() {
+        let room_id = get_test_room_id();
+
+        // Without user-defined rules, the predefined underride rule decides.
+        let mut ruleset = get_server_default_ruleset();
+        let explanation = Rules::new(ruleset.clone()).explain_room_notification_mode(
+            &room_id,
+            IsEncrypted::No,
+            IsOneToOne::No,
+        );
+        assert_eq!(explanation.mode, RoomNotificationMode::AllMessages);
+        assert_eq!(
+            explanation.source,
+            RoomNotificationModeSource::Underride {
+                rule_id: PredefinedUnderrideRuleId::Message.as_str().to_owned()
+            }
+        );
+        assert!(explanation.shadowed_rules.is_empty());
+
+        // If it is disabled, the default mode is used.
+        ruleset
+            .set_enabled(RuleKind::Underride, PredefinedUnderrideRuleId::Message, false)
+            .unwrap();
+        let explanation = Rules::new(ruleset).explain_room_notification_mode(
+            &room_id,
+            IsEncrypted::No,
+            IsOneToOne::No,
+        );
+        assert_eq!(explanation.mode, RoomNotificationMode::MentionsAndKeywordsOnly);
+        assert_eq!(explanation.source, RoomNotificationModeSource::Default);
+
+        // A `Room` rule decides over the predefined underride rule.
+        let ruleset = build_ruleset(vec![(RuleKind::Room, &room_id, false)]);
+        let explanation = Rules::new(ruleset).explain_room_notification_mode(
+            &room_id,
+            IsEncrypted::No,
+            IsOneToOne::No,
+        );
+        assert_eq!(explanation.mode, RoomNotificationMode::MentionsAndKeywordsOnly);
+        assert_eq!(
+            explanation.source,
+            RoomNotificationModeSource::Room { rule_id: room_id.to_string() }
+        );
+
+        // A muting `Override` rule shadows the `Room` rule.
+        let ruleset = build_ruleset(vec![
+            (RuleKind::Override, &room_id, false),
+            (RuleKind::Room, &room_id, true),
+        ]);
+        let rules = Rules::new(ruleset);
+        let explanation =
+            rules.explain_room_notification_mode(&room_id, IsEncrypted::No, IsOneToOne::No);
+        assert_eq!(explanation.mode, RoomNotificationMode::Mute);
+        assert_eq!(
+            explanation.source,
+            RoomNotificationModeSource::Override { rule_id: room_id.to_string() }
+        );
+        assert_eq!(explanation.shadowed_rules, vec![(RuleKind::Room, room_id.to_string())]);
+        assert_eq!(rules.get_user_defined_room_notification_mode(&room_id), Some(explanation.mode));
+    }
+
     #[async_test]
     async fn test_get_predefined_underride_room_rule_id() {
         assert_eq!(
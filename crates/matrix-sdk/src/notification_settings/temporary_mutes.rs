@@ -0,0 +1,262 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rooms muted until a point in time, see
+//! [`NotificationSettings::mute_room_until()`].
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use matrix_sdk_base::{StateStoreDataKey, StateStoreDataValue, StoreError, TemporaryRoomMute};
+use matrix_sdk_common::executor::{spawn, JoinHandle};
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedRoomId, RoomId};
+use tokio::sync::{Mutex, MutexGuard, Notify};
+use tracing::{debug, error, warn};
+
+use super::{NotificationSettings, RoomNotificationMode};
+use crate::{client::WeakClient, error::NotificationSettingsError, utils::sleep, Client};
+
+/// How long to wait before trying again when the temporary mutes couldn't be
+/// loaded or saved.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The state of the temporary mutes of a [`Client`].
+#[derive(Default)]
+pub(crate) struct TemporaryRoomMutes {
+    /// Lock held while the temporary mutes are read and written back to the
+    /// store.
+    store_lock: Mutex<()>,
+
+    /// Wakes the restoring task up when the temporary mutes change.
+    changed: Arc<Notify>,
+
+    /// The task restoring the notification mode of the rooms whose mute
+    /// expired.
+    task: OnceLock<JoinHandle<()>>,
+}
+
+impl TemporaryRoomMutes {
+    /// Start the task restoring the notification mode of the rooms whose mute
+    /// expired, if it's not running yet.
+    pub(crate) fn start(&self, client: &Client) {
+        self.task.get_or_init(|| {
+            spawn(restore_expired_mutes_task(WeakClient::from_client(client), self.changed.clone()))
+        });
+    }
+
+    /// Lock the temporary mutes, to modify them.
+    pub(crate) async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.store_lock.lock().await
+    }
+
+    /// Wake the restoring task up, after the temporary mutes changed.
+    pub(crate) fn notify_changed(&self) {
+        self.changed.notify_one();
+    }
+}
+
+impl Drop for TemporaryRoomMutes {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.get() {
+            task.abort();
+        }
+    }
+}
+
+/// Load the temporary mutes from the store.
+async fn load(client: &Client) -> Result<BTreeMap<OwnedRoomId, TemporaryRoomMute>, StoreError> {
+    Ok(client
+        .store()
+        .get_kv_data(StateStoreDataKey::TemporaryRoomMutes)
+        .await?
+        .and_then(StateStoreDataValue::into_temporary_room_mutes)
+        .unwrap_or_default())
+}
+
+/// Save the temporary mutes to the store.
+async fn save(
+    client: &Client,
+    mutes: BTreeMap<OwnedRoomId, TemporaryRoomMute>,
+) -> Result<(), StoreError> {
+    client
+        .store()
+        .set_kv_data(
+            StateStoreDataKey::TemporaryRoomMutes,
+            StateStoreDataValue::TemporaryRoomMutes(mutes),
+        )
+        .await
+}
+
+/// Restore the notification mode of the rooms whose mute expired, then wait
+/// for the next one to expire, for as long as the client is alive.
+async fn restore_expired_mutes_task(client: WeakClient, changed: Arc<Notify>) {
+    loop {
+        let Some(client) = client.get() else {
+            break;
+        };
+
+        let next_expiry = match restore_expired_mutes(&client).await {
+            Ok(next_expiry) => next_expiry,
+            Err(err) => {
+                warn!("Couldn't restore the notification mode of the muted rooms: {err}");
+                Some(RETRY_DELAY)
+            }
+        };
+
+        // Don't keep the client alive while waiting.
+        drop(client);
+
+        match next_expiry {
+            Some(delay) => {
+                tokio::select! {
+                    _ = sleep(delay) => {}
+                    _ = changed.notified() => {}
+                }
+            }
+            None => changed.notified().await,
+        }
+    }
+}
+
+/// Restore the notification mode of the rooms whose mute expired.
+///
+/// Returns how long to wait until the next mute expires, if any.
+async fn restore_expired_mutes(client: &Client) -> Result<Option<Duration>, StoreError> {
+    let now = MilliSecondsSinceUnixEpoch::now();
+
+    let (expired, next_expiry) = {
+        let _guard = client.inner.temporary_room_mutes.lock().await;
+
+        let mut mutes = load(client).await?;
+        let (expired, remaining): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            mutes.into_iter().partition(|(_, mute)| mute.until <= now);
+        mutes = remaining;
+
+        if !expired.is_empty() {
+            save(client, mutes.clone()).await?;
+        }
+
+        (expired, mutes.values().map(|mute| mute.until).min())
+    };
+
+    if !expired.is_empty() {
+        let settings = client.notification_settings().await;
+
+        for (room_id, mute) in expired {
+            debug!(%room_id, previous_mode = ?mute.previous_mode, "The mute of the room expired");
+
+            if let Err(err) = settings.restore_room_mode(&room_id, mute.previous_mode).await {
+                warn!(%room_id, "Couldn't restore the notification mode of the room: {err}");
+            }
+        }
+    }
+
+    Ok(next_expiry.map(|until| delay_until(now, until)))
+}
+
+/// The time from `now` until `until`.
+fn delay_until(now: MilliSecondsSinceUnixEpoch, until: MilliSecondsSinceUnixEpoch) -> Duration {
+    let millis = u64::from(until.get()).saturating_sub(now.get().into());
+    Duration::from_millis(millis)
+}
+
+/// Convert an error of the store holding the temporary mutes.
+fn store_error(error: StoreError) -> NotificationSettingsError {
+    error!("Unable to access the temporary room mutes: {error}");
+    NotificationSettingsError::UnableToAccessTemporaryMutes
+}
+
+impl NotificationSettings {
+    /// Mute a room until the given point in time.
+    ///
+    /// The room is muted right away, and its previous notification mode is
+    /// restored once `until` is reached. The mute is persisted in the store,
+    /// so a mute that expired while the client wasn't running is lifted the
+    /// next time [`Client::notification_settings()`] is called.
+    ///
+    /// Setting the notification mode of the room in the meantime cancels the
+    /// mute. Muting a room that is already muted until a point in time only
+    /// changes when it will be unmuted.
+    pub async fn mute_room_until(
+        &self,
+        room_id: &RoomId,
+        until: MilliSecondsSinceUnixEpoch,
+    ) -> Result<(), NotificationSettingsError> {
+        let temporary_mutes = &self.client.inner.temporary_room_mutes;
+        let _guard = temporary_mutes.lock().await;
+
+        let mut mutes = load(&self.client).await.map_err(store_error)?;
+        let previous_mode = match mutes.get(room_id) {
+            Some(mute) => mute.previous_mode,
+            None => self.get_user_defined_room_notification_mode(room_id).await,
+        };
+
+        self.apply_room_notification_mode(room_id, RoomNotificationMode::Mute).await?;
+
+        mutes.insert(room_id.to_owned(), TemporaryRoomMute { until, previous_mode });
+        save(&self.client, mutes).await.map_err(store_error)?;
+        temporary_mutes.notify_changed();
+
+        Ok(())
+    }
+
+    /// Get the point in time until which a room is muted, if it was muted
+    /// with [`Self::mute_room_until()`].
+    pub async fn room_muted_until(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<MilliSecondsSinceUnixEpoch>, NotificationSettingsError> {
+        let mutes = load(&self.client).await.map_err(store_error)?;
+        Ok(mutes.get(room_id).map(|mute| mute.until))
+    }
+
+    /// Forget the temporary mute of a room, if any, after its notification
+    /// mode was set explicitly.
+    pub(super) async fn cancel_temporary_mute(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(), NotificationSettingsError> {
+        let temporary_mutes = &self.client.inner.temporary_room_mutes;
+        let _guard = temporary_mutes.lock().await;
+
+        let mut mutes = load(&self.client).await.map_err(store_error)?;
+        if mutes.remove(room_id).is_some() {
+            save(&self.client, mutes).await.map_err(store_error)?;
+            temporary_mutes.notify_changed();
+        }
+
+        Ok(())
+    }
+
+    /// Restore the notification mode of a room whose mute expired, unless it
+    /// was changed since then.
+    async fn restore_room_mode(
+        &self,
+        room_id: &RoomId,
+        previous_mode: Option<RoomNotificationMode>,
+    ) -> Result<(), NotificationSettingsError> {
+        let current_mode = self.rules.read().await.get_user_defined_room_notification_mode(room_id);
+        if current_mode != Some(RoomNotificationMode::Mute) {
+            return Ok(());
+        }
+
+        match previous_mode {
+            Some(mode) => self.apply_room_notification_mode(room_id, mode).await,
+            None => self.apply_delete_user_defined_room_rules(room_id).await,
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read-only access to world-readable rooms the current user hasn't joined.
+//!
+//! See [`PeekedRoom`].
+
+use std::collections::HashSet;
+
+use eyeball::{SharedObservable, Subscriber};
+use eyeball_im::Vector;
+use matrix_sdk_common::deserialized_responses::TimelineEvent;
+use ruma::{
+    api::{client::message::get_message_events, Direction},
+    assign, uint, OwnedRoomId, RoomId, UInt,
+};
+use tokio::sync::Mutex;
+use tracing::{debug, instrument};
+
+use crate::{room_preview::RoomPreview, Client, Error, Result};
+
+/// The number of events fetched by each `/messages` request.
+const PAGINATION_LIMIT: UInt = uint!(20);
+
+/// A read-only handle on a world-readable room, which the current user can
+/// look into without joining it.
+///
+/// The room isn't part of the sync responses, so its timeline is kept up to
+/// date by polling the homeserver, with [`PeekedRoom::poll_for_updates()`].
+/// Older events can be loaded with [`PeekedRoom::paginate_backwards()`].
+#[derive(Debug)]
+pub struct PeekedRoom {
+    client: Client,
+
+    /// A preview of the room, computed out of its state when it was peeked
+    /// into.
+    preview: RoomPreview,
+
+    /// The known events of the room, in chronological order.
+    events: SharedObservable<Vector<TimelineEvent>>,
+
+    /// The pagination tokens delimiting the known events.
+    tokens: Mutex<PaginationTokens>,
+}
+
+#[derive(Debug)]
+struct PaginationTokens {
+    /// The token to paginate backwards from, or `None` if the start of the
+    /// visible history has been reached.
+    backward: Option<String>,
+
+    /// The token to paginate forwards from, to get new events.
+    forward: String,
+}
+
+impl PeekedRoom {
+    /// Peek into a room, fetching its state and its most recent events.
+    ///
+    /// Fails with [`Error::NotWorldReadable`] if the room isn't world-readable.
+    #[instrument(skip(client))]
+    pub(crate) async fn new(client: &Client, room_id: OwnedRoomId) -> Result<Self> {
+        let preview = RoomPreview::from_state_events(client, &room_id).await?;
+
+        if preview.is_world_readable != Some(true) {
+            return Err(Error::NotWorldReadable);
+        }
+
+        let request = assign!(get_message_events::v3::Request::new(room_id, Direction::Backward), {
+            limit: PAGINATION_LIMIT,
+        });
+        let response = client.send(request).await?;
+
+        let events = response.chunk.into_iter().rev().map(TimelineEvent::new).collect();
+
+        Ok(Self {
+            client: client.clone(),
+            preview,
+            events: SharedObservable::new(events),
+            tokens: Mutex::new(PaginationTokens {
+                backward: response.end,
+                forward: response.start,
+            }),
+        })
+    }
+
+    /// The ID of the peeked room.
+    pub fn room_id(&self) -> &RoomId {
+        &self.preview.room_id
+    }
+
+    /// The preview of the room, as computed when it was peeked into.
+    pub fn preview(&self) -> &RoomPreview {
+        &self.preview
+    }
+
+    /// The events of the room known so far, in chronological order.
+    pub fn events(&self) -> Vector<TimelineEvent> {
+        self.events.get()
+    }
+
+    /// Subscribe to the events of the room, in chronological order.
+    ///
+    /// A new value is observed every time new or older events are loaded.
+    pub fn subscribe(&self) -> Subscriber<Vector<TimelineEvent>> {
+        self.events.subscribe()
+    }
+
+    /// Fetch the events that happened in the room since the last time it was
+    /// polled, and append them to the timeline.
+    ///
+    /// Returns the number of new events.
+    #[instrument(skip(self), fields(room_id = ?self.room_id()))]
+    pub async fn poll_for_updates(&self) -> Result<usize> {
+        let mut tokens = self.tokens.lock().await;
+        let mut num_new_events = 0;
+
+        loop {
+            let request = assign!(
+                get_message_events::v3::Request::new(self.room_id().to_owned(), Direction::Forward),
+                { from: Some(tokens.forward.clone()), limit: PAGINATION_LIMIT }
+            );
+            let response = self.client.send(request).await?;
+
+            let chunk_is_empty = response.chunk.is_empty();
+
+            // Without an `end` token, we can't move the `from` token forward, so the next
+            // poll may return the same events again: skip the ones we already know about.
+            let new_events = {
+                let events = self.events.get();
+                let known_event_ids: HashSet<_> =
+                    events.iter().filter_map(|event| event.event_id()).collect();
+
+                response
+                    .chunk
+                    .into_iter()
+                    .map(TimelineEvent::new)
+                    .filter(|event| {
+                        !event
+                            .event_id()
+                            .is_some_and(|event_id| known_event_ids.contains(&event_id))
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            // The last page of events may be shorter than the limit, and come without an
+            // `end` token: add its events before stopping.
+            if !new_events.is_empty() {
+                num_new_events += new_events.len();
+                self.events.update(|events| events.extend(new_events));
+            }
+
+            let Some(end) = response.end else {
+                // We've caught up with the present.
+                break;
+            };
+
+            tokens.forward = end;
+
+            if chunk_is_empty {
+                break;
+            }
+        }
+
+        debug!(num_new_events, "Polled a peeked room");
+
+        Ok(num_new_events)
+    }
+
+    /// Load older events of the room, and prepend them to the timeline.
+    ///
+    /// Returns `true` if the start of the visible history has been reached,
+    /// in which case there's no point in calling this method again.
+    #[instrument(skip(self), fields(room_id = ?self.room_id()))]
+    pub async fn paginate_backwards(&self) -> Result<bool> {
+        let mut tokens = self.tokens.lock().await;
+
+        let Some(from) = tokens.backward.clone() else {
+            return Ok(true);
+        };
+
+        let request = assign!(
+            get_message_events::v3::Request::new(self.room_id().to_owned(), Direction::Backward),
+            { from: Some(from), limit: PAGINATION_LIMIT }
+        );
+        let response = self.client.send(request).await?;
+
+        tokens.backward = response.end;
+
+        self.events.update(|events| {
+            for event in response.chunk {
+                events.push_front(TimelineEvent::new(event));
+            }
+        });
+
+        Ok(tokens.backward.is_none())
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Background prefetching of what is needed to render the visible rooms of a
+//! room list.
+//!
+//! The [`RoomPrefetcher`] warms the media cache with the avatars of the rooms
+//! and of their heroes, and fetches the profiles of the heroes that the
+//! server didn't describe, so they're available from
+//! [`Client::get_profiles()`] without a request when the rooms are rendered.
+
+use std::sync::Mutex;
+
+use eyeball::SharedObservable;
+use matrix_sdk_common::executor::{spawn, JoinHandle};
+use ruma::{events::room::MediaSource, uint, OwnedMxcUri, OwnedRoomId, OwnedUserId};
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
+    Client,
+};
+
+/// A prefetcher of the avatars and profiles needed to render the visible rooms
+/// of a room list.
+///
+/// The visible rooms are given with [`RoomPrefetcher::set_visible_rooms()`].
+/// The prefetching runs in the background, and is cancelled when the visible
+/// rooms change or when the prefetcher is dropped.
+///
+/// The avatars are only downloaded if the [`MediaPolicy`] allows to download
+/// them automatically.
+///
+/// [`MediaPolicy`]: crate::media::MediaPolicy
+#[derive(Debug)]
+pub struct RoomPrefetcher {
+    client: Client,
+    avatar_format: MediaFormat,
+    task: Mutex<Option<PrefetchTask>>,
+}
+
+#[derive(Debug)]
+struct PrefetchTask {
+    handle: JoinHandle<()>,
+    /// Whether the prefetching of the visible rooms is done.
+    is_done: SharedObservable<bool>,
+}
+
+impl RoomPrefetcher {
+    /// Create a prefetcher for the rooms of the given client.
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            avatar_format: MediaFormat::Thumbnail(MediaThumbnailSettings::new(
+                uint!(96),
+                uint!(96),
+            )),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// Set the format of the avatars to prefetch.
+    ///
+    /// It should match the format used to render them, otherwise the cached
+    /// media aren't reused. Defaults to a 96×96 thumbnail.
+    pub fn avatar_format(mut self, format: MediaFormat) -> Self {
+        self.avatar_format = format;
+        self
+    }
+
+    /// Set the rooms that are currently visible, in the order in which they
+    /// should be prefetched.
+    ///
+    /// The prefetching of the previously visible rooms is cancelled.
+    pub fn set_visible_rooms(&self, room_ids: Vec<OwnedRoomId>) {
+        let client = self.client.clone();
+        let avatar_format = self.avatar_format.clone();
+        let is_done = SharedObservable::new(false);
+
+        let handle = spawn({
+            let is_done = is_done.clone();
+
+            async move {
+                prefetch(client, avatar_format, room_ids).await;
+                is_done.set(true);
+            }
+        });
+
+        let task = PrefetchTask { handle, is_done };
+
+        if let Some(previous_task) = self.task.lock().unwrap().replace(task) {
+            previous_task.handle.abort();
+        }
+    }
+
+    /// Wait until the visible rooms have been prefetched, or until their
+    /// prefetching is cancelled.
+    pub async fn wait(&self) {
+        let Some(mut is_done) =
+            self.task.lock().unwrap().as_ref().map(|task| task.is_done.subscribe())
+        else {
+            return;
+        };
+
+        // The stream ends when the task is cancelled.
+        while !is_done.get() {
+            if is_done.next().await.is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Cancel the prefetching of the visible rooms, if it's still running.
+    pub fn cancel(&self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.handle.abort();
+        }
+    }
+}
+
+impl Drop for RoomPrefetcher {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[instrument(skip_all, fields(rooms = room_ids.len()))]
+async fn prefetch(client: Client, avatar_format: MediaFormat, room_ids: Vec<OwnedRoomId>) {
+    for room_id in room_ids {
+        let Some(room) = client.get_room(&room_id) else { continue };
+
+        let mut avatar_urls: Vec<OwnedMxcUri> = room.avatar_url().into_iter().collect();
+        let mut missing_profiles: Vec<OwnedUserId> = Vec::new();
+
+        for hero in room.heroes() {
+            match hero.avatar_url {
+                Some(avatar_url) => avatar_urls.push(avatar_url),
+                None if hero.display_name.is_none() => missing_profiles.push(hero.user_id),
+                None => {}
+            }
+        }
+
+        if !missing_profiles.is_empty() {
+            match client.get_profiles(missing_profiles.iter().map(|user_id| &**user_id)).await {
+                Ok(profiles) => {
+                    avatar_urls.extend(profiles.into_values().filter_map(|p| p.avatar_url));
+                }
+                Err(error) => {
+                    warn!(%room_id, "Failed to fetch the profiles of the heroes: {error}")
+                }
+            }
+        }
+
+        for avatar_url in avatar_urls {
+            match client.media().can_auto_download(&avatar_format, None).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    debug!(%room_id, "The media policy doesn't allow to prefetch the avatars");
+                    break;
+                }
+                Err(error) => {
+                    warn!(%room_id, "Failed to get the media policy: {error}");
+                    break;
+                }
+            }
+
+            let request = MediaRequestParameters {
+                source: MediaSource::Plain(avatar_url),
+                format: avatar_format.clone(),
+            };
+
+            if let Err(error) = client.media().get_media_content(&request, true).await {
+                debug!(%room_id, "Failed to prefetch an avatar: {error}");
+            }
+        }
+    }
+
+    debug!("Prefetched the visible rooms");
+}
@@ -0,0 +1,478 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bug reports, also known as rageshakes, uploaded to a [rageshake server].
+//!
+//! The recent logs are collected by a [`LogBuffer`], which must be installed
+//! as a layer of the `tracing` subscriber of the app with
+//! [`LogBuffer::layer()`]. A [`BugReport`] is then submitted with
+//! [`Client::submit_bug_report()`], with the logs, the metadata of the
+//! session, and optionally a snapshot of the state of the rooms. Secrets are
+//! removed from the logs according to the [`RedactionRules`].
+//!
+//! [rageshake server]: https://github.com/matrix-org/rageshake
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, instrument};
+use tracing_subscriber::{fmt::MakeWriter, registry::LookupSpan, Layer};
+use url::Url;
+
+use crate::{Client, RoomState};
+
+/// The replacement of the redacted secrets in the logs.
+const REDACTED: &str = "[redacted]";
+
+/// An error occurring while submitting a bug report.
+#[derive(Debug, Error)]
+pub enum RageshakeError {
+    /// The request to the rageshake server failed.
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    /// The logs couldn't be compressed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// The state snapshot couldn't be serialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// A buffer of the most recent log lines, to be attached to bug reports.
+///
+/// Cloning is shallow, and thus is cheap to do.
+#[derive(Clone, Debug)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    /// Create a buffer keeping the given number of log lines.
+    ///
+    /// A buffer with a capacity of 0 doesn't keep any line.
+    pub fn new(capacity: usize) -> Self {
+        Self { lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    /// A `tracing` layer writing the logs to this buffer, to add to the
+    /// subscriber of the app.
+    pub fn layer<S>(&self) -> impl Layer<S>
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        tracing_subscriber::fmt::layer().with_ansi(false).with_writer(self.clone())
+    }
+
+    /// Add a line to the buffer, removing the oldest one if it's full.
+    fn push(&self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut lines = self.lines.lock().unwrap();
+
+        while lines.len() >= self.capacity {
+            lines.pop_front();
+        }
+
+        lines.push_back(line);
+    }
+
+    /// The lines in the buffer, from the oldest to the most recent.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = LogBufferWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogBufferWriter { buffer: self.clone(), data: Vec::new() }
+    }
+}
+
+/// The writer of a single log event into a [`LogBuffer`].
+#[derive(Debug)]
+pub struct LogBufferWriter {
+    buffer: LogBuffer,
+    data: Vec<u8>,
+}
+
+impl Write for LogBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogBufferWriter {
+    fn drop(&mut self) {
+        if self.data.is_empty() {
+            return;
+        }
+
+        let line = String::from_utf8_lossy(&self.data);
+        self.buffer.push(line.trim_end().to_owned());
+    }
+}
+
+/// The rules to remove secrets from the logs of a bug report.
+///
+/// The access and refresh tokens of the client are always redacted.
+#[derive(Clone, Debug)]
+pub struct RedactionRules {
+    secrets: Vec<String>,
+    query_params: Vec<String>,
+}
+
+impl RedactionRules {
+    /// Create rules redacting the `access_token` query parameter of URLs.
+    pub fn new() -> Self {
+        Self { secrets: Vec::new(), query_params: vec!["access_token".to_owned()] }
+    }
+
+    /// Redact the given secret wherever it appears.
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.secrets.push(secret.into());
+        self
+    }
+
+    /// Redact the values of the query parameter with the given name, in URLs.
+    pub fn query_param(mut self, name: impl Into<String>) -> Self {
+        self.query_params.push(name.into());
+        self
+    }
+
+    /// Apply the rules to the given log line.
+    fn apply(&self, line: &str) -> String {
+        let mut line = line.to_owned();
+
+        for secret in self.secrets.iter().filter(|secret| !secret.is_empty()) {
+            line = line.replace(secret.as_str(), REDACTED);
+        }
+
+        for name in &self.query_params {
+            line = redact_query_param(&line, name);
+        }
+
+        line
+    }
+}
+
+impl Default for RedactionRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Replace the values of the query parameter with the given name in the line.
+fn redact_query_param(line: &str, name: &str) -> String {
+    let pattern = format!("{name}=");
+    let mut redacted = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find(&pattern) {
+        let value_start = start + pattern.len();
+        let value_len = rest[value_start..]
+            .find(|c: char| c == '&' || c == '"' || c == '\'' || c.is_whitespace())
+            .unwrap_or(rest.len() - value_start);
+
+        redacted.push_str(&rest[..value_start]);
+        redacted.push_str(REDACTED);
+        rest = &rest[value_start + value_len..];
+    }
+
+    redacted.push_str(rest);
+    redacted
+}
+
+/// Where and how to submit bug reports.
+#[derive(Clone, Debug)]
+pub struct RageshakeConfig {
+    /// The URL of the submit endpoint of the rageshake server, like
+    /// `https://rageshake.example.org/api/submit`.
+    pub endpoint: Url,
+
+    /// The name of the app, used by the server to sort the reports.
+    pub app: String,
+
+    /// The version of the app.
+    pub version: String,
+
+    /// The user agent of the app, if any.
+    pub user_agent: Option<String>,
+
+    /// The rules to remove secrets from the logs.
+    pub redaction_rules: RedactionRules,
+}
+
+impl RageshakeConfig {
+    /// Create a configuration with the given endpoint, app name and version.
+    pub fn new(endpoint: Url, app: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            endpoint,
+            app: app.into(),
+            version: version.into(),
+            user_agent: None,
+            redaction_rules: RedactionRules::new(),
+        }
+    }
+}
+
+/// A bug report, to submit with [`Client::submit_bug_report()`].
+#[derive(Clone, Debug)]
+pub struct BugReport {
+    text: String,
+    labels: Vec<String>,
+    fields: BTreeMap<String, String>,
+    include_state_snapshot: bool,
+}
+
+impl BugReport {
+    /// Create a bug report with the given description of the problem.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            labels: Vec::new(),
+            fields: BTreeMap::new(),
+            include_state_snapshot: false,
+        }
+    }
+
+    /// Add a label to the report, to help sorting it.
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.labels.push(label.into());
+        self
+    }
+
+    /// Add a custom field to the report.
+    pub fn field(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.insert(name.into(), value.into());
+        self
+    }
+
+    /// Attach a snapshot of the state of the rooms to the report.
+    ///
+    /// The snapshot doesn't include the names or the messages of the rooms,
+    /// only their IDs, and their membership, encryption and unread state.
+    pub fn include_state_snapshot(mut self) -> Self {
+        self.include_state_snapshot = true;
+        self
+    }
+}
+
+/// The response of the rageshake server to a submitted bug report.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BugReportResponse {
+    /// The URL of the report in an issue tracker, if the server created one.
+    pub report_url: Option<String>,
+}
+
+/// The state of a room, in the state snapshot of a bug report.
+#[derive(Debug, Serialize)]
+struct RoomSnapshot {
+    room_id: String,
+    state: &'static str,
+    is_encrypted: Option<bool>,
+    joined_members: u64,
+    num_unread_messages: u64,
+    num_unread_notifications: u64,
+}
+
+impl Client {
+    /// Submit a bug report to a rageshake server.
+    ///
+    /// The report includes the logs of the given buffer, with the secrets
+    /// redacted, and the metadata of the session: the user and device IDs, the
+    /// homeserver, the version of the SDK, and the identity keys of the
+    /// device.
+    #[instrument(skip_all, fields(endpoint = %config.endpoint))]
+    pub async fn submit_bug_report(
+        &self,
+        config: &RageshakeConfig,
+        logs: &LogBuffer,
+        report: BugReport,
+    ) -> Result<BugReportResponse, RageshakeError> {
+        let mut redaction_rules = config.redaction_rules.clone();
+        if let Some(session) = self.session() {
+            redaction_rules = redaction_rules.secret(session.access_token());
+
+            if let Some(refresh_token) = session.get_refresh_token() {
+                redaction_rules = redaction_rules.secret(refresh_token);
+            }
+        }
+
+        let mut form = Form::new()
+            .text("text", report.text)
+            .text("app", config.app.clone())
+            .text("version", config.version.clone())
+            .text("sdk_version", env!("CARGO_PKG_VERSION"))
+            .text("homeserver", self.homeserver().to_string());
+
+        if let Some(user_agent) = &config.user_agent {
+            form = form.text("user_agent", user_agent.clone());
+        }
+
+        if let Some(session_meta) = self.session_meta() {
+            form = form
+                .text("user_id", session_meta.user_id.to_string())
+                .text("device_id", session_meta.device_id.to_string());
+        }
+
+        #[cfg(feature = "e2e-encryption")]
+        {
+            if let Some(ed25519_key) = self.encryption().ed25519_key().await {
+                form = form.text("device_keys", format!("ed25519:{ed25519_key}"));
+            }
+        }
+
+        for label in report.labels {
+            form = form.text("label", label);
+        }
+
+        for (name, value) in report.fields {
+            form = form.text(name, value);
+        }
+
+        let lines = logs.lines();
+        debug!(lines = lines.len(), "Compressing the logs");
+        let compressed_logs = compress_logs(&lines, &redaction_rules)?;
+        form = form.part(
+            "compressed-log",
+            Part::bytes(compressed_logs).file_name("logs.log.gz").mime_str("application/gzip")?,
+        );
+
+        if report.include_state_snapshot {
+            let snapshot = serde_json::to_vec_pretty(&self.state_snapshot())?;
+            form = form.part(
+                "file",
+                Part::bytes(snapshot).file_name("state.json").mime_str("application/json")?,
+            );
+        }
+
+        // Use the HTTP client of the client, to go through the same proxy and
+        // trust the same certificates.
+        let response = self
+            .http_client()
+            .post(config.endpoint.clone())
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // The response of older servers is empty.
+        let body = response.bytes().await?;
+        let response = serde_json::from_slice(&body).unwrap_or_default();
+
+        Ok(response)
+    }
+
+    /// Get the state of the rooms, for a bug report.
+    fn state_snapshot(&self) -> Vec<RoomSnapshot> {
+        self.rooms()
+            .into_iter()
+            .map(|room| RoomSnapshot {
+                room_id: room.room_id().to_string(),
+                state: match room.state() {
+                    RoomState::Joined => "joined",
+                    RoomState::Left => "left",
+                    RoomState::Invited => "invited",
+                    RoomState::Knocked => "knocked",
+                    RoomState::Banned => "banned",
+                },
+                is_encrypted: room.is_encryption_state_synced().then(|| room.is_encrypted()),
+                joined_members: room.joined_members_count(),
+                num_unread_messages: room.num_unread_messages(),
+                num_unread_notifications: room.num_unread_notifications(),
+            })
+            .collect()
+    }
+}
+
+/// Redact the given log lines, and compress them with gzip.
+fn compress_logs(lines: &[String], redaction_rules: &RedactionRules) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+    for line in lines {
+        encoder.write_all(redaction_rules.apply(line).as_bytes())?;
+        encoder.write_all(b"\n")?;
+    }
+
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::{compress_logs, redact_query_param, LogBuffer, RedactionRules};
+
+    #[test]
+    fn test_log_buffer_keeps_recent_lines() {
+        let buffer = LogBuffer::new(2);
+
+        buffer.push("first".to_owned());
+        buffer.push("second".to_owned());
+        buffer.push("third".to_owned());
+
+        assert_eq!(buffer.lines(), vec!["second".to_owned(), "third".to_owned()]);
+
+        let buffer = LogBuffer::new(0);
+
+        buffer.push("first".to_owned());
+        buffer.push("second".to_owned());
+
+        assert!(buffer.lines().is_empty());
+    }
+
+    #[test]
+    fn test_redaction_rules() {
+        assert_eq!(
+            redact_query_param("GET /sync?access_token=secret&since=s1 200", "access_token"),
+            "GET /sync?access_token=[redacted]&since=s1 200"
+        );
+        assert_eq!(
+            redact_query_param("access_token=secret", "access_token"),
+            "access_token=[redacted]"
+        );
+
+        let rules = RedactionRules::new().secret("syt_token");
+        let compressed = compress_logs(
+            &["Authorization: Bearer syt_token".to_owned(), "GET /?access_token=abc".to_owned()],
+            &rules,
+        )
+        .unwrap();
+
+        let mut logs = String::new();
+        GzDecoder::new(compressed.as_slice()).read_to_string(&mut logs).unwrap();
+        assert_eq!(logs, "Authorization: Bearer [redacted]\nGET /?access_token=[redacted]\n");
+    }
+}
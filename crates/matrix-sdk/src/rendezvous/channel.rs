@@ -19,6 +19,7 @@ use http::{
     HeaderMap, HeaderName, Method, StatusCode,
 };
 use ruma::api::{
+    client::rendezvous::create_rendezvous_session,
     error::{FromHttpResponseError, HeaderDeserializationError, IntoHttpError, MatrixError},
     EndpointError,
 };
@@ -50,7 +51,7 @@ fn get_header(
 }
 
 /// The result of the [`RendezvousChannel::create_inbound()`] method.
-pub(super) struct InboundChannelCreationResult {
+pub(crate) struct InboundChannelCreationResult {
     /// The connected [`RendezvousChannel`].
     pub channel: RendezvousChannel,
     /// The initial message we received when we connected to the
@@ -81,7 +82,7 @@ struct RendezvousMessage {
     pub content_type: String,
 }
 
-pub(super) struct RendezvousChannel {
+pub(crate) struct RendezvousChannel {
     client: HttpClient,
     rendezvous_url: Url,
     etag: Etag,
@@ -106,13 +107,10 @@ impl RendezvousChannel {
     /// By outbound we mean that we're going to tell the Matrix server to create
     /// a new rendezvous session. We're going to send an initial empty message
     /// through the channel.
-    #[cfg(test)]
-    pub(super) async fn create_outbound(
+    pub(crate) async fn create_outbound(
         client: HttpClient,
         rendezvous_server: &Url,
     ) -> Result<Self, HttpError> {
-        use ruma::api::client::rendezvous::create_rendezvous_session;
-
         let request = create_rendezvous_session::unstable::Request::default();
         let response = client
             .send(request, None, rendezvous_server.to_string(), None, &[], Default::default())
@@ -128,7 +126,7 @@ impl RendezvousChannel {
     ///
     /// By inbound we mean that we're going to attempt to read an initial
     /// message from the rendezvous session on the given [`rendezvous_url`].
-    pub(super) async fn create_inbound(
+    pub(crate) async fn create_inbound(
         client: HttpClient,
         rendezvous_url: &Url,
     ) -> Result<InboundChannelCreationResult, HttpError> {
@@ -151,7 +149,7 @@ impl RendezvousChannel {
 
     /// Get the URL of the rendezvous session we're using to exchange messages
     /// through the channel.
-    pub(super) fn rendezvous_url(&self) -> &Url {
+    pub(crate) fn rendezvous_url(&self) -> &Url {
         &self.rendezvous_url
     }
 
@@ -160,7 +158,7 @@ impl RendezvousChannel {
     ///
     /// The message must be of the `text/plain` content type.
     #[instrument(skip_all)]
-    pub(super) async fn send(&mut self, message: Vec<u8>) -> Result<(), HttpError> {
+    pub(crate) async fn send(&mut self, message: Vec<u8>) -> Result<(), HttpError> {
         let etag = self.etag.clone();
 
         let request = self
@@ -201,7 +199,7 @@ impl RendezvousChannel {
     ///
     /// This method will wait in a loop for the channel to give us a new
     /// message.
-    pub(super) async fn receive(&mut self) -> Result<Vec<u8>, HttpError> {
+    pub(crate) async fn receive(&mut self) -> Result<Vec<u8>, HttpError> {
         loop {
             let message = self.receive_single_message().await?;
 
@@ -283,18 +281,123 @@ impl RendezvousChannel {
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
+    use std::sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, Mutex,
+    };
+
     use matrix_sdk_test::async_test;
     use serde_json::json;
     use similar_asserts::assert_eq;
     use wiremock::{
         matchers::{header, method, path},
-        Mock, MockServer, ResponseTemplate,
+        Mock, MockGuard, MockServer, ResponseTemplate,
     };
 
     use super::*;
     use crate::config::RequestConfig;
 
+    #[allow(dead_code)]
+    pub struct MockedRendezvousServer {
+        pub homeserver_url: Url,
+        pub rendezvous_url: Url,
+        content: Arc<Mutex<Option<String>>>,
+        etag: Arc<AtomicU8>,
+        post_guard: MockGuard,
+        put_guard: MockGuard,
+        get_guard: MockGuard,
+    }
+
+    impl MockedRendezvousServer {
+        pub async fn new(server: &MockServer, location: &str) -> Self {
+            let content: Arc<Mutex<Option<String>>> = Mutex::default().into();
+            let etag = Arc::new(AtomicU8::new(0));
+
+            let homeserver_url = Url::parse(&server.uri())
+                .expect("We should be able to parse the example homeserver");
+
+            let rendezvous_url = homeserver_url
+                .join(location)
+                .expect("We should be able to create a rendezvous URL");
+
+            let post_guard = server
+                .register_as_scoped(
+                    Mock::given(method("POST"))
+                        .and(path("/_matrix/client/unstable/org.matrix.msc4108/rendezvous"))
+                        .respond_with(
+                            ResponseTemplate::new(200)
+                                .append_header("X-Max-Bytes", "10240")
+                                .append_header("ETag", "1")
+                                .append_header("Expires", "Wed, 07 Sep 2022 14:28:51 GMT")
+                                .append_header("Last-Modified", "Wed, 07 Sep 2022 14:27:51 GMT")
+                                .set_body_json(json!({
+                                    "url": rendezvous_url,
+                                })),
+                        ),
+                )
+                .await;
+
+            let put_guard = server
+                .register_as_scoped(
+                    Mock::given(method("PUT")).and(path("/abcdEFG12345")).respond_with({
+                        let content = content.clone();
+                        let etag = etag.clone();
+
+                        move |request: &wiremock::Request| {
+                            *content.lock().unwrap() =
+                                Some(String::from_utf8(request.body.clone()).unwrap());
+                            let current_etag = etag.fetch_add(1, Ordering::SeqCst);
+
+                            ResponseTemplate::new(200)
+                                .append_header("ETag", (current_etag + 2).to_string())
+                                .append_header("Expires", "Wed, 07 Sep 2022 14:28:51 GMT")
+                                .append_header("Last-Modified", "Wed, 07 Sep 2022 14:27:51 GMT")
+                        }
+                    }),
+                )
+                .await;
+
+            let get_guard = server
+                .register_as_scoped(
+                    Mock::given(method("GET")).and(path("/abcdEFG12345")).respond_with({
+                        let content = content.clone();
+                        let etag = etag.clone();
+
+                        move |request: &wiremock::Request| {
+                            let requested_etag = request.headers.get("if-none-match").map(|etag| {
+                                str::parse::<u8>(std::str::from_utf8(etag.as_bytes()).unwrap())
+                                    .unwrap()
+                            });
+
+                            let mut content = content.lock().unwrap();
+                            let current_etag = etag.load(Ordering::SeqCst);
+
+                            if requested_etag == Some(current_etag) || requested_etag.is_none() {
+                                let content = content.take();
+
+                                ResponseTemplate::new(200)
+                                    .append_header("ETag", (current_etag).to_string())
+                                    .append_header("Expires", "Wed, 07 Sep 2022 14:28:51 GMT")
+                                    .append_header("Last-Modified", "Wed, 07 Sep 2022 14:27:51 GMT")
+                                    .set_body_string(content.unwrap_or_default())
+                            } else {
+                                let etag = requested_etag.unwrap_or_default();
+
+                                ResponseTemplate::new(304)
+                                    .append_header("ETag", etag.to_string())
+                                    .append_header("Expires", "Wed, 07 Sep 2022 14:28:51 GMT")
+                                    .append_header("Last-Modified", "Wed, 07 Sep 2022 14:27:51 GMT")
+                            }
+                        }
+                    }),
+                )
+                .await;
+
+            Self { content, etag, post_guard, put_guard, get_guard, homeserver_url, rendezvous_url }
+        }
+    }
+
     async fn mock_rendzvous_create(server: &MockServer, rendezvous_url: &Url) {
         server
             .register(
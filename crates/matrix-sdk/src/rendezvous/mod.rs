@@ -0,0 +1,347 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A secure channel between two devices, through a rendezvous session on the
+//! homeserver, as defined in [MSC4108].
+//!
+//! This is the channel used by the QR code login, usable to exchange other
+//! small payloads between two devices, like a hand-off of settings.
+//!
+//! One device creates a [`RendezvousSession`], and shares its
+//! [`RendezvousSession::url()`] and [`RendezvousSession::public_key()`] with
+//! the other device out of band, for example with a QR code. The other device
+//! connects to it with [`SecureRendezvousChannel::connect()`], while the first
+//! one waits for it with [`RendezvousSession::accept()`].
+//!
+//! The messages are encrypted with ECIES. Both devices should then compare
+//! their [`SecureRendezvousChannel::check_code()`] to make sure that they are
+//! talking to each other, and not to a third party.
+//!
+//! This module is only available with the `unstable-msc4108` feature, which
+//! is also enabled by the `experimental-oidc` feature.
+//!
+//! [MSC4108]: https://github.com/matrix-org/matrix-spec-proposals/pull/4108
+
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tracing::{instrument, trace};
+use url::Url;
+use vodozemac::ecies::{
+    Ecies, EstablishedEcies, InboundCreationResult, InitialMessage, Message, OutboundCreationResult,
+};
+pub use vodozemac::{
+    ecies::{CheckCode, Error as EciesError, MessageDecodeError},
+    Curve25519PublicKey,
+};
+
+use self::channel::{InboundChannelCreationResult, RendezvousChannel};
+use crate::{Client, HttpError, RumaApiError};
+
+pub(crate) mod channel;
+
+const RENDEZVOUS_INITIATE_MESSAGE: &str = "MATRIX_RENDEZVOUS_INITIATE";
+const RENDEZVOUS_OK_MESSAGE: &str = "MATRIX_RENDEZVOUS_OK";
+
+/// The default duration after which waiting for a message from the other
+/// device fails.
+const DEFAULT_INACTIVITY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Error type for the failures of a [`SecureRendezvousChannel`].
+#[derive(Debug, Error)]
+pub enum RendezvousError {
+    /// The rendezvous session doesn't exist anymore, it expired or was deleted
+    /// by the other device.
+    #[error("The rendezvous session has expired")]
+    SessionExpired,
+
+    /// The other device didn't send a message before the inactivity timeout.
+    #[error("The other device didn't send a message in {0:?}")]
+    Timeout(Duration),
+
+    /// A message we received was not a valid UTF-8 encoded string.
+    #[error(transparent)]
+    Utf8(#[from] std::str::Utf8Error),
+
+    /// A message has failed to be encrypted or decrypted.
+    #[error(transparent)]
+    Ecies(#[from] EciesError),
+
+    /// A received message has failed to be decoded.
+    #[error(transparent)]
+    MessageDecode(#[from] MessageDecodeError),
+
+    /// A message couldn't be serialized to or deserialized from JSON.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The channel failed to be established because it received an
+    /// unexpected message.
+    #[error("The secure channel setup has received an unexpected message, expected: {expected}, got {received}")]
+    UnexpectedMessage {
+        /// The message we expected.
+        expected: &'static str,
+        /// The message we received instead.
+        received: String,
+    },
+
+    /// A request to the rendezvous session failed.
+    #[error(transparent)]
+    Http(HttpError),
+}
+
+impl From<HttpError> for RendezvousError {
+    fn from(error: HttpError) -> Self {
+        let status_code = match error.as_ruma_api_error() {
+            Some(RumaApiError::Other(error)) => Some(error.status_code),
+            Some(RumaApiError::ClientApi(error)) => Some(error.status_code),
+            _ => None,
+        };
+
+        match status_code {
+            Some(http::StatusCode::NOT_FOUND | http::StatusCode::GONE) => Self::SessionExpired,
+            _ => Self::Http(error),
+        }
+    }
+}
+
+/// A rendezvous session created by this device, waiting for another device
+/// to connect to it.
+pub struct RendezvousSession {
+    channel: RendezvousChannel,
+    ecies: Ecies,
+    inactivity_timeout: Duration,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for RendezvousSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RendezvousSession")
+            .field("url", self.channel.rendezvous_url())
+            .field("public_key", &self.ecies.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RendezvousSession {
+    /// Create a new rendezvous session on the homeserver of the given client.
+    pub async fn create(client: &Client) -> Result<Self, RendezvousError> {
+        let channel = RendezvousChannel::create_outbound(
+            client.inner.http_client.clone(),
+            &client.homeserver(),
+        )
+        .await?;
+
+        Ok(Self { channel, ecies: Ecies::new(), inactivity_timeout: DEFAULT_INACTIVITY_TIMEOUT })
+    }
+
+    /// The URL of the rendezvous session, to share with the other device.
+    pub fn url(&self) -> &Url {
+        self.channel.rendezvous_url()
+    }
+
+    /// The public key of this device, to share with the other device.
+    pub fn public_key(&self) -> Curve25519PublicKey {
+        self.ecies.public_key()
+    }
+
+    /// Set the duration after which waiting for a message from the other
+    /// device fails with [`RendezvousError::Timeout`].
+    ///
+    /// Defaults to five minutes. It is kept by the established channel.
+    pub fn inactivity_timeout(mut self, timeout: Duration) -> Self {
+        self.inactivity_timeout = timeout;
+        self
+    }
+
+    /// Wait for the other device to connect to the rendezvous session, and
+    /// establish the secure channel with it.
+    #[instrument(skip(self))]
+    pub async fn accept(mut self) -> Result<SecureRendezvousChannel, RendezvousError> {
+        trace!("Waiting for the other device to connect to the rendezvous session");
+
+        let message = receive_with_timeout(&mut self.channel, self.inactivity_timeout).await?;
+        let message = InitialMessage::decode(std::str::from_utf8(&message)?)?;
+
+        let InboundCreationResult { ecies, message } =
+            self.ecies.establish_inbound_channel(&message)?;
+        let message = std::str::from_utf8(&message)?;
+
+        if message != RENDEZVOUS_INITIATE_MESSAGE {
+            return Err(RendezvousError::UnexpectedMessage {
+                expected: RENDEZVOUS_INITIATE_MESSAGE,
+                received: message.to_owned(),
+            });
+        }
+
+        let mut channel = SecureRendezvousChannel {
+            channel: self.channel,
+            ecies,
+            inactivity_timeout: self.inactivity_timeout,
+        };
+        channel.send(RENDEZVOUS_OK_MESSAGE.as_bytes()).await?;
+
+        Ok(channel)
+    }
+}
+
+/// An established secure channel with another device, through a rendezvous
+/// session.
+pub struct SecureRendezvousChannel {
+    channel: RendezvousChannel,
+    ecies: EstablishedEcies,
+    inactivity_timeout: Duration,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for SecureRendezvousChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecureRendezvousChannel")
+            .field("url", self.channel.rendezvous_url())
+            .field("inactivity_timeout", &self.inactivity_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecureRendezvousChannel {
+    /// Connect to the rendezvous session at the given URL, created by the
+    /// device with the given public key, and establish the secure channel
+    /// with it.
+    #[instrument(skip(client))]
+    pub async fn connect(
+        client: &Client,
+        rendezvous_url: &Url,
+        public_key: Curve25519PublicKey,
+    ) -> Result<Self, RendezvousError> {
+        let OutboundCreationResult { ecies, message } = Ecies::new()
+            .establish_outbound_channel(public_key, RENDEZVOUS_INITIATE_MESSAGE.as_bytes())?;
+
+        // The initial message of the rendezvous session is empty, we only need its
+        // ETag.
+        let InboundChannelCreationResult { channel, .. } =
+            RendezvousChannel::create_inbound(client.inner.http_client.clone(), rendezvous_url)
+                .await?;
+
+        let mut channel = Self { channel, ecies, inactivity_timeout: DEFAULT_INACTIVITY_TIMEOUT };
+        channel.channel.send(message.encode().into_bytes()).await?;
+
+        trace!("Waiting for the other device to confirm the secure channel");
+
+        let response = channel.receive().await?;
+        if response != RENDEZVOUS_OK_MESSAGE.as_bytes() {
+            return Err(RendezvousError::UnexpectedMessage {
+                expected: RENDEZVOUS_OK_MESSAGE,
+                received: String::from_utf8_lossy(&response).into_owned(),
+            });
+        }
+
+        Ok(channel)
+    }
+
+    /// Get the [`CheckCode`] which can be used to, out of band, verify that
+    /// both sides of the channel are indeed communicating with each other and
+    /// not with a third party.
+    pub fn check_code(&self) -> &CheckCode {
+        self.ecies.check_code()
+    }
+
+    /// Set the duration after which waiting for a message from the other
+    /// device fails with [`RendezvousError::Timeout`].
+    ///
+    /// Defaults to five minutes.
+    pub fn set_inactivity_timeout(&mut self, timeout: Duration) {
+        self.inactivity_timeout = timeout;
+    }
+
+    /// Encrypt the given message and send it to the other device.
+    pub async fn send(&mut self, message: &[u8]) -> Result<(), RendezvousError> {
+        let message = self.ecies.encrypt(message).encode();
+        Ok(self.channel.send(message.into_bytes()).await?)
+    }
+
+    /// Wait for a message from the other device, and decrypt it.
+    pub async fn receive(&mut self) -> Result<Vec<u8>, RendezvousError> {
+        let message = receive_with_timeout(&mut self.channel, self.inactivity_timeout).await?;
+        let message = Message::decode(std::str::from_utf8(&message)?)?;
+
+        Ok(self.ecies.decrypt(&message)?)
+    }
+
+    /// Serialize the given message to JSON and send it to the other device.
+    pub async fn send_json(&mut self, message: impl Serialize) -> Result<(), RendezvousError> {
+        let message = serde_json::to_vec(&message)?;
+        self.send(&message).await
+    }
+
+    /// Wait for a JSON message from the other device, and deserialize it.
+    pub async fn receive_json<D: DeserializeOwned>(&mut self) -> Result<D, RendezvousError> {
+        let message = self.receive().await?;
+        Ok(serde_json::from_slice(&message)?)
+    }
+}
+
+/// Wait for a message on the rendezvous channel, for at most the given
+/// duration.
+async fn receive_with_timeout(
+    channel: &mut RendezvousChannel,
+    timeout: Duration,
+) -> Result<Vec<u8>, RendezvousError> {
+    match tokio::time::timeout(timeout, channel.receive()).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(RendezvousError::Timeout(timeout)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use assert_matches2::assert_matches;
+    use matrix_sdk_test::async_test;
+    use serde_json::{json, Value};
+    use similar_asserts::assert_eq;
+    use wiremock::MockServer;
+
+    use super::{RendezvousError, RendezvousSession, SecureRendezvousChannel};
+    use crate::{rendezvous::channel::test::MockedRendezvousServer, test_utils::logged_in_client};
+
+    #[async_test]
+    async fn test_exchange_messages() {
+        let server = MockServer::start().await;
+        let _rendezvous_server = MockedRendezvousServer::new(&server, "abcdEFG12345").await;
+
+        let alice = logged_in_client(Some(server.uri())).await;
+        let bob = logged_in_client(Some(server.uri())).await;
+
+        let session = RendezvousSession::create(&alice).await.unwrap();
+        let url = session.url().clone();
+        let public_key = session.public_key();
+
+        let alice_task = tokio::spawn(async move { session.accept().await.unwrap() });
+        let mut bob_channel =
+            SecureRendezvousChannel::connect(&bob, &url, public_key).await.unwrap();
+        let mut alice_channel = alice_task.await.unwrap();
+
+        assert_eq!(alice_channel.check_code(), bob_channel.check_code());
+
+        bob_channel.send_json(json!({ "theme": "dark" })).await.unwrap();
+        let settings: Value = alice_channel.receive_json().await.unwrap();
+        assert_eq!(settings, json!({ "theme": "dark" }));
+
+        // Nothing else is sent, so waiting for another message times out.
+        bob_channel.set_inactivity_timeout(Duration::from_millis(50));
+        assert_matches!(bob_channel.receive().await, Err(RendezvousError::Timeout(_)));
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Invite many users to a room at once.
+
+use std::{fmt, time::Duration};
+
+use futures_util::{stream, StreamExt};
+use ruma::{
+    api::client::{
+        error::{ErrorKind, RetryAfter},
+        membership::{
+            invite_user::{self, v3::InvitationRecipient},
+            Invite3pidInit,
+        },
+    },
+    thirdparty::Medium,
+    OwnedUserId,
+};
+use tracing::{debug, warn};
+
+use crate::{utils::sleep, HttpError, Room};
+
+/// How many invites are sent at the same time.
+const MAX_CONCURRENT_INVITES: usize = 5;
+
+/// Email addresses to invite through an identity server.
+///
+/// The identity server stores the invites until the addresses are bound to a
+/// Matrix account.
+#[derive(Clone)]
+pub struct EmailInvites {
+    /// The hostname and port of the identity server, e.g. `vector.im`.
+    pub id_server: String,
+
+    /// An access token registered with the identity server.
+    pub id_access_token: String,
+
+    /// The email addresses to invite.
+    pub addresses: Vec<String>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for EmailInvites {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EmailInvites")
+            .field("id_server", &self.id_server)
+            .field("addresses", &self.addresses)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Someone invited with [`Room::invite_users()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InviteTarget {
+    /// A Matrix user.
+    UserId(OwnedUserId),
+
+    /// An email address, invited through an identity server.
+    Email(String),
+}
+
+/// The outcome of [`Room::invite_users()`].
+#[derive(Debug, Default)]
+pub struct InviteUsersReport {
+    /// The targets that were invited.
+    pub invited: Vec<InviteTarget>,
+
+    /// The targets that couldn't be invited, with the reason why.
+    pub failed: Vec<(InviteTarget, HttpError)>,
+}
+
+impl InviteUsersReport {
+    /// Whether all the targets were invited.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl Room {
+    /// Invite many users to this room at once.
+    ///
+    /// The invites are sent a few at a time; when the homeserver rate-limits
+    /// them, they're retried after the delay it asks for. An invite that fails
+    /// doesn't stop the others, and is reported in
+    /// [`InviteUsersReport::failed`].
+    ///
+    /// # Arguments
+    ///
+    /// * `user_ids` - The Matrix users to invite.
+    ///
+    /// * `emails` - The email addresses to invite through an identity server,
+    ///   if any.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::{room_id, user_id}};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let room = client.get_room(room_id!("!test:localhost")).unwrap();
+    /// let user_ids = [
+    ///     user_id!("@alice:localhost").to_owned(),
+    ///     user_id!("@bob:localhost").to_owned(),
+    /// ];
+    /// let report = room.invite_users(user_ids, None).await;
+    ///
+    /// for (target, error) in &report.failed {
+    ///     println!("Couldn't invite {target:?}: {error}");
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn invite_users(
+        &self,
+        user_ids: impl IntoIterator<Item = OwnedUserId>,
+        emails: Option<EmailInvites>,
+    ) -> InviteUsersReport {
+        let mut targets: Vec<_> = user_ids
+            .into_iter()
+            .map(|user_id| {
+                let recipient = InvitationRecipient::UserId { user_id: user_id.clone() };
+                (InviteTarget::UserId(user_id), recipient)
+            })
+            .collect();
+
+        if let Some(EmailInvites { id_server, id_access_token, addresses }) = emails {
+            targets.extend(addresses.into_iter().map(|address| {
+                let invite = Invite3pidInit {
+                    id_server: id_server.clone(),
+                    id_access_token: id_access_token.clone(),
+                    medium: Medium::Email,
+                    address: address.clone(),
+                };
+                (InviteTarget::Email(address), InvitationRecipient::ThirdPartyId(invite.into()))
+            }));
+        }
+
+        let results: Vec<_> = stream::iter(targets)
+            .map(|(target, recipient)| async move {
+                let result = self.invite_with_backoff(recipient).await;
+                (target, result)
+            })
+            .buffered(MAX_CONCURRENT_INVITES)
+            .collect()
+            .await;
+
+        let mut report = InviteUsersReport::default();
+
+        for (target, result) in results {
+            match result {
+                Ok(()) => report.invited.push(target),
+                Err(err) => {
+                    warn!(?target, "Couldn't invite to the room: {err}");
+                    report.failed.push((target, err));
+                }
+            }
+        }
+
+        if !report.invited.is_empty() {
+            // Force a future room members reload before sending any event to prevent
+            // UTDs, see `Room::invite_user_by_id()`.
+            self.mark_members_missing();
+        }
+
+        report
+    }
+
+    /// Send an invite, waiting and trying again when rate-limited.
+    async fn invite_with_backoff(&self, recipient: InvitationRecipient) -> Result<(), HttpError> {
+        const MAX_ATTEMPTS: usize = 5;
+
+        let mut attempt = 1;
+
+        loop {
+            let request =
+                invite_user::v3::Request::new(self.room_id().to_owned(), recipient.clone());
+
+            let err = match self.client.send(request).await {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+
+            let Some(ErrorKind::LimitExceeded { retry_after }) = err.client_api_error_kind() else {
+                return Err(err);
+            };
+
+            let delay = match retry_after {
+                Some(RetryAfter::Delay(delay)) => *delay,
+                _ => Duration::from_secs(1),
+            };
+
+            if attempt == MAX_ATTEMPTS {
+                return Err(err);
+            }
+
+            debug!(?delay, "Rate-limited, waiting before inviting again");
+            sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
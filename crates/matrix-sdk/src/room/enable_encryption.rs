@@ -0,0 +1,98 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Preflight checks to run before enabling encryption in a room.
+#![cfg(feature = "e2e-encryption")]
+
+use matrix_sdk_base::RoomMemberships;
+use ruma::OwnedUserId;
+use tracing::warn;
+
+use super::Room;
+use crate::{Error, Result};
+
+/// What enabling encryption in a room would look like, computed by
+/// [`Room::enable_encryption_checked()`].
+///
+/// Enabling encryption can't be undone, so UIs should show this to the user
+/// before calling [`EnableEncryptionPreview::enable()`].
+#[derive(Debug, Clone)]
+pub struct EnableEncryptionPreview {
+    room: Room,
+
+    /// Whether the room is public. Encrypting a public room is rarely useful,
+    /// since anybody can join and read the messages.
+    pub is_public: bool,
+
+    /// The number of joined and invited members of the room, including the
+    /// current user.
+    pub member_count: usize,
+
+    /// The members who don't have any device able to receive encrypted
+    /// messages. They won't be able to read the messages sent once
+    /// encryption is enabled.
+    pub members_without_devices: Vec<OwnedUserId>,
+}
+
+impl EnableEncryptionPreview {
+    /// Compute the preview, refusing to do so in a public room unless `force`
+    /// is set.
+    pub(crate) async fn new(room: &Room, force: bool) -> Result<Self> {
+        let is_public = room.is_public();
+
+        if is_public && !force {
+            return Err(Error::EncryptionInPublicRoom);
+        }
+
+        let members = room.members(RoomMemberships::ACTIVE).await?;
+        let user_ids: Vec<_> = members.iter().map(|member| member.user_id().to_owned()).collect();
+
+        // Make sure the device lists of the members are up to date, since most of
+        // them are likely not tracked yet if the room isn't encrypted.
+        {
+            let olm = room.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            let (request_id, request) =
+                olm.query_keys_for_users(user_ids.iter().map(|user_id| &**user_id));
+            room.client.keys_query(&request_id, request.device_keys).await?;
+        }
+
+        let encryption = room.client.encryption();
+        let mut members_without_devices = Vec::new();
+
+        for user_id in user_ids {
+            match encryption.get_user_devices(&user_id).await {
+                Ok(devices) if devices.devices().next().is_some() => {}
+                Ok(_) => members_without_devices.push(user_id),
+                Err(err) => {
+                    warn!(%user_id, "Couldn't load the devices of a room member: {err}");
+                }
+            }
+        }
+
+        Ok(Self {
+            room: room.clone(),
+            is_public,
+            member_count: members.len(),
+            members_without_devices,
+        })
+    }
+
+    /// Actually enable encryption in the room.
+    ///
+    /// See [`Room::enable_encryption()`] for details.
+    pub async fn enable(self) -> Result<()> {
+        self.room.enable_encryption().await
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Information about how an event was encrypted, for "message info" dialogs
+//! and moderation tooling.
+#![cfg(feature = "e2e-encryption")]
+
+use std::collections::BTreeMap;
+
+use matrix_sdk_base::{
+    crypto::{EventError, MegolmError},
+    deserialized_responses::{AlgorithmInfo, EncryptionInfo, TimelineEventKind, VerificationState},
+};
+use ruma::{DeviceKeyAlgorithm, EventEncryptionAlgorithm, EventId, OwnedDeviceId, OwnedUserId};
+use tracing::instrument;
+
+use super::Room;
+use crate::{Error, Result};
+
+/// How an event was encrypted, and who it was encrypted by, as returned by
+/// [`Room::event_encryption_info()`].
+#[derive(Clone, Debug)]
+pub struct EventEncryptionInfo {
+    /// The algorithm used to encrypt the event.
+    pub algorithm: EventEncryptionAlgorithm,
+
+    /// The ID of the megolm session used to encrypt the event.
+    pub session_id: String,
+
+    /// The sender of the event.
+    ///
+    /// This is untrusted data unless the `verification_state` is
+    /// [`VerificationState::Verified`].
+    pub sender: OwnedUserId,
+
+    /// The device that sent the event, if known.
+    ///
+    /// This is untrusted data unless the `verification_state` is
+    /// [`VerificationState::Verified`].
+    pub sender_device: Option<OwnedDeviceId>,
+
+    /// The Curve25519 key of the device that created the megolm session.
+    pub sender_curve25519_key: String,
+
+    /// The signing keys that the creator of the megolm session claims to own.
+    pub sender_claimed_keys: BTreeMap<DeviceKeyAlgorithm, String>,
+
+    /// The verification state of the sender when the event was decrypted.
+    ///
+    /// If the event wasn't decrypted before, this is the current state.
+    pub verification_state: VerificationState,
+
+    /// The Curve25519 keys of the devices that forwarded the megolm session
+    /// to us, starting with the device that received it from its creator.
+    ///
+    /// Empty if the session was received directly from the sender, or if it
+    /// was imported from a backup or an export which didn't record the chain.
+    /// The keys of a forwarded session can't be linked to the sender, so the
+    /// authenticity of the event isn't guaranteed.
+    pub forwarding_chain: Vec<String>,
+}
+
+impl Room {
+    /// Get how the event with the given ID was encrypted, and who it was
+    /// encrypted by.
+    ///
+    /// The event is taken from the [event cache](crate::event_cache) if it's
+    /// there, in which case the verification state of the sender is the one
+    /// at the time the event was decrypted. Otherwise, it's fetched from the
+    /// homeserver and decrypted.
+    ///
+    /// Returns `None` if the event isn't encrypted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event can't be fetched, or if it can't be
+    /// decrypted, for example because the room key is missing.
+    #[instrument(skip(self), fields(room_id = ?self.room_id()))]
+    pub async fn event_encryption_info(
+        &self,
+        event_id: &EventId,
+    ) -> Result<Option<EventEncryptionInfo>> {
+        let cached_event = match self.event_cache().await {
+            Ok((cache, _drop_handles)) => cache.event(event_id).await,
+            Err(_) => None,
+        };
+
+        let event_kind = match cached_event.map(|event| event.kind) {
+            // Events decrypted by older versions don't know their session ID, so they
+            // are fetched again.
+            Some(TimelineEventKind::Decrypted(decrypted))
+                if decrypted.encryption_info.session_id.is_none() =>
+            {
+                self.event(event_id, None).await?.kind
+            }
+            Some(kind) => kind,
+            None => self.event(event_id, None).await?.kind,
+        };
+
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let encryption_info = match event_kind {
+            TimelineEventKind::Decrypted(decrypted) => decrypted.encryption_info,
+            TimelineEventKind::UnableToDecrypt { event, .. }
+            | TimelineEventKind::PlainText { event } => {
+                let event_type = event.get_field::<String>("type").ok().flatten();
+                if event_type.as_deref() != Some("m.room.encrypted") {
+                    return Ok(None);
+                }
+
+                // The event couldn't be decrypted: this fails with the reason why, unless the
+                // room key was received in the meantime.
+                olm_machine.get_room_event_encryption_info(event.cast_ref(), self.room_id()).await?
+            }
+        };
+
+        let EncryptionInfo {
+            sender,
+            sender_device,
+            algorithm_info,
+            verification_state,
+            session_id,
+        } = encryption_info;
+
+        // Only megolm sessions are identified by a session ID.
+        let session_id =
+            session_id.ok_or_else(|| MegolmError::from(EventError::UnsupportedAlgorithm))?;
+
+        let AlgorithmInfo::MegolmV1AesSha2 { curve25519_key, sender_claimed_keys } = algorithm_info;
+
+        let forwarding_chain = olm_machine
+            .store()
+            .get_inbound_group_session(self.room_id(), &session_id)
+            .await?
+            .map(|session| {
+                session
+                    .forwarding_curve25519_key_chain()
+                    .iter()
+                    .map(|key| key.to_base64())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Some(EventEncryptionInfo {
+            algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2,
+            session_id,
+            sender,
+            sender_device,
+            sender_curve25519_key: curve25519_key,
+            sender_claimed_keys,
+            verification_state,
+            forwarding_chain,
+        }))
+    }
+}
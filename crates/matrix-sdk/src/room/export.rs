@@ -0,0 +1,645 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of the decrypted history of a room to a portable archive.
+//!
+//! An archive is a directory containing the messages of the room, in the
+//! chosen [`ExportFormat`], and a checkpoint file which allows an interrupted
+//! export to be resumed.
+
+use std::{
+    future::IntoFuture,
+    io,
+    ops::{Bound, RangeBounds},
+    path::{Path, PathBuf},
+};
+
+use eyeball::SharedObservable;
+use matrix_sdk_base::deserialized_responses::{TimelineEvent, TimelineEventKind};
+use matrix_sdk_common::boxed_into_future;
+use ruma::{
+    api::client::filter::RoomEventFilter,
+    assign,
+    events::{
+        room::message::{MessageType, OriginalSyncRoomMessageEvent, SyncRoomMessageEvent},
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+    },
+    serde::{Base64, Raw},
+    uint, MilliSecondsSinceUnixEpoch, OwnedEventId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+use tracing::{debug, trace};
+
+use crate::{room::MessagesOptions, Result, Room};
+
+/// The format of an archive created by [`Room::export_history`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A `messages.jsonl` file, with one JSON object per line and per message.
+    ///
+    /// Each object contains the decrypted `event`, and the path of its
+    /// attachment in the archive as `media`, if it has been downloaded.
+    Json,
+
+    /// A `messages` directory, with one email file per message, as defined in
+    /// RFC 5322. Attachments are embedded in the emails, if they have been
+    /// downloaded.
+    Eml,
+}
+
+impl ExportFormat {
+    /// The name of the file, or directory, containing the messages in the
+    /// archive.
+    fn messages_file_name(self) -> &'static str {
+        match self {
+            Self::Json => "messages.jsonl",
+            Self::Eml => "messages",
+        }
+    }
+
+    /// The name of the checkpoint file of an export in this format.
+    fn checkpoint_file_name(self) -> &'static str {
+        match self {
+            Self::Json => ".messages.jsonl.checkpoint",
+            Self::Eml => ".messages.eml.checkpoint",
+        }
+    }
+}
+
+/// The progress of an [`ExportHistory`] operation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExportProgress {
+    /// The number of messages in the archive, including the ones exported by
+    /// previous runs of an export that's been resumed.
+    pub exported: usize,
+
+    /// The number of attachments downloaded so far.
+    pub media_downloaded: usize,
+
+    /// The number of messages that couldn't be decrypted, and thus haven't
+    /// been exported.
+    pub undecryptable: usize,
+}
+
+/// The state of an export, saved after each batch of messages so it can be
+/// resumed.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ExportCheckpoint {
+    /// The pagination token to resume from.
+    from: Option<String>,
+
+    /// The last exported event, to skip the messages that have already been
+    /// exported when resuming from the same token.
+    last_event_id: Option<OwnedEventId>,
+
+    /// The number of exported messages.
+    exported: usize,
+
+    /// The length of the messages file, for the formats using a single file.
+    archive_len: u64,
+}
+
+/// An attachment of a message, downloaded from the media repository.
+struct ExportedMedia {
+    file_name: String,
+    mimetype: Option<String>,
+    data: Vec<u8>,
+}
+
+impl Room {
+    /// Export the decrypted history of this room to an archive.
+    ///
+    /// The messages are fetched from the homeserver from the oldest to the
+    /// most recent, decrypted when possible, and written to the `path`
+    /// directory in the given `format`. Only the `m.room.message` events sent
+    /// in `range` are exported; the ones that can't be decrypted are counted
+    /// in [`ExportProgress::undecryptable`]. Attachments are only downloaded
+    /// when asked to, with [`ExportHistory::with_media`].
+    ///
+    /// Running an export again with the same `path` and `format` resumes it
+    /// where it stopped, if it's been interrupted, or exports the messages
+    /// received since if it completed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory of the archive, which is created if needed.
+    ///
+    /// * `format` - The format of the archive.
+    ///
+    /// * `range` - The range of the times at which the messages were sent.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, room::export::ExportFormat, ruma::room_id};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let room = client.get_room(room_id!("!test:localhost")).unwrap();
+    /// let progress = room.export_history("/tmp/export", ExportFormat::Json, ..).with_media().await?;
+    ///
+    /// println!("Exported {} messages", progress.exported);
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn export_history(
+        &self,
+        path: impl AsRef<Path>,
+        format: ExportFormat,
+        range: impl RangeBounds<MilliSecondsSinceUnixEpoch>,
+    ) -> ExportHistory<'_> {
+        ExportHistory {
+            room: self,
+            path: path.as_ref().to_owned(),
+            format,
+            range: (range.start_bound().cloned(), range.end_bound().cloned()),
+            download_media: false,
+            progress: Default::default(),
+        }
+    }
+
+    /// Download the attachment of a message, if it has one.
+    async fn download_message_media(&self, msgtype: &MessageType) -> Result<Option<ExportedMedia>> {
+        let media = self.client.media();
+
+        let (data, file_name, mimetype) = match msgtype {
+            MessageType::Audio(content) => (
+                media.get_file(content, false).await?,
+                content.filename(),
+                content.info.as_ref().and_then(|info| info.mimetype.clone()),
+            ),
+            MessageType::File(content) => (
+                media.get_file(content, false).await?,
+                content.filename(),
+                content.info.as_ref().and_then(|info| info.mimetype.clone()),
+            ),
+            MessageType::Image(content) => (
+                media.get_file(content, false).await?,
+                content.filename(),
+                content.info.as_ref().and_then(|info| info.mimetype.clone()),
+            ),
+            MessageType::Video(content) => (
+                media.get_file(content, false).await?,
+                content.filename(),
+                content.info.as_ref().and_then(|info| info.mimetype.clone()),
+            ),
+            _ => return Ok(None),
+        };
+
+        Ok(data.map(|data| ExportedMedia { file_name: sanitize(file_name), mimetype, data }))
+    }
+}
+
+/// Future returned by [`Room::export_history`].
+#[allow(missing_debug_implementations)]
+pub struct ExportHistory<'a> {
+    room: &'a Room,
+    path: PathBuf,
+    format: ExportFormat,
+    range: (Bound<MilliSecondsSinceUnixEpoch>, Bound<MilliSecondsSinceUnixEpoch>),
+    download_media: bool,
+    progress: SharedObservable<ExportProgress>,
+}
+
+impl ExportHistory<'_> {
+    /// Also download the attachments of the messages, and add them to the
+    /// archive.
+    pub fn with_media(mut self) -> Self {
+        self.download_media = true;
+        self
+    }
+
+    /// Replace the default `SharedObservable` used for tracking the progress
+    /// of the export.
+    pub fn with_progress_observable(mut self, progress: SharedObservable<ExportProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+}
+
+impl<'a> IntoFuture for ExportHistory<'a> {
+    type Output = Result<ExportProgress>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { room, path, format, range, download_media, progress } = self;
+
+        Box::pin(async move {
+            fs::create_dir_all(&path).await?;
+
+            let checkpoint_path = path.join(format.checkpoint_file_name());
+            let mut checkpoint: ExportCheckpoint = match fs::read(&checkpoint_path).await {
+                Ok(bytes) => serde_json::from_slice(&bytes)?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Default::default(),
+                Err(err) => return Err(err.into()),
+            };
+
+            if checkpoint.exported > 0 {
+                debug!(exported = checkpoint.exported, "Resuming the export");
+            }
+
+            progress.update(|progress| progress.exported = checkpoint.exported);
+
+            let mut archive = Archive::open(&path, format, &checkpoint).await?;
+
+            loop {
+                let options = assign!(MessagesOptions::forward(), {
+                    from: checkpoint.from.clone(),
+                    limit: uint!(100),
+                    filter: assign!(RoomEventFilter::default(), {
+                        types: Some(vec!["m.room.message".to_owned(), "m.room.encrypted".to_owned()]),
+                    }),
+                });
+                let messages = room.messages(options).await?;
+
+                let mut events = messages.chunk;
+
+                // The last batch of a previous run may be returned again.
+                if let Some(last_event_id) = &checkpoint.last_event_id {
+                    if let Some(position) = events
+                        .iter()
+                        .position(|event| event_id(event).as_ref() == Some(last_event_id))
+                    {
+                        events.drain(..=position);
+                    }
+                }
+
+                let mut reached_range_end = false;
+
+                for event in events {
+                    let Some(ts) = event
+                        .raw()
+                        .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                        .ok()
+                        .flatten()
+                    else {
+                        continue;
+                    };
+
+                    if is_after(&range, ts) {
+                        reached_range_end = true;
+                        break;
+                    }
+
+                    if !range.contains(&ts) {
+                        continue;
+                    }
+
+                    checkpoint.last_event_id = event_id(&event);
+
+                    let Some(message) = as_message(&event, &progress) else {
+                        continue;
+                    };
+
+                    let media = if download_media {
+                        let media = room.download_message_media(&message.content.msgtype).await?;
+                        if media.is_some() {
+                            progress.update(|progress| progress.media_downloaded += 1);
+                        }
+                        media
+                    } else {
+                        None
+                    };
+
+                    archive
+                        .write_message(checkpoint.exported, event.raw(), &message, media)
+                        .await?;
+
+                    checkpoint.exported += 1;
+                    progress.update(|progress| progress.exported += 1);
+                }
+
+                checkpoint.archive_len = archive.len().await?;
+
+                // At the end of the timeline, keep the current token so the next run exports
+                // the messages received in the meantime.
+                let reached_timeline_end = messages.end.is_none();
+                if let Some(end) = messages.end {
+                    checkpoint.from = Some(end);
+                }
+
+                save_checkpoint(&checkpoint_path, &checkpoint).await?;
+
+                trace!(exported = checkpoint.exported, "Exported a batch of messages");
+
+                if reached_range_end || reached_timeline_end {
+                    break;
+                }
+            }
+
+            Ok(progress.get())
+        })
+    }
+}
+
+/// The messages file, or directory, of an archive.
+enum Archive {
+    Json { file: File, media_dir: PathBuf },
+    Eml { dir: PathBuf },
+}
+
+impl Archive {
+    /// Open the messages of the archive, discarding the ones written after
+    /// the checkpoint.
+    async fn open(
+        path: &Path,
+        format: ExportFormat,
+        checkpoint: &ExportCheckpoint,
+    ) -> Result<Self> {
+        let messages_path = path.join(format.messages_file_name());
+
+        Ok(match format {
+            ExportFormat::Json => {
+                let mut file =
+                    OpenOptions::new().create(true).write(true).open(&messages_path).await?;
+                file.set_len(checkpoint.archive_len).await?;
+                file.seek(io::SeekFrom::End(0)).await?;
+
+                Self::Json { file, media_dir: path.join("media") }
+            }
+
+            ExportFormat::Eml => {
+                // Messages are written to their own file, named after their index, so the
+                // ones written after the checkpoint are overwritten when resuming.
+                fs::create_dir_all(&messages_path).await?;
+                Self::Eml { dir: messages_path }
+            }
+        })
+    }
+
+    /// The length of the messages file, or 0 if there isn't a single one.
+    async fn len(&mut self) -> Result<u64> {
+        Ok(match self {
+            Self::Json { file, .. } => {
+                file.flush().await?;
+                file.metadata().await?.len()
+            }
+            Self::Eml { .. } => 0,
+        })
+    }
+
+    async fn write_message(
+        &mut self,
+        index: usize,
+        raw: &Raw<AnySyncTimelineEvent>,
+        message: &OriginalSyncRoomMessageEvent,
+        media: Option<ExportedMedia>,
+    ) -> Result<()> {
+        match self {
+            Self::Json { file, media_dir } => {
+                let media_path = match media {
+                    Some(media) => {
+                        fs::create_dir_all(&*media_dir).await?;
+
+                        let file_name =
+                            format!("{}-{}", sanitize(message.event_id.as_str()), media.file_name);
+                        fs::write(media_dir.join(&file_name), media.data).await?;
+
+                        Some(format!("media/{file_name}"))
+                    }
+                    None => None,
+                };
+
+                let mut line =
+                    serde_json::to_vec(&JsonArchiveEntry { event: raw, media: media_path })?;
+                line.push(b'\n');
+                file.write_all(&line).await?;
+            }
+
+            Self::Eml { dir } => {
+                let email = format_email(index, message, media);
+                fs::write(dir.join(format!("{index:08}.eml")), email).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A line of a JSON archive.
+#[derive(Serialize)]
+struct JsonArchiveEntry<'a> {
+    event: &'a Raw<AnySyncTimelineEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    media: Option<String>,
+}
+
+/// Write the checkpoint of an export, atomically.
+async fn save_checkpoint(path: &Path, checkpoint: &ExportCheckpoint) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, serde_json::to_vec(checkpoint)?).await?;
+    fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+/// Whether the timestamp is after the end of the range.
+fn is_after(
+    range: &(Bound<MilliSecondsSinceUnixEpoch>, Bound<MilliSecondsSinceUnixEpoch>),
+    ts: MilliSecondsSinceUnixEpoch,
+) -> bool {
+    match range.1 {
+        Bound::Included(end) => ts > end,
+        Bound::Excluded(end) => ts >= end,
+        Bound::Unbounded => false,
+    }
+}
+
+/// The ID of the event, if it has one.
+fn event_id(event: &TimelineEvent) -> Option<OwnedEventId> {
+    event.raw().get_field("event_id").ok().flatten()
+}
+
+/// Get the message in this event, if it's a decrypted and non-redacted
+/// `m.room.message` event.
+fn as_message(
+    event: &TimelineEvent,
+    progress: &SharedObservable<ExportProgress>,
+) -> Option<OriginalSyncRoomMessageEvent> {
+    if matches!(event.kind, TimelineEventKind::UnableToDecrypt { .. }) {
+        progress.update(|progress| progress.undecryptable += 1);
+        return None;
+    }
+
+    match event.raw().deserialize() {
+        Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+            SyncRoomMessageEvent::Original(message),
+        ))) => Some(message),
+        _ => None,
+    }
+}
+
+/// Replace the characters that aren't safe in file names, and email headers.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect()
+}
+
+/// Format a message as an email.
+fn format_email(
+    index: usize,
+    message: &OriginalSyncRoomMessageEvent,
+    media: Option<ExportedMedia>,
+) -> String {
+    let sender = &message.sender;
+    let mut email = format!(
+        "From: \"{sender}\" <{}@{}>\r\n\
+         Date: {}\r\n\
+         Message-ID: <{}@matrix>\r\n\
+         X-Matrix-Event-Id: {}\r\n\
+         MIME-Version: 1.0\r\n",
+        sender.localpart(),
+        sender.server_name(),
+        format_date(message.origin_server_ts),
+        sanitize(message.event_id.as_str()),
+        message.event_id,
+    );
+
+    let body = message.content.body().replace('\n', "\r\n");
+
+    match media {
+        None => {
+            email.push_str(
+                "Content-Type: text/plain; charset=utf-8\r\n\
+                 Content-Transfer-Encoding: 8bit\r\n\r\n",
+            );
+            email.push_str(&body);
+            email.push_str("\r\n");
+        }
+
+        Some(media) => {
+            let boundary = format!("matrix-export-{index}");
+            let mimetype = media.mimetype.as_deref().unwrap_or("application/octet-stream");
+
+            email.push_str(&format!(
+                "Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\r\n\
+                 --{boundary}\r\n\
+                 Content-Type: text/plain; charset=utf-8\r\n\
+                 Content-Transfer-Encoding: 8bit\r\n\r\n\
+                 {body}\r\n\
+                 --{boundary}\r\n\
+                 Content-Type: {mimetype}\r\n\
+                 Content-Disposition: attachment; filename=\"{}\"\r\n\
+                 Content-Transfer-Encoding: base64\r\n\r\n",
+                media.file_name,
+            ));
+
+            // Lines of base64 are limited to 76 characters.
+            let data = Base64::new(media.data).encode();
+            for line in data.as_bytes().chunks(76) {
+                // Base64 is ASCII, so this is always valid UTF-8.
+                email.push_str(std::str::from_utf8(line).unwrap_or_default());
+                email.push_str("\r\n");
+            }
+
+            email.push_str(&format!("--{boundary}--\r\n"));
+        }
+    }
+
+    email
+}
+
+/// Format a timestamp as a date of an email, as defined in RFC 5322.
+fn format_date(ts: MilliSecondsSinceUnixEpoch) -> String {
+    // The 1st of January 1970 was a Thursday.
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] =
+        ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = u64::from(ts.as_secs());
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Convert the number of days since the epoch to a date, with the algorithm from
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} +0000",
+        DAYS[(days % 7) as usize],
+        MONTHS[(month - 1) as usize],
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{from_value, json};
+
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(
+            format_date(MilliSecondsSinceUnixEpoch(uint!(0))),
+            "Thu, 01 Jan 1970 00:00:00 +0000"
+        );
+        assert_eq!(
+            format_date(MilliSecondsSinceUnixEpoch(uint!(1_700_000_000_000))),
+            "Tue, 14 Nov 2023 22:13:20 +0000"
+        );
+        assert_eq!(
+            format_date(MilliSecondsSinceUnixEpoch(uint!(951_782_400_000))),
+            "Tue, 29 Feb 2000 00:00:00 +0000"
+        );
+    }
+
+    #[test]
+    fn test_format_email() {
+        let message: OriginalSyncRoomMessageEvent = from_value(json!({
+            "content": { "msgtype": "m.text", "body": "hello\nworld" },
+            "event_id": "$ev:example.org",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 0,
+            "type": "m.room.message",
+        }))
+        .unwrap();
+
+        assert_eq!(
+            format_email(0, &message, None),
+            "From: \"@alice:example.org\" <alice@example.org>\r\n\
+             Date: Thu, 01 Jan 1970 00:00:00 +0000\r\n\
+             Message-ID: <_ev_example.org@matrix>\r\n\
+             X-Matrix-Event-Id: $ev:example.org\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: 8bit\r\n\r\n\
+             hello\r\nworld\r\n"
+        );
+
+        let media = ExportedMedia {
+            file_name: "cat.png".to_owned(),
+            mimetype: Some("image/png".to_owned()),
+            data: b"meow".to_vec(),
+        };
+        let email = format_email(1, &message, Some(media));
+        assert!(email.contains("Content-Type: multipart/mixed; boundary=\"matrix-export-1\""));
+        assert!(email.contains("Content-Disposition: attachment; filename=\"cat.png\""));
+        assert!(email.contains("\r\nbWVvdw==\r\n--matrix-export-1--\r\n"));
+    }
+}
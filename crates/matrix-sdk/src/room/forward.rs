@@ -0,0 +1,254 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Facilities to forward events to another room.
+
+#[cfg(feature = "e2e-encryption")]
+use std::io::Cursor;
+
+use matrix_sdk_base::deserialized_responses::SyncTimelineEvent;
+use ruma::{
+    api::client::message::send_message_event,
+    events::{
+        room::{
+            message::{MessageType, Relation, RoomMessageEventContent},
+            MediaSource, ThumbnailInfo,
+        },
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent, SyncMessageLikeEvent,
+    },
+};
+use thiserror::Error;
+use tracing::{debug, instrument};
+
+use crate::{
+    media::{MediaFormat, MediaRequestParameters},
+    Room,
+};
+
+/// An error occurring while forwarding an event.
+#[derive(Debug, Error)]
+pub enum ForwardEventError {
+    /// Only `m.room.message` events that haven't been redacted can be
+    /// forwarded.
+    #[error("Only room messages can be forwarded")]
+    UnsupportedEvent,
+
+    /// We couldn't properly deserialize the event.
+    #[error(transparent)]
+    Deserialize(#[from] serde_json::Error),
+
+    /// We couldn't download or upload again an encrypted attachment.
+    #[error("Couldn't upload the attachment again: {0}")]
+    Media(Box<crate::Error>),
+
+    /// We couldn't send the forwarded event.
+    #[error("Couldn't send the forwarded event: {0}")]
+    Send(Box<crate::Error>),
+}
+
+impl Room {
+    /// Forward a message of this room to the target room.
+    ///
+    /// The content of the event is sent again as is, except for:
+    ///
+    /// - its relations, since replies, threads and edits only make sense in the
+    ///   original room. The reply fallback is removed from the body too, and
+    ///   the new content of an edit is forwarded instead of its fallback,
+    /// - its mentions, to not notify the users mentioned in the original event,
+    /// - its encrypted attachment and thumbnail, if any, which are downloaded
+    ///   and uploaded again for the target room, with new keys if it is
+    ///   encrypted. Attachments that weren't encrypted are reused.
+    ///
+    /// The event must have been decrypted already, if it was encrypted.
+    #[instrument(skip_all, fields(room = %self.room_id(), target_room = %target_room.room_id()))]
+    pub async fn forward_event(
+        &self,
+        event: &SyncTimelineEvent,
+        target_room: &Room,
+    ) -> Result<send_message_event::v3::Response, ForwardEventError> {
+        let AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+            SyncMessageLikeEvent::Original(original),
+        )) = event.raw().deserialize()?
+        else {
+            return Err(ForwardEventError::UnsupportedEvent);
+        };
+
+        debug!(event_id = %original.event_id, "Forwarding event");
+
+        let mut msgtype = match original.content.relates_to {
+            // Forward the edited content, not the `* ` fallback of the edit.
+            Some(Relation::Replacement(replacement)) => replacement.new_content.msgtype,
+            Some(Relation::Reply { .. }) => {
+                let mut msgtype = original.content.msgtype;
+                remove_reply_fallback(&mut msgtype);
+                msgtype
+            }
+            _ => original.content.msgtype,
+        };
+
+        target_room
+            .reupload_attachments(&mut msgtype)
+            .await
+            .map_err(|error| ForwardEventError::Media(Box::new(error)))?;
+
+        target_room
+            .send(RoomMessageEventContent::new(msgtype))
+            .await
+            .map_err(|error| ForwardEventError::Send(Box::new(error)))
+    }
+
+    /// Upload again the encrypted attachment and thumbnail of a message, for
+    /// this room.
+    async fn reupload_attachments(&self, msgtype: &mut MessageType) -> crate::Result<()> {
+        match msgtype {
+            MessageType::Audio(content) => {
+                let mimetype = content.info.as_ref().and_then(|info| info.mimetype.as_deref());
+                self.reupload_media(&mut content.source, mimetype).await?;
+            }
+            MessageType::File(content) => {
+                let mimetype = content.info.as_ref().and_then(|info| info.mimetype.as_deref());
+                self.reupload_media(&mut content.source, mimetype).await?;
+
+                if let Some(info) = &mut content.info {
+                    self.reupload_thumbnail(
+                        &mut info.thumbnail_source,
+                        info.thumbnail_info.as_deref(),
+                    )
+                    .await?;
+                }
+            }
+            MessageType::Image(content) => {
+                let mimetype = content.info.as_ref().and_then(|info| info.mimetype.as_deref());
+                self.reupload_media(&mut content.source, mimetype).await?;
+
+                if let Some(info) = &mut content.info {
+                    self.reupload_thumbnail(
+                        &mut info.thumbnail_source,
+                        info.thumbnail_info.as_deref(),
+                    )
+                    .await?;
+                }
+            }
+            MessageType::Video(content) => {
+                let mimetype = content.info.as_ref().and_then(|info| info.mimetype.as_deref());
+                self.reupload_media(&mut content.source, mimetype).await?;
+
+                if let Some(info) = &mut content.info {
+                    self.reupload_thumbnail(
+                        &mut info.thumbnail_source,
+                        info.thumbnail_info.as_deref(),
+                    )
+                    .await?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Upload again the given thumbnail for this room, if it is encrypted.
+    async fn reupload_thumbnail(
+        &self,
+        source: &mut Option<MediaSource>,
+        info: Option<&ThumbnailInfo>,
+    ) -> crate::Result<()> {
+        let Some(source) = source else { return Ok(()) };
+        self.reupload_media(source, info.and_then(|info| info.mimetype.as_deref())).await
+    }
+
+    /// Upload again the given media for this room, if it is encrypted.
+    async fn reupload_media(
+        &self,
+        source: &mut MediaSource,
+        mimetype: Option<&str>,
+    ) -> crate::Result<()> {
+        if matches!(source, MediaSource::Plain(_)) {
+            return Ok(());
+        }
+
+        let media = self.client.media();
+        let request = MediaRequestParameters { source: source.clone(), format: MediaFormat::File };
+        let data = media.get_media_content(&request, true).await?;
+
+        let content_type = mimetype
+            .and_then(|mimetype| mimetype.parse().ok())
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+
+        #[cfg(feature = "e2e-encryption")]
+        if self.is_encrypted().await? {
+            let file =
+                self.client.upload_encrypted_file(&content_type, &mut Cursor::new(data)).await?;
+            *source = MediaSource::Encrypted(Box::new(file));
+            return Ok(());
+        }
+
+        let response = media.upload(&content_type, data, None).await?;
+        *source = MediaSource::Plain(response.content_uri);
+
+        Ok(())
+    }
+}
+
+/// Remove the fallback of the replied-to event from the body of a reply.
+fn remove_reply_fallback(msgtype: &mut MessageType) {
+    let (body, formatted) = match msgtype {
+        MessageType::Emote(content) => (&mut content.body, content.formatted.as_mut()),
+        MessageType::Notice(content) => (&mut content.body, content.formatted.as_mut()),
+        MessageType::Text(content) => (&mut content.body, content.formatted.as_mut()),
+        _ => return,
+    };
+
+    // The plain text fallback is a quote of the replied-to event, followed by an
+    // empty line.
+    if body.starts_with("> ") {
+        if let Some((_, reply)) = body.split_once("\n\n") {
+            *body = reply.to_owned();
+        }
+    }
+
+    if let Some(formatted) = formatted {
+        if let Some(end) = formatted.body.find("</mx-reply>") {
+            formatted.body = formatted.body[end + "</mx-reply>".len()..].to_owned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::events::room::message::{MessageType, RoomMessageEventContent};
+
+    use super::remove_reply_fallback;
+
+    #[test]
+    fn test_remove_reply_fallback() {
+        let mut msgtype = RoomMessageEventContent::text_html(
+            "> <@alice:localhost> Hello\n\nHi!",
+            "<mx-reply><blockquote>Hello</blockquote></mx-reply>Hi!",
+        )
+        .msgtype;
+        remove_reply_fallback(&mut msgtype);
+
+        let MessageType::Text(content) = msgtype else { panic!("the msgtype should be text") };
+        assert_eq!(content.body, "Hi!");
+        assert_eq!(content.formatted.unwrap().body, "Hi!");
+
+        // A quote that isn't a reply fallback is kept.
+        let mut msgtype = RoomMessageEventContent::text_plain("> To be or not to be").msgtype;
+        remove_reply_fallback(&mut msgtype);
+
+        let MessageType::Text(content) = msgtype else { panic!("the msgtype should be text") };
+        assert_eq!(content.body, "> To be or not to be");
+    }
+}
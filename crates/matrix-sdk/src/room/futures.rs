@@ -190,18 +190,7 @@ impl<'a> IntoFuture for SendRawMessageLikeEvent<'a> {
                         "Sending encrypted event because the room is encrypted.",
                     );
 
-                    if !room.are_members_synced() {
-                        room.sync_members().await?;
-                    }
-
-                    // Query keys in case we don't have them for newly synced members.
-                    //
-                    // Note we do it all the time, because we might have sync'd members before
-                    // sending a message (so didn't enter the above branch), but
-                    // could have not query their keys ever.
-                    room.query_keys_for_untracked_users().await?;
-
-                    room.preshare_room_key().await?;
+                    room.prepare_room_key().await?;
 
                     let olm = room.client.olm_machine().await;
                     let olm = olm.as_ref().expect("Olm machine wasn't started");
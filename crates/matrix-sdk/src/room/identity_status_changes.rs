@@ -122,6 +122,65 @@ impl IdentityStatusChanges {
     }
 }
 
+/// A summary of the members of a room whose identity changed in a way the
+/// current user should know about before sending messages to the room.
+///
+/// Obtained with [`Room::encryption_risk()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoomEncryptionRisk {
+    /// Members whose identity changed since we first saw it, and who haven't
+    /// been verified. Their new identity needs to be acknowledged, for example
+    /// with [`UserIdentity::remember_identity()`].
+    pub pin_violations: Vec<OwnedUserId>,
+
+    /// Members who were verified, and whose identity changed since. This is a
+    /// serious problem: the new identity needs to be verified again, or the
+    /// verification requirement withdrawn with
+    /// [`UserIdentity::withdraw_verification()`].
+    pub verification_violations: Vec<OwnedUserId>,
+}
+
+impl RoomEncryptionRisk {
+    /// Compute the risk of a room, out of the current identity states of its
+    /// members.
+    pub(crate) async fn compute(room: &Room) -> Result<Self> {
+        let own_user_id = room.client.user_id().ok_or(Error::InsufficientData)?.to_owned();
+        let room_identity_state = RoomIdentityState::new(room.clone()).await;
+
+        let mut risk = Self::default();
+
+        for change in filter_for_initial_update(room_identity_state.current_state(), &own_user_id) {
+            match change.changed_to {
+                IdentityState::PinViolation => risk.pin_violations.push(change.user_id),
+                IdentityState::VerificationViolation => {
+                    risk.verification_violations.push(change.user_id)
+                }
+                IdentityState::Verified | IdentityState::Pinned => {}
+            }
+        }
+
+        risk.pin_violations.sort();
+        risk.verification_violations.sort();
+
+        Ok(risk)
+    }
+
+    /// The number of members in violation, of either kind.
+    pub fn members_in_violation_count(&self) -> usize {
+        self.pin_violations.len() + self.verification_violations.len()
+    }
+
+    /// Whether sending messages to the room should be blocked until the
+    /// current user has resolved the violations.
+    ///
+    /// Only verification violations block sending: a pin violation is merely a
+    /// warning, since the identity of the member was never verified in the
+    /// first place.
+    pub fn should_block_sending(&self) -> bool {
+        !self.verification_violations.is_empty()
+    }
+}
+
 fn filter_for_initial_update(
     mut input: Vec<IdentityStatusChange>,
     own_user_id: &UserId,
@@ -570,6 +629,43 @@ mod tests {
         assert_eq!(change.len(), 1);
     }
 
+    #[async_test]
+    async fn test_encryption_risk_reports_pin_violations() {
+        // Given a room containing Bob, who is unpinned
+        let t = TestSetup::new_room_with_other_bob().await;
+        t.unpin_bob().await;
+
+        // Then the room reports a pin violation, which doesn't block sending
+        let risk = t.encryption_risk().await;
+        assert_eq!(risk.pin_violations, vec![t.bob_user_id().to_owned()]);
+        assert!(risk.verification_violations.is_empty());
+        assert_eq!(risk.members_in_violation_count(), 1);
+        assert!(!risk.should_block_sending());
+
+        // And once Bob's identity is remembered, the room isn't at risk anymore
+        t.remember_bob_identity().await;
+        assert_eq!(t.encryption_risk().await, Default::default());
+    }
+
+    #[async_test]
+    async fn test_encryption_risk_reports_verification_violations() {
+        // Given a room containing Bob, who was verified, and whose identity changed
+        let t = TestSetup::new_room_with_other_bob().await;
+        t.verify_bob().await;
+        t.unpin_bob().await;
+
+        // Then the room reports a verification violation, which blocks sending
+        let risk = t.encryption_risk().await;
+        assert!(risk.pin_violations.is_empty());
+        assert_eq!(risk.verification_violations, vec![t.bob_user_id().to_owned()]);
+        assert!(risk.should_block_sending());
+
+        // And once Bob's identity is remembered, the verification requirement is
+        // withdrawn and the room isn't at risk anymore
+        t.remember_bob_identity().await;
+        assert_eq!(t.encryption_risk().await, Default::default());
+    }
+
     // TODO: I (andyb) haven't figured out how to test room membership changes that
     // affect our own user (they should not be shown). Specifically, I haven't
     // figure out how to get out own user into a non-pinned state.
@@ -612,7 +708,8 @@ mod tests {
         };
 
         use crate::{
-            encryption::identities::UserIdentity, test_utils::logged_in_client, Client, Room,
+            encryption::identities::UserIdentity, room::RoomEncryptionRisk,
+            test_utils::logged_in_client, Client, Room,
         };
 
         /// Sets up a client and a room and allows changing user identities and
@@ -858,6 +955,19 @@ mod tests {
                 };
             }
 
+            pub(super) async fn encryption_risk(&self) -> RoomEncryptionRisk {
+                self.room.encryption_risk().await.expect("Should be able to compute the risk")
+            }
+
+            pub(super) async fn remember_bob_identity(&self) {
+                self.bob_user_identity()
+                    .await
+                    .expect("User identity should exist")
+                    .remember_identity()
+                    .await
+                    .expect("Should not fail to remember the identity");
+            }
+
             async fn bob_is_pinned(&self) -> bool {
                 !self.bob_crypto_other_identity().await.identity_needs_user_approval()
             }
@@ -0,0 +1,87 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! High-level helpers to list and handle the invites of the current user.
+
+use ruma::{events::room::member::MembershipState, RoomId, UserId};
+use tracing::warn;
+
+use super::Invite;
+use crate::{room_preview::RoomPreview, Client, Result, Room};
+
+/// An invite the current user hasn't acted upon yet, enriched with the
+/// information a client usually wants to display next to it.
+#[derive(Debug, Clone)]
+pub struct PendingInvite {
+    /// The details of the invite itself, including the inviter's profile if
+    /// it's known.
+    pub invite: Invite,
+
+    /// A preview of the room the user has been invited to.
+    pub room_preview: RoomPreview,
+
+    /// The number of joined rooms the current user shares with the inviter.
+    ///
+    /// This is `0` if the inviter is unknown.
+    pub shared_rooms_count: usize,
+}
+
+impl PendingInvite {
+    /// Build a [`PendingInvite`] from an invited room.
+    pub(crate) async fn from_room(client: &Client, room: &Room) -> Result<Self> {
+        let invite = room.invite_details().await?;
+        let room_preview = RoomPreview::from_joined(room).await;
+
+        let shared_rooms_count = match &invite.inviter {
+            Some(inviter) => shared_rooms_count(client, inviter.user_id()).await,
+            None => 0,
+        };
+
+        Ok(Self { invite, room_preview, shared_rooms_count })
+    }
+
+    /// The room id of the room the user has been invited to.
+    pub fn room_id(&self) -> &RoomId {
+        &self.room_preview.room_id
+    }
+
+    /// Accept the invite by joining the room.
+    pub async fn accept(&self) -> Result<()> {
+        self.invite.room().join().await
+    }
+
+    /// Decline the invite, optionally reporting the inviter as a spammer.
+    ///
+    /// See [`Invite::decline`] for details.
+    pub async fn decline(&self, report_as_spam: bool, reason: Option<String>) -> Result<()> {
+        self.invite.decline(report_as_spam, reason).await
+    }
+}
+
+/// Count the joined rooms in which `user_id` is a joined member.
+async fn shared_rooms_count(client: &Client, user_id: &UserId) -> usize {
+    let mut count = 0;
+
+    for room in client.joined_rooms() {
+        match room.get_member_no_sync(user_id).await {
+            Ok(Some(member)) if *member.membership() == MembershipState::Join => count += 1,
+            Ok(_) => {}
+            Err(err) => {
+                warn!(room_id = ?room.room_id(), "couldn't load a member to count shared rooms: {err}");
+            }
+        }
+    }
+
+    count
+}
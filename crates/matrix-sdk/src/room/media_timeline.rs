@@ -0,0 +1,309 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Listing of the media sent in a room, like for the "media and files" pane
+//! of a room.
+
+use std::{collections::BTreeSet, ops::ControlFlow, sync::Arc};
+
+use js_int::UInt;
+use ruma::{
+    events::{
+        room::{
+            message::{MessageType, SyncRoomMessageEvent},
+            MediaSource,
+        },
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+    },
+    serde::Raw,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId,
+};
+use tracing::{debug, instrument};
+
+use crate::{
+    event_cache::{EventCacheDropHandles, RoomEventCache},
+    Result, Room,
+};
+
+/// The number of events requested by each back-pagination of a
+/// [`MediaTimeline`].
+const PAGINATION_BATCH_SIZE: u16 = 50;
+
+/// The kind of a media in a [`MediaTimeline`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MediaKind {
+    /// An `m.image` message.
+    Image,
+
+    /// An `m.video` message.
+    Video,
+
+    /// An `m.audio` message.
+    Audio,
+
+    /// An `m.file` message.
+    File,
+}
+
+/// A media sent in a room, as listed by a [`MediaTimeline`].
+#[derive(Clone, Debug)]
+pub struct MediaItem {
+    /// The ID of the event of the media.
+    pub event_id: OwnedEventId,
+
+    /// The sender of the media.
+    pub sender: OwnedUserId,
+
+    /// When the media was sent.
+    pub timestamp: MilliSecondsSinceUnixEpoch,
+
+    /// The kind of the media.
+    pub kind: MediaKind,
+
+    /// The source of the media.
+    pub source: MediaSource,
+
+    /// The source of the thumbnail of the media, if any.
+    pub thumbnail_source: Option<MediaSource>,
+
+    /// The name of the file of the media.
+    pub filename: String,
+
+    /// The caption of the media, if any.
+    pub caption: Option<String>,
+
+    /// The mimetype of the media, if known.
+    pub mimetype: Option<String>,
+
+    /// The size of the media in bytes, if known.
+    pub size: Option<UInt>,
+}
+
+impl MediaItem {
+    /// Get the media of the given event, if it's a media message of one of
+    /// the given kinds.
+    fn from_raw(raw: &Raw<AnySyncTimelineEvent>, kinds: &BTreeSet<MediaKind>) -> Option<Self> {
+        let AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+            SyncRoomMessageEvent::Original(event),
+        )) = raw.deserialize().ok()?
+        else {
+            return None;
+        };
+
+        let (kind, source, thumbnail_source, mimetype, size) = match &event.content.msgtype {
+            MessageType::Image(content) => {
+                let info = content.info.as_deref();
+                (
+                    MediaKind::Image,
+                    content.source.clone(),
+                    info.and_then(|info| info.thumbnail_source.clone()),
+                    info.and_then(|info| info.mimetype.clone()),
+                    info.and_then(|info| info.size),
+                )
+            }
+            MessageType::Video(content) => {
+                let info = content.info.as_deref();
+                (
+                    MediaKind::Video,
+                    content.source.clone(),
+                    info.and_then(|info| info.thumbnail_source.clone()),
+                    info.and_then(|info| info.mimetype.clone()),
+                    info.and_then(|info| info.size),
+                )
+            }
+            MessageType::Audio(content) => {
+                let info = content.info.as_deref();
+                (
+                    MediaKind::Audio,
+                    content.source.clone(),
+                    None,
+                    info.and_then(|info| info.mimetype.clone()),
+                    info.and_then(|info| info.size),
+                )
+            }
+            MessageType::File(content) => {
+                let info = content.info.as_deref();
+                (
+                    MediaKind::File,
+                    content.source.clone(),
+                    info.and_then(|info| info.thumbnail_source.clone()),
+                    info.and_then(|info| info.mimetype.clone()),
+                    info.and_then(|info| info.size),
+                )
+            }
+            _ => return None,
+        };
+
+        if !kinds.contains(&kind) {
+            return None;
+        }
+
+        let (filename, caption) = match &event.content.msgtype {
+            MessageType::Image(content) => (content.filename(), content.caption()),
+            MessageType::Video(content) => (content.filename(), content.caption()),
+            MessageType::Audio(content) => (content.filename(), content.caption()),
+            MessageType::File(content) => (content.filename(), content.caption()),
+            _ => return None,
+        };
+
+        Some(Self {
+            event_id: event.event_id,
+            sender: event.sender,
+            timestamp: event.origin_server_ts,
+            kind,
+            source,
+            thumbnail_source,
+            filename: filename.to_owned(),
+            caption: caption.map(ToOwned::to_owned),
+            mimetype,
+            size,
+        })
+    }
+}
+
+/// The media sent in a room, from the most recent to the oldest, as returned
+/// by [`Room::media_timeline()`].
+///
+/// It starts with the media of the events in the [event
+/// cache](crate::event_cache), and more can be loaded with
+/// [`MediaTimeline::paginate_backwards()`].
+#[derive(Debug)]
+pub struct MediaTimeline {
+    room_event_cache: RoomEventCache,
+    _drop_handles: Arc<EventCacheDropHandles>,
+    kinds: BTreeSet<MediaKind>,
+    items: Vec<MediaItem>,
+    event_ids: BTreeSet<OwnedEventId>,
+    reached_start: bool,
+}
+
+impl MediaTimeline {
+    /// The media loaded so far, from the most recent to the oldest.
+    pub fn items(&self) -> &[MediaItem] {
+        &self.items
+    }
+
+    /// Whether the start of the room was reached, in which case all the media
+    /// of the room have been loaded.
+    pub fn reached_start(&self) -> bool {
+        self.reached_start
+    }
+
+    /// Back-paginate the room until at least `count` older media are loaded,
+    /// or the start of the room is reached.
+    ///
+    /// Returns the number of media that were loaded, which were appended to
+    /// the [`items()`](Self::items).
+    #[instrument(skip(self))]
+    pub async fn paginate_backwards(&mut self, count: usize) -> Result<usize> {
+        let previous_len = self.items.len();
+
+        while !self.reached_start && self.items.len() - previous_len < count {
+            let outcome = self
+                .room_event_cache
+                .pagination()
+                .run_backwards(PAGINATION_BATCH_SIZE, |outcome, _| async move {
+                    ControlFlow::Break(outcome)
+                })
+                .await?;
+
+            // The events of a back-pagination are in reverse order, like the items.
+            self.extend(outcome.events.iter().map(|event| event.raw()));
+            self.reached_start = outcome.reached_start;
+        }
+
+        let loaded = self.items.len() - previous_len;
+        debug!(loaded, reached_start = self.reached_start, "Loaded older media");
+
+        Ok(loaded)
+    }
+
+    /// Add the media of the given events, from the most recent to the oldest,
+    /// after the current items.
+    fn extend<'a>(&mut self, events: impl Iterator<Item = &'a Raw<AnySyncTimelineEvent>>) {
+        for event in events {
+            let Some(item) = MediaItem::from_raw(event, &self.kinds) else { continue };
+
+            // The events of the cache can be returned again by a back-pagination.
+            if self.event_ids.insert(item.event_id.clone()) {
+                self.items.push(item);
+            }
+        }
+    }
+}
+
+impl Room {
+    /// Get the media of the given kinds sent in this room, from the most
+    /// recent to the oldest.
+    ///
+    /// The media of the events in the [event cache](crate::event_cache) are
+    /// listed first, and older media can be loaded with
+    /// [`MediaTimeline::paginate_backwards()`]. Only the events that could be
+    /// decrypted are considered.
+    ///
+    /// This requires the event cache to be subscribed.
+    pub async fn media_timeline(&self, kinds: &[MediaKind]) -> Result<MediaTimeline> {
+        let (room_event_cache, drop_handles) = self.event_cache().await?;
+        let (events, _) = room_event_cache.subscribe().await?;
+
+        let mut timeline = MediaTimeline {
+            room_event_cache,
+            _drop_handles: drop_handles,
+            kinds: kinds.iter().copied().collect(),
+            items: Vec::new(),
+            event_ids: BTreeSet::new(),
+            reached_start: false,
+        };
+        timeline.extend(events.iter().rev().map(|event| event.raw()));
+
+        Ok(timeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use assert_matches2::assert_let;
+    use matrix_sdk_test::event_factory::EventFactory;
+    use ruma::{event_id, events::room::MediaSource, mxc_uri, owned_mxc_uri, user_id};
+
+    use super::{MediaItem, MediaKind};
+
+    #[test]
+    fn test_media_item_from_raw() {
+        let f = EventFactory::new().sender(user_id!("@alice:localhost"));
+        let kinds = BTreeSet::from([MediaKind::Image]);
+
+        let image = f
+            .image("cat.png".to_owned(), owned_mxc_uri!("mxc://localhost/cat"))
+            .event_id(event_id!("$image"))
+            .into_raw_sync();
+        let item = MediaItem::from_raw(&image, &kinds).unwrap();
+        assert_eq!(item.event_id, event_id!("$image"));
+        assert_eq!(item.sender, user_id!("@alice:localhost"));
+        assert_eq!(item.kind, MediaKind::Image);
+        assert_eq!(item.filename, "cat.png");
+        assert!(item.caption.is_none());
+        assert_let!(MediaSource::Plain(uri) = item.source);
+        assert_eq!(uri, mxc_uri!("mxc://localhost/cat"));
+
+        // Other kinds of media are filtered out.
+        assert!(MediaItem::from_raw(&image, &BTreeSet::from([MediaKind::File])).is_none());
+
+        // So are the messages without media.
+        let text = f.text_msg("hello").event_id(event_id!("$text")).into_raw_sync();
+        assert!(MediaItem::from_raw(&text, &kinds).is_none());
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A summary of the memberships of a room, to display badges like "3 pending
+//! knocks".
+
+use async_stream::stream;
+use futures_core::Stream;
+use matrix_sdk_base::RoomMemberships;
+use ruma::events::room::join_rules::JoinRule;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+use crate::{Result, Room};
+
+/// The number of members of a room in each membership state, along with its
+/// join rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoomMembershipSummary {
+    /// The join rule of the room.
+    pub join_rule: JoinRule,
+
+    /// The number of joined members, from the room summary.
+    pub joined: u64,
+
+    /// The number of invited members, from the room summary.
+    pub invited: u64,
+
+    /// The number of members asking to join the room, from the known
+    /// `m.room.member` events.
+    pub knocking: u64,
+}
+
+impl RoomMembershipSummary {
+    /// Whether there are pending requests to join the room.
+    pub fn has_pending_knocks(&self) -> bool {
+        self.knocking > 0
+    }
+}
+
+impl Room {
+    /// Get the [`RoomMembershipSummary`] of this room.
+    ///
+    /// The number of knocking members is computed from the members known
+    /// locally, which may be incomplete if they were never loaded, see
+    /// [`Room::sync_members()`].
+    pub async fn membership_summary(&self) -> Result<RoomMembershipSummary> {
+        let knocking = self
+            .client
+            .store()
+            .get_user_ids(self.room_id(), RoomMemberships::KNOCK)
+            .await?
+            .len()
+            .try_into()
+            .unwrap_or(u64::MAX);
+
+        Ok(RoomMembershipSummary {
+            join_rule: self.join_rule(),
+            joined: self.joined_members_count(),
+            invited: self.invited_members_count(),
+            knocking,
+        })
+    }
+
+    /// Subscribe to the [`RoomMembershipSummary`] of this room.
+    ///
+    /// The current summary is emitted immediately when subscribing. A new one
+    /// is emitted whenever it changes, i.e. when the room summary or join
+    /// rule are updated, or when `m.room.member` events are received.
+    pub async fn subscribe_to_membership_summary(
+        &self,
+    ) -> Result<impl Stream<Item = RoomMembershipSummary>> {
+        let this = self.clone();
+        let mut current = self.membership_summary().await?;
+
+        let mut room_info_stream = self.subscribe_info();
+        let mut member_updates = self.room_member_updates_sender.subscribe();
+
+        Ok(stream! {
+            yield current.clone();
+
+            loop {
+                tokio::select! {
+                    Some(_) = room_info_stream.next() => {}
+
+                    result = member_updates.recv() => {
+                        if let Err(RecvError::Closed) = result {
+                            break;
+                        }
+                    }
+
+                    else => break,
+                }
+
+                match this.membership_summary().await {
+                    Ok(summary) => {
+                        if summary != current {
+                            current = summary;
+                            yield current.clone();
+                        }
+                    }
+                    Err(err) => warn!("Failed to get the updated membership summary: {err}"),
+                }
+            }
+        })
+    }
+}
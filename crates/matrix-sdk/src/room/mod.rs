@@ -18,12 +18,16 @@ use std::{
     borrow::Borrow,
     collections::{BTreeMap, HashMap},
     ops::Deref,
+    slice,
     sync::Arc,
     time::Duration,
 };
 
 use async_stream::stream;
-use eyeball::SharedObservable;
+#[cfg(feature = "e2e-encryption")]
+pub use enable_encryption::EnableEncryptionPreview;
+use eyeball::{SharedObservable, Subscriber};
+use eyeball_im::{Vector, VectorDiff};
 use futures_core::Stream;
 use futures_util::{
     future::{try_join, try_join_all},
@@ -31,7 +35,7 @@ use futures_util::{
 };
 use http::StatusCode;
 #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
-pub use identity_status_changes::IdentityStatusChanges;
+pub use identity_status_changes::{IdentityStatusChanges, RoomEncryptionRisk};
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::crypto::{DecryptionSettings, RoomEventDecryptionResult};
 #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
@@ -42,8 +46,8 @@ use matrix_sdk_base::{
     },
     media::MediaThumbnailSettings,
     store::StateStoreExt,
-    ComposerDraft, RoomInfoNotableUpdateReasons, RoomMemberships, StateChanges, StateStoreDataKey,
-    StateStoreDataValue,
+    ComposerDraft, RoomInfoNotableUpdateReasons, RoomMembersUpdate, RoomMemberships, StateChanges,
+    StateStoreDataKey, StateStoreDataValue,
 };
 #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
 use matrix_sdk_common::BoxFuture;
@@ -53,11 +57,6 @@ use matrix_sdk_common::{
     timeout::timeout,
 };
 use mime::Mime;
-#[cfg(feature = "e2e-encryption")]
-use ruma::events::{
-    room::encrypted::OriginalSyncRoomEncryptedEvent, AnySyncMessageLikeEvent, AnySyncTimelineEvent,
-    SyncMessageLikeEvent,
-};
 use ruma::{
     api::client::{
         config::{set_global_account_data, set_room_account_data},
@@ -84,13 +83,15 @@ use ruma::{
         beacon_info::BeaconInfoEventContent,
         call::notify::{ApplicationType, CallNotifyEventContent, NotifyType},
         direct::DirectEventContent,
-        marked_unread::{MarkedUnreadEventContent, UnstableMarkedUnreadEventContent},
+        marked_unread::MarkedUnreadEventContent,
         receipt::{Receipt, ReceiptThread, ReceiptType},
         room::{
             avatar::{self, RoomAvatarEventContent},
             encryption::RoomEncryptionEventContent,
             history_visibility::HistoryVisibility,
-            member::{MembershipChange, SyncRoomMemberEvent},
+            member::{
+                MembershipChange, MembershipState, RoomMemberEventContent, SyncRoomMemberEvent,
+            },
             message::{
                 AudioInfo, AudioMessageEventContent, FileInfo, FileMessageEventContent,
                 FormattedBody, ImageMessageEventContent, MessageType, RoomMessageEventContent,
@@ -99,17 +100,19 @@ use ruma::{
             },
             name::RoomNameEventContent,
             pinned_events::RoomPinnedEventsEventContent,
-            power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+            power_levels::{
+                RoomPowerLevels, RoomPowerLevelsEventContent, SyncRoomPowerLevelsEvent,
+            },
             server_acl::RoomServerAclEventContent,
             topic::RoomTopicEventContent,
             ImageInfo, MediaSource, ThumbnailInfo,
         },
         space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
-        tag::{TagInfo, TagName},
+        tag::{TagEvent, TagInfo, TagName, Tags},
         typing::SyncTypingEvent,
-        AnyRoomAccountDataEvent, AnyRoomAccountDataEventContent, AnyTimelineEvent, EmptyStateKey,
-        Mentions, MessageLikeEventContent, MessageLikeEventType, OriginalSyncStateEvent,
-        RedactContent, RedactedStateEventContent, RoomAccountDataEvent,
+        AnyRoomAccountDataEvent, AnyRoomAccountDataEventContent, AnySyncStateEvent,
+        AnyTimelineEvent, EmptyStateKey, Mentions, MessageLikeEventContent, MessageLikeEventType,
+        OriginalSyncStateEvent, RedactContent, RedactedStateEventContent, RoomAccountDataEvent,
         RoomAccountDataEventContent, RoomAccountDataEventType, StateEventContent, StateEventType,
         StaticEventContent, StaticStateEventContent, SyncStateEvent,
     },
@@ -119,9 +122,17 @@ use ruma::{
     EventId, Int, MatrixToUri, MatrixUri, MxcUri, OwnedEventId, OwnedRoomId, OwnedServerName,
     OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt, UserId,
 };
+#[cfg(feature = "e2e-encryption")]
+use ruma::{
+    events::{
+        room::encrypted::OriginalSyncRoomEncryptedEvent, AnySyncMessageLikeEvent,
+        AnySyncTimelineEvent, SyncMessageLikeEvent,
+    },
+    DeviceId,
+};
 use serde::de::DeserializeOwned;
 use thiserror::Error;
-use tokio::sync::broadcast;
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio_stream::StreamExt;
 use tracing::{debug, info, instrument, warn};
 
@@ -135,16 +146,18 @@ use crate::event_cache::EventCache;
 use crate::{
     attachment::{AttachmentConfig, AttachmentInfo},
     client::WeakClient,
-    config::RequestConfig,
+    config::{MemberStorageStrategy, RequestConfig},
     error::{BeaconError, WrongRoomState},
-    event_cache::{self, EventCacheDropHandles, RoomEventCache},
+    event_cache::{self, EventCacheDropHandles, EventIsland, RoomEventCache},
     event_handler::{EventHandler, EventHandlerDropGuard, EventHandlerHandle, SyncEvent},
     live_location_share::ObservableLiveLocation,
     media::{MediaFormat, MediaRequestParameters},
     notification_settings::{IsEncrypted, IsOneToOne, RoomNotificationMode},
     room::{
         knock_requests::{KnockRequest, KnockRequestMemberInfo},
-        power_levels::{RoomPowerLevelChanges, RoomPowerLevelsExt},
+        power_levels::{RoomPermissions, RoomPowerLevelChanges, RoomPowerLevelsExt},
+        privacy_settings::RoomPrivacySettings,
+        sorted_members::{MemberSortStrategy, SortedMembers},
     },
     sync::RoomUpdate,
     utils::{IntoRawMessageLikeEventContent, IntoRawStateEventContent},
@@ -153,14 +166,31 @@ use crate::{
 #[cfg(feature = "e2e-encryption")]
 use crate::{crypto::types::events::CryptoContextInfo, encryption::backups::BackupState};
 
+pub mod bulk_invite;
 pub mod edit;
+pub mod enable_encryption;
+pub mod encryption_info;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod export;
+pub mod forward;
 pub mod futures;
 pub mod identity_status_changes;
+pub mod invites;
 /// Contains code related to requests to join a room.
 pub mod knock_requests;
+pub mod media_timeline;
 mod member;
+pub mod membership_summary;
 mod messages;
+pub mod moderation;
 pub mod power_levels;
+pub mod privacy_settings;
+pub mod reactions;
+mod read_by;
+pub mod sorted_members;
+pub mod state_history;
+#[cfg(feature = "unstable-msc3765")]
+pub mod topic;
 
 /// A struct containing methods that are common for Joined, Invited and Left
 /// Rooms
@@ -210,7 +240,8 @@ impl Room {
 
     /// Join this room.
     ///
-    /// Only invited and left rooms can be joined via this method.
+    /// Only invited and left rooms can be joined via this method. Left rooms
+    /// are joined through the servers returned by [`Room::route()`].
     #[doc(alias = "accept_invitation")]
     pub async fn join(&self) -> Result<()> {
         let state = self.state();
@@ -226,7 +257,15 @@ impl Room {
                 false
             });
 
-        self.client.join_room_by_id(self.room_id()).await?;
+        if prev_room_state == RoomState::Left {
+            let via = self.route().await.unwrap_or_else(|e| {
+                warn!(room_id = ?self.room_id(), "Couldn't compute the route of the room: {e}");
+                Vec::new()
+            });
+            self.client.join_room_by_id_or_alias(self.room_id().into(), &via).await?;
+        } else {
+            self.client.join_room_by_id(self.room_id()).await?;
+        }
 
         if mark_as_direct {
             self.set_is_direct(true).await?;
@@ -438,6 +477,19 @@ impl Room {
         IdentityStatusChanges::create_stream(self.clone()).await
     }
 
+    /// Summarize which members of this room are in identity violation.
+    ///
+    /// Apps can use this to implement an "identity changed" flow: warn about
+    /// members in pin violation, and block sending while some members are in
+    /// verification violation (see [`RoomEncryptionRisk::should_block_sending`]).
+    ///
+    /// Only the identities already known by the crypto store are looked at;
+    /// the current user is never included.
+    #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
+    pub async fn encryption_risk(&self) -> Result<RoomEncryptionRisk> {
+        RoomEncryptionRisk::compute(self).await
+    }
+
     /// Returns a wrapping `TimelineEvent` for the input `AnyTimelineEvent`,
     /// decrypted if needs be.
     ///
@@ -477,7 +529,9 @@ impl Room {
 
         // Save the event into the event cache, if it's set up.
         if let Ok((cache, _handles)) = self.event_cache().await {
-            cache.save_event(event.clone().into()).await;
+            let mut event_to_save = SyncTimelineEvent::from(event.clone());
+            self.client.enrich_events(self, slice::from_mut(&mut event_to_save)).await;
+            cache.save_event(event_to_save).await;
         }
 
         Ok(event)
@@ -534,6 +588,7 @@ impl Room {
                 events_to_save.push(event.clone().into());
             }
 
+            self.client.enrich_events(self, &mut events_to_save).await;
             cache.save_events(events_to_save).await;
         }
 
@@ -547,6 +602,70 @@ impl Room {
         })
     }
 
+    /// Fetch the event with the given `EventId` in this room, with at least
+    /// `before` events before it and `after` events after it, to jump to it in
+    /// a timeline.
+    ///
+    /// The events are kept as an island in the event cache, if it's set up, so
+    /// jumping to the same event again doesn't need any request. The island
+    /// may contain more events than requested, and fewer at the start or the
+    /// end of the room.
+    ///
+    /// The room members of the senders of the events are lazy-loaded, and
+    /// returned in [`EventWithContextResponse::state`].
+    pub async fn jump_to_event(
+        &self,
+        event_id: &EventId,
+        before: u16,
+        after: u16,
+    ) -> Result<EventWithContextResponse> {
+        let cache = self.event_cache().await.ok().map(|(cache, _handles)| cache);
+
+        if let Some(cache) = &cache {
+            if let Some((event, events_before, events_after, island)) =
+                cache.island(event_id, before.into(), after.into()).await
+            {
+                let into_timeline_event = |event: SyncTimelineEvent| TimelineEvent {
+                    kind: event.kind,
+                    push_actions: Some(event.push_actions),
+                };
+
+                return Ok(EventWithContextResponse {
+                    event: Some(into_timeline_event(event)),
+                    events_before: events_before.into_iter().map(into_timeline_event).collect(),
+                    events_after: events_after.into_iter().map(into_timeline_event).collect(),
+                    prev_batch_token: island.prev_batch_token,
+                    next_batch_token: island.next_batch_token,
+                    state: island.state,
+                });
+            }
+        }
+
+        // The limit of `/context` applies to the sum of the events before and after
+        // the target event, and the server splits it as it wishes, usually evenly.
+        let context_size = UInt::from(u32::from(before.max(after)) * 2);
+        let response = self.event_with_context(event_id, true, context_size, None).await?;
+
+        if let (Some(cache), Some(event)) = (&cache, &response.event) {
+            let event_ids = |events: &[TimelineEvent]| -> Vec<OwnedEventId> {
+                events.iter().filter_map(|event| event.event_id()).collect()
+            };
+
+            if let Some(event_id) = event.event_id() {
+                let island = EventIsland {
+                    events_before: event_ids(&response.events_before),
+                    events_after: event_ids(&response.events_after),
+                    prev_batch_token: response.prev_batch_token.clone(),
+                    next_batch_token: response.next_batch_token.clone(),
+                    state: response.state.clone(),
+                };
+                cache.save_island(event_id, island).await;
+            }
+        }
+
+        Ok(response)
+    }
+
     pub(crate) async fn request_members(&self) -> Result<()> {
         self.client
             .locks()
@@ -571,6 +690,9 @@ impl Room {
                 ))
                 .await?;
 
+                // The members changed, the servers of the room need to be computed again.
+                self.client.inner.room_routes.lock().unwrap().invalidate(self.room_id());
+
                 Ok(())
             })
             .await
@@ -715,6 +837,19 @@ impl Room {
             .map(|member| RoomMember::new(self.client.clone(), member)))
     }
 
+    /// Get how the member events of this room are persisted, according to the
+    /// [`MemberStoragePolicy`] of the store.
+    ///
+    /// With [`MemberStorageStrategy::MembershipOnly`], the members returned by
+    /// this room don't have a display name or an avatar, except for the heroes
+    /// of the room and the current user. Their global profiles can be fetched
+    /// lazily with [`Client::get_profiles()`] instead.
+    ///
+    /// [`MemberStoragePolicy`]: crate::config::MemberStoragePolicy
+    pub fn member_storage_strategy(&self) -> MemberStorageStrategy {
+        self.client.base_client().member_storage_policy().strategy_for(&self.clone_info())
+    }
+
     /// Get members for this room, with the given memberships.
     ///
     /// *Note*: This method will fetch the members from the homeserver if the
@@ -746,6 +881,82 @@ impl Room {
             .collect())
     }
 
+    /// Get the active members of this room, sorted for display, and a stream
+    /// of updates to this list.
+    ///
+    /// Members are sorted by power level tier, then by name, according to the
+    /// given [`MemberSortStrategy`]. The list is updated incrementally when
+    /// members change, and is reset when the power levels of the room change
+    /// or when the whole member list is reloaded, e.g. after calling
+    /// [`Room::sync_members()`].
+    ///
+    /// *Note*: Like [`Room::members_no_sync()`], this method doesn't fetch the
+    /// members from the homeserver, so the initial list might be incomplete.
+    pub async fn sorted_members(
+        &self,
+        strategy: MemberSortStrategy,
+    ) -> Result<(Vector<RoomMember>, impl Stream<Item = Vec<VectorDiff<RoomMember>>>)> {
+        let mut member_updates = self.room_member_updates_sender.subscribe();
+        let power_levels_observer =
+            self.client.observe_room_events::<SyncRoomPowerLevelsEvent, ()>(self.room_id());
+        let mut power_levels_stream = power_levels_observer.subscribe();
+
+        let mut sorted =
+            SortedMembers::new(strategy, self.members_no_sync(RoomMemberships::ACTIVE).await?);
+        let initial = sorted.members().clone();
+
+        let this = self.clone();
+        let stream = stream! {
+            // Keep the observer alive as long as the stream.
+            let _power_levels_observer = power_levels_observer;
+
+            loop {
+                let reset = tokio::select! {
+                    update = member_updates.recv() => match update {
+                        Ok(RoomMembersUpdate::Partial(user_ids)) => {
+                            let mut diffs = Vec::new();
+
+                            for user_id in user_ids {
+                                let member = match this.get_member_no_sync(&user_id).await {
+                                    Ok(member) => member.filter(|member| {
+                                        RoomMemberships::ACTIVE.matches(member.membership())
+                                    }),
+                                    Err(err) => {
+                                        warn!("Failed to load updated member {user_id}: {err}");
+                                        continue;
+                                    }
+                                };
+
+                                diffs.extend(sorted.update(&user_id, member));
+                            }
+
+                            if !diffs.is_empty() {
+                                yield diffs;
+                            }
+
+                            false
+                        }
+                        Ok(RoomMembersUpdate::FullReload) | Err(RecvError::Lagged(_)) => true,
+                        Err(RecvError::Closed) => break,
+                    },
+
+                    Some(_) = power_levels_stream.next() => true,
+
+                    else => break,
+                };
+
+                if reset {
+                    match this.members_no_sync(RoomMemberships::ACTIVE).await {
+                        Ok(members) => yield vec![sorted.reset(members)],
+                        Err(err) => warn!("Failed to reload the sorted members: {err}"),
+                    }
+                }
+            }
+        };
+
+        Ok((initial, stream))
+    }
+
     /// Get all state events of a given type in this room.
     pub async fn get_state_events(
         &self,
@@ -1111,7 +1322,9 @@ impl Room {
     /// * `tag` - The tag to add or update.
     ///
     /// * `tag_info` - Information about the tag, generally containing the
-    ///   `order` parameter.
+    ///   `order` parameter. The order is the relative position of the room
+    ///   among the rooms with the same tag, and must be between 0 and 1,
+    ///   otherwise [`Error::InvalidTagOrder`] is returned.
     ///
     /// # Examples
     ///
@@ -1138,6 +1351,12 @@ impl Room {
         tag: TagName,
         tag_info: TagInfo,
     ) -> Result<create_tag::v3::Response> {
+        if let Some(order) = tag_info.order {
+            if !(0.0..=1.0).contains(&order) {
+                return Err(Error::InvalidTagOrder(order));
+            }
+        }
+
         let user_id = self.client.user_id().ok_or(Error::AuthenticationRequired)?;
         let request = create_tag::v3::Request::new(
             user_id.to_owned(),
@@ -1164,6 +1383,22 @@ impl Room {
         Ok(self.client.send(request).await?)
     }
 
+    /// Subscribe to the changes of the tags of this room.
+    ///
+    /// The returned receiver receives the new tags of the room, with their
+    /// order, for each sync response that updates them. The current tags can
+    /// be read with [`Room::tags()`].
+    pub fn subscribe_to_tags(&self) -> (EventHandlerDropGuard, broadcast::Receiver<Tags>) {
+        let (sender, receiver) = broadcast::channel(16);
+        let tag_event_handler_handle =
+            self.client.add_room_event_handler(self.room_id(), move |event: TagEvent| async move {
+                // Ignore the result. It can only fail if there are no listeners.
+                let _ = sender.send(event.content.tags);
+            });
+        let drop_guard = self.client().event_handler_drop_guard(tag_event_handler_handle);
+        (drop_guard, receiver)
+    }
+
     /// Add or remove the `m.favourite` flag for this room.
     ///
     /// If `is_favourite` is `true`, and the `m.low_priority` tag is set on the
@@ -1322,6 +1557,128 @@ impl Room {
         }
     }
 
+    /// Export the room keys of this room, encrypted with the given passphrase.
+    ///
+    /// The export can be imported on another device with
+    /// [`Encryption::import_room_keys()`], to decrypt the history of this room
+    /// without importing a whole backup.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it isn't run on a Tokio runtime.
+    ///
+    /// [`Encryption::import_room_keys()`]: crate::encryption::Encryption::import_room_keys
+    #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
+    pub async fn export_room_keys(&self, passphrase: &str) -> Result<String> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let room_id = self.room_id();
+        let keys = olm.store().export_room_keys(|session| session.room_id() == room_id).await?;
+        let passphrase = zeroize::Zeroizing::new(passphrase.to_owned());
+
+        let encrypt = move || -> Result<String> {
+            Ok(matrix_sdk_base::crypto::encrypt_room_key_export(&keys, &passphrase, 500_000)?)
+        };
+
+        let task = tokio::task::spawn_blocking(encrypt);
+        task.await.expect("Task join error")
+    }
+
+    /// Share all the room keys of this room with another device of our own
+    /// user.
+    ///
+    /// This allows a new device to decrypt the history of this room, without
+    /// importing a whole backup. The device must be verified, otherwise
+    /// [`Error::NotVerifiedOwnDevice`] is returned. The other device only
+    /// accepts the room keys if it verified this device, and if it enabled
+    /// it with [`Encryption::set_unrequested_room_key_forwards_enabled()`].
+    ///
+    /// [`Encryption::set_unrequested_room_key_forwards_enabled()`]: crate::encryption::Encryption::set_unrequested_room_key_forwards_enabled
+    ///
+    /// Returns the number of room keys that were shared.
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn share_room_keys_with_own_device(&self, device_id: &DeviceId) -> Result<usize> {
+        let encryption = self.client.encryption();
+        let own_user_id = self.own_user_id();
+
+        let device = encryption.get_device(own_user_id, device_id).await?;
+        if !device.is_some_and(|device| device.is_verified()) {
+            return Err(Error::NotVerifiedOwnDevice);
+        }
+
+        encryption.claim_one_time_keys(std::iter::once(own_user_id)).await?;
+
+        let requests = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+            olm.forward_room_keys_to_own_device(self.room_id(), device_id)
+                .await?
+                .ok_or(Error::NotVerifiedOwnDevice)?
+        };
+
+        // A to-device request can only hold one room key for a device, so send
+        // the requests concurrently.
+        const MAX_CONCURRENT_REQUESTS: usize = 10;
+
+        let mut responses = futures_util::StreamExt::buffer_unordered(
+            tokio_stream::iter(&requests).map(|request| encryption.send_to_device_request(request)),
+            MAX_CONCURRENT_REQUESTS,
+        );
+
+        while let Some(response) = responses.next().await {
+            response?;
+        }
+
+        Ok(requests.len())
+    }
+
+    /// Get whether room keys of this room are only shared with trusted
+    /// devices, if this was overridden for this room.
+    ///
+    /// Returns `None` if the room follows the `only_allow_trusted_devices`
+    /// setting of the [`CollectStrategy`] used by the client.
+    ///
+    /// [`CollectStrategy`]: crate::crypto::CollectStrategy
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn encryption_trust_requirement(&self) -> Result<Option<bool>> {
+        let machine = self.client.olm_machine().await;
+        let machine = machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(machine.store().get_room_only_allow_trusted_devices(self.room_id()).await?)
+    }
+
+    /// Override whether room keys of this room should only be shared with
+    /// trusted devices.
+    ///
+    /// The override is persisted in the crypto store. When set to `true`,
+    /// untrusted devices receive an `m.unverified` withheld code instead of the
+    /// room key, and the current room key is rotated if it was already shared
+    /// with some of them. Pass `None` to go back to the
+    /// `only_allow_trusted_devices` setting of the [`CollectStrategy`] used by
+    /// the client.
+    ///
+    /// This has no effect with the identity-based strategy, which never shares
+    /// room keys with devices that aren't signed by their owner.
+    ///
+    /// [`CollectStrategy`]: crate::crypto::CollectStrategy
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn set_encryption_trust_requirement(
+        &self,
+        only_allow_trusted_devices: Option<bool>,
+    ) -> Result<()> {
+        let machine = self.client.olm_machine().await;
+        let machine = machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        machine
+            .store()
+            .set_room_only_allow_trusted_devices(self.room_id(), only_allow_trusted_devices)
+            .await?;
+
+        Ok(())
+    }
+
     /// Ban the user with `UserId` from this room.
     ///
     /// # Arguments
@@ -1522,6 +1879,9 @@ impl Room {
         thread: ReceiptThread,
         event_id: OwnedEventId,
     ) -> Result<()> {
+        // Reading the main timeline cancels the unread flag.
+        let is_main_read_receipt = !matches!(thread, ReceiptThread::Thread(_));
+
         // Since the receipt type and the thread aren't Hash/Ord, flatten then as a
         // string key.
         let request_key = format!("{}|{}", receipt_type, thread.as_str().unwrap_or("<unthreaded>"));
@@ -1541,7 +1901,13 @@ impl Room {
                 self.client.send(request).await?;
                 Ok(())
             })
-            .await
+            .await?;
+
+        if is_main_read_receipt {
+            self.unset_unread_flag_after_read().await;
+        }
+
+        Ok(())
     }
 
     /// Send a request to set multiple receipts at once.
@@ -1565,9 +1931,26 @@ impl Room {
         });
 
         self.client.send(request).await?;
+
+        self.unset_unread_flag_after_read().await;
+
         Ok(())
     }
 
+    /// Unset the unread flag of the room, if it is set, after the user read it.
+    ///
+    /// The receipt has been sent already, so a failure to unset the flag is
+    /// only logged.
+    async fn unset_unread_flag_after_read(&self) {
+        if self.is_marked_unread() {
+            debug!("The room was read, unsetting its unread flag");
+
+            if let Err(error) = self.set_unread_flag(false).await {
+                warn!("Couldn't unset the unread flag of the room: {error}");
+            }
+        }
+    }
+
     /// Enable End-to-end encryption in this room.
     ///
     /// This method will be a noop if encryption is already enabled, otherwise
@@ -1639,14 +2022,85 @@ impl Room {
         Ok(())
     }
 
+    /// Check what enabling end-to-end encryption in this room would look like,
+    /// before actually doing it.
+    ///
+    /// This refuses to proceed in a public room, unless `force` is set. It then
+    /// fetches the device lists of all the joined and invited members, and
+    /// returns an [`EnableEncryptionPreview`] listing the members who have no
+    /// device able to receive encrypted messages. Encryption is only enabled
+    /// once [`EnableEncryptionPreview::enable()`] is called, so UIs can warn
+    /// the user before flipping this irreversible switch.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::room_id};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let room_id = room_id!("!test:localhost");
+    /// let room = client.get_room(room_id).unwrap();
+    /// let preview = room.enable_encryption_checked(false).await?;
+    ///
+    /// if !preview.members_without_devices.is_empty() {
+    ///     println!(
+    ///         "{} members won't be able to read new messages",
+    ///         preview.members_without_devices.len()
+    ///     );
+    /// }
+    ///
+    /// preview.enable().await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn enable_encryption_checked(&self, force: bool) -> Result<EnableEncryptionPreview> {
+        EnableEncryptionPreview::new(self, force).await
+    }
+
+    /// Prepare the encryption of the next message sent in this room.
+    ///
+    /// This creates Olm sessions with the devices of the room members if
+    /// necessary, and shares the current room key with them. It can be called
+    /// when the user opens the composer of an encrypted room, so that sending
+    /// the first message doesn't need to wait for all of this, which can take
+    /// a while in large rooms.
+    ///
+    /// Does nothing if the room isn't encrypted.
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn prepare_encryption(&self) -> Result<()> {
+        if !self.is_encrypted().await? {
+            return Ok(());
+        }
+
+        self.prepare_room_key().await
+    }
+
+    /// Make sure that the current room key of this room is shared with all the
+    /// devices of its members.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) async fn prepare_room_key(&self) -> Result<()> {
+        if !self.are_members_synced() {
+            self.sync_members().await?;
+        }
+
+        // Query keys in case we don't have them for newly synced members.
+        //
+        // Note we do it all the time, because we might have sync'd members before
+        // sending a message (so didn't enter the above branch), but
+        // could have not query their keys ever.
+        self.query_keys_for_untracked_users().await?;
+
+        self.preshare_room_key().await
+    }
+
     /// Share a room key with users in the given room.
     ///
     /// This will create Olm sessions with all the users/device pairs in the
     /// room if necessary and share a room key that can be shared with them.
     ///
     /// Does nothing if no room key needs to be shared.
-    // TODO: expose this publicly so people can pre-share a group session if
-    // e.g. a user starts to type a message for a room.
     #[cfg(feature = "e2e-encryption")]
     #[instrument(skip_all, fields(room_id = ?self.room_id(), store_generation))]
     async fn preshare_room_key(&self) -> Result<()> {
@@ -1700,7 +2154,7 @@ impl Room {
         let requests = self.client.base_client().share_room_key(self.room_id()).await?;
 
         for request in requests {
-            let response = self.client.send_to_device(&request).await?;
+            let response = self.client.send_to_device_request(&request).await?;
             self.client.mark_request_as_sent(&request.txn_id, &response).await?;
         }
 
@@ -1980,6 +2434,11 @@ impl Room {
     ) -> Result<send_message_event::v3::Response> {
         self.ensure_room_joined()?;
 
+        #[cfg(feature = "attachment-metadata")]
+        let (content_type, data) = config.extract_missing_metadata(content_type, data).await;
+        #[cfg(feature = "attachment-metadata")]
+        let content_type = &content_type;
+
         let txn_id = config.txn_id.take();
         let mentions = config.mentions.take();
 
@@ -2257,6 +2716,11 @@ impl Room {
         self.send_state_event(RoomNameEventContent::new(name)).await
     }
 
+    /// Get a helper to read and update the privacy settings of this room.
+    pub fn privacy_settings(&self) -> RoomPrivacySettings<'_> {
+        RoomPrivacySettings::new(self)
+    }
+
     /// Sets a new topic for this room.
     pub async fn set_room_topic(&self, topic: &str) -> Result<send_state_event::v3::Response> {
         self.send_state_event(RoomTopicEventContent::new(topic.into())).await
@@ -2603,15 +3067,109 @@ impl Room {
         Ok(self.power_levels().await?.user_can_trigger_room_notification(user_id))
     }
 
+    /// Get the actions the user with the given user_id is allowed to perform in
+    /// the room, and subscribe to their updates.
+    ///
+    /// The permissions are computed from the power levels of the room and the
+    /// membership of the user, and are recomputed whenever a sync response
+    /// contains a new `m.room.power_levels` event or a new `m.room.member`
+    /// event for this user. The subscriber is only notified when the
+    /// permissions actually change.
+    ///
+    /// A user whose membership isn't known to the store is considered not to
+    /// be in the room, unless it is the current user.
+    ///
+    /// The updates stop when the returned [`EventHandlerDropGuard`] is
+    /// dropped.
+    ///
+    /// The call may fail if there is an error in getting the power levels.
+    pub async fn permissions_for(
+        &self,
+        user_id: &UserId,
+    ) -> Result<(EventHandlerDropGuard, Subscriber<RoomPermissions>)> {
+        let permissions = SharedObservable::new(self.compute_permissions(user_id).await?);
+        let subscriber = permissions.subscribe();
+
+        let handle = self.client.add_room_event_handler(self.room_id(), {
+            let room = self.clone();
+            let user_id = user_id.to_owned();
+
+            move |event: AnySyncStateEvent| {
+                let room = room.clone();
+                let user_id = user_id.clone();
+                let permissions = permissions.clone();
+
+                async move {
+                    let is_relevant = match event.event_type() {
+                        StateEventType::RoomPowerLevels => true,
+                        StateEventType::RoomMember => event.state_key() == user_id.as_str(),
+                        _ => false,
+                    };
+
+                    if !is_relevant {
+                        return;
+                    }
+
+                    match room.compute_permissions(&user_id).await {
+                        Ok(new_permissions) => {
+                            permissions.set_if_not_eq(new_permissions);
+                        }
+                        Err(error) => {
+                            warn!(%user_id, "Couldn't update the permissions of a user: {error}");
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok((self.client.event_handler_drop_guard(handle), subscriber))
+    }
+
+    async fn compute_permissions(&self, user_id: &UserId) -> Result<RoomPermissions> {
+        let power_levels = self.power_levels().await?;
+
+        let is_joined = match self.get_member_no_sync(user_id).await? {
+            Some(member) => *member.membership() == MembershipState::Join,
+            None => user_id == self.own_user_id() && self.state() == RoomState::Joined,
+        };
+
+        Ok(RoomPermissions::new(&power_levels, user_id, is_joined))
+    }
+
     /// Get a list of servers that should know this room.
     ///
     /// Uses the synced members of the room and the suggested [routing
     /// algorithm] from the Matrix spec.
     ///
-    /// Returns at most three servers.
+    /// Returns at most three servers, the best candidate first. They are
+    /// cached until the members, the power levels or the server ACLs of the
+    /// room change.
     ///
     /// [routing algorithm]: https://spec.matrix.org/v1.3/appendices/#routing
     pub async fn route(&self) -> Result<Vec<OwnedServerName>> {
+        let generation = {
+            let routes = self.client.inner.room_routes.lock().unwrap();
+
+            if let Some(route) = routes.get(self.room_id()) {
+                return Ok(route.clone());
+            }
+
+            routes.generation()
+        };
+
+        let route = self.compute_route().await?;
+
+        // The route isn't cached if the state of a room changed while it was computed.
+        self.client.inner.room_routes.lock().unwrap().insert(
+            self.room_id(),
+            route.clone(),
+            generation,
+        );
+
+        Ok(route)
+    }
+
+    async fn compute_route(&self) -> Result<Vec<OwnedServerName>> {
         let acl_ev = self
             .get_state_event_static::<RoomServerAclEventContent>()
             .await?
@@ -2845,7 +3403,7 @@ impl Room {
         let event = invitee.event();
         let inviter_id = event.sender();
         let inviter = self.get_member_no_sync(inviter_id).await?;
-        Ok(Invite { invitee, inviter })
+        Ok(Invite { room: self.clone(), invitee, inviter })
     }
 
     /// Forget this room.
@@ -2976,10 +3534,18 @@ impl Room {
 
     /// Set a flag on the room to indicate that the user has explicitly marked
     /// it as (un)read.
+    ///
+    /// The flag is stored in the `m.marked_unread` room account data, from
+    /// [MSC2867], and is reflected in [`Room::is_marked_unread()`] once it is
+    /// received back through sync. It is unset automatically when a read
+    /// receipt is sent for the main timeline of the room, with
+    /// [`Room::send_single_receipt()`] or [`Room::send_multiple_receipts()`].
+    ///
+    /// [MSC2867]: https://github.com/matrix-org/matrix-spec-proposals/pull/2867
     pub async fn set_unread_flag(&self, unread: bool) -> Result<()> {
         let user_id = self.client.user_id().ok_or(Error::AuthenticationRequired)?;
 
-        let content = UnstableMarkedUnreadEventContent::from(MarkedUnreadEventContent::new(unread));
+        let content = MarkedUnreadEventContent::new(unread);
 
         let request = set_room_account_data::v3::Request::new(
             user_id.to_owned(),
@@ -3422,12 +3988,92 @@ impl WeakRoom {
 /// Details of the (latest) invite.
 #[derive(Debug, Clone)]
 pub struct Invite {
+    room: Room,
     /// Who has been invited.
     pub invitee: RoomMember,
     /// Who sent the invite.
     pub inviter: Option<RoomMember>,
 }
 
+impl Invite {
+    /// The room this invite is for.
+    pub fn room(&self) -> &Room {
+        &self.room
+    }
+
+    /// Decline the invite by leaving the room.
+    ///
+    /// If `report_as_spam` is set and the inviter is known, the invite event
+    /// is reported to the homeserver administrators with the given `reason`,
+    /// and the inviter is added to the ignored users list, so their subsequent
+    /// invites and messages are filtered out.
+    ///
+    /// Reporting is best effort: a failure to report or ignore the inviter is
+    /// logged, but doesn't prevent the invite from being declined.
+    pub async fn decline(&self, report_as_spam: bool, reason: Option<String>) -> Result<()> {
+        if report_as_spam {
+            self.report_as_spam(reason).await;
+        }
+
+        self.room.leave().await
+    }
+
+    /// Report the invite event and ignore the inviter.
+    ///
+    /// This must happen before leaving the room, since the homeserver may not
+    /// let us report an event of a room we're no longer invited to.
+    async fn report_as_spam(&self, reason: Option<String>) {
+        let Some(inviter) = &self.inviter else {
+            warn!(room_id = ?self.room.room_id(), "can't report an unknown inviter as spam");
+            return;
+        };
+
+        match self.invite_event_id().await {
+            Some(event_id) => {
+                let request = report_content::v3::Request::new(
+                    self.room.room_id().to_owned(),
+                    event_id,
+                    None,
+                    reason,
+                );
+
+                if let Err(err) = self.room.client.send(request).await {
+                    warn!(room_id = ?self.room.room_id(), "failed to report the invite: {err}");
+                }
+            }
+            None => {
+                warn!(room_id = ?self.room.room_id(), "can't report an invite without event ID");
+            }
+        }
+
+        if let Err(err) = self.room.client.account().ignore_user(inviter.user_id()).await {
+            warn!(user_id = ?inviter.user_id(), "failed to ignore the inviter: {err}");
+        }
+    }
+
+    /// The ID of the invite event, if known.
+    ///
+    /// Stripped state events don't have an event ID, but homeservers include
+    /// it in the invite event of the invited user anyway, so look for it in
+    /// the raw event.
+    async fn invite_event_id(&self) -> Option<OwnedEventId> {
+        if let Some(event_id) = self.invitee.event().event_id() {
+            return Some(event_id.to_owned());
+        }
+
+        let raw_event = self
+            .room
+            .get_state_event_static_for_key::<RoomMemberEventContent, _>(self.invitee.user_id())
+            .await
+            .ok()??;
+
+        match raw_event {
+            RawSyncOrStrippedState::Sync(raw) => raw.get_field("event_id").ok().flatten(),
+            RawSyncOrStrippedState::Stripped(raw) => raw.get_field("event_id").ok().flatten(),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 enum InvitationError {
     #[error("No membership event found")]
@@ -3879,4 +4525,20 @@ mod tests {
             (event_id.to_owned(), user_id.to_owned())
         )
     }
+
+    #[cfg(feature = "e2e-encryption")]
+    #[async_test]
+    async fn test_share_room_keys_with_unknown_own_device() {
+        use assert_matches2::assert_matches;
+
+        use crate::Error;
+
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room = server.sync_joined_room(&client, room_id!("!a:b.c")).await;
+
+        // Room keys are never shared with a device we don't know about.
+        let result = room.share_room_keys_with_own_device(device_id!("UNKNOWN")).await;
+        assert_matches!(result, Err(Error::NotVerifiedOwnDevice));
+    }
 }
@@ -0,0 +1,322 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Moderation tools, to clean up after a user in a room.
+
+use std::{collections::BTreeSet, future::IntoFuture, time::Duration};
+
+use eyeball::SharedObservable;
+use matrix_sdk_common::boxed_into_future;
+use ruma::{
+    api::client::{
+        error::{ErrorKind, RetryAfter},
+        filter::RoomEventFilter,
+    },
+    assign,
+    events::{AnySyncTimelineEvent, TimelineEventType},
+    serde::Raw,
+    uint, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, TransactionId, UserId,
+};
+use serde::Deserialize;
+use tracing::{debug, trace, warn};
+
+use crate::{room::MessagesOptions, utils::sleep, HttpError, Result, Room};
+
+/// The progress of a [`RedactUserMessages`] operation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RedactionProgress {
+    /// The number of messages of the user found so far.
+    pub found: usize,
+
+    /// The number of messages redacted so far.
+    pub redacted: usize,
+
+    /// The number of messages that couldn't be redacted so far.
+    pub failed: usize,
+}
+
+/// The outcome of a [`RedactUserMessages`] operation.
+#[derive(Debug, Default)]
+pub struct RedactUserMessagesReport {
+    /// Whether the user has been banned.
+    pub banned: bool,
+
+    /// The messages that were redacted.
+    pub redacted: Vec<OwnedEventId>,
+
+    /// The messages that couldn't be redacted, with the reason why.
+    pub failed: Vec<(OwnedEventId, HttpError)>,
+}
+
+/// The fields of an event needed to decide whether to redact it.
+#[derive(Deserialize)]
+struct EventFields {
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    #[serde(rename = "type")]
+    event_type: TimelineEventType,
+    state_key: Option<String>,
+    #[serde(default)]
+    unsigned: EventUnsigned,
+}
+
+#[derive(Default, Deserialize)]
+struct EventUnsigned {
+    redacted_because: Option<serde::de::IgnoredAny>,
+}
+
+impl EventFields {
+    /// Whether this event is a message of the given user, sent since the given
+    /// time, that hasn't been redacted yet.
+    ///
+    /// State events are left out, since redacting them would reset the state
+    /// of the room.
+    fn should_redact(&self, user_id: &UserId, since: MilliSecondsSinceUnixEpoch) -> bool {
+        self.sender == user_id
+            && self.origin_server_ts >= since
+            && self.state_key.is_none()
+            && self.event_type != TimelineEventType::RoomRedaction
+            && self.unsigned.redacted_because.is_none()
+    }
+}
+
+impl Room {
+    /// Redact the messages sent by a user in this room since the given time.
+    ///
+    /// The messages are looked up in the event cache, if it's enabled, and by
+    /// paginating the room backwards until a message older than `since` is
+    /// found. State events, like the membership of the user, are left
+    /// untouched.
+    ///
+    /// Redactions are sent one at a time; when the homeserver rate-limits
+    /// them, they're retried after the delay it asks for. A redaction that
+    /// fails doesn't stop the others, and is reported in
+    /// [`RedactUserMessagesReport::failed`].
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user whose messages should be redacted.
+    ///
+    /// * `since` - Only the messages sent from this time onwards are redacted.
+    ///
+    /// * `reason` - The reason for the redactions, and for the ban if any.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::{room_id, user_id, MilliSecondsSinceUnixEpoch}};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let room = client.get_room(room_id!("!test:localhost")).unwrap();
+    /// let since = MilliSecondsSinceUnixEpoch::now();
+    /// let report = room
+    ///     .redact_user_messages(user_id!("@spammer:localhost"), since, Some("Spam"))
+    ///     .and_ban()
+    ///     .await?;
+    ///
+    /// println!("Redacted {} messages", report.redacted.len());
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn redact_user_messages<'a>(
+        &'a self,
+        user_id: &'a UserId,
+        since: MilliSecondsSinceUnixEpoch,
+        reason: Option<&'a str>,
+    ) -> RedactUserMessages<'a> {
+        RedactUserMessages {
+            room: self,
+            user_id,
+            since,
+            reason,
+            ban: false,
+            progress: Default::default(),
+        }
+    }
+
+    /// Collect the IDs of the messages of a user sent since the given time,
+    /// from the most recent to the oldest.
+    async fn user_messages(
+        &self,
+        user_id: &UserId,
+        since: MilliSecondsSinceUnixEpoch,
+        progress: &SharedObservable<RedactionProgress>,
+    ) -> Result<Vec<OwnedEventId>> {
+        let mut seen = BTreeSet::new();
+        let mut event_ids = Vec::new();
+
+        let mut add_event = |raw: &Raw<AnySyncTimelineEvent>| {
+            let Ok(fields) = raw.deserialize_as::<EventFields>() else {
+                return;
+            };
+
+            if fields.should_redact(user_id, since) && seen.insert(fields.event_id.clone()) {
+                event_ids.push(fields.event_id);
+                progress.update(|progress| progress.found += 1);
+            }
+        };
+
+        if let Ok((event_cache, _drop_handles)) = self.event_cache().await {
+            let (events, _) = event_cache.subscribe().await?;
+
+            for event in events.iter().rev() {
+                add_event(event.raw());
+            }
+        }
+
+        trace!("Paginating the messages of the user");
+
+        let mut from = None;
+
+        loop {
+            let options = assign!(MessagesOptions::backward(), {
+                from,
+                limit: uint!(100),
+                filter: assign!(RoomEventFilter::default(), {
+                    senders: Some(vec![user_id.to_owned()]),
+                }),
+            });
+            let messages = self.messages(options).await?;
+
+            let mut reached_since = false;
+
+            for event in &messages.chunk {
+                if event
+                    .raw()
+                    .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                    .ok()
+                    .flatten()
+                    .is_some_and(|ts| ts < since)
+                {
+                    reached_since = true;
+                }
+
+                add_event(event.raw());
+            }
+
+            match messages.end {
+                Some(end) if !reached_since && !messages.chunk.is_empty() => from = Some(end),
+                _ => break,
+            }
+        }
+
+        Ok(event_ids)
+    }
+
+    /// Redact an event, waiting and trying again when rate-limited.
+    async fn redact_with_backoff(
+        &self,
+        event_id: &OwnedEventId,
+        reason: Option<&str>,
+    ) -> Result<(), HttpError> {
+        const MAX_ATTEMPTS: usize = 5;
+
+        // Reuse the same transaction ID for all the attempts, so the redaction
+        // can't be sent twice.
+        let txn_id = TransactionId::new();
+        let mut attempt = 1;
+
+        loop {
+            let err = match self.redact(event_id, reason, Some(txn_id.clone())).await {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+
+            let Some(ErrorKind::LimitExceeded { retry_after }) = err.client_api_error_kind() else {
+                return Err(err);
+            };
+
+            let delay = match retry_after {
+                Some(RetryAfter::Delay(delay)) => *delay,
+                _ => Duration::from_secs(1),
+            };
+
+            if attempt == MAX_ATTEMPTS {
+                return Err(err);
+            }
+
+            debug!(%event_id, ?delay, "Rate-limited, waiting before redacting again");
+            sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Future returned by [`Room::redact_user_messages`].
+#[allow(missing_debug_implementations)]
+pub struct RedactUserMessages<'a> {
+    room: &'a Room,
+    user_id: &'a UserId,
+    since: MilliSecondsSinceUnixEpoch,
+    reason: Option<&'a str>,
+    ban: bool,
+    progress: SharedObservable<RedactionProgress>,
+}
+
+impl RedactUserMessages<'_> {
+    /// Also ban the user from the room, before redacting their messages so
+    /// they can't send new ones in the meantime.
+    pub fn and_ban(mut self) -> Self {
+        self.ban = true;
+        self
+    }
+
+    /// Replace the default `SharedObservable` used for tracking the progress
+    /// of the redactions.
+    pub fn with_progress_observable(
+        mut self,
+        progress: SharedObservable<RedactionProgress>,
+    ) -> Self {
+        self.progress = progress;
+        self
+    }
+}
+
+impl<'a> IntoFuture for RedactUserMessages<'a> {
+    type Output = Result<RedactUserMessagesReport>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { room, user_id, since, reason, ban, progress } = self;
+
+        Box::pin(async move {
+            let mut report = RedactUserMessagesReport::default();
+
+            if ban {
+                room.ban_user(user_id, reason).await?;
+                report.banned = true;
+            }
+
+            let event_ids = room.user_messages(user_id, since, &progress).await?;
+
+            for event_id in event_ids {
+                match room.redact_with_backoff(&event_id, reason).await {
+                    Ok(()) => {
+                        progress.update(|progress| progress.redacted += 1);
+                        report.redacted.push(event_id);
+                    }
+                    Err(err) => {
+                        warn!(%event_id, "Couldn't redact a message: {err}");
+                        progress.update(|progress| progress.failed += 1);
+                        report.failed.push((event_id, err));
+                    }
+                }
+            }
+
+            Ok(report)
+        })
+    }
+}
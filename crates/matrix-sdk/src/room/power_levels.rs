@@ -1,6 +1,6 @@
 //! Power level configuration types used in [the `room` module][super].
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use ruma::{
     events::{
@@ -8,9 +8,9 @@ use ruma::{
             PossiblyRedactedRoomPowerLevelsEventContent, RoomPowerLevels,
             RoomPowerLevelsEventContent,
         },
-        StateEventType,
+        MessageLikeEventType, StateEventType,
     },
-    OwnedUserId,
+    OwnedUserId, UserId,
 };
 
 use crate::Result;
@@ -195,6 +195,67 @@ pub fn power_level_user_changes(
     changes
 }
 
+/// The actions a user is allowed to perform in a room.
+///
+/// This is computed once from the power levels of the room and the membership
+/// of the user, see [`Room::permissions_for()`](super::Room::permissions_for).
+/// A user who isn't joined to the room isn't allowed to do anything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoomPermissions {
+    /// Whether the user can send `m.room.message` events.
+    pub send_message: bool,
+    /// Whether the user can invite other users.
+    pub invite: bool,
+    /// Whether the user can kick other users.
+    pub kick: bool,
+    /// Whether the user can ban other users.
+    pub ban: bool,
+    /// Whether the user can redact the events of other users.
+    pub redact_other: bool,
+    /// Whether the user can send state events of a type that doesn't have a
+    /// specific power level.
+    state_default: bool,
+    /// Whether the user can send state events, for the types that have a
+    /// specific power level.
+    state_events: BTreeMap<StateEventType, bool>,
+}
+
+impl RoomPermissions {
+    pub(crate) fn new(power_levels: &RoomPowerLevels, user_id: &UserId, is_joined: bool) -> Self {
+        if !is_joined {
+            return Self::default();
+        }
+
+        // The `events` map contains both message-like and state event types, it's
+        // fine to also compute the latter for the former as they won't be looked up.
+        let state_events = power_levels
+            .events
+            .keys()
+            .map(|event_type| {
+                let event_type = StateEventType::from(event_type.to_string());
+                let allowed = power_levels.user_can_send_state(user_id, event_type.clone());
+                (event_type, allowed)
+            })
+            .collect();
+
+        Self {
+            send_message: power_levels
+                .user_can_send_message(user_id, MessageLikeEventType::RoomMessage),
+            invite: power_levels.user_can_invite(user_id),
+            kick: power_levels.user_can_kick(user_id),
+            ban: power_levels.user_can_ban(user_id),
+            redact_other: power_levels.user_can_redact_event_of_other(user_id),
+            state_default: power_levels.for_user(user_id) >= power_levels.state_default,
+            state_events,
+        }
+    }
+
+    /// Whether the user can send state events of the given type.
+    pub fn can_send_state(&self, event_type: &StateEventType) -> bool {
+        self.state_events.get(event_type).copied().unwrap_or(self.state_default)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::BTreeMap;
@@ -423,6 +484,37 @@ mod tests {
         assert!(changes.is_empty());
     }
 
+    #[test]
+    fn test_permissions() {
+        let mut power_levels = default_power_levels();
+        power_levels.events.insert(StateEventType::RoomTopic.into(), int!(0));
+
+        let alice = OwnedUserId::try_from("@alice:example.com").unwrap();
+        let permissions = RoomPermissions::new(&power_levels, &alice, true);
+        assert!(permissions.send_message);
+        assert!(permissions.invite);
+        assert!(permissions.kick);
+        assert!(permissions.ban);
+        assert!(permissions.redact_other);
+        assert!(permissions.can_send_state(&StateEventType::RoomName));
+        assert!(permissions.can_send_state(&StateEventType::RoomTopic));
+
+        let carol = OwnedUserId::try_from("@carol:example.com").unwrap();
+        let permissions = RoomPermissions::new(&power_levels, &carol, true);
+        assert!(permissions.send_message);
+        assert!(!permissions.invite);
+        assert!(!permissions.kick);
+        assert!(!permissions.ban);
+        assert!(!permissions.redact_other);
+        assert!(!permissions.can_send_state(&StateEventType::RoomName));
+        assert!(permissions.can_send_state(&StateEventType::RoomTopic));
+
+        // A user who isn't in the room can't do anything, whatever their power level.
+        let permissions = RoomPermissions::new(&power_levels, &alice, false);
+        assert_eq!(permissions, RoomPermissions::default());
+        assert!(!permissions.can_send_state(&StateEventType::RoomTopic));
+    }
+
     fn default_power_levels() -> RoomPowerLevels {
         default_power_levels_event_content().into()
     }
@@ -0,0 +1,168 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Facilities to read and update the settings that control who can find,
+//! join and read a room.
+
+use ruma::{
+    api::client::{directory::get_room_visibility, room::Visibility},
+    events::room::{
+        guest_access::{GuestAccess, RoomGuestAccessEventContent},
+        history_visibility::HistoryVisibility,
+        join_rules::{AllowRule, JoinRule, Restricted, RoomJoinRulesEventContent},
+    },
+    OwnedRoomAliasId, OwnedRoomId, RoomVersionId,
+};
+use thiserror::Error;
+
+use crate::{Result, Room};
+
+/// An error preventing to update the join rule of a room.
+#[derive(Debug, Error)]
+pub enum JoinRuleError {
+    /// The version of the room doesn't support restricted join rules, which
+    /// were introduced in room version 8.
+    #[error("room version {0} doesn't support restricted join rules")]
+    RestrictedUnsupported(RoomVersionId),
+
+    /// No space was given to grant access to the room, so no one could join it
+    /// without an invite.
+    #[error("a restricted room needs at least one space to grant access to it")]
+    EmptyAllowList,
+}
+
+/// The privacy settings of a room, as returned by
+/// [`RoomPrivacySettings::privacy_snapshot()`].
+#[derive(Clone, Debug)]
+pub struct RoomPrivacySnapshot {
+    /// Who can join the room.
+    pub join_rule: JoinRule,
+
+    /// Who can read the history of the room.
+    pub history_visibility: HistoryVisibility,
+
+    /// Whether guests can join the room.
+    pub guest_access: GuestAccess,
+
+    /// Whether the room is published in the room directory of the homeserver.
+    pub directory_visibility: Visibility,
+
+    /// The canonical alias of the room, if any.
+    pub canonical_alias: Option<OwnedRoomAliasId>,
+}
+
+/// A helper to read and update the privacy settings of a room, created with
+/// [`Room::privacy_settings()`].
+#[derive(Debug)]
+pub struct RoomPrivacySettings<'a> {
+    room: &'a Room,
+}
+
+impl<'a> RoomPrivacySettings<'a> {
+    pub(crate) fn new(room: &'a Room) -> Self {
+        Self { room }
+    }
+
+    /// Get all the privacy settings of the room at once.
+    ///
+    /// All of them are read from the local state of the room, except the
+    /// visibility of the room in the room directory, which is the only one
+    /// that needs a request to the homeserver.
+    pub async fn privacy_snapshot(&self) -> Result<RoomPrivacySnapshot> {
+        let request = get_room_visibility::v3::Request::new(self.room.room_id().to_owned());
+        let directory_visibility = self.room.client.send(request).await?.visibility;
+
+        Ok(RoomPrivacySnapshot {
+            join_rule: self.room.join_rule(),
+            history_visibility: self.room.history_visibility_or_default(),
+            guest_access: self.room.guest_access(),
+            directory_visibility,
+            canonical_alias: self.room.canonical_alias(),
+        })
+    }
+
+    /// Get the spaces whose members can join the room, if its join rule is
+    /// restricted.
+    ///
+    /// Returns `None` if the join rule of the room isn't `restricted` or
+    /// `knock_restricted`. Allow rules of unknown types are ignored.
+    pub fn restricted_join_spaces(&self) -> Option<Vec<OwnedRoomId>> {
+        let (JoinRule::Restricted(restricted) | JoinRule::KnockRestricted(restricted)) =
+            self.room.join_rule()
+        else {
+            return None;
+        };
+
+        Some(
+            restricted
+                .allow
+                .into_iter()
+                .filter_map(|rule| match rule {
+                    AllowRule::RoomMembership(membership) => Some(membership.room_id),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Restrict who can join the room to the members of the given spaces, by
+    /// sending a new `m.room.join_rules` state event.
+    ///
+    /// The allow list of the room is replaced by the given spaces. Other users
+    /// can still join the room if they are invited.
+    ///
+    /// Fails with a [`JoinRuleError`] if no space is given, or if the version
+    /// of the room doesn't support restricted join rules.
+    pub async fn restrict_join_to_spaces(
+        &self,
+        space_ids: impl IntoIterator<Item = OwnedRoomId>,
+    ) -> Result<()> {
+        let allow: Vec<_> = space_ids.into_iter().map(AllowRule::room_membership).collect();
+
+        if allow.is_empty() {
+            return Err(JoinRuleError::EmptyAllowList.into());
+        }
+
+        let room_version = self.room.clone_info().room_version_or_default();
+        if !supports_restricted_join_rule(&room_version) {
+            return Err(JoinRuleError::RestrictedUnsupported(room_version).into());
+        }
+
+        let content = RoomJoinRulesEventContent::new(JoinRule::Restricted(Restricted::new(allow)));
+        self.room.send_state_event(content).await?;
+
+        Ok(())
+    }
+
+    /// Update whether guests can join the room, by sending a new
+    /// `m.room.guest_access` state event.
+    pub async fn update_guest_access(&self, guest_access: GuestAccess) -> Result<()> {
+        self.room.send_state_event(RoomGuestAccessEventContent::new(guest_access)).await?;
+        Ok(())
+    }
+}
+
+/// Whether the given room version supports the `restricted` join rule.
+fn supports_restricted_join_rule(room_version: &RoomVersionId) -> bool {
+    !matches!(
+        room_version,
+        RoomVersionId::V1
+            | RoomVersionId::V2
+            | RoomVersionId::V3
+            | RoomVersionId::V4
+            | RoomVersionId::V5
+            | RoomVersionId::V6
+            | RoomVersionId::V7
+    )
+}
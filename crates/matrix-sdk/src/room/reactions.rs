@@ -0,0 +1,249 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Facilities to toggle reactions on events.
+
+use ruma::{
+    api::client::relations::get_relating_events_with_rel_type_and_event_type,
+    assign,
+    events::{
+        reaction::{ReactionEventContent, SyncReactionEvent},
+        relation::{Annotation, RelationType},
+        AnyMessageLikeEventContent, TimelineEventType,
+    },
+    EventId, OwnedEventId, UserId,
+};
+use thiserror::Error;
+use tracing::{debug, instrument, trace};
+
+use crate::{
+    send_queue::{LocalEchoContent, RoomSendQueueError, RoomSendQueueStorageError, SendHandle},
+    HttpError, Room,
+};
+
+/// The outcome of [`Room::toggle_reaction()`].
+#[derive(Debug)]
+pub enum ToggledReaction {
+    /// A new reaction has been queued for sending.
+    Added(SendHandle),
+
+    /// The previous reaction of the current user has been removed.
+    Removed,
+}
+
+/// An error occurring while toggling a reaction.
+#[derive(Debug, Error)]
+pub enum ToggleReactionError {
+    /// The reaction couldn't be queued, or a queued reaction couldn't be
+    /// aborted.
+    #[error(transparent)]
+    SendQueue(#[from] RoomSendQueueError),
+
+    /// The previous reactions couldn't be fetched, or redacted.
+    #[error(transparent)]
+    Http(#[from] HttpError),
+}
+
+impl From<RoomSendQueueStorageError> for ToggleReactionError {
+    fn from(err: RoomSendQueueStorageError) -> Self {
+        Self::SendQueue(err.into())
+    }
+}
+
+impl Room {
+    /// Toggle the current user's reaction with the given key on an event.
+    ///
+    /// If the user already reacted with this key, the reaction is removed:
+    /// it is aborted if it's still in the [send queue](crate::send_queue), and
+    /// redacted otherwise. If the user didn't, a new reaction is queued for
+    /// sending, and shows up in the send queue's local echoes right away.
+    ///
+    /// A user may only react once with a given key: if several reactions of
+    /// theirs are found, for instance because they were sent from different
+    /// devices, they're all removed.
+    ///
+    /// The sent reactions are looked up in the event cache when it knows about
+    /// the event, and fetched from the homeserver otherwise. Reactions redacted
+    /// by this method are considered removed even before the redaction is
+    /// received through sync.
+    ///
+    /// Toggles are processed one at a time, so that quickly toggling the same
+    /// reaction doesn't result in duplicates. No lock is held while the
+    /// reactions are fetched or redacted.
+    #[instrument(skip(self), fields(room_id = %self.room_id()))]
+    pub async fn toggle_reaction(
+        &self,
+        event_id: &EventId,
+        key: &str,
+    ) -> Result<ToggledReaction, ToggleReactionError> {
+        // Fetch the sent reactions before taking the lock, since it might need network
+        // requests.
+        let own_reactions = self.own_reactions(event_id, key).await?;
+
+        let mut redacted_reactions = self.client.locks().toggle_reaction_lock.lock().await;
+
+        // A reaction that hasn't been sent yet can simply be aborted.
+        let (local_echoes, _) = self.send_queue().subscribe().await?;
+        let mut aborted = false;
+
+        for local_echo in local_echoes {
+            let LocalEchoContent::Event { serialized_event, send_handle, .. } = local_echo.content
+            else {
+                continue;
+            };
+
+            let Ok(AnyMessageLikeEventContent::Reaction(content)) = serialized_event.deserialize()
+            else {
+                continue;
+            };
+
+            if *content.relates_to.event_id == *event_id && content.relates_to.key == key {
+                trace!(txn_id = %local_echo.transaction_id, "Aborting a queued reaction");
+                aborted |= send_handle.abort().await?;
+            }
+        }
+
+        if aborted {
+            return Ok(ToggledReaction::Removed);
+        }
+
+        // Forget about the reactions we redacted ourselves, once the redaction has been
+        // received.
+        redacted_reactions.retain(|reaction_id, target_id| {
+            &**target_id != event_id || own_reactions.contains(reaction_id)
+        });
+
+        let own_reactions: Vec<_> = own_reactions
+            .into_iter()
+            .filter(|reaction_id| !redacted_reactions.contains_key(reaction_id))
+            .collect();
+
+        if own_reactions.is_empty() {
+            trace!("Queuing a new reaction");
+            let annotation = Annotation::new(event_id.to_owned(), key.to_owned());
+            let send_handle =
+                self.send_queue().send(ReactionEventContent::from(annotation).into()).await?;
+
+            return Ok(ToggledReaction::Added(send_handle));
+        }
+
+        // Consider the reactions removed right away, so the redactions can be sent
+        // without holding the lock.
+        for reaction_id in &own_reactions {
+            redacted_reactions.insert(reaction_id.clone(), event_id.to_owned());
+        }
+        drop(redacted_reactions);
+
+        for (i, reaction_id) in own_reactions.iter().enumerate() {
+            debug!(%reaction_id, "Redacting a previous reaction");
+
+            if let Err(err) = self.redact(reaction_id, None, None).await {
+                // The reactions that weren't redacted are still there.
+                let mut redacted_reactions = self.client.locks().toggle_reaction_lock.lock().await;
+                for reaction_id in &own_reactions[i..] {
+                    redacted_reactions.remove(reaction_id);
+                }
+
+                return Err(err.into());
+            }
+        }
+
+        Ok(ToggledReaction::Removed)
+    }
+
+    /// Get the IDs of the sent reactions of the current user with the given
+    /// key on an event.
+    async fn own_reactions(
+        &self,
+        event_id: &EventId,
+        key: &str,
+    ) -> Result<Vec<OwnedEventId>, HttpError> {
+        let own_user_id = self.own_user_id();
+
+        if let Ok((event_cache, _drop_handles)) = self.event_cache().await {
+            if let Some((_, reactions)) = event_cache
+                .event_with_relations(event_id, Some(vec![RelationType::Annotation]))
+                .await
+            {
+                let mut reaction_ids = Vec::new();
+
+                for reaction in reactions {
+                    let Some(reaction_id) =
+                        matching_reaction_id(reaction.raw().deserialize_as(), own_user_id, key)
+                    else {
+                        continue;
+                    };
+
+                    // Redactions are stored as replacements of the event they redact.
+                    let is_redacted = event_cache
+                        .event_with_relations(&reaction_id, Some(vec![RelationType::Replacement]))
+                        .await
+                        .is_some_and(|(_, redactions)| !redactions.is_empty());
+
+                    if !is_redacted {
+                        reaction_ids.push(reaction_id);
+                    }
+                }
+
+                return Ok(reaction_ids);
+            }
+        }
+
+        trace!("The event isn't in the event cache, fetching its reactions");
+
+        let mut reaction_ids = Vec::new();
+        let mut from = None;
+
+        loop {
+            let request = assign!(
+                get_relating_events_with_rel_type_and_event_type::v1::Request::new(
+                    self.room_id().to_owned(),
+                    event_id.to_owned(),
+                    RelationType::Annotation,
+                    TimelineEventType::Reaction,
+                ),
+                { from }
+            );
+            let response = self.client.send(request).await?;
+
+            reaction_ids.extend(response.chunk.iter().filter_map(|reaction| {
+                matching_reaction_id(reaction.deserialize_as(), own_user_id, key)
+            }));
+
+            from = response.next_batch;
+            if from.is_none() {
+                break;
+            }
+        }
+
+        Ok(reaction_ids)
+    }
+}
+
+/// Get the ID of a reaction, if it's a non-redacted reaction of the given user
+/// with the given key.
+fn matching_reaction_id(
+    reaction: Result<SyncReactionEvent, serde_json::Error>,
+    user_id: &UserId,
+    key: &str,
+) -> Option<OwnedEventId> {
+    match reaction {
+        Ok(SyncReactionEvent::Original(reaction))
+            if reaction.sender == user_id && reaction.content.relates_to.key == key =>
+        {
+            Some(reaction.event_id)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Facilities to find out who has read an event, from the read receipts.
+
+use std::collections::{BTreeMap, HashMap};
+
+use async_stream::stream;
+use eyeball_im::Vector;
+use futures_core::Stream;
+use matrix_sdk_base::deserialized_responses::SyncTimelineEvent;
+use ruma::{
+    events::receipt::{ReceiptThread, ReceiptType},
+    EventId, OwnedEventId, OwnedUserId,
+};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{instrument, warn};
+
+use crate::{event_cache::RoomEventCacheUpdate, Result, Room};
+
+/// The receipt types that mark an event as read.
+const READ_RECEIPT_TYPES: [ReceiptType; 2] = [ReceiptType::Read, ReceiptType::ReadPrivate];
+
+/// The threads of the receipts that apply to the main timeline.
+const READ_RECEIPT_THREADS: [ReceiptThread; 2] = [ReceiptThread::Unthreaded, ReceiptThread::Main];
+
+impl Room {
+    /// Get the users who have read the given event, and a stream of the
+    /// updates of this list.
+    ///
+    /// A user has read an event if their read receipt is on this event, or on
+    /// an event that comes after it in the [event cache](crate::event_cache).
+    /// If the event isn't in the event cache, only the receipts on the event
+    /// itself are considered. Only the receipts of the joined members of the
+    /// room are taken into account, so the members are synced if needed.
+    ///
+    /// The list is sorted by user ID. The stream yields a new list when a read
+    /// receipt is received, or when the events of the event cache change, and
+    /// requires the event cache to be subscribed.
+    #[instrument(skip(self), fields(room_id = ?self.room_id()))]
+    pub async fn event_read_by(
+        &self,
+        event_id: &EventId,
+    ) -> Result<(Vec<OwnedUserId>, impl Stream<Item = Vec<OwnedUserId>>)> {
+        self.sync_members().await?;
+
+        let (room_event_cache, drop_handles) = self.event_cache().await?;
+        let (events, mut updates) = room_event_cache.subscribe().await?;
+
+        let mut events: Vector<SyncTimelineEvent> = events.into();
+        let mut receipts = self.load_members_read_receipts().await?;
+        let initial = users_with_read_receipt_after(event_id, &events, &receipts);
+
+        let room = self.clone();
+        let event_id = event_id.to_owned();
+        let mut read_by = initial.clone();
+
+        let stream = stream! {
+            // Keep the event cache alive as long as the stream is.
+            let _drop_handles = drop_handles;
+
+            loop {
+                // Whether the read receipts need to be reloaded.
+                let mut reload_receipts = false;
+
+                match updates.recv().await {
+                    Ok(RoomEventCacheUpdate::AddEphemeralEvents { .. }) => reload_receipts = true,
+                    Ok(RoomEventCacheUpdate::UpdateTimelineEvents { diffs, .. }) => {
+                        for diff in diffs {
+                            diff.apply(&mut events);
+                        }
+                    }
+                    Ok(RoomEventCacheUpdate::Clear) => events.clear(),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => {
+                        // Some updates were missed, reload the events and the receipts.
+                        match room_event_cache.subscribe().await {
+                            Ok((new_events, new_updates)) => {
+                                events = new_events.into();
+                                updates = new_updates;
+                                reload_receipts = true;
+                            }
+                            Err(error) => {
+                                warn!("Couldn't reload the events of the room: {error}");
+                                continue;
+                            }
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+
+                if reload_receipts {
+                    match room.load_members_read_receipts().await {
+                        Ok(new_receipts) => receipts = new_receipts,
+                        Err(error) => {
+                            warn!("Couldn't load the read receipts of the room: {error}");
+                        }
+                    }
+                }
+
+                let new_read_by = users_with_read_receipt_after(&event_id, &events, &receipts);
+                if new_read_by != read_by {
+                    read_by = new_read_by;
+                    yield read_by.clone();
+                }
+            }
+        };
+
+        Ok((initial, stream))
+    }
+
+    /// Load the latest read receipts of the joined members of the room, in the
+    /// main timeline.
+    ///
+    /// Returns the IDs of the events of the receipts, by user ID.
+    async fn load_members_read_receipts(&self) -> Result<BTreeMap<OwnedUserId, Vec<OwnedEventId>>> {
+        let own_user_id = self.own_user_id();
+        let mut receipts = BTreeMap::new();
+
+        for user_id in self.joined_user_ids().await? {
+            let mut event_ids = Vec::new();
+
+            for receipt_type in &READ_RECEIPT_TYPES {
+                // Only our own private read receipts are known.
+                if *receipt_type == ReceiptType::ReadPrivate && &*user_id != own_user_id {
+                    continue;
+                }
+
+                for thread in &READ_RECEIPT_THREADS {
+                    if let Some((event_id, _)) = self
+                        .load_user_receipt(receipt_type.clone(), thread.clone(), &user_id)
+                        .await?
+                    {
+                        event_ids.push(event_id);
+                    }
+                }
+            }
+
+            if !event_ids.is_empty() {
+                receipts.insert(user_id, event_ids);
+            }
+        }
+
+        Ok(receipts)
+    }
+}
+
+/// Get the users with a read receipt on the given event, or on any event after
+/// it in the given ordered events.
+///
+/// If the event isn't in the events, only the receipts on the event itself are
+/// considered.
+fn users_with_read_receipt_after(
+    event_id: &EventId,
+    events: &Vector<SyncTimelineEvent>,
+    receipts: &BTreeMap<OwnedUserId, Vec<OwnedEventId>>,
+) -> Vec<OwnedUserId> {
+    let positions: HashMap<OwnedEventId, usize> = events
+        .iter()
+        .enumerate()
+        .filter_map(|(position, event)| Some((event.event_id()?, position)))
+        .collect();
+    let event_position = positions.get(event_id);
+
+    let has_read = |receipt_event_id: &OwnedEventId| {
+        &**receipt_event_id == event_id
+            || event_position
+                .zip(positions.get(receipt_event_id))
+                .is_some_and(|(event_position, receipt_position)| receipt_position > event_position)
+    };
+
+    receipts
+        .iter()
+        .filter(|(_, event_ids)| event_ids.iter().any(&has_read))
+        .map(|(user_id, _)| user_id.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use eyeball_im::Vector;
+    use matrix_sdk_base::deserialized_responses::SyncTimelineEvent;
+    use matrix_sdk_test::event_factory::EventFactory;
+    use ruma::{event_id, owned_event_id, owned_user_id, user_id};
+
+    use super::users_with_read_receipt_after;
+
+    #[test]
+    fn test_users_with_read_receipt_after() {
+        let f = EventFactory::new().sender(user_id!("@alice:localhost"));
+        let events: Vector<SyncTimelineEvent> = [event_id!("$1"), event_id!("$2"), event_id!("$3")]
+            .into_iter()
+            .map(|event_id| f.text_msg("hello").event_id(event_id).into_sync())
+            .collect();
+
+        let receipts = BTreeMap::from([
+            (owned_user_id!("@bob:localhost"), vec![owned_event_id!("$1")]),
+            (owned_user_id!("@carl:localhost"), vec![owned_event_id!("$2")]),
+            // A receipt on an unknown event doesn't count, unless it is the event itself.
+            (owned_user_id!("@dan:localhost"), vec![owned_event_id!("$4")]),
+            // Any of the receipts of the user counts.
+            (owned_user_id!("@eve:localhost"), vec![owned_event_id!("$1"), owned_event_id!("$3")]),
+        ]);
+
+        assert_eq!(
+            users_with_read_receipt_after(event_id!("$2"), &events, &receipts),
+            [owned_user_id!("@carl:localhost"), owned_user_id!("@eve:localhost")]
+        );
+
+        // An unknown event only includes the receipts on itself.
+        assert_eq!(
+            users_with_read_receipt_after(event_id!("$4"), &events, &receipts),
+            [owned_user_id!("@dan:localhost")]
+        );
+    }
+}
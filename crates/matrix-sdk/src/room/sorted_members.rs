@@ -0,0 +1,158 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A list of room members sorted for display, kept up to date as member and
+//! power level updates are received.
+//!
+//! See [`Room::sorted_members()`].
+//!
+//! [`Room::sorted_members()`]: crate::Room::sorted_members
+
+use std::{cmp::Ordering, fmt, sync::Arc};
+
+use eyeball_im::{Vector, VectorDiff};
+use ruma::UserId;
+
+use super::{RoomMember, RoomMemberRole};
+
+/// A function comparing two member names.
+pub type NameComparator = Arc<dyn Fn(&str, &str) -> Ordering + Send + Sync>;
+
+/// How to sort the members returned by [`Room::sorted_members()`].
+///
+/// Members are always grouped by [`RoomMemberRole`], administrators first,
+/// then sorted by name inside each group. Members with the same name are
+/// sorted by user ID, so that the order is stable.
+///
+/// [`Room::sorted_members()`]: crate::Room::sorted_members
+#[derive(Clone, Default)]
+pub enum MemberSortStrategy {
+    /// Sort names case-insensitively, by Unicode code point.
+    #[default]
+    PowerLevelThenName,
+
+    /// Sort names with the given function.
+    ///
+    /// This can be used to plug in a locale-aware collator.
+    PowerLevelThenNameWith(NameComparator),
+}
+
+impl fmt::Debug for MemberSortStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PowerLevelThenName => f.write_str("PowerLevelThenName"),
+            Self::PowerLevelThenNameWith(_) => f.write_str("PowerLevelThenNameWith(..)"),
+        }
+    }
+}
+
+impl MemberSortStrategy {
+    fn compare_names(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            Self::PowerLevelThenName => a.to_lowercase().cmp(&b.to_lowercase()),
+            Self::PowerLevelThenNameWith(compare) => compare(a, b),
+        }
+    }
+
+    /// Compare two members according to this strategy.
+    pub fn compare(&self, a: &RoomMember, b: &RoomMember) -> Ordering {
+        role_rank(a)
+            .cmp(&role_rank(b))
+            .then_with(|| self.compare_names(a.name(), b.name()))
+            .then_with(|| a.user_id().cmp(b.user_id()))
+    }
+}
+
+/// The position of the role of the member in the list, administrators first.
+fn role_rank(member: &RoomMember) -> u8 {
+    match member.suggested_role_for_power_level() {
+        RoomMemberRole::Administrator => 0,
+        RoomMemberRole::Moderator => 1,
+        RoomMemberRole::User => 2,
+    }
+}
+
+/// A sorted list of members, updated incrementally.
+#[derive(Debug)]
+pub(crate) struct SortedMembers {
+    strategy: MemberSortStrategy,
+    members: Vector<RoomMember>,
+}
+
+impl SortedMembers {
+    pub(crate) fn new(strategy: MemberSortStrategy, members: Vec<RoomMember>) -> Self {
+        let mut this = Self { strategy, members: Vector::new() };
+        this.reset(members);
+        this
+    }
+
+    /// The current list of members.
+    pub(crate) fn members(&self) -> &Vector<RoomMember> {
+        &self.members
+    }
+
+    /// Replace the whole list of members.
+    pub(crate) fn reset(&mut self, mut members: Vec<RoomMember>) -> VectorDiff<RoomMember> {
+        members.sort_by(|a, b| self.strategy.compare(a, b));
+        self.members = members.into();
+        VectorDiff::Reset { values: self.members.clone() }
+    }
+
+    /// Update the member with the given user ID.
+    ///
+    /// `member` is the new value of the member, or `None` if it must not be
+    /// part of the list anymore.
+    pub(crate) fn update(
+        &mut self,
+        user_id: &UserId,
+        member: Option<RoomMember>,
+    ) -> Vec<VectorDiff<RoomMember>> {
+        let old_index = self.members.iter().position(|m| m.user_id() == user_id);
+
+        let Some(member) = member else {
+            return match old_index {
+                Some(index) => {
+                    self.members.remove(index);
+                    vec![VectorDiff::Remove { index }]
+                }
+                None => Vec::new(),
+            };
+        };
+
+        let mut diffs = Vec::new();
+
+        if let Some(index) = old_index {
+            // The sort keys didn't change, the member stays in place.
+            if self.strategy.compare(&self.members[index], &member) == Ordering::Equal {
+                self.members.set(index, member.clone());
+                return vec![VectorDiff::Set { index, value: member }];
+            }
+
+            self.members.remove(index);
+            diffs.push(VectorDiff::Remove { index });
+        }
+
+        let index = self.insertion_index(&member);
+        self.members.insert(index, member.clone());
+        diffs.push(VectorDiff::Insert { index, value: member });
+
+        diffs
+    }
+
+    fn insertion_index(&self, member: &RoomMember) -> usize {
+        self.members
+            .binary_search_by(|m| self.strategy.compare(m, member))
+            .unwrap_or_else(|index| index)
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Facilities to look up the previous values of a state event, for instance
+//! to show who changed the topic of a room and when.
+
+use matrix_sdk_base::deserialized_responses::RawAnySyncOrStrippedState;
+use ruma::{
+    api::client::filter::RoomEventFilter,
+    assign,
+    events::{AnySyncStateEvent, StateEventType},
+    serde::Raw,
+    uint, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId,
+};
+use serde::Deserialize;
+use tracing::{debug, instrument, trace};
+
+use crate::{room::MessagesOptions, Result, Room};
+
+/// A change of a state event, as returned by [`Room::state_event_history()`].
+#[derive(Clone, Debug)]
+pub struct StateEventChange {
+    /// The ID of the state event.
+    pub event_id: OwnedEventId,
+
+    /// The user who changed the state.
+    pub sender: OwnedUserId,
+
+    /// When the state was changed.
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+
+    /// The state event, with the new value in its content.
+    pub event: Raw<AnySyncStateEvent>,
+}
+
+/// The fields of a state event needed to build a [`StateEventChange`].
+#[derive(Deserialize)]
+struct StateEventFields {
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    state_key: Option<String>,
+}
+
+/// The maximum number of `/messages` requests made to fetch the history of a
+/// state event.
+const MAX_HISTORY_PAGES: usize = 10;
+
+impl Room {
+    /// Get the history of a state event of this room, from the most recent
+    /// change to the oldest one.
+    ///
+    /// The history is fetched by back-paginating the room with a filter on the
+    /// event type, so only the changes visible to the current user are
+    /// returned. At most 10 pages of 100 events are fetched, so the oldest
+    /// changes of a very long history may be left out. The history is cached
+    /// for as long as the current state event doesn't change.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the state event, e.g. `m.room.topic`.
+    ///
+    /// * `state_key` - The state key of the state event, which is empty for
+    ///   most room settings.
+    #[instrument(skip(self), fields(room_id = %self.room_id()))]
+    pub async fn state_event_history(
+        &self,
+        event_type: StateEventType,
+        state_key: &str,
+    ) -> Result<Vec<StateEventChange>> {
+        let current_event_id = match self.get_state_event(event_type.clone(), state_key).await? {
+            Some(RawAnySyncOrStrippedState::Sync(raw)) => {
+                raw.get_field::<OwnedEventId>("event_id").ok().flatten()
+            }
+            Some(RawAnySyncOrStrippedState::Stripped(_)) | None => None,
+        };
+
+        let cache_key = (self.room_id().to_owned(), event_type.to_string(), state_key.to_owned());
+
+        if let Some(changes) = self.cached_state_event_history(&cache_key, &current_event_id) {
+            trace!("Using the cached history");
+            return Ok(changes);
+        }
+
+        // Only fetch a given history once at a time: concurrent calls wait for the first
+        // one to fill the cache.
+        self.client
+            .locks()
+            .state_event_history_deduplicated_handler
+            .run(cache_key.clone(), async {
+                let changes = self.fetch_state_event_history(&event_type, state_key).await?;
+                self.client
+                    .inner
+                    .state_event_histories
+                    .lock()
+                    .unwrap()
+                    .insert(cache_key.clone(), changes);
+
+                Ok(())
+            })
+            .await?;
+
+        Ok(self
+            .client
+            .inner
+            .state_event_histories
+            .lock()
+            .unwrap()
+            .get(&cache_key)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    /// Get the cached history of a state event, if it's up to date with the
+    /// current state event.
+    fn cached_state_event_history(
+        &self,
+        cache_key: &(OwnedRoomId, String, String),
+        current_event_id: &Option<OwnedEventId>,
+    ) -> Option<Vec<StateEventChange>> {
+        let histories = self.client.inner.state_event_histories.lock().unwrap();
+        let changes = histories.get(cache_key)?;
+
+        let is_up_to_date = current_event_id.is_some()
+            && changes.first().map(|change| &change.event_id) == current_event_id.as_ref();
+
+        is_up_to_date.then(|| changes.clone())
+    }
+
+    /// Back-paginate the room to fetch the history of a state event.
+    async fn fetch_state_event_history(
+        &self,
+        event_type: &StateEventType,
+        state_key: &str,
+    ) -> Result<Vec<StateEventChange>> {
+        let mut changes = Vec::new();
+        let mut from = None;
+
+        for _ in 0..MAX_HISTORY_PAGES {
+            let options = assign!(MessagesOptions::backward(), {
+                from,
+                limit: uint!(100),
+                filter: assign!(RoomEventFilter::default(), {
+                    types: Some(vec![event_type.to_string()]),
+                }),
+            });
+            let messages = self.messages(options).await?;
+
+            for event in &messages.chunk {
+                let Ok(fields) = event.raw().deserialize_as::<StateEventFields>() else {
+                    continue;
+                };
+
+                if fields.state_key.as_deref() != Some(state_key) {
+                    continue;
+                }
+
+                changes.push(StateEventChange {
+                    event_id: fields.event_id,
+                    sender: fields.sender,
+                    origin_server_ts: fields.origin_server_ts,
+                    event: event.raw().clone().cast(),
+                });
+            }
+
+            match messages.end {
+                Some(end) if !messages.chunk.is_empty() => from = Some(end),
+                _ => {
+                    trace!(num_changes = changes.len(), "Fetched the history");
+                    return Ok(changes);
+                }
+            }
+        }
+
+        debug!(
+            num_changes = changes.len(),
+            "Fetched the maximum number of pages, the history may be incomplete"
+        );
+
+        Ok(changes)
+    }
+}
@@ -0,0 +1,252 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rich room topics, as defined in [MSC3765].
+//!
+//! A rich topic is stored in the `m.topic` field of the `m.room.topic` state
+//! event, next to the plain `topic` string which is still set for clients and
+//! servers that don't support it.
+//!
+//! [MSC3765]: https://github.com/matrix-org/matrix-spec-proposals/pull/3765
+
+use matrix_sdk_base::deserialized_responses::RawAnySyncOrStrippedState;
+use ruma::{api::client::state::send_state_event, events::StateEventType};
+use serde::{Deserialize, Serialize};
+
+use crate::{Result, Room};
+
+const PLAIN_MIMETYPE: &str = "text/plain";
+const HTML_MIMETYPE: &str = "text/html";
+
+/// The topic of a room, with its optional HTML representation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RichTopic {
+    /// The plain text representation of the topic.
+    pub plain: String,
+
+    /// The HTML representation of the topic, if any.
+    pub html: Option<String>,
+}
+
+/// A builder for a [`RichTopic`], to be used with [`Room::set_topic_rich()`].
+#[derive(Clone, Debug)]
+pub struct RichTopicBuilder {
+    plain: String,
+    html: Option<String>,
+}
+
+impl RichTopicBuilder {
+    /// Create a new builder for a topic with the given plain text
+    /// representation.
+    ///
+    /// The plain text is also used as the `topic` of the event, for clients
+    /// that don't support rich topics.
+    pub fn new(plain: impl Into<String>) -> Self {
+        Self { plain: plain.into(), html: None }
+    }
+
+    /// Set the HTML representation of the topic.
+    pub fn html(mut self, html: impl Into<String>) -> Self {
+        self.html = Some(html.into());
+        self
+    }
+
+    /// Build the [`RichTopic`].
+    pub fn build(self) -> RichTopic {
+        RichTopic { plain: self.plain, html: self.html }
+    }
+}
+
+/// The content of an `m.room.topic` event, with the unstable `m.topic` field.
+#[derive(Debug, Deserialize, Serialize)]
+struct RichTopicEventContent {
+    topic: String,
+
+    #[serde(rename = "m.topic", default, skip_serializing_if = "Option::is_none")]
+    topic_block: Option<TopicContentBlock>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TopicContentBlock {
+    #[serde(rename = "m.text", default)]
+    text: Vec<TextRepresentation>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TextRepresentation {
+    #[serde(default = "default_mimetype", skip_serializing_if = "is_plain_mimetype")]
+    mimetype: String,
+    body: String,
+}
+
+fn default_mimetype() -> String {
+    PLAIN_MIMETYPE.to_owned()
+}
+
+fn is_plain_mimetype(mimetype: &str) -> bool {
+    mimetype == PLAIN_MIMETYPE
+}
+
+impl From<RichTopicEventContent> for RichTopic {
+    fn from(content: RichTopicEventContent) -> Self {
+        let text = content.topic_block.map(|block| block.text).unwrap_or_default();
+        let find = |mimetype: &str| {
+            text.iter().find(|repr| repr.mimetype == mimetype).map(|repr| repr.body.clone())
+        };
+
+        // Fall back to the string topic when the block has no plain text
+        // representation, like events sent by clients without rich topics.
+        let plain = find(PLAIN_MIMETYPE).unwrap_or(content.topic);
+        let html = find(HTML_MIMETYPE);
+
+        Self { plain, html }
+    }
+}
+
+impl From<RichTopic> for RichTopicEventContent {
+    fn from(topic: RichTopic) -> Self {
+        // Representations are ordered by preference, so the HTML comes first.
+        let text = topic
+            .html
+            .map(|body| TextRepresentation { mimetype: HTML_MIMETYPE.to_owned(), body })
+            .into_iter()
+            .chain([TextRepresentation { mimetype: default_mimetype(), body: topic.plain.clone() }])
+            .collect();
+
+        Self { topic: topic.plain, topic_block: Some(TopicContentBlock { text }) }
+    }
+}
+
+impl Room {
+    /// Get the topic of this room, with its HTML representation if it has
+    /// one.
+    ///
+    /// This reads the `m.room.topic` event from the store, and falls back to
+    /// the plain `topic` string when the event doesn't have a rich topic.
+    ///
+    /// Returns `None` if the room doesn't have a topic.
+    pub async fn topic_rich(&self) -> Result<Option<RichTopic>> {
+        let Some(raw) = self.get_state_event(StateEventType::RoomTopic, "").await? else {
+            return Ok(None);
+        };
+
+        let content: Option<RichTopicEventContent> = match raw {
+            RawAnySyncOrStrippedState::Sync(raw) => raw.get_field("content"),
+            RawAnySyncOrStrippedState::Stripped(raw) => raw.get_field("content"),
+        }
+        // A redacted topic event has no `topic` field, it's the same as not
+        // having a topic.
+        .ok()
+        .flatten();
+
+        Ok(content.map(Into::into))
+    }
+
+    /// Set the topic of this room, with its optional HTML representation.
+    ///
+    /// The plain text representation is also sent as the `topic` field of the
+    /// event, so clients and servers that don't support rich topics still see
+    /// it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{room::topic::RichTopicBuilder, Room};
+    /// # async {
+    /// # let room: Room = unimplemented!();
+    /// let topic = RichTopicBuilder::new("All about *Rust*").html("All about <em>Rust</em>");
+    /// room.set_topic_rich(topic).await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn set_topic_rich(
+        &self,
+        topic: RichTopicBuilder,
+    ) -> Result<send_state_event::v3::Response> {
+        let content = RichTopicEventContent::from(topic.build());
+        self.send_state_event_raw(
+            &StateEventType::RoomTopic.to_string(),
+            "",
+            serde_json::to_value(content)?,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{from_value, json, to_value};
+
+    use super::{RichTopic, RichTopicBuilder, RichTopicEventContent};
+
+    #[test]
+    fn test_rich_topic_serialization() {
+        let content = RichTopicEventContent::from(
+            RichTopicBuilder::new("All about *Rust*").html("All about <em>Rust</em>").build(),
+        );
+        assert_eq!(
+            to_value(content).unwrap(),
+            json!({
+                "topic": "All about *Rust*",
+                "m.topic": {
+                    "m.text": [
+                        { "mimetype": "text/html", "body": "All about <em>Rust</em>" },
+                        { "body": "All about *Rust*" },
+                    ],
+                },
+            })
+        );
+
+        let content = RichTopicEventContent::from(RichTopicBuilder::new("Rust").build());
+        assert_eq!(
+            to_value(content).unwrap(),
+            json!({
+                "topic": "Rust",
+                "m.topic": { "m.text": [{ "body": "Rust" }] },
+            })
+        );
+    }
+
+    #[test]
+    fn test_rich_topic_deserialization() {
+        let content: RichTopicEventContent = from_value(json!({
+            "topic": "fallback",
+            "m.topic": {
+                "m.text": [
+                    { "mimetype": "text/html", "body": "<b>Rust</b>" },
+                    { "mimetype": "text/plain", "body": "Rust" },
+                ],
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            RichTopic::from(content),
+            RichTopic { plain: "Rust".to_owned(), html: Some("<b>Rust</b>".to_owned()) }
+        );
+
+        // Without a rich topic, the string topic is used.
+        let content: RichTopicEventContent = from_value(json!({ "topic": "Rust" })).unwrap();
+        assert_eq!(RichTopic::from(content), RichTopic { plain: "Rust".to_owned(), html: None });
+
+        // Without a plain text representation, the string topic is used too.
+        let content: RichTopicEventContent = from_value(json!({
+            "topic": "Rust",
+            "m.topic": { "m.text": [{ "mimetype": "text/html", "body": "<b>Rust</b>" }] },
+        }))
+        .unwrap();
+        assert_eq!(
+            RichTopic::from(content),
+            RichTopic { plain: "Rust".to_owned(), html: Some("<b>Rust</b>".to_owned()) }
+        );
+    }
+}
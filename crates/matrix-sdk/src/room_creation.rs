@@ -0,0 +1,521 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Creation of rooms with their whole initial state in one call.
+//!
+//! See [`RoomCreationBuilder`].
+
+use ruma::{
+    api::client::room::{
+        create_room::v3::{CreationContent, Request as CreateRoomRequest, RoomPreset},
+        Visibility,
+    },
+    assign,
+    events::{
+        room::{
+            encryption::RoomEncryptionEventContent,
+            history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+            join_rules::{AllowRule, JoinRule, Restricted, RoomJoinRulesEventContent},
+        },
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
+        InitialStateEvent, StateEventType,
+    },
+    room::RoomType,
+    serde::Raw,
+    OwnedRoomId, OwnedServerName, OwnedUserId, RoomVersionId,
+};
+use thiserror::Error;
+use tracing::{instrument, warn};
+
+use crate::{Client, Error, Result, Room};
+
+/// An inconsistency between the options of a [`RoomCreationBuilder`].
+#[derive(Debug, Error)]
+pub enum RoomCreationError {
+    /// The room would be encrypted, but anyone could join it.
+    #[error("a public room can't be encrypted")]
+    EncryptedPublicRoom,
+
+    /// The room would be encrypted, but its history would be readable by
+    /// anyone, who wouldn't be able to decrypt it.
+    #[error("a world-readable room can't be encrypted")]
+    EncryptedWorldReadableRoom,
+
+    /// The room would be published in the room directory, but no one could
+    /// join it without an invite.
+    #[error("an invite-only room can't be published in the room directory")]
+    PublishedInviteOnlyRoom,
+
+    /// The join rule of the room is restricted, but no room grants access to
+    /// it, and there is no parent space to grant it either.
+    #[error("a restricted room needs at least one room to grant access to it")]
+    RestrictedWithoutAllowRules,
+
+    /// The room would be a direct message, but no one would be invited to it.
+    #[error("a direct message needs at least one invited user")]
+    DirectWithoutInvite,
+
+    /// The room would be a direct message, but also a space.
+    #[error("a space can't be a direct message")]
+    DirectSpace,
+
+    /// The parent space isn't a joined room, so the room can't be added to
+    /// it.
+    #[error("the parent space {0} isn't a joined room")]
+    UnknownParentSpace(OwnedRoomId),
+
+    /// The current user isn't allowed to add children to the parent space.
+    #[error("not allowed to add a child to the parent space {0}")]
+    ParentSpaceForbidden(OwnedRoomId),
+
+    /// No server was given to reach the room through its parent space, when
+    /// building the request with [`RoomCreationBuilder::build()`].
+    #[error("no server was given to reach the room from its parent space")]
+    MissingVia,
+}
+
+/// A parent space of a room to create.
+#[derive(Clone, Debug)]
+struct ParentSpace {
+    room_id: OwnedRoomId,
+    via: Vec<OwnedServerName>,
+}
+
+/// A builder for a room with its encryption, history visibility, join rule and
+/// parent space set in its initial state.
+///
+/// The options are checked for consistency before the room is created, with
+/// [`Client::create_room_with()`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use matrix_sdk::{
+///     room_creation::RoomCreationBuilder,
+///     ruma::{events::room::join_rules::JoinRule, room_id},
+///     Client,
+/// };
+/// # async {
+/// # let client: Client = todo!();
+///
+/// // A room that the members of the space can join, listed in the space.
+/// let builder = RoomCreationBuilder::new()
+///     .name("Random")
+///     .join_rule(JoinRule::Restricted(Default::default()))
+///     .parent_space(room_id!("!space:example.org").to_owned(), Vec::new());
+///
+/// let room = client.create_room_with(builder).await?;
+/// # anyhow::Ok(()) };
+/// ```
+#[derive(Clone, Debug)]
+pub struct RoomCreationBuilder {
+    name: Option<String>,
+    topic: Option<String>,
+    alias_name: Option<String>,
+    room_version: Option<RoomVersionId>,
+    invite: Vec<OwnedUserId>,
+    is_direct: bool,
+    is_space: bool,
+    encrypted: bool,
+    history_visibility: HistoryVisibility,
+    join_rule: JoinRule,
+    visibility: Visibility,
+    parent_space: Option<ParentSpace>,
+}
+
+impl Default for RoomCreationBuilder {
+    fn default() -> Self {
+        Self {
+            name: None,
+            topic: None,
+            alias_name: None,
+            room_version: None,
+            invite: Vec::new(),
+            is_direct: false,
+            is_space: false,
+            encrypted: cfg!(feature = "e2e-encryption"),
+            history_visibility: HistoryVisibility::Shared,
+            join_rule: JoinRule::Invite,
+            visibility: Visibility::Private,
+            parent_space: None,
+        }
+    }
+}
+
+impl RoomCreationBuilder {
+    /// Create a builder for a private room, that is encrypted if the
+    /// `e2e-encryption` feature is enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the room.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the topic of the room.
+    pub fn topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    /// Set the local part of the canonical alias of the room.
+    pub fn alias_name(mut self, alias_name: impl Into<String>) -> Self {
+        self.alias_name = Some(alias_name.into());
+        self
+    }
+
+    /// Set the version of the room.
+    ///
+    /// The homeserver's default room version is used if it doesn't support
+    /// this one.
+    pub fn room_version(mut self, room_version: RoomVersionId) -> Self {
+        self.room_version = Some(room_version);
+        self
+    }
+
+    /// Set the users to invite to the room.
+    pub fn invite(mut self, invite: Vec<OwnedUserId>) -> Self {
+        self.invite = invite;
+        self
+    }
+
+    /// Set whether the room is a direct message with the invited users.
+    pub fn is_direct(mut self, is_direct: bool) -> Self {
+        self.is_direct = is_direct;
+        self
+    }
+
+    /// Set whether the room is a space.
+    pub fn is_space(mut self, is_space: bool) -> Self {
+        self.is_space = is_space;
+        self
+    }
+
+    /// Set whether the room is encrypted.
+    pub fn encrypted(mut self, encrypted: bool) -> Self {
+        self.encrypted = encrypted;
+        self
+    }
+
+    /// Set who can read the history of the room.
+    ///
+    /// Defaults to [`HistoryVisibility::Shared`].
+    pub fn history_visibility(mut self, history_visibility: HistoryVisibility) -> Self {
+        self.history_visibility = history_visibility;
+        self
+    }
+
+    /// Set who can join the room.
+    ///
+    /// Defaults to [`JoinRule::Invite`].
+    ///
+    /// If the join rule is restricted without any allow rule, the members of
+    /// the parent space are allowed to join.
+    pub fn join_rule(mut self, join_rule: JoinRule) -> Self {
+        self.join_rule = join_rule;
+        self
+    }
+
+    /// Set whether the room is published in the room directory.
+    ///
+    /// Defaults to [`Visibility::Private`].
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Add the room to the given space.
+    ///
+    /// The space is set as the canonical parent of the room, and the room is
+    /// added as a child of the space once it's created.
+    ///
+    /// `via` lists the servers to reach the room and the space through. If
+    /// it's empty, [`Client::create_room_with()`] uses the server of the
+    /// current user, while [`RoomCreationBuilder::build()`] fails with
+    /// [`RoomCreationError::MissingVia`], since it doesn't know about the
+    /// current user.
+    pub fn parent_space(mut self, room_id: OwnedRoomId, via: Vec<OwnedServerName>) -> Self {
+        self.parent_space = Some(ParentSpace { room_id, via });
+        self
+    }
+
+    /// Check the consistency of the options.
+    fn validate(&self) -> Result<(), RoomCreationError> {
+        if self.encrypted {
+            if self.join_rule == JoinRule::Public {
+                return Err(RoomCreationError::EncryptedPublicRoom);
+            }
+
+            if self.history_visibility == HistoryVisibility::WorldReadable {
+                return Err(RoomCreationError::EncryptedWorldReadableRoom);
+            }
+        }
+
+        if self.visibility == Visibility::Public && self.join_rule == JoinRule::Invite {
+            return Err(RoomCreationError::PublishedInviteOnlyRoom);
+        }
+
+        if let JoinRule::Restricted(restricted) | JoinRule::KnockRestricted(restricted) =
+            &self.join_rule
+        {
+            if restricted.allow.is_empty() && self.parent_space.is_none() {
+                return Err(RoomCreationError::RestrictedWithoutAllowRules);
+            }
+        }
+
+        if self.is_direct {
+            if self.invite.is_empty() {
+                return Err(RoomCreationError::DirectWithoutInvite);
+            }
+
+            if self.is_space {
+                return Err(RoomCreationError::DirectSpace);
+            }
+        }
+
+        if self.parent_space.as_ref().is_some_and(|parent| parent.via.is_empty()) {
+            return Err(RoomCreationError::MissingVia);
+        }
+
+        Ok(())
+    }
+
+    /// Build the request to create the room, after checking the consistency of
+    /// the options.
+    ///
+    /// The room isn't added to its parent space by this request, use
+    /// [`Client::create_room_with()`] for that.
+    pub fn build(self) -> Result<CreateRoomRequest, RoomCreationError> {
+        self.validate()?;
+
+        let mut join_rule = self.join_rule;
+        let mut initial_state = Vec::new();
+
+        if let Some(parent) = &self.parent_space {
+            if let JoinRule::Restricted(restricted) | JoinRule::KnockRestricted(restricted) =
+                &mut join_rule
+            {
+                if restricted.allow.is_empty() {
+                    *restricted =
+                        Restricted::new(vec![AllowRule::room_membership(parent.room_id.clone())]);
+                }
+            }
+
+            initial_state.push(
+                InitialStateEvent {
+                    content: assign!(SpaceParentEventContent::new(parent.via.clone()), {
+                        canonical: true,
+                    }),
+                    state_key: parent.room_id.clone(),
+                }
+                .to_raw_any(),
+            );
+        }
+
+        if self.encrypted {
+            initial_state.push(
+                InitialStateEvent::new(RoomEncryptionEventContent::with_recommended_defaults())
+                    .to_raw_any(),
+            );
+        }
+
+        initial_state.push(
+            InitialStateEvent::new(RoomHistoryVisibilityEventContent::new(self.history_visibility))
+                .to_raw_any(),
+        );
+
+        let preset = if self.is_direct {
+            RoomPreset::TrustedPrivateChat
+        } else if join_rule == JoinRule::Public {
+            RoomPreset::PublicChat
+        } else {
+            RoomPreset::PrivateChat
+        };
+
+        initial_state
+            .push(InitialStateEvent::new(RoomJoinRulesEventContent::new(join_rule)).to_raw_any());
+
+        let creation_content = self
+            .is_space
+            .then(|| {
+                Raw::new(&assign!(CreationContent::new(), { room_type: Some(RoomType::Space) }))
+            })
+            .transpose()
+            .expect("the creation content should serialize");
+
+        Ok(assign!(CreateRoomRequest::new(), {
+            name: self.name,
+            topic: self.topic,
+            room_alias_name: self.alias_name,
+            room_version: self.room_version,
+            invite: self.invite,
+            is_direct: self.is_direct,
+            visibility: self.visibility,
+            preset: Some(preset),
+            creation_content,
+            initial_state,
+        }))
+    }
+}
+
+impl Client {
+    /// Create a room from a [`RoomCreationBuilder`].
+    ///
+    /// The options of the builder are checked before the room is created, and
+    /// inconsistencies are reported as an [`Error::RoomCreation`]. If the
+    /// room has a parent space, the current user must be allowed to add
+    /// children to it, and the room is added to the space once created.
+    ///
+    /// See [`Client::create_room()`] for the handling of the room version and
+    /// of direct messages.
+    #[instrument(skip_all)]
+    pub async fn create_room_with(&self, mut builder: RoomCreationBuilder) -> Result<Room> {
+        let parent_room = match &mut builder.parent_space {
+            Some(parent) => {
+                let own_user_id = self.user_id().ok_or(Error::AuthenticationRequired)?;
+
+                if parent.via.is_empty() {
+                    parent.via.push(own_user_id.server_name().to_owned());
+                }
+
+                let Some(parent_room) = self.get_room(&parent.room_id) else {
+                    return Err(
+                        RoomCreationError::UnknownParentSpace(parent.room_id.clone()).into()
+                    );
+                };
+
+                if !parent_room.can_user_send_state(own_user_id, StateEventType::SpaceChild).await?
+                {
+                    return Err(
+                        RoomCreationError::ParentSpaceForbidden(parent.room_id.clone()).into()
+                    );
+                }
+
+                Some((parent_room, parent.via.clone()))
+            }
+            None => None,
+        };
+
+        let room = self.create_room(builder.build()?).await?;
+
+        if let Some((parent_room, via)) = parent_room {
+            // The room is created at this point, so failing to add it to the space
+            // shouldn't fail its creation.
+            if let Err(error) = parent_room
+                .send_state_event_for_key(room.room_id(), SpaceChildEventContent::new(via))
+                .await
+            {
+                warn!("Failed to add the new room to its parent space: {error}");
+            }
+        }
+
+        Ok(room)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use ruma::{
+        api::client::room::Visibility,
+        events::room::{history_visibility::HistoryVisibility, join_rules::JoinRule},
+        owned_server_name, room_id, user_id,
+    };
+    use serde_json::{json, Value as JsonValue};
+
+    use super::{RoomCreationBuilder, RoomCreationError};
+
+    #[test]
+    fn test_inconsistent_options() {
+        let builder = RoomCreationBuilder::new().encrypted(true).join_rule(JoinRule::Public);
+        assert_matches!(builder.build(), Err(RoomCreationError::EncryptedPublicRoom));
+
+        let builder = RoomCreationBuilder::new()
+            .encrypted(true)
+            .history_visibility(HistoryVisibility::WorldReadable);
+        assert_matches!(builder.build(), Err(RoomCreationError::EncryptedWorldReadableRoom));
+
+        let builder = RoomCreationBuilder::new().visibility(Visibility::Public);
+        assert_matches!(builder.build(), Err(RoomCreationError::PublishedInviteOnlyRoom));
+
+        let builder =
+            RoomCreationBuilder::new().join_rule(JoinRule::Restricted(Default::default()));
+        assert_matches!(builder.build(), Err(RoomCreationError::RestrictedWithoutAllowRules));
+
+        let builder = RoomCreationBuilder::new().is_direct(true);
+        assert_matches!(builder.build(), Err(RoomCreationError::DirectWithoutInvite));
+
+        let builder = RoomCreationBuilder::new()
+            .parent_space(room_id!("!space:localhost").to_owned(), Vec::new());
+        assert_matches!(builder.build(), Err(RoomCreationError::MissingVia));
+    }
+
+    #[test]
+    fn test_build_room_in_space() {
+        let space_id = room_id!("!space:localhost");
+        let request = RoomCreationBuilder::new()
+            .name("Random")
+            .encrypted(true)
+            .invite(vec![user_id!("@alice:localhost").to_owned()])
+            .join_rule(JoinRule::Restricted(Default::default()))
+            .parent_space(space_id.to_owned(), vec![owned_server_name!("localhost")])
+            .build()
+            .unwrap();
+
+        assert_eq!(request.name.as_deref(), Some("Random"));
+        assert_eq!(request.invite.len(), 1);
+
+        let initial_state = request
+            .initial_state
+            .iter()
+            .map(|event| event.deserialize_as::<JsonValue>().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            initial_state,
+            vec![
+                json!({
+                    "type": "m.space.parent",
+                    "state_key": space_id,
+                    "content": { "via": ["localhost"], "canonical": true },
+                }),
+                json!({
+                    "type": "m.room.encryption",
+                    "state_key": "",
+                    "content": {
+                        "algorithm": "m.megolm.v1.aes-sha2",
+                        "rotation_period_ms": 604800000,
+                        "rotation_period_msgs": 100,
+                    },
+                }),
+                json!({
+                    "type": "m.room.history_visibility",
+                    "state_key": "",
+                    "content": { "history_visibility": "shared" },
+                }),
+                json!({
+                    "type": "m.room.join_rules",
+                    "state_key": "",
+                    "content": {
+                        "join_rule": "restricted",
+                        "allow": [{ "type": "m.room_membership", "room_id": space_id }],
+                    },
+                }),
+            ]
+        );
+    }
+}
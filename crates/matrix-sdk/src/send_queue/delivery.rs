@@ -0,0 +1,286 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracking of the delivery of the events sent with a send queue.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use ruma::{events::AnySyncTimelineEvent, serde::Raw, OwnedEventId, OwnedTransactionId};
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, error::SendError};
+
+use super::RoomSendQueueUpdate;
+
+/// The delivery state of an event sent with a [`RoomSendQueue`].
+///
+/// [`RoomSendQueue`]: super::RoomSendQueue
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeliveryState {
+    /// The event is waiting to be sent, or is being sent.
+    Queued,
+
+    /// The homeserver acknowledged the event.
+    Sent {
+        /// The ID of the event, returned by the homeserver.
+        event_id: OwnedEventId,
+    },
+
+    /// The event was received back from the homeserver in a sync response.
+    ///
+    /// This is the final state of an event.
+    SeenInSync {
+        /// The ID of the event.
+        event_id: OwnedEventId,
+    },
+
+    /// Sending the event failed.
+    Failed {
+        /// Whether the error is recoverable, in which case the event will be
+        /// sent again once the send queue is enabled again.
+        is_recoverable: bool,
+    },
+}
+
+/// The tracked delivery state of an event.
+#[derive(Debug)]
+struct TrackedEvent {
+    state: DeliveryState,
+
+    /// Whether the homeserver acknowledged the event. It can happen after
+    /// the event is seen in a sync response.
+    acknowledged: bool,
+}
+
+/// The delivery states of the events sent with a send queue.
+#[derive(Debug)]
+pub(super) struct DeliveryTracker {
+    /// The events that are not delivered yet, by transaction ID.
+    ///
+    /// An event is removed once it was both acknowledged by the homeserver and
+    /// seen in a sync response, or when it's cancelled.
+    events: Mutex<BTreeMap<OwnedTransactionId, TrackedEvent>>,
+
+    /// Broadcaster of the updates of the delivery states.
+    updates: broadcast::Sender<(OwnedTransactionId, DeliveryState)>,
+}
+
+impl DeliveryTracker {
+    fn new() -> Self {
+        Self { events: Default::default(), updates: broadcast::Sender::new(32) }
+    }
+
+    /// The delivery states of the events that are not delivered yet.
+    pub(super) fn states(&self) -> BTreeMap<OwnedTransactionId, DeliveryState> {
+        let events = self.events.lock().unwrap();
+        events.iter().map(|(txn_id, event)| (txn_id.clone(), event.state.clone())).collect()
+    }
+
+    /// Subscribe to the updates of the delivery states.
+    pub(super) fn subscribe(&self) -> broadcast::Receiver<(OwnedTransactionId, DeliveryState)> {
+        self.updates.subscribe()
+    }
+
+    /// Update the delivery states with the given send queue update.
+    fn on_update(&self, update: &RoomSendQueueUpdate) {
+        let mut events = self.events.lock().unwrap();
+
+        let (transaction_id, state) = match update {
+            RoomSendQueueUpdate::NewLocalEvent(local_echo) => {
+                (&local_echo.transaction_id, DeliveryState::Queued)
+            }
+            RoomSendQueueUpdate::RetryEvent { transaction_id } => {
+                (transaction_id, DeliveryState::Queued)
+            }
+            RoomSendQueueUpdate::SendError { transaction_id, is_recoverable, .. } => {
+                (transaction_id, DeliveryState::Failed { is_recoverable: *is_recoverable })
+            }
+            RoomSendQueueUpdate::SentEvent { transaction_id, event_id } => {
+                let is_seen = events
+                    .get(transaction_id)
+                    .is_some_and(|event| matches!(event.state, DeliveryState::SeenInSync { .. }));
+
+                if is_seen {
+                    // The event was seen in a sync response before the homeserver responded to
+                    // the request, it's delivered now.
+                    events.remove(transaction_id);
+                    return;
+                }
+
+                events.insert(
+                    transaction_id.clone(),
+                    TrackedEvent {
+                        state: DeliveryState::Sent { event_id: event_id.clone() },
+                        acknowledged: true,
+                    },
+                );
+
+                let _ = self.updates.send((
+                    transaction_id.clone(),
+                    DeliveryState::Sent { event_id: event_id.clone() },
+                ));
+                return;
+            }
+            RoomSendQueueUpdate::CancelledLocalEvent { transaction_id } => {
+                events.remove(transaction_id);
+                return;
+            }
+            RoomSendQueueUpdate::ReplacedLocalEvent { .. }
+            | RoomSendQueueUpdate::UploadedMedia { .. } => return,
+        };
+
+        events.insert(
+            transaction_id.clone(),
+            TrackedEvent { state: state.clone(), acknowledged: false },
+        );
+
+        let _ = self.updates.send((transaction_id.clone(), state));
+    }
+
+    /// Mark the given event, received in a sync response, as seen if it was
+    /// sent with the send queue.
+    pub(super) fn on_remote_event(&self, event: &Raw<AnySyncTimelineEvent>) {
+        #[derive(Deserialize)]
+        struct Unsigned {
+            transaction_id: Option<OwnedTransactionId>,
+        }
+
+        #[derive(Deserialize)]
+        struct RemoteEcho {
+            event_id: OwnedEventId,
+            unsigned: Option<Unsigned>,
+        }
+
+        let Ok(remote_echo) = event.deserialize_as::<RemoteEcho>() else { return };
+        let mut events = self.events.lock().unwrap();
+
+        // The transaction ID is only included for the events sent by this device, but
+        // it can be missing, so look for the event ID too.
+        let transaction_id = remote_echo
+            .unsigned
+            .and_then(|unsigned| unsigned.transaction_id)
+            .filter(|transaction_id| events.contains_key(transaction_id))
+            .or_else(|| {
+                events.iter().find_map(|(transaction_id, event)| match &event.state {
+                    DeliveryState::Sent { event_id } if *event_id == remote_echo.event_id => {
+                        Some(transaction_id.clone())
+                    }
+                    _ => None,
+                })
+            });
+
+        let Some(transaction_id) = transaction_id else { return };
+        let state = DeliveryState::SeenInSync { event_id: remote_echo.event_id };
+
+        if events.get(&transaction_id).is_some_and(|event| event.acknowledged) {
+            events.remove(&transaction_id);
+        } else if let Some(event) = events.get_mut(&transaction_id) {
+            event.state = state.clone();
+        }
+
+        let _ = self.updates.send((transaction_id, state));
+    }
+}
+
+/// The sender of the updates of a room send queue, that keeps track of the
+/// delivery states of the events too.
+#[derive(Clone, Debug)]
+pub(super) struct RoomSendQueueUpdateSender {
+    updates: broadcast::Sender<RoomSendQueueUpdate>,
+    delivery: Arc<DeliveryTracker>,
+}
+
+impl RoomSendQueueUpdateSender {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            updates: broadcast::Sender::new(capacity),
+            delivery: Arc::new(DeliveryTracker::new()),
+        }
+    }
+
+    /// Send an update to the subscribers, after updating the delivery states.
+    pub(super) fn send(
+        &self,
+        update: RoomSendQueueUpdate,
+    ) -> Result<usize, SendError<RoomSendQueueUpdate>> {
+        self.delivery.on_update(&update);
+        self.updates.send(update)
+    }
+
+    /// Subscribe to the updates.
+    pub(super) fn subscribe(&self) -> broadcast::Receiver<RoomSendQueueUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// The tracker of the delivery states of the events.
+    pub(super) fn delivery(&self) -> &Arc<DeliveryTracker> {
+        &self.delivery
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{event_id, owned_event_id, owned_transaction_id, serde::Raw};
+    use serde_json::json;
+
+    use super::{DeliveryState, DeliveryTracker};
+    use crate::send_queue::RoomSendQueueUpdate;
+
+    #[test]
+    fn test_sync_before_send_response() {
+        let tracker = DeliveryTracker::new();
+        let mut updates = tracker.subscribe();
+        let transaction_id = owned_transaction_id!("txn");
+
+        tracker
+            .on_update(&RoomSendQueueUpdate::RetryEvent { transaction_id: transaction_id.clone() });
+        assert_eq!(updates.try_recv().unwrap(), (transaction_id.clone(), DeliveryState::Queued));
+
+        // The remote echo is received before the response to the request.
+        let event = Raw::new(&json!({
+            "type": "m.room.message",
+            "event_id": "$event",
+            "sender": "@alice:localhost",
+            "origin_server_ts": 0,
+            "content": { "msgtype": "m.text", "body": "hello" },
+            "unsigned": { "transaction_id": "txn" },
+        }))
+        .unwrap()
+        .cast();
+        tracker.on_remote_event(&event);
+
+        assert_eq!(
+            updates.try_recv().unwrap(),
+            (
+                transaction_id.clone(),
+                DeliveryState::SeenInSync { event_id: owned_event_id!("$event") }
+            )
+        );
+
+        // The late acknowledgement doesn't change the state, and the event isn't
+        // tracked anymore.
+        tracker.on_update(&RoomSendQueueUpdate::SentEvent {
+            transaction_id,
+            event_id: event_id!("$event").to_owned(),
+        });
+        assert!(updates.try_recv().is_err());
+        assert!(tracker.states().is_empty());
+
+        // Events that weren't sent with the send queue are ignored.
+        tracker.on_remote_event(&event);
+        assert!(updates.try_recv().is_err());
+    }
+}
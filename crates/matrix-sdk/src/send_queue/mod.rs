@@ -138,6 +138,8 @@ use std::{
 };
 
 use as_variant::as_variant;
+use async_stream::stream;
+use futures_core::Stream;
 use matrix_sdk_base::{
     event_cache::store::EventCacheStoreError,
     media::MediaRequestParameters,
@@ -159,7 +161,7 @@ use ruma::{
             message::{FormattedBody, RoomMessageEventContent},
             MediaSource,
         },
-        AnyMessageLikeEventContent, EventContent as _, Mentions,
+        AnyMessageLikeEventContent, AnySyncTimelineEvent, EventContent as _, Mentions,
     },
     serde::Raw,
     OwnedEventId, OwnedRoomId, OwnedTransactionId, TransactionId,
@@ -177,8 +179,12 @@ use crate::{
     Client, Media, Room,
 };
 
+mod delivery;
 mod upload;
 
+pub use self::delivery::DeliveryState;
+use self::delivery::RoomSendQueueUpdateSender;
+
 /// A client-wide send queue, for all the rooms known by a client.
 pub struct SendQueue {
     client: Client,
@@ -386,7 +392,15 @@ impl RoomSendQueue {
         client: &Client,
         room_id: OwnedRoomId,
     ) -> Self {
-        let (updates_sender, _) = broadcast::channel(32);
+        let updates_sender = RoomSendQueueUpdateSender::new(32);
+
+        // Mark the sent events as seen when they are received back in a sync response.
+        // The room send queue lives as long as the client, so the handler is never
+        // removed.
+        client.add_room_event_handler(&room_id, {
+            let delivery = updates_sender.delivery().clone();
+            move |event: Raw<AnySyncTimelineEvent>| async move { delivery.on_remote_event(&event) }
+        });
 
         let queue = QueueStorage::new(WeakClient::from_client(client), room_id.clone());
         let notifier = Arc::new(Notify::new());
@@ -503,6 +517,38 @@ impl RoomSendQueue {
         Ok((local_echoes, self.inner.updates.subscribe()))
     }
 
+    /// Returns the delivery states of the events sent with this queue that
+    /// aren't delivered yet, as well as a stream of the updates of the
+    /// delivery states, keyed by the transaction IDs of the events.
+    ///
+    /// An event is delivered once the homeserver acknowledged it, and it was
+    /// received back in a sync response. This allows to show whether an event
+    /// was sent, and whether it was delivered, like with one or two ticks.
+    pub fn subscribe_to_delivery_states(
+        &self,
+    ) -> (
+        BTreeMap<OwnedTransactionId, DeliveryState>,
+        impl Stream<Item = (OwnedTransactionId, DeliveryState)>,
+    ) {
+        let delivery = self.inner.updates.delivery();
+        let mut updates = delivery.subscribe();
+        let states = delivery.states();
+
+        let stream = stream! {
+            loop {
+                match updates.recv().await {
+                    Ok(update) => yield update,
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        warn!("Missed {count} updates of the delivery states");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+
+        (states, stream)
+    }
+
     /// A task that must be spawned in the async runtime, running in the
     /// background for each room that has a send queue.
     ///
@@ -513,7 +559,7 @@ impl RoomSendQueue {
         room: WeakRoom,
         queue: QueueStorage,
         notifier: Arc<Notify>,
-        updates: broadcast::Sender<RoomSendQueueUpdate>,
+        updates: RoomSendQueueUpdateSender,
         locally_enabled: Arc<AtomicBool>,
         global_error_reporter: broadcast::Sender<SendQueueRoomError>,
         is_dropping: Arc<AtomicBool>,
@@ -831,7 +877,7 @@ struct RoomSendQueueInner {
     /// Broadcaster for notifications about the statuses of requests to be sent.
     ///
     /// Can be subscribed to from the outside.
-    updates: broadcast::Sender<RoomSendQueueUpdate>,
+    updates: RoomSendQueueUpdateSender,
 
     /// Queue of requests that are either to be sent, or being sent.
     ///
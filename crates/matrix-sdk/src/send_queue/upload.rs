@@ -118,6 +118,9 @@ impl RoomSendQueue {
             return Err(RoomSendQueueError::RoomNotJoined);
         }
 
+        #[cfg(feature = "attachment-metadata")]
+        let (content_type, data) = config.extract_missing_metadata(&content_type, data).await;
+
         let filename = filename.into();
         let upload_file_txn = TransactionId::new();
         let send_event_txn = config.txn_id.map_or_else(ChildTransactionId::new, Into::into);
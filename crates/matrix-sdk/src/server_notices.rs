@@ -0,0 +1,121 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Notices sent by the homeserver to the user, in the server notices room.
+//!
+//! See [`Client::server_notices_room()`] and
+//! [`Client::subscribe_to_server_notices()`].
+//!
+//! [`Client::server_notices_room()`]: crate::Client::server_notices_room
+//! [`Client::subscribe_to_server_notices()`]: crate::Client::subscribe_to_server_notices
+
+use ruma::{
+    events::{
+        room::message::{LimitType, MessageType, OriginalSyncRoomMessageEvent, ServerNoticeType},
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent, SyncMessageLikeEvent,
+    },
+    serde::Raw,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, UserId,
+};
+
+/// A notice sent by the homeserver in the server notices room.
+#[derive(Clone, Debug)]
+pub struct ServerNotice {
+    /// The ID of the server notices room.
+    pub room_id: OwnedRoomId,
+
+    /// The ID of the event containing the notice.
+    pub event_id: OwnedEventId,
+
+    /// The user the homeserver sent the notice as.
+    pub sender: OwnedUserId,
+
+    /// When the notice was sent.
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+
+    /// The human-readable description of the notice.
+    pub body: String,
+
+    /// The kind of notice.
+    pub kind: ServerNoticeKind,
+}
+
+/// The kind of a [`ServerNotice`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerNoticeKind {
+    /// A limit of the homeserver has been reached, and the user is blocked
+    /// from sending messages until it's lifted.
+    ///
+    /// Apps should show a blocking banner with the body of the notice.
+    UsageLimitReached(UsageLimitReached),
+
+    /// Another kind of notice, unknown to the SDK.
+    Other(ServerNoticeType),
+}
+
+/// The content of a `m.server_notice.usage_limit_reached` notice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsageLimitReached {
+    /// The kind of limit that has been reached, if known.
+    pub limit_type: Option<LimitType>,
+
+    /// A URI to contact the administrator of the homeserver, if any.
+    pub admin_contact: Option<String>,
+}
+
+impl ServerNotice {
+    /// Parse a timeline event of the server notices room into a notice.
+    ///
+    /// Only the notices sent by a user of the homeserver of the current user
+    /// are considered, since other users could have been invited to the
+    /// room.
+    pub(crate) fn from_timeline_event(
+        room_id: OwnedRoomId,
+        raw: &Raw<AnySyncTimelineEvent>,
+        own_user_id: &UserId,
+    ) -> Option<Self> {
+        let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+            SyncMessageLikeEvent::Original(OriginalSyncRoomMessageEvent {
+                content,
+                event_id,
+                sender,
+                origin_server_ts,
+                ..
+            }),
+        ))) = raw.deserialize()
+        else {
+            return None;
+        };
+
+        let MessageType::ServerNotice(notice) = content.msgtype else {
+            return None;
+        };
+
+        if sender.server_name() != own_user_id.server_name() {
+            return None;
+        }
+
+        let kind = match notice.server_notice_type {
+            ServerNoticeType::UsageLimitReached => {
+                ServerNoticeKind::UsageLimitReached(UsageLimitReached {
+                    limit_type: notice.limit_type,
+                    admin_contact: notice.admin_contact,
+                })
+            }
+            other => ServerNoticeKind::Other(other),
+        };
+
+        Some(Self { room_id, event_id, sender, origin_server_ts, body: notice.body, kind })
+    }
+}
@@ -0,0 +1,572 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export the settings of a [`Client`] to an encrypted blob, and import them
+//! on another device or installation.
+//!
+//! The settings include the push rules of the account, with the notification
+//! settings of the rooms, the tags of the rooms, and the local preferences of
+//! the client, like the [`MediaPolicy`].
+
+use std::collections::BTreeMap;
+
+use matrix_sdk_base::media::MediaPolicy;
+use matrix_sdk_store_encryption::StoreCipher;
+use ruma::{
+    api::client::push::{
+        delete_pushrule, set_pushrule, set_pushrule_actions, set_pushrule_enabled,
+    },
+    events::tag::Tags,
+    push::{
+        Action, NewConditionalPushRule, NewPatternedPushRule, NewPushRule, NewSimplePushRule,
+        RuleKind, Ruleset,
+    },
+    serde::Base64,
+    OwnedRoomId,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, instrument};
+
+use crate::{Client, HttpError};
+
+/// The version of the format of the exported settings.
+const SETTINGS_EXPORT_VERSION: u8 = 1;
+
+/// An error occurring while exporting or importing the settings of a client.
+#[derive(Debug, Error)]
+pub enum SettingsExportError {
+    /// The settings couldn't be (de)serialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The settings couldn't be encrypted or decrypted, for instance because
+    /// the passphrase is wrong.
+    #[error(transparent)]
+    Encryption(#[from] matrix_sdk_store_encryption::Error),
+
+    /// The settings were exported with an unsupported version of the format.
+    #[error("unsupported version of the settings export: {0}")]
+    UnsupportedVersion(u8),
+
+    /// The settings couldn't be loaded from or saved to the store.
+    #[error(transparent)]
+    Sdk(#[from] crate::Error),
+
+    /// The settings couldn't be applied on the homeserver.
+    #[error(transparent)]
+    Http(#[from] HttpError),
+}
+
+/// The settings of a client, as exported by [`Client::export_settings()`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ClientSettings {
+    /// The push rules of the account, including the notification settings of
+    /// the rooms.
+    pub push_rules: Option<Ruleset>,
+
+    /// The tags of the rooms, like `m.favourite`.
+    #[serde(default)]
+    pub room_tags: BTreeMap<OwnedRoomId, Tags>,
+
+    /// The policy choosing which media can be downloaded automatically.
+    pub media_policy: Option<MediaPolicy>,
+}
+
+/// The content of an encrypted settings export.
+#[derive(Serialize, Deserialize)]
+struct SettingsExport {
+    /// The version of the format.
+    version: u8,
+
+    /// The cipher, encrypted with the passphrase.
+    cipher: Base64,
+
+    /// The settings, encrypted with the cipher.
+    settings: Base64,
+}
+
+impl ClientSettings {
+    /// Encrypt these settings with the given passphrase.
+    pub fn encrypt(&self, passphrase: &str) -> Result<Vec<u8>, SettingsExportError> {
+        let cipher = StoreCipher::new()?;
+
+        let export = SettingsExport {
+            version: SETTINGS_EXPORT_VERSION,
+            cipher: Base64::new(cipher.export(passphrase)?),
+            settings: Base64::new(cipher.encrypt_value(self)?),
+        };
+
+        Ok(serde_json::to_vec(&export)?)
+    }
+
+    /// Decrypt settings encrypted with [`ClientSettings::encrypt()`], with the
+    /// given passphrase.
+    pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Self, SettingsExportError> {
+        let export: SettingsExport = serde_json::from_slice(data)?;
+
+        if export.version != SETTINGS_EXPORT_VERSION {
+            return Err(SettingsExportError::UnsupportedVersion(export.version));
+        }
+
+        let cipher = StoreCipher::import(passphrase, export.cipher.as_bytes())?;
+
+        Ok(cipher.decrypt_value(export.settings.as_bytes())?)
+    }
+}
+
+impl Client {
+    /// Get the current settings of the client.
+    ///
+    /// The tags are only included for the joined rooms.
+    pub async fn settings(&self) -> Result<ClientSettings, SettingsExportError> {
+        let push_rules = self.account().push_rules().await?;
+
+        let mut room_tags = BTreeMap::new();
+        for room in self.joined_rooms() {
+            if let Some(tags) = room.tags().await.map_err(crate::Error::from)? {
+                if !tags.is_empty() {
+                    room_tags.insert(room.room_id().to_owned(), tags);
+                }
+            }
+        }
+
+        let media_policy = self.media().media_policy().await?;
+
+        Ok(ClientSettings {
+            push_rules: Some(push_rules),
+            room_tags,
+            media_policy: Some(media_policy),
+        })
+    }
+
+    /// Export the settings of the client to a blob encrypted with the given
+    /// passphrase.
+    ///
+    /// The blob can be imported with [`Client::import_settings()`] on another
+    /// device or installation, even with another account.
+    pub async fn export_settings(&self, passphrase: &str) -> Result<Vec<u8>, SettingsExportError> {
+        self.settings().await?.encrypt(passphrase)
+    }
+
+    /// Import the settings exported with [`Client::export_settings()`], with
+    /// the given passphrase.
+    ///
+    /// Returns the imported settings.
+    ///
+    /// See [`Client::apply_settings()`] for how they are applied.
+    pub async fn import_settings(
+        &self,
+        data: &[u8],
+        passphrase: &str,
+    ) -> Result<ClientSettings, SettingsExportError> {
+        let settings = ClientSettings::decrypt(data, passphrase)?;
+        self.apply_settings(&settings).await?;
+        Ok(settings)
+    }
+
+    /// Apply the given settings to the client.
+    ///
+    /// - The push rules are changed to match the given ones. The actions and
+    ///   the enabled state of the server-default rules are updated, and the
+    ///   user-defined rules are added, updated or removed. The order of the
+    ///   user-defined rules is not kept.
+    /// - The tags of the rooms that are known by the client are replaced with
+    ///   the given ones. The tags of the other rooms are ignored.
+    /// - The local preferences are saved in the store.
+    #[instrument(skip_all)]
+    pub async fn apply_settings(
+        &self,
+        settings: &ClientSettings,
+    ) -> Result<(), SettingsExportError> {
+        if let Some(push_rules) = &settings.push_rules {
+            let current = self.account().push_rules().await?;
+
+            for change in push_rule_changes(&current, push_rules) {
+                debug!(?change, "Applying push rule change");
+                change.apply(self).await?;
+            }
+        }
+
+        for (room_id, tags) in &settings.room_tags {
+            let Some(room) = self.get_room(room_id) else {
+                debug!(%room_id, "Ignoring the tags of an unknown room");
+                continue;
+            };
+
+            let current = room.tags().await.map_err(crate::Error::from)?.unwrap_or_default();
+
+            for name in current.keys().filter(|name| !tags.contains_key(*name)) {
+                room.remove_tag(name.clone()).await?;
+            }
+
+            for (name, info) in tags {
+                if current.get(name).map(|current| current.order) != Some(info.order) {
+                    room.set_tag(name.clone(), info.clone()).await?;
+                }
+            }
+        }
+
+        if let Some(media_policy) = &settings.media_policy {
+            self.media().set_media_policy(media_policy.clone()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A push rule, with what's needed to compare it and to create it again.
+struct PushRuleEntry {
+    kind: RuleKind,
+    rule_id: String,
+    is_server_default: bool,
+    enabled: bool,
+    actions: Vec<Action>,
+    /// What the rule matches, i.e. its conditions or its pattern, serialized
+    /// to be compared.
+    criteria: serde_json::Value,
+    new_rule: NewPushRule,
+}
+
+/// A change to apply to the push rules of the account.
+#[derive(Debug)]
+enum PushRuleChange {
+    /// Add a user-defined push rule.
+    Add { new_rule: NewPushRule, enabled: bool },
+
+    /// Delete a user-defined push rule.
+    Delete { kind: RuleKind, rule_id: String },
+
+    /// Set whether a push rule is enabled.
+    SetEnabled { kind: RuleKind, rule_id: String, enabled: bool },
+
+    /// Set the actions of a push rule.
+    SetActions { kind: RuleKind, rule_id: String, actions: Vec<Action> },
+}
+
+impl PushRuleChange {
+    async fn apply(self, client: &Client) -> Result<(), HttpError> {
+        match self {
+            Self::Add { new_rule, enabled } => {
+                let kind = new_rule.kind();
+                let rule_id = new_rule.rule_id().to_owned();
+                client.send(set_pushrule::v3::Request::new(new_rule)).await?;
+
+                // New rules are enabled by default.
+                if !enabled {
+                    client
+                        .send(set_pushrule_enabled::v3::Request::new(kind, rule_id, false))
+                        .await?;
+                }
+            }
+            Self::Delete { kind, rule_id } => {
+                client.send(delete_pushrule::v3::Request::new(kind, rule_id)).await?;
+            }
+            Self::SetEnabled { kind, rule_id, enabled } => {
+                client.send(set_pushrule_enabled::v3::Request::new(kind, rule_id, enabled)).await?;
+            }
+            Self::SetActions { kind, rule_id, actions } => {
+                client.send(set_pushrule_actions::v3::Request::new(kind, rule_id, actions)).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the entries of all the push rules of the given ruleset.
+fn push_rule_entries(ruleset: &Ruleset) -> Vec<PushRuleEntry> {
+    let conditional = |kind: RuleKind, rule: &ruma::push::ConditionalPushRule| PushRuleEntry {
+        kind: kind.clone(),
+        rule_id: rule.rule_id.clone(),
+        is_server_default: rule.default,
+        enabled: rule.enabled,
+        actions: rule.actions.clone(),
+        criteria: serde_json::to_value(&rule.conditions).unwrap_or_default(),
+        new_rule: {
+            let new_rule = NewConditionalPushRule::new(
+                rule.rule_id.clone(),
+                rule.conditions.clone(),
+                rule.actions.clone(),
+            );
+
+            if kind == RuleKind::Override {
+                NewPushRule::Override(new_rule)
+            } else {
+                NewPushRule::Underride(new_rule)
+            }
+        },
+    };
+
+    let override_ = ruleset.override_.iter().map(|rule| conditional(RuleKind::Override, rule));
+    let underride = ruleset.underride.iter().map(|rule| conditional(RuleKind::Underride, rule));
+
+    let content = ruleset.content.iter().map(|rule| PushRuleEntry {
+        kind: RuleKind::Content,
+        rule_id: rule.rule_id.clone(),
+        is_server_default: rule.default,
+        enabled: rule.enabled,
+        actions: rule.actions.clone(),
+        criteria: rule.pattern.clone().into(),
+        new_rule: NewPushRule::Content(NewPatternedPushRule::new(
+            rule.rule_id.clone(),
+            rule.pattern.clone(),
+            rule.actions.clone(),
+        )),
+    });
+
+    let room = ruleset.room.iter().map(|rule| PushRuleEntry {
+        kind: RuleKind::Room,
+        rule_id: rule.rule_id.to_string(),
+        is_server_default: rule.default,
+        enabled: rule.enabled,
+        actions: rule.actions.clone(),
+        // The rule ID is what the rule matches.
+        criteria: serde_json::Value::Null,
+        new_rule: NewPushRule::Room(NewSimplePushRule::new(
+            rule.rule_id.clone(),
+            rule.actions.clone(),
+        )),
+    });
+
+    let sender = ruleset.sender.iter().map(|rule| PushRuleEntry {
+        kind: RuleKind::Sender,
+        rule_id: rule.rule_id.to_string(),
+        is_server_default: rule.default,
+        enabled: rule.enabled,
+        actions: rule.actions.clone(),
+        // The rule ID is what the rule matches.
+        criteria: serde_json::Value::Null,
+        new_rule: NewPushRule::Sender(NewSimplePushRule::new(
+            rule.rule_id.clone(),
+            rule.actions.clone(),
+        )),
+    });
+
+    override_.chain(content).chain(room).chain(sender).chain(underride).collect()
+}
+
+/// Get the changes to apply to the `current` push rules so they match the
+/// `target` push rules.
+fn push_rule_changes(current: &Ruleset, target: &Ruleset) -> Vec<PushRuleChange> {
+    let current = push_rule_entries(current);
+    let target = push_rule_entries(target);
+
+    let find = |entries: &[PushRuleEntry], entry: &PushRuleEntry| {
+        entries.iter().position(|other| other.kind == entry.kind && other.rule_id == entry.rule_id)
+    };
+
+    let mut changes = Vec::new();
+
+    // Remove the user-defined rules that don't exist anymore.
+    for entry in &current {
+        if !entry.is_server_default && find(&target, entry).is_none() {
+            changes.push(PushRuleChange::Delete {
+                kind: entry.kind.clone(),
+                rule_id: entry.rule_id.clone(),
+            });
+        }
+    }
+
+    for entry in target {
+        let Some(index) = find(&current, &entry) else {
+            // The server-default rules can't be created, they are probably not supported
+            // by the homeserver.
+            if !entry.is_server_default {
+                changes
+                    .push(PushRuleChange::Add { new_rule: entry.new_rule, enabled: entry.enabled });
+            }
+            continue;
+        };
+
+        let existing = &current[index];
+
+        // The conditions or the pattern of a rule can't be changed, so a user-defined
+        // rule is created again if they differ. Those of the server-default rules are
+        // controlled by the homeserver.
+        if !entry.is_server_default && existing.criteria != entry.criteria {
+            changes.push(PushRuleChange::Delete { kind: entry.kind, rule_id: entry.rule_id });
+            changes.push(PushRuleChange::Add { new_rule: entry.new_rule, enabled: entry.enabled });
+            continue;
+        }
+
+        if existing.actions != entry.actions {
+            changes.push(PushRuleChange::SetActions {
+                kind: entry.kind.clone(),
+                rule_id: entry.rule_id.clone(),
+                actions: entry.actions,
+            });
+        }
+
+        if existing.enabled != entry.enabled {
+            changes.push(PushRuleChange::SetEnabled {
+                kind: entry.kind,
+                rule_id: entry.rule_id,
+                enabled: entry.enabled,
+            });
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_let;
+    use ruma::{
+        owned_room_id,
+        push::{
+            Action, NewConditionalPushRule, NewPatternedPushRule, NewPushRule, NewSimplePushRule,
+            PredefinedContentRuleId, PredefinedOverrideRuleId, PushCondition, RuleKind, Ruleset,
+        },
+        user_id,
+    };
+
+    use super::{push_rule_changes, ClientSettings, PushRuleChange, SettingsExportError};
+
+    #[test]
+    fn test_settings_encryption() {
+        let settings = ClientSettings {
+            push_rules: Some(Ruleset::server_default(user_id!("@alice:localhost"))),
+            ..Default::default()
+        };
+
+        let data = settings.encrypt("passphrase").unwrap();
+
+        let decrypted = ClientSettings::decrypt(&data, "passphrase").unwrap();
+        let push_rules = decrypted.push_rules.unwrap();
+        assert_eq!(push_rules.override_.len(), settings.push_rules.unwrap().override_.len());
+
+        assert_let!(
+            Err(SettingsExportError::Encryption(_)) =
+                ClientSettings::decrypt(&data, "wrong passphrase")
+        );
+    }
+
+    #[test]
+    fn test_push_rule_changes() {
+        let mut current = Ruleset::server_default(user_id!("@alice:localhost"));
+        let mut target = current.clone();
+
+        // Identical rulesets don't need changes.
+        assert!(push_rule_changes(&current, &target).is_empty());
+
+        // A room rule only exists in the target, a server-default rule is disabled and
+        // another one has new actions.
+        target
+            .insert(
+                NewPushRule::Room(NewSimplePushRule::new(
+                    owned_room_id!("!room:localhost"),
+                    Vec::new(),
+                )),
+                None,
+                None,
+            )
+            .unwrap();
+        target.set_enabled(RuleKind::Override, PredefinedOverrideRuleId::Reaction, false).unwrap();
+        target
+            .set_actions(
+                RuleKind::Content,
+                PredefinedContentRuleId::ContainsUserName,
+                vec![Action::Notify],
+            )
+            .unwrap();
+
+        // A room rule only exists in the current ruleset.
+        current
+            .insert(
+                NewPushRule::Room(NewSimplePushRule::new(
+                    owned_room_id!("!other_room:localhost"),
+                    Vec::new(),
+                )),
+                None,
+                None,
+            )
+            .unwrap();
+
+        let changes = push_rule_changes(&current, &target);
+        assert_eq!(changes.len(), 4);
+
+        assert_let!(PushRuleChange::Delete { kind: RuleKind::Room, rule_id } = &changes[0]);
+        assert_eq!(rule_id, "!other_room:localhost");
+
+        assert_let!(
+            PushRuleChange::SetEnabled { kind: RuleKind::Override, enabled, .. } = &changes[1]
+        );
+        assert!(!enabled);
+
+        assert_let!(
+            PushRuleChange::SetActions { kind: RuleKind::Content, actions, .. } = &changes[2]
+        );
+        assert_eq!(actions.len(), 1);
+
+        assert_let!(
+            PushRuleChange::Add { new_rule: NewPushRule::Room(rule), enabled } = &changes[3]
+        );
+        assert_eq!(rule.rule_id, "!room:localhost");
+        assert!(enabled);
+
+        // User-defined rules whose pattern or conditions changed are created again,
+        // keeping whether they're enabled.
+        let mut current = Ruleset::new();
+        let mut target = Ruleset::new();
+
+        let content_rule = |pattern: &str| {
+            NewPushRule::Content(NewPatternedPushRule::new(
+                "animal".to_owned(),
+                pattern.to_owned(),
+                vec![Action::Notify],
+            ))
+        };
+        let override_rule = |pattern: &str| {
+            NewPushRule::Override(NewConditionalPushRule::new(
+                "message".to_owned(),
+                vec![PushCondition::EventMatch {
+                    key: "content.msgtype".to_owned(),
+                    pattern: pattern.to_owned(),
+                }],
+                vec![Action::Notify],
+            ))
+        };
+
+        current.insert(content_rule("cat"), None, None).unwrap();
+        current.insert(override_rule("m.text"), None, None).unwrap();
+        target.insert(content_rule("dog"), None, None).unwrap();
+        target.insert(override_rule("m.notice"), None, None).unwrap();
+        target.set_enabled(RuleKind::Override, "message", false).unwrap();
+
+        let changes = push_rule_changes(&current, &target);
+        assert_eq!(changes.len(), 4);
+
+        assert_let!(PushRuleChange::Delete { kind: RuleKind::Override, rule_id } = &changes[0]);
+        assert_eq!(rule_id, "message");
+        assert_let!(
+            PushRuleChange::Add { new_rule: NewPushRule::Override(rule), enabled } = &changes[1]
+        );
+        assert_let!(Some(PushCondition::EventMatch { pattern, .. }) = rule.conditions.first());
+        assert_eq!(pattern, "m.notice");
+        assert!(!enabled);
+
+        assert_let!(PushRuleChange::Delete { kind: RuleKind::Content, rule_id } = &changes[2]);
+        assert_eq!(rule_id, "animal");
+        assert_let!(
+            PushRuleChange::Add { new_rule: NewPushRule::Content(rule), enabled } = &changes[3]
+        );
+        assert_eq!(rule.pattern, "dog");
+        assert!(enabled);
+
+        // Unchanged rules don't need changes.
+        assert!(push_rule_changes(&target, &target).is_empty());
+    }
+}
@@ -1,13 +1,13 @@
 use std::{
     collections::BTreeMap,
     fmt::Debug,
-    sync::{Arc, RwLock as StdRwLock},
+    sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock},
     time::Duration,
 };
 
 use matrix_sdk_base::sliding_sync::http;
 use matrix_sdk_common::timer;
-use ruma::OwnedRoomId;
+use ruma::{time::Instant, OwnedRoomId};
 use tokio::sync::{broadcast::channel, Mutex as AsyncMutex, RwLock as AsyncRwLock};
 
 use super::{
@@ -35,6 +35,9 @@ pub struct SlidingSyncBuilder {
     network_timeout: Duration,
     #[cfg(feature = "e2e-encryption")]
     share_pos: bool,
+    restart_on_expired_session: bool,
+    max_room_subscriptions: Option<usize>,
+    room_subscription_idle_timeout: Option<Duration>,
 }
 
 impl SlidingSyncBuilder {
@@ -57,6 +60,9 @@ impl SlidingSyncBuilder {
                 network_timeout: Duration::from_secs(30),
                 #[cfg(feature = "e2e-encryption")]
                 share_pos: false,
+                restart_on_expired_session: false,
+                max_room_subscriptions: None,
+                room_subscription_idle_timeout: None,
             })
         }
     }
@@ -223,6 +229,54 @@ impl SlidingSyncBuilder {
         self
     }
 
+    /// Should the session be restarted when the server says it has expired?
+    ///
+    /// By default, when the server responds with an `M_UNKNOWN_POS` error, the
+    /// session is expired and the sync loop stops with this error, leaving it
+    /// up to the caller to start it again. With this option, a new session is
+    /// started right away instead, and the sync loop keeps on running: the
+    /// lists, the rooms and the room subscriptions are kept, and the state of
+    /// the lists and the subscribed rooms is sent again by the server.
+    ///
+    /// The number of restarts is counted in [`SlidingSync::metrics`].
+    ///
+    /// [`SlidingSync::metrics`]: super::SlidingSync::metrics
+    pub fn restart_on_expired_session(mut self) -> Self {
+        self.restart_on_expired_session = true;
+        self
+    }
+
+    /// Set the maximum number of room subscriptions.
+    ///
+    /// When subscribing to a room with [`SlidingSync::subscribe_to_rooms`]
+    /// would exceed this number, the subscriptions to the rooms that were
+    /// viewed the least recently are removed.
+    ///
+    /// By default, the number of room subscriptions isn't limited.
+    ///
+    /// [`SlidingSync::subscribe_to_rooms`]: super::SlidingSync::subscribe_to_rooms
+    pub fn max_room_subscriptions(mut self, max: usize) -> Self {
+        self.max_room_subscriptions = Some(max);
+        self
+    }
+
+    /// Remove the subscriptions to the rooms that haven't been viewed for the
+    /// given duration.
+    ///
+    /// A room is viewed when it is subscribed to, or marked as viewed with
+    /// [`SlidingSync::mark_rooms_viewed`]. Idle subscriptions are removed
+    /// before sending each request.
+    ///
+    /// By default, room subscriptions are kept until they are removed with
+    /// [`SlidingSync::unsubscribe_from_rooms`].
+    ///
+    /// [`SlidingSync::mark_rooms_viewed`]: super::SlidingSync::mark_rooms_viewed
+    /// [`SlidingSync::unsubscribe_from_rooms`]: super::SlidingSync::unsubscribe_from_rooms
+    pub fn room_subscription_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.room_subscription_idle_timeout = Some(timeout);
+        self
+    }
+
     /// Build the Sliding Sync.
     ///
     /// If `self.storage_key` is `Some(_)`, load the cached data from cold
@@ -269,6 +323,12 @@ impl SlidingSyncBuilder {
         let rooms = AsyncRwLock::new(rooms);
         let lists = AsyncRwLock::new(lists);
 
+        // The initial room subscriptions are considered viewed when the sliding sync
+        // is built.
+        let now = Instant::now();
+        let room_subscriptions_last_viewed =
+            self.subscriptions.keys().map(|room_id| (room_id.clone(), now)).collect();
+
         Ok(SlidingSync::new(SlidingSyncInner {
             id: self.id,
             version,
@@ -293,6 +353,14 @@ impl SlidingSyncBuilder {
 
             poll_timeout: self.poll_timeout,
             network_timeout: self.network_timeout,
+
+            restart_on_expired_session: self.restart_on_expired_session,
+            metrics: Default::default(),
+
+            max_room_subscriptions: self.max_room_subscriptions,
+            room_subscription_idle_timeout: self.room_subscription_idle_timeout,
+            room_subscriptions_last_viewed: StdMutex::new(room_subscriptions_last_viewed),
+            room_previews: StdMutex::new(BTreeMap::new()),
         }))
     }
 }
@@ -1,8 +1,10 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, time::Duration};
 
 use as_variant::as_variant;
 use imbl::Vector;
 use matrix_sdk_base::{sliding_sync::http, sync::SyncResponse, PreviousEventsProvider};
+#[cfg(feature = "e2e-encryption")]
+use ruma::time::Instant;
 use ruma::{
     api::{
         client::discovery::{discover_homeserver, get_supported_versions},
@@ -239,12 +241,13 @@ impl Client {
         &self,
         response: &http::Response,
     ) -> Result<SyncResponse> {
-        let response = self
+        let mut response = self
             .base_client()
             .process_sliding_sync(response, &(), self.sliding_sync_version().is_native())
             .await?;
 
         tracing::debug!("done processing on base_client");
+        self.enrich_sync_response(&mut response.rooms).await;
         self.call_sync_response_handlers(&response).await?;
 
         Ok(response)
@@ -271,13 +274,22 @@ impl PreviousEventsProvider for SlidingSyncPreviousEventsProvider<'_> {
 pub(crate) struct SlidingSyncResponseProcessor<'a> {
     client: Client,
     to_device_events: Vec<Raw<AnyToDeviceEvent>>,
+    /// Time spent processing the to-device events and the E2EE related parts
+    /// of the response.
+    to_device_duration: Duration,
     response: Option<SyncResponse>,
     rooms: &'a BTreeMap<OwnedRoomId, SlidingSyncRoom>,
 }
 
 impl<'a> SlidingSyncResponseProcessor<'a> {
     pub fn new(client: Client, rooms: &'a BTreeMap<OwnedRoomId, SlidingSyncRoom>) -> Self {
-        Self { client, to_device_events: Vec::new(), response: None, rooms }
+        Self {
+            client,
+            to_device_events: Vec::new(),
+            to_device_duration: Duration::ZERO,
+            response: None,
+            rooms,
+        }
     }
 
     #[cfg(feature = "e2e-encryption")]
@@ -289,6 +301,8 @@ impl<'a> SlidingSyncResponseProcessor<'a> {
         // `handle_room_response` before this function), so panic is fine.
         assert!(self.response.is_none());
 
+        let now = Instant::now();
+
         self.to_device_events = if let Some(to_device_events) = self
             .client
             .base_client()
@@ -303,6 +317,8 @@ impl<'a> SlidingSyncResponseProcessor<'a> {
             Vec::new()
         };
 
+        self.to_device_duration = now.elapsed();
+
         Ok(())
     }
 
@@ -338,7 +354,10 @@ impl<'a> SlidingSyncResponseProcessor<'a> {
         let mut response = self.response.take().unwrap_or_default();
 
         response.to_device.extend(self.to_device_events);
+        response.processing_report.to_device = self.to_device_duration;
+        response.processing_report.total += self.to_device_duration;
 
+        self.client.enrich_sync_response(&mut response.rooms).await;
         self.client.call_sync_response_handlers(&response).await?;
 
         Ok(response)
@@ -38,6 +38,7 @@ pub struct SlidingSyncListBuilder {
     include_heroes: Option<bool>,
     filters: Option<http::request::ListFilters>,
     timeline_limit: Bound,
+    preview_mode: bool,
     pub(crate) name: String,
 
     /// Should this list be cached and reloaded from the cache?
@@ -61,6 +62,7 @@ impl fmt::Debug for SlidingSyncListBuilder {
             .field("include_heroes", &self.include_heroes)
             .field("filters", &self.filters)
             .field("timeline_limit", &self.timeline_limit)
+            .field("preview_mode", &self.preview_mode)
             .field("name", &self.name)
             .finish_non_exhaustive()
     }
@@ -77,6 +79,7 @@ impl SlidingSyncListBuilder {
             include_heroes: None,
             filters: None,
             timeline_limit: 1,
+            preview_mode: false,
             name: name.into(),
             reloaded_cached_data: None,
             cache_policy: SlidingSyncListCachePolicy::Disabled,
@@ -133,6 +136,23 @@ impl SlidingSyncListBuilder {
         self
     }
 
+    /// Use this list to preview the rooms only.
+    ///
+    /// When all the lists of a [`SlidingSync`] are in preview mode, the rooms
+    /// of their responses that are unknown to the client are not processed
+    /// into full [`Room`]s: only a lightweight [`RoomListEntry`] is kept for
+    /// them, until they are hydrated with [`SlidingSync::hydrate_room()`],
+    /// for example when the user selects them.
+    ///
+    /// [`SlidingSync`]: crate::sliding_sync::SlidingSync
+    /// [`SlidingSync::hydrate_room()`]: crate::sliding_sync::SlidingSync::hydrate_room
+    /// [`Room`]: crate::Room
+    /// [`RoomListEntry`]: crate::sliding_sync::RoomListEntry
+    pub fn preview_mode(mut self, preview_mode: bool) -> Self {
+        self.preview_mode = preview_mode;
+        self
+    }
+
     /// Marks this list as sync'd from the cache, and attempts to reload it from
     /// storage.
     ///
@@ -180,6 +200,7 @@ impl SlidingSyncListBuilder {
                     ),
                 )),
                 timeline_limit: StdRwLock::new(self.timeline_limit),
+                preview_mode: self.preview_mode,
                 name: self.name,
                 cache_policy: self.cache_policy,
 
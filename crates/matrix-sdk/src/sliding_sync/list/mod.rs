@@ -141,6 +141,25 @@ impl SlidingSyncList {
         *self.inner.timeline_limit.write().unwrap() = timeline;
     }
 
+    /// Whether this list only previews its rooms. See
+    /// [`SlidingSyncListBuilder::preview_mode`] to learn more.
+    pub fn is_preview(&self) -> bool {
+        self.inner.preview_mode
+    }
+
+    /// The number of rooms covered by the ranges currently requested by this
+    /// list.
+    pub(super) fn number_of_requested_rooms(&self) -> usize {
+        self.inner
+            .request_generator
+            .read()
+            .unwrap()
+            .requested_ranges()
+            .iter()
+            .map(|range| (range.end().saturating_sub(*range.start()) as usize) + 1)
+            .sum()
+    }
+
     /// Get the maximum number of rooms. See [`Self::maximum_number_of_rooms`]
     /// to learn more.
     pub fn maximum_number_of_rooms(&self) -> Option<u32> {
@@ -234,6 +253,10 @@ pub(super) struct SlidingSyncListInner {
     /// The maximum number of timeline events to query for.
     timeline_limit: StdRwLock<Bound>,
 
+    /// Whether this list only previews its rooms. See
+    /// [`SlidingSyncListBuilder::preview_mode`] to learn more.
+    preview_mode: bool,
+
     /// The total number of rooms that is possible to interact with for the
     /// given list.
     ///
@@ -0,0 +1,72 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+/// Counters about the health of a Sliding Sync connection, as returned by
+/// [`SlidingSync::metrics`](super::SlidingSync::metrics).
+///
+/// They are kept in memory only, for the lifetime of the [`SlidingSync`]
+/// instance, and are meant to be reported to diagnose connection issues.
+///
+/// [`SlidingSync`]: super::SlidingSync
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SlidingSyncMetrics {
+    /// The number of responses received from the server.
+    pub responses: u64,
+
+    /// The number of requests that didn't get a valid response, because of a
+    /// network error or because the server rejected them.
+    pub missed_responses: u64,
+
+    /// The number of times the session expired on the server side, e.g.
+    /// because of an `M_UNKNOWN_POS` error, and was restarted.
+    pub restarts: u64,
+
+    /// The cumulated time spent waiting for the responses.
+    total_latency: Duration,
+}
+
+impl SlidingSyncMetrics {
+    /// The average time between sending a request and receiving its
+    /// response, or `None` if no response has been received yet.
+    pub fn average_latency(&self) -> Option<Duration> {
+        let responses = u32::try_from(self.responses).ok().filter(|n| *n > 0)?;
+        Some(self.total_latency / responses)
+    }
+
+    pub(super) fn record_response(&mut self, latency: Duration) {
+        self.responses += 1;
+        self.total_latency += latency;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SlidingSyncMetrics;
+
+    #[test]
+    fn test_average_latency() {
+        let mut metrics = SlidingSyncMetrics::default();
+        assert_eq!(metrics.average_latency(), None);
+
+        metrics.record_response(Duration::from_millis(100));
+        metrics.record_response(Duration::from_millis(300));
+
+        assert_eq!(metrics.responses, 2);
+        assert_eq!(metrics.average_latency(), Some(Duration::from_millis(200)));
+    }
+}
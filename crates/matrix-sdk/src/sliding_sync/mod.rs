@@ -20,6 +20,8 @@ mod cache;
 mod client;
 mod error;
 mod list;
+mod metrics;
+mod preview;
 mod room;
 mod sticky_parameters;
 mod utils;
@@ -28,7 +30,7 @@ use std::{
     collections::{btree_map::Entry, BTreeMap, HashSet},
     fmt::Debug,
     future::Future,
-    sync::{Arc, RwLock as StdRwLock},
+    sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock},
     time::Duration,
 };
 
@@ -39,7 +41,9 @@ pub use matrix_sdk_base::sliding_sync::http;
 use matrix_sdk_common::{deserialized_responses::SyncTimelineEvent, executor::spawn, timer};
 use ruma::{
     api::{client::error::ErrorKind, OutgoingRequest},
-    assign, OwnedEventId, OwnedRoomId, RoomId,
+    assign,
+    time::Instant,
+    OwnedEventId, OwnedRoomId, RoomId,
 };
 use serde::{Deserialize, Serialize};
 use tokio::{
@@ -50,13 +54,17 @@ use tracing::{debug, error, info, instrument, trace, warn, Instrument, Span};
 
 #[cfg(feature = "e2e-encryption")]
 use self::utils::JoinHandleExt as _;
-pub use self::{builder::*, client::VersionBuilderError, error::*, list::*, room::*};
+pub use self::{
+    builder::*, client::VersionBuilderError, error::*, list::*, metrics::SlidingSyncMetrics,
+    preview::RoomListEntry, room::*,
+};
 use self::{
     cache::restore_sliding_sync_state,
     client::SlidingSyncResponseProcessor,
+    preview::{merge_room_data, prune_room_previews},
     sticky_parameters::{LazyTransactionId, SlidingSyncStickyManager, StickyData},
 };
-use crate::{config::RequestConfig, Client, HttpError, Result};
+use crate::{config::RequestConfig, Client, HttpError, Result, Room};
 
 /// The Sliding Sync instance.
 ///
@@ -125,6 +133,31 @@ pub(super) struct SlidingSyncInner {
     /// Internal channel used to pass messages between Sliding Sync and other
     /// types.
     internal_channel: Sender<SlidingSyncInternalMessage>,
+
+    /// Should the session be restarted when the server says it has expired,
+    /// instead of stopping the sync loop?
+    restart_on_expired_session: bool,
+
+    /// Counters about the health of the connection.
+    metrics: StdMutex<SlidingSyncMetrics>,
+
+    /// The maximum number of room subscriptions, if any.
+    max_room_subscriptions: Option<usize>,
+
+    /// The duration after which the subscription to a room that hasn't been
+    /// viewed is removed, if any.
+    room_subscription_idle_timeout: Option<Duration>,
+
+    /// When each subscribed room was viewed for the last time.
+    ///
+    /// It is always locked after `sticky`.
+    room_subscriptions_last_viewed: StdMutex<BTreeMap<OwnedRoomId, Instant>>,
+
+    /// The accumulated data of the rooms received by the lists in preview mode,
+    /// that haven't been hydrated yet.
+    ///
+    /// It is always locked after the sync lock of the base client.
+    room_previews: StdMutex<BTreeMap<OwnedRoomId, http::response::Room>>,
 }
 
 impl SlidingSync {
@@ -146,7 +179,15 @@ impl SlidingSync {
     /// If the associated `Room`s exist, it will be marked as
     /// members are missing, so that it ensures to re-fetch all members.
     ///
-    /// A subscription to an already subscribed room is ignored.
+    /// The `settings`, like the timeline limit and the required state, apply
+    /// to the new subscriptions only: a subscription to an already subscribed
+    /// room is ignored, except that the room is marked as viewed.
+    ///
+    /// If the [maximum number of room subscriptions] is exceeded, the
+    /// subscriptions to the rooms that were viewed the least recently are
+    /// removed.
+    ///
+    /// [maximum number of room subscriptions]: SlidingSyncBuilder::max_room_subscriptions
     pub fn subscribe_to_rooms(
         &self,
         room_ids: &[&RoomId],
@@ -156,10 +197,14 @@ impl SlidingSync {
         let settings = settings.unwrap_or_default();
         let mut sticky = self.inner.sticky.write().unwrap();
         let room_subscriptions = &mut sticky.data_mut().room_subscriptions;
+        let mut last_viewed = self.inner.room_subscriptions_last_viewed.lock().unwrap();
+        let now = Instant::now();
 
         let mut skip_over_current_sync_loop_iteration = false;
 
         for room_id in room_ids {
+            last_viewed.insert((*room_id).to_owned(), now);
+
             // If the room subscription already exists, let's not
             // override it with a new one. First, it would reset its
             // state (`RoomSubscriptionState`), and second it would try to
@@ -177,6 +222,24 @@ impl SlidingSync {
             }
         }
 
+        if let Some(max) = self.inner.max_room_subscriptions {
+            let excess = room_subscriptions.len().saturating_sub(max);
+
+            if excess > 0 {
+                let mut by_last_viewed: Vec<_> = last_viewed
+                    .iter()
+                    .map(|(room_id, viewed)| (*viewed, room_id.clone()))
+                    .collect();
+                by_last_viewed.sort();
+
+                for (_, room_id) in by_last_viewed.into_iter().take(excess) {
+                    debug!(%room_id, "Too many room subscriptions, unsubscribing");
+                    room_subscriptions.remove(&room_id);
+                    last_viewed.remove(&room_id);
+                }
+            }
+        }
+
         if cancel_in_flight_request && skip_over_current_sync_loop_iteration {
             self.inner.internal_channel_send_if_possible(
                 SlidingSyncInternalMessage::SyncLoopSkipOverCurrentIteration,
@@ -184,6 +247,63 @@ impl SlidingSync {
         }
     }
 
+    /// Unsubscribe from many rooms.
+    ///
+    /// The rooms aren't subscribed to in the next requests anymore, and a
+    /// later subscription to them is sent again to the server, with its new
+    /// settings.
+    pub fn unsubscribe_from_rooms(&self, room_ids: &[&RoomId]) {
+        let mut sticky = self.inner.sticky.write().unwrap();
+        let mut last_viewed = self.inner.room_subscriptions_last_viewed.lock().unwrap();
+
+        for room_id in room_ids {
+            if sticky.data().room_subscriptions.contains_key(*room_id) {
+                sticky.data_mut().room_subscriptions.remove(*room_id);
+                last_viewed.remove(*room_id);
+            }
+        }
+    }
+
+    /// Mark the given subscribed rooms as viewed, so that their subscriptions
+    /// aren't removed for being idle.
+    ///
+    /// See [`SlidingSyncBuilder::room_subscription_idle_timeout`].
+    pub fn mark_rooms_viewed(&self, room_ids: &[&RoomId]) {
+        let mut last_viewed = self.inner.room_subscriptions_last_viewed.lock().unwrap();
+        let now = Instant::now();
+
+        for room_id in room_ids {
+            if let Some(viewed) = last_viewed.get_mut(*room_id) {
+                *viewed = now;
+            }
+        }
+    }
+
+    /// The rooms that are currently subscribed to.
+    pub fn subscribed_rooms(&self) -> Vec<OwnedRoomId> {
+        self.inner.sticky.read().unwrap().data().room_subscriptions.keys().cloned().collect()
+    }
+
+    /// Remove the subscriptions to the rooms that haven't been viewed since the
+    /// idle timeout, if any.
+    fn unsubscribe_from_idle_rooms(&self, now: Instant) {
+        let Some(idle_timeout) = self.inner.room_subscription_idle_timeout else { return };
+
+        let mut sticky = self.inner.sticky.write().unwrap();
+        let mut last_viewed = self.inner.room_subscriptions_last_viewed.lock().unwrap();
+
+        last_viewed.retain(|room_id, viewed| {
+            let is_idle = now.saturating_duration_since(*viewed) > idle_timeout;
+
+            if is_idle {
+                debug!(%room_id, "Unsubscribing from idle room");
+                sticky.data_mut().room_subscriptions.remove(room_id);
+            }
+
+            !is_idle
+        });
+    }
+
     /// Lookup a specific room
     pub async fn get_room(&self, room_id: &RoomId) -> Option<SlidingSyncRoom> {
         self.inner.rooms.read().await.get(room_id).cloned()
@@ -264,6 +384,141 @@ impl SlidingSync {
         self.inner.rooms.read().await.values().cloned().collect()
     }
 
+    /// Get the entries of the rooms received by the lists in preview mode,
+    /// that haven't been hydrated yet.
+    ///
+    /// See [`SlidingSyncListBuilder::preview_mode`] to learn more.
+    pub fn room_list_entries(&self) -> Vec<RoomListEntry> {
+        let room_previews = self.inner.room_previews.lock().unwrap();
+
+        room_previews
+            .iter()
+            .map(|(room_id, room_data)| RoomListEntry::new(room_id, room_data))
+            .collect()
+    }
+
+    /// Get the entry of a room received by the lists in preview mode, if it
+    /// hasn't been hydrated yet.
+    pub fn room_list_entry(&self, room_id: &RoomId) -> Option<RoomListEntry> {
+        let room_previews = self.inner.room_previews.lock().unwrap();
+
+        room_previews.get(room_id).map(|room_data| RoomListEntry::new(room_id, room_data))
+    }
+
+    /// Turn the entry of a room received by the lists in preview mode into a
+    /// full [`Room`], for example when the user selects it.
+    ///
+    /// The data received for the room so far is processed like it would have
+    /// been in a sync response, and the room is synced normally afterwards.
+    ///
+    /// Returns `None` if the room is unknown. If the room was already
+    /// hydrated, or wasn't received by a list in preview mode, it is returned
+    /// as is.
+    pub async fn hydrate_room(&self, room_id: &RoomId) -> Result<Option<Room>> {
+        // Take the lock to avoid a concurrent sync response diverting the room again.
+        let _sync_lock = self.inner.client.base_client().sync_lock().lock().await;
+
+        let Some(room_data) = self.inner.room_previews.lock().unwrap().remove(room_id) else {
+            return Ok(self.inner.client.get_room(room_id));
+        };
+
+        debug!(%room_id, "Hydrating a previewed room");
+
+        let mut response = http::Response::new(String::new());
+        response.rooms.insert(room_id.to_owned(), room_data);
+
+        let mut sync_response = {
+            let rooms = &*self.inner.rooms.read().await;
+            let mut response_processor =
+                SlidingSyncResponseProcessor::new(self.inner.client.clone(), rooms);
+
+            response_processor
+                .handle_room_response(&response, self.inner.version.is_native())
+                .await?;
+
+            response_processor.process_and_take_response().await?
+        };
+
+        if let Some(mut room_data) = response.rooms.remove(room_id) {
+            let timeline = if let Some(joined_room) = sync_response.rooms.join.remove(room_id) {
+                joined_room.timeline.events
+            } else {
+                room_data.timeline.drain(..).map(SyncTimelineEvent::new).collect()
+            };
+
+            self.inner.rooms.write().await.insert(
+                room_id.to_owned(),
+                SlidingSyncRoom::new(room_id.to_owned(), room_data.prev_batch, timeline),
+            );
+        }
+
+        Ok(self.inner.client.get_room(room_id))
+    }
+
+    /// Move the rooms of the response that must only be previewed to
+    /// [`SlidingSyncInner::room_previews`].
+    ///
+    /// Rooms are only previewed when all the lists are in preview mode, since
+    /// the response doesn't say which list a room was received for. The rooms
+    /// that are subscribed to, or that are already known to the client, are
+    /// processed normally. Only the previews of the most recent rooms, within
+    /// the ranges of the lists, are kept.
+    ///
+    /// Returns the IDs of the previewed rooms that were updated.
+    async fn take_previewed_rooms(
+        &self,
+        sliding_sync_response: &mut http::Response,
+    ) -> Vec<OwnedRoomId> {
+        let max_previews = {
+            let lists = self.inner.lists.read().await;
+
+            if lists.is_empty() || !lists.values().all(|list| list.is_preview()) {
+                return Vec::new();
+            }
+
+            lists.values().map(|list| list.number_of_requested_rooms()).sum()
+        };
+
+        let mut previewed_room_ids: Vec<_> = {
+            let sticky = self.inner.sticky.read().unwrap();
+            let room_subscriptions = &sticky.data().room_subscriptions;
+
+            sliding_sync_response
+                .rooms
+                .keys()
+                .filter(|room_id| {
+                    !room_subscriptions.contains_key(*room_id)
+                        && self.inner.client.get_room(room_id).is_none()
+                })
+                .cloned()
+                .collect()
+        };
+
+        let mut room_previews = self.inner.room_previews.lock().unwrap();
+
+        for room_id in &previewed_room_ids {
+            let Some(room_data) = sliding_sync_response.rooms.remove(room_id) else { continue };
+
+            match room_previews.entry(room_id.clone()) {
+                Entry::Occupied(mut entry) => merge_room_data(entry.get_mut(), room_data),
+                Entry::Vacant(entry) => {
+                    entry.insert(room_data);
+                }
+            }
+        }
+
+        // The rooms that are processed normally don't need a preview anymore.
+        for room_id in sliding_sync_response.rooms.keys() {
+            room_previews.remove(room_id);
+        }
+
+        prune_room_previews(&mut room_previews, max_previews);
+
+        previewed_room_ids.retain(|room_id| room_previews.contains_key(room_id));
+
+        previewed_room_ids
+    }
+
     /// Handle the HTTP response.
     #[instrument(skip_all)]
     async fn handle_response(
@@ -291,11 +546,17 @@ impl SlidingSync {
         // `sliding_sync_response` is vital, so it must be done somewhere; for now it
         // happens here.
 
+        let mut previewed_room_ids = Vec::new();
+
         let mut sync_response = {
             // Take the lock to avoid concurrent sliding syncs overwriting each other's room
             // infos.
             let _sync_lock = self.inner.client.base_client().sync_lock().lock().await;
 
+            if must_process_rooms_response {
+                previewed_room_ids = self.take_previewed_rooms(&mut sliding_sync_response).await;
+            }
+
             let rooms = &*self.inner.rooms.read().await;
             let mut response_processor =
                 SlidingSyncResponseProcessor::new(self.inner.client.clone(), rooms);
@@ -379,6 +640,9 @@ impl SlidingSync {
                 // `updated_rooms` and wouldn't cause any duplicates.
                 updated_rooms.extend(sync_response.rooms.join.keys().cloned());
 
+                // The previewed rooms weren't in the room subsection anymore either.
+                updated_rooms.extend(previewed_room_ids);
+
                 updated_rooms
             };
 
@@ -432,6 +696,8 @@ impl SlidingSync {
         &self,
         txn_id: &mut LazyTransactionId,
     ) -> Result<(http::Request, RequestConfig, OwnedMutexGuard<SlidingSyncPositionMarkers>)> {
+        self.unsubscribe_from_idle_rooms(Instant::now());
+
         // Collect requests for lists.
         let mut requests_lists = BTreeMap::new();
 
@@ -576,6 +842,8 @@ impl SlidingSync {
     {
         debug!("Sending request");
 
+        let sent_at = Instant::now();
+
         // Prepare the request.
         let request = self
             .inner
@@ -648,6 +916,8 @@ impl SlidingSync {
 
         debug!("Received response");
 
+        self.inner.metrics.lock().unwrap().record_response(sent_at.elapsed());
+
         // At this point, the request has been sent, and a response has been received.
         //
         // We must ensure the handling of the response cannot be stopped/
@@ -773,13 +1043,24 @@ impl SlidingSync {
                                 yield Ok(updates);
                             }
 
-                            // Here, errors we **cannot** ignore, and that must stop the sync loop.
                             Err(error) => {
+                                self.inner.metrics.lock().unwrap().missed_responses += 1;
+
                                 if error.client_api_error_kind() == Some(&ErrorKind::UnknownPos) {
+                                    if self.inner.restart_on_expired_session
+                                        && self.inner.position.lock().await.pos.is_some()
+                                    {
+                                        // The Sliding Sync session has expired. Start a new
+                                        // one, and keep on syncing.
+                                        self.restart_session().await;
+                                        continue;
+                                    }
+
                                     // The Sliding Sync session has expired. Let's reset `pos` and sticky parameters.
                                     self.expire_session().await;
                                 }
 
+                                // Here, errors we **cannot** ignore, and that must stop the sync loop.
                                 yield Err(error);
 
                                 // Terminates the loop, and terminates the stream.
@@ -806,6 +1087,40 @@ impl SlidingSync {
         Ok(self.inner.internal_channel_send(SlidingSyncInternalMessage::SyncLoopStop)?)
     }
 
+    /// Get a snapshot of the counters about the health of the connection.
+    pub fn metrics(&self) -> SlidingSyncMetrics {
+        self.inner.metrics.lock().unwrap().clone()
+    }
+
+    /// Start a new session, after the server said the current one has
+    /// expired.
+    ///
+    /// Unlike [`Self::expire_session`], the room subscriptions are kept: all
+    /// the sticky parameters are sent again in the first request of the new
+    /// session, so that the server sends the state of the lists and the
+    /// subscribed rooms again. The rooms already known are kept, and
+    /// reconciled with the data of the new session as it comes.
+    async fn restart_session(&self) {
+        info!("Session expired; restarting a new session");
+
+        {
+            let mut position = self.inner.position.lock().await;
+            position.pos = None;
+
+            if let Err(err) = self.cache_to_storage(&position).await {
+                error!(
+                    "couldn't invalidate sliding sync frozen state when restarting session: {err}"
+                );
+            }
+        }
+
+        // Resend all the sticky parameters, including the room subscriptions.
+        self.inner.sticky.write().unwrap().data_mut();
+        self.inner.lists.read().await.values().for_each(|list| list.invalidate_sticky_data());
+
+        self.inner.metrics.lock().unwrap().restarts += 1;
+    }
+
     /// Expire the current Sliding Sync session on the client-side.
     ///
     /// Expiring a Sliding Sync session means: resetting `pos`. It also resets
@@ -838,6 +1153,7 @@ impl SlidingSync {
             // Clear all room subscriptions: we don't want to resend all room subscriptions
             // when the session will restart.
             sticky.data_mut().room_subscriptions.clear();
+            self.inner.room_subscriptions_last_viewed.lock().unwrap().clear();
         }
 
         self.inner.lists.read().await.values().for_each(|list| list.invalidate_sticky_data());
@@ -1106,8 +1422,8 @@ mod tests {
     use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
     use matrix_sdk_test::async_test;
     use ruma::{
-        api::client::error::ErrorKind, assign, owned_room_id, room_id, serde::Raw, uint,
-        OwnedRoomId, TransactionId,
+        api::client::error::ErrorKind, assign, owned_room_id, room_id, serde::Raw, time::Instant,
+        uint, OwnedRoomId, TransactionId,
     };
     use serde::Deserialize;
     use serde_json::json;
@@ -1271,6 +1587,70 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_max_room_subscriptions() -> Result<()> {
+        let client = logged_in_client(Some("https://example.org".to_owned())).await;
+        let sliding_sync = client.sliding_sync("test")?.max_room_subscriptions(2).build().await?;
+
+        let room_id_0 = room_id!("!r0:bar.org");
+        let room_id_1 = room_id!("!r1:bar.org");
+        let room_id_2 = room_id!("!r2:bar.org");
+
+        // Make sure that the rooms are viewed at different instants.
+        let tick = || tokio::time::sleep(Duration::from_millis(1));
+
+        sliding_sync.subscribe_to_rooms(&[room_id_0], None, false);
+        tick().await;
+        sliding_sync.subscribe_to_rooms(&[room_id_1], None, false);
+        tick().await;
+
+        // Viewing the first room makes the second one the least recently viewed.
+        sliding_sync.mark_rooms_viewed(&[room_id_0]);
+        tick().await;
+        sliding_sync.subscribe_to_rooms(&[room_id_2], None, false);
+
+        assert_eq!(sliding_sync.subscribed_rooms(), [room_id_0.to_owned(), room_id_2.to_owned()]);
+
+        sliding_sync.unsubscribe_from_rooms(&[room_id_0]);
+        assert_eq!(sliding_sync.subscribed_rooms(), [room_id_2.to_owned()]);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_idle_room_subscriptions() -> Result<()> {
+        let client = logged_in_client(Some("https://example.org".to_owned())).await;
+        let sliding_sync = client
+            .sliding_sync("test")?
+            .room_subscription_idle_timeout(Duration::from_secs(5 * 60))
+            .build()
+            .await?;
+
+        let room_id_0 = room_id!("!r0:bar.org");
+        let room_id_1 = room_id!("!r1:bar.org");
+
+        sliding_sync.subscribe_to_rooms(&[room_id_0, room_id_1], None, false);
+
+        // Nothing is idle yet.
+        sliding_sync.unsubscribe_from_idle_rooms(Instant::now());
+        assert_eq!(sliding_sync.subscribed_rooms().len(), 2);
+
+        let viewed = sliding_sync.inner.room_subscriptions_last_viewed.lock().unwrap()[room_id_0];
+
+        // Viewing a room keeps its subscription alive.
+        sliding_sync
+            .inner
+            .room_subscriptions_last_viewed
+            .lock()
+            .unwrap()
+            .insert(room_id_0.to_owned(), viewed + Duration::from_secs(4 * 60));
+        sliding_sync.unsubscribe_from_idle_rooms(viewed + Duration::from_secs(6 * 60));
+
+        assert_eq!(sliding_sync.subscribed_rooms(), [room_id_0.to_owned()]);
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_room_subscriptions_are_reset_when_session_expires() -> Result<()> {
         let (_server, sliding_sync) = new_sliding_sync(vec![SlidingSyncList::builder("foo")
@@ -1968,6 +2348,97 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_unknown_pos_restarts_session() -> Result<()> {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        let sliding_sync = client
+            .sliding_sync("test-slidingsync")?
+            .add_list(
+                SlidingSyncList::builder("foo")
+                    .sync_mode(SlidingSyncMode::new_selective().add_range(0..=10)),
+            )
+            .restart_on_expired_session()
+            .build()
+            .await?;
+
+        let room_id = room_id!("!r0:bar.org");
+        sliding_sync.subscribe_to_rooms(&[room_id], None, false);
+
+        let sync = sliding_sync.sync();
+        pin_mut!(sync);
+
+        #[derive(Deserialize)]
+        struct PartialRequest {
+            txn_id: Option<String>,
+        }
+
+        // The first session starts.
+        {
+            let _mock_guard = Mock::given(SlidingSyncMatcher)
+                .respond_with(|request: &Request| {
+                    let request: PartialRequest = request.body_json().unwrap();
+
+                    ResponseTemplate::new(200).set_body_json(json!({
+                        "txn_id": request.txn_id,
+                        "pos": "0",
+                    }))
+                })
+                .mount_as_scoped(&server)
+                .await;
+
+            assert_matches!(sync.next().await, Some(Ok(_update_summary)));
+            assert_eq!(sliding_sync.inner.position.lock().await.pos, Some("0".to_owned()));
+        }
+
+        // The session expires, and a new one is started right away.
+        {
+            let _unknown_pos_guard = Mock::given(SlidingSyncMatcher)
+                .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                    "error": "foo",
+                    "errcode": "M_UNKNOWN_POS",
+                })))
+                .up_to_n_times(1)
+                .mount_as_scoped(&server)
+                .await;
+
+            let _mock_guard = Mock::given(SlidingSyncMatcher)
+                .respond_with(|request: &Request| {
+                    let request: PartialRequest = request.body_json().unwrap();
+
+                    ResponseTemplate::new(200).set_body_json(json!({
+                        "txn_id": request.txn_id,
+                        "pos": "a",
+                    }))
+                })
+                .mount_as_scoped(&server)
+                .await;
+
+            // The error isn't yielded, and the sync loop is still running.
+            assert_matches!(sync.next().await, Some(Ok(_update_summary)));
+            assert_eq!(sliding_sync.inner.position.lock().await.pos, Some("a".to_owned()));
+        }
+
+        // The room subscriptions are kept.
+        assert!(sliding_sync
+            .inner
+            .sticky
+            .read()
+            .unwrap()
+            .data()
+            .room_subscriptions
+            .contains_key(room_id));
+
+        let metrics = sliding_sync.metrics();
+        assert_eq!(metrics.responses, 2);
+        assert_eq!(metrics.missed_responses, 1);
+        assert_eq!(metrics.restarts, 1);
+        assert!(metrics.average_latency().is_some());
+
+        Ok(())
+    }
+
     #[cfg(feature = "e2e-encryption")]
     #[async_test]
     async fn test_sliding_sync_doesnt_remember_pos() -> Result<()> {
@@ -2608,6 +3079,69 @@ mod tests {
         assign!(http::Response::new(response_number.to_owned()), { rooms: rooms, extensions: extensions })
     }
 
+    #[async_test]
+    async fn test_preview_mode() -> Result<()> {
+        let room_id = owned_room_id!("!unicorn:example.org");
+
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        let sliding_sync = client
+            .sliding_sync("test")?
+            .add_list(
+                SlidingSyncList::builder("all")
+                    .sync_mode(SlidingSyncMode::new_selective().add_range(0..=100))
+                    .preview_mode(true),
+            )
+            .build()
+            .await?;
+
+        // The room is only previewed.
+        let server_response = assign!(http::Response::new("0".to_owned()), {
+            rooms: BTreeMap::from([(
+                room_id.clone(),
+                assign!(http::response::Room::new(), {
+                    name: Some("Unicorns".to_owned()),
+                    bump_stamp: Some(uint!(42)),
+                }),
+            )])
+        });
+
+        let update_summary = {
+            let mut pos_guard = sliding_sync.inner.position.clone().lock_owned().await;
+            sliding_sync.handle_response(server_response, &mut pos_guard).await?
+        };
+
+        assert!(update_summary.rooms.contains(&room_id));
+        assert!(client.get_room(&room_id).is_none());
+        assert!(sliding_sync.get_room(&room_id).await.is_none());
+
+        let entry = sliding_sync.room_list_entry(&room_id).unwrap();
+        assert_eq!(entry.name.as_deref(), Some("Unicorns"));
+        assert_eq!(entry.recency_stamp, Some(42));
+        assert_eq!(sliding_sync.room_list_entries().len(), 1);
+
+        // Hydrating the room creates it.
+        let room = sliding_sync.hydrate_room(&room_id).await?.unwrap();
+        assert_eq!(room.room_id(), room_id);
+        assert!(sliding_sync.get_room(&room_id).await.is_some());
+        assert!(sliding_sync.room_list_entry(&room_id).is_none());
+
+        // The next updates of the room are processed normally.
+        let server_response = assign!(http::Response::new("1".to_owned()), {
+            rooms: BTreeMap::from([(room_id.clone(), http::response::Room::new())])
+        });
+
+        {
+            let mut pos_guard = sliding_sync.inner.position.clone().lock_owned().await;
+            sliding_sync.handle_response(server_response, &mut pos_guard).await?;
+        }
+
+        assert!(sliding_sync.room_list_entries().is_empty());
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_process_rooms_account_data() -> Result<()> {
         let room = owned_room_id!("!pony:example.org");
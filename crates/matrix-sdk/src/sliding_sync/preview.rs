@@ -0,0 +1,311 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lightweight entries for the rooms of the lists in preview mode.
+
+use std::collections::{BTreeMap, HashSet};
+
+use matrix_sdk_base::sliding_sync::http;
+use ruma::{
+    events::{AnySyncStateEvent, AnySyncTimelineEvent},
+    serde::Raw,
+    JsOption, OwnedMxcUri, OwnedRoomId, RoomId,
+};
+
+/// A lightweight entry of a room list, for a room received by a list in
+/// preview mode.
+///
+/// It only contains what is needed to display the room in a room list. The
+/// full [`Room`] is created with [`SlidingSync::hydrate_room()`].
+///
+/// [`Room`]: crate::Room
+/// [`SlidingSync::hydrate_room()`]: super::SlidingSync::hydrate_room
+#[derive(Clone, Debug)]
+pub struct RoomListEntry {
+    /// The ID of the room.
+    pub room_id: OwnedRoomId,
+
+    /// The name of the room, as computed by the server.
+    pub name: Option<String>,
+
+    /// The URL of the avatar of the room.
+    pub avatar_url: Option<OwnedMxcUri>,
+
+    /// The latest event of the room, to preview the last message.
+    pub latest_event: Option<Raw<AnySyncTimelineEvent>>,
+
+    /// The number of unread notifications.
+    pub notification_count: u64,
+
+    /// The number of unread highlights.
+    pub highlight_count: u64,
+
+    /// Whether the room is a direct message.
+    pub is_dm: bool,
+
+    /// Whether the user is invited to the room.
+    pub is_invite: bool,
+
+    /// The recency stamp of the room, to sort the entries.
+    pub recency_stamp: Option<u64>,
+}
+
+impl RoomListEntry {
+    /// Build the entry of a room from its accumulated sliding sync data.
+    pub(super) fn new(room_id: &RoomId, room_data: &http::response::Room) -> Self {
+        Self {
+            room_id: room_id.to_owned(),
+            name: room_data.name.clone(),
+            avatar_url: room_data.avatar.clone().into_option(),
+            latest_event: room_data.timeline.last().cloned(),
+            notification_count: room_data
+                .unread_notifications
+                .notification_count
+                .map(u64::from)
+                .unwrap_or_default(),
+            highlight_count: room_data
+                .unread_notifications
+                .highlight_count
+                .map(u64::from)
+                .unwrap_or_default(),
+            is_dm: room_data.is_dm.unwrap_or_default(),
+            is_invite: room_data.invite_state.is_some(),
+            recency_stamp: room_data.bump_stamp.map(u64::from),
+        }
+    }
+}
+
+/// Merge the data of a room from a new response into the data accumulated
+/// from the previous responses.
+///
+/// Sliding sync only sends the fields that changed, so the fields of the new
+/// data only override the accumulated ones when they are set.
+pub(super) fn merge_room_data(accumulated: &mut http::response::Room, new: http::response::Room) {
+    if new.name.is_some() {
+        accumulated.name = new.name;
+    }
+
+    if !matches!(new.avatar, JsOption::Undefined) {
+        accumulated.avatar = new.avatar;
+    }
+
+    if new.is_dm.is_some() {
+        accumulated.is_dm = new.is_dm;
+    }
+
+    if new.invite_state.is_some() {
+        accumulated.invite_state = new.invite_state;
+    }
+
+    if new.unread_notifications.notification_count.is_some()
+        || new.unread_notifications.highlight_count.is_some()
+    {
+        accumulated.unread_notifications = new.unread_notifications;
+    }
+
+    // Only the latest event is needed to preview the room: keep it, and consider
+    // there's a gap with the events before it.
+    if let Some(latest_event) = new.timeline.into_iter().last() {
+        accumulated.timeline = vec![latest_event];
+        accumulated.prev_batch = new.prev_batch;
+        accumulated.limited = true;
+    }
+
+    // Only keep the latest state event for a given type and state key.
+    if !new.required_state.is_empty() {
+        let state_key = |event: &Raw<AnySyncStateEvent>| {
+            (
+                event.get_field::<String>("type").ok().flatten(),
+                event.get_field::<String>("state_key").ok().flatten(),
+            )
+        };
+
+        let new_state_keys: HashSet<_> = new.required_state.iter().map(state_key).collect();
+        accumulated.required_state.retain(|event| !new_state_keys.contains(&state_key(event)));
+        accumulated.required_state.extend(new.required_state);
+    }
+
+    if new.joined_count.is_some() {
+        accumulated.joined_count = new.joined_count;
+    }
+
+    if new.invited_count.is_some() {
+        accumulated.invited_count = new.invited_count;
+    }
+
+    if new.bump_stamp.is_some() {
+        accumulated.bump_stamp = new.bump_stamp;
+    }
+
+    if new.heroes.is_some() {
+        accumulated.heroes = new.heroes;
+    }
+}
+
+/// Only keep the `max_rooms` most recent previews, the other ones are out of
+/// the ranges of the lists and won't receive updates anymore.
+pub(super) fn prune_room_previews(
+    room_previews: &mut BTreeMap<OwnedRoomId, http::response::Room>,
+    max_rooms: usize,
+) {
+    if room_previews.len() <= max_rooms {
+        return;
+    }
+
+    let mut by_recency: Vec<_> = room_previews
+        .iter()
+        .map(|(room_id, room_data)| (room_data.bump_stamp, room_id.clone()))
+        .collect();
+    // Most recent first; rooms without a recency stamp come last.
+    by_recency.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    for (_, room_id) in by_recency.into_iter().skip(max_rooms) {
+        room_previews.remove(&room_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use matrix_sdk_base::sliding_sync::http;
+    use ruma::{assign, owned_mxc_uri, owned_room_id, room_id, serde::Raw, uint, JsOption};
+    use serde_json::json;
+
+    use super::{merge_room_data, prune_room_previews, RoomListEntry};
+
+    #[test]
+    fn test_merge_room_data() {
+        let room_id = room_id!("!r0:matrix.org");
+        let event = |body: &str| {
+            Raw::new(&json!({
+                "type": "m.room.message",
+                "event_id": format!("${body}"),
+                "sender": "@alice:matrix.org",
+                "origin_server_ts": 0,
+                "content": { "msgtype": "m.text", "body": body },
+            }))
+            .unwrap()
+            .cast()
+        };
+
+        let mut room_data = assign!(http::response::Room::new(), {
+            name: Some("Kitchen".to_owned()),
+            avatar: JsOption::Some(owned_mxc_uri!("mxc://matrix.org/kitchen")),
+            timeline: vec![event("hello")],
+            bump_stamp: Some(uint!(1)),
+        });
+        room_data.unread_notifications.notification_count = Some(uint!(1));
+
+        // The fields that aren't set don't override the accumulated ones.
+        let mut new = assign!(http::response::Room::new(), {
+            timeline: vec![event("world")],
+            bump_stamp: Some(uint!(2)),
+        });
+        new.unread_notifications.notification_count = Some(uint!(2));
+        merge_room_data(&mut room_data, new);
+
+        let entry = RoomListEntry::new(room_id, &room_data);
+        assert_eq!(entry.room_id, room_id);
+        assert_eq!(entry.name.as_deref(), Some("Kitchen"));
+        assert_eq!(entry.avatar_url, Some(owned_mxc_uri!("mxc://matrix.org/kitchen")));
+        let latest_event_id = entry.latest_event.unwrap().get_field::<String>("event_id").unwrap();
+        assert_eq!(latest_event_id.as_deref(), Some("$world"));
+        assert_eq!(entry.notification_count, 2);
+        assert_eq!(entry.highlight_count, 0);
+        assert_eq!(entry.recency_stamp, Some(2));
+        assert!(!entry.is_invite);
+
+        // A null avatar removes it.
+        merge_room_data(
+            &mut room_data,
+            assign!(http::response::Room::new(), { avatar: JsOption::Null }),
+        );
+        assert!(RoomListEntry::new(room_id, &room_data).avatar_url.is_none());
+
+        // Only the latest event is kept.
+        assert_eq!(room_data.timeline.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_room_data_keeps_latest_state() {
+        let state_event = |event_type: &str, state_key: &str, event_id: &str| {
+            Raw::new(&json!({
+                "type": event_type,
+                "state_key": state_key,
+                "event_id": event_id,
+                "sender": "@alice:matrix.org",
+                "origin_server_ts": 0,
+                "content": {},
+            }))
+            .unwrap()
+            .cast()
+        };
+
+        let mut room_data = assign!(http::response::Room::new(), {
+            required_state: vec![
+                state_event("m.room.name", "", "$name1"),
+                state_event("m.room.member", "@alice:matrix.org", "$alice1"),
+            ],
+        });
+
+        merge_room_data(
+            &mut room_data,
+            assign!(http::response::Room::new(), {
+                required_state: vec![
+                    state_event("m.room.name", "", "$name2"),
+                    state_event("m.room.member", "@bob:matrix.org", "$bob1"),
+                ],
+            }),
+        );
+
+        let mut event_ids = room_data
+            .required_state
+            .iter()
+            .map(|event| event.get_field::<String>("event_id").unwrap().unwrap())
+            .collect::<Vec<_>>();
+        event_ids.sort();
+        assert_eq!(event_ids, ["$alice1", "$bob1", "$name2"]);
+    }
+
+    #[test]
+    fn test_prune_room_previews() {
+        let mut room_previews = BTreeMap::from([
+            (
+                owned_room_id!("!old:matrix.org"),
+                assign!(http::response::Room::new(), {
+                    bump_stamp: Some(uint!(1)),
+                }),
+            ),
+            (
+                owned_room_id!("!new:matrix.org"),
+                assign!(http::response::Room::new(), {
+                    bump_stamp: Some(uint!(3)),
+                }),
+            ),
+            (owned_room_id!("!unknown:matrix.org"), http::response::Room::new()),
+            (
+                owned_room_id!("!mid:matrix.org"),
+                assign!(http::response::Room::new(), {
+                    bump_stamp: Some(uint!(2)),
+                }),
+            ),
+        ]);
+
+        prune_room_previews(&mut room_previews, 2);
+
+        let room_ids = room_previews.keys().map(|room_id| room_id.as_str()).collect::<Vec<_>>();
+        assert_eq!(room_ids, ["!mid:matrix.org", "!new:matrix.org"]);
+    }
+}
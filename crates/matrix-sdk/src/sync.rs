@@ -55,14 +55,30 @@ pub struct SyncResponse {
     pub to_device: Vec<Raw<AnyToDeviceEvent>>,
     /// New notifications per room.
     pub notifications: BTreeMap<OwnedRoomId, Vec<Notification>>,
+    /// How long each stage of the processing of this response took.
+    pub processing_report: SyncProcessingReport,
 }
 
 impl SyncResponse {
     pub(crate) fn new(next_batch: String, base_response: BaseSyncResponse) -> Self {
-        let BaseSyncResponse { rooms, presence, account_data, to_device, notifications } =
-            base_response;
-
-        Self { next_batch, rooms, presence, account_data, to_device, notifications }
+        let BaseSyncResponse {
+            rooms,
+            presence,
+            account_data,
+            to_device,
+            notifications,
+            processing_report,
+        } = base_response;
+
+        Self {
+            next_batch,
+            rooms,
+            presence,
+            account_data,
+            to_device,
+            notifications,
+            processing_report,
+        }
     }
 }
 
@@ -75,6 +91,7 @@ impl fmt::Debug for SyncResponse {
             .field("account_data", &DebugListOfRawEventsNoId(&self.account_data))
             .field("to_device", &DebugListOfRawEventsNoId(&self.to_device))
             .field("notifications", &self.notifications)
+            .field("processing_report", &self.processing_report)
             .finish_non_exhaustive()
     }
 }
@@ -145,12 +162,13 @@ impl Client {
         &self,
         response: sync_events::v3::Response,
     ) -> Result<BaseSyncResponse> {
-        let response = Box::pin(self.base_client().receive_sync_response(response)).await?;
+        let mut response = Box::pin(self.base_client().receive_sync_response(response)).await?;
 
         // Some new keys might have been received, so trigger a backup if needed.
         #[cfg(feature = "e2e-encryption")]
         self.encryption().backups().maybe_trigger_backup();
 
+        self.enrich_sync_response(&mut response.rooms).await;
         self.call_sync_response_handlers(&response).await?;
 
         Ok(response)
@@ -167,7 +185,14 @@ impl Client {
         &self,
         response: &BaseSyncResponse,
     ) -> Result<()> {
-        let BaseSyncResponse { rooms, presence, account_data, to_device, notifications } = response;
+        let BaseSyncResponse { rooms, presence, account_data, to_device, notifications, .. } =
+            response;
+
+        self.refresh_cached_profiles(rooms).await;
+        self.update_contact_activity(presence).await;
+        self.repair_direct_rooms(rooms).await;
+        self.handle_server_notices(rooms);
+        self.invalidate_room_routes(rooms);
 
         let now = Instant::now();
         self.handle_sync_events(HandlerKind::GlobalAccountData, None, account_data).await?;
@@ -298,6 +323,23 @@ impl Client {
         }
     }
 
+    /// Replace the named filter of the sync settings, if any, with the ID of
+    /// the filter.
+    pub(crate) async fn resolve_named_filter(
+        &self,
+        sync_settings: &mut crate::config::SyncSettings,
+    ) -> Result<()> {
+        if let Some(named_filter) = &sync_settings.named_filter {
+            let (name, definition) = &**named_filter;
+            let filter_id = self.get_or_upload_filter(name, definition.clone()).await?;
+
+            sync_settings.filter = Some(Box::new(sync_events::v3::Filter::FilterId(filter_id)));
+            sync_settings.named_filter = None;
+        }
+
+        Ok(())
+    }
+
     async fn sleep() {
         #[cfg(target_arch = "wasm32")]
         gloo_timers::future::TimeoutFuture::new(1_000).await;
@@ -310,6 +352,13 @@ impl Client {
         &self,
         sync_settings: &mut crate::config::SyncSettings,
     ) -> Result<SyncResponse> {
+        // Resolve the named filter once for the whole loop, rather than on every
+        // sync.
+        if let Err(e) = self.resolve_named_filter(sync_settings).await {
+            error!("Couldn't get the ID of the sync filter: {e}");
+            return Err(e);
+        }
+
         let response = self.sync_once(sync_settings.clone()).await;
 
         match response {
@@ -18,7 +18,7 @@
 #![allow(missing_debug_implementations)]
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     sync::{Arc, Mutex},
 };
 
@@ -35,7 +35,8 @@ use ruma::{
     },
     serde::Raw,
     time::Duration,
-    MxcUri, OwnedEventId, OwnedRoomId, RoomId, ServerName,
+    DeviceId, MxcUri, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, ServerName,
+    UserId,
 };
 use serde::Deserialize;
 use serde_json::{json, Value};
@@ -47,6 +48,10 @@ use wiremock::{
 use super::client::MockClientBuilder;
 use crate::{Client, OwnedServerName, Room};
 
+mod scenario;
+
+pub use self::scenario::{Scenario, ScenarioGuard};
+
 /// A [`wiremock`] [`MockServer`] along with useful methods to help mocking
 /// Matrix client-server API endpoints easily.
 ///
@@ -117,18 +122,33 @@ pub struct MatrixMockServer {
     /// token and avoid the client ignoring subsequent responses after the first
     /// one.
     sync_response_builder: Arc<Mutex<SyncResponseBuilder>>,
+
+    /// The one-time keys uploaded to the server, shared by the `/keys/upload`
+    /// and `/keys/claim` mocks.
+    one_time_keys: OneTimeKeys,
 }
 
 impl MatrixMockServer {
     /// Create a new [`wiremock`] server specialized for Matrix usage.
     pub async fn new() -> Self {
         let server = MockServer::start().await;
-        Self { server, sync_response_builder: Default::default() }
+        Self::from_server(server)
     }
 
     /// Creates a new [`MatrixMockServer`] from a [`wiremock`] server.
     pub fn from_server(server: MockServer) -> Self {
-        Self { server, sync_response_builder: Default::default() }
+        Self {
+            server,
+            sync_response_builder: Default::default(),
+            one_time_keys: Default::default(),
+        }
+    }
+
+    /// Get the one-time keys uploaded to this server with the mock from
+    /// [`Self::mock_keys_upload()`], to inspect them or to simulate failures of
+    /// the mock from [`Self::mock_keys_claim()`].
+    pub fn one_time_keys(&self) -> &OneTimeKeys {
+        &self.one_time_keys
     }
 
     /// Creates a new [`MockClientBuilder`] configured to use this server,
@@ -240,6 +260,14 @@ impl MatrixMockServer {
         self.server.verify().await;
         self.server.reset().await;
     }
+
+    /// Start declaring a [`Scenario`], an ordered sequence of requests that
+    /// the server expects, with their canned responses.
+    ///
+    /// See [`Scenario`] for an example.
+    pub fn scenario(&self) -> Scenario<'_> {
+        Scenario::new(&self.server)
+    }
 }
 
 // Specific mount endpoints.
@@ -611,6 +639,73 @@ impl MatrixMockServer {
         MockEndpoint { mock, server: &self.server, endpoint: DeleteRoomKeysVersionEndpoint }
     }
 
+    /// Create a prebuilt mock for uploading device keys and one-time keys.
+    ///
+    /// The uploaded one-time and fallback keys are stored in the
+    /// [`OneTimeKeys`] of this server, so they can be claimed with the mock
+    /// from [`Self::mock_keys_claim()`].
+    pub fn mock_keys_upload(&self) -> MockEndpoint<'_, KeysUploadEndpoint> {
+        let mock = Mock::given(method("POST")).and(path_regex(r"/_matrix/client/v3/keys/upload"));
+        MockEndpoint {
+            mock,
+            server: &self.server,
+            endpoint: KeysUploadEndpoint { one_time_keys: self.one_time_keys.clone() },
+        }
+    }
+
+    /// Create a prebuilt mock for claiming one-time keys.
+    ///
+    /// The keys are served from the [`OneTimeKeys`] of this server, which are
+    /// uploaded with the mock from [`Self::mock_keys_upload()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// use matrix_sdk::{
+    ///     ruma::{
+    ///         api::client::keys::claim_keys, device_id, owned_device_id, owned_user_id,
+    ///         server_name, user_id, OneTimeKeyAlgorithm,
+    ///     },
+    ///     test_utils::mocks::{KeyClaimOutcome, MatrixMockServer},
+    /// };
+    ///
+    /// let mock_server = MatrixMockServer::new().await;
+    /// let client = mock_server.client_builder().build().await;
+    ///
+    /// mock_server.mock_keys_claim().ok().mount().await;
+    ///
+    /// // Bob's homeserver can't be reached.
+    /// mock_server.one_time_keys().fail_server(server_name!("bob.org"));
+    ///
+    /// let request = claim_keys::v3::Request::new(
+    ///     [(
+    ///         owned_user_id!("@bob:bob.org"),
+    ///         [(owned_device_id!("BOBDEVICE"), OneTimeKeyAlgorithm::SignedCurve25519)].into(),
+    ///     )]
+    ///     .into(),
+    /// );
+    /// let response = client.send(request).await?;
+    ///
+    /// assert!(response.one_time_keys.is_empty());
+    /// assert!(response.failures.contains_key("bob.org"));
+    ///
+    /// let claims = mock_server.one_time_keys().claims();
+    /// assert_eq!(claims.len(), 1);
+    /// assert_eq!(claims[0].user_id, user_id!("@bob:bob.org"));
+    /// assert_eq!(claims[0].device_id, device_id!("BOBDEVICE"));
+    /// assert_eq!(claims[0].outcome, KeyClaimOutcome::FederationFailure);
+    /// # anyhow::Ok(()) });
+    /// ```
+    pub fn mock_keys_claim(&self) -> MockEndpoint<'_, KeysClaimEndpoint> {
+        let mock = Mock::given(method("POST")).and(path_regex(r"/_matrix/client/v3/keys/claim"));
+        MockEndpoint {
+            mock,
+            server: &self.server,
+            endpoint: KeysClaimEndpoint { one_time_keys: self.one_time_keys.clone() },
+        }
+    }
+
     /// Create a prebuilt mock for getting the room members in a room.
     ///
     /// # Examples
@@ -1942,3 +2037,250 @@ impl<'a> MockEndpoint<'a, BanUserEndpoint> {
         MatrixMock { server: self.server, mock }
     }
 }
+
+/// A prebuilt mock for `POST /keys/upload` request.
+pub struct KeysUploadEndpoint {
+    one_time_keys: OneTimeKeys,
+}
+
+impl<'a> MockEndpoint<'a, KeysUploadEndpoint> {
+    /// Returns a successful response, after storing the uploaded one-time and
+    /// fallback keys.
+    pub fn ok(self) -> MatrixMock<'a> {
+        let one_time_keys = self.endpoint.one_time_keys;
+        self.respond_with(move |request: &Request| {
+            let body: Value = request.body_json().expect("The body should be a JSON body");
+            ResponseTemplate::new(200).set_body_json(one_time_keys.upload(&body))
+        })
+    }
+}
+
+/// A prebuilt mock for `POST /keys/claim` request.
+pub struct KeysClaimEndpoint {
+    one_time_keys: OneTimeKeys,
+}
+
+impl<'a> MockEndpoint<'a, KeysClaimEndpoint> {
+    /// Returns a response serving the uploaded one-time keys, or the fallback
+    /// keys when a device ran out of one-time keys.
+    ///
+    /// Failures can be simulated with [`OneTimeKeys::exhaust()`] and
+    /// [`OneTimeKeys::fail_server()`].
+    pub fn ok(self) -> MatrixMock<'a> {
+        let one_time_keys = self.endpoint.one_time_keys;
+        self.respond_with(move |request: &Request| {
+            let body: Value = request.body_json().expect("The body should be a JSON body");
+            ResponseTemplate::new(200).set_body_json(one_time_keys.claim(&body))
+        })
+    }
+}
+
+/// The one-time keys known by a [`MatrixMockServer`].
+///
+/// See [`MatrixMockServer::one_time_keys()`].
+#[derive(Clone, Default)]
+pub struct OneTimeKeys {
+    inner: Arc<Mutex<OneTimeKeysInner>>,
+}
+
+#[derive(Default)]
+struct OneTimeKeysInner {
+    /// The unclaimed one-time keys of each device, by key ID.
+    one_time_keys: BTreeMap<(OwnedUserId, OwnedDeviceId), BTreeMap<String, Value>>,
+
+    /// The fallback key of each device.
+    fallback_keys: BTreeMap<(OwnedUserId, OwnedDeviceId), (String, Value)>,
+
+    /// The servers for which claims fail.
+    failing_servers: BTreeSet<OwnedServerName>,
+
+    /// All the claims received so far.
+    claims: Vec<KeyClaim>,
+}
+
+/// A one-time key claimed for a device with the `/keys/claim` mock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyClaim {
+    /// The owner of the device.
+    pub user_id: OwnedUserId,
+    /// The device for which a key was claimed.
+    pub device_id: OwnedDeviceId,
+    /// What the claim returned.
+    pub outcome: KeyClaimOutcome,
+}
+
+/// The outcome of a [`KeyClaim`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyClaimOutcome {
+    /// The one-time key with the given ID was returned.
+    OneTimeKey(String),
+    /// The device ran out of one-time keys, the fallback key with the given ID
+    /// was returned.
+    FallbackKey(String),
+    /// The device ran out of one-time keys and has no fallback key.
+    Exhausted,
+    /// The server of the user failed to respond.
+    FederationFailure,
+}
+
+impl OneTimeKeys {
+    /// The number of unclaimed one-time keys of the given device.
+    pub fn count(&self, user_id: &UserId, device_id: &DeviceId) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .one_time_keys
+            .get(&(user_id.to_owned(), device_id.to_owned()))
+            .map_or(0, |keys| keys.len())
+    }
+
+    /// Remove all the one-time keys and the fallback key of the given device,
+    /// so that claims for it return nothing.
+    pub fn exhaust(&self, user_id: &UserId, device_id: &DeviceId) {
+        let mut inner = self.inner.lock().unwrap();
+        let device = (user_id.to_owned(), device_id.to_owned());
+        inner.one_time_keys.remove(&device);
+        inner.fallback_keys.remove(&device);
+    }
+
+    /// Make claims for the users of the given server fail, as if the server
+    /// couldn't be reached over federation.
+    pub fn fail_server(&self, server_name: &ServerName) {
+        self.inner.lock().unwrap().failing_servers.insert(server_name.to_owned());
+    }
+
+    /// Make claims for the users of the given server succeed again.
+    pub fn restore_server(&self, server_name: &ServerName) {
+        self.inner.lock().unwrap().failing_servers.remove(server_name);
+    }
+
+    /// All the claims received so far, in order.
+    pub fn claims(&self) -> Vec<KeyClaim> {
+        self.inner.lock().unwrap().claims.clone()
+    }
+
+    /// The number of claims received so far for the given device.
+    pub fn claim_count(&self, user_id: &UserId, device_id: &DeviceId) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.claims.iter().filter(|c| *c.user_id == *user_id && *c.device_id == *device_id).count()
+    }
+
+    /// Store the keys of a `/keys/upload` request and build the response.
+    fn upload(&self, body: &Value) -> Value {
+        let mut inner = self.inner.lock().unwrap();
+
+        // The owner of the keys is in the device keys, if they are uploaded,
+        // otherwise in the signatures of the keys.
+        let device_owner = body.get("device_keys").and_then(|device_keys| {
+            let user_id = device_keys.get("user_id")?.as_str()?;
+            let device_id = device_keys.get("device_id")?.as_str()?;
+            Some((UserId::parse(user_id).ok()?, device_id.into()))
+        });
+        let owner = |key: &Value| device_owner.clone().or_else(|| key_signer(key));
+
+        let mut owners = BTreeSet::new();
+
+        if let Some(keys) = body.get("one_time_keys").and_then(Value::as_object) {
+            for (key_id, key) in keys {
+                let Some(device) = owner(key) else { continue };
+                inner
+                    .one_time_keys
+                    .entry(device.clone())
+                    .or_default()
+                    .insert(key_id.clone(), key.clone());
+                owners.insert(device);
+            }
+        }
+
+        if let Some(keys) = body.get("fallback_keys").and_then(Value::as_object) {
+            for (key_id, key) in keys {
+                let Some(device) = owner(key) else { continue };
+                inner.fallback_keys.insert(device.clone(), (key_id.clone(), key.clone()));
+                owners.insert(device);
+            }
+        }
+
+        let mut counts = BTreeMap::<&str, usize>::new();
+
+        if let Some(device) = device_owner.or_else(|| owners.pop_first()) {
+            for key_id in inner.one_time_keys.get(&device).into_iter().flat_map(|keys| keys.keys())
+            {
+                *counts.entry(key_algorithm(key_id)).or_default() += 1;
+            }
+        }
+
+        json!({ "one_time_key_counts": counts })
+    }
+
+    /// Serve the one-time keys of a `/keys/claim` request.
+    fn claim(&self, body: &Value) -> Value {
+        let mut inner = self.inner.lock().unwrap();
+        let mut one_time_keys = BTreeMap::<String, BTreeMap<String, Value>>::new();
+        let mut failures = BTreeMap::<String, Value>::new();
+
+        let requested = body.get("one_time_keys").and_then(Value::as_object);
+
+        for (user_id, devices) in requested.into_iter().flatten() {
+            let Ok(user_id) = UserId::parse(user_id) else { continue };
+            let Some(devices) = devices.as_object() else { continue };
+
+            for (device_id, algorithm) in devices {
+                let device_id: OwnedDeviceId = device_id.as_str().into();
+                let algorithm = algorithm.as_str().unwrap_or_default();
+                let device = (user_id.clone(), device_id.clone());
+
+                let outcome = if inner.failing_servers.contains(user_id.server_name()) {
+                    failures.insert(
+                        user_id.server_name().to_string(),
+                        json!({ "errcode": "M_UNKNOWN", "error": "Server unreachable" }),
+                    );
+                    KeyClaimOutcome::FederationFailure
+                } else {
+                    let one_time_key = inner.one_time_keys.get_mut(&device).and_then(|keys| {
+                        let key_id = keys.keys().find(|id| key_algorithm(id) == algorithm)?.clone();
+                        keys.remove_entry(&key_id)
+                    });
+
+                    // Fallback keys are reused until a new one is uploaded.
+                    let (key, outcome) = match one_time_key {
+                        Some((key_id, key)) => {
+                            (Some((key_id.clone(), key)), KeyClaimOutcome::OneTimeKey(key_id))
+                        }
+                        None => match inner.fallback_keys.get(&device) {
+                            Some((key_id, key)) if key_algorithm(key_id) == algorithm => (
+                                Some((key_id.clone(), key.clone())),
+                                KeyClaimOutcome::FallbackKey(key_id.clone()),
+                            ),
+                            _ => (None, KeyClaimOutcome::Exhausted),
+                        },
+                    };
+
+                    if let Some((key_id, key)) = key {
+                        let keys = serde_json::Map::from_iter([(key_id, key)]);
+                        one_time_keys
+                            .entry(user_id.to_string())
+                            .or_default()
+                            .insert(device_id.to_string(), Value::Object(keys));
+                    }
+
+                    outcome
+                };
+
+                inner.claims.push(KeyClaim { user_id: user_id.clone(), device_id, outcome });
+            }
+        }
+
+        json!({ "one_time_keys": one_time_keys, "failures": failures })
+    }
+}
+
+/// The algorithm of a key, from its ID, e.g. `signed_curve25519`.
+fn key_algorithm(key_id: &str) -> &str {
+    key_id.split_once(':').map_or(key_id, |(algorithm, _)| algorithm)
+}
+
+/// The device that signed a key.
+fn key_signer(key: &Value) -> Option<(OwnedUserId, OwnedDeviceId)> {
+    let (user_id, signatures) = key.get("signatures")?.as_object()?.iter().next()?;
+    let device_id = signatures.as_object()?.keys().find_map(|id| id.strip_prefix("ed25519:"))?;
+    Some((UserId::parse(user_id).ok()?, device_id.into()))
+}
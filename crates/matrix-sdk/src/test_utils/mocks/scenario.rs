@@ -0,0 +1,294 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scenarios, ordered sequences of requests expected by a
+//! [`MatrixMockServer`](super::MatrixMockServer).
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+};
+#[cfg(feature = "testing-time-control")]
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path_regex, MethodExactMatcher, PathRegexMatcher},
+    Match, Mock, MockGuard, MockServer, Request, Respond, ResponseTemplate,
+};
+
+/// A sequence of requests that the server expects to receive in order, with
+/// their canned responses, created with
+/// [`MatrixMockServer::scenario()`](super::MatrixMockServer::scenario).
+///
+/// The steps of a scenario take precedence over the other mocks mounted on the
+/// server. A request that matches a step, but not the next one, is answered
+/// with a `404` and reported as unexpected by [`ScenarioGuard::verify()`], as
+/// are the requests that are not handled by any mock.
+///
+/// # Examples
+///
+/// ```
+/// # tokio_test::block_on(async {
+/// use matrix_sdk::{ruma::{room_alias_id, room_id}, test_utils::mocks::MatrixMockServer};
+/// use serde_json::json;
+/// use wiremock::ResponseTemplate;
+///
+/// let mock_server = MatrixMockServer::new().await;
+/// let client = mock_server.client_builder().build().await;
+///
+/// let scenario = mock_server
+///     .scenario()
+///     .then(
+///         "GET",
+///         r"^/_matrix/client/v3/directory/room/",
+///         ResponseTemplate::new(404).set_body_json(json!({ "errcode": "M_NOT_FOUND" })),
+///     )
+///     .then_ok("PUT", r"^/_matrix/client/v3/directory/room/", json!({}))
+///     .start()
+///     .await;
+///
+/// let alias = room_alias_id!("#alias:localhost");
+/// assert!(client.is_room_alias_available(alias).await?);
+/// client.create_room_alias(alias, room_id!("!room:localhost")).await?;
+///
+/// scenario.verify();
+/// # anyhow::Ok(()) });
+/// ```
+pub struct Scenario<'a> {
+    server: &'a MockServer,
+    steps: VecDeque<ScenarioStep>,
+}
+
+impl<'a> Scenario<'a> {
+    pub(super) fn new(server: &'a MockServer) -> Self {
+        Self { server, steps: VecDeque::new() }
+    }
+
+    /// Expect a request with the given method, and a path matching the given
+    /// regular expression, after the previous steps, and answer it with the
+    /// given response.
+    pub fn then(mut self, http_method: &str, path: &str, response: ResponseTemplate) -> Self {
+        self.steps.push_back(ScenarioStep {
+            description: format!("{http_method} {path}"),
+            method: method(http_method),
+            path: path_regex(path),
+            response,
+        });
+        self
+    }
+
+    /// Like [`Self::then()`], with a successful response with the given JSON
+    /// body.
+    pub fn then_ok(self, http_method: &str, path: &str, body: Value) -> Self {
+        self.then(http_method, path, ResponseTemplate::new(200).set_body_json(body))
+    }
+
+    /// Mount the scenario on the server.
+    ///
+    /// The scenario is active as long as the returned [`ScenarioGuard`] is not
+    /// dropped.
+    pub async fn start(self) -> ScenarioGuard {
+        let state = Arc::new(Mutex::new(ScenarioState { steps: self.steps, ..Default::default() }));
+
+        let steps_guard = Mock::given(PendingStepMatcher(state.clone()))
+            .respond_with(ScenarioResponder(state.clone()))
+            .with_priority(1)
+            .named("scenario steps")
+            .mount_as_scoped(self.server)
+            .await;
+
+        let fallback_guard = Mock::given(AnyRequestMatcher)
+            .respond_with(UnexpectedResponder(state.clone()))
+            .with_priority(u8::MAX)
+            .named("scenario fallback")
+            .mount_as_scoped(self.server)
+            .await;
+
+        ScenarioGuard {
+            state,
+            #[cfg(feature = "testing-time-control")]
+            time_paused: AtomicBool::new(false),
+            _guards: [steps_guard, fallback_guard],
+        }
+    }
+}
+
+/// A running [`Scenario`].
+///
+/// Dropping it unmounts the scenario from the server.
+pub struct ScenarioGuard {
+    state: Arc<Mutex<ScenarioState>>,
+    #[cfg(feature = "testing-time-control")]
+    time_paused: AtomicBool,
+    _guards: [MockGuard; 2],
+}
+
+impl ScenarioGuard {
+    /// Check that all the steps of the scenario happened, and that no
+    /// unexpected request was received.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a report of the missing steps and of the unexpected
+    /// requests, if any.
+    #[track_caller]
+    pub fn verify(&self) {
+        let state = self.state.lock().unwrap();
+
+        if state.steps.is_empty() && state.unexpected.is_empty() {
+            return;
+        }
+
+        let mut report = format!(
+            "The scenario wasn't followed, {} step(s) out of {} happened.\n",
+            state.completed,
+            state.completed + state.steps.len()
+        );
+
+        if !state.steps.is_empty() {
+            report.push_str("Missing steps:\n");
+            for step in &state.steps {
+                writeln!(report, "  - {}", step.description).unwrap();
+            }
+        }
+
+        if !state.unexpected.is_empty() {
+            report.push_str("Unexpected requests:\n");
+            for request in &state.unexpected {
+                writeln!(report, "  - {request}").unwrap();
+            }
+        }
+
+        panic!("{report}");
+    }
+
+    /// Advance the clock of the Tokio runtime by the given duration, to fire
+    /// the timers of the SDK, like retry backoffs, without waiting for them.
+    ///
+    /// The first call pauses the time, which then only advances with this
+    /// method, or automatically when the runtime has nothing else to do. As the
+    /// latter fires the timeouts of the requests sent to the server, the time
+    /// should be resumed with [`Self::resume_time()`] before awaiting requests.
+    ///
+    /// This only works on a current-thread runtime, and doesn't affect the
+    /// code that reads the system time, like [`Instant`](std::time::Instant).
+    #[cfg(feature = "testing-time-control")]
+    pub async fn advance_time(&self, duration: Duration) {
+        if !self.time_paused.swap(true, Ordering::SeqCst) {
+            tokio::time::pause();
+        }
+
+        tokio::time::advance(duration).await;
+    }
+
+    /// Resume the time of the Tokio runtime, if it was paused by
+    /// [`Self::advance_time()`].
+    #[cfg(feature = "testing-time-control")]
+    pub fn resume_time(&self) {
+        if self.time_paused.swap(false, Ordering::SeqCst) {
+            tokio::time::resume();
+        }
+    }
+}
+
+#[cfg(feature = "testing-time-control")]
+impl Drop for ScenarioGuard {
+    fn drop(&mut self) {
+        self.resume_time();
+    }
+}
+
+/// A step of a [`Scenario`].
+struct ScenarioStep {
+    description: String,
+    method: MethodExactMatcher,
+    path: PathRegexMatcher,
+    response: ResponseTemplate,
+}
+
+impl ScenarioStep {
+    fn matches(&self, request: &Request) -> bool {
+        self.method.matches(request) && self.path.matches(request)
+    }
+}
+
+#[derive(Default)]
+struct ScenarioState {
+    /// The steps that didn't happen yet, in order.
+    steps: VecDeque<ScenarioStep>,
+
+    /// The number of steps that happened.
+    completed: usize,
+
+    /// The requests that didn't match the next step.
+    unexpected: Vec<String>,
+}
+
+impl ScenarioState {
+    fn record_unexpected(&mut self, request: &Request) -> ResponseTemplate {
+        self.unexpected.push(format!("{} {}", request.method, request.url.path()));
+        ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_UNRECOGNIZED",
+            "error": "Unexpected request in the scenario",
+        }))
+    }
+}
+
+/// Matches the requests that match any of the steps that didn't happen yet.
+struct PendingStepMatcher(Arc<Mutex<ScenarioState>>);
+
+impl Match for PendingStepMatcher {
+    fn matches(&self, request: &Request) -> bool {
+        self.0.lock().unwrap().steps.iter().any(|step| step.matches(request))
+    }
+}
+
+/// Answers the requests matching the next step, and reports the others.
+struct ScenarioResponder(Arc<Mutex<ScenarioState>>);
+
+impl Respond for ScenarioResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut state = self.0.lock().unwrap();
+
+        if state.steps.front().is_some_and(|step| step.matches(request)) {
+            let step = state.steps.pop_front().expect("there is a next step");
+            state.completed += 1;
+            step.response
+        } else {
+            state.record_unexpected(request)
+        }
+    }
+}
+
+/// Reports the requests that aren't handled by any mock.
+struct UnexpectedResponder(Arc<Mutex<ScenarioState>>);
+
+impl Respond for UnexpectedResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        self.0.lock().unwrap().record_unexpected(request)
+    }
+}
+
+struct AnyRequestMatcher;
+
+impl Match for AnyRequestMatcher {
+    fn matches(&self, _request: &Request) -> bool {
+        true
+    }
+}
@@ -0,0 +1,304 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sending and receiving custom to-device messages.
+
+use std::{collections::BTreeMap, future::Future};
+
+#[cfg(feature = "e2e-encryption")]
+use std::collections::BTreeSet;
+
+use matrix_sdk_base::{SendOutsideWasm, SyncOutsideWasm};
+#[cfg(feature = "e2e-encryption")]
+use ruma::OwnedDeviceId;
+use ruma::{
+    api::client::to_device::send_event_to_device::v3::Request as RumaToDeviceRequest,
+    events::{AnyToDeviceEvent, AnyToDeviceEventContent, ToDeviceEventType},
+    serde::Raw,
+    to_device::DeviceIdOrAllDevices,
+    OwnedUserId, TransactionId,
+};
+#[cfg(feature = "e2e-encryption")]
+use tracing::warn;
+use tracing::{instrument, trace};
+
+#[cfg(feature = "e2e-encryption")]
+use crate::encryption::{identities::Device, OlmError};
+use crate::{event_handler::EventHandlerHandle, Client, Result};
+
+/// The messages of a to-device request, keyed by recipient user and device.
+pub type ToDeviceMessages =
+    BTreeMap<OwnedUserId, BTreeMap<DeviceIdOrAllDevices, Raw<AnyToDeviceEventContent>>>;
+
+impl Client {
+    /// Send a to-device event to a set of devices, as is.
+    ///
+    /// The messages are sent in clear text, use
+    /// [`Client::send_encrypted_to_device()`] if they should be encrypted for
+    /// each of the recipient devices.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event, which must be the same for all
+    ///   of the messages.
+    ///
+    /// * `messages` - The content to send, for each recipient user and device.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::collections::BTreeMap;
+    /// # use matrix_sdk::{ruma::{serde::Raw, to_device::DeviceIdOrAllDevices, user_id}, Client};
+    /// # use serde_json::json;
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// let content = Raw::new(&json!({ "cursor": 42 }))?.cast();
+    /// let messages = BTreeMap::from([(
+    ///     user_id!("@alice:example.org").to_owned(),
+    ///     BTreeMap::from([(DeviceIdOrAllDevices::AllDevices, content)]),
+    /// )]);
+    ///
+    /// client.send_to_device(&"org.example.cursor".into(), messages).await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip(self, messages))]
+    pub async fn send_to_device(
+        &self,
+        event_type: &ToDeviceEventType,
+        messages: ToDeviceMessages,
+    ) -> Result<()> {
+        if messages.is_empty() {
+            trace!("No recipients, not sending anything");
+            return Ok(());
+        }
+
+        let request =
+            RumaToDeviceRequest::new_raw(event_type.clone(), TransactionId::new(), messages);
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    /// Encrypt a to-device event for each of the given devices, and send it.
+    ///
+    /// Olm sessions are established with the devices we don't share one with
+    /// yet, by claiming one-time keys on the homeserver. The recipients then
+    /// receive an `m.room.encrypted` event, which is transparently decrypted
+    /// into the original event by their client.
+    ///
+    /// Returns the devices for which no Olm session could be established, and
+    /// that thus haven't received the event. This usually means that the
+    /// device has run out of one-time keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `devices` - The devices that should receive the event.
+    ///
+    /// * `event_type` - The type of the event, once decrypted.
+    ///
+    /// * `content` - The content of the event, once decrypted.
+    #[cfg(feature = "e2e-encryption")]
+    #[instrument(skip(self, devices, content))]
+    pub async fn send_encrypted_to_device(
+        &self,
+        devices: &[Device],
+        event_type: &str,
+        content: Raw<AnyToDeviceEventContent>,
+    ) -> Result<Vec<(OwnedUserId, OwnedDeviceId)>> {
+        let users: BTreeSet<_> = devices.iter().map(|device| device.user_id()).collect();
+        self.claim_one_time_keys(users.into_iter()).await?;
+
+        let content = content.deserialize_as::<serde_json::Value>()?;
+
+        let mut messages = ToDeviceMessages::new();
+        let mut failures = Vec::new();
+
+        for device in devices {
+            match device.inner.encrypt_event_raw(event_type, &content).await {
+                Ok(encrypted) => {
+                    messages.entry(device.user_id().to_owned()).or_default().insert(
+                        DeviceIdOrAllDevices::DeviceId(device.device_id().to_owned()),
+                        encrypted.cast(),
+                    );
+                }
+
+                Err(OlmError::MissingSession) => {
+                    warn!(
+                        user_id = ?device.user_id(),
+                        device_id = ?device.device_id(),
+                        "Couldn't establish an Olm session, not sending the event to this device"
+                    );
+                    failures.push((device.user_id().to_owned(), device.device_id().to_owned()));
+                }
+
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        self.send_to_device(&ToDeviceEventType::RoomEncrypted, messages).await?;
+
+        Ok(failures)
+    }
+
+    /// Register a handler for the to-device events of a custom type.
+    ///
+    /// This is a convenience wrapper over [`Client::add_event_handler()`] for
+    /// protocols whose event types aren't known at compile time: the handler
+    /// receives the raw event, and is only called for events whose type
+    /// matches `event_type`. Encrypted to-device events are decrypted before
+    /// being dispatched, so the handler sees the type of the decrypted event.
+    ///
+    /// If the event type is known statically, a handler receiving a
+    /// [`ToDeviceEvent`](ruma::events::ToDeviceEvent) of a content type
+    /// implementing [`StaticEventContent`](ruma::events::StaticEventContent)
+    /// can be registered with [`Client::add_event_handler()`] directly
+    /// instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{ruma::events::AnyToDeviceEvent, ruma::serde::Raw, Client};
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// client.add_to_device_event_handler(
+    ///     "org.example.cursor",
+    ///     |event: Raw<AnyToDeviceEvent>, _client: Client| async move {
+    ///         println!("Received a cursor update: {}", event.json());
+    ///     },
+    /// );
+    /// # };
+    /// ```
+    pub fn add_to_device_event_handler<F, Fut>(
+        &self,
+        event_type: impl Into<String>,
+        handler: F,
+    ) -> EventHandlerHandle
+    where
+        F: Fn(Raw<AnyToDeviceEvent>, Client) -> Fut
+            + Clone
+            + SendOutsideWasm
+            + SyncOutsideWasm
+            + 'static,
+        Fut: Future<Output = ()> + SendOutsideWasm + 'static,
+    {
+        let event_type = event_type.into();
+
+        self.add_event_handler(move |event: Raw<AnyToDeviceEvent>, client: Client| {
+            let handler = handler.clone();
+            let matches =
+                event.get_field::<String>("type").ok().flatten().is_some_and(|ty| ty == event_type);
+
+            async move {
+                if matches {
+                    handler(event, client).await;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            atomic::{AtomicU8, Ordering::SeqCst},
+            Arc,
+        },
+    };
+
+    use matrix_sdk_test::{async_test, SyncResponseBuilder};
+    use ruma::{events::AnyToDeviceEvent, serde::Raw, to_device::DeviceIdOrAllDevices, user_id};
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method, path_regex},
+        Mock, ResponseTemplate,
+    };
+
+    use crate::test_utils::{logged_in_client, logged_in_client_with_server};
+
+    #[async_test]
+    async fn test_send_to_device() {
+        let (client, server) = logged_in_client_with_server().await;
+
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/r0/sendToDevice/org\.example\.cursor/.*"))
+            .and(body_partial_json(json!({
+                "messages": {
+                    "@alice:example.org": {
+                        "*": { "cursor": 42 },
+                    },
+                },
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let messages = BTreeMap::from([(
+            user_id!("@alice:example.org").to_owned(),
+            BTreeMap::from([(
+                DeviceIdOrAllDevices::AllDevices,
+                Raw::new(&json!({ "cursor": 42 })).unwrap().cast(),
+            )]),
+        )]);
+
+        client.send_to_device(&"org.example.cursor".into(), messages).await.unwrap();
+
+        // Nothing is sent without recipients.
+        client.send_to_device(&"org.example.cursor".into(), BTreeMap::new()).await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_custom_to_device_event_handler() {
+        let client = logged_in_client(None).await;
+
+        let counter = Arc::new(AtomicU8::new(0));
+        client.add_to_device_event_handler("org.example.cursor", {
+            let counter = counter.clone();
+            move |event: Raw<AnyToDeviceEvent>, _| {
+                let counter = counter.clone();
+                async move {
+                    assert_eq!(
+                        event.get_field::<String>("type").unwrap().unwrap(),
+                        "org.example.cursor"
+                    );
+                    counter.fetch_add(1, SeqCst);
+                }
+            }
+        });
+
+        let mut response = SyncResponseBuilder::new().build_sync_response();
+        response.to_device.events = vec![
+            Raw::new(&json!({
+                "type": "org.example.cursor",
+                "sender": "@alice:example.org",
+                "content": { "cursor": 42 },
+            }))
+            .unwrap()
+            .cast(),
+            Raw::new(&json!({
+                "type": "org.example.other",
+                "sender": "@alice:example.org",
+                "content": {},
+            }))
+            .unwrap()
+            .cast(),
+        ];
+
+        client.process_sync(response).await.unwrap();
+
+        assert_eq!(counter.load(SeqCst), 1);
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The TURN servers to use for VoIP calls.
+//!
+//! See [`Client::turn_servers()`] and [`Client::subscribe_to_turn_servers()`].
+//!
+//! [`Client::turn_servers()`]: crate::Client::turn_servers
+//! [`Client::subscribe_to_turn_servers()`]: crate::Client::subscribe_to_turn_servers
+
+use std::time::Duration;
+
+use ruma::{api::client::voip::get_turn_server_info, time::Instant};
+
+/// How long before their expiry the credentials are refreshed.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// How long to wait before retrying after the credentials couldn't be fetched.
+pub(crate) const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// The TURN servers to use for VoIP calls, and the credentials to access them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TurnServers {
+    /// The username to use.
+    pub username: String,
+
+    /// The password to use.
+    pub password: String,
+
+    /// The TURN URIs, e.g. `turn:turn.example.com:3478?transport=udp`.
+    pub uris: Vec<String>,
+
+    /// When the credentials expire, or `None` if they never do.
+    pub expires_at: Option<Instant>,
+
+    /// When the credentials should be refreshed.
+    refresh_at: Option<Instant>,
+}
+
+impl TurnServers {
+    /// Create TURN servers with credentials that never expire, for instance
+    /// from a static configuration of the deployment.
+    pub fn new_static(username: String, password: String, uris: Vec<String>) -> Self {
+        Self { username, password, uris, expires_at: None, refresh_at: None }
+    }
+
+    /// Create TURN servers from the response of the homeserver, received at
+    /// `now`.
+    pub(crate) fn from_response(
+        response: get_turn_server_info::v3::Response,
+        now: Instant,
+    ) -> Self {
+        let ttl = response.ttl;
+        // Don't wait until the last moment, but don't refresh short-lived
+        // credentials continuously either.
+        let margin = REFRESH_MARGIN.min(ttl / 2);
+
+        Self {
+            username: response.username,
+            password: response.password,
+            uris: response.uris,
+            expires_at: Some(now + ttl),
+            refresh_at: Some(now + (ttl - margin)),
+        }
+    }
+
+    /// Whether the credentials should be refreshed at the given time.
+    pub(crate) fn needs_refresh(&self, now: Instant) -> bool {
+        self.refresh_at.is_some_and(|refresh_at| now >= refresh_at)
+    }
+
+    /// How long until the credentials should be refreshed, or `None` if they
+    /// never expire.
+    pub(crate) fn refresh_delay(&self, now: Instant) -> Option<Duration> {
+        self.refresh_at.map(|refresh_at| refresh_at.saturating_duration_since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ruma::{api::client::voip::get_turn_server_info, time::Instant};
+
+    use super::TurnServers;
+
+    fn response(ttl: Duration) -> get_turn_server_info::v3::Response {
+        get_turn_server_info::v3::Response::new(
+            "user".to_owned(),
+            "pass".to_owned(),
+            vec!["turn:turn.example.org".to_owned()],
+            ttl,
+        )
+    }
+
+    #[test]
+    fn test_refresh_before_expiry() {
+        let now = Instant::now();
+        let servers = TurnServers::from_response(response(Duration::from_secs(3600)), now);
+
+        assert_eq!(servers.expires_at, Some(now + Duration::from_secs(3600)));
+        assert_eq!(servers.refresh_delay(now), Some(Duration::from_secs(3540)));
+        assert!(!servers.needs_refresh(now + Duration::from_secs(3539)));
+        assert!(servers.needs_refresh(now + Duration::from_secs(3540)));
+    }
+
+    #[test]
+    fn test_short_lived_credentials() {
+        let now = Instant::now();
+        let servers = TurnServers::from_response(response(Duration::from_secs(30)), now);
+
+        assert_eq!(servers.refresh_delay(now), Some(Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn test_static_credentials() {
+        let servers = TurnServers::new_static(
+            "user".to_owned(),
+            "pass".to_owned(),
+            vec!["turn:turn.example.org".to_owned()],
+        );
+
+        let now = Instant::now();
+        assert!(!servers.needs_refresh(now + Duration::from_secs(365 * 24 * 60 * 60)));
+        assert_eq!(servers.refresh_delay(now), None);
+    }
+}
@@ -0,0 +1,537 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Previews of URLs, generated by the homeserver, and the per-room settings to
+//! enable or disable them.
+
+use std::time::Duration;
+
+use matrix_sdk_base::{
+    CachedUrlPreview, StateStoreDataKey, StateStoreDataValue, UrlPreview, UrlPreviewImage,
+};
+use ruma::{
+    api::client::{authenticated_media, media},
+    assign,
+    events::{RoomAccountDataEventType, StateEventType},
+    serde::Raw,
+    MilliSecondsSinceUnixEpoch, OwnedMxcUri, UInt,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{debug, instrument};
+use url::Url;
+
+use crate::{
+    deserialized_responses::RawAnySyncOrStrippedState, media::MediaError, Client, Result, Room,
+};
+
+/// How long a preview cached by [`Client::get_url_preview`] is considered
+/// fresh.
+const URL_PREVIEW_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The maximum number of previews cached by [`Client::get_url_preview`]. The
+/// least recently used ones are evicted first.
+const MAX_CACHED_URL_PREVIEWS: usize = 500;
+
+/// How many times cached previews can be used before their new order is
+/// persisted.
+const MAX_UNSAVED_URL_PREVIEW_USES: usize = 20;
+
+/// The type of the room account data and state event controlling whether URL
+/// previews are enabled in a room.
+const URL_PREVIEWS_EVENT_TYPE: &str = "org.matrix.room.preview_urls";
+
+/// The maximum number of characters kept from the title and the site name of
+/// a preview.
+const MAX_TITLE_LEN: usize = 300;
+
+/// The maximum number of characters kept from the description of a preview.
+const MAX_DESCRIPTION_LEN: usize = 1000;
+
+/// The content of the [`URL_PREVIEWS_EVENT_TYPE`] events.
+#[derive(Deserialize)]
+struct UrlPreviewsContent {
+    #[serde(default)]
+    disable: bool,
+}
+
+/// The OpenGraph data returned by the homeserver for a URL.
+#[derive(Default, Deserialize)]
+struct OpenGraphData {
+    #[serde(rename = "og:title")]
+    title: Option<String>,
+    #[serde(rename = "og:description")]
+    description: Option<String>,
+    #[serde(rename = "og:site_name")]
+    site_name: Option<String>,
+    #[serde(rename = "og:image")]
+    image: Option<String>,
+    #[serde(rename = "og:image:width")]
+    image_width: Option<NumberOrString>,
+    #[serde(rename = "og:image:height")]
+    image_height: Option<NumberOrString>,
+    #[serde(rename = "og:image:type")]
+    image_type: Option<String>,
+    #[serde(rename = "matrix:image:size")]
+    image_size: Option<NumberOrString>,
+}
+
+/// A number that some homeservers send as a string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(UInt),
+    String(String),
+}
+
+impl NumberOrString {
+    fn into_uint(self) -> Option<UInt> {
+        match self {
+            Self::Number(n) => Some(n),
+            Self::String(s) => s.trim().parse().ok(),
+        }
+    }
+}
+
+impl OpenGraphData {
+    /// Build a [`UrlPreview`] out of this data, only keeping what can be safely
+    /// displayed.
+    ///
+    /// The texts are stripped of control characters and truncated, and the
+    /// image is only kept if it's in the media repository of the homeserver,
+    /// so that displaying the preview doesn't leak anything to the website.
+    fn into_preview(self) -> UrlPreview {
+        let Self {
+            title,
+            description,
+            site_name,
+            image,
+            image_width,
+            image_height,
+            image_type,
+            image_size,
+        } = self;
+
+        let image = image
+            .map(|uri| OwnedMxcUri::from(uri.trim()))
+            .filter(|uri| uri.is_valid())
+            .map(|uri| UrlPreviewImage {
+                uri,
+                width: image_width.and_then(NumberOrString::into_uint),
+                height: image_height.and_then(NumberOrString::into_uint),
+                size: image_size.and_then(NumberOrString::into_uint),
+                mimetype: image_type.and_then(|mimetype| sanitize_text(mimetype, MAX_TITLE_LEN)),
+            });
+
+        UrlPreview {
+            title: title.and_then(|title| sanitize_text(title, MAX_TITLE_LEN)),
+            description: description
+                .and_then(|description| sanitize_text(description, MAX_DESCRIPTION_LEN)),
+            site_name: site_name.and_then(|site_name| sanitize_text(site_name, MAX_TITLE_LEN)),
+            image,
+        }
+    }
+}
+
+/// Remove the control characters and the surrounding whitespace of a text, and
+/// truncate it to the given number of characters.
+///
+/// Returns `None` if nothing is left.
+fn sanitize_text(text: String, max_len: usize) -> Option<String> {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| !c.is_control())
+        .collect();
+    let text: String = text.trim().chars().take(max_len).collect();
+    let text = text.trim_end();
+
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// Whether a cached preview is older than [`URL_PREVIEW_CACHE_TTL`].
+fn is_url_preview_stale(preview: &CachedUrlPreview, now: MilliSecondsSinceUnixEpoch) -> bool {
+    let age = u64::from(now.0).saturating_sub(preview.fetched_at.0.into());
+    Duration::from_millis(age) >= URL_PREVIEW_CACHE_TTL
+}
+
+/// The URLs of the cached previews, from the least to the most recently used.
+#[derive(Debug)]
+pub(crate) struct UrlPreviewLru {
+    urls: Vec<String>,
+
+    /// How many times cached previews were used since the order was last
+    /// persisted.
+    unsaved_uses: usize,
+}
+
+impl Client {
+    /// Get a preview of the given URL, generated by the homeserver.
+    ///
+    /// Previews are cached in the state store for a day, up to 500 of them,
+    /// the least recently used ones being evicted first. The returned preview
+    /// is sanitized: its texts are stripped of control characters and
+    /// truncated, and its image is always an MXC URI, so it can be displayed
+    /// without contacting the website.
+    ///
+    /// This doesn't check whether URL previews are enabled in a room, use
+    /// [`Room::get_url_preview()`] for URLs found in the messages of a room.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to preview. Only `http` and `https` URLs are
+    ///   supported.
+    ///
+    /// * `ts` - The preferred point in time to return a preview for, for
+    ///   instance the time the message containing the URL was sent at.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let url = Url::parse("https://matrix.org")?;
+    /// let preview = client.get_url_preview(&url, None).await?;
+    ///
+    /// if let Some(title) = preview.title {
+    ///     println!("{url} is titled {title}");
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip_all, fields(url = %url))]
+    pub async fn get_url_preview(
+        &self,
+        url: &Url,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<UrlPreview> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(MediaError::UnsupportedPreviewUrl.into());
+        }
+
+        let key = StateStoreDataKey::UrlPreview(url.as_str());
+        let now = MilliSecondsSinceUnixEpoch::now();
+
+        if let Some(cached) =
+            self.store().get_kv_data(key).await?.and_then(|value| value.into_url_preview())
+        {
+            if cached.ts == ts && !is_url_preview_stale(&cached, now) {
+                debug!("Using the cached preview");
+                self.touch_cached_url_preview(url.as_str()).await?;
+                return Ok(cached.preview);
+            }
+        }
+
+        let (use_auth, request_config) = self.media().authenticated_media_config().await?;
+
+        let data = if use_auth {
+            let request = assign!(
+                authenticated_media::get_media_preview::v1::Request::new(url.to_string()),
+                { ts }
+            );
+            self.send(request).with_request_config(request_config).await?.data
+        } else {
+            #[allow(deprecated)]
+            let request =
+                assign!(media::get_media_preview::v3::Request::new(url.to_string()), { ts });
+            self.send(request).await?.data
+        };
+
+        let data = match data {
+            Some(data) => serde_json::from_str::<OpenGraphData>(data.get())?,
+            None => OpenGraphData::default(),
+        };
+        let preview = data.into_preview();
+
+        self.store()
+            .set_kv_data(
+                key,
+                StateStoreDataValue::UrlPreview(CachedUrlPreview {
+                    preview: preview.clone(),
+                    ts,
+                    fetched_at: now,
+                }),
+            )
+            .await?;
+        self.touch_cached_url_preview(url.as_str()).await?;
+
+        Ok(preview)
+    }
+
+    /// Mark the cached preview of the given URL as the most recently used
+    /// one, and evict the least recently used previews if there are more than
+    /// [`MAX_CACHED_URL_PREVIEWS`].
+    ///
+    /// The order is kept in memory. It's persisted right away when a preview
+    /// is added to the cache, but only every [`MAX_UNSAVED_URL_PREVIEW_USES`]
+    /// uses of previews that were already cached: losing the latest uses only
+    /// makes the eviction slightly less accurate.
+    async fn touch_cached_url_preview(&self, url: &str) -> Result<()> {
+        let mut lru = self.inner.url_preview_lru.lock().await;
+
+        if lru.is_none() {
+            let urls = self
+                .store()
+                .get_kv_data(StateStoreDataKey::CachedUrlPreviews)
+                .await?
+                .and_then(StateStoreDataValue::into_cached_url_previews)
+                .unwrap_or_default();
+
+            *lru = Some(UrlPreviewLru { urls, unsaved_uses: 0 });
+        }
+
+        let lru = lru.as_mut().expect("the order of the cached previews was loaded above");
+
+        let is_new = match lru.urls.iter().position(|cached_url| cached_url == url) {
+            Some(index) => {
+                let url = lru.urls.remove(index);
+                lru.urls.push(url);
+                false
+            }
+            None => {
+                lru.urls.push(url.to_owned());
+                true
+            }
+        };
+
+        if !is_new {
+            lru.unsaved_uses += 1;
+
+            if lru.unsaved_uses < MAX_UNSAVED_URL_PREVIEW_USES {
+                return Ok(());
+            }
+        }
+
+        let num_evicted = lru.urls.len().saturating_sub(MAX_CACHED_URL_PREVIEWS);
+        for evicted_url in lru.urls.drain(..num_evicted) {
+            debug!(url = %evicted_url, "Evicting the cached preview");
+            self.store().remove_kv_data(StateStoreDataKey::UrlPreview(&evicted_url)).await?;
+        }
+
+        self.store()
+            .set_kv_data(
+                StateStoreDataKey::CachedUrlPreviews,
+                StateStoreDataValue::CachedUrlPreviews(lru.urls.clone()),
+            )
+            .await?;
+        lru.unsaved_uses = 0;
+
+        Ok(())
+    }
+}
+
+impl Room {
+    /// Whether URL previews are enabled in this room.
+    ///
+    /// The choice of the user, stored in the room account data, takes
+    /// precedence. Otherwise, previews are disabled in encrypted rooms, since
+    /// they would let the homeserver know the URLs sent in the room, and
+    /// follow the default set by the room admins in other rooms.
+    pub async fn url_previews_enabled(&self) -> Result<bool> {
+        if let Some(raw) = self.account_data(URL_PREVIEWS_EVENT_TYPE.into()).await? {
+            if let Ok(Some(content)) = raw.get_field::<UrlPreviewsContent>("content") {
+                return Ok(!content.disable);
+            }
+        }
+
+        if self.is_encrypted().await? {
+            return Ok(false);
+        }
+
+        if let Some(RawAnySyncOrStrippedState::Sync(raw)) =
+            self.get_state_event(StateEventType::from(URL_PREVIEWS_EVENT_TYPE), "").await?
+        {
+            if let Ok(Some(content)) = raw.get_field::<UrlPreviewsContent>("content") {
+                return Ok(!content.disable);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Enable or disable URL previews in this room, for the current user.
+    ///
+    /// The choice is stored in the room account data, so it's shared with the
+    /// other clients of the user.
+    pub async fn set_url_previews_enabled(&self, enabled: bool) -> Result<()> {
+        let content = Raw::new(&json!({ "disable": !enabled }))?.cast();
+
+        self.set_account_data_raw(RoomAccountDataEventType::from(URL_PREVIEWS_EVENT_TYPE), content)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get a preview of a URL found in this room, if URL previews are enabled
+    /// in it.
+    ///
+    /// Returns `None` if URL previews are disabled, see
+    /// [`Room::url_previews_enabled()`]. Otherwise, this is the same as
+    /// [`Client::get_url_preview()`].
+    pub async fn get_url_preview(
+        &self,
+        url: &Url,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<Option<UrlPreview>> {
+        if !self.url_previews_enabled().await? {
+            debug!(room_id = %self.room_id(), "URL previews are disabled in this room");
+            return Ok(None);
+        }
+
+        self.client.get_url_preview(url, ts).await.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_base::{CachedUrlPreview, StateStoreDataKey, StateStoreDataValue, UrlPreview};
+    use matrix_sdk_test::async_test;
+    use ruma::MilliSecondsSinceUnixEpoch;
+    use serde_json::json;
+
+    use super::{
+        sanitize_text, OpenGraphData, MAX_CACHED_URL_PREVIEWS, MAX_UNSAVED_URL_PREVIEW_USES,
+    };
+    use crate::{test_utils::logged_in_client, Client};
+
+    #[test]
+    fn test_sanitize_preview() {
+        let data: OpenGraphData = serde_json::from_value(json!({
+            "og:title": "  A\u{0}title\nwith\tcontrol characters ",
+            "og:description": "",
+            "og:image": "https://example.org/tracking.png",
+            "og:image:width": "640",
+        }))
+        .unwrap();
+        let preview = data.into_preview();
+
+        assert_eq!(preview.title.as_deref(), Some("Atitle with control characters"));
+        assert_eq!(preview.description, None);
+        // Images outside of the media repository are dropped.
+        assert_eq!(preview.image, None);
+
+        let data: OpenGraphData = serde_json::from_value(json!({
+            "og:image": "mxc://example.org/abcdef",
+            "og:image:width": "640",
+            "og:image:height": 480,
+            "matrix:image:size": 12345,
+        }))
+        .unwrap();
+        let image = data.into_preview().image.unwrap();
+
+        assert_eq!(image.uri.as_str(), "mxc://example.org/abcdef");
+        assert_eq!(image.width, Some(640u32.into()));
+        assert_eq!(image.height, Some(480u32.into()));
+        assert_eq!(image.size, Some(12345u32.into()));
+    }
+
+    #[test]
+    fn test_sanitize_text_truncates() {
+        assert_eq!(sanitize_text("abcdef".to_owned(), 3).as_deref(), Some("abc"));
+        assert_eq!(sanitize_text("ab   cdef".to_owned(), 4).as_deref(), Some("ab"));
+        assert_eq!(sanitize_text(" \u{7} ".to_owned(), 10), None);
+    }
+
+    async fn cache_preview(client: &Client, url: &str) {
+        let cached = CachedUrlPreview {
+            preview: UrlPreview::default(),
+            ts: None,
+            fetched_at: MilliSecondsSinceUnixEpoch::now(),
+        };
+        client
+            .store()
+            .set_kv_data(
+                StateStoreDataKey::UrlPreview(url),
+                StateStoreDataValue::UrlPreview(cached),
+            )
+            .await
+            .unwrap();
+        client.touch_cached_url_preview(url).await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_cached_url_previews_are_bounded() {
+        let client = logged_in_client(None).await;
+        let store = client.store();
+
+        let url = |i: usize| format!("https://example.org/{i}");
+
+        for i in 0..MAX_CACHED_URL_PREVIEWS {
+            cache_preview(&client, &url(i)).await;
+        }
+
+        // The first preview is used again, so the second one is now the least
+        // recently used.
+        client.touch_cached_url_preview(&url(0)).await.unwrap();
+        cache_preview(&client, &url(MAX_CACHED_URL_PREVIEWS)).await;
+
+        assert!(store.get_kv_data(StateStoreDataKey::UrlPreview(&url(0))).await.unwrap().is_some());
+        assert!(store.get_kv_data(StateStoreDataKey::UrlPreview(&url(1))).await.unwrap().is_none());
+        assert!(store
+            .get_kv_data(StateStoreDataKey::UrlPreview(&url(MAX_CACHED_URL_PREVIEWS)))
+            .await
+            .unwrap()
+            .is_some());
+
+        let urls = store
+            .get_kv_data(StateStoreDataKey::CachedUrlPreviews)
+            .await
+            .unwrap()
+            .and_then(StateStoreDataValue::into_cached_url_previews)
+            .unwrap();
+        assert_eq!(urls.len(), MAX_CACHED_URL_PREVIEWS);
+        assert_eq!(urls.last(), Some(&url(MAX_CACHED_URL_PREVIEWS)));
+    }
+
+    async fn persisted_urls(client: &Client) -> Vec<String> {
+        client
+            .store()
+            .get_kv_data(StateStoreDataKey::CachedUrlPreviews)
+            .await
+            .unwrap()
+            .and_then(StateStoreDataValue::into_cached_url_previews)
+            .unwrap()
+    }
+
+    #[async_test]
+    async fn test_cached_url_previews_order_is_persisted_lazily() {
+        let client = logged_in_client(None).await;
+
+        // New previews are persisted right away.
+        cache_preview(&client, "https://example.org/0").await;
+        cache_preview(&client, "https://example.org/1").await;
+        assert_eq!(
+            persisted_urls(&client).await,
+            ["https://example.org/0", "https://example.org/1"]
+        );
+
+        // Using a cached preview only changes the order in memory.
+        for _ in 1..MAX_UNSAVED_URL_PREVIEW_USES {
+            client.touch_cached_url_preview("https://example.org/0").await.unwrap();
+        }
+        assert_eq!(
+            persisted_urls(&client).await,
+            ["https://example.org/0", "https://example.org/1"]
+        );
+
+        // Until enough previews have been used.
+        client.touch_cached_url_preview("https://example.org/0").await.unwrap();
+        assert_eq!(
+            persisted_urls(&client).await,
+            ["https://example.org/1", "https://example.org/0"]
+        );
+    }
+}
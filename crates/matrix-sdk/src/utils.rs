@@ -16,6 +16,7 @@
 
 #[cfg(feature = "e2e-encryption")]
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 #[cfg(feature = "e2e-encryption")]
 use futures_core::Stream;
@@ -237,6 +238,15 @@ pub fn formatted_body_from(
     }
 }
 
+/// Wait for the given duration, on both native platforms and WebAssembly.
+pub(crate) async fn sleep(delay: Duration) {
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::TimeoutFuture::new(delay.as_millis().try_into().unwrap_or(u32::MAX)).await;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(delay).await;
+}
+
 #[cfg(test)]
 mod test {
     #[cfg(feature = "markdown")]
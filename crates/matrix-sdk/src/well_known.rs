@@ -0,0 +1,307 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The client configuration advertised by a server in its
+//! `.well-known/matrix/client` document.
+//!
+//! Besides the homeserver and identity server URLs defined by the
+//! specification, servers commonly advertise vendor extensions in this
+//! document, like end-to-end encryption defaults or the URL of a tile server.
+//! [`WellKnownConfig`] exposes the ones known by the SDK in a typed way, and
+//! keeps the raw document around for the others.
+//!
+//! See [`Client::well_known_config()`],
+//! [`Client::refresh_well_known_config()`] and
+//! [`Client::subscribe_to_well_known_config()`].
+//!
+//! [`Client::well_known_config()`]: crate::Client::well_known_config
+//! [`Client::refresh_well_known_config()`]: crate::Client::refresh_well_known_config
+//! [`Client::subscribe_to_well_known_config()`]: crate::Client::subscribe_to_well_known_config
+
+use ruma::serde::JsonObject;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value as JsonValue;
+use tracing::warn;
+use url::Url;
+
+use crate::HttpError;
+
+/// The key of the end-to-end encryption extension.
+const E2EE_KEY: &str = "io.element.e2ee";
+/// The legacy key of the end-to-end encryption extension.
+const LEGACY_E2EE_KEY: &str = "im.vector.riot.e2ee";
+/// The key of the sliding sync proxy extension, from MSC3575.
+const SLIDING_SYNC_PROXY_KEY: &str = "org.matrix.msc3575.proxy";
+/// The key of the tile server extension.
+const TILE_SERVER_KEY: &str = "m.tile_server";
+/// The unstable key of the tile server extension, from MSC3488.
+const UNSTABLE_TILE_SERVER_KEY: &str = "org.matrix.msc3488.tile_server";
+/// The key of the Jitsi extension.
+const JITSI_KEY: &str = "im.vector.riot.jitsi";
+
+/// An error that happened while fetching or verifying the well-known
+/// document of a server.
+#[derive(Debug, thiserror::Error)]
+pub enum WellKnownError {
+    /// The document couldn't be fetched.
+    #[error(transparent)]
+    Http(#[from] HttpError),
+
+    /// The document isn't a valid JSON object.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The document doesn't contain a valid `m.homeserver.base_url`.
+    #[error("the well-known document doesn't contain a valid homeserver URL")]
+    InvalidHomeserver,
+
+    /// The document advertises another homeserver than the one used by the
+    /// client.
+    #[error("the well-known document advertises {found}, but the client uses {expected}")]
+    HomeserverMismatch {
+        /// The homeserver used by the client.
+        expected: Url,
+        /// The homeserver advertised in the document.
+        found: Url,
+    },
+}
+
+/// The end-to-end encryption defaults advertised by the server.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct E2eeConfig {
+    /// Whether new rooms should be encrypted by default.
+    #[serde(default = "default_true")]
+    pub default: bool,
+
+    /// Whether the user must set up a secure backup before using the client.
+    #[serde(default)]
+    pub secure_backup_required: bool,
+
+    /// The methods the user may use to set up a secure backup, e.g. `key` or
+    /// `passphrase`.
+    ///
+    /// An empty list means that all methods are allowed.
+    #[serde(default)]
+    pub secure_backup_setup_methods: Vec<String>,
+
+    /// Whether end-to-end encryption must be disabled entirely.
+    #[serde(default)]
+    pub force_disable: bool,
+}
+
+/// The tile server to use to render maps, e.g. for location sharing.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct TileServerConfig {
+    /// The URL of the style of the map.
+    pub map_style_url: Url,
+}
+
+/// The Jitsi configuration to use for conference calls.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct JitsiConfig {
+    /// The domain of the Jitsi server to use.
+    #[serde(rename = "preferredDomain")]
+    pub preferred_domain: String,
+}
+
+#[derive(Deserialize)]
+struct UrlConfig {
+    #[serde(alias = "base_url")]
+    url: Url,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The verified content of the `.well-known/matrix/client` document of a
+/// server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WellKnownConfig {
+    /// The URL of the homeserver.
+    pub homeserver: Url,
+
+    /// The URL of the identity server, if any.
+    pub identity_server: Option<Url>,
+
+    /// The end-to-end encryption defaults, if any.
+    pub e2ee: Option<E2eeConfig>,
+
+    /// The URL of the sliding sync proxy, if any.
+    pub sliding_sync_proxy: Option<Url>,
+
+    /// The tile server configuration, if any.
+    pub tile_server: Option<TileServerConfig>,
+
+    /// The Jitsi configuration, if any.
+    pub jitsi: Option<JitsiConfig>,
+
+    raw: JsonObject,
+}
+
+impl WellKnownConfig {
+    /// Parse a well-known document.
+    ///
+    /// The document must contain a valid `m.homeserver.base_url`. Extensions
+    /// that can't be parsed are ignored, so that a single misconfigured
+    /// extension doesn't prevent using the rest of the document.
+    pub fn parse(raw: JsonObject) -> Result<Self, WellKnownError> {
+        let homeserver = extension::<UrlConfig>(&raw, "m.homeserver")
+            .ok_or(WellKnownError::InvalidHomeserver)?
+            .url;
+        let identity_server =
+            extension::<UrlConfig>(&raw, "m.identity_server").map(|config| config.url);
+        let e2ee = extension(&raw, E2EE_KEY).or_else(|| extension(&raw, LEGACY_E2EE_KEY));
+        let sliding_sync_proxy =
+            extension::<UrlConfig>(&raw, SLIDING_SYNC_PROXY_KEY).map(|config| config.url);
+        let tile_server =
+            extension(&raw, TILE_SERVER_KEY).or_else(|| extension(&raw, UNSTABLE_TILE_SERVER_KEY));
+        let jitsi = extension(&raw, JITSI_KEY);
+
+        Ok(Self { homeserver, identity_server, e2ee, sliding_sync_proxy, tile_server, jitsi, raw })
+    }
+
+    /// Verify that this document advertises the given homeserver.
+    pub(crate) fn verify_homeserver(&self, homeserver: &Url) -> Result<(), WellKnownError> {
+        if self.homeserver.as_str().trim_end_matches('/')
+            != homeserver.as_str().trim_end_matches('/')
+        {
+            return Err(WellKnownError::HomeserverMismatch {
+                expected: homeserver.clone(),
+                found: self.homeserver.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The raw content of the document, to access extensions that aren't
+    /// exposed by this type.
+    pub fn raw(&self) -> &JsonObject {
+        &self.raw
+    }
+}
+
+/// Deserialize the extension at the given key, ignoring it with a warning if
+/// it's invalid.
+fn extension<T: DeserializeOwned>(raw: &JsonObject, key: &str) -> Option<T> {
+    let value = raw.get(key)?;
+
+    match T::deserialize(value) {
+        Ok(extension) => Some(extension),
+        Err(error) => {
+            warn!(key, %error, "Ignoring invalid well-known extension");
+            None
+        }
+    }
+}
+
+/// Build the URL of the well-known document for the given server.
+pub(crate) fn well_known_url(server: &Url) -> Url {
+    let mut url = server.clone();
+    url.set_path("/.well-known/matrix/client");
+    url.set_query(None);
+    url
+}
+
+/// Parse the body of a well-known response into a JSON object.
+pub(crate) fn parse_body(body: &[u8]) -> Result<JsonObject, WellKnownError> {
+    match serde_json::from_slice(body)? {
+        JsonValue::Object(object) => Ok(object),
+        _ => Err(WellKnownError::InvalidHomeserver),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use serde_json::json;
+
+    use super::*;
+
+    fn object(value: JsonValue) -> JsonObject {
+        assert_matches!(value, JsonValue::Object(object));
+        object
+    }
+
+    #[test]
+    fn test_parse_extensions() {
+        let config = WellKnownConfig::parse(object(json!({
+            "m.homeserver": { "base_url": "https://matrix.example.org" },
+            "m.identity_server": { "base_url": "https://identity.example.org" },
+            "im.vector.riot.e2ee": { "default": false },
+            "io.element.e2ee": {
+                "secure_backup_required": true,
+                "secure_backup_setup_methods": ["passphrase"],
+            },
+            "org.matrix.msc3575.proxy": { "url": "https://slidingsync.example.org" },
+            "org.matrix.msc3488.tile_server": {
+                "map_style_url": "https://tiles.example.org/style.json",
+            },
+            "im.vector.riot.jitsi": { "preferredDomain": "jitsi.example.org" },
+            "org.example.custom": { "enabled": true },
+        })))
+        .unwrap();
+
+        assert_eq!(config.homeserver.as_str(), "https://matrix.example.org/");
+        assert_eq!(config.identity_server.unwrap().as_str(), "https://identity.example.org/");
+
+        // The stable key takes precedence over the legacy one.
+        let e2ee = config.e2ee.unwrap();
+        assert!(e2ee.default);
+        assert!(e2ee.secure_backup_required);
+        assert_eq!(e2ee.secure_backup_setup_methods, ["passphrase"]);
+        assert!(!e2ee.force_disable);
+
+        assert_eq!(config.sliding_sync_proxy.unwrap().as_str(), "https://slidingsync.example.org/");
+        assert_eq!(
+            config.tile_server.unwrap().map_style_url.as_str(),
+            "https://tiles.example.org/style.json"
+        );
+        assert_eq!(config.jitsi.unwrap().preferred_domain, "jitsi.example.org");
+        assert!(config.raw.contains_key("org.example.custom"));
+    }
+
+    #[test]
+    fn test_parse_ignores_invalid_extensions() {
+        let config = WellKnownConfig::parse(object(json!({
+            "m.homeserver": { "base_url": "https://matrix.example.org" },
+            "org.matrix.msc3575.proxy": { "url": "not a url" },
+            "im.vector.riot.jitsi": "jitsi.example.org",
+        })))
+        .unwrap();
+
+        assert!(config.sliding_sync_proxy.is_none());
+        assert!(config.jitsi.is_none());
+    }
+
+    #[test]
+    fn test_verify() {
+        assert_matches!(
+            WellKnownConfig::parse(object(json!({ "m.homeserver": { "base_url": "nope" } }))),
+            Err(WellKnownError::InvalidHomeserver)
+        );
+
+        let config = WellKnownConfig::parse(object(json!({
+            "m.homeserver": { "base_url": "https://matrix.example.org/" },
+        })))
+        .unwrap();
+
+        config.verify_homeserver(&Url::parse("https://matrix.example.org").unwrap()).unwrap();
+        assert_matches!(
+            config.verify_homeserver(&Url::parse("https://other.example.org").unwrap()),
+            Err(WellKnownError::HomeserverMismatch { .. })
+        );
+    }
+}
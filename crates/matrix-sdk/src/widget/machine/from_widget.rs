@@ -51,6 +51,9 @@ pub(super) struct FromWidgetErrorResponse {
 impl FromWidgetErrorResponse {
     /// Create a error response to send to the widget from an http error.
     pub(crate) fn from_http_error(error: HttpError) -> Self {
+        // The ids of the request are only useful to correlate logs, don't send them to
+        // the widget.
+        let error = error.into_without_request_ids();
         let message = error.to_string();
         let matrix_api_error = as_variant!(error, HttpError::Api(ruma::api::error::FromHttpResponseError::Server(RumaApiError::ClientApi(err))) => err);
 
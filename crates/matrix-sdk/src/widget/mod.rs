@@ -35,11 +35,13 @@ mod capabilities;
 mod filter;
 mod machine;
 mod matrix;
+mod room_widgets;
 mod settings;
 
 pub use self::{
     capabilities::{Capabilities, CapabilitiesProvider},
     filter::{EventFilter, MessageLikeEventFilter, StateEventFilter},
+    room_widgets::{JitsiWidgetData, RoomWidget, WidgetEventContent, WidgetType},
     settings::{
         ClientProperties, EncryptionSystem, VirtualElementCallWidgetOptions, WidgetSettings,
     },
@@ -0,0 +1,392 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The widgets added to a room with `im.vector.modular.widgets` state events,
+//! like Jitsi or Element Call conferences.
+
+use std::collections::BTreeMap;
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::StreamExt;
+use matrix_sdk_base::deserialized_responses::SyncOrStrippedState;
+use ruma::{
+    api::client::state::send_state_event,
+    events::{macros::EventContent, SyncStateEvent},
+    OwnedUserId,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tracing::{info, warn};
+use urlencoding::encode;
+
+use super::WidgetSettings;
+use crate::{Result, Room};
+
+/// The content of an `im.vector.modular.widgets` state event, adding a widget
+/// to a room.
+///
+/// The state key is the ID of the widget. A widget is removed by replacing its
+/// state event with an empty content, which is what [`Default`] returns.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "im.vector.modular.widgets", kind = State, state_key_type = String)]
+pub struct WidgetEventContent {
+    /// The type of the widget.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub widget_type: Option<WidgetType>,
+
+    /// The URL of the widget.
+    ///
+    /// It can contain placeholders for the values of [`Self::data`], like
+    /// `$conferenceId`, and for the properties of the client, like
+    /// `$matrix_user_id`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// The human-readable name of the widget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The data of the widget, specific to its type.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub data: BTreeMap<String, JsonValue>,
+
+    /// Whether the widget should be initialized as soon as the iframe is
+    /// loaded, rather than when the widget says its content is loaded.
+    ///
+    /// Defaults to `true`.
+    #[serde(rename = "waitForIframeLoad", skip_serializing_if = "Option::is_none")]
+    pub wait_for_iframe_load: Option<bool>,
+
+    /// The user who added the widget.
+    #[serde(rename = "creatorUserId", skip_serializing_if = "Option::is_none")]
+    pub creator_user_id: Option<OwnedUserId>,
+}
+
+impl WidgetEventContent {
+    /// Create a new `WidgetEventContent` with the given type and URL.
+    pub fn new(widget_type: WidgetType, url: String) -> Self {
+        Self { widget_type: Some(widget_type), url: Some(url), ..Default::default() }
+    }
+
+    /// Create a new `WidgetEventContent` for a Jitsi conference, using the
+    /// given URL template.
+    ///
+    /// The URL can contain the placeholders of the fields of [`JitsiWidgetData`],
+    /// like `$domain` or `$conferenceId`.
+    pub fn jitsi(url: String, data: JitsiWidgetData) -> Self {
+        let data = match serde_json::to_value(data) {
+            Ok(JsonValue::Object(data)) => data.into_iter().collect(),
+            _ => BTreeMap::new(),
+        };
+
+        Self { data, ..Self::new(WidgetType::Jitsi, url) }
+    }
+}
+
+/// The type of a room widget.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum WidgetType {
+    /// A Jitsi conference.
+    Jitsi,
+
+    /// An Element Call conference.
+    ElementCall,
+
+    /// Another type of widget.
+    Custom(String),
+}
+
+impl WidgetType {
+    /// Get the string representation of this type.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Jitsi => "m.jitsi",
+            Self::ElementCall => "m.call",
+            Self::Custom(widget_type) => widget_type,
+        }
+    }
+}
+
+impl From<String> for WidgetType {
+    fn from(widget_type: String) -> Self {
+        match widget_type.as_str() {
+            "m.jitsi" | "jitsi" => Self::Jitsi,
+            "m.call" | "io.element.call" => Self::ElementCall,
+            _ => Self::Custom(widget_type),
+        }
+    }
+}
+
+impl From<WidgetType> for String {
+    fn from(widget_type: WidgetType) -> Self {
+        match widget_type {
+            WidgetType::Custom(widget_type) => widget_type,
+            widget_type => widget_type.as_str().to_owned(),
+        }
+    }
+}
+
+/// The data of a Jitsi widget.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JitsiWidgetData {
+    /// The domain of the Jitsi server.
+    pub domain: String,
+
+    /// The ID of the conference on the Jitsi server.
+    pub conference_id: String,
+
+    /// Whether the conference is audio-only.
+    #[serde(default)]
+    pub is_audio_only: bool,
+
+    /// The name of the room, to display in the conference.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room_name: Option<String>,
+}
+
+/// A widget that is active in a room.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomWidget {
+    /// The ID of the widget, the state key of its state event.
+    pub id: String,
+
+    /// The type of the widget.
+    pub widget_type: WidgetType,
+
+    /// The URL template of the widget.
+    pub url: String,
+
+    /// The human-readable name of the widget.
+    pub name: Option<String>,
+
+    /// The data of the widget, specific to its type.
+    pub data: BTreeMap<String, JsonValue>,
+
+    /// Whether the widget should be initialized as soon as the iframe is
+    /// loaded.
+    pub wait_for_iframe_load: bool,
+
+    /// The user who added the widget, or who sent its state event if it is
+    /// unknown.
+    pub creator: OwnedUserId,
+}
+
+impl RoomWidget {
+    /// Create a `RoomWidget` from the content of its state event, if it is
+    /// active.
+    fn new(id: String, sender: OwnedUserId, content: WidgetEventContent) -> Option<Self> {
+        Some(Self {
+            id,
+            widget_type: content.widget_type?,
+            url: content.url.filter(|url| !url.is_empty())?,
+            name: content.name,
+            data: content.data,
+            wait_for_iframe_load: content.wait_for_iframe_load.unwrap_or(true),
+            creator: content.creator_user_id.unwrap_or(sender),
+        })
+    }
+
+    /// Get the data of this widget if it is a Jitsi widget.
+    pub fn jitsi_data(&self) -> Option<JitsiWidgetData> {
+        if self.widget_type != WidgetType::Jitsi {
+            return None;
+        }
+
+        let data = JsonValue::Object(self.data.clone().into_iter().collect());
+        serde_json::from_value(data).ok()
+    }
+
+    /// Get the URL of this widget, with the placeholders of its data filled
+    /// in.
+    ///
+    /// The placeholders for the properties of the client, like
+    /// `$matrix_user_id`, are kept, they are filled in by
+    /// [`WidgetSettings::generate_webview_url()`].
+    pub fn url_with_data(&self) -> String {
+        fill_data_placeholders(&self.url, &self.data)
+    }
+
+    /// Get the [`WidgetSettings`] to run this widget with a
+    /// [`WidgetDriver`](super::WidgetDriver).
+    pub fn settings(&self) -> Result<WidgetSettings, url::ParseError> {
+        WidgetSettings::new(self.id.clone(), !self.wait_for_iframe_load, &self.url_with_data())
+    }
+}
+
+/// Replace the `$key` placeholders of the given URL with the URL-encoded values
+/// of the data.
+fn fill_data_placeholders(url: &str, data: &BTreeMap<String, JsonValue>) -> String {
+    let mut values: Vec<_> = data
+        .iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                JsonValue::String(value) => value.clone(),
+                JsonValue::Bool(_) | JsonValue::Number(_) => value.to_string(),
+                _ => return None,
+            };
+            Some((format!("${key}"), encode(&value).into_owned()))
+        })
+        .collect();
+
+    // Replace the longest placeholders first, so a placeholder that is the
+    // prefix of another one doesn't replace a part of it.
+    values.sort_by_key(|(placeholder, _)| std::cmp::Reverse(placeholder.len()));
+
+    values
+        .into_iter()
+        .fold(url.to_owned(), |url, (placeholder, value)| url.replace(&placeholder, &value))
+}
+
+impl Room {
+    /// Get the widgets that are active in this room.
+    pub async fn widgets(&self) -> Result<Vec<RoomWidget>> {
+        let widgets = self
+            .get_state_events_static::<WidgetEventContent>()
+            .await?
+            .into_iter()
+            .filter_map(|raw| match raw.deserialize() {
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event))) => {
+                    RoomWidget::new(event.state_key, event.sender, event.content)
+                }
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Redacted(_))) => None,
+                Ok(SyncOrStrippedState::Stripped(event)) => {
+                    RoomWidget::new(event.state_key, event.sender, event.content)
+                }
+                Err(error) => {
+                    info!(room_id = ?self.room_id(), "Could not deserialize a widget: {error}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(widgets)
+    }
+
+    /// Add a widget to this room, or replace the widget with the same ID.
+    ///
+    /// The creator of the widget is set to the current user if it isn't set.
+    pub async fn add_widget(
+        &self,
+        widget_id: &str,
+        mut content: WidgetEventContent,
+    ) -> Result<send_state_event::v3::Response> {
+        if content.creator_user_id.is_none() {
+            content.creator_user_id = Some(self.own_user_id().to_owned());
+        }
+
+        self.send_state_event_for_key(widget_id, content).await
+    }
+
+    /// Remove the widget with the given ID from this room.
+    pub async fn remove_widget(&self, widget_id: &str) -> Result<send_state_event::v3::Response> {
+        self.send_state_event_for_key(widget_id, WidgetEventContent::default()).await
+    }
+
+    /// Subscribe to the widgets that are active in this room.
+    ///
+    /// The current widgets are emitted immediately, then every time a widget
+    /// state event is received.
+    pub fn subscribe_to_widgets(&self) -> impl Stream<Item = Vec<RoomWidget>> {
+        let room = self.clone();
+        let observer = self
+            .client
+            .observe_room_events::<SyncStateEvent<WidgetEventContent>, ()>(self.room_id());
+
+        stream! {
+            let mut widget_events = observer.subscribe();
+
+            loop {
+                match room.widgets().await {
+                    Ok(widgets) => yield widgets,
+                    Err(error) => warn!("Couldn't load the widgets of the room: {error}"),
+                }
+
+                if widget_events.next().await.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ruma::owned_user_id;
+    use serde_json::{from_value, json, to_value};
+
+    use super::{
+        fill_data_placeholders, JitsiWidgetData, RoomWidget, WidgetEventContent, WidgetType,
+    };
+
+    #[test]
+    fn test_widget_event_content() {
+        let content: WidgetEventContent = from_value(json!({
+            "type": "jitsi",
+            "url": "https://app.element.io/jitsi.html#conferenceId=$conferenceId",
+            "name": "Jitsi",
+            "data": {
+                "domain": "meet.example.org",
+                "conferenceId": "abc",
+                "isAudioOnly": false,
+            },
+        }))
+        .unwrap();
+
+        let widget =
+            RoomWidget::new("widget".to_owned(), owned_user_id!("@alice:localhost"), content)
+                .unwrap();
+        assert_eq!(widget.widget_type, WidgetType::Jitsi);
+        assert!(widget.wait_for_iframe_load);
+        assert_eq!(widget.creator, "@alice:localhost");
+        assert_eq!(
+            widget.jitsi_data().unwrap(),
+            JitsiWidgetData {
+                domain: "meet.example.org".to_owned(),
+                conference_id: "abc".to_owned(),
+                is_audio_only: false,
+                room_name: None,
+            }
+        );
+        assert_eq!(widget.url_with_data(), "https://app.element.io/jitsi.html#conferenceId=abc");
+
+        // A removed widget has an empty content.
+        assert_eq!(to_value(WidgetEventContent::default()).unwrap(), json!({}));
+        let content: WidgetEventContent = from_value(json!({})).unwrap();
+        assert!(RoomWidget::new("widget".to_owned(), owned_user_id!("@alice:localhost"), content)
+            .is_none());
+    }
+
+    #[test]
+    fn test_fill_data_placeholders() {
+        let data = BTreeMap::from([
+            ("domain".to_owned(), json!("meet.example.org")),
+            ("domainName".to_owned(), json!("Example Meet")),
+            ("isAudioOnly".to_owned(), json!(true)),
+            ("nested".to_owned(), json!({ "key": "value" })),
+        ]);
+
+        assert_eq!(
+            fill_data_placeholders(
+                "https://example.org/#d=$domain&n=$domainName&a=$isAudioOnly&x=$nested&u=$matrix_user_id",
+                &data
+            ),
+            "https://example.org/#d=meet.example.org&n=Example%20Meet&a=true&x=$nested&u=$matrix_user_id"
+        );
+    }
+}
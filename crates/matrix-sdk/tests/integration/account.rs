@@ -1,7 +1,8 @@
 use matrix_sdk_test::async_test;
+use ruma::room_id;
 use serde_json::json;
 use wiremock::{
-    matchers::{method, path},
+    matchers::{body_json, method, path},
     Mock, Request, ResponseTemplate,
 };
 
@@ -59,3 +60,127 @@ async fn test_account_deactivation() {
         assert!(client.account().deactivate(None, None, true).await.is_ok());
     }
 }
+
+#[async_test]
+async fn test_hide_room() {
+    let (client, server) = logged_in_client_with_server().await;
+    let room_id = room_id!("!hidden:localhost");
+
+    {
+        let _scope = Mock::given(method("PUT"))
+            .and(path(
+                "/_matrix/client/r0/user/@example:localhost/account_data/org.matrix.custom.hidden_rooms",
+            ))
+            .and(body_json(json!({ "rooms": [room_id] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount_as_scoped(&server)
+            .await;
+
+        client.account().hide_room(room_id).await.unwrap();
+    }
+
+    // The room isn't hidden yet in the stored list, so there's nothing to unhide.
+    {
+        let _scope = Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(0)
+            .mount_as_scoped(&server)
+            .await;
+
+        client.account().unhide_room(room_id).await.unwrap();
+        assert!(client.account().hidden_rooms().await.unwrap().is_empty());
+    }
+}
+
+#[cfg(feature = "image")]
+#[async_test]
+async fn test_set_avatar_from_bytes_updates_room_overrides() {
+    use matrix_sdk::{test_utils::mocks::MatrixMockServer, AvatarResizePolicy};
+    use matrix_sdk_test::{event_factory::EventFactory, JoinedRoomBuilder};
+    use ruma::{mxc_uri, room_id, user_id};
+    use wiremock::matchers::body_partial_json;
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let user_id = user_id!("@example:localhost");
+    let old_avatar = mxc_uri!("mxc://localhost/old");
+    let new_avatar = mxc_uri!("mxc://localhost/new");
+    let f = EventFactory::new();
+
+    // In this room, the member avatar follows the global avatar.
+    let following_room_id = room_id!("!following:localhost");
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(following_room_id).add_state_bulk([f
+                .member(user_id)
+                .display_name("Example")
+                .avatar_url(old_avatar)
+                .into_raw()]),
+        )
+        .await;
+
+    // In this room, the user set a different avatar.
+    let override_room_id = room_id!("!override:localhost");
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(override_room_id).add_state_bulk([f
+                .member(user_id)
+                .display_name("Example")
+                .avatar_url(mxc_uri!("mxc://localhost/override"))
+                .into_raw()]),
+        )
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/profile/@example:localhost/avatar_url"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "avatar_url": old_avatar,
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    server.mock_upload().ok(new_avatar).mock_once().mount().await;
+    Mock::given(method("PUT"))
+        .and(path("/_matrix/client/v3/profile/@example:localhost/avatar_url"))
+        .and(body_partial_json(json!({ "avatar_url": new_avatar })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // Only the member event of the room with an override is sent.
+    Mock::given(method("PUT"))
+        .and(path(
+            "/_matrix/client/v3/rooms/!override:localhost/state/m.room.member/@example:localhost",
+        ))
+        .and(body_partial_json(json!({
+            "membership": "join",
+            "displayname": "Example",
+            "avatar_url": new_avatar,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$member" })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let avatar_url = client
+        .account()
+        .set_avatar_from_bytes(
+            b"image".to_vec(),
+            &mime::IMAGE_PNG,
+            AvatarResizePolicy::Original,
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(avatar_url, new_avatar);
+
+    assert_eq!(
+        client.account().get_cached_avatar_url().await.unwrap().as_deref(),
+        Some(new_avatar)
+    );
+}
@@ -2,17 +2,20 @@ use std::{collections::BTreeMap, time::Duration};
 
 use assert_matches2::{assert_let, assert_matches};
 use eyeball_im::VectorDiff;
-use futures_util::FutureExt;
+use futures_util::{future, pin_mut, FutureExt};
 use matrix_sdk::{
-    config::{RequestConfig, StoreConfig, SyncSettings},
+    config::{EndpointOverrides, RequestConfig, StoreConfig, SyncSettings},
+    long_poll::LongPollDriver,
     matrix_auth::{MatrixSession, MatrixSessionTokens},
+    server_notices::ServerNoticeKind,
     sync::RoomUpdate,
-    test_utils::no_retry_test_client_with_server,
-    Client, MemoryStore, SessionMeta, StateChanges, StateStore,
+    test_utils::{no_retry_test_client_with_server, test_client_builder_with_server},
+    Client, ClientStatus, ConnectionState, Error, ErrorCategory, HttpError, MemoryStore,
+    SessionMeta, StateChanges, StateStore,
 };
-use matrix_sdk_base::{sync::RoomUpdates, RoomState};
+use matrix_sdk_base::{sync::RoomUpdates, RoomState, StateStoreDataKey, StateStoreDataValue};
 use matrix_sdk_test::{
-    async_test, sync_state_event,
+    async_test, sync_state_event, sync_timeline_event,
     test_json::{
         self,
         sync::{
@@ -22,14 +25,20 @@ use matrix_sdk_test::{
         sync_events::PINNED_EVENTS,
         TAG,
     },
-    GlobalAccountDataTestEvent, JoinedRoomBuilder, SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
+    GlobalAccountDataTestEvent, JoinedRoomBuilder, LeftRoomBuilder, PresenceTestEvent,
+    RoomAccountDataTestEvent, SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
 };
 use ruma::{
     api::client::{
+        account::whoami,
         directory::{
             get_public_rooms,
             get_public_rooms_filtered::{self, v3::Request as PublicRoomsFilterRequest},
         },
+        error::ErrorKind,
+        filter::{FilterDefinition, LazyLoadOptions},
+        room::create_room,
+        sync::sync_events,
         uiaa,
     },
     assign, device_id,
@@ -37,17 +46,20 @@ use ruma::{
     event_id,
     events::{
         direct::{DirectEventContent, OwnedDirectUserIdentifier},
+        room::message::LimitType,
         AnyInitialStateEvent,
     },
+    presence::PresenceState,
     room_id,
     serde::Raw,
-    user_id, OwnedUserId,
+    user_id, MilliSecondsSinceUnixEpoch, OwnedUserId, RoomVersionId,
 };
 use serde_json::{json, Value as JsonValue};
-use stream_assert::{assert_next_matches, assert_pending};
+use stream_assert::{assert_next_eq, assert_next_matches, assert_pending};
+use tokio::{sync::mpsc, time::timeout};
 use tokio_stream::wrappers::BroadcastStream;
 use wiremock::{
-    matchers::{header, method, path, path_regex},
+    matchers::{body_partial_json, header, method, path, path_regex, query_param},
     Mock, Request, ResponseTemplate,
 };
 
@@ -145,6 +157,136 @@ async fn test_delete_devices() {
     }
 }
 
+#[async_test]
+async fn test_delete_inactive_devices() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let now = u64::from(MilliSecondsSinceUnixEpoch::now().get());
+    let long_ago = now - 100 * 24 * 60 * 60 * 1000;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "devices": [
+                { "device_id": "DEVICEID", "last_seen_ts": long_ago },
+                { "device_id": "OLDDEVICE", "last_seen_ts": long_ago },
+                { "device_id": "RECENTDEVICE", "last_seen_ts": now },
+                { "device_id": "UNKNOWNDEVICE" },
+            ]
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/delete_devices"))
+        .and(body_partial_json(json!({ "devices": ["OLDDEVICE"] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let deleted =
+        client.delete_inactive_devices(Duration::from_secs(90 * 24 * 60 * 60), None).await.unwrap();
+
+    assert_eq!(deleted, vec![device_id!("OLDDEVICE").to_owned()]);
+}
+
+#[async_test]
+async fn test_delete_inactive_devices_in_batches() {
+    use std::sync::{Arc, Mutex};
+
+    let (client, server) = logged_in_client_with_server().await;
+
+    let long_ago = u64::from(MilliSecondsSinceUnixEpoch::now().get()) - 100 * 24 * 60 * 60 * 1000;
+
+    // More devices than what is deleted with a single request.
+    let devices =
+        Arc::new(Mutex::new((0..30).map(|i| format!("OLDDEVICE{i}")).collect::<Vec<_>>()));
+    // The homeserver accepts a user-interactive auth session only once.
+    let used_sessions = Arc::new(Mutex::new(Vec::<String>::new()));
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/devices"))
+        .respond_with({
+            let devices = devices.clone();
+            move |_: &Request| {
+                let devices: Vec<_> = devices
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|device_id| json!({ "device_id": device_id, "last_seen_ts": long_ago }))
+                    .collect();
+                ResponseTemplate::new(200).set_body_json(json!({ "devices": devices }))
+            }
+        })
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/delete_devices"))
+        .respond_with({
+            let devices = devices.clone();
+            move |request: &Request| {
+                let body: JsonValue = request.body_json().unwrap();
+                let session = body["auth"]["session"].as_str().map(ToOwned::to_owned);
+                let mut used_sessions = used_sessions.lock().unwrap();
+
+                match session {
+                    Some(session) if !used_sessions.contains(&session) => {
+                        used_sessions.push(session);
+
+                        let deleted: Vec<_> = body["devices"]
+                            .as_array()
+                            .unwrap()
+                            .iter()
+                            .map(|device_id| device_id.as_str().unwrap().to_owned())
+                            .collect();
+                        devices.lock().unwrap().retain(|device_id| !deleted.contains(device_id));
+
+                        ResponseTemplate::new(200).set_body_json(json!({}))
+                    }
+                    _ => ResponseTemplate::new(401).set_body_json(json!({
+                        "flows": [{ "stages": ["m.login.password"] }],
+                        "params": {},
+                        "session": format!("session{}", used_sessions.len()),
+                    })),
+                }
+            }
+        })
+        .mount(&server)
+        .await;
+
+    let older_than = Duration::from_secs(90 * 24 * 60 * 60);
+    let auth_data = |session: Option<String>| {
+        uiaa::AuthData::Password(assign!(
+            uiaa::Password::new(
+                uiaa::UserIdentifier::UserIdOrLocalpart("example".to_owned()),
+                "wordpass".to_owned(),
+            ), {
+                session,
+            }
+        ))
+    };
+
+    // The homeserver requires user-interactive auth, nothing is deleted.
+    let error = client.delete_inactive_devices(older_than, None).await.unwrap_err();
+    let session = error.as_uiaa_response().unwrap().session.clone();
+    assert_eq!(devices.lock().unwrap().len(), 30);
+
+    // The first batch is deleted, but the homeserver doesn't accept the same
+    // session for the second one, so the error is returned.
+    let error =
+        client.delete_inactive_devices(older_than, Some(auth_data(session))).await.unwrap_err();
+    let session = error.as_uiaa_response().unwrap().session.clone();
+    assert_eq!(devices.lock().unwrap().len(), 5);
+
+    // Calling again with a new session deletes the remaining devices.
+    let deleted =
+        client.delete_inactive_devices(older_than, Some(auth_data(session))).await.unwrap();
+    assert_eq!(deleted.len(), 5);
+    assert!(devices.lock().unwrap().is_empty());
+}
+
 #[async_test]
 async fn test_resolve_room_alias() {
     let (client, server) = no_retry_test_client_with_server().await;
@@ -242,6 +384,28 @@ async fn test_room_search_all() {
     assert_eq!(chunk.len(), 1);
 }
 
+#[async_test]
+async fn test_endpoint_overrides() {
+    let (builder, server) = test_client_builder_with_server().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .endpoint_overrides(EndpointOverrides::new().client_api_prefix("/gateway/client"))
+        .build()
+        .await
+        .unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/gateway/client/r0/publicRooms"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::PUBLIC_ROOMS))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let get_public_rooms::v3::Response { chunk, .. } =
+        client.public_rooms(Some(10), None, None).await.unwrap();
+    assert_eq!(chunk.len(), 1);
+}
+
 #[async_test]
 async fn test_room_search_filtered() {
     let (client, server) = logged_in_client_with_server().await;
@@ -310,6 +474,259 @@ async fn test_whoami() {
     assert_eq!(client.whoami().await.unwrap().user_id, user_id);
 }
 
+#[async_test]
+async fn test_client_status() {
+    let (client, server) = logged_in_client_with_server().await;
+    let mut status = client.subscribe_to_status();
+    assert_eq!(client.status(), ClientStatus::Active);
+    assert!(client.send_queue().is_enabled());
+
+    // The account is locked.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "errcode": "M_USER_LOCKED",
+            "error": "This account has been locked",
+            "soft_logout": true,
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    client.whoami().await.unwrap_err();
+    assert_next_eq!(status, ClientStatus::Locked);
+    assert!(!client.send_queue().is_enabled());
+
+    // Once it is unlocked, requests succeed again.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::WHOAMI))
+        .mount(&server)
+        .await;
+
+    client.whoami().await.unwrap();
+    assert_next_eq!(status, ClientStatus::Active);
+    assert!(client.send_queue().is_enabled());
+
+    // The account is suspended, it can't send anything anymore.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/profile/.*/displayname"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_USER_SUSPENDED",
+            "error": "This account has been suspended",
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    client.account().set_display_name(Some("Alice")).await.unwrap_err();
+    assert_next_eq!(status, ClientStatus::Suspended);
+    assert!(!client.send_queue().is_enabled());
+
+    // But it can still read.
+    client.whoami().await.unwrap();
+    assert_pending!(status);
+    assert_eq!(client.status(), ClientStatus::Suspended);
+
+    // Once it isn't suspended anymore, it can write again.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/profile/.*/displayname"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&server)
+        .await;
+
+    client.account().set_display_name(Some("Alice")).await.unwrap();
+    assert_next_eq!(status, ClientStatus::Active);
+    assert!(client.send_queue().is_enabled());
+
+    // A send queue that was disabled on purpose stays disabled.
+    client.send_queue().set_enabled(false).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/profile/.*/displayname"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_USER_SUSPENDED",
+            "error": "This account has been suspended",
+        })))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    client.account().set_display_name(Some("Alice")).await.unwrap_err();
+    assert_next_eq!(status, ClientStatus::Suspended);
+
+    client.account().set_display_name(Some("Alice")).await.unwrap();
+    assert_next_eq!(status, ClientStatus::Active);
+    assert!(!client.send_queue().is_enabled());
+}
+
+#[async_test]
+async fn test_connection_state() {
+    let (client, server) = logged_in_client_with_server().await;
+    let mut connection_state = client.subscribe_to_connection_state();
+    assert_eq!(client.connection_state(), ConnectionState::Online);
+
+    // The homeserver doesn't answer in time.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(&*test_json::WHOAMI)
+                .set_delay(Duration::from_secs(1)),
+        )
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    let config = RequestConfig::new().disable_retry().timeout(Duration::from_millis(100));
+    client.send(whoami::v3::Request::new()).with_request_config(config).await.unwrap_err();
+    assert_next_eq!(connection_state, ConnectionState::Offline);
+
+    // As soon as it answers again, the client is back online.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::WHOAMI))
+        .mount(&server)
+        .await;
+
+    client.whoami().await.unwrap();
+    assert_next_eq!(connection_state, ConnectionState::Online);
+
+    // An error response still means that the homeserver is reachable.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/profile/.*/displayname"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Profile not found",
+        })))
+        .mount(&server)
+        .await;
+
+    client.account().get_display_name().await.unwrap_err();
+    assert_pending!(connection_state);
+    assert_eq!(client.connection_state(), ConnectionState::Online);
+}
+
+#[async_test]
+async fn test_error_category() {
+    let (client, server) = logged_in_client_with_server().await;
+    let config = RequestConfig::new().disable_retry();
+
+    // Rate-limited.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+            "errcode": "M_LIMIT_EXCEEDED",
+            "error": "Too many requests",
+            "retry_after_ms": 2000,
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    let error = client.send(whoami::v3::Request::new()).with_request_config(config).await;
+    let error = Error::from(error.unwrap_err());
+    assert_eq!(error.category(), ErrorCategory::RateLimited);
+    assert!(error.is_retryable());
+    assert_eq!(error.retry_after(), Some(Duration::from_secs(2)));
+
+    // Invalid access token.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "errcode": "M_UNKNOWN_TOKEN",
+            "error": "Invalid access token",
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    let error = client.send(whoami::v3::Request::new()).with_request_config(config).await;
+    let error = error.unwrap_err();
+    assert_eq!(error.category(), ErrorCategory::Authentication);
+    assert!(!error.is_retryable());
+    assert_eq!(error.retry_after(), None);
+
+    // A reverse proxy in front of an unavailable homeserver.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(ResponseTemplate::new(502).set_body_string("Bad Gateway"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    let error = client.send(whoami::v3::Request::new()).with_request_config(config).await;
+    let error = error.unwrap_err();
+    assert_eq!(error.category(), ErrorCategory::Server);
+    assert!(error.is_retryable());
+    assert_eq!(error.retry_after(), None);
+
+    // A request that the homeserver refuses.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/profile/.*/displayname"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Profile not found",
+        })))
+        .mount(&server)
+        .await;
+
+    let error = client.account().get_display_name().await.unwrap_err();
+    assert_eq!(error.category(), ErrorCategory::Validation);
+    assert!(!error.is_retryable());
+
+    // Local errors have a category too.
+    assert_eq!(Error::InsufficientData.category(), ErrorCategory::Validation);
+    assert!(!Error::InsufficientData.is_retryable());
+}
+
+#[async_test]
+async fn test_error_request_ids() {
+    let (client, server) = logged_in_client_with_server().await;
+    let config = RequestConfig::new().disable_retry();
+
+    // The homeserver reports the id of the request.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(
+            ResponseTemplate::new(500).insert_header("X-Request-Id", "SYN-1234").set_body_json(
+                json!({
+                    "errcode": "M_UNKNOWN",
+                    "error": "Internal server error",
+                }),
+            ),
+        )
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    let error = client.send(whoami::v3::Request::new()).with_request_config(config).await;
+    let error = error.unwrap_err();
+
+    let request_ids = error.request_ids().unwrap();
+    assert!(request_ids.client_request_id.starts_with("REQ-"));
+    assert_eq!(request_ids.server_request_id.as_deref(), Some("SYN-1234"));
+    assert!(error.to_string().contains("server request id: SYN-1234"));
+    assert_matches!(error.without_request_ids(), HttpError::Api(_));
+    assert_matches!(error.client_api_error_kind(), Some(ErrorKind::Unknown));
+
+    // The homeserver doesn't report the id of the request.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(ResponseTemplate::new(502).set_body_string("Bad Gateway"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    let error = client.send(whoami::v3::Request::new()).with_request_config(config).await;
+    let error = Error::from(error.unwrap_err());
+
+    let request_ids = error.request_ids().unwrap();
+    assert!(request_ids.client_request_id.starts_with("REQ-"));
+    assert_eq!(request_ids.server_request_id, None);
+}
+
 #[async_test]
 async fn test_room_update_channel() {
     let (client, server) = logged_in_client_with_server().await;
@@ -913,46 +1330,394 @@ async fn test_create_dm_error() {
 }
 
 #[async_test]
-async fn test_test_ambiguity_changes() {
+async fn test_dm_room_with_uses_m_direct() {
     let (client, server) = logged_in_client_with_server().await;
+    let bob = user_id!("@bob:localhost");
+    let dm_room_id = room_id!("!dm:localhost");
 
-    let example_id = user_id!("@example:localhost");
-    let example_2_id = user_id!("@example2:localhost");
-    let example_3_id = user_id!("@example3:localhost");
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder
+        .add_joined_room(JoinedRoomBuilder::new(dm_room_id).add_state_bulk([sync_state_event!({
+            "content": { "membership": "join" },
+            "event_id": "$bob_join",
+            "origin_server_ts": 151800140,
+            "sender": bob,
+            "state_key": bob,
+            "type": "m.room.member",
+        })]))
+        .add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+            "content": { "@bob:localhost": ["!dm:localhost"] },
+            "type": "m.direct",
+        })));
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
 
-    let mut updates = BroadcastStream::new(client.subscribe_to_room_updates(&DEFAULT_TEST_ROOM_ID));
-    assert_pending!(updates);
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/createRoom"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "room_id": "!new:localhost" })),
+        )
+        .expect(0)
+        .mount(&server)
+        .await;
 
-    // Initial sync, adds 2 members.
-    mock_sync(&server, &*test_json::SYNC, None).await;
-    let response = client.sync_once(SyncSettings::default()).await.unwrap();
-    server.reset().await;
+    let room = client.dm_room_with(bob).await.unwrap();
+    assert_eq!(room.room_id(), dm_room_id);
+}
 
-    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+#[async_test]
+async fn test_dm_room_with_creates_room_once() {
+    let (client, server) = logged_in_client_with_server().await;
+    let bob = user_id!("@bob:localhost");
 
-    let changes = &response.rooms.join.get(*DEFAULT_TEST_ROOM_ID).unwrap().ambiguity_changes;
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/createRoom"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "room_id": "!new:localhost" })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
 
-    // A new member always triggers an ambiguity change.
-    let example_change = changes.get(event_id!("$151800140517rfvjc:localhost")).unwrap();
-    assert_eq!(example_change.member_id, example_id);
-    assert!(!example_change.member_ambiguous);
-    assert_eq!(example_change.ambiguated_member, None);
-    assert_eq!(example_change.disambiguated_member, None);
+    Mock::given(method("GET"))
+        .and(path("_matrix/client/r0/user/@example:localhost/account_data/m.direct"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Account data not found",
+        })))
+        .mount(&server)
+        .await;
 
-    let example_2_change = changes.get(event_id!("$152034824468gOeNB:localhost")).unwrap();
-    assert_eq!(example_2_change.member_id, example_2_id);
-    assert!(!example_2_change.member_ambiguous);
-    assert_eq!(example_2_change.ambiguated_member, None);
-    assert_eq!(example_2_change.disambiguated_member, None);
+    Mock::given(method("PUT"))
+        .and(path("_matrix/client/r0/user/@example:localhost/account_data/m.direct"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
 
-    let example = room.get_member_no_sync(example_id).await.unwrap().unwrap();
-    assert!(!example.name_ambiguous());
-    let example_2 = room.get_member_no_sync(example_2_id).await.unwrap().unwrap();
-    assert!(!example_2.name_ambiguous());
+    // Concurrent calls wait for the same room to be created.
+    let (room, other_room) = future::join(client.dm_room_with(bob), client.dm_room_with(bob)).await;
+    assert_eq!(room.unwrap().room_id(), room_id!("!new:localhost"));
+    assert_eq!(other_room.unwrap().room_id(), room_id!("!new:localhost"));
 
-    let changes = assert_next_matches!(updates, Ok(RoomUpdate::Joined { updates, .. }) => updates.ambiguity_changes);
+    // The room isn't in m.direct yet, but it's reused anyway.
+    let room = client.dm_room_with(bob).await.unwrap();
+    assert_eq!(room.room_id(), room_id!("!new:localhost"));
+}
 
-    let example_change = changes.get(event_id!("$151800140517rfvjc:localhost")).unwrap();
+#[async_test]
+async fn test_joining_dm_invite_repairs_m_direct() {
+    let (client, server) = logged_in_client_with_server().await;
+    let bob = user_id!("@bob:localhost");
+    let dm_room_id = room_id!("!dm:localhost");
+    let (repaired_sender, mut repaired_receiver) = mpsc::unbounded_channel();
+
+    Mock::given(method("GET"))
+        .and(path("_matrix/client/r0/user/@example:localhost/account_data/m.direct"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "@alice:localhost": ["!other:localhost"],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path("_matrix/client/r0/user/@example:localhost/account_data/m.direct"))
+        .and(move |request: &Request| {
+            let Ok(content) = request.body_json::<DirectEventContent>() else {
+                return false;
+            };
+
+            content.len() == 2
+                && content.get(&OwnedDirectUserIdentifier::from(bob.to_owned()))
+                    == Some(&vec![dm_room_id.to_owned()])
+        })
+        .respond_with(move |_: &Request| {
+            repaired_sender.send(()).unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({}))
+        })
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The invite was accepted by another client, which didn't update m.direct.
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(dm_room_id)
+            .set_room_summary(json!({
+                "m.joined_member_count": 2,
+                "m.invited_member_count": 0,
+            }))
+            .add_state_bulk([
+                sync_state_event!({
+                    "content": { "membership": "join" },
+                    "event_id": "$bob_join",
+                    "origin_server_ts": 151800140,
+                    "sender": bob,
+                    "state_key": bob,
+                    "type": "m.room.member",
+                }),
+                sync_state_event!({
+                    "content": { "membership": "join" },
+                    "event_id": "$own_join",
+                    "origin_server_ts": 151800150,
+                    "sender": "@example:localhost",
+                    "state_key": "@example:localhost",
+                    "type": "m.room.member",
+                    "unsigned": {
+                        "prev_content": { "membership": "invite", "is_direct": true },
+                    },
+                }),
+            ]),
+    );
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+
+    // m.direct is updated in the background.
+    timeout(Duration::from_secs(5), repaired_receiver.recv()).await.unwrap().unwrap();
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn test_leaving_dm_room_repairs_m_direct() {
+    let (client, server) = logged_in_client_with_server().await;
+    let bob = user_id!("@bob:localhost");
+    let dm_room_id = room_id!("!dm:localhost");
+    let (repaired_sender, mut repaired_receiver) = mpsc::unbounded_channel();
+
+    // The room is a DM with Bob.
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder
+        .add_joined_room(JoinedRoomBuilder::new(dm_room_id).add_state_bulk([
+            sync_state_event!({
+                "content": { "membership": "join" },
+                "event_id": "$bob_join",
+                "origin_server_ts": 151800140,
+                "sender": bob,
+                "state_key": bob,
+                "type": "m.room.member",
+            }),
+            sync_state_event!({
+                "content": { "membership": "join" },
+                "event_id": "$own_join",
+                "origin_server_ts": 151800150,
+                "sender": "@example:localhost",
+                "state_key": "@example:localhost",
+                "type": "m.room.member",
+            }),
+        ]))
+        .add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+            "content": { "@bob:localhost": ["!dm:localhost"] },
+            "type": "m.direct",
+        })));
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    assert_eq!(client.get_room(dm_room_id).unwrap().direct_targets_length(), 1);
+
+    Mock::given(method("GET"))
+        .and(path("_matrix/client/r0/user/@example:localhost/account_data/m.direct"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "@bob:localhost": ["!dm:localhost", "!other:localhost"],
+            "@alice:localhost": ["!dm:localhost"],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The left room is removed, along with the users we have no DM with anymore.
+    Mock::given(method("PUT"))
+        .and(path("_matrix/client/r0/user/@example:localhost/account_data/m.direct"))
+        .and(move |request: &Request| {
+            let Ok(content) = request.body_json::<DirectEventContent>() else {
+                return false;
+            };
+
+            content.len() == 1
+                && content.get(&OwnedDirectUserIdentifier::from(bob.to_owned()))
+                    == Some(&vec![room_id!("!other:localhost").to_owned()])
+        })
+        .respond_with(move |_: &Request| {
+            repaired_sender.send(()).unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({}))
+        })
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The user leaves the room, from another client.
+    sync_builder.add_left_room(LeftRoomBuilder::new(dm_room_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": { "membership": "leave" },
+            "event_id": "$own_leave",
+            "origin_server_ts": 151800160,
+            "sender": "@example:localhost",
+            "state_key": "@example:localhost",
+            "type": "m.room.member",
+        }),
+    ));
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+
+    // m.direct is updated in the background.
+    timeout(Duration::from_secs(5), repaired_receiver.recv()).await.unwrap().unwrap();
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn test_get_or_upload_filter() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/user/@example:localhost/filter"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "filter_id": "abc" })))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let mut definition = FilterDefinition::default();
+    definition.room.state.lazy_load_options =
+        LazyLoadOptions::Enabled { include_redundant_members: false };
+
+    let filter_id = client.get_or_upload_filter("sync", definition.clone()).await.unwrap();
+    assert_eq!(filter_id, "abc");
+
+    // The filter is only uploaded once.
+    let filter_id = client.get_or_upload_filter("sync", definition).await.unwrap();
+    assert_eq!(filter_id, "abc");
+
+    // The filter is uploaded again when its definition changes.
+    client.get_or_upload_filter("sync", FilterDefinition::default()).await.unwrap();
+}
+
+#[async_test]
+async fn test_sync_with_named_filter() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/user/@example:localhost/filter"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "filter_id": "abc" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/sync"))
+        .and(query_param("filter", "abc"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::SYNC))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let sync_settings = SyncSettings::new().named_filter("sync", FilterDefinition::default());
+
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    client.sync_once(sync_settings).await.unwrap();
+}
+
+#[async_test]
+async fn test_server_notices() {
+    let (client, server) = logged_in_client_with_server().await;
+    let room_id = room_id!("!notices:localhost");
+
+    let mut notices = client.subscribe_to_server_notices();
+    assert!(client.server_notices_room().is_none());
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_account_data(RoomAccountDataTestEvent::Custom(json!({
+                "content": { "tags": { "m.server_notice": {} } },
+                "type": "m.tag",
+            })))
+            .add_timeline_bulk([
+                sync_timeline_event!({
+                    "content": {
+                        "msgtype": "m.server_notice",
+                        "body": "You've reached the monthly active user limit",
+                        "server_notice_type": "m.server_notice.usage_limit_reached",
+                        "admin_contact": "mailto:admin@localhost",
+                        "limit_type": "monthly_active_user",
+                    },
+                    "event_id": "$notice",
+                    "origin_server_ts": 151800140,
+                    "sender": "@notices:localhost",
+                    "type": "m.room.message",
+                }),
+                // Notices sent by users of other homeservers are ignored.
+                sync_timeline_event!({
+                    "content": {
+                        "msgtype": "m.server_notice",
+                        "body": "Fake notice",
+                        "server_notice_type": "m.server_notice.usage_limit_reached",
+                    },
+                    "event_id": "$fake_notice",
+                    "origin_server_ts": 151800150,
+                    "sender": "@mallory:example.org",
+                    "type": "m.room.message",
+                }),
+            ]),
+    );
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+
+    assert_eq!(client.server_notices_room().unwrap().room_id(), room_id);
+
+    let notice = notices.try_recv().unwrap();
+    assert_eq!(notice.room_id, room_id);
+    assert_eq!(notice.event_id, "$notice");
+    assert_eq!(notice.body, "You've reached the monthly active user limit");
+    assert_let!(ServerNoticeKind::UsageLimitReached(usage_limit) = notice.kind);
+    assert_eq!(usage_limit.limit_type, Some(LimitType::MonthlyActiveUser));
+    assert_eq!(usage_limit.admin_contact.as_deref(), Some("mailto:admin@localhost"));
+
+    assert!(notices.try_recv().is_err());
+}
+
+#[async_test]
+async fn test_test_ambiguity_changes() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let example_id = user_id!("@example:localhost");
+    let example_2_id = user_id!("@example2:localhost");
+    let example_3_id = user_id!("@example3:localhost");
+
+    let mut updates = BroadcastStream::new(client.subscribe_to_room_updates(&DEFAULT_TEST_ROOM_ID));
+    assert_pending!(updates);
+
+    // Initial sync, adds 2 members.
+    mock_sync(&server, &*test_json::SYNC, None).await;
+    let response = client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+
+    let changes = &response.rooms.join.get(*DEFAULT_TEST_ROOM_ID).unwrap().ambiguity_changes;
+
+    // A new member always triggers an ambiguity change.
+    let example_change = changes.get(event_id!("$151800140517rfvjc:localhost")).unwrap();
+    assert_eq!(example_change.member_id, example_id);
+    assert!(!example_change.member_ambiguous);
+    assert_eq!(example_change.ambiguated_member, None);
+    assert_eq!(example_change.disambiguated_member, None);
+
+    let example_2_change = changes.get(event_id!("$152034824468gOeNB:localhost")).unwrap();
+    assert_eq!(example_2_change.member_id, example_2_id);
+    assert!(!example_2_change.member_ambiguous);
+    assert_eq!(example_2_change.ambiguated_member, None);
+    assert_eq!(example_2_change.disambiguated_member, None);
+
+    let example = room.get_member_no_sync(example_id).await.unwrap().unwrap();
+    assert!(!example.name_ambiguous());
+    let example_2 = room.get_member_no_sync(example_2_id).await.unwrap().unwrap();
+    assert!(!example_2.name_ambiguous());
+
+    let changes = assert_next_matches!(updates, Ok(RoomUpdate::Joined { updates, .. }) => updates.ambiguity_changes);
+
+    let example_change = changes.get(event_id!("$151800140517rfvjc:localhost")).unwrap();
     assert_eq!(example_change.member_id, example_id);
     assert!(!example_change.member_ambiguous);
     assert_eq!(example_change.ambiguated_member, None);
@@ -1396,3 +2161,357 @@ async fn test_restore_room() {
     assert!(room.is_favourite());
     assert!(!room.pinned_event_ids().unwrap().is_empty());
 }
+
+#[async_test]
+async fn test_create_room_falls_back_to_default_room_version() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/capabilities"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "capabilities": {
+                "m.room_versions": {
+                    "default": "10",
+                    "available": {
+                        "10": "stable",
+                        "11": "stable",
+                    }
+                }
+            }
+        })))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let room_versions = client.supported_room_versions().await.unwrap();
+    assert_eq!(room_versions.default, RoomVersionId::V10);
+    assert!(room_versions.available.contains_key(&RoomVersionId::V11));
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/createRoom"))
+        .and(|request: &Request| {
+            let Ok(body) = request.body_json::<Raw<JsonValue>>() else {
+                return false;
+            };
+
+            // The unsupported room version has been removed from the request.
+            body.get_field::<RoomVersionId>("room_version").is_ok_and(|v| v.is_none())
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+          "room_id": "!sefiuhWgwghwWgh:example.com"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut request = create_room::v3::Request::new();
+    request.room_version = Some(RoomVersionId::V1);
+    client.create_room(request).await.unwrap();
+}
+
+#[async_test]
+async fn test_get_profiles_caches_and_coalesces_requests() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let alice = user_id!("@alice:localhost");
+    let bob = user_id!("@bob:localhost");
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/profile/@alice:localhost$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "displayname": "Alice" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/profile/@bob:localhost$"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Profile not found",
+        })))
+        .mount(&server)
+        .await;
+
+    // Concurrent lookups of the same user share a single request.
+    let (first, second) =
+        future::join(client.get_profiles([alice]), client.get_profiles([alice])).await;
+    assert_eq!(first.unwrap()[alice].display_name.as_deref(), Some("Alice"));
+    assert_eq!(second.unwrap()[alice].display_name.as_deref(), Some("Alice"));
+
+    // The profile is now served from the cache, and unknown profiles are left out.
+    let profiles = client.get_profiles([alice, bob]).await.unwrap();
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[alice].display_name.as_deref(), Some("Alice"));
+}
+
+#[async_test]
+async fn test_get_profiles_skips_failures() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let alice = user_id!("@alice:localhost");
+    let bob = user_id!("@bob:localhost");
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/profile/@alice:localhost$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "displayname": "Alice" })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/profile/@bob:localhost$"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "You can't see this profile",
+        })))
+        .mount(&server)
+        .await;
+
+    // The profile that couldn't be fetched is left out, the others are returned.
+    let profiles = client.get_profiles([alice, bob]).await.unwrap();
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[alice].display_name.as_deref(), Some("Alice"));
+}
+
+#[async_test]
+async fn test_get_profiles_refreshed_by_member_events() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let alice = user_id!("@alice:localhost");
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/profile/@alice:localhost$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "displayname": "Alice" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client.get_profiles([alice]).await.unwrap();
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(&DEFAULT_TEST_ROOM_ID).add_state_bulk([
+        sync_state_event!({
+            "content": {
+                "avatar_url": "mxc://localhost/alice",
+                "displayname": "Alice in Wonderland",
+                "membership": "join"
+            },
+            "event_id": "$alice_rename",
+            "origin_server_ts": 151800140,
+            "sender": alice,
+            "state_key": alice,
+            "type": "m.room.member",
+            "unsigned": {
+                "prev_content": {
+                    "displayname": "Alice",
+                    "membership": "join"
+                }
+            }
+        }),
+    ]));
+    // Alice has a custom profile in this room, it doesn't change her global profile.
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id!("!custom:localhost")).add_state_bulk([sync_state_event!({
+            "content": {
+                "displayname": "Alice the Dog",
+                "membership": "join"
+            },
+            "event_id": "$alice_custom_rename",
+            "origin_server_ts": 151800141,
+            "sender": alice,
+            "state_key": alice,
+            "type": "m.room.member",
+            "unsigned": {
+                "prev_content": {
+                    "displayname": "Alice the Cat",
+                    "membership": "join"
+                }
+            }
+        })]),
+    );
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+
+    let profiles = client.get_profiles([alice]).await.unwrap();
+    assert_eq!(profiles[alice].display_name.as_deref(), Some("Alice in Wonderland"));
+    assert_eq!(
+        profiles[alice].avatar_url.as_deref().map(|url| url.as_str()),
+        Some("mxc://localhost/alice")
+    );
+}
+
+#[async_test]
+async fn test_get_profiles_invalidated_by_member_events() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let alice = user_id!("@alice:localhost");
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/profile/@alice:localhost$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "displayname": "Alice" })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client.get_profiles([alice]).await.unwrap();
+
+    // Alice joins a room again with a new profile, it might be her global profile
+    // or a custom one.
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(&DEFAULT_TEST_ROOM_ID).add_state_bulk([
+        sync_state_event!({
+            "content": {
+                "displayname": "Alice in Wonderland",
+                "membership": "join"
+            },
+            "event_id": "$alice_rejoin",
+            "origin_server_ts": 151800140,
+            "sender": alice,
+            "state_key": alice,
+            "type": "m.room.member",
+            "unsigned": {
+                "prev_content": {
+                    "membership": "leave"
+                }
+            }
+        }),
+    ]));
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+
+    // The cached profile was invalidated, so it's fetched again.
+    Mock::given(method("GET"))
+        .and(path_regex(r"/profile/@alice:localhost$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "displayname": "Alice in Wonderland" })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let profiles = client.get_profiles([alice]).await.unwrap();
+    assert_eq!(profiles[alice].display_name.as_deref(), Some("Alice in Wonderland"));
+}
+
+#[async_test]
+async fn test_recently_active_contacts() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let alice = user_id!("@alice:localhost");
+    let bob = user_id!("@bob:localhost");
+    let carol = user_id!("@carol:localhost");
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder
+        .add_presence_event(PresenceTestEvent::Custom(json!({
+            "content": { "presence": "offline", "last_active_ago": 3_600_000 },
+            "sender": alice,
+            "type": "m.presence",
+        })))
+        .add_presence_event(PresenceTestEvent::Custom(json!({
+            "content": { "presence": "online", "currently_active": true },
+            "sender": bob,
+            "type": "m.presence",
+        })))
+        .add_presence_event(PresenceTestEvent::Custom(json!({
+            "content": { "presence": "unavailable", "last_active_ago": 60_000 },
+            "sender": carol,
+            "type": "m.presence",
+        })))
+        // Users whose last activity is unknown are left out.
+        .add_presence_event(PresenceTestEvent::Custom(json!({
+            "content": { "presence": "offline" },
+            "sender": "@dave:localhost",
+            "type": "m.presence",
+        })));
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    let response = client.sync_once(SyncSettings::default()).await.unwrap();
+
+    let contacts = client.recently_active_contacts(10).await.unwrap();
+    let user_ids: Vec<_> = contacts.iter().map(|activity| activity.user_id.as_ref()).collect();
+    assert_eq!(user_ids, [bob, carol, alice]);
+
+    let contacts = client.recently_active_contacts(1).await.unwrap();
+    assert_eq!(contacts.len(), 1);
+    assert_eq!(contacts[0].user_id, bob);
+
+    // The activity is persisted across sync responses.
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_presence_event(PresenceTestEvent::Custom(json!({
+        "content": { "presence": "offline" },
+        "sender": bob,
+        "type": "m.presence",
+    })));
+    mock_sync(&server, sync_builder.build_json_sync_response(), Some(response.next_batch)).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+
+    let bob_activity = client.contact_activity(bob).await.unwrap().unwrap();
+    assert_eq!(bob_activity.presence, PresenceState::Offline);
+    assert!(!bob_activity.currently_active);
+    assert!(bob_activity.last_active_at.is_some());
+    assert!(client.contact_activity(alice).await.unwrap().is_some());
+
+    // The change is saved in the store.
+    let stored = client
+        .store()
+        .get_kv_data(StateStoreDataKey::ContactActivity)
+        .await
+        .unwrap()
+        .and_then(StateStoreDataValue::into_contact_activity)
+        .unwrap();
+    assert_eq!(stored[bob].presence, PresenceState::Offline);
+    assert_eq!(stored.len(), 4);
+}
+
+#[async_test]
+async fn test_long_poll_driver() {
+    use futures_util::StreamExt as _;
+
+    let (client, server) = logged_in_client_with_server().await;
+
+    // The first request is rate-limited.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/.*/sync/?$"))
+        .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+            "errcode": "M_LIMIT_EXCEEDED",
+            "error": "Too many requests",
+            "retry_after_ms": 10,
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The position is passed from one request to the next.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/.*/sync/?$"))
+        .and(query_param("since", "s1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "next_batch": "s2" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/.*/sync/?$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "next_batch": "s1" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let driver = LongPollDriver::new(client)
+        .request_config(RequestConfig::new().disable_retry())
+        .min_interval(Duration::ZERO);
+    let stream = driver.stream(|previous: Option<&sync_events::v3::Response>| {
+        assign!(sync_events::v3::Request::new(), {
+            since: previous.map(|response| response.next_batch.clone()),
+        })
+    });
+    pin_mut!(stream);
+
+    let error = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(error.category(), ErrorCategory::RateLimited);
+
+    assert_eq!(stream.next().await.unwrap().unwrap().next_batch, "s1");
+    assert_eq!(stream.next().await.unwrap().unwrap().next_batch, "s2");
+}
@@ -1,5 +1,7 @@
 mod backups;
 mod cross_signing;
+mod devices;
+mod one_time_keys;
 mod recovery;
 mod secret_storage;
 mod verification;
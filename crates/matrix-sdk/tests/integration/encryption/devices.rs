@@ -0,0 +1,213 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use matrix_sdk::{
+    config::RequestConfig,
+    encryption::{
+        identities::{StaleDeviceAction, StaleDeviceReason},
+        EncryptionSettings, LocalTrust, SessionMismatch, SessionMismatchRecovery,
+    },
+    matrix_auth::{MatrixSession, MatrixSessionTokens},
+    test_utils::test_client_builder_with_server,
+    Error, SessionMeta,
+};
+use matrix_sdk_test::{async_test, test_json};
+use ruma::{device_id, user_id};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::logged_in_client_with_server;
+
+/// Mock the `/keys/query` endpoint, answering with the given keys for the
+/// `DEVICEID` device of `@example:localhost`.
+async fn mock_own_device_keys(server: &MockServer, ed25519: &str, curve25519: &str) {
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/r0/keys/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_keys": {
+                "@example:localhost": {
+                    "DEVICEID": {
+                        "algorithms": ["m.olm.v1.curve25519-aes-sha2", "m.megolm.v1.aes-sha2"],
+                        "device_id": "DEVICEID",
+                        "keys": {
+                            "curve25519:DEVICEID": curve25519,
+                            "ed25519:DEVICEID": ed25519,
+                        },
+                        "signatures": {},
+                        "user_id": "@example:localhost",
+                    },
+                },
+            },
+        })))
+        .mount(server)
+        .await;
+}
+
+#[async_test]
+async fn test_stale_devices_of_other_user() {
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = user_id!("@web2:localhost:8482");
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/r0/keys/query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(&*test_json::KEYS_QUERY_TWO_DEVICES_ONE_SIGNED),
+        )
+        .mount(&server)
+        .await;
+
+    let encryption = client.encryption();
+
+    // The devices have just been discovered, so they aren't stale yet.
+    let stale_devices = encryption.stale_devices(user_id, Duration::from_secs(3600)).await.unwrap();
+    assert!(stale_devices.is_empty());
+
+    // The device that isn't cross-signed is stale once the inactivity period has
+    // passed.
+    let stale_devices = encryption.stale_devices(user_id, Duration::ZERO).await.unwrap();
+    assert_eq!(stale_devices.len(), 1);
+
+    let stale_device = &stale_devices[0];
+    assert_eq!(stale_device.device.device_id(), device_id!("AVXFQWJUQA"));
+    assert_eq!(stale_device.reason, StaleDeviceReason::NotCrossSigned);
+    assert_eq!(stale_device.suggested_action(), StaleDeviceAction::Distrust);
+
+    encryption
+        .distrust_devices(user_id, &[stale_device.device.device_id().to_owned()])
+        .await
+        .unwrap();
+
+    let device = encryption.get_device(user_id, device_id!("AVXFQWJUQA")).await.unwrap().unwrap();
+    assert!(device.is_blacklisted());
+    let device = encryption.get_device(user_id, device_id!("JERTCKWUWG")).await.unwrap().unwrap();
+    assert!(!device.is_blacklisted());
+}
+
+#[async_test]
+async fn test_blacklist_device() {
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = user_id!("@web2:localhost:8482");
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/r0/keys/query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(&*test_json::KEYS_QUERY_TWO_DEVICES_ONE_SIGNED),
+        )
+        .mount(&server)
+        .await;
+
+    let encryption = client.encryption();
+    encryption.request_user_identity(user_id).await.unwrap();
+
+    let device = encryption.get_device(user_id, device_id!("AVXFQWJUQA")).await.unwrap().unwrap();
+    assert!(!device.is_blacklisted());
+
+    device.set_blacklisted(true).await.unwrap();
+    let device = encryption.get_device(user_id, device_id!("AVXFQWJUQA")).await.unwrap().unwrap();
+    assert!(device.is_blacklisted());
+
+    device.set_blacklisted(false).await.unwrap();
+    let device = encryption.get_device(user_id, device_id!("AVXFQWJUQA")).await.unwrap().unwrap();
+    assert!(!device.is_blacklisted());
+    assert_eq!(device.local_trust_state(), LocalTrust::Unset);
+}
+
+#[async_test]
+async fn test_check_own_device_keys() {
+    let (client, server) = logged_in_client_with_server().await;
+    let encryption = client.encryption();
+
+    let ed25519 = encryption.ed25519_key().await.unwrap();
+    let curve25519 = encryption.curve25519_key().await.unwrap().to_base64();
+    mock_own_device_keys(&server, &ed25519, &curve25519).await;
+
+    encryption.check_own_device_keys().await.unwrap();
+}
+
+#[async_test]
+async fn test_check_own_device_keys_not_uploaded_yet() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/r0/keys/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "device_keys": {} })))
+        .mount(&server)
+        .await;
+
+    // The device keys of a new device are uploaded during the first sync.
+    client.encryption().check_own_device_keys().await.unwrap();
+}
+
+#[async_test]
+async fn test_check_own_device_keys_mismatch() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    // The keys of another device.
+    mock_own_device_keys(
+        &server,
+        "loz5i40dP+azDtWvsD0L/xpnCjNkmrcvtXVXzCHX8Vw",
+        "LTpv2DGMhggPAXO02+7f68CNEp6A40F0Yl8B094Y8gc",
+    )
+    .await;
+
+    let error = client.encryption().check_own_device_keys().await.unwrap_err();
+    let mismatch = assert_matches!(error, Error::SessionMismatch(mismatch) => mismatch);
+    assert_matches!(
+        &mismatch,
+        SessionMismatch::MismatchedDeviceKeys { device_id, server_ed25519: Some(_), .. } => {
+            assert_eq!(device_id.as_str(), "DEVICEID");
+        }
+    );
+    assert_eq!(mismatch.recovery_options(), [SessionMismatchRecovery::RotateDevice]);
+}
+
+#[async_test]
+async fn test_restore_session_checks_device_keys() {
+    let (builder, server) = test_client_builder_with_server().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .with_encryption_settings(EncryptionSettings {
+            check_device_keys_on_restore: true,
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+
+    mock_own_device_keys(
+        &server,
+        "loz5i40dP+azDtWvsD0L/xpnCjNkmrcvtXVXzCHX8Vw",
+        "LTpv2DGMhggPAXO02+7f68CNEp6A40F0Yl8B094Y8gc",
+    )
+    .await;
+
+    let session = MatrixSession {
+        meta: SessionMeta {
+            user_id: user_id!("@example:localhost").to_owned(),
+            device_id: device_id!("DEVICEID").to_owned(),
+        },
+        tokens: MatrixSessionTokens { access_token: "1234".to_owned(), refresh_token: None },
+    };
+
+    let error = client.restore_session(session).await.unwrap_err();
+    assert_matches!(error, Error::SessionMismatch(SessionMismatch::MismatchedDeviceKeys { .. }));
+}
@@ -0,0 +1,66 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use assert_matches2::assert_matches;
+use matrix_sdk::test_utils::mocks::{KeyClaimOutcome, MatrixMockServer};
+use matrix_sdk_test::async_test;
+use ruma::{api::client::keys::claim_keys, room_id, server_name, OneTimeKeyAlgorithm};
+
+#[async_test]
+async fn test_one_time_keys_accounting() {
+    let server = MatrixMockServer::new().await;
+    server.mock_keys_upload().ok().mount().await;
+    server.mock_keys_claim().ok().mount().await;
+
+    let client = server.client_builder().build().await;
+    let user_id = client.user_id().unwrap().to_owned();
+    let device_id = client.device_id().unwrap().to_owned();
+
+    // The keys are uploaded around the first sync.
+    server.sync_joined_room(&client, room_id!("!room:localhost")).await;
+
+    let one_time_keys = server.one_time_keys();
+    let uploaded = one_time_keys.count(&user_id, &device_id);
+    assert!(uploaded > 0);
+
+    let request = claim_keys::v3::Request::new(BTreeMap::from([(
+        user_id.clone(),
+        BTreeMap::from([(device_id.clone(), OneTimeKeyAlgorithm::SignedCurve25519)]),
+    )]));
+
+    // A claim consumes a one-time key.
+    let response = client.send(request.clone()).await.unwrap();
+    assert_eq!(response.one_time_keys[&user_id][&device_id].len(), 1);
+    assert_eq!(one_time_keys.count(&user_id, &device_id), uploaded - 1);
+
+    // Nothing is returned once the keys are exhausted.
+    one_time_keys.exhaust(&user_id, &device_id);
+    let response = client.send(request.clone()).await.unwrap();
+    assert!(response.one_time_keys.is_empty());
+
+    // Nothing is returned either when the server of the user can't be reached.
+    one_time_keys.fail_server(server_name!("localhost"));
+    let response = client.send(request).await.unwrap();
+    assert!(response.one_time_keys.is_empty());
+    assert!(response.failures.contains_key("localhost"));
+
+    let claims = one_time_keys.claims();
+    assert_eq!(claims.len(), 3);
+    assert_eq!(one_time_keys.claim_count(&user_id, &device_id), 3);
+    assert_matches!(&claims[0].outcome, KeyClaimOutcome::OneTimeKey(_));
+    assert_eq!(claims[1].outcome, KeyClaimOutcome::Exhausted);
+    assert_eq!(claims[2].outcome, KeyClaimOutcome::FederationFailure);
+}
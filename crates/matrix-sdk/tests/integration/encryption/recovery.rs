@@ -12,24 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use assert_matches2::assert_let;
-use futures_util::StreamExt;
+use async_trait::async_trait;
+use futures_util::{pin_mut, StreamExt};
 use matrix_sdk::{
     config::RequestConfig,
     encryption::{
         backups::BackupState,
         recovery::{EnableProgress, RecoveryState},
+        secret_storage::{SecretStorageProvider, SecretStorageProviderError},
         BackupDownloadStrategy, CrossSigningResetAuthType,
     },
     matrix_auth::{MatrixSession, MatrixSessionTokens},
     test_utils::{no_retry_test_client_with_server, test_client_builder_with_server},
     Client,
 };
-use matrix_sdk_base::SessionMeta;
+use matrix_sdk_base::{SessionMeta, StateStoreDataKey};
 use matrix_sdk_test::async_test;
-use ruma::{api::client::uiaa, device_id, user_id, UserId};
+use ruma::{api::client::uiaa, device_id, events::secret::request::SecretName, user_id, UserId};
 use serde::Deserialize;
 use serde_json::{json, Value};
 use tokio::spawn;
@@ -456,6 +462,132 @@ async fn test_recovery_setup() {
     server.verify().await
 }
 
+#[derive(Clone, Default)]
+struct InMemorySecretProvider {
+    secrets: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+#[async_trait]
+impl SecretStorageProvider for InMemorySecretProvider {
+    async fn store_secret(
+        &self,
+        name: &SecretName,
+        secret: &str,
+    ) -> Result<(), SecretStorageProviderError> {
+        self.secrets.lock().unwrap().insert(name.to_string(), secret.to_owned());
+        Ok(())
+    }
+
+    async fn retrieve_secret(
+        &self,
+        name: &SecretName,
+    ) -> Result<Option<String>, SecretStorageProviderError> {
+        Ok(self.secrets.lock().unwrap().get(name.as_str()).cloned())
+    }
+}
+
+#[async_test]
+async fn test_recovery_setup_stores_secrets_in_provider() {
+    let user_id = user_id!("@example:morpheus.localhost");
+    let (client, server) = test_client(user_id).await;
+
+    let provider = InMemorySecretProvider::default();
+    client.encryption().secret_storage().set_provider(provider.clone()).await;
+
+    enable(user_id, &client, &server, true).await;
+
+    let secrets = provider.secrets.lock().unwrap().clone();
+    assert!(secrets.contains_key(SecretName::CrossSigningMasterKey.as_str()));
+    assert!(secrets.contains_key(SecretName::CrossSigningSelfSigningKey.as_str()));
+    assert!(secrets.contains_key(SecretName::CrossSigningUserSigningKey.as_str()));
+    assert!(secrets.contains_key(SecretName::RecoveryKey.as_str()));
+
+    server.verify().await
+}
+
+#[async_test]
+async fn test_recover_from_provider_without_provider() {
+    let user_id = user_id!("@example:morpheus.localhost");
+    let (client, _server) = test_client(user_id).await;
+
+    assert!(!client.encryption().recovery().recover_from_provider().await.unwrap());
+}
+
+#[async_test]
+async fn test_recover_from_provider() {
+    let user_id = user_id!("@example:morpheus.localhost");
+    let (client, server) = test_client(user_id).await;
+
+    let provider = InMemorySecretProvider::default();
+    client.encryption().secret_storage().set_provider(provider.clone()).await;
+
+    enable(user_id, &client, &server, true).await;
+    assert_eq!(provider.secrets.lock().unwrap().len(), 4);
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/r0/keys/query"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_keys": {}
+        })))
+        .expect(2)
+        .named("/keys/query POST")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/unstable/keys/signatures/upload"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "failures": {},
+        })))
+        .expect(1)
+        .named("/keys/signatures/upload POST")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("_matrix/client/r0/room_keys/version"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "algorithm": "m.megolm_backup.v1.curve25519-aes-sha2",
+            "auth_data": {
+                "public_key": "hdx5rSn94rBuvJI5cwnhKAVmFyZgfJjk7vwEBD6mIHc",
+                "signatures": {}
+            },
+            "count": 1,
+            "etag": "1",
+            "version": "1"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "_matrix/client/r0/user/{user_id}/account_data/m.secret_storage.default_key"
+        )))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "key": "yJWwBm2Ts8jHygTBslKpABFyykavhhfA",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The secrets held by the provider match the ones of the account, so they are
+    // all imported back without the recovery key.
+    let recovery = client.encryption().recovery();
+    assert!(recovery.recover_from_provider().await.unwrap());
+    assert_eq!(recovery.state(), RecoveryState::Enabled);
+    assert_eq!(client.encryption().backups().state(), BackupState::Enabled);
+
+    let status = client.encryption().cross_signing_status().await.unwrap();
+    assert!(status.is_complete());
+
+    server.verify().await
+}
+
 #[async_test]
 async fn test_recovery_setup_without_wait() {
     let user_id = user_id!("@example:morpheus.localhost");
@@ -701,6 +833,29 @@ async fn test_reset_recovery_key() {
     server.verify().await
 }
 
+#[async_test]
+async fn test_setup_recommendations_after_enabling() {
+    let user_id = user_id!("@example:morpheus.localhost");
+    let (client, server) = test_client(user_id).await;
+
+    enable(user_id, &client, &server, true).await;
+
+    let recovery = client.encryption().recovery();
+    assert_eq!(recovery.state(), RecoveryState::Enabled);
+
+    let recommendations =
+        recovery.setup_recommendations_stream(Duration::from_secs(30 * 24 * 60 * 60)).await;
+    pin_mut!(recommendations);
+
+    // The recovery key was just created, there's no need to confirm it, and the
+    // backup is enabled.
+    let recommendations = recommendations.next().await.unwrap();
+    assert!(!recommendations.enable_backup);
+    assert!(!recommendations.confirm_recovery_key);
+
+    server.verify().await
+}
+
 #[async_test]
 async fn test_recover_and_reset() {
     let user_id = user_id!("@example:morpheus.localhost");
@@ -768,6 +923,53 @@ async fn test_recover_and_reset() {
     server.verify().await
 }
 
+#[async_test]
+async fn test_confirm_recovery_key() {
+    let user_id = user_id!("@example:morpheus.localhost");
+    const SECRET_STORE_KEY: &str = "mypassphrase";
+    const KEY_ID: &str = "yJWwBm2Ts8jHygTBslKpABFyykavhhfA";
+
+    let session = MatrixSession {
+        meta: SessionMeta { user_id: user_id.into(), device_id: device_id!("DEVICEID").to_owned() },
+        tokens: MatrixSessionTokens { access_token: "1234".to_owned(), refresh_token: None },
+    };
+
+    let (client, server) = no_retry_test_client_with_server().await;
+
+    mock_secret_store_with_backup_key(user_id, KEY_ID, &server).await;
+
+    client.restore_session(session).await.unwrap();
+    client.encryption().wait_for_e2ee_initialization_tasks().await;
+
+    let recovery = client.encryption().recovery();
+    let last_confirmation = || async {
+        client
+            .store()
+            .get_kv_data(StateStoreDataKey::RecoveryKeyConfirmation)
+            .await
+            .unwrap()
+            .and_then(|value| value.into_recovery_key_confirmation())
+    };
+
+    // A wrong recovery key isn't confirmed.
+    recovery
+        .confirm_recovery_key("wrong passphrase")
+        .await
+        .expect_err("A wrong recovery key shouldn't be confirmed");
+    assert!(last_confirmation().await.is_none());
+
+    // The right one can decrypt the backup recovery key, the confirmation is
+    // recorded.
+    recovery
+        .confirm_recovery_key(SECRET_STORE_KEY)
+        .await
+        .expect("We should be able to confirm our recovery key");
+    assert!(last_confirmation().await.is_some());
+
+    // The secrets are not imported.
+    assert!(!client.encryption().backups().are_enabled().await);
+}
+
 #[async_test]
 async fn test_reset_identity() {
     let user_id = user_id!("@example:morpheus.localhost");
@@ -952,3 +1154,62 @@ async fn test_reset_identity() {
 
     server.verify().await;
 }
+
+#[async_test]
+async fn test_reset_cross_signing_signs_active_backup() {
+    let user_id = user_id!("@example:morpheus.localhost");
+    let (client, server) = test_client(user_id).await;
+
+    enable(user_id, &client, &server, true).await;
+    assert_eq!(client.encryption().backups().state(), BackupState::Enabled);
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/unstable/keys/device_signing/upload"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .named("Cross-signing keys upload")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/unstable/keys/signatures/upload"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .named("Signatures upload")
+        .mount(&server)
+        .await;
+
+    let backup_info = Arc::new(Mutex::new(None));
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/.*/room_keys/version/1$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with({
+            let backup_info = backup_info.clone();
+            move |request: &wiremock::Request| {
+                let content: Value = request.body_json().expect("The body should be a JSON body");
+                *backup_info.lock().unwrap() = Some(content);
+                ResponseTemplate::new(200).set_body_json(json!({}))
+            }
+        })
+        .expect(1)
+        .named("room_keys/version PUT")
+        .mount(&server)
+        .await;
+
+    let handle = client.encryption().reset_cross_signing().await.unwrap();
+    assert!(handle.is_none(), "No additional authentication should have been required");
+
+    server.verify().await;
+
+    // The backup is signed by the new master key, and by the current device.
+    let identity = client.encryption().get_user_identity(user_id).await.unwrap().unwrap();
+    let master_key = identity.master_key().get_first_key().unwrap().to_base64();
+
+    let backup_info = backup_info.lock().unwrap().take().unwrap();
+    let signatures = backup_info["auth_data"]["signatures"][user_id.as_str()]
+        .as_object()
+        .expect("The backup should be signed by the user");
+    assert!(signatures.contains_key("ed25519:DEVICEID"));
+    assert!(signatures.contains_key(&format!("ed25519:{master_key}")));
+}
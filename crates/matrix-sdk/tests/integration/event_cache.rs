@@ -8,13 +8,15 @@ use assert_matches::assert_matches;
 use assert_matches2::assert_let;
 use eyeball_im::VectorDiff;
 use matrix_sdk::{
-    assert_let_timeout, assert_next_matches_with_timeout,
+    assert_let_timeout, assert_next_matches_with_timeout, async_trait,
     deserialized_responses::SyncTimelineEvent,
+    enrichment::EventEnricher,
     event_cache::{
         paginator::PaginatorState, BackPaginationOutcome, EventCacheError, PaginationToken,
         RoomEventCacheUpdate, TimelineHasBeenResetWhilePaginating,
     },
     test_utils::{assert_event_matches_msg, mocks::MatrixMockServer},
+    Room,
 };
 use matrix_sdk_test::{
     async_test, event_factory::EventFactory, GlobalAccountDataTestEvent, JoinedRoomBuilder,
@@ -1442,3 +1444,142 @@ async fn test_dont_delete_gap_that_wasnt_inserted() {
     // This doesn't cause an update, because nothing changed.
     assert!(stream.is_empty());
 }
+
+struct EventIdEnricher;
+
+#[async_trait]
+impl EventEnricher for EventIdEnricher {
+    fn key(&self) -> &str {
+        "org.example.event_id"
+    }
+
+    async fn enrich(&self, _room: &Room, event: &SyncTimelineEvent) -> Option<serde_json::Value> {
+        Some(json!(event.event_id()?))
+    }
+}
+
+#[async_test]
+async fn test_backpaginated_events_are_enriched() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    client.add_event_enricher(EventIdEnricher);
+
+    let event_cache = client.event_cache();
+    event_cache.subscribe().unwrap();
+
+    let room_id = room_id!("!omelette:fromage.fr");
+    let f = EventFactory::new().room(room_id).sender(user_id!("@a:b.c"));
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("world").event_id(event_id!("$2")))
+                .set_timeline_limited()
+                .set_timeline_prev_batch("prev-batch".to_owned()),
+        )
+        .await;
+
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+    let (events, mut stream) = room_event_cache.subscribe().await.unwrap();
+    wait_for_initial_events(events, &mut stream).await;
+
+    server
+        .mock_room_messages()
+        .ok(
+            "start-token-unused".to_owned(),
+            None,
+            vec![f.text_msg("hello").event_id(event_id!("$1"))],
+            Vec::new(),
+        )
+        .mock_once()
+        .mount()
+        .await;
+
+    room_event_cache.pagination().run_backwards(20, once).await.unwrap();
+
+    // The events from the sync and from the back-pagination are both enriched.
+    let (events, _stream) = room_event_cache.subscribe().await.unwrap();
+    assert_eq!(events.len(), 2);
+
+    for event in &events {
+        assert_eq!(
+            event.enrichments.get::<String>("org.example.event_id").unwrap().unwrap(),
+            event.event_id().unwrap().as_str()
+        );
+    }
+}
+
+#[async_test]
+async fn test_sync_to_unloaded_room_then_subscribe() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let event_cache = client.event_cache();
+
+    event_cache.subscribe().unwrap();
+    event_cache.enable_storage().unwrap();
+
+    // Only keep a single event in memory.
+    event_cache.set_memory_budget(Some(1));
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let f = EventFactory::new().room(room_id).sender(user_id!("@ben:saucisse.bzh"));
+
+    // The first sync exceeds the budget, and nobody observes the room, so its events
+    // get unloaded.
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("hello").event_id(event_id!("$1")))
+                .add_timeline_event(f.text_msg("world").event_id(event_id!("$2"))),
+        )
+        .await;
+
+    // The second sync brings new events to the unloaded room.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("how").event_id(event_id!("$3")))
+                .add_timeline_event(f.text_msg("are you").event_id(event_id!("$4")))
+                .set_timeline_limited()
+                .set_timeline_prev_batch("prev-batch".to_owned()),
+        )
+        .await;
+
+    // Sync responses are handled in order: once an event of a third sync is known,
+    // the previous syncs have been handled too.
+    let marker_room_id = room_id!("!crepe:saucisse.bzh");
+    let marker_room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(marker_room_id).add_timeline_event(
+                f.text_msg("marker").room(marker_room_id).event_id(event_id!("$marker")),
+            ),
+        )
+        .await;
+    let (marker_event_cache, _marker_drop_handles) = marker_room.event_cache().await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while marker_event_cache.event(event_id!("$marker")).await.is_none() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the sync responses should have been handled");
+
+    // Subscribing to the room reloads all its events, from both syncs, in order.
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+    let (events, stream) = room_event_cache.subscribe().await.unwrap();
+
+    assert_eq!(events.len(), 4);
+    assert_event_matches_msg(&events[0], "hello");
+    assert_event_matches_msg(&events[1], "world");
+    assert_event_matches_msg(&events[2], "how");
+    assert_event_matches_msg(&events[3], "are you");
+
+    assert!(stream.is_empty());
+}
@@ -16,6 +16,7 @@ mod event_cache;
 mod matrix_auth;
 mod media;
 mod notification;
+mod peeked_room;
 mod refresh_token;
 mod room;
 mod room_preview;
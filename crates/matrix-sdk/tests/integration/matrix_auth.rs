@@ -1,11 +1,14 @@
-use std::{collections::BTreeMap, sync::Mutex};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
 
 use assert_matches::assert_matches;
 use matrix_sdk::{
     config::RequestConfig,
     matrix_auth::{MatrixSession, MatrixSessionTokens},
     test_utils::{logged_in_client_with_server, no_retry_test_client_with_server},
-    AuthApi, AuthSession, Client, RumaApiError,
+    AuthApi, AuthSession, Client, Error, RumaApiError,
 };
 use matrix_sdk_base::SessionMeta;
 use matrix_sdk_test::{async_test, test_json};
@@ -23,12 +26,12 @@ use ruma::{
     assign, device_id,
     encryption::CrossSigningKey,
     serde::Raw,
-    user_id, OwnedUserId,
+    uint, user_id, ClientSecret, OwnedUserId,
 };
 use serde_json::{from_value as from_json_value, json, to_value as to_json_value};
 use url::Url;
 use wiremock::{
-    matchers::{method, path},
+    matchers::{body_partial_json, header, method, path},
     Mock, MockServer, Request, ResponseTemplate,
 };
 
@@ -258,6 +261,263 @@ async fn test_login_error() {
     }
 }
 
+#[async_test]
+async fn test_register_with_registration_token() {
+    let (client, server) = no_retry_test_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .and(body_partial_json(json!({
+            "auth": {
+                "type": "m.login.registration_token",
+                "token": "0123456789",
+                "session": "session",
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@alice:localhost",
+            "access_token": "abc123",
+            "device_id": "DEVICEID",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [
+                { "stages": ["m.login.email.identity"] },
+                { "stages": ["m.login.registration_token"] },
+            ],
+            "params": {},
+            "session": "session",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let response = client
+        .register()
+        .username("alice")
+        .password("hunter2")
+        .registration_token("0123456789")
+        .await
+        .unwrap();
+
+    assert_eq!(response.user_id, "@alice:localhost");
+    assert!(client.logged_in());
+    assert_eq!(client.user_id().unwrap(), "@alice:localhost");
+}
+
+#[async_test]
+async fn test_register_with_email_identity() {
+    let (client, server) = no_retry_test_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register/email/requestToken"))
+        .and(body_partial_json(json!({
+            "client_secret": "secret",
+            "email": "alice@example.org",
+            "send_attempt": 1,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "sid": "sid" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .and(body_partial_json(json!({
+            "auth": {
+                "type": "m.login.email.identity",
+                "threepid_creds": { "sid": "sid", "client_secret": "secret" },
+                "session": "session",
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@alice:localhost",
+            "access_token": "abc123",
+            "device_id": "DEVICEID",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.email.identity"] }],
+            "params": {},
+            "session": "session",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client_secret = ClientSecret::parse("secret").unwrap();
+    let sid = client
+        .matrix_auth()
+        .request_registration_email_token("alice@example.org", client_secret.clone(), uint!(1))
+        .await
+        .unwrap();
+
+    let response = client
+        .register()
+        .username("alice")
+        .password("hunter2")
+        .email_identity(sid, client_secret)
+        .await
+        .unwrap();
+
+    assert_eq!(response.user_id, "@alice:localhost");
+    assert_eq!(client.user_id().unwrap(), "@alice:localhost");
+}
+
+#[async_test]
+async fn test_register_with_recaptcha() {
+    let (client, server) = no_retry_test_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .and(body_partial_json(json!({
+            "auth": {
+                "type": "m.login.recaptcha",
+                "response": "captcha response",
+                "session": "session",
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@alice:localhost",
+            "access_token": "abc123",
+            "device_id": "DEVICEID",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.recaptcha"] }],
+            "params": { "m.login.recaptcha": { "public_key": "public key" } },
+            "session": "session",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let public_keys = Arc::new(Mutex::new(Vec::new()));
+    let hook_public_keys = public_keys.clone();
+
+    let response = client
+        .register()
+        .username("alice")
+        .password("hunter2")
+        .recaptcha(move |public_key| {
+            hook_public_keys.lock().unwrap().push(public_key);
+            async { Ok("captcha response".to_owned()) }
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(response.user_id, "@alice:localhost");
+    assert_eq!(*public_keys.lock().unwrap(), [Some("public key".to_owned())]);
+}
+
+#[async_test]
+async fn test_register_upgrade_guest() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "kind": "user",
+            "username": "example",
+            "auth": { "type": "m.login.dummy", "session": "session" },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@example:localhost",
+            "access_token": "abc123",
+            "device_id": "DEVICEID",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.dummy"] }],
+            "params": {},
+            "session": "session",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client.register().upgrade_guest().username("example").password("hunter2").await.unwrap();
+
+    // The session of the guest account was updated.
+    assert_eq!(client.user_id().unwrap(), "@example:localhost");
+    assert_eq!(client.access_token().unwrap(), "abc123");
+}
+
+#[async_test]
+async fn test_register_upgrade_guest_with_another_user_id() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@mallory:localhost",
+            "access_token": "abc123",
+            "device_id": "DEVICEID",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let error = client
+        .register()
+        .upgrade_guest()
+        .username("mallory")
+        .password("hunter2")
+        .await
+        .unwrap_err();
+
+    assert_matches!(error, Error::GuestUpgradeMismatch { guest, registered } => {
+        assert_eq!(guest, "@example:localhost");
+        assert_eq!(registered, "@mallory:localhost");
+    });
+
+    // The session of the guest account was kept.
+    assert_eq!(client.access_token().unwrap(), "1234");
+}
+
+#[async_test]
+async fn test_register_with_unsupported_stage() {
+    let (client, server) = no_retry_test_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/register"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.terms"] }],
+            "params": {},
+            "session": "session",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let error = client.register().username("alice").password("hunter2").await.unwrap_err();
+
+    let info = error.as_uiaa_response().unwrap();
+    assert_eq!(info.flows[0].stages, vec![uiaa::AuthType::Terms]);
+    assert!(!client.logged_in());
+}
+
 #[async_test]
 async fn test_register_error() {
     let (client, server) = no_retry_test_client_with_server().await;
@@ -1,20 +1,38 @@
+#[cfg(feature = "e2e-encryption")]
+use assert_matches::assert_matches;
+use eyeball::SharedObservable;
 use matrix_sdk::{
     config::RequestConfig,
     matrix_auth::{MatrixSession, MatrixSessionTokens},
-    media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
-    test_utils::logged_in_client_with_server,
-    Client, SessionMeta,
+    media::{
+        MediaFormat, MediaPolicy, MediaRequestParameters, MediaThumbnailSettings,
+        NetworkMediaPolicy, NetworkType,
+    },
+    prefetch::RoomPrefetcher,
+    test_utils::{
+        logged_in_client_with_server, mocks::MatrixMockServer, test_client_builder_with_server,
+    },
+    Client, SessionMeta, TransmissionProgress,
+};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk::{encryption::DecryptorError, Error};
+use matrix_sdk_base::{StateStoreDataKey, StateStoreDataValue};
+use matrix_sdk_test::{
+    async_test, event_factory::EventFactory, test_json, JoinedRoomBuilder,
+    RoomAccountDataTestEvent, StateTestEvent,
 };
-use matrix_sdk_test::async_test;
 use ruma::{
-    api::client::media::get_content_thumbnail::v3::Method,
+    api::{client::media::get_content_thumbnail::v3::Method, MatrixVersion},
     assign, device_id,
-    events::room::{message::ImageMessageEventContent, ImageInfo, MediaSource},
-    mxc_uri, owned_mxc_uri, uint, user_id,
+    events::room::{
+        avatar::RoomAvatarEventContent, message::ImageMessageEventContent, ImageInfo, MediaSource,
+    },
+    mxc_uri, owned_mxc_uri, room_id, uint, user_id,
 };
 use serde_json::json;
+use url::Url;
 use wiremock::{
-    matchers::{header, method, path, query_param},
+    matchers::{body_json, header, method, path, path_regex, query_param},
     Mock, ResponseTemplate,
 };
 
@@ -465,3 +483,436 @@ async fn test_async_media_upload() {
         .await
         .unwrap();
 }
+
+#[async_test]
+async fn test_download_to_file() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .named("versions")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/textfile"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
+        .named("get_file")
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("textfile.txt");
+    let progress = SharedObservable::new(TransmissionProgress::default());
+
+    client
+        .media()
+        .download_to_file(
+            &MediaSource::Plain(owned_mxc_uri!("mxc://localhost/textfile")),
+            &file_path,
+            progress.clone(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&file_path).unwrap(), b"Hello, World!");
+
+    let progress = progress.get();
+    assert_eq!(progress.current, 13);
+    assert_eq!(progress.total, 13);
+}
+
+#[async_test]
+async fn test_download_to_file_refreshes_token() {
+    let (builder, server) = test_client_builder_with_server().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .handle_refresh_tokens()
+        .build()
+        .await
+        .unwrap();
+    client
+        .matrix_auth()
+        .restore_session(MatrixSession {
+            meta: SessionMeta {
+                user_id: user_id!("@example:localhost").to_owned(),
+                device_id: device_id!("DEVICEID").to_owned(),
+            },
+            tokens: MatrixSessionTokens {
+                access_token: "1234".to_owned(),
+                refresh_token: Some("abcd".to_owned()),
+            },
+        })
+        .await
+        .unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::REFRESH_TOKEN))
+        .expect(1)
+        .named("refresh")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/v3/download/localhost/textfile"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .expect(1)
+        .named("get_file_wrong_token")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/v3/download/localhost/textfile"))
+        .and(header("authorization", "Bearer 5678"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
+        .expect(1)
+        .named("get_file_good_token")
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("textfile.txt");
+
+    client
+        .media()
+        .download_to_file(
+            &MediaSource::Plain(owned_mxc_uri!("mxc://localhost/textfile")),
+            &file_path,
+            SharedObservable::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&file_path).unwrap(), b"Hello, World!");
+}
+
+#[async_test]
+async fn test_auto_download_media_policy() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .named("versions")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/image"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("image"))
+        .named("get_file")
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/thumbnail/localhost/image"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("thumbnail"))
+        .named("get_thumbnail")
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let media = client.media();
+    let content = ImageMessageEventContent::plain(
+        "image.png".to_owned(),
+        owned_mxc_uri!("mxc://localhost/image"),
+    )
+    .info(Box::new(assign!(ImageInfo::new(), { size: Some(uint!(2000)) })));
+    let settings = MediaThumbnailSettings::new(uint!(100), uint!(100));
+
+    // Only small files are downloaded automatically on metered networks.
+    let policy = MediaPolicy {
+        metered: NetworkMediaPolicy {
+            max_auto_download_size: Some(uint!(1000)),
+            thumbnails_only: false,
+        },
+        ..Default::default()
+    };
+    media.set_media_policy(policy.clone()).await.unwrap();
+    media.set_network_type(NetworkType::Metered);
+
+    assert_eq!(media.auto_download_file(&content, false).await.unwrap(), None);
+    assert_eq!(
+        media.auto_download_thumbnail(&content, settings.clone(), false).await.unwrap().unwrap(),
+        b"thumbnail"
+    );
+
+    // The policy is persisted.
+    let stored = client
+        .store()
+        .get_kv_data(StateStoreDataKey::MediaPolicy)
+        .await
+        .unwrap()
+        .and_then(StateStoreDataValue::into_media_policy);
+    assert_eq!(stored.as_ref(), Some(&policy));
+
+    // Only thumbnails are downloaded automatically in data saver mode.
+    media.set_network_type(NetworkType::Unmetered);
+    media.set_media_policy(MediaPolicy { data_saver: true, ..policy }).await.unwrap();
+
+    assert_eq!(media.auto_download_file(&content, false).await.unwrap(), None);
+    assert_eq!(
+        media.auto_download_thumbnail(&content, settings, false).await.unwrap().unwrap(),
+        b"thumbnail"
+    );
+
+    // Everything is downloaded automatically with the default policy.
+    media.set_media_policy(MediaPolicy::default()).await.unwrap();
+    assert_eq!(media.auto_download_file(&content, false).await.unwrap().unwrap(), b"image");
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_download_encrypted_to_file() {
+    use ruma::events::room::EncryptedFile;
+
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .named("versions")
+        .mount(&server)
+        .await;
+
+    let encrypted_data: &[u8] = &[
+        179, 154, 118, 127, 186, 127, 110, 33, 203, 33, 33, 134, 67, 100, 173, 46, 235, 27, 215,
+        172, 36, 26, 75, 47, 33, 160,
+    ];
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/encrypted"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(encrypted_data))
+        .named("get_encrypted_file")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/tampered"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(&encrypted_data[..10]))
+        .named("get_tampered_file")
+        .mount(&server)
+        .await;
+
+    let encrypted_file = |url: &str| -> Box<EncryptedFile> {
+        serde_json::from_value(json!({
+            "url": url,
+            "v": "v2",
+            "key": {
+                "kty": "oct",
+                "alg": "A256CTR",
+                "ext": true,
+                "k": "Voq2nkPme_x8no5-Tjq_laDAdxE6iDbxnlQXxwFPgE4",
+                "key_ops": ["encrypt", "decrypt"]
+            },
+            "iv": "i0DovxYdJEcAAAAAAAAAAA",
+            "hashes": {
+                "sha256": "ANdt819a8bZl4jKy3Z+jcqtiNICa2y0AW4BBJ/iQRAU"
+            }
+        }))
+        .unwrap()
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let media = client.media();
+
+    // The content is decrypted on the fly.
+    let file_path = dir.path().join("secret.txt");
+    media
+        .download_to_file(
+            &MediaSource::Encrypted(encrypted_file("mxc://localhost/encrypted")),
+            &file_path,
+            Default::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&file_path).unwrap(), b"It's a secret to everybody");
+
+    // The hash doesn't match, the file is removed.
+    let file_path = dir.path().join("tampered.txt");
+    let error = media
+        .download_to_file(
+            &MediaSource::Encrypted(encrypted_file("mxc://localhost/tampered")),
+            &file_path,
+            Default::default(),
+        )
+        .await
+        .unwrap_err();
+
+    assert_matches!(error, Error::DecryptorError(DecryptorError::HashMismatch));
+    assert!(!file_path.exists());
+}
+
+#[async_test]
+async fn test_get_url_preview() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v1/media/preview_url"))
+        .and(query_param("url", "https://example.org/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "og:title": " Example\u{0} ",
+            "og:description": "An example website",
+            "og:image": "mxc://example.org/image",
+            "og:image:width": 640,
+            "og:image:height": "480",
+            "matrix:image:size": 1024,
+        })))
+        // The second call uses the cache.
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let url = Url::parse("https://example.org").unwrap();
+    let preview = client.get_url_preview(&url, None).await.unwrap();
+
+    assert_eq!(preview.title.as_deref(), Some("Example"));
+    assert_eq!(preview.description.as_deref(), Some("An example website"));
+    assert_eq!(preview.site_name, None);
+    let image = preview.image.clone().unwrap();
+    assert_eq!(image.uri, owned_mxc_uri!("mxc://example.org/image"));
+    assert_eq!(image.width, Some(uint!(640)));
+    assert_eq!(image.height, Some(uint!(480)));
+    assert_eq!(image.size, Some(uint!(1024)));
+
+    let cached = client.get_url_preview(&url, None).await.unwrap();
+    assert_eq!(cached, preview);
+
+    // Only web pages can be previewed.
+    let url = Url::parse("file:///etc/passwd").unwrap();
+    let err = client.get_url_preview(&url, None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        matrix_sdk::Error::Media(matrix_sdk::media::MediaError::UnsupportedPreviewUrl)
+    ));
+}
+
+#[async_test]
+async fn test_room_url_previews_enabled() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:localhost");
+    let url = Url::parse("https://example.org").unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v1/media/preview_url"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "og:title": "Example",
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // URL previews are disabled by default in encrypted rooms, and no request is
+    // made.
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Encryption),
+        )
+        .await;
+    assert!(!room.url_previews_enabled().await.unwrap());
+    assert!(room.get_url_preview(&url, None).await.unwrap().is_none());
+
+    // The choice of the user takes precedence.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_account_data(RoomAccountDataTestEvent::Custom(
+                json!({
+                    "type": "org.matrix.room.preview_urls",
+                    "content": { "disable": false },
+                }),
+            )),
+        )
+        .await;
+    assert!(room.url_previews_enabled().await.unwrap());
+    let preview = room.get_url_preview(&url, None).await.unwrap().unwrap();
+    assert_eq!(preview.title.as_deref(), Some("Example"));
+
+    Mock::given(method("PUT"))
+        .and(path_regex(
+            r"^/_matrix/client/v3/user/.*/rooms/.*/account_data/org.matrix.room.preview_urls",
+        ))
+        .and(body_json(json!({ "disable": true })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    room.set_url_previews_enabled(false).await.unwrap();
+}
+
+#[async_test]
+async fn test_room_prefetcher() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().sender(user_id!("@alice:b.c"));
+    let mut avatar = RoomAvatarEventContent::new();
+    avatar.url = Some(owned_mxc_uri!("mxc://example.org/avatar"));
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk(vec![f
+                .event(avatar)
+                .state_key("")
+                .into_raw_sync()
+                .cast()]),
+        )
+        .await;
+
+    // The avatar is only downloaded once.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/.*/thumbnail/example.org/avatar"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("avatar", "image/png"))
+        .expect(1)
+        .named("get_avatar_thumbnail")
+        .mount(server.server())
+        .await;
+
+    let prefetcher = RoomPrefetcher::new(client.clone());
+    prefetcher.set_visible_rooms(vec![room_id.to_owned()]);
+    prefetcher.wait().await;
+
+    // Rendering the avatar uses the media cache.
+    let room = client.get_room(room_id).unwrap();
+    let format = MediaFormat::Thumbnail(MediaThumbnailSettings::new(uint!(96), uint!(96)));
+    assert_eq!(room.avatar(format).await.unwrap().unwrap(), b"avatar");
+
+    // Full avatars aren't prefetched in data saver mode.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/.*/download/example.org/avatar"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("avatar", "image/png"))
+        .expect(0)
+        .named("get_avatar_file")
+        .mount(server.server())
+        .await;
+
+    client
+        .media()
+        .set_media_policy(MediaPolicy { data_saver: true, ..Default::default() })
+        .await
+        .unwrap();
+
+    let prefetcher = RoomPrefetcher::new(client.clone()).avatar_format(MediaFormat::File);
+    prefetcher.set_visible_rooms(vec![room_id.to_owned()]);
+    prefetcher.wait().await;
+}
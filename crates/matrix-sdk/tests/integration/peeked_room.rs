@@ -0,0 +1,189 @@
+use assert_matches2::assert_let;
+use matrix_sdk::{peeked_room::PeekedRoom, test_utils::logged_in_client_with_server, Error};
+use matrix_sdk_test::async_test;
+use ruma::{event_id, owned_event_id, room_id, EventId, RoomId};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{method, path_regex, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+fn message(room_id: &RoomId, event_id: &EventId) -> JsonValue {
+    json!({
+        "content": { "body": "hello", "msgtype": "m.text" },
+        "event_id": event_id,
+        "origin_server_ts": 151957878,
+        "room_id": room_id,
+        "sender": "@alice:localhost",
+        "type": "m.room.message",
+    })
+}
+
+async fn mock_state(room_id: &RoomId, history_visibility: &str, server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path_regex(format!("/rooms/{room_id}/state$")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            {
+                "content": { "history_visibility": history_visibility },
+                "event_id": "$history_visibility",
+                "origin_server_ts": 151957878,
+                "room_id": room_id,
+                "sender": "@alice:localhost",
+                "state_key": "",
+                "type": "m.room.history_visibility",
+            },
+            {
+                "content": { "name": "Archive" },
+                "event_id": "$name",
+                "origin_server_ts": 151957878,
+                "room_id": room_id,
+                "sender": "@alice:localhost",
+                "state_key": "",
+                "type": "m.room.name",
+            },
+        ])))
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!("/rooms/{room_id}/joined_members$")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "joined": {} })))
+        .mount(server)
+        .await;
+}
+
+#[async_test]
+async fn test_peek_world_readable_room() {
+    let (client, server) = logged_in_client_with_server().await;
+    let room_id = room_id!("!archive:localhost");
+
+    mock_state(room_id, "world_readable", &server).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!("/rooms/{room_id}/messages$")))
+        .and(query_param("dir", "b"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [
+                message(room_id, event_id!("$2")),
+                message(room_id, event_id!("$1")),
+            ],
+            "start": "now",
+            "end": "before",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.peek_room(room_id.into()).await.unwrap();
+    assert_eq!(room.room_id(), room_id);
+    assert_eq!(room.preview().name.as_deref(), Some("Archive"));
+
+    let event_ids = |room: &PeekedRoom| {
+        room.events().iter().map(|event| event.event_id().unwrap()).collect::<Vec<_>>()
+    };
+    assert_eq!(event_ids(&room), vec![owned_event_id!("$1"), owned_event_id!("$2")]);
+
+    let mut subscriber = room.subscribe();
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!("/rooms/{room_id}/messages$")))
+        .and(query_param("dir", "f"))
+        .and(query_param("from", "now"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [message(room_id, event_id!("$3"))],
+            "start": "now",
+            "end": "later",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!("/rooms/{room_id}/messages$")))
+        .and(query_param("dir", "f"))
+        .and(query_param("from", "later"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [],
+            "start": "later",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert_eq!(room.poll_for_updates().await.unwrap(), 1);
+    assert_eq!(
+        event_ids(&room),
+        vec![owned_event_id!("$1"), owned_event_id!("$2"), owned_event_id!("$3")]
+    );
+    assert_eq!(subscriber.next_now().len(), 3);
+}
+
+#[async_test]
+async fn test_peek_room_requires_world_readable_history() {
+    let (client, server) = logged_in_client_with_server().await;
+    let room_id = room_id!("!private:localhost");
+
+    mock_state(room_id, "shared", &server).await;
+
+    assert_let!(Err(Error::NotWorldReadable) = client.peek_room(room_id.into()).await);
+}
+
+#[async_test]
+async fn test_poll_keeps_the_last_partial_page() {
+    let (client, server) = logged_in_client_with_server().await;
+    let room_id = room_id!("!archive:localhost");
+
+    mock_state(room_id, "world_readable", &server).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!("/rooms/{room_id}/messages$")))
+        .and(query_param("dir", "b"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [],
+            "start": "now",
+        })))
+        .mount(&server)
+        .await;
+
+    let room = client.peek_room(room_id.into()).await.unwrap();
+
+    // 25 new events: a full page of 20 events, then a partial page of 5 events,
+    // which comes without an `end` token.
+    let events = (0..25)
+        .map(|i| message(room_id, &EventId::parse(format!("${i}")).unwrap()))
+        .collect::<Vec<_>>();
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!("/rooms/{room_id}/messages$")))
+        .and(query_param("dir", "f"))
+        .and(query_param("from", "now"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": events[..20],
+            "start": "now",
+            "end": "page2",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!("/rooms/{room_id}/messages$")))
+        .and(query_param("dir", "f"))
+        .and(query_param("from", "page2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": events[20..],
+            "start": "page2",
+        })))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    assert_eq!(room.poll_for_updates().await.unwrap(), 25);
+    assert_eq!(room.events().len(), 25);
+    assert_eq!(room.events().back().unwrap().event_id(), Some(owned_event_id!("$24")));
+
+    // Polling again returns the events of the last page again, they're not
+    // duplicated.
+    assert_eq!(room.poll_for_updates().await.unwrap(), 0);
+    assert_eq!(room.events().len(), 25);
+}
@@ -7,6 +7,11 @@ use assert_matches::assert_matches;
 use assert_matches2::assert_let;
 use futures_util::StreamExt;
 use matrix_sdk::{
+    async_trait,
+    authentication::{
+        session_store::{EncryptedFileSessionStore, SessionEncryptionKey, SessionStore},
+        ReauthCredentials, ReauthHandler,
+    },
     config::RequestConfig,
     executor::spawn,
     matrix_auth::{MatrixSession, MatrixSessionTokens},
@@ -14,7 +19,7 @@ use matrix_sdk::{
         logged_in_client_with_server, no_retry_test_client_with_server,
         test_client_builder_with_server,
     },
-    HttpError, RefreshTokenError, SessionChange,
+    Client, HttpError, RefreshTokenError, SessionChange, TokenInvalidation,
 };
 use matrix_sdk_base::SessionMeta;
 use matrix_sdk_test::{async_test, test_json};
@@ -560,3 +565,156 @@ async fn test_refresh_token_handled_other_error() {
 
     client.whoami().await.unwrap_err();
 }
+
+#[async_test]
+async fn test_refresh_token_persisted_to_session_store() {
+    let (builder, server) = test_client_builder_with_server().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .build()
+        .await
+        .unwrap();
+    let auth = client.matrix_auth();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("session.json");
+    let passphrase = || SessionEncryptionKey::Passphrase("passphrase".to_owned());
+    let store = Arc::new(EncryptedFileSessionStore::open(&path, passphrase()).unwrap());
+
+    // Nothing was stored yet.
+    assert!(!auth.restore_session_from(&*store).await.unwrap());
+
+    auth.restore_session(session()).await.unwrap();
+    auth.persist_session_to(store.clone()).unwrap();
+
+    // The session is saved right away.
+    let stored = store.load().unwrap().unwrap();
+    assert_eq!(stored.homeserver, client.homeserver());
+    assert_eq!(stored.session, session());
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(&*test_json::REFRESH_TOKEN_WITH_REFRESH_TOKEN),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    auth.refresh_access_token().await.unwrap();
+
+    // The refreshed tokens are saved, and can be used to restore the session.
+    let store = EncryptedFileSessionStore::open(&path, passphrase()).unwrap();
+    let new_client = matrix_sdk::Client::builder()
+        .homeserver_url(client.homeserver())
+        .server_versions([MatrixVersion::V1_3])
+        .build()
+        .await
+        .unwrap();
+    assert!(new_client.matrix_auth().restore_session_from(&store).await.unwrap());
+
+    let tokens = new_client.matrix_auth().session_tokens().unwrap();
+    assert_eq!(tokens.access_token, "9012");
+    assert_eq!(tokens.refresh_token.as_deref(), Some("wxyz"));
+}
+
+struct PasswordReauthHandler;
+
+#[async_trait]
+impl ReauthHandler for PasswordReauthHandler {
+    async fn reauthenticate(&self, _client: &Client) -> Option<ReauthCredentials> {
+        Some(ReauthCredentials::Password("wordpass".to_owned()))
+    }
+}
+
+#[async_test]
+async fn test_soft_logout_without_reauth_handler() {
+    let (builder, server) = test_client_builder_with_server().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .build()
+        .await
+        .unwrap();
+    client.matrix_auth().restore_session(session()).await.unwrap();
+
+    let mut session_changes = client.subscribe_to_session_changes();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .mount(&server)
+        .await;
+
+    let error = client.whoami().await.unwrap_err();
+    assert_eq!(error.token_invalidation(), Some(TokenInvalidation::SoftLogout));
+
+    assert_eq!(session_changes.try_recv(), Ok(SessionChange::UnknownToken { soft_logout: true }));
+
+    // The session is kept, so the user can log in again on the same device.
+    assert_eq!(client.device_id(), Some(device_id!("DEVICEID")));
+    assert_eq!(client.access_token().as_deref(), Some("1234"));
+}
+
+#[async_test]
+async fn test_soft_logout_resumed_by_reauth_handler() {
+    let (builder, server) = test_client_builder_with_server().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .build()
+        .await
+        .unwrap();
+    client.matrix_auth().restore_session(session()).await.unwrap();
+    client.set_reauth_handler(PasswordReauthHandler);
+
+    let mut session_changes = client.subscribe_to_session_changes();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 1234"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .expect(1)
+        .named("`GET /whoami` soft logged out token")
+        .mount(&server)
+        .await;
+
+    // The user logs in again on the same device.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/login"))
+        .and(body_partial_json(json!({
+            "type": "m.login.password",
+            "identifier": { "type": "m.id.user", "user": "@example:localhost" },
+            "password": "wordpass",
+            "device_id": "DEVICEID",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "5678",
+            "device_id": "DEVICEID",
+            "user_id": "@example:localhost",
+        })))
+        .expect(1)
+        .named("`POST /login`")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 5678"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::WHOAMI))
+        .expect(1)
+        .named("`GET /whoami` new token")
+        .mount(&server)
+        .await;
+
+    client.whoami().await.unwrap();
+
+    assert_eq!(client.access_token().as_deref(), Some("5678"));
+    assert_eq!(client.device_id(), Some(device_id!("DEVICEID")));
+    assert_eq!(session_changes.try_recv(), Ok(SessionChange::TokensRefreshed));
+}
@@ -23,7 +23,7 @@ use ruma::{
 };
 use serde_json::json;
 use wiremock::{
-    matchers::{body_json, header, method, path, path_regex},
+    matchers::{body_json, header, method, path, path_regex, query_param, query_param_is_missing},
     Mock, ResponseTemplate,
 };
 
@@ -746,6 +746,84 @@ async fn test_event_with_context() {
     assert!(cache.event(next_event_id).await.is_some());
 }
 
+#[async_test]
+async fn test_jump_to_event() {
+    let event_id = event_id!("$cur1234");
+    let prev_event_id = event_id!("$prev1234");
+    let next_event_id = event_id!("$next_1234");
+
+    let (client, server) = logged_in_client_with_server().await;
+    let cache = client.event_cache();
+    let _ = cache.subscribe();
+
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(&DEFAULT_TEST_ROOM_ID)
+            .add_state_event(StateTestEvent::Member)
+            .add_state_event(StateTestEvent::PowerLevels),
+    );
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    let room_id = room.room_id();
+
+    let f = EventFactory::new().room(room_id).sender(*BOB);
+    let event =
+        f.event(RoomMessageEventContent::text_plain("The requested message")).event_id(event_id);
+    let event_before =
+        f.event(RoomMessageEventContent::text_plain("A previous message")).event_id(prev_event_id);
+    let event_next =
+        f.event(RoomMessageEventContent::text_plain("A newer message")).event_id(next_event_id);
+    let context_response = json!({
+        "events_before": [event_before.into_raw_timeline()],
+        "event": event.into_raw_timeline(),
+        "events_after": [event_next.into_raw_timeline()],
+        "state": [],
+        "start": "prev",
+        "end": "next",
+    });
+
+    Mock::given(method("GET"))
+        .and(path(format!("/_matrix/client/r0/rooms/{room_id}/context/{event_id}")))
+        .and(query_param("limit", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&context_response))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let response = room.jump_to_event(event_id, 1, 1).await.unwrap();
+    assert_let!(Some(timeline_event) = response.event);
+    assert_eq!(timeline_event.event_id().as_deref(), Some(event_id));
+    assert_eq!(response.events_before.len(), 1);
+    assert_eq!(response.events_after.len(), 1);
+    assert_eq!(response.prev_batch_token.as_deref(), Some("prev"));
+    assert_eq!(response.next_batch_token.as_deref(), Some("next"));
+
+    // Jumping to the same event again uses the island in the event cache.
+    let response = room.jump_to_event(event_id, 1, 0).await.unwrap();
+    assert_let!(Some(timeline_event) = response.event);
+    assert_eq!(timeline_event.event_id().as_deref(), Some(event_id));
+    assert_eq!(response.events_before[0].event_id().as_deref(), Some(prev_event_id));
+    assert_eq!(response.events_after[0].event_id().as_deref(), Some(next_event_id));
+    assert_eq!(response.prev_batch_token.as_deref(), Some("prev"));
+
+    // Asking for more context than the island has needs a new request.
+    Mock::given(method("GET"))
+        .and(path(format!("/_matrix/client/r0/rooms/{room_id}/context/{event_id}")))
+        .and(query_param("limit", "10"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&context_response))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    room.jump_to_event(event_id, 5, 1).await.unwrap();
+}
+
 #[async_test]
 async fn test_is_direct() {
     let (client, server) = logged_in_client_with_server().await;
@@ -921,3 +999,110 @@ async fn test_room_avatar() {
     assert_eq!(avatar_info.mimetype.as_deref(), Some("image/png"));
     assert_eq!(avatar_info.size, Some(uint!(5243)));
 }
+
+#[async_test]
+async fn test_state_event_history() {
+    let (client, server) = logged_in_client_with_server().await;
+    let room_id = room_id!("!test:localhost");
+
+    let topic = |event_id: &str, sender: &str, topic: &str, ts: u64| {
+        json!({
+            "content": { "topic": topic },
+            "event_id": event_id,
+            "origin_server_ts": ts,
+            "sender": sender,
+            "state_key": "",
+            "type": "m.room.topic",
+        })
+    };
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(topic(
+            "$2",
+            "@bob:localhost",
+            "Second",
+            2000,
+        ))),
+    );
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(query_param("dir", "b"))
+        .and(query_param_is_missing("from"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [topic("$2", "@bob:localhost", "Second", 2000)],
+            "start": "now",
+            "end": "before",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(query_param("dir", "b"))
+        .and(query_param("from", "before"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [topic("$1", "@alice:localhost", "First", 1000)],
+            "start": "before",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let history = room.state_event_history(StateEventType::RoomTopic, "").await.unwrap();
+
+    let summary = history
+        .iter()
+        .map(|change| (change.event_id.as_str(), change.sender.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(summary, vec![("$2", "@bob:localhost"), ("$1", "@alice:localhost")]);
+    assert_eq!(history[1].origin_server_ts.0, uint!(1000));
+    assert_let!(Ok(AnySyncStateEvent::RoomTopic(event)) = history[1].event.deserialize());
+    assert_eq!(event.as_original().unwrap().content.topic, "First");
+
+    // The history is cached, since the topic didn't change.
+    let cached_history = room.state_event_history(StateEventType::RoomTopic, "").await.unwrap();
+    assert_eq!(cached_history.len(), 2);
+}
+
+#[async_test]
+async fn test_state_event_history_is_capped() {
+    let (client, server) = logged_in_client_with_server().await;
+    let room_id = room_id!("!test:localhost");
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    // The homeserver always has older events to return.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(query_param("dir", "b"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [{
+                "content": { "topic": "Topic" },
+                "event_id": "$topic",
+                "origin_server_ts": 1000,
+                "sender": "@alice:localhost",
+                "state_key": "",
+                "type": "m.room.topic",
+            }],
+            "start": "now",
+            "end": "before",
+        })))
+        .expect(10)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let history = room.state_event_history(StateEventType::RoomTopic, "").await.unwrap();
+    assert_eq!(history.len(), 10);
+}
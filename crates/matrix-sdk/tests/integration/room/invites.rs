@@ -0,0 +1,119 @@
+use futures_util::{pin_mut, StreamExt as _};
+use matrix_sdk::test_utils::mocks::MatrixMockServer;
+use matrix_sdk_test::{async_test, stripped_state_event, InvitedRoomBuilder};
+use ruma::{room_id, user_id};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+fn invited_room_builder() -> InvitedRoomBuilder {
+    InvitedRoomBuilder::new(room_id!("!invite:localhost")).add_state_bulk([
+        stripped_state_event!({
+            "content": {
+                "displayname": "Bob",
+                "membership": "join"
+            },
+            "sender": "@bob:localhost",
+            "state_key": "@bob:localhost",
+            "type": "m.room.member",
+        }),
+        stripped_state_event!({
+            "content": {
+                "membership": "invite"
+            },
+            "event_id": "$invite",
+            "sender": "@bob:localhost",
+            "state_key": "@example:localhost",
+            "type": "m.room.member",
+        }),
+    ])
+}
+
+#[async_test]
+async fn test_pending_invites() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server.sync_room(&client, invited_room_builder()).await;
+
+    let pending_invites = client.pending_invites();
+    pin_mut!(pending_invites);
+
+    let invites = pending_invites.next().await.unwrap();
+    assert_eq!(invites.len(), 1);
+
+    let invite = &invites[0];
+    assert_eq!(invite.room_id(), room_id!("!invite:localhost"));
+    assert_eq!(invite.shared_rooms_count, 0);
+
+    let inviter = invite.invite.inviter.as_ref().expect("the inviter should be known");
+    assert_eq!(inviter.user_id(), user_id!("@bob:localhost"));
+    assert_eq!(inviter.display_name(), Some("Bob"));
+}
+
+#[async_test]
+async fn test_decline_invite_and_report_as_spam() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room = server.sync_room(&client, invited_room_builder()).await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/leave"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/m.ignored_user_list"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/report/\$invite"))
+        .and(body_partial_json(json!({ "reason": "spam" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let invite = room.invite_details().await.unwrap();
+    invite.decline(true, Some("spam".to_owned())).await.unwrap();
+}
+
+#[async_test]
+async fn test_decline_invite_without_reporting() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room = server.sync_room(&client, invited_room_builder()).await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/leave"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/m.ignored_user_list"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(0)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/report/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(0)
+        .mount(server.server())
+        .await;
+
+    let invite = room.invite_details().await.unwrap();
+    invite.decline(false, None).await.unwrap();
+}
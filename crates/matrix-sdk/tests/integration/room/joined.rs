@@ -4,13 +4,33 @@ use std::{
     time::Duration,
 };
 
+use assert_matches::assert_matches;
 use assert_matches2::assert_let;
+use eyeball::SharedObservable;
+use eyeball_im::VectorDiff;
 use futures_util::{future::join_all, pin_mut};
 use matrix_sdk::{
     assert_next_with_timeout, assert_recv_with_timeout,
     config::SyncSettings,
-    room::{edit::EditedContent, Receipts, ReportedContentScore, RoomMemberRole},
+    crypto::{
+        olm::{InboundGroupSession, OutboundGroupSession, SenderData},
+        types::EventEncryptionAlgorithm,
+        EncryptionSettings,
+    },
+    deserialized_responses::TimelineEvent,
+    room::{
+        bulk_invite::{EmailInvites, InviteTarget},
+        edit::EditedContent,
+        export::{ExportFormat, ExportProgress},
+        forward::ForwardEventError,
+        moderation::RedactionProgress,
+        power_levels::RoomPermissions,
+        privacy_settings::JoinRuleError,
+        reactions::ToggledReaction,
+        Receipts, ReportedContentScore, RoomMemberRole,
+    },
     test_utils::mocks::MatrixMockServer,
+    Error,
 };
 use matrix_sdk_base::{RoomMembersUpdate, RoomState};
 use matrix_sdk_test::{
@@ -22,24 +42,34 @@ use matrix_sdk_test::{
     SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
 };
 use ruma::{
-    api::client::{membership::Invite3pidInit, receipt::create_receipt::v3::ReceiptType},
-    assign, event_id,
+    api::client::{
+        membership::Invite3pidInit, receipt::create_receipt::v3::ReceiptType, room::Visibility,
+    },
+    assign, device_id, event_id,
     events::{
         direct::DirectUserIdentifier,
         receipt::ReceiptThread,
         room::{
+            canonical_alias::RoomCanonicalAliasEventContent,
+            guest_access::GuestAccess,
+            history_visibility::HistoryVisibility,
+            join_rules::JoinRule,
             member::{MembershipState, RoomMemberEventContent},
             message::{RoomMessageEventContent, RoomMessageEventContentWithoutRelation},
         },
-        TimelineEventType,
+        StateEventType, TimelineEventType,
     },
-    int, mxc_uri, owned_event_id, room_id, thirdparty, user_id, OwnedUserId, TransactionId,
+    int, mxc_uri, owned_event_id, room_id,
+    serde::Raw,
+    thirdparty, uint, user_id, MilliSecondsSinceUnixEpoch, OwnedUserId, RoomVersionId,
+    TransactionId,
 };
 use serde_json::{from_value, json, Value};
 use stream_assert::assert_pending;
 use tokio::time::sleep;
+use vodozemac::{olm::IdentityKeys, Curve25519PublicKey, Curve25519SecretKey, Ed25519SecretKey};
 use wiremock::{
-    matchers::{body_json, body_partial_json, header, method, path_regex},
+    matchers::{body_json, body_partial_json, header, method, path, path_regex},
     Mock, ResponseTemplate,
 };
 
@@ -99,6 +129,81 @@ async fn test_invite_user_by_3pid() {
     .unwrap();
 }
 
+#[async_test]
+async fn test_invite_users() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!room:localhost")).await;
+
+    // Bob can't be invited.
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/invite$"))
+        .and(body_partial_json(json!({ "user_id": "@bob:localhost" })))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "Bob is banned",
+        })))
+        .mount(server.server())
+        .await;
+
+    // The first invite of Carol is rate-limited.
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/invite$"))
+        .and(body_partial_json(json!({ "user_id": "@carol:localhost" })))
+        .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+            "errcode": "M_LIMIT_EXCEEDED",
+            "error": "Too many requests",
+            "retry_after_ms": 10,
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/invite$"))
+        .and(body_partial_json(json!({
+            "id_server": "identity.localhost",
+            "id_access_token": "IdToken",
+            "medium": "email",
+            "address": "dave@example.org",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    server.mock_invite_user_by_id().ok().expect(2).mount().await;
+
+    let user_ids = [
+        user_id!("@alice:localhost").to_owned(),
+        user_id!("@bob:localhost").to_owned(),
+        user_id!("@carol:localhost").to_owned(),
+    ];
+    let emails = EmailInvites {
+        id_server: "identity.localhost".to_owned(),
+        id_access_token: "IdToken".to_owned(),
+        addresses: vec!["dave@example.org".to_owned()],
+    };
+
+    // The access token doesn't end up in the logs.
+    assert!(!format!("{emails:?}").contains("IdToken"));
+
+    let report = room.invite_users(user_ids, Some(emails)).await;
+
+    assert!(!report.is_complete());
+    assert_eq!(
+        report.invited,
+        [
+            InviteTarget::UserId(user_id!("@alice:localhost").to_owned()),
+            InviteTarget::UserId(user_id!("@carol:localhost").to_owned()),
+            InviteTarget::Email("dave@example.org".to_owned()),
+        ]
+    );
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, InviteTarget::UserId(user_id!("@bob:localhost").to_owned()));
+}
+
 #[async_test]
 async fn test_leave_room() -> Result<(), anyhow::Error> {
     let (client, server) = logged_in_client_with_server().await;
@@ -176,9 +281,7 @@ async fn test_mark_as_unread() {
     let (client, server) = logged_in_client_with_server().await;
 
     Mock::given(method("PUT"))
-        .and(path_regex(
-            r"^/_matrix/client/r0/user/.*/rooms/.*/account_data/com.famedly.marked_unread",
-        ))
+        .and(path_regex(r"^/_matrix/client/r0/user/.*/rooms/.*/account_data/m.marked_unread"))
         .and(header("authorization", "Bearer 1234"))
         .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
         .mount(&server)
@@ -267,6 +370,97 @@ async fn test_send_multiple_receipts() {
     room.send_multiple_receipts(receipts).await.unwrap();
 }
 
+#[async_test]
+async fn test_send_receipt_unsets_unread_flag() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!test:localhost");
+    let marked_unread = Raw::new(&json!({
+        "type": "m.marked_unread",
+        "content": { "unread": true },
+    }))
+    .unwrap()
+    .cast();
+    let room = server
+        .sync_room(&client, JoinedRoomBuilder::new(room_id).add_account_data_bulk([marked_unread]))
+        .await;
+    assert!(room.is_marked_unread());
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/receipt"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(2)
+        .mount(server.server())
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/rooms/.*/account_data/m.marked_unread"))
+        .and(body_json(json!({ "unread": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // A threaded receipt doesn't unset the flag.
+    room.send_single_receipt(
+        ReceiptType::Read,
+        ReceiptThread::Thread(owned_event_id!("$root")),
+        owned_event_id!("$reply"),
+    )
+    .await
+    .unwrap();
+
+    room.send_single_receipt(
+        ReceiptType::Read,
+        ReceiptThread::Unthreaded,
+        owned_event_id!("$event"),
+    )
+    .await
+    .unwrap();
+}
+
+#[async_test]
+async fn test_send_receipt_when_unsetting_unread_flag_fails() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!test:localhost");
+    let marked_unread = Raw::new(&json!({
+        "type": "m.marked_unread",
+        "content": { "unread": true },
+    }))
+    .unwrap()
+    .cast();
+    let room = server
+        .sync_room(&client, JoinedRoomBuilder::new(room_id).add_account_data_bulk([marked_unread]))
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/receipt"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/rooms/.*/account_data/m.marked_unread"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "Nope",
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The receipt was sent, so failing to unset the flag isn't an error.
+    room.send_single_receipt(
+        ReceiptType::Read,
+        ReceiptThread::Unthreaded,
+        owned_event_id!("$event"),
+    )
+    .await
+    .unwrap();
+}
+
 #[async_test]
 async fn test_typing_notice() {
     let (client, server) = logged_in_client_with_server().await;
@@ -429,6 +623,272 @@ async fn test_set_name() {
     room.set_name(name.to_owned()).await.unwrap();
 }
 
+#[async_test]
+async fn test_privacy_settings() {
+    let (client, server) = synced_client().await;
+
+    mock_sync(&server, &*test_json::SYNC, None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/directory/list/room/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "visibility": "public",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let snapshot = room.privacy_settings().privacy_snapshot().await.unwrap();
+    assert_eq!(snapshot.join_rule, JoinRule::Public);
+    assert_eq!(snapshot.history_visibility, HistoryVisibility::Shared);
+    assert_eq!(snapshot.guest_access, GuestAccess::Forbidden);
+    assert_eq!(snapshot.directory_visibility, Visibility::Public);
+    assert_eq!(snapshot.canonical_alias.unwrap().as_str(), "#tutorial:localhost");
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/state/m.room.guest_access/$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_json(json!({
+            "guest_access": "can_join",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    room.privacy_settings().update_guest_access(GuestAccess::CanJoin).await.unwrap();
+}
+
+#[async_test]
+async fn test_restrict_join_to_spaces() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:localhost");
+    let space_id = room_id!("!space:localhost");
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "content": { "creator": "@example:localhost", "room_version": "10" },
+                "event_id": "$create",
+                "origin_server_ts": 151800140,
+                "sender": "@example:localhost",
+                "state_key": "",
+                "type": "m.room.create",
+            }))),
+        )
+        .await;
+    assert_eq!(room.privacy_settings().restricted_join_spaces(), None);
+
+    server
+        .mock_room_send_state()
+        .body_matches_partial_json(json!({
+            "join_rule": "restricted",
+            "allow": [{ "type": "m.room_membership", "room_id": space_id }],
+        }))
+        .ok(event_id!("$join_rules"))
+        .mock_once()
+        .mount()
+        .await;
+
+    room.privacy_settings().restrict_join_to_spaces([space_id.to_owned()]).await.unwrap();
+
+    // The allow list is read back from the state of the room.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "content": {
+                    "join_rule": "restricted",
+                    "allow": [{ "type": "m.room_membership", "room_id": space_id }],
+                },
+                "event_id": "$join_rules",
+                "origin_server_ts": 151800141,
+                "sender": "@example:localhost",
+                "state_key": "",
+                "type": "m.room.join_rules",
+            }))),
+        )
+        .await;
+    assert_eq!(room.privacy_settings().restricted_join_spaces(), Some(vec![space_id.to_owned()]));
+
+    // An empty allow list is refused.
+    assert_matches!(
+        room.privacy_settings().restrict_join_to_spaces([]).await,
+        Err(Error::JoinRule(JoinRuleError::EmptyAllowList))
+    );
+
+    // So are rooms whose version doesn't support restricted join rules.
+    let old_room_id = room_id!("!old:localhost");
+    let old_room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(old_room_id).add_state_event(StateTestEvent::Custom(json!({
+                "content": { "creator": "@example:localhost", "room_version": "6" },
+                "event_id": "$old_create",
+                "origin_server_ts": 151800140,
+                "sender": "@example:localhost",
+                "state_key": "",
+                "type": "m.room.create",
+            }))),
+        )
+        .await;
+    assert_matches!(
+        old_room.privacy_settings().restrict_join_to_spaces([space_id.to_owned()]).await,
+        Err(Error::JoinRule(JoinRuleError::RestrictedUnsupported(RoomVersionId::V6)))
+    );
+}
+
+#[async_test]
+async fn test_forward_event() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!source:localhost")).await;
+    let target_room = server.sync_joined_room(&client, room_id!("!target:localhost")).await;
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server
+        .mock_room_send()
+        .respond_with(|request: &wiremock::Request| {
+            let content: Value = request.body_json().expect("The body should be a JSON body");
+            // The relation, the mentions and the reply fallback are removed.
+            assert_eq!(content, json!({ "msgtype": "m.text", "body": "Hello" }));
+            ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$forwarded" }))
+        })
+        .expect(1)
+        .mount()
+        .await;
+
+    let event = EventFactory::new()
+        .text_msg("> <@alice:localhost> Hi\n\nHello")
+        .sender(user_id!("@bob:localhost"))
+        .room(room.room_id())
+        .reply_to(event_id!("$parent"))
+        .into_sync();
+
+    let response = room.forward_event(&event, &target_room).await.unwrap();
+    assert_eq!(response.event_id, "$forwarded");
+
+    // Only messages can be forwarded.
+    let reaction = EventFactory::new()
+        .reaction(event_id!("$parent"), "👍".to_owned())
+        .sender(user_id!("@bob:localhost"))
+        .room(room.room_id())
+        .into_sync();
+    assert_matches!(
+        room.forward_event(&reaction, &target_room).await,
+        Err(ForwardEventError::UnsupportedEvent)
+    );
+}
+
+#[async_test]
+async fn test_forward_edit() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!source:localhost")).await;
+    let target_room = server.sync_joined_room(&client, room_id!("!target:localhost")).await;
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server
+        .mock_room_send()
+        .respond_with(|request: &wiremock::Request| {
+            let content: Value = request.body_json().expect("The body should be a JSON body");
+            // The new content of the edit is sent, without the edit relation.
+            assert_eq!(content, json!({ "msgtype": "m.text", "body": "Hello, world" }));
+            ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$forwarded" }))
+        })
+        .expect(1)
+        .mount()
+        .await;
+
+    let edit = EventFactory::new()
+        .text_msg("* Hello, world")
+        .sender(user_id!("@bob:localhost"))
+        .room(room.room_id())
+        .edit(event_id!("$original"), RoomMessageEventContent::text_plain("Hello, world").into())
+        .into_sync();
+
+    let response = room.forward_event(&edit, &target_room).await.unwrap();
+    assert_eq!(response.event_id, "$forwarded");
+}
+
+#[async_test]
+async fn test_scenario_publish_room_alias() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!test:localhost")).await;
+
+    let scenario = server
+        .scenario()
+        .then(
+            "GET",
+            r"^/_matrix/client/v3/directory/room/",
+            ResponseTemplate::new(404).set_body_json(json!({ "errcode": "M_NOT_FOUND" })),
+        )
+        .then_ok("PUT", r"^/_matrix/client/v3/directory/room/", json!({}))
+        .then_ok(
+            "PUT",
+            r"^/_matrix/client/v3/rooms/.*/state/m.room.canonical_alias/",
+            json!({ "event_id": "$canonical_alias" }),
+        )
+        .start()
+        .await;
+
+    let alias = ruma::room_alias_id!("#test:localhost");
+    assert!(client.is_room_alias_available(alias).await.unwrap());
+    client.create_room_alias(alias, room.room_id()).await.unwrap();
+    room.send_state_event(assign!(RoomCanonicalAliasEventContent::new(), {
+        alias: Some(alias.to_owned()),
+    }))
+    .await
+    .unwrap();
+
+    scenario.verify();
+}
+
+#[async_test]
+async fn test_scenario_reports_unexpected_requests() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!test:localhost")).await;
+
+    let scenario = server
+        .scenario()
+        .then_ok("PUT", r"^/_matrix/client/v3/directory/room/", json!({}))
+        .then_ok(
+            "PUT",
+            r"^/_matrix/client/v3/rooms/.*/state/m.room.canonical_alias/",
+            json!({ "event_id": "$canonical_alias" }),
+        )
+        .start()
+        .await;
+
+    // The canonical alias is sent before the alias is created.
+    let alias = ruma::room_alias_id!("#test:localhost");
+    room.send_state_event(assign!(RoomCanonicalAliasEventContent::new(), {
+        alias: Some(alias.to_owned()),
+    }))
+    .await
+    .unwrap_err();
+    client.create_room_alias(alias, room.room_id()).await.unwrap();
+
+    // A request that isn't part of the scenario.
+    client.resolve_room_alias(alias).await.unwrap_err();
+
+    let report = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| scenario.verify()))
+        .unwrap_err()
+        .downcast::<String>()
+        .unwrap();
+    assert!(report.contains("1 step(s) out of 2 happened"), "{report}");
+    assert!(report.contains("- PUT ^/_matrix/client/v3/rooms/.*/state/m.room.canonical_alias/"));
+    assert!(report.contains("- PUT /_matrix/client/v3/rooms/"), "{report}");
+    assert!(report.contains("- GET /_matrix/client/v3/directory/room/"), "{report}");
+}
+
 #[async_test]
 async fn test_report_content() {
     let (client, server) = logged_in_client_with_server().await;
@@ -842,6 +1302,117 @@ async fn test_enable_encryption_doesnt_stay_unencrypted() {
     assert!(room.is_encrypted().await.unwrap());
 }
 
+#[async_test]
+async fn test_enable_encryption_checked_refuses_public_rooms() {
+    let mock = MatrixMockServer::new().await;
+    let client = mock.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let room = mock
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::JoinRules),
+        )
+        .await;
+    assert!(room.is_public());
+
+    assert_let!(
+        Err(matrix_sdk::Error::EncryptionInPublicRoom) =
+            room.enable_encryption_checked(false).await
+    );
+}
+
+#[async_test]
+async fn test_enable_encryption_checked_reports_members_without_devices() {
+    let mock = MatrixMockServer::new().await;
+    let client = mock.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let bob = user_id!("@bob:b.c");
+    let f = EventFactory::new().room(room_id);
+
+    mock.mock_room_state_encryption().plain().mount().await;
+    mock.mock_get_members()
+        .ok(vec![f
+            .event(RoomMemberEventContent::new(MembershipState::Join))
+            .sender(bob)
+            .state_key(bob)
+            .into_raw_timeline()
+            .cast()])
+        .mock_once()
+        .mount()
+        .await;
+
+    // Bob has no devices at all.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/keys/query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "device_keys": { bob.as_str(): {} } })),
+        )
+        .expect(1)
+        .mount(mock.server())
+        .await;
+
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let preview = room.enable_encryption_checked(false).await.unwrap();
+    assert!(!preview.is_public);
+    assert!(preview.members_without_devices.iter().any(|user_id| user_id == bob));
+
+    mock.mock_set_room_state_encryption().ok(event_id!("$1")).mock_once().mount().await;
+    preview.enable().await.unwrap();
+}
+
+#[async_test]
+async fn test_prepare_encryption() {
+    let mock = MatrixMockServer::new().await;
+    let client = mock.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let bob = user_id!("@bob:b.c");
+
+    // Nothing is prepared in unencrypted rooms.
+    mock.mock_room_state_encryption().plain().mount().await;
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    room.prepare_encryption().await.unwrap();
+    assert!(!room.are_members_synced());
+
+    // In encrypted rooms, the members and their devices are loaded before the
+    // room key is shared.
+    let room_id = room_id!("!encrypted:b.c");
+    let f = EventFactory::new().room(room_id);
+    mock.verify_and_reset().await;
+    mock.mock_room_state_encryption().encrypted().mount().await;
+    mock.mock_get_members()
+        .ok(vec![f
+            .event(RoomMemberEventContent::new(MembershipState::Join))
+            .sender(bob)
+            .state_key(bob)
+            .into_raw_timeline()
+            .cast()])
+        .mock_once()
+        .mount()
+        .await;
+
+    // Bob has no devices at all.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/keys/query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "device_keys": { bob.as_str(): {} } })),
+        )
+        .expect(1)
+        .mount(mock.server())
+        .await;
+
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    room.prepare_encryption().await.unwrap();
+    assert!(room.are_members_synced());
+}
+
 #[async_test]
 async fn test_subscribe_to_knock_requests() {
     let server = MatrixMockServer::new().await;
@@ -918,6 +1489,73 @@ async fn test_subscribe_to_knock_requests() {
     handle.abort();
 }
 
+#[async_test]
+async fn test_subscribe_to_membership_summary() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id);
+    let user_id = user_id!("@alice:b.c");
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .set_room_summary(json!({
+                    "m.joined_member_count": 2,
+                    "m.invited_member_count": 0,
+                }))
+                .add_state_bulk(vec![f
+                    .event(RoomMemberEventContent::new(MembershipState::Knock))
+                    .sender(user_id)
+                    .state_key(user_id)
+                    .into_raw_timeline()
+                    .cast()]),
+        )
+        .await;
+
+    let summary = room.membership_summary().await.unwrap();
+    assert_eq!(summary.joined, 2);
+    assert_eq!(summary.invited, 0);
+    assert_eq!(summary.knocking, 1);
+    assert!(summary.has_pending_knocks());
+
+    let stream = room.subscribe_to_membership_summary().await.unwrap();
+    pin_mut!(stream);
+
+    // The current summary is emitted first.
+    assert_eq!(assert_next_with_timeout!(stream, 100), summary);
+
+    // Alice's knock is accepted.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .set_room_summary(json!({
+                    "m.joined_member_count": 2,
+                    "m.invited_member_count": 1,
+                }))
+                .add_state_bulk(vec![f
+                    .event(RoomMemberEventContent::new(MembershipState::Invite))
+                    .sender(client.user_id().unwrap())
+                    .state_key(user_id)
+                    .into_raw_timeline()
+                    .cast()]),
+        )
+        .await;
+
+    let summary = assert_next_with_timeout!(stream, 100);
+    assert_eq!(summary.joined, 2);
+    assert_eq!(summary.invited, 1);
+    assert_eq!(summary.knocking, 0);
+    assert!(!summary.has_pending_knocks());
+
+    // The same summary isn't emitted twice.
+    sleep(Duration::from_millis(100)).await;
+    assert_pending!(stream);
+}
+
 #[async_test]
 async fn test_subscribe_to_knock_requests_reloads_members_on_limited_sync() {
     let server = MatrixMockServer::new().await;
@@ -1218,3 +1856,519 @@ async fn test_room_member_updates_sender_on_partial_members_update() {
     assert_let!(RoomMembersUpdate::Partial(user_ids) = next);
     assert_eq!(user_ids, BTreeSet::from_iter(vec![user_id!("@alice:b.c").to_owned()]));
 }
+
+#[async_test]
+async fn test_toggle_reaction() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    // Keep the new reactions in the send queue.
+    client.send_queue().set_enabled(false).await;
+
+    let room_id = room_id!("!a:b.c");
+    let event_id = event_id!("$message");
+    let f = EventFactory::new().room(room_id).sender(user_id!("@example:localhost"));
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("hello").event_id(event_id))
+                .add_timeline_event(
+                    f.reaction(event_id, "👍".to_owned()).event_id(event_id!("$reaction")),
+                ),
+        )
+        .await;
+
+    server.mock_room_redact().ok(event_id!("$redaction")).mock_once().mount().await;
+
+    // The existing reaction is redacted.
+    assert_matches!(room.toggle_reaction(event_id, "👍").await, Ok(ToggledReaction::Removed));
+
+    // The redaction hasn't been received yet, but a new reaction is added.
+    assert_matches!(room.toggle_reaction(event_id, "👍").await, Ok(ToggledReaction::Added(_)));
+    let (local_echoes, _) = room.send_queue().subscribe().await.unwrap();
+    assert_eq!(local_echoes.len(), 1);
+
+    // The new reaction is aborted before it's sent.
+    assert_matches!(room.toggle_reaction(event_id, "👍").await, Ok(ToggledReaction::Removed));
+    let (local_echoes, _) = room.send_queue().subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+}
+
+#[async_test]
+async fn test_toggle_reaction_fetches_unknown_reactions() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let event_id = event_id!("$message");
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    let f = EventFactory::new().room(room_id);
+
+    // The event cache isn't enabled, so the reactions are fetched from the
+    // homeserver. Both reactions of the current user with this key are removed.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v1/rooms/.*/relations/.*/m\.annotation/m\.reaction"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [
+                f.reaction(event_id, "👍".to_owned())
+                    .sender(user_id!("@example:localhost"))
+                    .event_id(event_id!("$reaction1"))
+                    .into_raw_timeline(),
+                f.reaction(event_id, "👍".to_owned())
+                    .sender(user_id!("@example:localhost"))
+                    .event_id(event_id!("$reaction2"))
+                    .into_raw_timeline(),
+                f.reaction(event_id, "👍".to_owned())
+                    .sender(user_id!("@bob:b.c"))
+                    .event_id(event_id!("$reaction3"))
+                    .into_raw_timeline(),
+            ],
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    server.mock_room_redact().ok(event_id!("$redaction")).expect(2).mount().await;
+
+    assert_matches!(room.toggle_reaction(event_id, "👍").await, Ok(ToggledReaction::Removed));
+}
+
+#[async_test]
+async fn test_redact_user_messages() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let spammer = user_id!("@spammer:b.c");
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    let f = EventFactory::new().room(room_id).sender(spammer);
+    server
+        .mock_room_messages()
+        .ok(
+            "start".to_owned(),
+            Some("end".to_owned()),
+            vec![
+                f.text_msg("spam 2")
+                    .event_id(event_id!("$spam2"))
+                    .server_ts(3000)
+                    .into_raw_timeline(),
+                f.member(spammer).display_name("Spammer").server_ts(2500).into_raw_timeline(),
+                f.text_msg("spam 1")
+                    .event_id(event_id!("$spam1"))
+                    .server_ts(2000)
+                    .into_raw_timeline(),
+                f.text_msg("old").event_id(event_id!("$old")).server_ts(500).into_raw_timeline(),
+            ],
+            Vec::new(),
+        )
+        .mock_once()
+        .mount()
+        .await;
+
+    server.mock_ban_user().ok().mock_once().mount().await;
+
+    // The first redaction is rate-limited.
+    server
+        .mock_room_redact()
+        .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+            "errcode": "M_LIMIT_EXCEEDED",
+            "error": "Too many requests",
+            "retry_after_ms": 10,
+        })))
+        .mock_once()
+        .mount()
+        .await;
+    server.mock_room_redact().ok(event_id!("$redaction")).expect(2).mount().await;
+
+    let progress = SharedObservable::new(RedactionProgress::default());
+    let report = room
+        .redact_user_messages(spammer, MilliSecondsSinceUnixEpoch(uint!(1000)), Some("Spam"))
+        .and_ban()
+        .with_progress_observable(progress.clone())
+        .await
+        .unwrap();
+
+    assert!(report.banned);
+    assert_eq!(report.redacted, vec![owned_event_id!("$spam2"), owned_event_id!("$spam1")]);
+    assert!(report.failed.is_empty());
+    assert_eq!(progress.get(), RedactionProgress { found: 2, redacted: 2, failed: 0 });
+}
+
+#[async_test]
+async fn test_export_history() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    let f = EventFactory::new().room(room_id).sender(user_id!("@alice:b.c"));
+    let chunk = vec![
+        f.text_msg("too old").event_id(event_id!("$old")).server_ts(500).into_raw_timeline(),
+        f.text_msg("hello").event_id(event_id!("$hello")).server_ts(1000).into_raw_timeline(),
+        f.text_msg("world").event_id(event_id!("$world")).server_ts(2000).into_raw_timeline(),
+    ];
+
+    // The end of the timeline is reached with the first batch.
+    server
+        .mock_room_messages()
+        .ok("start".to_owned(), None, chunk, Vec::new())
+        .expect(2)
+        .mount()
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let since = MilliSecondsSinceUnixEpoch(uint!(1000));
+
+    let progress = SharedObservable::new(ExportProgress::default());
+    let outcome = room
+        .export_history(dir.path(), ExportFormat::Json, since..)
+        .with_progress_observable(progress.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, ExportProgress { exported: 2, media_downloaded: 0, undecryptable: 0 });
+    assert_eq!(progress.get(), outcome);
+
+    let exported_event_ids = || {
+        std::fs::read_to_string(dir.path().join("messages.jsonl"))
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let entry: Value = serde_json::from_str(line).unwrap();
+                entry["event"]["event_id"].as_str().unwrap().to_owned()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(exported_event_ids(), ["$hello", "$world"]);
+
+    // Running the export again resumes it, without exporting the same messages
+    // twice.
+    let outcome = room.export_history(dir.path(), ExportFormat::Json, since..).await.unwrap();
+    assert_eq!(outcome.exported, 2);
+    assert_eq!(exported_event_ids(), ["$hello", "$world"]);
+}
+
+#[async_test]
+async fn test_permissions_for() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!test:localhost");
+    let user_id = client.user_id().unwrap().to_owned();
+
+    let power_levels = |event_id: &str, own_level: i64| {
+        StateTestEvent::Custom(json!({
+            "content": {
+                "ban": 50,
+                "events": { "m.room.topic": 0 },
+                "events_default": 0,
+                "invite": 0,
+                "kick": 50,
+                "redact": 50,
+                "state_default": 50,
+                "users": { user_id.as_str(): own_level },
+                "users_default": 0,
+            },
+            "event_id": event_id,
+            "origin_server_ts": 151800140,
+            "sender": user_id.as_str(),
+            "state_key": "",
+            "type": "m.room.power_levels",
+        }))
+    };
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(power_levels("$pl1", 100)),
+        )
+        .await;
+
+    let (_drop_guard, mut subscriber) = room.permissions_for(&user_id).await.unwrap();
+
+    // As an admin, we can do everything.
+    let permissions = subscriber.get();
+    assert!(permissions.send_message);
+    assert!(permissions.invite);
+    assert!(permissions.ban);
+    assert!(permissions.redact_other);
+    assert!(permissions.can_send_state(&StateEventType::RoomName));
+    assert!(permissions.can_send_state(&StateEventType::RoomTopic));
+
+    // Our power level is lowered.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(power_levels("$pl2", 0)),
+        )
+        .await;
+
+    let permissions = assert_next_with_timeout!(subscriber, 100);
+    assert!(permissions.send_message);
+    assert!(permissions.invite);
+    assert!(!permissions.ban);
+    assert!(!permissions.redact_other);
+    assert!(!permissions.can_send_state(&StateEventType::RoomName));
+    assert!(permissions.can_send_state(&StateEventType::RoomTopic));
+
+    // Unrelated state events don't trigger an update.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "content": { "name": "Room" },
+                "event_id": "$name",
+                "origin_server_ts": 151800140,
+                "sender": user_id.as_str(),
+                "state_key": "",
+                "type": "m.room.name",
+            }))),
+        )
+        .await;
+    assert_pending!(subscriber);
+
+    // We leave the room.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "content": { "membership": "leave" },
+                "event_id": "$leave",
+                "origin_server_ts": 151800140,
+                "sender": user_id.as_str(),
+                "state_key": user_id.as_str(),
+                "type": "m.room.member",
+            }))),
+        )
+        .await;
+
+    let permissions = assert_next_with_timeout!(subscriber, 100);
+    assert_eq!(permissions, RoomPermissions::default());
+}
+
+#[async_test]
+async fn test_encryption_trust_requirement() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room = server.sync_joined_room(&client, room_id!("!test:localhost")).await;
+    assert_eq!(room.encryption_trust_requirement().await.unwrap(), None);
+
+    room.set_encryption_trust_requirement(Some(true)).await.unwrap();
+    assert_eq!(room.encryption_trust_requirement().await.unwrap(), Some(true));
+
+    // Other rooms aren't affected.
+    let other_room = server.sync_joined_room(&client, room_id!("!other:localhost")).await;
+    assert_eq!(other_room.encryption_trust_requirement().await.unwrap(), None);
+
+    room.set_encryption_trust_requirement(None).await.unwrap();
+    assert_eq!(room.encryption_trust_requirement().await.unwrap(), None);
+}
+
+#[async_test]
+async fn test_sorted_members() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let admin = user_id!("@admin:b.c");
+    let moderator = user_id!("@moderator:b.c");
+    let bob = user_id!("@bob:b.c");
+    let carol = user_id!("@carol:b.c");
+    let f = EventFactory::new().room(room_id);
+
+    let power_levels = |event_id: &str, moderator_level: i64| {
+        StateTestEvent::Custom(json!({
+            "content": {
+                "users": {
+                    admin.as_str(): 100,
+                    moderator.as_str(): moderator_level,
+                },
+                "users_default": 0,
+            },
+            "event_id": event_id,
+            "origin_server_ts": 151800140,
+            "sender": admin.as_str(),
+            "state_key": "",
+            "type": "m.room.power_levels",
+        }))
+    };
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_event(power_levels("$pl1", 50))
+                .add_state_bulk(vec![
+                    f.member(carol).display_name("Carol").into_raw_sync().cast(),
+                    f.member(bob).display_name("bob").into_raw_sync().cast(),
+                    f.member(moderator).display_name("Zed").into_raw_sync().cast(),
+                    f.member(admin).display_name("Zoe").into_raw_sync().cast(),
+                ]),
+        )
+        .await;
+
+    let (members, stream) = room.sorted_members(Default::default()).await.unwrap();
+    pin_mut!(stream);
+
+    // Members are sorted by role first, then case-insensitively by name.
+    let user_ids = members.iter().map(|m| m.user_id()).collect::<Vec<_>>();
+    assert_eq!(user_ids, [admin, moderator, bob, carol]);
+
+    // Carol changes her display name, she moves before Bob.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk(vec![f
+                .member(carol)
+                .display_name("Alice")
+                .into_raw_sync()
+                .cast()]),
+        )
+        .await;
+
+    let diffs = assert_next_with_timeout!(stream, 100);
+    assert_eq!(diffs.len(), 2);
+    assert_matches!(&diffs[0], VectorDiff::Remove { index: 3 });
+    assert_let!(VectorDiff::Insert { index: 2, value } = &diffs[1]);
+    assert_eq!(value.user_id(), carol);
+    assert_eq!(value.name(), "Alice");
+
+    // Bob leaves, he is removed from the list.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk(vec![f
+                .member(bob)
+                .membership(MembershipState::Leave)
+                .into_raw_sync()
+                .cast()]),
+        )
+        .await;
+
+    let diffs = assert_next_with_timeout!(stream, 100);
+    assert_eq!(diffs.len(), 1);
+    assert_matches!(&diffs[0], VectorDiff::Remove { index: 3 });
+
+    // The moderator is demoted, the whole list is sorted again.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(power_levels("$pl2", 0)),
+        )
+        .await;
+
+    let diffs = assert_next_with_timeout!(stream, 100);
+    assert_eq!(diffs.len(), 1);
+    assert_let!(VectorDiff::Reset { values } = &diffs[0]);
+    let user_ids = values.iter().map(|m| m.user_id()).collect::<Vec<_>>();
+    assert_eq!(user_ids, [admin, carol, moderator]);
+
+    assert_pending!(stream);
+}
+
+#[async_test]
+async fn test_event_encryption_info_of_unencrypted_event() {
+    let mock = MatrixMockServer::new().await;
+    let client = mock.client_builder().build().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let event_id = event_id!("$1");
+    let f = EventFactory::new();
+    mock.mock_room_event()
+        .ok(f.text_msg("hi").event_id(event_id).sender(user_id!("@a:b.c")).into_timeline())
+        .expect(1)
+        .named("/event")
+        .mount()
+        .await;
+
+    // There's nothing to show for an event that isn't encrypted.
+    assert!(room.event_encryption_info(event_id).await.unwrap().is_none());
+}
+
+#[async_test]
+async fn test_event_encryption_info_of_decrypted_event() {
+    let mock = MatrixMockServer::new().await;
+    let client = mock.client_builder().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    // The room key was forwarded to us by another device.
+    let sender_signing_key = Ed25519SecretKey::new().public_key();
+    let sender_keys = IdentityKeys {
+        ed25519: sender_signing_key,
+        curve25519: Curve25519PublicKey::from(&Curve25519SecretKey::new()),
+    };
+    let forwarder_key = Curve25519PublicKey::from(&Curve25519SecretKey::new());
+
+    let outbound_session = OutboundGroupSession::new(
+        device_id!("SENDERDEVICE").to_owned(),
+        Arc::new(sender_keys),
+        room_id,
+        EncryptionSettings::default(),
+    )
+    .unwrap();
+    let inbound_session = InboundGroupSession::new(
+        outbound_session.sender_key(),
+        sender_signing_key,
+        room_id,
+        &outbound_session.session_key().await,
+        SenderData::unknown(),
+        EventEncryptionAlgorithm::MegolmV1AesSha2,
+        None,
+    )
+    .unwrap();
+
+    let mut room_key = inbound_session.export().await;
+    room_key.forwarding_curve25519_key_chain = vec![forwarder_key];
+    client
+        .olm_machine_for_testing()
+        .await
+        .as_ref()
+        .unwrap()
+        .store()
+        .import_room_keys(vec![room_key], None, |_, _| ())
+        .await
+        .unwrap();
+
+    let event_id = event_id!("$1");
+    let content = outbound_session
+        .encrypt(
+            "m.room.message",
+            &Raw::new(&json!({ "body": "hi", "msgtype": "m.text" })).unwrap().cast(),
+        )
+        .await;
+    let event = Raw::new(&json!({
+        "content": content,
+        "event_id": event_id,
+        "origin_server_ts": 1,
+        "room_id": room_id,
+        "sender": "@a:b.c",
+        "type": "m.room.encrypted",
+    }))
+    .unwrap()
+    .cast();
+
+    // The event is only fetched once, then it's taken from the event cache.
+    mock.mock_room_event().ok(TimelineEvent::new(event)).expect(1).named("/event").mount().await;
+
+    for _ in 0..2 {
+        let info = room.event_encryption_info(event_id).await.unwrap().unwrap();
+
+        assert_eq!(info.algorithm, ruma::EventEncryptionAlgorithm::MegolmV1AesSha2);
+        assert_eq!(info.session_id, outbound_session.session_id());
+        assert_eq!(info.sender, "@a:b.c");
+        assert_eq!(info.sender_curve25519_key, outbound_session.sender_key().to_base64());
+        assert_eq!(info.forwarding_chain, [forwarder_key.to_base64()]);
+    }
+}
@@ -116,12 +116,14 @@ async fn test_forget_direct_room() {
 async fn test_rejoin_room() {
     let (client, server) = logged_in_client_with_server().await;
 
+    // Left rooms are joined through the servers of their route.
     Mock::given(method("POST"))
-        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/join"))
+        .and(path_regex(r"^/_matrix/client/r0/join/.*"))
         .and(header("authorization", "Bearer 1234"))
         .respond_with(
             ResponseTemplate::new(200).set_body_json(json!({ "room_id": *DEFAULT_TEST_ROOM_ID })),
         )
+        .expect(1)
         .mount(&server)
         .await;
     mock_sync(&server, &*test_json::LEAVE_SYNC, None).await;
@@ -2,6 +2,7 @@ mod attachment;
 mod beacon;
 mod beacon_info;
 mod common;
+mod invites;
 mod joined;
 mod left;
 mod notification_mode;
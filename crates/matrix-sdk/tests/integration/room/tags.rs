@@ -1,10 +1,12 @@
 use std::{collections::BTreeMap, ops::Not, time::Duration};
 
-use matrix_sdk::{config::SyncSettings, Client, Room};
+use assert_matches2::assert_matches;
+use matrix_sdk::{config::SyncSettings, Client, Error, Room};
 use matrix_sdk_test::{
     async_test, test_json, JoinedRoomBuilder, RoomAccountDataTestEvent, SyncResponseBuilder,
 };
 use ruma::{
+    assign,
     events::tag::{TagInfo, TagName, Tags},
     room_id, RoomId,
 };
@@ -225,3 +227,44 @@ async fn test_unset_low_priority() {
 
     server.verify().await;
 }
+
+#[async_test]
+async fn test_rooms_by_tag() {
+    let room_a = room_id!("!a:example.org");
+    let room_b = room_id!("!b:example.org");
+    let room_c = room_id!("!c:example.org");
+    let mut sync_builder = SyncResponseBuilder::new();
+    let (client, room, server) = synced_client_with_room(&mut sync_builder, room_a).await;
+    let (_drop_guard, mut tags_receiver) = room.subscribe_to_tags();
+
+    for (room_id, order) in [(room_a, Some(0.5)), (room_b, Some(0.1)), (room_c, None)] {
+        let tags = BTreeMap::from([(TagName::Favorite, assign!(TagInfo::new(), { order }))]);
+        sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_account_data(
+            RoomAccountDataTestEvent::Custom(json!({
+                "content": { "tags": tags },
+                "type": "m.tag",
+            })),
+        ));
+    }
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    sync_once(&client, &server).await;
+
+    // The change of the tags was observed.
+    let tags = tags_receiver.recv().await.unwrap();
+    assert_eq!(tags[&TagName::Favorite].order, Some(0.5));
+
+    // The rooms with an order come first.
+    let rooms = client.rooms_by_tag(&TagName::Favorite).await.unwrap();
+    let room_ids: Vec<_> = rooms.iter().map(|room| room.room_id()).collect();
+    assert_eq!(room_ids, [room_b, room_a, room_c]);
+
+    assert!(client.rooms_by_tag(&TagName::LowPriority).await.unwrap().is_empty());
+
+    // The order of a tag must be between 0 and 1.
+    let tag_info = assign!(TagInfo::new(), { order: Some(1.5) });
+    assert_matches!(
+        room.set_tag(TagName::Favorite, tag_info).await,
+        Err(Error::InvalidTagOrder(order))
+    );
+    assert_eq!(order, 1.5);
+}
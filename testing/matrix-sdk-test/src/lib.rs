@@ -117,6 +117,7 @@ pub mod mocks;
 pub mod event_factory;
 pub mod notification_settings;
 mod sync_builder;
+pub mod sync_replay;
 pub mod test_json;
 
 pub use self::{
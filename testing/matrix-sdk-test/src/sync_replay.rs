@@ -0,0 +1,297 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Record sync responses to disk, and replay them in tests.
+//!
+//! This allows to write regression tests with the data that triggered a bug,
+//! like a wrong room display name or a state resolution issue, captured from a
+//! real account.
+//!
+//! The [`SyncRecorder`] writes the JSON bodies of `/sync` responses to a
+//! directory, one file per response, after removing the private data that is
+//! not needed to compute the state of the rooms with
+//! [`scrub_sync_response()`].
+//!
+//! The [`SyncReplay`] loads them back in order, optionally shifting the
+//! timestamps of the events with a [`TimeWarp`]. The responses can then be
+//! given to a `BaseClient` with [`SyncReplay::responses()`], or served to a
+//! `Client` by a mock server with [`SyncReplay::mount()`].
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use ruma::{
+    api::client::sync::sync_events::v3::Response as SyncResponse, MilliSecondsSinceUnixEpoch,
+};
+use serde_json::Value as JsonValue;
+#[cfg(not(target_arch = "wasm32"))]
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::ruma_response_from_json;
+
+/// The extension of the files of the recorded responses.
+const RESPONSE_FILE_EXTENSION: &str = "json";
+
+/// The value of the scrubbed strings.
+const SCRUBBED: &str = "[scrubbed]";
+
+/// The value of the scrubbed media URLs.
+const SCRUBBED_MXC_URI: &str = "mxc://localhost/scrubbed";
+
+/// The top-level fields of a sync response that are removed when it's
+/// scrubbed, because they contain end-to-end encryption data.
+const SCRUBBED_RESPONSE_FIELDS: &[&str] = &[
+    "to_device",
+    "device_lists",
+    "device_one_time_keys_count",
+    "device_unused_fallback_key_types",
+];
+
+/// Writes sync responses to a directory, to be replayed with a
+/// [`SyncReplay`].
+#[derive(Debug)]
+pub struct SyncRecorder {
+    dir: PathBuf,
+    next_index: usize,
+}
+
+impl SyncRecorder {
+    /// Create a recorder writing to the given directory.
+    ///
+    /// The directory is created if it doesn't exist. If it already contains
+    /// recorded responses, the new ones are recorded after them.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let next_index = response_files(&dir)?.len();
+
+        Ok(Self { dir, next_index })
+    }
+
+    /// Scrub the given JSON body of a sync response with
+    /// [`scrub_sync_response()`], and write it to a new file.
+    ///
+    /// Returns the path of the file.
+    pub fn record(&mut self, response: &JsonValue) -> io::Result<PathBuf> {
+        let mut response = response.clone();
+        scrub_sync_response(&mut response);
+
+        let path = self.dir.join(format!("{:06}.{RESPONSE_FILE_EXTENSION}", self.next_index));
+        fs::write(&path, serde_json::to_vec_pretty(&response)?)?;
+        self.next_index += 1;
+
+        Ok(path)
+    }
+}
+
+/// Remove the private data of a sync response that isn't needed to compute the
+/// state of the rooms.
+///
+/// The end-to-end encryption data is removed, and the text and media of the
+/// message-like events are replaced with placeholders. State events, receipts
+/// and account data are kept as is, since the state of the rooms depends on
+/// them.
+pub fn scrub_sync_response(response: &mut JsonValue) {
+    let Some(response) = response.as_object_mut() else { return };
+
+    for field in SCRUBBED_RESPONSE_FIELDS {
+        response.remove(*field);
+    }
+
+    if let Some(rooms) = response.get_mut("rooms") {
+        scrub_events(rooms);
+    }
+}
+
+/// Scrub the contents of the message-like events found in the given value.
+fn scrub_events(value: &mut JsonValue) {
+    match value {
+        JsonValue::Object(object) => {
+            let is_message_like_event = object.contains_key("event_id")
+                && object.contains_key("type")
+                && !object.contains_key("state_key");
+
+            if is_message_like_event {
+                if let Some(content) = object.get_mut("content") {
+                    scrub_content(content);
+                }
+            } else {
+                object.values_mut().for_each(scrub_events);
+            }
+        }
+        JsonValue::Array(array) => array.iter_mut().for_each(scrub_events),
+        _ => {}
+    }
+}
+
+/// Replace the text and media of the given event content with placeholders.
+fn scrub_content(value: &mut JsonValue) {
+    match value {
+        JsonValue::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                match key.as_str() {
+                    "body" | "formatted_body" | "filename" if value.is_string() => {
+                        *value = SCRUBBED.into();
+                    }
+                    "url" | "thumbnail_url" if value.is_string() => {
+                        *value = SCRUBBED_MXC_URI.into();
+                    }
+                    _ => scrub_content(value),
+                }
+            }
+        }
+        JsonValue::Array(array) => array.iter_mut().for_each(scrub_content),
+        _ => {}
+    }
+}
+
+/// How to change the timestamps of the events of a [`SyncReplay`].
+#[derive(Clone, Copy, Debug, Default)]
+pub enum TimeWarp {
+    /// The timestamps are kept as is.
+    #[default]
+    None,
+
+    /// The timestamps are shifted by the given number of milliseconds.
+    ShiftBy(i64),
+
+    /// The timestamps are shifted so that the most recent one is the given
+    /// time, like [`MilliSecondsSinceUnixEpoch::now()`].
+    EndAt(MilliSecondsSinceUnixEpoch),
+}
+
+/// Sync responses, recorded by a [`SyncRecorder`], to be replayed in order.
+#[derive(Clone, Debug, Default)]
+pub struct SyncReplay {
+    responses: Vec<JsonValue>,
+}
+
+impl SyncReplay {
+    /// Load the responses recorded in the given directory by a
+    /// [`SyncRecorder`], in order.
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let responses = response_files(dir.as_ref())?
+            .into_iter()
+            .map(|path| Ok(serde_json::from_slice(&fs::read(path)?)?))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { responses })
+    }
+
+    /// Create a replay of the given JSON bodies of sync responses.
+    pub fn from_responses(responses: Vec<JsonValue>) -> Self {
+        Self { responses }
+    }
+
+    /// Change the timestamps of the events of the responses.
+    pub fn time_warp(mut self, time_warp: TimeWarp) -> Self {
+        let offset = match time_warp {
+            TimeWarp::None => return self,
+            TimeWarp::ShiftBy(offset) => offset,
+            TimeWarp::EndAt(end) => {
+                let Some(latest) = self.responses.iter().filter_map(latest_timestamp).max() else {
+                    return self;
+                };
+                i64::from(end.get()) - latest
+            }
+        };
+
+        for response in &mut self.responses {
+            shift_timestamps(response, offset);
+        }
+
+        self
+    }
+
+    /// The JSON bodies of the responses, in order.
+    pub fn json_responses(&self) -> &[JsonValue] {
+        &self.responses
+    }
+
+    /// The typed responses, in order, to give to
+    /// `BaseClient::receive_sync_response()`.
+    pub fn responses(&self) -> impl Iterator<Item = SyncResponse> + '_ {
+        self.responses.iter().map(ruma_response_from_json)
+    }
+
+    /// Mount the responses on the given server, so that each `/sync` request
+    /// is answered by the next response.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn mount(&self, server: &MockServer) {
+        for (index, response) in self.responses.iter().enumerate() {
+            Mock::given(method("GET"))
+                .and(path_regex(r"^/_matrix/client/(r0|v3)/sync"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(response))
+                .up_to_n_times(1)
+                .named(format!("replayed sync response {index}"))
+                .mount(server)
+                .await;
+        }
+    }
+}
+
+/// Get the paths of the recorded responses in the given directory, in order.
+fn response_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.extension().is_some_and(|extension| extension == RESPONSE_FILE_EXTENSION) {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Get the most recent `origin_server_ts` in the given value.
+fn latest_timestamp(value: &JsonValue) -> Option<i64> {
+    match value {
+        JsonValue::Object(object) => {
+            let own = object.get("origin_server_ts").and_then(JsonValue::as_i64);
+            object.values().filter_map(latest_timestamp).chain(own).max()
+        }
+        JsonValue::Array(array) => array.iter().filter_map(latest_timestamp).max(),
+        _ => None,
+    }
+}
+
+/// Shift every `origin_server_ts` in the given value by the given offset.
+fn shift_timestamps(value: &mut JsonValue, offset: i64) {
+    match value {
+        JsonValue::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if key == "origin_server_ts" {
+                    if let Some(ts) = value.as_i64() {
+                        *value = ts.saturating_add(offset).max(0).into();
+                    }
+                } else {
+                    shift_timestamps(value, offset);
+                }
+            }
+        }
+        JsonValue::Array(array) => {
+            array.iter_mut().for_each(|value| shift_timestamps(value, offset))
+        }
+        _ => {}
+    }
+}